@@ -147,6 +147,7 @@ fn format_value(value: &Value) -> String {
         Value::String(value) => value.clone(),
         Value::Boolean(value) => value.to_string(),
         Value::DateTime(value) => value.to_rfc3339(),
+        Value::Date(value) => value.to_string(),
         Value::Guid(value) => value.to_string(),
         Value::Money(value) => value.value.to_string(),
         Value::OptionSetValue(value) => match &value.name {
@@ -155,11 +156,17 @@ fn format_value(value: &Value) -> String {
         },
         Value::OptionSetValueCollection(value) => format!("{:?}", value.values),
         Value::Null => "null".to_string(),
+        Value::EntityCollection(rows) => format!("[{} rows]", rows.len()),
         Value::EntityReference(reference) => format!(
             "{}:{} ({})",
             reference.logical_name,
             reference.id,
             reference.name.as_deref().unwrap_or("<no name>")
         ),
+        Value::PartyList(parties) => parties
+            .iter()
+            .map(|party| format!("{}:{}", party.logical_name, party.id))
+            .collect::<Vec<_>>()
+            .join(", "),
     }
 }
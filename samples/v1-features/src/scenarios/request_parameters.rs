@@ -10,12 +10,19 @@ use uuid::Uuid;
 
 pub fn run(connection_string: &str) -> Pin<Box<dyn Future<Output = Result<(), String>> + '_>> {
     Box::pin(async move {
-        let client = ServiceClient::new(connection_string, LogLevel::Information).await?;
+        // Bypass parameters are refused unless the client opts in.
+        let client = ServiceClient::builder()
+            .connection_string(connection_string)
+            .log_level(LogLevel::Information)
+            .allow_bypass_custom_logic(true)
+            .build()
+            .await?;
         let options = RequestParameters {
             bypass_business_logic_execution_custom_sync: true,
             bypass_business_logic_execution_custom_async: false,
             bypass_custom_plugin_execution: false,
             suppress_callback_registration_expander_job: false,
+            custom_headers: Vec::new(),
         };
 
         println!("Request parameter headers:");
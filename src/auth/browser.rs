@@ -0,0 +1,382 @@
+use std::process::Command;
+use std::time::Duration;
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use uuid::Uuid;
+
+use crate::LogLevel;
+use crate::auth::config::AuthConfig;
+use crate::auth::credentials::{
+    TokenExchange, build_dataverse_device_code_scope, exchange_authorization_code,
+};
+use crate::auth::events::{TokenFlow, TokenOperation, observe_token_request};
+use crate::auth::token::{
+    CachedToken, is_expiring_soon, load_cached_token, resolve_token_cache_file_path,
+    save_cached_token,
+};
+use crate::dataverse::dataverseurl::DataverseUrl;
+
+/// How long the loopback listener waits for the browser to return with a code.
+const SIGN_IN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Largest redirect request read from the browser; the code and state fit well within it.
+const MAX_REQUEST_BYTES: usize = 16 * 1024;
+
+/// Progress updates emitted during interactive browser sign-in.
+#[derive(Clone, Debug)]
+pub enum BrowserSignInEvent {
+    /// The loopback listener is waiting at `redirect_uri` and the system browser is opening
+    /// `authorize_url`.
+    Opening {
+        authorize_url: String,
+        redirect_uri: String,
+    },
+    /// The system browser could not be started. Open `authorize_url` by hand to continue.
+    BrowserUnavailable {
+        authorize_url: String,
+        error: String,
+    },
+    /// The browser returned with a code, which is being exchanged for tokens.
+    Exchanging,
+    /// Tokens were acquired and cached.
+    Success,
+}
+
+/// Ensure a valid cached delegated token exists, signing in through the system browser with the
+/// authorization code flow and PKCE when needed. Progress is reported to the caller, and token
+/// events are logged at the default `LogLevel`, which reports failures only.
+///
+/// The token is saved to the same cache as device code tokens, so a `ServiceClient` built from
+/// `auth` afterwards uses and refreshes it without prompting again.
+pub async fn ensure_browser_token_with_progress<F>(
+    auth: &AuthConfig,
+    progress: F,
+) -> Result<(), String>
+where
+    F: Fn(BrowserSignInEvent) + Send + Sync,
+{
+    let AuthConfig::DeviceCode {
+        client_id,
+        dataverse_url,
+        tenant_id,
+        ..
+    } = auth
+    else {
+        return Err("Browser sign-in is only supported for delegated (OAuth) auth".to_string());
+    };
+
+    let token_cache_path = resolve_token_cache_file_path(auth)?;
+    if let Some(cached) = load_cached_token(&token_cache_path)?
+        && !cached.access_token.trim().is_empty()
+        && !is_expiring_soon(cached.expires_at)
+    {
+        return Ok(());
+    }
+
+    let scope = build_dataverse_device_code_scope(&DataverseUrl::parse(dataverse_url)?);
+    let tenant_id = if tenant_id.trim().is_empty() {
+        "organizations"
+    } else {
+        tenant_id.as_str()
+    };
+    let token = observe_token_request(
+        LogLevel::default(),
+        TokenOperation::Acquire,
+        TokenFlow::AuthorizationCode,
+        sign_in_with_browser(client_id, tenant_id, &scope, &progress),
+    )
+    .await?;
+    progress(BrowserSignInEvent::Success);
+
+    save_cached_token(
+        &token_cache_path,
+        &CachedToken {
+            expires_at: Some(token.expires_at),
+            refresh_token: Some(token.refresh_token),
+            access_token: token.access_token,
+        },
+    )
+}
+
+async fn sign_in_with_browser<F>(
+    client_id: &str,
+    tenant_id: &str,
+    scope: &str,
+    progress: &F,
+) -> Result<TokenExchange, String>
+where
+    F: Fn(BrowserSignInEvent) + Send + Sync,
+{
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(|e| format!("Failed to start the sign-in listener: {e}"))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    // Microsoft Entra ID matches `http://localhost` redirect URIs on any port.
+    let redirect_uri = format!("http://localhost:{port}");
+
+    let code_verifier = new_code_verifier();
+    let state = Uuid::new_v4().simple().to_string();
+    let authorize_url = authorize_url(
+        tenant_id,
+        client_id,
+        &redirect_uri,
+        scope,
+        &state,
+        &code_challenge(&code_verifier),
+    );
+
+    progress(BrowserSignInEvent::Opening {
+        authorize_url: authorize_url.clone(),
+        redirect_uri: redirect_uri.clone(),
+    });
+    if let Err(error) = open_browser(&authorize_url) {
+        println!("Open this URL in your browser: {authorize_url}");
+        progress(BrowserSignInEvent::BrowserUnavailable {
+            authorize_url: authorize_url.clone(),
+            error,
+        });
+    }
+
+    let code = timeout(SIGN_IN_TIMEOUT, wait_for_code(&listener, &state))
+        .await
+        .map_err(|_| "Browser sign-in timed out before the redirect arrived".to_string())??;
+
+    progress(BrowserSignInEvent::Exchanging);
+    exchange_authorization_code(
+        client_id,
+        tenant_id,
+        scope,
+        &code,
+        &redirect_uri,
+        &code_verifier,
+    )
+    .await
+}
+
+/// A PKCE code verifier: 64 random characters from the unreserved set.
+fn new_code_verifier() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// The `S256` PKCE code challenge for `code_verifier`.
+fn code_challenge(code_verifier: &str) -> String {
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier))
+}
+
+/// Microsoft identity platform authorize URL for the authorization code flow with PKCE.
+fn authorize_url(
+    tenant_id: &str,
+    client_id: &str,
+    redirect_uri: &str,
+    scope: &str,
+    state: &str,
+    code_challenge: &str,
+) -> String {
+    format!(
+        "https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/authorize?client_id={}&response_type=code&redirect_uri={}&response_mode=query&scope={}&state={state}&code_challenge={code_challenge}&code_challenge_method=S256",
+        urlencoding::encode(client_id),
+        urlencoding::encode(redirect_uri),
+        urlencoding::encode(scope)
+    )
+}
+
+/// Open `url` in the system browser.
+fn open_browser(url: &str) -> Result<(), String> {
+    let mut command = if cfg!(target_os = "windows") {
+        // `start` would need the `&` separators escaped for `cmd`.
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+    let status = command
+        .arg(url)
+        .status()
+        .map_err(|e| format!("Failed to open the browser: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("Failed to open the browser: {status}"))
+    }
+}
+
+/// Accept loopback connections until the browser is redirected back with a code or an error.
+async fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String, String> {
+    loop {
+        let (mut stream, _) = listener
+            .accept()
+            .await
+            .map_err(|e| format!("Sign-in listener failed: {e}"))?;
+        let Some(request_line) = read_request_line(&mut stream).await else {
+            continue;
+        };
+
+        let outcome = parse_redirect(&request_line, state);
+        let (status, page) = match &outcome {
+            None => ("404 Not Found", "Not found."),
+            Some(Ok(_)) => ("200 OK", "Sign-in complete. You can close this window."),
+            Some(Err(_)) => (
+                "400 Bad Request",
+                "Sign-in failed. Return to the application for details.",
+            ),
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{page}",
+            page.len()
+        );
+        stream.write_all(response.as_bytes()).await.ok();
+        stream.shutdown().await.ok();
+
+        if let Some(outcome) = outcome {
+            return outcome;
+        }
+    }
+}
+
+/// Read the request line of an HTTP request, or `None` for a connection that sends none.
+async fn read_request_line(stream: &mut TcpStream) -> Option<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(2).any(|window| window == b"\r\n") && buffer.len() < MAX_REQUEST_BYTES {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    }
+    let text = String::from_utf8_lossy(&buffer);
+    text.lines()
+        .next()
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+/// Read the code from a redirect request line such as `GET /?code=…&state=… HTTP/1.1`.
+///
+/// `None` for requests that are not the redirect, such as a browser fetching `/favicon.ico`.
+/// The `state` is checked before the code or error is read, so a redirect that was not for this
+/// sign-in can neither complete it nor report its own error.
+fn parse_redirect(request_line: &str, state: &str) -> Option<Result<String, String>> {
+    let target = request_line.split_whitespace().nth(1)?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/" {
+        return None;
+    }
+
+    let parameters = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| {
+            let value = value.replace('+', " ");
+            let value = urlencoding::decode(&value)
+                .map(|value| value.into_owned())
+                .unwrap_or(value);
+            (name, value)
+        })
+        .collect::<Vec<_>>();
+    let parameter = |name: &str| {
+        parameters
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let error = parameter("error");
+    let code = parameter("code");
+    if error.is_none() && code.is_none() {
+        return None;
+    }
+    if parameter("state") != Some(state) {
+        return Some(Err(
+            "Browser sign-in returned a mismatched state; the redirect was not for this sign-in"
+                .to_string(),
+        ));
+    }
+    if let Some(error) = error {
+        return Some(Err(match parameter("error_description") {
+            Some(description) => format!("Browser sign-in failed: {error}: {description}"),
+            None => format!("Browser sign-in failed: {error}"),
+        }));
+    }
+    code.map(|code| Ok(code.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{authorize_url, code_challenge, new_code_verifier, parse_redirect};
+
+    #[test]
+    fn derives_pkce_challenge() {
+        // RFC 7636, appendix B.
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+        let verifier = new_code_verifier();
+        assert_eq!(verifier.len(), 64);
+        assert!(verifier.chars().all(|ch| ch.is_ascii_alphanumeric()));
+
+        let url = authorize_url(
+            "organizations",
+            "client",
+            "http://localhost:8400",
+            "https://contoso.crm.dynamics.com/user_impersonation offline_access",
+            "state1",
+            "challenge",
+        );
+        assert!(url.starts_with(
+            "https://login.microsoftonline.com/organizations/oauth2/v2.0/authorize?client_id=client&response_type=code&redirect_uri=http%3A%2F%2Flocalhost%3A8400&"
+        ));
+        assert!(url.contains("&state=state1&code_challenge=challenge&code_challenge_method=S256"));
+    }
+
+    #[test]
+    fn reads_code_from_redirect() {
+        assert_eq!(
+            parse_redirect(
+                "GET /?code=0.AX%2Fabc&state=s1&session_state=x HTTP/1.1",
+                "s1"
+            ),
+            Some(Ok("0.AX/abc".to_string()))
+        );
+        assert_eq!(parse_redirect("GET /favicon.ico HTTP/1.1", "s1"), None);
+        assert!(matches!(
+            parse_redirect("GET /?code=abc&state=other HTTP/1.1", "s1"),
+            Some(Err(error)) if error.contains("mismatched state")
+        ));
+        assert_eq!(
+            parse_redirect(
+                "GET /?error=access_denied&error_description=The+user+cancelled&state=s1 HTTP/1.1",
+                "s1"
+            ),
+            Some(Err(
+                "Browser sign-in failed: access_denied: The user cancelled".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn checks_state_before_reading_an_error_redirect() {
+        for request_line in [
+            "GET /?error=access_denied&error_description=Forged&state=other HTTP/1.1",
+            "GET /?error=access_denied HTTP/1.1",
+        ] {
+            assert!(matches!(
+                parse_redirect(request_line, "s1"),
+                Some(Err(error)) if error.contains("mismatched state")
+            ));
+        }
+        assert!(matches!(
+            parse_redirect("GET /?code=abc HTTP/1.1", "s1"),
+            Some(Err(error)) if error.contains("mismatched state")
+        ));
+        assert_eq!(parse_redirect("GET /?state=s1 HTTP/1.1", "s1"), None);
+    }
+}
@@ -1,9 +1,18 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
 use crate::auth::connectionstring::parse_connection_string_auth_config;
+use crate::auth::secrets::{
+    CLIENT_ID_SECRET, CLIENT_SECRET_SECRET, SecretProvider, TENANT_ID_SECRET,
+};
+use crate::dataverse::dataverseurl::DataverseUrl;
+use crate::dataverse::transport::REDACTED;
 
 /// Public authentication configuration for acquiring Dataverse access tokens.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+///
+/// The `Debug` output replaces the client secret with `[REDACTED]`.
+#[derive(Clone, Serialize, Deserialize)]
 #[serde(tag = "method")]
 pub enum AuthConfig {
     /// Azure AD client credentials (app-only) flow configuration.
@@ -46,12 +55,116 @@ pub enum AuthConfig {
     },
 }
 
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthConfig::ClientCredentials {
+                client_id,
+                tenant_id,
+                dataverse_url,
+                token_cache_store_path,
+                ..
+            } => f
+                .debug_struct("ClientCredentials")
+                .field("client_id", client_id)
+                .field("client_secret", &REDACTED)
+                .field("tenant_id", tenant_id)
+                .field("dataverse_url", dataverse_url)
+                .field("token_cache_store_path", token_cache_store_path)
+                .finish(),
+            AuthConfig::DeviceCode {
+                client_id,
+                dataverse_url,
+                tenant_id,
+                token_cache_store_path,
+            } => f
+                .debug_struct("DeviceCode")
+                .field("client_id", client_id)
+                .field("dataverse_url", dataverse_url)
+                .field("tenant_id", tenant_id)
+                .field("token_cache_store_path", token_cache_store_path)
+                .finish(),
+        }
+    }
+}
+
 impl AuthConfig {
     /// Parse an `AuthConfig` from a Dataverse-style connection string.
     pub fn from_connection_string(connection_string: &str) -> Result<Self, String> {
         parse_connection_string_auth_config(connection_string)
     }
 
+    /// Client credentials configuration for the environment at `dataverse_url`. The URL is
+    /// validated and normalized with `DataverseUrl::parse`, and tokens are requested for its
+    /// `{url}/.default` scope, so no scope string needs to be configured.
+    pub fn client_credentials_for_environment(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        tenant_id: impl Into<String>,
+        dataverse_url: &str,
+    ) -> Result<Self, String> {
+        Ok(Self::client_credentials_for_url(
+            client_id,
+            client_secret,
+            tenant_id,
+            DataverseUrl::parse(dataverse_url)?,
+        ))
+    }
+
+    /// Like `client_credentials_for_environment`, but validates `dataverse_url` with
+    /// `DataverseUrl::parse_insecure`, for clients built with
+    /// `ServiceClientBuilder::allow_insecure_url` against a local mock server or proxy.
+    pub fn client_credentials_for_insecure_environment(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        tenant_id: impl Into<String>,
+        dataverse_url: &str,
+    ) -> Result<Self, String> {
+        Ok(Self::client_credentials_for_url(
+            client_id,
+            client_secret,
+            tenant_id,
+            DataverseUrl::parse_insecure(dataverse_url)?,
+        ))
+    }
+
+    fn client_credentials_for_url(
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+        tenant_id: impl Into<String>,
+        dataverse_url: DataverseUrl,
+    ) -> Self {
+        AuthConfig::ClientCredentials {
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            tenant_id: tenant_id.into(),
+            dataverse_url: dataverse_url.to_string(),
+            token_cache_store_path: None,
+        }
+    }
+
+    /// Parse the connection string held in the secret `name` of `provider`.
+    pub async fn from_secret_connection_string(
+        provider: &dyn SecretProvider,
+        name: &str,
+    ) -> Result<Self, String> {
+        Self::from_connection_string(&provider.require_secret(name).await?)
+    }
+
+    /// Client credentials configuration for the environment at `dataverse_url`, reading the
+    /// `client_id`, `client_secret`, and `tenant_id` secrets from `provider`.
+    pub async fn client_credentials_from_secrets(
+        provider: &dyn SecretProvider,
+        dataverse_url: &str,
+    ) -> Result<Self, String> {
+        Self::client_credentials_for_environment(
+            provider.require_secret(CLIENT_ID_SECRET).await?,
+            provider.require_secret(CLIENT_SECRET_SECRET).await?,
+            provider.require_secret(TENANT_ID_SECRET).await?,
+            dataverse_url,
+        )
+    }
+
     pub(crate) fn dataverse_url(&self) -> &str {
         match self {
             AuthConfig::ClientCredentials { dataverse_url, .. } => dataverse_url.trim_end_matches('/'),
@@ -59,12 +172,83 @@ impl AuthConfig {
         }
     }
 
-    pub(crate) fn scope(&self) -> Option<String> {
+    /// The client credentials scope, parsing the URL with `DataverseUrl::parse_insecure` when
+    /// `allow_insecure_url` is set.
+    pub(crate) fn scope(&self, allow_insecure_url: bool) -> Result<Option<String>, String> {
         match self {
-            AuthConfig::ClientCredentials { dataverse_url, .. } => {
-                Some(format!("{}/.default", dataverse_url.trim_end_matches('/')))
-            }
-            AuthConfig::DeviceCode { .. } => None,
+            AuthConfig::ClientCredentials { dataverse_url, .. } => Ok(Some(
+                DataverseUrl::parse_with(dataverse_url, allow_insecure_url)?.default_scope(),
+            )),
+            AuthConfig::DeviceCode { .. } => Ok(None),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::AuthConfig;
+
+    #[test]
+    fn derives_client_credentials_scope_from_environment_url() {
+        let auth = AuthConfig::client_credentials_for_environment(
+            "client",
+            "secret",
+            "tenant",
+            "https://Contoso.crm.dynamics.com/",
+        )
+        .expect("should build");
+        assert_eq!(auth.dataverse_url(), "https://contoso.crm.dynamics.com");
+        assert_eq!(
+            auth.scope(false),
+            Ok(Some(
+                "https://contoso.crm.dynamics.com/.default".to_string()
+            ))
+        );
+
+        assert!(
+            AuthConfig::client_credentials_for_environment(
+                "client",
+                "secret",
+                "tenant",
+                "https://contoso.crm.dynamics.com/api/data/v9.2",
+            )
+            .is_err()
+        );
+
+        assert!(
+            AuthConfig::client_credentials_for_environment(
+                "client",
+                "secret",
+                "tenant",
+                "http://localhost:8080",
+            )
+            .is_err()
+        );
+        let local = AuthConfig::client_credentials_for_insecure_environment(
+            "client",
+            "secret",
+            "tenant",
+            "http://localhost:8080/",
+        )
+        .expect("should build");
+        assert!(local.scope(false).is_err());
+        assert_eq!(
+            local.scope(true),
+            Ok(Some("http://localhost:8080/.default".to_string()))
+        );
+    }
+    #[test]
+    fn debug_output_redacts_client_secret() {
+        let auth = AuthConfig::client_credentials_for_environment(
+            "client",
+            "s3cr3t-value",
+            "tenant",
+            "https://contoso.crm.dynamics.com",
+        )
+        .expect("should build");
+        let debug = format!("{auth:?}");
+        assert!(!debug.contains("s3cr3t-value"), "{debug}");
+        assert!(debug.contains("client_secret: \"[REDACTED]\""), "{debug}");
+        assert!(debug.contains("client_id: \"client\""), "{debug}");
+    }
+}
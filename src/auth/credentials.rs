@@ -1,15 +1,43 @@
 use std::{
     collections::HashMap,
+    fmt,
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use reqwest::Client;
+use reqwest::header::HeaderMap;
 use serde_json::Value;
 use tokio::time::{Duration, sleep};
 
 use crate::auth::devicecode::DeviceCodeFlowEvent;
+use crate::auth::tokenerror::{TokenError, token_error};
+use crate::dataverse::apierror::retry_after;
+use crate::dataverse::dataverseurl::DataverseUrl;
+use crate::dataverse::transport::REDACTED;
 
-/// Result of exchanging an authorization code or refresh token.
+/// Attempts made for a token request that fails transiently.
+const TOKEN_REQUEST_ATTEMPTS: u32 = 3;
+
+/// Longest `Retry-After` delay honored between token request attempts.
+const MAX_RETRY_AFTER_SECS: u64 = 30;
+
+#[cfg(test)]
+tokio::task_local! {
+    /// Authority that tests serving tokens from a local port use in place of Entra ID.
+    pub(crate) static TEST_AUTHORITY: String;
+}
+
+/// URL of the Entra ID OAuth `endpoint`, such as `token`, for `tenant_id`.
+fn oauth_endpoint(tenant_id: &str, endpoint: &str) -> String {
+    #[cfg(test)]
+    if let Ok(authority) = TEST_AUTHORITY.try_with(Clone::clone) {
+        return format!("{authority}/{tenant_id}/oauth2/v2.0/{endpoint}");
+    }
+    format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/{endpoint}")
+}
+
+/// Result of exchanging an authorization code or refresh token. The `Debug` output redacts both
+/// tokens.
 pub struct TokenExchange {
     /// OAuth access token.
     pub access_token: String,
@@ -19,7 +47,7 @@ pub struct TokenExchange {
     pub expires_at: u64,
 }
 
-/// Access token returned from the client credentials flow.
+/// Access token returned from the client credentials flow. The `Debug` output redacts the token.
 pub struct ClientCredentialsToken {
     /// OAuth access token.
     pub access_token: String,
@@ -27,6 +55,25 @@ pub struct ClientCredentialsToken {
     pub expires_at: u64,
 }
 
+impl fmt::Debug for TokenExchange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenExchange")
+            .field("access_token", &REDACTED)
+            .field("refresh_token", &REDACTED)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+impl fmt::Debug for ClientCredentialsToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientCredentialsToken")
+            .field("access_token", &REDACTED)
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
 struct DeviceCodeStart {
     device_code: String,
     expires_in: u64,
@@ -41,10 +88,7 @@ pub(crate) async fn fetch_client_credentials_token_with_expiry(
     scope: &str,
 ) -> Result<ClientCredentialsToken, String> {
     let client = Client::new();
-    let token_url = format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
-        tenant_id
-    );
+    let token_url = oauth_endpoint(tenant_id, "token");
 
     let mut params = HashMap::new();
     params.insert("client_id", client_id);
@@ -52,19 +96,7 @@ pub(crate) async fn fetch_client_credentials_token_with_expiry(
     params.insert("scope", scope);
     params.insert("grant_type", "client_credentials");
 
-    let resp = client
-        .post(&token_url)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !resp.status().is_success() {
-        let body = resp.text().await.unwrap_or_default();
-        return Err(body);
-    }
-
-    let json: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let json = post_token_request(&client, &token_url, &params).await?;
 
     let access_token = json
         .get("access_token")
@@ -90,15 +122,59 @@ pub(crate) async fn fetch_client_credentials_token_with_expiry(
     })
 }
 
+/// POST a form to a Microsoft Entra ID endpoint and return the JSON body. Connection failures and
+/// transient errors are retried with backoff, honoring `Retry-After`; other failures return a
+/// message `TokenError::parse` reads. Authorization codes are single-use, so a code exchange is
+/// sent once: a retry after a redemption whose response was lost fails with `invalid_grant`.
+async fn post_token_request(
+    client: &Client,
+    url: &str,
+    params: &HashMap<&str, &str>,
+) -> Result<Value, String> {
+    let attempts = if params.get("grant_type") == Some(&"authorization_code") {
+        1
+    } else {
+        TOKEN_REQUEST_ATTEMPTS
+    };
+    let mut attempt = 1;
+    loop {
+        let (transient, retry_after, message) = match client.post(url).form(params).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                return resp.json().await.map_err(|e| e.to_string());
+            }
+            Ok(resp) => {
+                let status = resp.status();
+                let retry_after = parse_retry_after(resp.headers());
+                let body = resp.text().await.unwrap_or_default();
+                let transient = TokenError::from_body(status.as_u16(), &body).is_transient();
+                (transient, retry_after, token_error(status, &body))
+            }
+            Err(e) => (e.is_connect() || e.is_timeout(), None, e.to_string()),
+        };
+        if !transient || attempt >= attempts {
+            return Err(message);
+        }
+        sleep(retry_after.unwrap_or(Duration::from_secs(1 << (attempt - 1)))).await;
+        attempt += 1;
+    }
+}
+
+/// `Retry-After` delay, capped so a sign-in never stalls for long.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    retry_after(headers).map(|delay| delay.min(Duration::from_secs(MAX_RETRY_AFTER_SECS)))
+}
+
 pub(crate) async fn fetch_device_code_token_exchange_from_parts(
     client_id: &str,
     dataverse_url: &str,
     tenant_id: &str,
+    allow_insecure_url: bool,
 ) -> Result<TokenExchange, String> {
     fetch_device_code_token_exchange_from_parts_with_progress(
         client_id,
         dataverse_url,
         tenant_id,
+        allow_insecure_url,
         Option::<&fn(DeviceCodeFlowEvent)>::None,
     )
     .await
@@ -108,12 +184,16 @@ pub(crate) async fn fetch_device_code_token_exchange_from_parts_with_progress<F>
     client_id: &str,
     dataverse_url: &str,
     tenant_id: &str,
+    allow_insecure_url: bool,
     progress: Option<&F>,
 ) -> Result<TokenExchange, String>
 where
     F: Fn(DeviceCodeFlowEvent) + Send + Sync,
 {
-    let scope = build_dataverse_device_code_scope(dataverse_url);
+    let scope = build_dataverse_device_code_scope(&DataverseUrl::parse_with(
+        dataverse_url,
+        allow_insecure_url,
+    )?);
     let client = Client::new();
     let start = start_device_code_flow(&client, tenant_id, client_id, &scope, progress).await?;
 
@@ -139,7 +219,7 @@ pub(crate) async fn refresh_device_code_token(
     refresh_token_exchange(client_id, None, tenant_id, scope, refresh_token).await
 }
 
-fn build_dataverse_device_code_scope(dataverse_url: &str) -> String {
+pub(crate) fn build_dataverse_device_code_scope(dataverse_url: &DataverseUrl) -> String {
     format!(
         "{}/user_impersonation offline_access openid profile",
         dataverse_url
@@ -156,26 +236,13 @@ async fn start_device_code_flow<F>(
 where
     F: Fn(DeviceCodeFlowEvent) + Send + Sync,
 {
-    let device_code_url =
-        format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/devicecode");
+    let device_code_url = oauth_endpoint(tenant_id, "devicecode");
 
     let mut params = HashMap::new();
     params.insert("client_id", client_id);
     params.insert("scope", scope);
 
-    let resp = client
-        .post(&device_code_url)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !resp.status().is_success() {
-        let body = resp.text().await.unwrap_or_default();
-        return Err(body);
-    }
-
-    let json: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let json = post_token_request(client, &device_code_url, &params).await?;
 
     let device_code = json
         .get("device_code")
@@ -232,6 +299,7 @@ where
     })
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn poll_device_code_token<F>(
     client: &Client,
     tenant_id: &str,
@@ -245,7 +313,7 @@ async fn poll_device_code_token<F>(
 where
     F: Fn(DeviceCodeFlowEvent) + Send + Sync,
 {
-    let token_url = format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token");
+    let token_url = oauth_endpoint(tenant_id, "token");
     let started_at = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .map_err(|e| e.to_string())?
@@ -311,6 +379,7 @@ where
             });
         }
 
+        let status = resp.status();
         let json: Value = resp.json().await.map_err(|e| e.to_string())?;
         let error = json
             .get("error")
@@ -341,7 +410,7 @@ where
                 return Err("Device code was rejected by the identity provider".to_string());
             }
             _ => {
-                return Err(json.to_string());
+                return Err(token_error(status, &json.to_string()));
             }
         }
     }
@@ -355,10 +424,7 @@ async fn refresh_token_exchange(
     refresh_token: &str,
 ) -> Result<TokenExchange, String> {
     let client = Client::new();
-    let token_url = format!(
-        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
-        tenant_id
-    );
+    let token_url = oauth_endpoint(tenant_id, "token");
 
     let mut params = HashMap::new();
     params.insert("client_id", client_id);
@@ -370,19 +436,7 @@ async fn refresh_token_exchange(
         params.insert("client_secret", client_secret);
     }
 
-    let resp = client
-        .post(&token_url)
-        .form(&params)
-        .send()
-        .await
-        .map_err(|e| e.to_string())?;
-
-    if !resp.status().is_success() {
-        let body = resp.text().await.unwrap_or_default();
-        return Err(body);
-    }
-
-    let json: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let json = post_token_request(&client, &token_url, &params).await?;
 
     let access_token = json
         .get("access_token")
@@ -410,3 +464,144 @@ async fn refresh_token_exchange(
         expires_at: now + expires_in,
     })
 }
+
+/// Redeem an authorization code from the browser flow, proving possession with the PKCE
+/// `code_verifier`.
+pub(crate) async fn exchange_authorization_code(
+    client_id: &str,
+    tenant_id: &str,
+    scope: &str,
+    code: &str,
+    redirect_uri: &str,
+    code_verifier: &str,
+) -> Result<TokenExchange, String> {
+    let client = Client::new();
+    let token_url = oauth_endpoint(tenant_id, "token");
+
+    let mut params = HashMap::new();
+    params.insert("client_id", client_id);
+    params.insert("scope", scope);
+    params.insert("grant_type", "authorization_code");
+    params.insert("code", code);
+    params.insert("redirect_uri", redirect_uri);
+    params.insert("code_verifier", code_verifier);
+
+    let json = post_token_request(&client, &token_url, &params).await?;
+
+    let access_token = json
+        .get("access_token")
+        .and_then(|v| v.as_str())
+        .ok_or("No access_token in response")?
+        .to_string();
+    let refresh_token = json
+        .get("refresh_token")
+        .and_then(|v| v.as_str())
+        .ok_or("No refresh_token in response")?
+        .to_string();
+    let expires_in = json
+        .get("expires_in")
+        .and_then(|v| v.as_u64())
+        .ok_or("No expires_in in response")?;
+
+    if access_token.trim().is_empty() {
+        return Err("Access token was empty".to_string());
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?
+        .as_secs();
+
+    Ok(TokenExchange {
+        access_token,
+        refresh_token,
+        expires_at: now + expires_in,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use reqwest::Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::post_token_request;
+
+    const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Type: application/json\r\nContent-Length: 35\r\nConnection: close\r\n\r\n{\"error\":\"temporarily_unavailable\"}";
+    const TOKEN: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"access_token\":\"token\"}";
+
+    /// Answer token requests on a local port with `responses` in turn, counting the requests.
+    async fn token_endpoint(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.expect("bind");
+        let url = format!("http://{}/token", listener.local_addr().expect("addr"));
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.expect("accept");
+                // Read the whole form before answering so the client sees the response.
+                let mut request = Vec::new();
+                let mut chunk = [0u8; 1024];
+                loop {
+                    let read = stream.read(&mut chunk).await.expect("read");
+                    request.extend_from_slice(&chunk[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    let complete = text.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                        let length = head
+                            .lines()
+                            .find_map(|line| {
+                                line.to_ascii_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|value| value.trim().parse::<usize>().unwrap_or(0))
+                            })
+                            .unwrap_or(0);
+                        body.len() >= length
+                    });
+                    if read == 0 || complete {
+                        break;
+                    }
+                }
+                counter.fetch_add(1, Ordering::SeqCst);
+                stream.write_all(response.as_bytes()).await.ok();
+                stream.shutdown().await.ok();
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn retries_transient_token_failures_up_to_the_attempt_limit() {
+        let params = HashMap::from([("grant_type", "client_credentials")]);
+
+        let (url, requests) = token_endpoint(vec![UNAVAILABLE, TOKEN]).await;
+        let json = post_token_request(&Client::new(), &url, &params)
+            .await
+            .expect("should succeed on the second attempt");
+        assert_eq!(json["access_token"], "token");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let (url, requests) = token_endpoint(vec![UNAVAILABLE; 3]).await;
+        let error = post_token_request(&Client::new(), &url, &params)
+            .await
+            .expect_err("should give up");
+        assert!(error.contains("temporarily_unavailable"), "{error}");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn sends_authorization_code_exchanges_once() {
+        let params = HashMap::from([("grant_type", "authorization_code"), ("code", "code")]);
+        let (url, requests) = token_endpoint(vec![UNAVAILABLE, TOKEN]).await;
+
+        assert!(
+            post_token_request(&Client::new(), &url, &params)
+                .await
+                .is_err()
+        );
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::LogLevel;
 use crate::auth::config::AuthConfig;
 use crate::auth::token::{
     fetch_token_for_config_with_progress, is_expiring_soon, load_cached_token,
@@ -17,7 +18,8 @@ pub enum DeviceCodeFlowEvent {
     Success,
 }
 
-/// Ensure a valid cached device-code token exists while reporting progress to the caller.
+/// Ensure a valid cached device-code token exists while reporting progress to the caller. Token
+/// events are logged at the default `LogLevel`, which reports failures only.
 pub async fn ensure_device_code_token_with_progress<F>(
     auth: &AuthConfig,
     progress: F,
@@ -35,7 +37,9 @@ where
             return Ok(());
         }
 
-    let token = fetch_token_for_config_with_progress(auth, Some(&progress)).await?;
+    let token =
+        fetch_token_for_config_with_progress(auth, false, Some(&progress), LogLevel::default())
+            .await?;
     save_cached_token(&token_cache_path, &token)?;
     Ok(())
 }
@@ -0,0 +1,133 @@
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+/// Commercial-cloud Global Discovery Service base URL.
+pub const GLOBAL_DISCOVERY_URL: &str = "https://globaldisco.crm.dynamics.com";
+
+/// OAuth scope for requesting a Global Discovery Service access token.
+pub const GLOBAL_DISCOVERY_SCOPE: &str = "https://globaldisco.crm.dynamics.com/user_impersonation";
+
+/// Dataverse environment returned by the Global Discovery Service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiscoveredInstance {
+    /// Organization ID.
+    #[serde(rename = "Id")]
+    pub id: String,
+    /// Organization unique name.
+    #[serde(rename = "UniqueName")]
+    pub unique_name: String,
+    /// URL name (the host prefix of the environment URL).
+    #[serde(rename = "UrlName")]
+    pub url_name: Option<String>,
+    /// Environment display name.
+    #[serde(rename = "FriendlyName")]
+    pub friendly_name: Option<String>,
+    /// Environment URL, suitable for a connection string `Url`.
+    #[serde(rename = "Url")]
+    pub url: String,
+    /// Web API base URL.
+    #[serde(rename = "ApiUrl")]
+    pub api_url: Option<String>,
+    /// Region the environment is hosted in, such as `NA` or `EUR`.
+    #[serde(rename = "Region")]
+    pub region: Option<String>,
+    /// Dataverse version.
+    #[serde(rename = "Version")]
+    pub version: Option<String>,
+    /// Numeric environment state; `0` means enabled.
+    #[serde(rename = "State")]
+    pub state: Option<i32>,
+    /// Power Platform environment ID.
+    #[serde(rename = "EnvironmentId")]
+    pub environment_id: Option<String>,
+    /// Azure AD tenant ID that owns the environment.
+    #[serde(rename = "TenantId")]
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DiscoveryResponse {
+    value: Vec<DiscoveredInstance>,
+}
+
+/// List the Dataverse environments the signed-in user can access in the commercial cloud.
+/// The access token must be issued for `GLOBAL_DISCOVERY_SCOPE`.
+pub async fn discover_instances(access_token: &str) -> Result<Vec<DiscoveredInstance>, String> {
+    discover_instances_from(GLOBAL_DISCOVERY_URL, access_token).await
+}
+
+/// List the Dataverse environments the signed-in user can access from a specific discovery
+/// endpoint, such as a sovereign-cloud Global Discovery Service.
+pub async fn discover_instances_from(
+    discovery_url: &str,
+    access_token: &str,
+) -> Result<Vec<DiscoveredInstance>, String> {
+    let url = format!(
+        "{}/api/discovery/v2.0/Instances",
+        discovery_url.trim_end_matches('/')
+    );
+
+    let resp = Client::new()
+        .get(&url)
+        .bearer_auth(access_token)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {e}"))?;
+
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!(
+            "Global Discovery Service error ({}): {}",
+            status, body
+        ));
+    }
+
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read discovery response: {e}"))?;
+    parse_discovery_response(&body)
+}
+
+fn parse_discovery_response(body: &str) -> Result<Vec<DiscoveredInstance>, String> {
+    serde_json::from_str::<DiscoveryResponse>(body)
+        .map(|response| response.value)
+        .map_err(|e| format!("Failed to parse JSON: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_discovery_response;
+
+    #[test]
+    fn parses_instances_from_discovery_payload() {
+        let instances = parse_discovery_response(
+            r#"{
+                "value": [
+                    {
+                        "Id": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                        "UniqueName": "unq123",
+                        "UrlName": "contoso",
+                        "FriendlyName": "Contoso",
+                        "State": 0,
+                        "Version": "9.2.24031.00190",
+                        "Url": "https://contoso.crm.dynamics.com",
+                        "ApiUrl": "https://contoso.api.crm.dynamics.com",
+                        "LastUpdated": "2024-01-01T00:00:00Z",
+                        "Region": "NA",
+                        "EnvironmentId": "env-1",
+                        "TenantId": "tenant-1"
+                    }
+                ]
+            }"#,
+        )
+        .expect("should parse");
+
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].unique_name, "unq123");
+        assert_eq!(instances[0].url, "https://contoso.crm.dynamics.com");
+        assert_eq!(instances[0].region.as_deref(), Some("NA"));
+    }
+}
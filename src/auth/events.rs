@@ -0,0 +1,141 @@
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use log::{Level, log};
+
+use crate::LogLevel;
+use crate::log::sanitize_message;
+
+/// OAuth grant used for a token request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenFlow {
+    ClientCredentials,
+    DeviceCode,
+    AuthorizationCode,
+    RefreshToken,
+}
+
+impl fmt::Display for TokenFlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenFlow::ClientCredentials => "client_credentials",
+            TokenFlow::DeviceCode => "device_code",
+            TokenFlow::AuthorizationCode => "authorization_code",
+            TokenFlow::RefreshToken => "refresh_token",
+        })
+    }
+}
+
+/// Why a token was requested: the first token for a client, or a replacement for one that is
+/// about to expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TokenOperation {
+    Acquire,
+    Refresh,
+}
+
+impl fmt::Display for TokenOperation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TokenOperation::Acquire => "acquire",
+            TokenOperation::Refresh => "refresh",
+        })
+    }
+}
+
+/// Run a token request and log its start, duration, and outcome at the client's log level.
+/// Failure messages pass through `sanitize_message` because identity provider errors can echo the
+/// submitted form.
+pub(crate) async fn observe_token_request<T>(
+    log_level: LogLevel,
+    operation: TokenOperation,
+    flow: TokenFlow,
+    request: impl Future<Output = Result<T, String>>,
+) -> Result<T, String> {
+    emit(
+        log_level,
+        Level::Debug,
+        format!("token {operation} started flow={flow}"),
+    );
+
+    let started = Instant::now();
+    let result = request.await;
+    let elapsed = started.elapsed();
+
+    match &result {
+        Ok(_) => emit(
+            log_level,
+            Level::Info,
+            token_event_message(operation, flow, elapsed, None),
+        ),
+        Err(error) => emit(
+            log_level,
+            Level::Error,
+            token_event_message(operation, flow, elapsed, Some(error)),
+        ),
+    }
+
+    result
+}
+
+/// Log that a cached token was reused instead of requesting a new one.
+pub(crate) fn log_cached_token(log_level: LogLevel, expires_at: Option<u64>) {
+    let expires_at = expires_at.map_or_else(|| "unknown".to_string(), |value| value.to_string());
+    emit(
+        log_level,
+        Level::Debug,
+        format!("token cache hit expires_at={expires_at}"),
+    );
+}
+
+fn token_event_message(
+    operation: TokenOperation,
+    flow: TokenFlow,
+    elapsed: Duration,
+    error: Option<&str>,
+) -> String {
+    let elapsed_ms = elapsed.as_millis();
+    match error {
+        None => format!("token {operation} succeeded flow={flow} elapsed_ms={elapsed_ms}"),
+        Some(error) => format!(
+            "token {operation} failed flow={flow} elapsed_ms={elapsed_ms} error={}",
+            sanitize_message(error)
+        ),
+    }
+}
+
+fn emit(log_level: LogLevel, level: Level, message: String) {
+    if log_level.includes(level) {
+        log!(level, "{message}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{TokenFlow, TokenOperation, token_event_message};
+
+    #[test]
+    fn formats_token_events_with_redacted_errors() {
+        assert_eq!(
+            token_event_message(
+                TokenOperation::Acquire,
+                TokenFlow::ClientCredentials,
+                Duration::from_millis(312),
+                None
+            ),
+            "token acquire succeeded flow=client_credentials elapsed_ms=312"
+        );
+        assert_eq!(
+            token_event_message(
+                TokenOperation::Refresh,
+                TokenFlow::RefreshToken,
+                Duration::from_millis(45),
+                Some("invalid_grant refresh_token=abc123&client_secret=s3cret")
+            ),
+            "token refresh failed flow=refresh_token elapsed_ms=45 error=invalid_grant refresh_token=[REDACTED]&client_secret=[REDACTED]"
+        );
+    }
+}
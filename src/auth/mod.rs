@@ -1,5 +1,16 @@
+/// Interactive browser sign-in with the authorization code flow and PKCE.
+pub mod browser;
 pub mod config;
 pub mod devicecode;
+/// Global Discovery Service client for listing a user's Dataverse environments.
+pub mod discovery;
+/// Secret providers for reading credentials from the environment, files, or Azure Key Vault.
+pub mod secrets;
+/// Shared token state with optional background renewal.
+pub mod tokencache;
+/// Typed Microsoft Entra ID token endpoint errors.
+pub mod tokenerror;
 pub(crate) mod connectionstring;
 pub(crate) mod credentials;
+pub(crate) mod events;
 pub(crate) mod token;
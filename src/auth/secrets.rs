@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+
+/// Secret holding the Azure AD client ID, read by `AuthConfig::client_credentials_from_secrets`.
+pub const CLIENT_ID_SECRET: &str = "client_id";
+/// Secret holding the Azure AD client secret.
+pub const CLIENT_SECRET_SECRET: &str = "client_secret";
+/// Secret holding the Azure AD tenant ID.
+pub const TENANT_ID_SECRET: &str = "tenant_id";
+
+/// Source of named secrets, such as a client secret or a connection string, so credentials can
+/// be read from the environment, a file, or a vault instead of living in code.
+pub trait SecretProvider: Send + Sync {
+    /// The secret called `name`, or `None` when the provider has no such secret.
+    fn get_secret<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<String>, String>>;
+
+    /// The secret called `name`, failing when the provider has no such secret.
+    fn require_secret<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<String, String>> {
+        Box::pin(async move {
+            self.get_secret(name)
+                .await?
+                .ok_or_else(|| format!("Secret '{name}' not found"))
+        })
+    }
+}
+
+/// Reads secrets from environment variables. The variable for a secret is its name in upper
+/// case, with characters other than letters and digits replaced by `_` and an optional prefix,
+/// so `client_secret` is read from `DATAVERSE_CLIENT_SECRET` with the prefix `DATAVERSE_`.
+#[derive(Debug, Clone, Default)]
+pub struct EnvSecretProvider {
+    prefix: String,
+}
+
+impl EnvSecretProvider {
+    /// A provider reading variables without a prefix.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A provider reading variables that start with `prefix`.
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    /// The environment variable read for the secret `name`.
+    pub fn variable_name(&self, name: &str) -> String {
+        let name = name
+            .chars()
+            .map(|ch| {
+                if ch.is_ascii_alphanumeric() {
+                    ch.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        format!("{}{name}", self.prefix)
+    }
+}
+
+impl SecretProvider for EnvSecretProvider {
+    fn get_secret<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<String>, String>> {
+        let value = std::env::var(self.variable_name(name))
+            .ok()
+            .filter(|value| !value.is_empty());
+        Box::pin(async move { Ok(value) })
+    }
+}
+
+/// Reads secrets from the top-level string properties of a JSON file, such as the samples'
+/// `secrets.json`. The file is read once, when the provider is opened.
+#[derive(Clone, Default)]
+pub struct JsonFileSecretProvider {
+    secrets: HashMap<String, String>,
+}
+
+impl JsonFileSecretProvider {
+    /// Read the secrets in the JSON object at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read secrets file {}: {e}", path.display()))?;
+        Self::from_json(&contents)
+            .map_err(|e| format!("Invalid secrets file {}: {e}", path.display()))
+    }
+
+    /// Read the secrets in a JSON object.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value = serde_json::from_str::<Value>(json).map_err(|e| e.to_string())?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| "expected a JSON object".to_string())?;
+        Ok(Self {
+            secrets: object
+                .iter()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+                .collect(),
+        })
+    }
+}
+
+impl SecretProvider for JsonFileSecretProvider {
+    fn get_secret<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<String>, String>> {
+        let value = self.secrets.get(name).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+}
+
+#[cfg(feature = "key-vault")]
+pub use keyvault::KeyVaultSecretProvider;
+
+#[cfg(feature = "key-vault")]
+mod keyvault {
+    use std::env;
+
+    use futures_util::future::BoxFuture;
+    use reqwest::{Client, StatusCode};
+    use serde_json::Value;
+
+    use super::SecretProvider;
+    use crate::auth::credentials::fetch_client_credentials_token_with_expiry;
+
+    const KEY_VAULT_API_VERSION: &str = "7.4";
+    const KEY_VAULT_RESOURCE: &str = "https://vault.azure.net";
+    const IMDS_TOKEN_URL: &str = "http://169.254.169.254/metadata/identity/oauth2/token";
+
+    enum KeyVaultCredential {
+        ClientCredentials {
+            client_id: String,
+            client_secret: String,
+            tenant_id: String,
+        },
+        ManagedIdentity {
+            client_id: Option<String>,
+        },
+        AccessToken(String),
+    }
+
+    /// Reads secrets from an Azure Key Vault, such as `https://contoso.vault.azure.net`.
+    ///
+    /// Key Vault secret names allow only letters, digits, and dashes, so other characters in a
+    /// name are replaced by `-`: `client_secret` is read from the secret `client-secret`. A
+    /// token for Key Vault is requested on every lookup.
+    pub struct KeyVaultSecretProvider {
+        vault_url: String,
+        credential: KeyVaultCredential,
+        client: Client,
+    }
+
+    impl KeyVaultSecretProvider {
+        /// Authenticate to the vault as an app registration.
+        pub fn with_client_credentials(
+            vault_url: &str,
+            client_id: impl Into<String>,
+            client_secret: impl Into<String>,
+            tenant_id: impl Into<String>,
+        ) -> Result<Self, String> {
+            Self::new(
+                vault_url,
+                KeyVaultCredential::ClientCredentials {
+                    client_id: client_id.into(),
+                    client_secret: client_secret.into(),
+                    tenant_id: tenant_id.into(),
+                },
+            )
+        }
+
+        /// Authenticate to the vault with the managed identity of the Azure host, such as a
+        /// virtual machine or App Service. `client_id` selects a user-assigned identity.
+        pub fn with_managed_identity(
+            vault_url: &str,
+            client_id: Option<String>,
+        ) -> Result<Self, String> {
+            Self::new(vault_url, KeyVaultCredential::ManagedIdentity { client_id })
+        }
+
+        /// Authenticate to the vault with a token for `https://vault.azure.net` obtained
+        /// elsewhere. The token is never refreshed.
+        pub fn with_access_token(
+            vault_url: &str,
+            access_token: impl Into<String>,
+        ) -> Result<Self, String> {
+            Self::new(
+                vault_url,
+                KeyVaultCredential::AccessToken(access_token.into()),
+            )
+        }
+
+        fn new(vault_url: &str, credential: KeyVaultCredential) -> Result<Self, String> {
+            let vault_url = vault_url.trim().trim_end_matches('/');
+            if !vault_url.starts_with("https://") || vault_url.len() == "https://".len() {
+                return Err(format!(
+                    "Invalid Key Vault URL '{vault_url}': expected https://<vault>.vault.azure.net"
+                ));
+            }
+            Ok(Self {
+                vault_url: vault_url.to_string(),
+                credential,
+                client: Client::new(),
+            })
+        }
+
+        /// URL of the latest version of the secret `name`.
+        pub(super) fn secret_url(&self, name: &str) -> String {
+            format!(
+                "{}/secrets/{}?api-version={KEY_VAULT_API_VERSION}",
+                self.vault_url,
+                key_vault_secret_name(name)
+            )
+        }
+
+        async fn access_token(&self) -> Result<String, String> {
+            match &self.credential {
+                KeyVaultCredential::ClientCredentials {
+                    client_id,
+                    client_secret,
+                    tenant_id,
+                } => fetch_client_credentials_token_with_expiry(
+                    client_id,
+                    client_secret,
+                    tenant_id,
+                    &format!("{KEY_VAULT_RESOURCE}/.default"),
+                )
+                .await
+                .map(|token| token.access_token),
+                KeyVaultCredential::ManagedIdentity { client_id } => {
+                    managed_identity_token(&self.client, client_id.as_deref()).await
+                }
+                KeyVaultCredential::AccessToken(access_token) => Ok(access_token.clone()),
+            }
+        }
+    }
+
+    impl SecretProvider for KeyVaultSecretProvider {
+        fn get_secret<'a>(
+            &'a self,
+            name: &'a str,
+        ) -> BoxFuture<'a, Result<Option<String>, String>> {
+            Box::pin(async move {
+                let access_token = self.access_token().await?;
+                let resp = self
+                    .client
+                    .get(self.secret_url(name))
+                    .bearer_auth(access_token)
+                    .send()
+                    .await
+                    .map_err(|e| format!("Key Vault request failed: {e}"))?;
+                let status = resp.status();
+                if status == StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                let body = resp.text().await.unwrap_or_default();
+                if !status.is_success() {
+                    return Err(format!("Key Vault request failed ({status}): {body}"));
+                }
+                let json = serde_json::from_str::<Value>(&body)
+                    .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+                json.get("value")
+                    .and_then(Value::as_str)
+                    .map(|value| Some(value.to_string()))
+                    .ok_or_else(|| "Invalid response from Key Vault".to_string())
+            })
+        }
+    }
+
+    /// Key Vault secret names allow only letters, digits, and dashes.
+    fn key_vault_secret_name(name: &str) -> String {
+        name.chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '-' })
+            .collect()
+    }
+
+    /// A Key Vault token from the App Service identity endpoint when the host provides one,
+    /// otherwise from the Azure Instance Metadata Service.
+    async fn managed_identity_token(
+        client: &Client,
+        client_id: Option<&str>,
+    ) -> Result<String, String> {
+        let mut query = vec![("resource", KEY_VAULT_RESOURCE)];
+        if let Some(client_id) = client_id {
+            query.push(("client_id", client_id));
+        }
+        let request = match (env::var("IDENTITY_ENDPOINT"), env::var("IDENTITY_HEADER")) {
+            (Ok(endpoint), Ok(header)) => {
+                query.push(("api-version", "2019-08-01"));
+                client.get(endpoint).header("X-IDENTITY-HEADER", header)
+            }
+            _ => {
+                query.push(("api-version", "2018-02-01"));
+                client.get(IMDS_TOKEN_URL).header("Metadata", "true")
+            }
+        };
+        let resp = request
+            .query(&query)
+            .send()
+            .await
+            .map_err(|e| format!("Managed identity token request failed: {e}"))?;
+        let status = resp.status();
+        let body = resp.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(format!(
+                "Managed identity token request failed ({status}): {body}"
+            ));
+        }
+        serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|json| json.get("access_token")?.as_str().map(str::to_string))
+            .ok_or_else(|| "No access_token in managed identity response".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EnvSecretProvider, JsonFileSecretProvider, SecretProvider};
+    use crate::auth::config::AuthConfig;
+
+    #[tokio::test]
+    async fn reads_secrets_and_builds_auth_config() {
+        let provider = JsonFileSecretProvider::from_json(
+            r#"{
+                "connection_string": "AuthType=ClientSecret;Url=https://contoso.crm.dynamics.com;ClientId=client;ClientSecret=secret;TenantId=tenant",
+                "client_id": "client",
+                "client_secret": "secret",
+                "tenant_id": "tenant",
+                "timeout": 30
+            }"#,
+        )
+        .expect("should parse");
+        assert_eq!(provider.get_secret("timeout").await, Ok(None));
+        assert_eq!(
+            provider.require_secret("missing").await,
+            Err("Secret 'missing' not found".to_string())
+        );
+
+        let from_connection_string =
+            AuthConfig::from_secret_connection_string(&provider, "connection_string")
+                .await
+                .expect("should build");
+        assert!(matches!(
+            from_connection_string,
+            AuthConfig::ClientCredentials { client_secret, .. } if client_secret == "secret"
+        ));
+        let from_parts = AuthConfig::client_credentials_from_secrets(
+            &provider,
+            "https://contoso.crm.dynamics.com",
+        )
+        .await
+        .expect("should build");
+        assert!(matches!(
+            from_parts,
+            AuthConfig::ClientCredentials { tenant_id, .. } if tenant_id == "tenant"
+        ));
+
+        assert!(JsonFileSecretProvider::from_json("[]").is_err());
+        assert_eq!(
+            EnvSecretProvider::with_prefix("DATAVERSE_").variable_name("client-secret"),
+            "DATAVERSE_CLIENT_SECRET"
+        );
+    }
+
+    #[cfg(feature = "key-vault")]
+    #[test]
+    fn maps_key_vault_secret_urls() {
+        use super::KeyVaultSecretProvider;
+
+        let provider =
+            KeyVaultSecretProvider::with_access_token("https://contoso.vault.azure.net/", "token")
+                .expect("should build");
+        assert_eq!(
+            provider.secret_url("client_secret"),
+            "https://contoso.vault.azure.net/secrets/client-secret?api-version=7.4"
+        );
+        assert!(KeyVaultSecretProvider::with_access_token("contoso", "token").is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -6,17 +7,21 @@ use base64::Engine;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::LogLevel;
 use crate::auth::config::AuthConfig;
 use crate::auth::devicecode::DeviceCodeFlowEvent;
 use crate::auth::credentials::{
     fetch_client_credentials_token_with_expiry, fetch_device_code_token_exchange_from_parts,
     fetch_device_code_token_exchange_from_parts_with_progress,
 };
+use crate::auth::events::{TokenFlow, TokenOperation, observe_token_request};
+use crate::dataverse::transport::REDACTED;
 
-const REFRESH_SKEW_SECS: u64 = 300;
+/// Tokens are refreshed when they expire within this many seconds.
+pub(crate) const REFRESH_SKEW_SECS: u64 = 300;
 
-/// Cached access token and optional expiry.
-#[derive(Clone, Debug)]
+/// Cached access token and optional expiry. The `Debug` output redacts both tokens.
+#[derive(Clone)]
 pub(crate) struct CachedToken {
     /// OAuth access token.
     pub access_token: String,
@@ -26,7 +31,20 @@ pub(crate) struct CachedToken {
     pub expires_at: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl fmt::Debug for CachedToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedToken")
+            .field("access_token", &REDACTED)
+            .field(
+                "refresh_token",
+                &self.refresh_token.as_ref().map(|_| REDACTED),
+            )
+            .field("expires_at", &self.expires_at)
+            .finish()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 struct TokenCacheFile {
     access_token: String,
     refresh_token: Option<String>,
@@ -42,13 +60,18 @@ fn now_secs() -> u64 {
 
 /// Returns true if the token is missing or nearing expiry.
 pub(crate) fn is_expiring_soon(expires_at: Option<u64>) -> bool {
+    expires_within(expires_at, REFRESH_SKEW_SECS)
+}
+
+/// Returns true if the expiry is unknown or within `secs` seconds from now.
+pub(crate) fn expires_within(expires_at: Option<u64>, secs: u64) -> bool {
     let Some(exp) = expires_at else {
         return true;
     };
-    now_secs() + REFRESH_SKEW_SECS >= exp
+    now_secs().saturating_add(secs) >= exp
 }
 
-fn parse_jwt_expiry(access_token: &str) -> Option<u64> {
+pub(crate) fn parse_jwt_expiry(access_token: &str) -> Option<u64> {
     // The cache file intentionally stores only the token strings so it stays compatible with the
     // original connection-string-driven tooling. Expiry is recovered from the JWT payload when
     // possible instead of being duplicated into a second persisted field.
@@ -60,40 +83,40 @@ fn parse_jwt_expiry(access_token: &str) -> Option<u64> {
     json.get("exp").and_then(|value| value.as_u64())
 }
 
-pub(crate) async fn fetch_token_for_config(auth: &AuthConfig) -> Result<CachedToken, String> {
-    fetch_token_for_config_with_progress(auth, Option::<&fn(DeviceCodeFlowEvent)>::None).await
+/// Acquire a new token for `auth`. `allow_insecure_url` accepts the URLs that
+/// `DataverseUrl::parse_insecure` allows, as set by `ServiceClientBuilder::allow_insecure_url`.
+pub(crate) async fn fetch_token_for_config(
+    auth: &AuthConfig,
+    allow_insecure_url: bool,
+    log_level: LogLevel,
+) -> Result<CachedToken, String> {
+    fetch_token_for_config_with_progress(
+        auth,
+        allow_insecure_url,
+        Option::<&fn(DeviceCodeFlowEvent)>::None,
+        log_level,
+    )
+    .await
 }
 
 pub(crate) async fn fetch_token_for_config_with_progress<F>(
     auth: &AuthConfig,
+    allow_insecure_url: bool,
     progress: Option<&F>,
+    log_level: LogLevel,
 ) -> Result<CachedToken, String>
 where
     F: Fn(DeviceCodeFlowEvent) + Send + Sync,
 {
     match auth {
-        AuthConfig::ClientCredentials {
-            client_id,
-            client_secret,
-            tenant_id,
-            ..
-        } => {
-            let scope = auth
-                .scope()
-                .ok_or("Client credentials auth config missing scope".to_string())?;
-            let token = fetch_client_credentials_token_with_expiry(
-                client_id,
-                client_secret,
-                tenant_id,
-                &scope,
+        AuthConfig::ClientCredentials { .. } => {
+            fetch_client_credentials_for_config(
+                auth,
+                TokenOperation::Acquire,
+                allow_insecure_url,
+                log_level,
             )
-            .await?;
-
-            Ok(CachedToken {
-                access_token: token.access_token,
-                refresh_token: None,
-                expires_at: Some(token.expires_at),
-            })
+            .await
         }
         AuthConfig::DeviceCode {
             client_id,
@@ -101,18 +124,32 @@ where
             tenant_id,
             ..
         } => {
-            let token = if progress.is_some() {
-                fetch_device_code_token_exchange_from_parts_with_progress(
-                    client_id,
-                    dataverse_url,
-                    tenant_id,
-                    progress,
-                )
-                .await?
-            } else {
-                fetch_device_code_token_exchange_from_parts(client_id, dataverse_url, tenant_id)
-                    .await?
-            };
+            let token = observe_token_request(
+                log_level,
+                TokenOperation::Acquire,
+                TokenFlow::DeviceCode,
+                async {
+                    if progress.is_some() {
+                        fetch_device_code_token_exchange_from_parts_with_progress(
+                            client_id,
+                            dataverse_url,
+                            tenant_id,
+                            allow_insecure_url,
+                            progress,
+                        )
+                        .await
+                    } else {
+                        fetch_device_code_token_exchange_from_parts(
+                            client_id,
+                            dataverse_url,
+                            tenant_id,
+                            allow_insecure_url,
+                        )
+                        .await
+                    }
+                },
+            )
+            .await?;
 
             Ok(CachedToken {
                 expires_at: Some(token.expires_at),
@@ -123,6 +160,41 @@ where
     }
 }
 
+/// Request a new client credentials token for `auth`, reported as `operation`. Client
+/// credentials have no refresh token, so a refresh is a new request of the same kind.
+pub(crate) async fn fetch_client_credentials_for_config(
+    auth: &AuthConfig,
+    operation: TokenOperation,
+    allow_insecure_url: bool,
+    log_level: LogLevel,
+) -> Result<CachedToken, String> {
+    let AuthConfig::ClientCredentials {
+        client_id,
+        client_secret,
+        tenant_id,
+        ..
+    } = auth
+    else {
+        return Err("Auth config is not client credentials".to_string());
+    };
+    let scope = auth
+        .scope(allow_insecure_url)?
+        .ok_or("Client credentials auth config missing scope".to_string())?;
+    let token = observe_token_request(
+        log_level,
+        operation,
+        TokenFlow::ClientCredentials,
+        fetch_client_credentials_token_with_expiry(client_id, client_secret, tenant_id, &scope),
+    )
+    .await?;
+
+    Ok(CachedToken {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_at: Some(token.expires_at),
+    })
+}
+
 pub(crate) fn load_cached_token(path: &Path) -> Result<Option<CachedToken>, String> {
     if !path.exists() {
         return Ok(None);
@@ -224,19 +296,12 @@ fn looks_like_file_path(path: &Path) -> bool {
 #[cfg(test)]
 mod tests {
     use std::fs;
-    use std::path::PathBuf;
 
     use super::{
         CachedToken, is_expiring_soon, load_cached_token, normalize_token_cache_path,
         parse_jwt_expiry, save_cached_token,
     };
-
-    fn unique_temp_path(name: &str) -> PathBuf {
-        std::env::temp_dir().join(format!(
-            "powerplatform_dataverse_client_{name}_{}",
-            uuid::Uuid::new_v4()
-        ))
-    }
+    use crate::testsupport::unique_temp_path;
 
     #[test]
     fn reports_missing_or_nearby_expiry_as_expiring() {
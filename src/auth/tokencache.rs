@@ -0,0 +1,368 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use log::{Level, warn};
+use tokio::sync::Mutex;
+use tokio::task::AbortHandle;
+use tokio::time::{MissedTickBehavior, interval_at};
+
+use crate::LogLevel;
+use crate::auth::config::AuthConfig;
+use crate::auth::credentials::{TokenExchange, refresh_device_code_token};
+use crate::auth::events::{TokenFlow, TokenOperation, log_cached_token, observe_token_request};
+use crate::auth::token::{
+    CachedToken, REFRESH_SKEW_SECS, expires_within, fetch_client_credentials_for_config,
+    fetch_token_for_config, is_expiring_soon, load_cached_token, resolve_token_cache_file_path,
+    save_cached_token,
+};
+use crate::dataverse::dataverseurl::DataverseUrl;
+use crate::log::sanitize_message;
+
+/// Access token state for one auth configuration, shared by every `ServiceClient` built from it.
+///
+/// Clients refresh the token on demand when it is within five minutes of expiry. A cache started
+/// with `spawn_auto_refresh` also renews it ahead of time on a background task, so requests do
+/// not wait for a token refresh.
+pub struct TokenCache {
+    // `None` for a static token, which is sent as is and never refreshed.
+    auth: Option<AuthConfig>,
+    // Set from `ServiceClientBuilder::allow_insecure_url`, so renewals accept the same URLs.
+    allow_insecure_url: bool,
+    path: PathBuf,
+    token: Mutex<CachedToken>,
+    // Held for the whole of a refresh, while `token` is only locked to read or swap the token, so
+    // callers holding a valid token never wait on a refresh in progress.
+    refreshing: Mutex<()>,
+    // The task started by `spawn_auto_refresh`, until `stop_auto_refresh` aborts it.
+    refresher: std::sync::Mutex<Option<AbortHandle>>,
+}
+
+impl TokenCache {
+    /// Load the token for `auth` from its token cache file, acquiring a new one when the cached
+    /// token is missing or close to expiry. Token events are logged at the default `LogLevel`.
+    pub async fn load(auth: AuthConfig) -> Result<Self, String> {
+        Self::load_with_options(auth, false, LogLevel::default()).await
+    }
+
+    /// Load the token for `auth` and renew it every `interval` on a background tokio task,
+    /// whenever it would otherwise come within five minutes of expiry before the next renewal.
+    ///
+    /// Pass the cache to `ServiceClientBuilder::token_cache` to use it. The task stops when
+    /// `stop_auto_refresh` is called, when a client using the cache is shut down, or once the
+    /// cache and every client using it have been dropped. Failed renewals are logged and tried
+    /// again on the next tick. Must be called within a tokio runtime.
+    pub async fn spawn_auto_refresh(
+        auth: AuthConfig,
+        interval: Duration,
+    ) -> Result<Arc<Self>, String> {
+        if interval.is_zero() {
+            return Err("Token auto-refresh interval must be greater than zero".to_string());
+        }
+        let cache = Arc::new(Self::load(auth).await?);
+        cache.start_auto_refresh(interval, LogLevel::default());
+        Ok(cache)
+    }
+
+    /// Stop the task started by `spawn_auto_refresh`. Tokens are still refreshed on demand when
+    /// requests find them close to expiry. Does nothing when no task is running.
+    pub fn stop_auto_refresh(&self) {
+        if let Some(refresher) = self
+            .refresher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .take()
+        {
+            refresher.abort();
+        }
+    }
+
+    /// Expiry of the current token as seconds since the Unix epoch, when known.
+    pub async fn expires_at(&self) -> Option<u64> {
+        self.token.lock().await.expires_at
+    }
+
+    /// `load`, accepting the URLs `DataverseUrl::parse_insecure` allows when
+    /// `allow_insecure_url` is set.
+    pub(crate) async fn load_with_options(
+        auth: AuthConfig,
+        allow_insecure_url: bool,
+        log_level: LogLevel,
+    ) -> Result<Self, String> {
+        let path = resolve_token_cache_file_path(&auth)?;
+        let token = match load_cached_token(&path)? {
+            Some(cached)
+                if !cached.access_token.trim().is_empty()
+                    && !is_expiring_soon(cached.expires_at) =>
+            {
+                log_cached_token(log_level, cached.expires_at);
+                cached
+            }
+            _ => {
+                let fetched = fetch_token_for_config(&auth, allow_insecure_url, log_level).await?;
+                save_cached_token(&path, &fetched)?;
+                fetched
+            }
+        };
+        let mut cache = Self::fixed(Some(auth), path, token);
+        cache.allow_insecure_url = allow_insecure_url;
+        Ok(cache)
+    }
+
+    /// A cache holding `token` as is, refreshed with `auth` when it is set.
+    pub(crate) fn fixed(auth: Option<AuthConfig>, path: PathBuf, token: CachedToken) -> Self {
+        Self {
+            auth,
+            allow_insecure_url: false,
+            path,
+            token: Mutex::new(token),
+            refreshing: Mutex::new(()),
+            refresher: std::sync::Mutex::new(None),
+        }
+    }
+
+    /// Renew the token every `interval` on a background task, replacing any earlier task.
+    fn start_auto_refresh(self: &Arc<Self>, interval: Duration, log_level: LogLevel) {
+        let task = tokio::spawn(auto_refresh(Arc::downgrade(self), interval, log_level));
+        let earlier = self
+            .refresher
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .replace(task.abort_handle());
+        if let Some(earlier) = earlier {
+            earlier.abort();
+        }
+    }
+
+    pub(crate) fn auth(&self) -> Option<&AuthConfig> {
+        self.auth.as_ref()
+    }
+
+    /// The access token to send, refreshed first when it is close to expiry.
+    pub(crate) async fn access_token(&self, log_level: LogLevel) -> Result<String, String> {
+        self.renew_within(REFRESH_SKEW_SECS, log_level).await
+    }
+
+    /// Renew the token when it expires within `margin_secs`, returning the token to send.
+    async fn renew_within(&self, margin_secs: u64, log_level: LogLevel) -> Result<String, String> {
+        let Some(auth) = &self.auth else {
+            // A static token is the caller's to renew; send it until Dataverse rejects it.
+            return Ok(self.token.lock().await.access_token.clone());
+        };
+        if let Some(access_token) = self.valid_token(margin_secs).await {
+            return Ok(access_token);
+        }
+
+        // Callers that waited here find the token renewed by the first and send it, instead of
+        // racing into multiple refreshes that stomp each other's cache file updates.
+        let _refreshing = self.refreshing.lock().await;
+        if let Some(access_token) = self.valid_token(margin_secs).await {
+            return Ok(access_token);
+        }
+        let refresh_token = self.token.lock().await.refresh_token.clone();
+        let refreshed =
+            renew_token(auth, refresh_token, self.allow_insecure_url, log_level).await?;
+
+        save_cached_token(&self.path, &refreshed)?;
+        let access_token = refreshed.access_token.clone();
+        *self.token.lock().await = refreshed;
+        Ok(access_token)
+    }
+
+    async fn valid_token(&self, margin_secs: u64) -> Option<String> {
+        let token = self.token.lock().await;
+        (!token.access_token.trim().is_empty() && !expires_within(token.expires_at, margin_secs))
+            .then(|| token.access_token.clone())
+    }
+}
+
+/// Acquire a replacement for the token of `auth`, using `refresh_token` for delegated auth.
+async fn renew_token(
+    auth: &AuthConfig,
+    refresh_token: Option<String>,
+    allow_insecure_url: bool,
+    log_level: LogLevel,
+) -> Result<CachedToken, String> {
+    match auth {
+        AuthConfig::ClientCredentials { .. } => {
+            fetch_client_credentials_for_config(
+                auth,
+                TokenOperation::Refresh,
+                allow_insecure_url,
+                log_level,
+            )
+            .await
+        }
+        AuthConfig::DeviceCode {
+            client_id,
+            dataverse_url,
+            tenant_id,
+            ..
+        } => {
+            let refresh_token = refresh_token
+                .ok_or("Device code token cannot refresh without a refresh token".to_string())?;
+            let scope = format!(
+                "{}/user_impersonation offline_access openid profile",
+                DataverseUrl::parse_with(dataverse_url, allow_insecure_url)?
+            );
+            let token: TokenExchange = observe_token_request(
+                log_level,
+                TokenOperation::Refresh,
+                TokenFlow::RefreshToken,
+                refresh_device_code_token(client_id, tenant_id, &scope, &refresh_token),
+            )
+            .await?;
+
+            Ok(CachedToken {
+                access_token: token.access_token,
+                refresh_token: Some(token.refresh_token),
+                expires_at: Some(token.expires_at),
+            })
+        }
+    }
+}
+
+/// Renew the token of `cache` every `interval` until the cache is dropped.
+async fn auto_refresh(cache: Weak<TokenCache>, interval: Duration, log_level: LogLevel) {
+    // Renewing while a full interval remains before the on-demand threshold means requests
+    // never find the token close to expiry.
+    let margin_secs = REFRESH_SKEW_SECS.saturating_add(interval.as_secs());
+    refresh_loop(interval, log_level, || {
+        cache
+            .upgrade()
+            .map(|cache| async move { cache.renew_within(margin_secs, log_level).await.map(drop) })
+    })
+    .await;
+}
+
+/// Run `renew` every `interval`, starting one interval from now, until it returns `None`.
+/// Failed renewals are logged and tried again on the next tick.
+async fn refresh_loop<F, Fut>(interval: Duration, log_level: LogLevel, mut renew: F)
+where
+    F: FnMut() -> Option<Fut>,
+    Fut: Future<Output = Result<(), String>>,
+{
+    let mut ticks = interval_at(tokio::time::Instant::now() + interval, interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        let Some(renewal) = renew() else {
+            return;
+        };
+        if let Err(error) = renewal.await
+            && log_level.includes(Level::Warn)
+        {
+            warn!("token auto-refresh failed: {}", sanitize_message(&error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use crate::LogLevel;
+    use crate::auth::config::AuthConfig;
+    use crate::auth::token::CachedToken;
+
+    use super::{TokenCache, refresh_loop};
+
+    #[tokio::test]
+    async fn renews_only_within_the_margin() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("clock")
+            .as_secs();
+        let auth = AuthConfig::client_credentials_for_environment(
+            "client",
+            "secret",
+            "tenant",
+            "https://contoso.crm.dynamics.com",
+        )
+        .expect("auth");
+        let cache = TokenCache::fixed(
+            Some(auth),
+            PathBuf::new(),
+            CachedToken {
+                access_token: "current".to_string(),
+                refresh_token: None,
+                expires_at: Some(now + 600),
+            },
+        );
+
+        // On demand, a token ten minutes from expiry is still sent as is.
+        assert_eq!(cache.valid_token(300).await, Some("current".to_string()));
+        // Ahead of time, the auto-refresh margin of five minutes plus the interval covers it.
+        assert_eq!(cache.valid_token(300 + 3600).await, None);
+    }
+
+    #[tokio::test]
+    async fn static_token_is_sent_without_refresh() {
+        let cache = TokenCache::fixed(
+            None,
+            PathBuf::new(),
+            CachedToken {
+                access_token: "static".to_string(),
+                refresh_token: None,
+                expires_at: Some(0),
+            },
+        );
+        assert_eq!(
+            cache.access_token(LogLevel::Off).await,
+            Ok("static".to_string())
+        );
+        assert_eq!(cache.expires_at().await, Some(0));
+    }
+
+    #[tokio::test]
+    async fn refresh_loop_renews_each_tick_and_retries_failures() {
+        let renewals = AtomicUsize::new(0);
+
+        refresh_loop(Duration::from_millis(5), LogLevel::Off, || {
+            let renewal = renewals.fetch_add(1, Ordering::SeqCst);
+            (renewal < 3).then_some(async move {
+                if renewal == 1 {
+                    Err("token endpoint unavailable".to_string())
+                } else {
+                    Ok(())
+                }
+            })
+        })
+        .await;
+
+        // Three renewals, the second failing, then the tick that found the cache gone.
+        assert_eq!(renewals.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn stop_auto_refresh_aborts_the_task() {
+        let cache = Arc::new(TokenCache::fixed(
+            None,
+            PathBuf::new(),
+            CachedToken {
+                access_token: "static".to_string(),
+                refresh_token: None,
+                expires_at: None,
+            },
+        ));
+        cache.start_auto_refresh(Duration::from_secs(3600), LogLevel::Off);
+        let refresher = cache
+            .refresher
+            .lock()
+            .expect("lock")
+            .clone()
+            .expect("running");
+        assert!(!refresher.is_finished());
+
+        cache.stop_auto_refresh();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        assert!(refresher.is_finished());
+        assert!(cache.refresher.lock().expect("lock").is_none());
+        cache.stop_auto_refresh();
+    }
+}
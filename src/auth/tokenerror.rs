@@ -0,0 +1,190 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+/// Prefix of every error message built from a failed token endpoint response.
+const TOKEN_ERROR_PREFIX: &str = "Token request failed (";
+
+/// The client secret is not valid for the app registration.
+pub const INVALID_CLIENT_SECRET: i64 = 7000215;
+/// The client secret has expired.
+pub const EXPIRED_CLIENT_SECRET: i64 = 7000222;
+/// No app registration with the client ID exists in the tenant.
+pub const APPLICATION_NOT_FOUND: i64 = 700016;
+/// The user or an administrator has not consented to the app.
+pub const CONSENT_REQUIRED: i64 = 65001;
+
+/// Broad category of a token endpoint error, for deciding how to react.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenErrorKind {
+    /// The client ID or secret is wrong, expired, or unknown to the tenant. Fix the app
+    /// registration or configuration; retrying does not help.
+    InvalidClient,
+    /// The app needs user or admin consent for the requested scope.
+    ConsentRequired,
+    /// The user must sign in interactively, for example to complete multifactor authentication.
+    InteractionRequired,
+    /// The refresh token, authorization code, or device code is invalid or expired. Sign in
+    /// again.
+    InvalidGrant,
+    /// Microsoft Entra ID is temporarily unavailable or throttling. Token requests are retried
+    /// before this is returned.
+    TemporarilyUnavailable,
+    /// Any other error.
+    Other,
+}
+
+/// A failed token endpoint response, parsed from the Microsoft Entra ID error body.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenError {
+    /// HTTP status code.
+    pub status_code: u16,
+    /// OAuth `error` code, such as `invalid_client`.
+    pub error: Option<String>,
+    /// `error_description`, which starts with the `AADSTS` code and message.
+    pub error_description: Option<String>,
+    /// Numeric `AADSTS` codes, such as `7000215`.
+    pub error_codes: Vec<i64>,
+    /// `correlation_id` to quote to Microsoft support.
+    pub correlation_id: Option<String>,
+    /// `trace_id` of the failed request.
+    pub trace_id: Option<String>,
+    /// Response body as returned.
+    pub raw_body: String,
+}
+
+impl TokenError {
+    /// Parse a token endpoint response body. Bodies that are not OAuth errors leave the fields
+    /// empty and keep the body in `raw_body`.
+    pub fn from_body(status_code: u16, body: &str) -> Self {
+        let mut error = Self {
+            status_code,
+            raw_body: body.to_string(),
+            ..Self::default()
+        };
+        let Some(json) = serde_json::from_str::<Value>(body)
+            .ok()
+            .filter(Value::is_object)
+        else {
+            return error;
+        };
+
+        let text = |key: &str| json.get(key).and_then(Value::as_str).map(str::to_string);
+        error.error = text("error");
+        error.error_description = text("error_description");
+        error.correlation_id = text("correlation_id");
+        error.trace_id = text("trace_id");
+        error.error_codes = json
+            .get("error_codes")
+            .and_then(Value::as_array)
+            .map(|codes| codes.iter().filter_map(Value::as_i64).collect())
+            .unwrap_or_default();
+        error
+    }
+
+    /// Parse the structured error from an error message returned by this crate, such as
+    /// `Token request failed (401 Unauthorized): {...}`. `None` for other errors, such as
+    /// connection failures.
+    pub fn parse(message: &str) -> Option<Self> {
+        let rest = message.strip_prefix(TOKEN_ERROR_PREFIX)?;
+        let status_code = rest.get(..3)?.parse::<u16>().ok()?;
+        let (_, body) = rest.split_once(')')?;
+        Some(Self::from_body(
+            status_code,
+            body.strip_prefix(": ").unwrap_or(body.trim_start()),
+        ))
+    }
+
+    /// True when the response carries the `AADSTS` code `code`.
+    pub fn has_code(&self, code: i64) -> bool {
+        self.error_codes.contains(&code)
+    }
+
+    /// Category of the error.
+    pub fn kind(&self) -> TokenErrorKind {
+        if [
+            INVALID_CLIENT_SECRET,
+            EXPIRED_CLIENT_SECRET,
+            APPLICATION_NOT_FOUND,
+        ]
+        .iter()
+        .any(|code| self.has_code(*code))
+        {
+            return TokenErrorKind::InvalidClient;
+        }
+        if self.has_code(CONSENT_REQUIRED) {
+            return TokenErrorKind::ConsentRequired;
+        }
+        match self.error.as_deref() {
+            Some("invalid_client" | "unauthorized_client") => TokenErrorKind::InvalidClient,
+            Some("consent_required") => TokenErrorKind::ConsentRequired,
+            Some("interaction_required") => TokenErrorKind::InteractionRequired,
+            Some("invalid_grant" | "expired_token") => TokenErrorKind::InvalidGrant,
+            Some("temporarily_unavailable") => TokenErrorKind::TemporarilyUnavailable,
+            _ if self.status_code == 429 || self.status_code >= 500 => {
+                TokenErrorKind::TemporarilyUnavailable
+            }
+            _ => TokenErrorKind::Other,
+        }
+    }
+
+    /// True for errors worth retrying unchanged.
+    pub fn is_transient(&self) -> bool {
+        self.kind() == TokenErrorKind::TemporarilyUnavailable
+    }
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.error, &self.error_description) {
+            (Some(error), Some(description)) => write!(f, "{error}: {description}"),
+            (Some(error), None) => f.write_str(error),
+            _ => f.write_str(&self.raw_body),
+        }
+    }
+}
+
+/// Error message for a failed token endpoint response, readable by `TokenError::parse`.
+pub(crate) fn token_error(status: StatusCode, body: &str) -> String {
+    format!("{TOKEN_ERROR_PREFIX}{status}): {body}")
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::{TokenError, TokenErrorKind, token_error};
+
+    const EXPIRED_SECRET_BODY: &str = r#"{"error":"invalid_client","error_description":"AADSTS7000222: The provided client secret keys for app '00000000-0000-0000-0000-000000000001' are expired.","error_codes":[7000222],"timestamp":"2025-01-01 00:00:00Z","trace_id":"trace","correlation_id":"correlation"}"#;
+
+    #[test]
+    fn parses_and_classifies_token_errors() {
+        let error = TokenError::parse(&token_error(StatusCode::UNAUTHORIZED, EXPIRED_SECRET_BODY))
+            .expect("should parse");
+        assert_eq!(error.status_code, 401);
+        assert_eq!(error.error.as_deref(), Some("invalid_client"));
+        assert_eq!(error.error_codes, vec![7000222]);
+        assert_eq!(error.correlation_id.as_deref(), Some("correlation"));
+        assert_eq!(error.kind(), TokenErrorKind::InvalidClient);
+        assert!(!error.is_transient());
+        assert!(
+            error
+                .to_string()
+                .starts_with("invalid_client: AADSTS7000222")
+        );
+
+        let consent = TokenError::from_body(
+            400,
+            r#"{"error":"invalid_grant","error_description":"AADSTS65001: consent","error_codes":[65001]}"#,
+        );
+        assert_eq!(consent.kind(), TokenErrorKind::ConsentRequired);
+
+        let unavailable = TokenError::from_body(503, "Service Unavailable");
+        assert_eq!(unavailable.kind(), TokenErrorKind::TemporarilyUnavailable);
+        assert!(unavailable.is_transient());
+        assert_eq!(unavailable.to_string(), "Service Unavailable");
+
+        assert!(TokenError::parse("connection refused").is_none());
+    }
+}
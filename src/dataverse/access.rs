@@ -0,0 +1,189 @@
+use bitflags::bitflags;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::dataverse::entity::EntityReference;
+
+bitflags! {
+    /// Dataverse `AccessRights` granted on a shared row.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct AccessRights: u32 {
+        /// Read the row.
+        const READ = 1;
+        /// Update the row.
+        const WRITE = 2;
+        /// Associate other rows to this row.
+        const APPEND = 4;
+        /// Associate this row to other rows.
+        const APPEND_TO = 16;
+        /// Create rows.
+        const CREATE = 32;
+        /// Delete the row.
+        const DELETE = 65536;
+        /// Share the row with other principals.
+        const SHARE = 262144;
+        /// Assign the row to another owner.
+        const ASSIGN = 524288;
+    }
+}
+
+const ACCESS_RIGHT_NAMES: &[(AccessRights, &str)] = &[
+    (AccessRights::READ, "ReadAccess"),
+    (AccessRights::WRITE, "WriteAccess"),
+    (AccessRights::APPEND, "AppendAccess"),
+    (AccessRights::APPEND_TO, "AppendToAccess"),
+    (AccessRights::CREATE, "CreateAccess"),
+    (AccessRights::DELETE, "DeleteAccess"),
+    (AccessRights::SHARE, "ShareAccess"),
+    (AccessRights::ASSIGN, "AssignAccess"),
+];
+
+impl AccessRights {
+    /// Format as the comma-separated enum member list the Web API expects, such as
+    /// `ReadAccess, WriteAccess`. An empty set formats as `None`.
+    pub fn to_access_mask(&self) -> String {
+        let names = ACCESS_RIGHT_NAMES
+            .iter()
+            .filter(|(right, _)| self.contains(*right))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            "None".to_string()
+        } else {
+            names.join(", ")
+        }
+    }
+
+    /// Parse a Web API `AccessRights` value, ignoring unknown members.
+    pub fn from_access_mask(value: &str) -> Self {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter_map(|member| {
+                ACCESS_RIGHT_NAMES
+                    .iter()
+                    .find(|(_, name)| name.eq_ignore_ascii_case(member))
+                    .map(|(right, _)| *right)
+            })
+            .fold(AccessRights::empty(), |rights, right| rights | right)
+    }
+}
+
+/// Build an entity reference payload for an action parameter, such as
+/// `{"@odata.type": "Microsoft.Dynamics.CRM.account", "accountid": "..."}`.
+pub(crate) fn action_entity_reference(
+    logical_name: &str,
+    primary_id_attribute: &str,
+    id: Uuid,
+) -> Value {
+    json!({
+        "@odata.type": format!("Microsoft.Dynamics.CRM.{logical_name}"),
+        primary_id_attribute: id.as_hyphenated().to_string(),
+    })
+}
+
+/// Build the payload reference for a sharing principal (`systemuser` or `team`).
+pub(crate) fn principal_reference(principal: &EntityReference) -> Value {
+    action_entity_reference(
+        &principal.logical_name,
+        &format!("{}id", principal.logical_name),
+        principal.id,
+    )
+}
+
+/// Build the body for the `GrantAccess` and `ModifyAccess` actions.
+pub(crate) fn build_principal_access_body(
+    target: Value,
+    principal: &EntityReference,
+    access_rights: AccessRights,
+) -> Value {
+    json!({
+        "Target": target,
+        "PrincipalAccess": {
+            "Principal": principal_reference(principal),
+            "AccessMask": access_rights.to_access_mask(),
+        }
+    })
+}
+
+/// Read `AccessRights` from a `RetrievePrincipalAccess` response.
+pub(crate) fn parse_principal_access(json: &Value) -> Result<AccessRights, String> {
+    json.get("AccessRights")
+        .and_then(|value| value.as_str())
+        .map(AccessRights::from_access_mask)
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        AccessRights, action_entity_reference, build_principal_access_body, parse_principal_access,
+    };
+    use crate::dataverse::entity::EntityReference;
+
+    #[test]
+    fn access_mask_round_trips_through_member_names() {
+        let rights = AccessRights::READ | AccessRights::WRITE | AccessRights::SHARE;
+
+        assert_eq!(
+            rights.to_access_mask(),
+            "ReadAccess, WriteAccess, ShareAccess"
+        );
+        assert_eq!(
+            AccessRights::from_access_mask("ReadAccess, WriteAccess, ShareAccess"),
+            rights
+        );
+        assert_eq!(AccessRights::empty().to_access_mask(), "None");
+        assert_eq!(
+            AccessRights::from_access_mask("None"),
+            AccessRights::empty()
+        );
+    }
+
+    #[test]
+    fn builds_grant_access_payload() {
+        let target_id = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+        let principal_id = Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid");
+        let body = build_principal_access_body(
+            action_entity_reference("account", "accountid", target_id),
+            &EntityReference {
+                id: principal_id,
+                logical_name: "team".to_string(),
+                name: None,
+            },
+            AccessRights::READ,
+        );
+
+        assert_eq!(
+            body,
+            json!({
+                "Target": {
+                    "@odata.type": "Microsoft.Dynamics.CRM.account",
+                    "accountid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee"
+                },
+                "PrincipalAccess": {
+                    "Principal": {
+                        "@odata.type": "Microsoft.Dynamics.CRM.team",
+                        "teamid": "11111111-2222-3333-4444-555555555555"
+                    },
+                    "AccessMask": "ReadAccess"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parses_principal_access_response() {
+        let rights = parse_principal_access(&json!({
+            "AccessRights": "ReadAccess, AppendToAccess"
+        }))
+        .expect("should parse");
+
+        assert_eq!(rights, AccessRights::READ | AccessRights::APPEND_TO);
+        assert!(parse_principal_access(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::access::AccessRights;
+use crate::dataverse::entity::EntityReference;
+use crate::dataverse::executioncontext::SecurityRole;
+use crate::dataverse::provisioning::{SystemUser, TEAM_COLUMNS, Team};
+use crate::dataverse::url::{encode_query_value, row_path};
+
+/// A principal a row is shared with and the rights the share grants.
+#[derive(Debug, Clone)]
+pub struct SharedAccess {
+    /// `systemuser` or `team` the row is shared with.
+    pub principal: EntityReference,
+    /// Rights granted by the share.
+    pub access: AccessRights,
+}
+
+/// A team the user belongs to, with the security roles assigned to the team.
+#[derive(Debug, Clone)]
+pub struct TeamAccess {
+    /// The team.
+    pub team: Team,
+    /// Roles assigned to the team, which its members hold as well.
+    pub roles: Vec<SecurityRole>,
+}
+
+/// Why a user can or cannot reach a row: their effective rights alongside the ownership,
+/// shares, roles, and team memberships that produce them.
+#[derive(Debug, Clone)]
+pub struct AccessDiagnosis {
+    /// The user being diagnosed.
+    pub user: SystemUser,
+    /// The row being diagnosed.
+    pub record: EntityReference,
+    /// Effective rights from `RetrievePrincipalAccess`, combining roles, ownership, and shares.
+    pub access: AccessRights,
+    /// Owning user or team, or `None` for organization-owned tables.
+    pub owner: Option<EntityReference>,
+    /// Roles assigned to the user directly.
+    pub user_roles: Vec<SecurityRole>,
+    /// Teams the user belongs to and their roles.
+    pub teams: Vec<TeamAccess>,
+    /// Every principal the row is shared with, including ones unrelated to the user.
+    pub shares: Vec<SharedAccess>,
+}
+
+impl AccessDiagnosis {
+    /// True when the user can read the row.
+    pub fn can_read(&self) -> bool {
+        self.access.contains(AccessRights::READ)
+    }
+
+    /// True when the user owns the row.
+    pub fn owned_by_user(&self) -> bool {
+        self.owner
+            .as_ref()
+            .is_some_and(|owner| owner.id == self.user.system_user_id)
+    }
+
+    /// The user's team that owns the row, if any.
+    pub fn owning_team(&self) -> Option<&TeamAccess> {
+        let owner = self.owner.as_ref()?;
+        self.teams.iter().find(|team| team.team.team_id == owner.id)
+    }
+
+    /// Shares that reach the user, directly or through one of their teams.
+    pub fn shares_with_user(&self) -> Vec<&SharedAccess> {
+        self.shares
+            .iter()
+            .filter(|share| {
+                share.principal.id == self.user.system_user_id
+                    || self
+                        .teams
+                        .iter()
+                        .any(|team| team.team.team_id == share.principal.id)
+            })
+            .collect()
+    }
+
+    /// Plain-language findings for a support ticket, most decisive first.
+    pub fn findings(&self) -> Vec<String> {
+        let mut findings = Vec::new();
+        if self.user.is_disabled {
+            findings.push("The user is disabled, so Dataverse denies all access.".to_string());
+        }
+        if self.can_read() {
+            findings.push(format!(
+                "The user can read the record ({}).",
+                self.access.to_access_mask()
+            ));
+        } else {
+            findings.push("The user cannot read the record.".to_string());
+        }
+
+        if self.owned_by_user() {
+            findings.push("The user owns the record.".to_string());
+        } else if let Some(team) = self.owning_team() {
+            findings.push(format!(
+                "The record is owned by team '{}', which the user belongs to.",
+                team.team.name
+            ));
+        } else if let Some(owner) = &self.owner {
+            findings.push(format!(
+                "The record is owned by {} {}, so the user's access comes from role depth or shares.",
+                owner.logical_name,
+                owner.id.as_hyphenated()
+            ));
+        }
+
+        for share in self.shares_with_user() {
+            match self
+                .teams
+                .iter()
+                .find(|team| team.team.team_id == share.principal.id)
+            {
+                Some(team) => findings.push(format!(
+                    "The record is shared with team '{}', which the user belongs to ({}).",
+                    team.team.name,
+                    share.access.to_access_mask()
+                )),
+                None => findings.push(format!(
+                    "The record is shared with the user ({}).",
+                    share.access.to_access_mask()
+                )),
+            }
+        }
+
+        let team_roles = self
+            .teams
+            .iter()
+            .map(|team| team.roles.len())
+            .sum::<usize>();
+        if self.user_roles.is_empty() && team_roles == 0 {
+            findings.push("The user has no security roles, directly or through teams.".to_string());
+        } else {
+            findings.push(format!(
+                "The user holds {} security roles directly and {team_roles} through teams.",
+                self.user_roles.len()
+            ));
+        }
+        findings
+    }
+}
+
+/// Path of the teams `user_id` belongs to.
+pub(crate) fn user_teams_path(user_id: Uuid) -> String {
+    format!(
+        "{}/teammembership_association?$select={TEAM_COLUMNS}",
+        row_path("systemusers", user_id.as_hyphenated())
+    )
+}
+
+/// Path of a `RetrieveSharedPrincipalsAndAccess` call for a row.
+pub(crate) fn shared_principals_path(entity_set: &str, id: Uuid) -> String {
+    let target = format!(
+        "{{\"@odata.id\":\"{}\"}}",
+        row_path(entity_set, id.as_hyphenated())
+    );
+    format!(
+        "RetrieveSharedPrincipalsAndAccess(Target=@tid)?@tid={}",
+        encode_query_value(&target)
+    )
+}
+
+/// Read the shares of a `RetrieveSharedPrincipalsAndAccess` response.
+pub(crate) fn parse_shared_principals(json: &Value) -> Result<Vec<SharedAccess>, String> {
+    let accesses = json
+        .get("PrincipalAccesses")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    accesses
+        .iter()
+        .map(|entry| {
+            let principal = entry
+                .get("Principal")
+                .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+            let logical_name = principal
+                .get("@odata.type")
+                .and_then(|value| value.as_str())
+                .map(|value| {
+                    value
+                        .trim_start_matches("#Microsoft.Dynamics.CRM.")
+                        .to_string()
+                })
+                .ok_or_else(|| "Shared principal has no @odata.type".to_string())?;
+            // Principals are typed as the `principal` base entity, keyed by `ownerid`.
+            let id = ["ownerid".to_string(), format!("{logical_name}id")]
+                .iter()
+                .find_map(|key| principal.get(key).and_then(|value| value.as_str()))
+                .and_then(|value| Uuid::parse_str(value).ok())
+                .ok_or_else(|| format!("Shared {logical_name} has no ID"))?;
+            Ok(SharedAccess {
+                principal: EntityReference {
+                    id,
+                    logical_name,
+                    name: None,
+                },
+                access: AccessRights::from_access_mask(
+                    entry
+                        .get("AccessMask")
+                        .and_then(|value| value.as_str())
+                        .unwrap_or_default(),
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Read the owner lookup of a row retrieved with `_ownerid_value` and lookup annotations.
+pub(crate) fn parse_owner(json: &Value) -> Option<EntityReference> {
+    let id = json
+        .get("_ownerid_value")
+        .and_then(|value| value.as_str())
+        .and_then(|value| Uuid::parse_str(value).ok())?;
+    let logical_name = json
+        .get("_ownerid_value@Microsoft.Dynamics.CRM.lookuplogicalname")
+        .and_then(|value| value.as_str())
+        .unwrap_or("systemuser")
+        .to_string();
+    let name = json
+        .get("_ownerid_value@OData.Community.Display.V1.FormattedValue")
+        .and_then(|value| value.as_str())
+        .map(str::to_string);
+    Some(EntityReference {
+        id,
+        logical_name,
+        name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        AccessDiagnosis, TeamAccess, parse_owner, parse_shared_principals, shared_principals_path,
+    };
+    use crate::dataverse::access::AccessRights;
+    use crate::dataverse::entity::EntityReference;
+    use crate::dataverse::executioncontext::SecurityRole;
+    use crate::dataverse::provisioning::{SystemUser, Team, TeamType};
+
+    const USER_ID: &str = "11111111-1111-1111-1111-111111111111";
+    const TEAM_ID: &str = "22222222-2222-2222-2222-222222222222";
+    const RECORD_ID: &str = "33333333-3333-3333-3333-333333333333";
+
+    fn id(value: &str) -> Uuid {
+        Uuid::parse_str(value).expect("uuid")
+    }
+
+    #[test]
+    fn parses_shares_and_owner() {
+        let shares = parse_shared_principals(&json!({
+            "PrincipalAccesses": [
+                {
+                    "AccessMask": "ReadAccess, WriteAccess",
+                    "Principal": {
+                        "@odata.type": "#Microsoft.Dynamics.CRM.team",
+                        "ownerid": TEAM_ID
+                    }
+                },
+                {
+                    "AccessMask": "ReadAccess",
+                    "Principal": {
+                        "@odata.type": "#Microsoft.Dynamics.CRM.systemuser",
+                        "systemuserid": USER_ID
+                    }
+                }
+            ]
+        }))
+        .expect("should parse");
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares[0].principal.logical_name, "team");
+        assert_eq!(shares[0].principal.id, id(TEAM_ID));
+        assert_eq!(shares[0].access, AccessRights::READ | AccessRights::WRITE);
+        assert_eq!(shares[1].principal.id, id(USER_ID));
+
+        let owner = parse_owner(&json!({
+            "_ownerid_value": TEAM_ID,
+            "_ownerid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "team",
+            "_ownerid_value@OData.Community.Display.V1.FormattedValue": "Sales"
+        }))
+        .expect("owner");
+        assert_eq!(owner.logical_name, "team");
+        assert_eq!(owner.name.as_deref(), Some("Sales"));
+        assert!(parse_owner(&json!({})).is_none());
+
+        assert!(
+            shared_principals_path("accounts", id(RECORD_ID)).starts_with(
+                "RetrieveSharedPrincipalsAndAccess(Target=@tid)?@tid=%7B%22%40odata.id"
+            )
+        );
+    }
+
+    #[test]
+    fn explains_access_through_team_ownership() {
+        let team = Team {
+            team_id: id(TEAM_ID),
+            name: "Sales".to_string(),
+            team_type: TeamType::Owner,
+            business_unit_id: Uuid::nil(),
+            administrator_id: None,
+            azure_ad_object_id: None,
+        };
+        let diagnosis = AccessDiagnosis {
+            user: SystemUser {
+                system_user_id: id(USER_ID),
+                full_name: Some("Ada".to_string()),
+                first_name: None,
+                last_name: None,
+                domain_name: None,
+                internal_email_address: None,
+                azure_ad_object_id: None,
+                business_unit_id: Uuid::nil(),
+                is_disabled: false,
+            },
+            record: EntityReference {
+                id: id(RECORD_ID),
+                logical_name: "account".to_string(),
+                name: None,
+            },
+            access: AccessRights::READ,
+            owner: Some(EntityReference {
+                id: id(TEAM_ID),
+                logical_name: "team".to_string(),
+                name: None,
+            }),
+            user_roles: Vec::new(),
+            teams: vec![TeamAccess {
+                team,
+                roles: vec![SecurityRole {
+                    id: Uuid::nil(),
+                    name: "Salesperson".to_string(),
+                }],
+            }],
+            shares: Vec::new(),
+        };
+
+        assert!(diagnosis.can_read());
+        assert!(!diagnosis.owned_by_user());
+        assert_eq!(
+            diagnosis.findings(),
+            vec![
+                "The user can read the record (ReadAccess).",
+                "The record is owned by team 'Sales', which the user belongs to.",
+                "The user holds 0 security roles directly and 1 through teams.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use serde_json::{Map, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::dataverse::entity::EntityReference;
+use crate::dataverse::url::bind_path;
+
+/// Suffix of the collection-valued navigation property that holds an activity's parties, such as
+/// `email_activity_parties`.
+pub const ACTIVITY_PARTIES_SUFFIX: &str = "_activity_parties";
+
+/// Party list columns by `participationtypemask` of the `activityparty` rows that store them.
+const PARTY_LIST_COLUMNS: [(i32, &str); 9] = [
+    (1, "from"),
+    (2, "to"),
+    (3, "cc"),
+    (4, "bcc"),
+    (5, "requiredattendees"),
+    (6, "optionalattendees"),
+    (7, "organizer"),
+    (10, "resources"),
+    (11, "customers"),
+];
+
+/// Navigation property of `activity`'s parties, such as `email_activity_parties` for `email`.
+pub fn activity_parties_navigation(activity: &str) -> String {
+    format!("{}{ACTIVITY_PARTIES_SUFFIX}", activity.to_ascii_lowercase())
+}
+
+/// Party list column stored with `participationtypemask`, such as `to` for 2.
+pub fn party_list_column(participation_type_mask: i32) -> Option<&'static str> {
+    PARTY_LIST_COLUMNS
+        .iter()
+        .find(|(mask, _)| *mask == participation_type_mask)
+        .map(|(_, column)| *column)
+}
+
+/// `participationtypemask` of a party list column, such as 2 for `to`.
+pub fn participation_type_mask(column: &str) -> Option<i32> {
+    PARTY_LIST_COLUMNS
+        .iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(column))
+        .map(|(mask, _)| *mask)
+}
+
+/// Group expanded `activityparty` rows into party list columns, in the order Dataverse returned
+/// them. Parties without a resolved `partyid`, such as an unresolved email address, and roles
+/// that are not party lists, such as the regarding row, are skipped.
+pub(crate) fn parse_activity_parties(rows: &[JsonValue]) -> Vec<(&'static str, EntityReference)> {
+    rows.iter()
+        .filter_map(|row| {
+            let column = row
+                .get("participationtypemask")
+                .and_then(JsonValue::as_i64)
+                .and_then(|mask| i32::try_from(mask).ok())
+                .and_then(party_list_column)?;
+            let id = row
+                .get("_partyid_value")
+                .and_then(JsonValue::as_str)
+                .and_then(|id| Uuid::parse_str(id).ok())?;
+            let logical_name = row
+                .get("_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname")
+                .and_then(JsonValue::as_str)?
+                .to_string();
+            let name = row
+                .get("_partyid_value@OData.Community.Display.V1.FormattedValue")
+                .and_then(JsonValue::as_str)
+                .map(str::to_string);
+            Some((
+                column,
+                EntityReference {
+                    id,
+                    logical_name,
+                    name,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// The `activityparty` row that adds `party` to the party list `column`, binding the party
+/// through its entity set.
+pub(crate) fn party_to_json(
+    column: &str,
+    party: &EntityReference,
+    entity_set_name: &str,
+) -> Result<JsonValue, String> {
+    let mask = participation_type_mask(column)
+        .ok_or_else(|| format!("'{column}' is not an activity party list column"))?;
+    let mut row = Map::new();
+    row.insert("participationtypemask".to_string(), JsonValue::from(mask));
+    row.insert(
+        format!(
+            "partyid_{}@odata.bind",
+            party.logical_name.to_ascii_lowercase()
+        ),
+        JsonValue::String(bind_path(entity_set_name, party.id.as_hyphenated())),
+    );
+    Ok(JsonValue::Object(row))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{parse_activity_parties, party_to_json};
+    use crate::dataverse::entity::EntityReference;
+
+    #[test]
+    fn groups_parties_by_participation_type_and_binds_them_back() {
+        let parties = parse_activity_parties(&[
+            json!({
+                "participationtypemask": 1,
+                "_partyid_value": "11111111-1111-1111-1111-111111111111",
+                "_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "systemuser",
+                "_partyid_value@OData.Community.Display.V1.FormattedValue": "Nancy Anderson",
+            }),
+            json!({
+                "participationtypemask": 2,
+                "_partyid_value": null,
+                "addressused": "someone@example.com",
+            }),
+            json!({
+                "participationtypemask": 8,
+                "_partyid_value": "22222222-2222-2222-2222-222222222222",
+                "_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "account",
+            }),
+        ]);
+
+        assert_eq!(parties.len(), 1);
+        assert_eq!(parties[0].0, "from");
+        assert_eq!(parties[0].1.logical_name, "systemuser");
+        assert_eq!(parties[0].1.name.as_deref(), Some("Nancy Anderson"));
+
+        let contact = EntityReference {
+            id: Uuid::parse_str("33333333-3333-3333-3333-333333333333").expect("uuid"),
+            logical_name: "contact".to_string(),
+            name: None,
+        };
+        assert_eq!(
+            party_to_json("to", &contact, "contacts").expect("should bind"),
+            json!({
+                "participationtypemask": 2,
+                "partyid_contact@odata.bind": "/contacts(33333333-3333-3333-3333-333333333333)",
+            })
+        );
+        assert_eq!(
+            party_to_json("subject", &contact, "contacts").expect_err("not a party list"),
+            "'subject' is not an activity party list column"
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::dataverse::entity::{Entity, EntityReference, Value};
+use crate::dataverse::fetchxml::ResultAttribute;
+
+/// One row of an aggregate or distinct FetchXML query, returned by
+/// `ServiceClient::retrieve_aggregate`.
+#[derive(Debug, Clone, Default)]
+pub struct AggregateRow {
+    /// Grouping keys in query order: the `groupby` attributes of an aggregate query, or every
+    /// attribute of a distinct query. A group whose key is empty holds `Value::Null`.
+    pub group_by: Vec<(String, Value)>,
+    /// Aggregated values in query order, such as a `sum` or `count`.
+    pub values: Vec<(String, Value)>,
+}
+
+impl AggregateRow {
+    /// The grouping key with this alias.
+    pub fn group_key(&self, alias: &str) -> Option<&Value> {
+        find(&self.group_by, alias)
+    }
+
+    /// The aggregated value with this alias.
+    pub fn value(&self, alias: &str) -> Option<&Value> {
+        find(&self.values, alias)
+    }
+
+    /// The grouping key or aggregated value with this alias.
+    pub fn get(&self, alias: &str) -> Option<&Value> {
+        self.group_key(alias).or_else(|| self.value(alias))
+    }
+}
+
+fn find<'a>(columns: &'a [(String, Value)], alias: &str) -> Option<&'a Value> {
+    columns
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(alias))
+        .map(|(_, value)| value)
+}
+
+/// Split parsed rows into grouping keys and aggregated values. `records` are the rows as
+/// Dataverse returned them, in the order `entities` were parsed from them; their annotations turn
+/// a grouped lookup, which arrives as a bare GUID under its alias, into an `EntityReference`.
+pub(crate) fn aggregate_rows(
+    records: &[JsonValue],
+    entities: Vec<Entity>,
+    attributes: &[ResultAttribute],
+    aggregate: bool,
+) -> Vec<AggregateRow> {
+    records
+        .iter()
+        .zip(entities)
+        .map(|(record, mut entity)| {
+            let mut row = AggregateRow::default();
+            for attribute in attributes {
+                let value = lookup_value(record, &attribute.key)
+                    .or_else(|| entity.attributes.remove(&attribute.key))
+                    .unwrap_or(Value::Null);
+                let target = if aggregate && attribute.aggregate.is_some() {
+                    &mut row.values
+                } else {
+                    &mut row.group_by
+                };
+                target.push((attribute.key.clone(), value));
+            }
+            row
+        })
+        .collect()
+}
+
+fn lookup_value(record: &JsonValue, key: &str) -> Option<Value> {
+    let logical_name = record
+        .get(format!("{key}@Microsoft.Dynamics.CRM.lookuplogicalname"))
+        .and_then(JsonValue::as_str)?;
+    let id = record
+        .get(key)
+        .and_then(JsonValue::as_str)
+        .and_then(|id| Uuid::parse_str(id).ok())?;
+    Some(Value::EntityReference(EntityReference {
+        id,
+        logical_name: logical_name.to_string(),
+        name: record
+            .get(format!("{key}@OData.Community.Display.V1.FormattedValue"))
+            .and_then(JsonValue::as_str)
+            .map(str::to_string),
+    }))
+}
@@ -0,0 +1,112 @@
+use chrono::SecondsFormat;
+
+use crate::dataverse::entity::Value;
+use crate::dataverse::url::{encode_query_value, encode_string_literal};
+
+/// Alternate key values identifying a row, as attribute logical name and value pairs.
+pub type KeyAttributes = Vec<(String, Value)>;
+
+/// Format alternate key values as the parenthesized segment of a Web API URL, such as
+/// `accountnumber='ACC-001',name='Contoso'`.
+pub(crate) fn format_key_segment(key: &[(String, Value)]) -> Result<String, String> {
+    if key.is_empty() {
+        return Err("Alternate key must contain at least one attribute".to_string());
+    }
+
+    key.iter()
+        .map(|(attribute, value)| Ok(format!("{attribute}={}", format_key_value(value)?)))
+        .collect::<Result<Vec<String>, String>>()
+        .map(|segments| segments.join(","))
+}
+
+fn format_key_value(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(encode_string_literal(value)),
+        Value::Int(value) => Ok(value.to_string()),
+        Value::Float(value) => Ok(value.to_string()),
+        Value::Decimal(value) => Ok(value.to_string()),
+        Value::Boolean(value) => Ok(value.to_string()),
+        Value::Guid(value) => Ok(value.as_hyphenated().to_string()),
+        Value::Money(value) => Ok(value.value.to_string()),
+        Value::OptionSetValue(value) => Ok(value.value.to_string()),
+        Value::EntityReference(reference) => Ok(reference.id.as_hyphenated().to_string()),
+        Value::DateTime(value) => Ok(encode_query_value(
+            &value.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        )),
+        Value::Date(value) => Ok(value.format("%Y-%m-%d").to_string()),
+        Value::OptionSetValueCollection(_)
+        | Value::PartyList(_)
+        | Value::EntityCollection(_)
+        | Value::Null => Err(format!("Unsupported alternate key value: {value:?}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::format_key_segment;
+    use crate::dataverse::entity::Value;
+
+    #[test]
+    fn formats_string_and_numeric_key_values() {
+        let segment = format_key_segment(&[
+            (
+                "accountnumber".to_string(),
+                Value::String("O'Brien & Co".to_string()),
+            ),
+            ("revision".to_string(), Value::Int(3)),
+        ])
+        .expect("should format");
+
+        assert_eq!(
+            segment,
+            "accountnumber='O%27%27Brien%20%26%20Co',revision=3"
+        );
+    }
+
+    #[test]
+    fn formats_guid_key_values_without_quotes() {
+        let id = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+
+        assert_eq!(
+            format_key_segment(&[("parentaccountid".to_string(), Value::Guid(id))])
+                .expect("should format"),
+            "parentaccountid=aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee"
+        );
+    }
+
+    #[test]
+    fn formats_boolean_decimal_and_date_key_values_without_quotes() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).expect("date");
+
+        assert_eq!(
+            format_key_segment(&[
+                ("isactive".to_string(), Value::Boolean(true)),
+                ("rate".to_string(), Value::Decimal(rust_decimal::Decimal::new(1250, 2))),
+                ("startdate".to_string(), Value::Date(date)),
+            ])
+            .expect("should format"),
+            "isactive=true,rate=12.50,startdate=2024-03-01"
+        );
+    }
+
+    #[test]
+    fn formats_date_time_key_values_in_utc_and_percent_encoded() {
+        let value = chrono::DateTime::parse_from_rfc3339("2024-03-01T10:30:00+02:00")
+            .expect("date time")
+            .with_timezone(&chrono::Utc);
+
+        assert_eq!(
+            format_key_segment(&[("effectivefrom".to_string(), Value::DateTime(value))])
+                .expect("should format"),
+            "effectivefrom=2024-03-01T08%3A30%3A00Z"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_null_keys() {
+        assert!(format_key_segment(&[]).is_err());
+        assert!(format_key_segment(&[("name".to_string(), Value::Null)]).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde_json::Value;
+
+use crate::dataverse::batch::OrganizationServiceFault;
+
+/// Prefix of every error message built from a failed Web API response.
+const API_ERROR_PREFIX: &str = "Dataverse API error (";
+
+/// A row with the same duplicate detection key already exists.
+pub const DUPLICATE_RECORD: &str = "0x80040237";
+/// A row with the same alternate key values already exists.
+pub const DUPLICATE_RECORD_ENTITY_KEY: &str = "0x80040333";
+/// The requested row does not exist.
+pub const OBJECT_DOES_NOT_EXIST: &str = "0x80040217";
+/// The caller lacks a privilege the operation needs.
+pub const PRIVILEGE_DENIED: &str = "0x80040220";
+/// The row changed since it was read; an `If-Match` check failed.
+pub const CONCURRENCY_VERSION_MISMATCH: &str = "0x80060882";
+/// The client version stamp passed to `RetrieveMetadataChanges` is too old.
+pub const EXPIRED_VERSION_STAMP: &str = "0x80044352";
+/// Service protection error codes returned when requests are throttled.
+pub const SERVICE_PROTECTION_CODES: [&str; 3] = ["0x80072321", "0x80072322", "0x80072326"];
+
+/// Annotation carrying Dataverse's help link for an error.
+const HELP_LINK_ANNOTATION: &str = "@Microsoft.PowerApps.CDS.HelpLink";
+/// Annotation carrying the plug-in trace text of an error.
+const TRACE_TEXT_ANNOTATION: &str = "@Microsoft.PowerApps.CDS.TraceText";
+/// Annotation carrying the inner exception message.
+const INNER_ERROR_MESSAGE_ANNOTATION: &str = "@Microsoft.PowerApps.CDS.InnerError.Message";
+
+/// `innererror` details returned with some Web API errors.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InnerError {
+    /// Inner exception message.
+    pub message: Option<String>,
+    /// Inner exception type name.
+    pub error_type: Option<String>,
+    /// Server stack trace, when the environment returns one.
+    pub stack_trace: Option<String>,
+}
+
+/// A failed Web API response, parsed from the standard OData error body.
+///
+/// Client methods return failed responses as `Dataverse API error (status): body` strings, ending
+/// with both request IDs; `parse` reads them back. Error codes are compared without regard to
+/// case.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiError {
+    /// HTTP status code.
+    pub status_code: u16,
+    /// Dataverse error code, such as `0x80040333`.
+    pub code: Option<String>,
+    /// Error message, or the raw body when it is not an OData error.
+    pub message: String,
+    /// `innererror` details, when returned.
+    pub inner_error: Option<InnerError>,
+    /// Annotations on the error object, such as
+    /// `@Microsoft.PowerApps.CDS.ErrorDetails.OperationStatus`, keyed by annotation name.
+    pub annotations: HashMap<String, Value>,
+    /// `x-ms-client-request-id` of the failed request.
+    pub client_request_id: Option<String>,
+    /// `x-ms-service-request-id` Dataverse returned.
+    pub service_request_id: Option<String>,
+    /// How long Dataverse asked the client to wait before retrying, from `Retry-After`.
+    pub retry_after: Option<Duration>,
+    /// Response body as returned.
+    pub raw_body: String,
+}
+
+impl ApiError {
+    /// Parse a response body. Bodies that are not OData errors keep the body as the message.
+    pub fn from_body(status_code: u16, body: &str) -> Self {
+        let mut error = Self {
+            status_code,
+            message: body.to_string(),
+            raw_body: body.to_string(),
+            ..Self::default()
+        };
+        let Some(object) = serde_json::from_str::<Value>(body)
+            .ok()
+            .and_then(|json| json.get("error").and_then(Value::as_object).cloned())
+        else {
+            return error;
+        };
+
+        let text = |value: Option<&Value>| value.and_then(Value::as_str).map(str::to_string);
+        error.code = text(object.get("code"));
+        if let Some(message) = text(object.get("message")) {
+            error.message = message;
+        }
+        error.inner_error = object.get("innererror").map(|inner| InnerError {
+            message: text(inner.get("message")),
+            error_type: text(inner.get("type")),
+            stack_trace: text(inner.get("stacktrace")),
+        });
+        error.annotations = object
+            .iter()
+            .filter(|(key, _)| key.starts_with('@'))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        error
+    }
+
+    /// Parse the structured error from an error message returned by this crate, such as
+    /// `Dataverse API error (412 Precondition Failed): {...}`. `None` for other errors, such as
+    /// connection failures.
+    pub fn parse(message: &str) -> Option<Self> {
+        let rest = message.strip_prefix(API_ERROR_PREFIX)?;
+        let status_code = rest.get(..3)?.parse::<u16>().ok()?;
+        let (_, rest) = rest.split_once(')')?;
+        let rest = rest.strip_prefix(": ").unwrap_or(rest.trim_start());
+
+        let (body, ids) = match rest.rfind(" [") {
+            Some(index)
+                if rest.ends_with(']')
+                    && (rest[index + 2..].starts_with("client request id: ")
+                        || rest[index + 2..].starts_with("service request id: ")
+                        || rest[index + 2..].starts_with("retry after: ")) =>
+            {
+                (&rest[..index], &rest[index + 2..rest.len() - 1])
+            }
+            _ => (rest, ""),
+        };
+
+        let mut error = Self::from_body(status_code, body);
+        for id in ids.split(", ") {
+            if let Some(id) = id.strip_prefix("client request id: ") {
+                error.client_request_id = Some(id.to_string());
+            } else if let Some(id) = id.strip_prefix("service request id: ") {
+                error.service_request_id = Some(id.to_string());
+            } else if let Some(seconds) = id
+                .strip_prefix("retry after: ")
+                .and_then(|value| value.strip_suffix('s'))
+            {
+                error.retry_after = retry_after_seconds(seconds);
+            }
+        }
+        Some(error)
+    }
+
+    /// The structured error of a failed batch item.
+    pub fn from_fault(fault: &OrganizationServiceFault) -> Self {
+        let error = match &fault.raw_body {
+            Some(body) => Self::from_body(fault.status_code, body),
+            None => Self {
+                status_code: fault.status_code,
+                code: fault.code.clone(),
+                message: fault.message.clone(),
+                ..Self::default()
+            },
+        };
+        Self {
+            retry_after: fault.retry_after,
+            ..error
+        }
+    }
+
+    /// True if the Dataverse error code is `code`, compared without regard to case.
+    pub fn has_code(&self, code: &str) -> bool {
+        self.code
+            .as_deref()
+            .is_some_and(|own| own.eq_ignore_ascii_case(code))
+    }
+
+    /// True if a row with the same duplicate detection or alternate key values already exists.
+    pub fn is_duplicate(&self) -> bool {
+        self.has_code(DUPLICATE_RECORD) || self.has_code(DUPLICATE_RECORD_ENTITY_KEY)
+    }
+
+    /// True if the requested row does not exist.
+    pub fn is_not_found(&self) -> bool {
+        self.status_code == 404 || self.has_code(OBJECT_DOES_NOT_EXIST)
+    }
+
+    /// True if the caller lacks a privilege the operation needs.
+    pub fn is_privilege_denied(&self) -> bool {
+        self.has_code(PRIVILEGE_DENIED)
+    }
+
+    /// True if an `If-Match` concurrency check failed. Dataverse also answers duplicate keys with
+    /// 412, so those are not counted.
+    pub fn is_concurrency_conflict(&self) -> bool {
+        self.has_code(CONCURRENCY_VERSION_MISMATCH)
+            || (self.status_code == 412 && !self.is_duplicate())
+    }
+
+    /// True if the request was throttled by service protection limits.
+    pub fn is_throttled(&self) -> bool {
+        self.status_code == 429
+            || SERVICE_PROTECTION_CODES
+                .iter()
+                .any(|code| self.has_code(code))
+    }
+
+    /// Text value of the annotation `name`, such as `@Microsoft.PowerApps.CDS.ErrorDetails.SubErrorCode`.
+    pub fn annotation(&self, name: &str) -> Option<&str> {
+        self.annotations.get(name).and_then(Value::as_str)
+    }
+
+    /// Help link Dataverse returned for the error.
+    pub fn help_link(&self) -> Option<&str> {
+        self.annotation(HELP_LINK_ANNOTATION)
+    }
+
+    /// Plug-in trace text, when a plug-in raised the error.
+    pub fn trace_text(&self) -> Option<&str> {
+        self.annotation(TRACE_TEXT_ANNOTATION)
+    }
+
+    /// Inner exception message, from `innererror` or its annotation.
+    pub fn inner_message(&self) -> Option<&str> {
+        self.inner_error
+            .as_ref()
+            .and_then(|inner| inner.message.as_deref())
+            .or_else(|| self.annotation(INNER_ERROR_MESSAGE_ANNOTATION))
+    }
+}
+
+/// The `Retry-After` delay of a response. Dataverse sends it in seconds with throttled responses.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    retry_after_seconds(headers.get(RETRY_AFTER)?.to_str().ok()?)
+}
+
+/// Parse a `Retry-After` value given in seconds.
+pub(crate) fn retry_after_seconds(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
+
+/// Formats as the error message the client returns, so `ApiError::parse` reads it back.
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = reqwest::StatusCode::from_u16(self.status_code)
+            .map(|status| status.to_string())
+            .unwrap_or_else(|_| self.status_code.to_string());
+        write!(f, "{API_ERROR_PREFIX}{status}): {}", self.raw_body)?;
+
+        let ids = [
+            self.client_request_id
+                .as_ref()
+                .map(|id| format!("client request id: {id}")),
+            self.service_request_id
+                .as_ref()
+                .map(|id| format!("service request id: {id}")),
+            self.retry_after
+                .map(|delay| format!("retry after: {}s", delay.as_secs())),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            write!(f, " [{}]", ids.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApiError, DUPLICATE_RECORD_ENTITY_KEY};
+    use crate::dataverse::batch::OrganizationServiceFault;
+
+    const DUPLICATE_KEY_BODY: &str = r#"{"error":{"code":"0x80040333","message":"A record that has the attribute values Account Number already exists.","@Microsoft.PowerApps.CDS.ErrorDetails.OperationStatus":"0","@Microsoft.PowerApps.CDS.HelpLink":"http://go.microsoft.com/fwlink/?LinkID=398563&error=Microsoft.Crm.CrmException%3a80040333","@Microsoft.PowerApps.CDS.InnerError.Message":"Duplicate key"}}"#;
+
+    #[test]
+    fn parses_odata_error_body_and_annotations() {
+        let error = ApiError::from_body(412, DUPLICATE_KEY_BODY);
+
+        assert_eq!(error.code.as_deref(), Some(DUPLICATE_RECORD_ENTITY_KEY));
+        assert!(error.is_duplicate());
+        assert!(!error.is_not_found());
+        assert!(!error.is_concurrency_conflict());
+        assert_eq!(
+            error.message,
+            "A record that has the attribute values Account Number already exists."
+        );
+        assert_eq!(
+            error.annotation("@Microsoft.PowerApps.CDS.ErrorDetails.OperationStatus"),
+            Some("0")
+        );
+        assert!(
+            error
+                .help_link()
+                .is_some_and(|link| link.contains("80040333"))
+        );
+        assert_eq!(error.inner_message(), Some("Duplicate key"));
+
+        let plain = ApiError::from_body(502, "Bad Gateway");
+        assert_eq!(plain.code, None);
+        assert_eq!(plain.message, "Bad Gateway");
+    }
+
+    #[test]
+    fn parses_client_error_messages_back() {
+        let message = format!(
+            "Dataverse API error (404 Not Found): {} [client request id: client-1, service request id: service-1]",
+            r#"{"error":{"code":"0x80040217","message":"account With Id = 1 Does Not Exist","innererror":{"message":"Not found","type":"Microsoft.Crm.CrmException","stacktrace":""}}}"#
+        );
+
+        let error = ApiError::parse(&message).expect("should parse");
+
+        assert_eq!(error.status_code, 404);
+        assert!(error.is_not_found());
+        assert_eq!(error.client_request_id.as_deref(), Some("client-1"));
+        assert_eq!(error.service_request_id.as_deref(), Some("service-1"));
+        assert_eq!(
+            error
+                .inner_error
+                .as_ref()
+                .and_then(|inner| inner.error_type.as_deref()),
+            Some("Microsoft.Crm.CrmException")
+        );
+        assert_eq!(error.to_string(), message);
+
+        let batch = ApiError::parse("Dataverse API error (429): {}").expect("should parse");
+        assert!(batch.is_throttled());
+        let throttled = ApiError::parse(
+            "Dataverse API error (429 Too Many Requests): {} [client request id: client-1, retry after: 7s]",
+        )
+        .expect("should parse");
+        assert_eq!(
+            throttled.retry_after,
+            Some(std::time::Duration::from_secs(7))
+        );
+        assert_eq!(throttled.client_request_id.as_deref(), Some("client-1"));
+        assert!(ApiError::parse("Request failed: connection reset").is_none());
+    }
+
+    #[test]
+    fn reads_batch_item_faults() {
+        let fault = OrganizationServiceFault {
+            status_code: 412,
+            code: Some("0x80040333".to_string()),
+            message: "duplicate".to_string(),
+            raw_body: Some(DUPLICATE_KEY_BODY.to_string()),
+            retry_after: None,
+        };
+
+        assert!(ApiError::from_fault(&fault).is_duplicate());
+    }
+}
@@ -0,0 +1,141 @@
+/// Aggregation method of an OData `aggregate` expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateMethod {
+    /// Sum of the column's values.
+    Sum,
+    /// Average of the column's values.
+    Average,
+    /// Smallest value.
+    Min,
+    /// Largest value.
+    Max,
+    /// Number of distinct values.
+    CountDistinct,
+}
+
+impl AggregateMethod {
+    fn keyword(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Average => "average",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::CountDistinct => "countdistinct",
+        }
+    }
+}
+
+/// Builder for an OData `$apply` transformation, such as
+/// `filter(statecode eq 0)/groupby((industrycode),aggregate(revenue with sum as total))`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ApplyQuery {
+    filter: Option<String>,
+    group_by: Vec<String>,
+    aggregates: Vec<String>,
+}
+
+impl ApplyQuery {
+    /// Start an empty transformation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only aggregate rows matching an OData filter expression, such as `statecode eq 0`.
+    pub fn filter(mut self, expression: &str) -> Self {
+        self.filter = Some(expression.to_string());
+        self
+    }
+
+    /// Group rows by `columns`. Lookup columns use their `_name_value` property.
+    pub fn group_by(mut self, columns: &[&str]) -> Self {
+        self.group_by
+            .extend(columns.iter().map(|column| column.to_string()));
+        self
+    }
+
+    /// Add `column with method as alias` to the aggregated values.
+    pub fn aggregate(mut self, column: &str, method: AggregateMethod, alias: &str) -> Self {
+        self.aggregates
+            .push(format!("{column} with {} as {alias}", method.keyword()));
+        self
+    }
+
+    /// Add the number of rows in each group as `alias`.
+    pub fn count(mut self, alias: &str) -> Self {
+        self.aggregates.push(format!("$count as {alias}"));
+        self
+    }
+
+    /// The `$apply` value. A query with only a filter returns the filtered rows.
+    pub fn build(&self) -> String {
+        let aggregate = (!self.aggregates.is_empty())
+            .then(|| format!("aggregate({})", self.aggregates.join(",")));
+        let last = match (self.group_by.is_empty(), aggregate) {
+            (true, aggregate) => aggregate,
+            (false, Some(aggregate)) => Some(format!(
+                "groupby(({}),{aggregate})",
+                self.group_by.join(",")
+            )),
+            (false, None) => Some(format!("groupby(({}))", self.group_by.join(","))),
+        };
+        self.filter
+            .as_ref()
+            .map(|filter| format!("filter({filter})"))
+            .into_iter()
+            .chain(last)
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// OData query options with `apply` as `$apply`, keeping a leading `$apply=` when given.
+pub(crate) fn apply_query_options(apply: &str) -> String {
+    let apply = apply.trim_start_matches('?');
+    if apply.starts_with("$apply=") {
+        apply.to_string()
+    } else {
+        format!("$apply={apply}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AggregateMethod, ApplyQuery, apply_query_options};
+
+    #[test]
+    fn builds_filter_groupby_and_aggregate() {
+        let apply = ApplyQuery::new()
+            .filter("statecode eq 0")
+            .group_by(&["industrycode", "_ownerid_value"])
+            .aggregate("revenue", AggregateMethod::Sum, "total")
+            .count("rows")
+            .build();
+
+        assert_eq!(
+            apply,
+            "filter(statecode eq 0)/groupby((industrycode,_ownerid_value),aggregate(revenue with sum as total,$count as rows))"
+        );
+        assert_eq!(
+            ApplyQuery::new()
+                .aggregate("revenue", AggregateMethod::Average, "avg")
+                .build(),
+            "aggregate(revenue with average as avg)"
+        );
+        assert_eq!(
+            ApplyQuery::new().group_by(&["industrycode"]).build(),
+            "groupby((industrycode))"
+        );
+    }
+
+    #[test]
+    fn accepts_raw_apply_values() {
+        assert_eq!(
+            apply_query_options("groupby((statecode))"),
+            "$apply=groupby((statecode))"
+        );
+        assert_eq!(
+            apply_query_options("?$apply=groupby((statecode))"),
+            "$apply=groupby((statecode))"
+        );
+    }
+}
@@ -1,13 +1,21 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
 use reqwest::header::CONTENT_TYPE;
 use serde_json::{Map, Number, Value as JsonValue};
 use uuid::Uuid;
 
+use crate::dataverse::activityparty::{activity_parties_navigation, party_to_json};
+use crate::dataverse::alternatekey::KeyAttributes;
+use crate::dataverse::apierror::retry_after_seconds;
+use crate::dataverse::bulkresult::BulkResult;
 use crate::dataverse::entity::{
-    Entity, EntityReference, OptionSetValueCollection, Value as DataverseValue,
+    Entity, EntityReference, OptionSetValueCollection, TRANSACTION_CURRENCY_ATTRIBUTE,
+    Value as DataverseValue,
 };
+use crate::dataverse::lookupbind::{LookupNavigation, bind_lookup, clear_lookup};
 use crate::dataverse::requestparameters::RequestParameters;
+use crate::dataverse::url::{batch_request_path, bind_path};
 
 const HEADER_SEPARATOR: &str = "\r\n\r\n";
 
@@ -33,6 +41,9 @@ pub struct ExecuteMultipleRequest {
 pub struct ExecuteMultipleResponse {
     /// Per-request outcomes in the same order as the submitted requests.
     pub responses: Vec<ExecuteMultipleResponseItem>,
+    /// Row-level outcome of every answered request, including successes that `responses` leaves
+    /// out without `return_responses`, with the ID of the created, upserted, or targeted row.
+    pub results: Vec<BulkResult>,
 }
 
 /// Result for a single request within an `ExecuteMultipleResponse`.
@@ -57,6 +68,9 @@ pub struct OrganizationServiceFault {
     pub message: String,
     /// Raw HTTP body for callers that need the original Dataverse payload.
     pub raw_body: Option<String>,
+    /// How long Dataverse asked the client to wait before retrying, from the item's
+    /// `Retry-After` header.
+    pub retry_after: Option<Duration>,
 }
 
 /// Supported Dataverse operations for a batch request.
@@ -68,6 +82,8 @@ pub enum OrganizationRequest {
     Update(UpdateRequest),
     /// Delete an existing Dataverse row.
     Delete(DeleteRequest),
+    /// Create a Dataverse row, or update it when it already exists.
+    Upsert(UpsertRequest),
 }
 
 /// Successful payload for a single `OrganizationRequest`.
@@ -79,6 +95,8 @@ pub enum OrganizationResponse {
     Update(UpdateResponse),
     /// Delete response placeholder for successful deletes.
     Delete(DeleteResponse),
+    /// Upsert response containing the affected row id when Dataverse returns it.
+    Upsert(UpsertResponse),
 }
 
 /// Create operation inside a batch request.
@@ -123,12 +141,32 @@ pub struct DeleteRequest {
 #[derive(Debug, Clone, Default)]
 pub struct DeleteResponse;
 
+/// Upsert operation inside a batch request.
+#[derive(Debug, Clone)]
+pub struct UpsertRequest {
+    /// Entity payload to upsert.
+    pub target: Entity,
+    /// Alternate key identifying the row. When empty, the entity id is used instead.
+    pub alternate_key: KeyAttributes,
+    /// Optional Dataverse headers that affect plugin/business logic execution.
+    pub parameters: RequestParameters,
+}
+
+/// Success payload for a batch upsert request.
+#[derive(Debug, Clone)]
+pub struct UpsertResponse {
+    /// Created or updated row id extracted from Dataverse response headers when available.
+    pub id: Option<Uuid>,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct PreparedBatchRequest {
     pub(crate) method: &'static str,
     pub(crate) path: String,
     pub(crate) body: Option<String>,
     pub(crate) parameters: RequestParameters,
+    /// `Prefer` header of the part, such as the annotations a GET should return.
+    pub(crate) prefer: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -173,6 +211,26 @@ impl DeleteRequest {
     }
 }
 
+impl UpsertRequest {
+    /// Create a batch upsert request keyed by the entity id.
+    pub fn new(target: Entity) -> Self {
+        Self {
+            target,
+            alternate_key: Vec::new(),
+            parameters: RequestParameters::default(),
+        }
+    }
+
+    /// Create a batch upsert request keyed by an alternate key.
+    pub fn with_alternate_key(target: Entity, alternate_key: KeyAttributes) -> Self {
+        Self {
+            target,
+            alternate_key,
+            parameters: RequestParameters::default(),
+        }
+    }
+}
+
 impl OrganizationRequest {
     pub(crate) fn success_response(&self, headers: &HashMap<String, String>) -> OrganizationResponse {
         match self {
@@ -181,6 +239,31 @@ impl OrganizationRequest {
             }),
             OrganizationRequest::Update(_) => OrganizationResponse::Update(UpdateResponse),
             OrganizationRequest::Delete(_) => OrganizationResponse::Delete(DeleteResponse),
+            OrganizationRequest::Upsert(_) => OrganizationResponse::Upsert(UpsertResponse {
+                id: entity_id_from_headers(headers),
+            }),
+        }
+    }
+
+    /// ID of the row the request targets, when the request names one.
+    pub(crate) fn target_id(&self) -> Option<Uuid> {
+        let id = match self {
+            OrganizationRequest::Create(request) => request.target.id,
+            OrganizationRequest::Update(request) => request.target.id,
+            OrganizationRequest::Delete(request) => request.target.id,
+            OrganizationRequest::Upsert(request) => request.target.id,
+        };
+        (!id.is_nil()).then_some(id)
+    }
+}
+
+impl OrganizationResponse {
+    /// ID of the created or upserted row, when Dataverse returned it.
+    pub(crate) fn id(&self) -> Option<Uuid> {
+        match self {
+            OrganizationResponse::Create(response) => response.id,
+            OrganizationResponse::Upsert(response) => response.id,
+            OrganizationResponse::Update(_) | OrganizationResponse::Delete(_) => None,
         }
     }
 }
@@ -188,8 +271,27 @@ impl OrganizationRequest {
 pub(crate) fn entity_to_write_body(
     entity: &Entity,
     entity_set_name_by_logical_name: &HashMap<String, String>,
+    lookup_navigations: &HashMap<String, Vec<LookupNavigation>>,
 ) -> Result<String, String> {
+    let body = entity_to_write_map(entity, entity_set_name_by_logical_name, lookup_navigations)?;
+    serde_json::to_string(&body).map_err(|e| format!("Failed to serialize request body: {e}"))
+}
+
+/// Convert an entity into a Web API write payload. Lookups become `@odata.bind` entries on the
+/// navigation property from metadata, a null lookup is written as a disassociation, party lists
+/// become `activityparty` rows under `{activity}_activity_parties`, and entity collections are
+/// written as nested rows for a deep insert. `lookup_navigations` is keyed by
+/// table logical name so nested rows bind their own lookups.
+pub(crate) fn entity_to_write_map(
+    entity: &Entity,
+    entity_set_name_by_logical_name: &HashMap<String, String>,
+    lookup_navigations: &HashMap<String, Vec<LookupNavigation>>,
+) -> Result<Map<String, JsonValue>, String> {
     let mut body = Map::new();
+    let navigations = lookup_navigations
+        .get(&entity.logical_name.to_ascii_lowercase())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
 
     for (attribute, value) in &entity.attributes {
         match value {
@@ -203,21 +305,77 @@ pub(crate) fn entity_to_write_body(
                         )
                     })?;
 
-                body.insert(
-                    format!("{attribute}@odata.bind"),
-                    JsonValue::String(format!(
-                        "{entity_set_name}({})",
-                        reference.id.as_hyphenated()
-                    )),
+                bind_lookup(
+                    &mut body,
+                    navigations,
+                    attribute,
+                    reference,
+                    entity_set_name,
                 );
             }
+            DataverseValue::Null => {
+                if !clear_lookup(&mut body, navigations, attribute) {
+                    body.insert(attribute.clone(), JsonValue::Null);
+                }
+            }
+            DataverseValue::PartyList(parties) => {
+                let navigation = activity_parties_navigation(&entity.logical_name);
+                for party in parties {
+                    let entity_set_name = entity_set_name_by_logical_name
+                        .get(&party.logical_name.to_ascii_lowercase())
+                        .ok_or_else(|| {
+                            format!(
+                                "Entity set metadata not found for referenced entity '{}'",
+                                party.logical_name
+                            )
+                        })?;
+                    let row = party_to_json(attribute, party, entity_set_name)?;
+                    match body
+                        .entry(navigation.clone())
+                        .or_insert_with(|| JsonValue::Array(Vec::new()))
+                    {
+                        JsonValue::Array(rows) => rows.push(row),
+                        _ => {
+                            return Err(format!(
+                                "'{navigation}' is set both directly and through party list columns"
+                            ));
+                        }
+                    }
+                }
+            }
+            DataverseValue::EntityCollection(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| {
+                        entity_to_write_map(row, entity_set_name_by_logical_name, lookup_navigations)
+                            .map(JsonValue::Object)
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                body.insert(attribute.clone(), JsonValue::Array(rows));
+            }
+            DataverseValue::Money(money) => {
+                body.insert(attribute.clone(), value_to_json(value)?);
+                // Money columns share the row's currency, so an explicit currency on the value is
+                // written as the `transactioncurrencyid` binding unless the caller set it directly.
+                if let Some(currency) = &money.currency
+                    && !entity.attributes.contains_key(TRANSACTION_CURRENCY_ATTRIBUTE)
+                {
+                    body.insert(
+                        format!("{TRANSACTION_CURRENCY_ATTRIBUTE}@odata.bind"),
+                        JsonValue::String(bind_path(
+                            "transactioncurrencies",
+                            currency.id.as_hyphenated(),
+                        )),
+                    );
+                }
+            }
             other => {
                 body.insert(attribute.clone(), value_to_json(other)?);
             }
         }
     }
 
-    serde_json::to_string(&body).map_err(|e| format!("Failed to serialize request body: {e}"))
+    Ok(body)
 }
 
 pub(crate) fn parse_batch_response_parts(
@@ -230,6 +388,34 @@ pub(crate) fn parse_batch_response_parts(
     parse_multipart_parts(response_text, &boundary)
 }
 
+/// Build a `$batch` GET part for a path relative to the Web API root.
+pub(crate) fn batch_get_item(path: &str) -> PreparedBatchItem {
+    batch_get_item_with_prefer(path, None)
+}
+
+/// Build a `$batch` GET part that sends a `Prefer` header, such as
+/// `odata.include-annotations`, so the part returns the same annotations as a direct GET.
+pub(crate) fn batch_get_item_with_prefer(path: &str, prefer: Option<String>) -> PreparedBatchItem {
+    PreparedBatchItem {
+        prepared_request: PreparedBatchRequest {
+            method: "GET",
+            path: batch_request_path(path),
+            body: None,
+            parameters: RequestParameters::default(),
+            prefer,
+        },
+    }
+}
+
+/// Parse the JSON body of a successful batch part, or surface its error.
+pub(crate) fn batch_part_json(part: &ParsedBatchPart) -> Result<JsonValue, String> {
+    let body = part.body.as_deref().unwrap_or_default();
+    if part.status_code >= 400 {
+        return Err(format!("Dataverse API error ({}): {}", part.status_code, body));
+    }
+    serde_json::from_str(body).map_err(|e| format!("Failed to parse JSON: {e}"))
+}
+
 fn value_to_json(value: &DataverseValue) -> Result<JsonValue, String> {
     match value {
         DataverseValue::Int(value) => Ok(JsonValue::Number(Number::from(*value))),
@@ -240,6 +426,7 @@ fn value_to_json(value: &DataverseValue) -> Result<JsonValue, String> {
         DataverseValue::String(value) => Ok(JsonValue::String(value.clone())),
         DataverseValue::Boolean(value) => Ok(JsonValue::Bool(*value)),
         DataverseValue::DateTime(value) => Ok(JsonValue::String(value.to_rfc3339())),
+        DataverseValue::Date(value) => Ok(JsonValue::String(value.format("%Y-%m-%d").to_string())),
         DataverseValue::Guid(value) => Ok(JsonValue::String(value.as_hyphenated().to_string())),
         DataverseValue::Money(value) => json_number_from_string(&value.value.to_string()),
         DataverseValue::OptionSetValue(value) => Ok(JsonValue::Number(Number::from(value.value))),
@@ -253,7 +440,11 @@ fn value_to_json(value: &DataverseValue) -> Result<JsonValue, String> {
             ),
         ),
         DataverseValue::Null => Ok(JsonValue::Null),
-        DataverseValue::EntityReference(_) => unreachable!("entity references are handled separately"),
+        DataverseValue::EntityReference(_)
+        | DataverseValue::PartyList(_)
+        | DataverseValue::EntityCollection(_) => {
+            unreachable!("entity references, party lists, and collections are handled separately")
+        }
     }
 }
 
@@ -383,6 +574,10 @@ pub(crate) fn parse_fault(part: &ParsedBatchPart) -> OrganizationServiceFault {
         code,
         message,
         raw_body: part.body.clone(),
+        retry_after: part
+            .headers
+            .get("retry-after")
+            .and_then(|value| retry_after_seconds(value)),
     }
 }
 
@@ -405,10 +600,11 @@ mod tests {
     use uuid::Uuid;
 
     use super::{
-        CreateRequest, OrganizationRequest, entity_to_write_body, parse_batch_response_parts,
-        parse_fault,
+        CreateRequest, OrganizationRequest, ParsedBatchPart, batch_get_item, batch_part_json,
+        entity_to_write_body, entity_to_write_map, parse_batch_response_parts, parse_fault,
     };
     use crate::dataverse::entity::{Entity, EntityReference, Money, Value};
+    use crate::dataverse::lookupbind::LookupNavigation;
 
     #[test]
     fn parses_flat_batch_response_parts() {
@@ -461,10 +657,11 @@ mod tests {
         let body = entity_to_write_body(
             &entity,
             &HashMap::from([("account".to_string(), "accounts".to_string())]),
+            &HashMap::new(),
         )
         .expect("should serialize");
 
-        assert!(body.contains("\"parentcustomerid@odata.bind\":\"accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)\""));
+        assert!(body.contains("\"parentcustomerid@odata.bind\":\"/accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)\""));
     }
 
     #[test]
@@ -504,13 +701,167 @@ mod tests {
         let mut entity = Entity::new(Uuid::new_v4(), "invoice", None);
         entity.attributes.insert(
             "totalamount".to_string(),
-            Value::Money(Money {
-                value: Decimal::new(12345, 2),
-            }),
+            Value::Money(Money::new(Decimal::new(12345, 2))),
         );
 
-        let body = entity_to_write_body(&entity, &HashMap::new()).expect("should serialize");
+        let body = entity_to_write_body(&entity, &HashMap::new(), &HashMap::new()).expect("should serialize");
 
         assert!(body.contains("\"totalamount\":123.45"));
     }
+
+    #[test]
+    fn money_currency_is_written_as_transaction_currency_binding() {
+        let currency_id = Uuid::new_v4();
+        let mut entity = Entity::new(Uuid::new_v4(), "opportunity", None);
+        entity.attributes.insert(
+            "estimatedvalue".to_string(),
+            Value::Money(Money::with_currency(Decimal::new(500, 0), currency_id)),
+        );
+
+        let body = entity_to_write_body(&entity, &HashMap::new(), &HashMap::new()).expect("should serialize");
+
+        assert!(body.contains("\"estimatedvalue\":500"));
+        assert!(body.contains(&format!(
+            "\"transactioncurrencyid@odata.bind\":\"/transactioncurrencies({currency_id})\""
+        )));
+    }
+
+    #[test]
+    fn serializes_date_only_values_without_time() {
+        let mut entity = Entity::new(Uuid::new_v4(), "contact", None);
+        entity.attributes.insert(
+            "birthdate".to_string(),
+            Value::Date(chrono::NaiveDate::from_ymd_opt(1990, 4, 12).expect("date")),
+        );
+
+        let body = entity_to_write_body(&entity, &HashMap::new(), &HashMap::new()).expect("should serialize");
+
+        assert!(body.contains("\"birthdate\":\"1990-04-12\""));
+    }
+
+    #[test]
+    fn batch_get_parts_encode_spaces_and_surface_errors() {
+        let item = batch_get_item("EntityDefinitions?$filter=LogicalName eq 'account'");
+        assert_eq!(item.prepared_request.method, "GET");
+        assert_eq!(
+            item.prepared_request.path,
+            "EntityDefinitions?$filter=LogicalName%20eq%20'account'"
+        );
+        assert!(item.prepared_request.body.is_none());
+
+        let ok = ParsedBatchPart {
+            status_code: 200,
+            headers: HashMap::new(),
+            body: Some(r#"{"value":[]}"#.to_string()),
+        };
+        assert_eq!(
+            batch_part_json(&ok).expect("should parse"),
+            serde_json::json!({ "value": [] })
+        );
+
+        let failed = ParsedBatchPart {
+            status_code: 404,
+            headers: HashMap::new(),
+            body: Some("not found".to_string()),
+        };
+        assert_eq!(
+            batch_part_json(&failed).unwrap_err(),
+            "Dataverse API error (404): not found"
+        );
+    }
+
+    #[test]
+    fn serializes_entity_collections_as_nested_rows_for_deep_insert() {
+        let account_id = Uuid::new_v4();
+        let mut contact = Entity::new(Uuid::nil(), "contact", None);
+        contact
+            .attributes
+            .insert("lastname".to_string(), Value::String("Lovelace".to_string()));
+        contact.attributes.insert(
+            "parentcustomerid".to_string(),
+            Value::EntityReference(EntityReference {
+                id: account_id,
+                logical_name: "account".to_string(),
+                name: None,
+            }),
+        );
+        let mut account = Entity::new(Uuid::nil(), "account", None);
+        account
+            .attributes
+            .insert("name".to_string(), Value::String("Contoso".to_string()));
+        account.attributes.insert(
+            "contact_customer_accounts".to_string(),
+            Value::EntityCollection(vec![contact]),
+        );
+        let navigations = HashMap::from([(
+            "contact".to_string(),
+            vec![LookupNavigation {
+                attribute: "parentcustomerid".to_string(),
+                referenced_entity: "account".to_string(),
+                navigation_property: "parentcustomerid_account".to_string(),
+            }],
+        )]);
+
+        let body = entity_to_write_body(
+            &account,
+            &HashMap::from([("account".to_string(), "accounts".to_string())]),
+            &navigations,
+        )
+        .expect("should serialize");
+        let json: serde_json::Value = serde_json::from_str(&body).expect("json");
+
+        assert_eq!(json["name"], "Contoso");
+        assert_eq!(json["contact_customer_accounts"][0]["lastname"], "Lovelace");
+        assert_eq!(
+            json["contact_customer_accounts"][0]["parentcustomerid_account@odata.bind"],
+            format!("/accounts({account_id})")
+        );
+    }
+
+    #[test]
+    fn serializes_party_lists_as_activity_parties() {
+        let contact_id = Uuid::new_v4();
+        let user_id = Uuid::new_v4();
+        let party = |id, logical_name: &str| EntityReference {
+            id,
+            logical_name: logical_name.to_string(),
+            name: None,
+        };
+        let mut email = Entity::new(Uuid::nil(), "email", None);
+        email.attributes.insert(
+            "to".to_string(),
+            Value::PartyList(vec![party(contact_id, "contact")]),
+        );
+        email.attributes.insert(
+            "from".to_string(),
+            Value::PartyList(vec![party(user_id, "systemuser")]),
+        );
+        let entity_sets = HashMap::from([
+            ("contact".to_string(), "contacts".to_string()),
+            ("systemuser".to_string(), "systemusers".to_string()),
+        ]);
+
+        let body =
+            entity_to_write_map(&email, &entity_sets, &HashMap::new()).expect("should serialize");
+        let mut parties = body["email_activity_parties"]
+            .as_array()
+            .expect("party rows")
+            .clone();
+        parties.sort_by_key(|row| row["participationtypemask"].as_i64());
+
+        assert_eq!(body.len(), 1);
+        assert_eq!(
+            parties,
+            vec![
+                serde_json::json!({
+                    "participationtypemask": 1,
+                    "partyid_systemuser@odata.bind": format!("/systemusers({user_id})"),
+                }),
+                serde_json::json!({
+                    "participationtypemask": 2,
+                    "partyid_contact@odata.bind": format!("/contacts({contact_id})"),
+                }),
+            ]
+        );
+    }
 }
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use futures_util::stream;
+use uuid::Uuid;
+
+use crate::dataverse::apierror::{ApiError, SERVICE_PROTECTION_CODES};
+use crate::dataverse::batch::{
+    ExecuteMultipleRequest, ExecuteMultipleSettings, OrganizationRequest, OrganizationResponse,
+    OrganizationServiceFault,
+};
+use crate::dataverse::bulkresult::BulkResult;
+use crate::dataverse::progress::{ProgressCallback, ProgressTracker};
+use crate::dataverse::requestid::with_operation_request_id;
+use crate::dataverse::serviceclient::ServiceClient;
+use crate::dataverse::telemetry::{record_retries, record_throttles};
+
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// HTTP statuses Dataverse returns for failures that can succeed when retried: service
+/// protection throttling and temporary unavailability.
+const TRANSIENT_STATUS_CODES: [u16; 4] = [429, 502, 503, 504];
+
+/// Options for `BulkExecutor`.
+#[derive(Debug, Clone)]
+pub struct BulkOptions {
+    /// Requests per `$batch` call, capped at 1000.
+    pub batch_size: usize,
+    /// Batches sent at the same time.
+    pub max_concurrency: usize,
+    /// Retries of a throttled or temporarily failed request before it is reported as failed.
+    pub max_retries: u32,
+    /// Wait before the first retry when Dataverse sends no `Retry-After`. Later retries double
+    /// it.
+    pub retry_delay: Duration,
+    /// Called as each batch finishes, with the batches and requests completed so far.
+    pub progress: Option<ProgressCallback>,
+}
+
+impl Default for BulkOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            max_concurrency: 4,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(1),
+            progress: None,
+        }
+    }
+}
+
+/// Why a request in a bulk run failed.
+#[derive(Debug, Clone)]
+pub struct BulkFailure {
+    /// HTTP status of the failed batch item, or `None` when the whole batch call failed.
+    pub status_code: Option<u16>,
+    /// Dataverse error code, when the response included one.
+    pub code: Option<String>,
+    /// Failure message.
+    pub message: String,
+}
+
+/// Outcome of one request in a bulk run.
+#[derive(Debug, Clone)]
+pub struct BulkItemResult {
+    /// Zero-based index of the request in the list passed to `BulkExecutor::execute`.
+    pub request_index: usize,
+    /// ID of the row: the row created or upserted, or the target of the request.
+    pub id: Option<Uuid>,
+    /// Times the request was sent, including retries.
+    pub attempts: u32,
+    /// The response on success, or why the request failed.
+    pub outcome: Result<OrganizationResponse, BulkFailure>,
+}
+
+/// Per-request results of a bulk run, in the order the requests were passed in.
+#[derive(Debug, Clone, Default)]
+pub struct BulkReport {
+    /// One result per request, ordered by `request_index`.
+    pub items: Vec<BulkItemResult>,
+}
+
+impl BulkReport {
+    /// Number of requests that succeeded.
+    pub fn succeeded(&self) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.outcome.is_ok())
+            .count()
+    }
+
+    /// Requests that failed after any retries.
+    pub fn failures(&self) -> impl Iterator<Item = &BulkItemResult> {
+        self.items.iter().filter(|item| item.outcome.is_err())
+    }
+
+    /// Row-level outcome of each request, for saving with `bulkresult::write_csv` or
+    /// `bulkresult::write_json_lines`.
+    pub fn results(&self) -> Vec<BulkResult> {
+        self.items.iter().map(BulkResult::from).collect()
+    }
+}
+
+/// Runs large sets of create, update, upsert, and delete requests as `$batch` calls with
+/// bounded concurrency, retrying throttled and temporarily failed requests.
+///
+/// Requests are split into batches in order, and requests in one batch run in order. Batches
+/// run concurrently, so a request that depends on another, such as an update of a row created
+/// earlier in the list, must be in the same batch or run with `max_concurrency` set to 1.
+pub struct BulkExecutor<'a> {
+    client: &'a ServiceClient,
+    options: BulkOptions,
+}
+
+impl<'a> BulkExecutor<'a> {
+    /// Create an executor that sends requests through `client`.
+    pub fn new(client: &'a ServiceClient, options: BulkOptions) -> Self {
+        Self { client, options }
+    }
+
+    /// Run every request and report the outcome of each one. A failed request never stops the
+    /// others; the report lists each failure with its Dataverse error.
+    pub async fn execute(&self, requests: Vec<OrganizationRequest>) -> BulkReport {
+        let batch_size = self.options.batch_size.clamp(1, MAX_BATCH_SIZE);
+        let mut progress =
+            ProgressTracker::new(self.options.progress.as_ref(), Some(requests.len()));
+        let mut chunks = Vec::new();
+        let mut requests = requests.into_iter().enumerate().peekable();
+        while requests.peek().is_some() {
+            chunks.push(requests.by_ref().take(batch_size).collect::<Vec<_>>());
+        }
+
+        let mut batches = stream::iter(chunks)
+            .map(|chunk| self.run_chunk(chunk))
+            .buffer_unordered(self.options.max_concurrency.max(1));
+        let mut items = Vec::new();
+        while let Some(batch) = batches.next().await {
+            progress.page_done(batch.len());
+            items.extend(batch);
+        }
+        items.sort_unstable_by_key(|item| item.request_index);
+
+        BulkReport { items }
+    }
+
+    /// Send one chunk as a batch, resending only the requests that failed transiently. Every
+    /// attempt carries the same client request ID.
+    async fn run_chunk(&self, chunk: Vec<(usize, OrganizationRequest)>) -> Vec<BulkItemResult> {
+        with_operation_request_id(self.run_chunk_attempts(chunk)).await
+    }
+
+    async fn run_chunk_attempts(
+        &self,
+        chunk: Vec<(usize, OrganizationRequest)>,
+    ) -> Vec<BulkItemResult> {
+        let mut results = Vec::with_capacity(chunk.len());
+        let mut pending = chunk;
+        let mut attempts = 1;
+
+        loop {
+            let can_retry = attempts <= self.options.max_retries;
+            let batch = ExecuteMultipleRequest {
+                settings: ExecuteMultipleSettings {
+                    continue_on_error: true,
+                    return_responses: true,
+                },
+                requests: pending.iter().map(|(_, request)| request.clone()).collect(),
+            };
+
+            let mut retry = Vec::new();
+            // Longest `Retry-After` Dataverse asked for among the requests to resend.
+            let mut wait = None;
+            match self.client.execute_multiple(&batch).await {
+                Ok(response) => {
+                    record_throttles(
+                        response
+                            .responses
+                            .iter()
+                            .filter_map(|item| item.fault.as_ref())
+                            .filter(|fault| ApiError::from_fault(fault).is_throttled())
+                            .count(),
+                    );
+                    let mut outcomes = response
+                        .responses
+                        .into_iter()
+                        .map(|item| (item.request_index, item))
+                        .collect::<HashMap<_, _>>();
+                    for (position, (request_index, request)) in pending.into_iter().enumerate() {
+                        let target_id = request.target_id();
+                        let outcome = match outcomes.remove(&position) {
+                            Some(item) => match (item.response, item.fault) {
+                                (_, Some(fault)) if can_retry && is_transient_fault(&fault) => {
+                                    wait = wait.max(fault.retry_after);
+                                    retry.push((request_index, request));
+                                    continue;
+                                }
+                                (_, Some(fault)) => Err(BulkFailure {
+                                    status_code: Some(fault.status_code),
+                                    code: fault.code,
+                                    message: fault.message,
+                                }),
+                                (Some(response), None) => Ok(response),
+                                (None, None) => Err(missing_response()),
+                            },
+                            None => Err(missing_response()),
+                        };
+                        results.push(BulkItemResult {
+                            request_index,
+                            id: outcome
+                                .as_ref()
+                                .ok()
+                                .and_then(OrganizationResponse::id)
+                                .or(target_id),
+                            attempts,
+                            outcome,
+                        });
+                    }
+                }
+                Err(message) if can_retry && is_transient_error(&message) => {
+                    let error = ApiError::parse(&message);
+                    wait = error.as_ref().and_then(|error| error.retry_after);
+                    // A throttled batch is rejected before it runs. After a dropped connection or a
+                    // gateway error it may have run, so only requests that are safe to send twice
+                    // are resent.
+                    let rejected = error.as_ref().is_some_and(ApiError::is_throttled);
+                    for (request_index, request) in pending {
+                        if rejected || is_idempotent(&request) {
+                            retry.push((request_index, request));
+                        } else {
+                            results.push(BulkItemResult {
+                                request_index,
+                                id: request.target_id(),
+                                attempts,
+                                outcome: Err(BulkFailure {
+                                    status_code: None,
+                                    code: None,
+                                    message: message.clone(),
+                                }),
+                            });
+                        }
+                    }
+                }
+                Err(message) => {
+                    results.extend(pending.into_iter().map(|(request_index, request)| {
+                        BulkItemResult {
+                            request_index,
+                            id: request.target_id(),
+                            attempts,
+                            outcome: Err(BulkFailure {
+                                status_code: None,
+                                code: None,
+                                message: message.clone(),
+                            }),
+                        }
+                    }));
+                }
+            }
+
+            if retry.is_empty() {
+                return results;
+            }
+            record_retries("bulk", retry.len());
+            let delay = wait.unwrap_or_else(|| retry_delay(self.options.retry_delay, attempts));
+            tokio::time::sleep(delay).await;
+            attempts += 1;
+            pending = retry;
+        }
+    }
+}
+
+/// Whether sending a request twice has the effect of sending it once: updates, upserts, deletes,
+/// and creates that name the ID of the new row, which Dataverse refuses to create twice.
+fn is_idempotent(request: &OrganizationRequest) -> bool {
+    match request {
+        OrganizationRequest::Create(create) => !create.target.id.is_nil(),
+        OrganizationRequest::Update(_)
+        | OrganizationRequest::Delete(_)
+        | OrganizationRequest::Upsert(_) => true,
+    }
+}
+
+fn missing_response() -> BulkFailure {
+    BulkFailure {
+        status_code: None,
+        code: None,
+        message: "Dataverse returned no response for the request".to_string(),
+    }
+}
+
+/// True for batch item faults that may succeed when the request is sent again.
+pub(crate) fn is_transient_fault(fault: &OrganizationServiceFault) -> bool {
+    TRANSIENT_STATUS_CODES.contains(&fault.status_code)
+        || fault.code.as_deref().is_some_and(|code| {
+            SERVICE_PROTECTION_CODES
+                .iter()
+                .any(|protection| protection.eq_ignore_ascii_case(code))
+        })
+}
+
+/// True for whole-batch errors that may succeed when the batch is sent again: connection
+/// failures and throttled or unavailable responses.
+pub(crate) fn is_transient_error(message: &str) -> bool {
+    if message.starts_with("Request failed:") {
+        return true;
+    }
+    ApiError::parse(message)
+        .is_some_and(|error| TRANSIENT_STATUS_CODES.contains(&error.status_code))
+}
+
+/// Wait before retry number `attempt`, doubling from `base`.
+pub(crate) fn retry_delay(base: Duration, attempt: u32) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        BulkFailure, BulkItemResult, BulkReport, is_transient_error, is_transient_fault,
+        retry_delay,
+    };
+    use crate::dataverse::batch::{OrganizationResponse, OrganizationServiceFault, UpdateResponse};
+
+    fn fault(status_code: u16, code: Option<&str>) -> OrganizationServiceFault {
+        OrganizationServiceFault {
+            status_code,
+            code: code.map(str::to_string),
+            message: "failed".to_string(),
+            raw_body: None,
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn throttling_and_unavailable_faults_are_transient() {
+        assert!(is_transient_fault(&fault(429, None)));
+        assert!(is_transient_fault(&fault(503, None)));
+        assert!(is_transient_fault(&fault(400, Some("0x80072322"))));
+        assert!(!is_transient_fault(&fault(400, Some("0x80040237"))));
+        assert!(!is_transient_fault(&fault(404, None)));
+    }
+
+    #[test]
+    fn connection_and_throttled_batch_errors_are_transient() {
+        assert!(is_transient_error("Request failed: connection reset"));
+        assert!(is_transient_error(
+            "Dataverse API error (429 Too Many Requests): slow down"
+        ));
+        assert!(!is_transient_error(
+            "Dataverse API error (400 Bad Request): invalid payload"
+        ));
+        assert!(!is_transient_error("Invalid option set value"));
+    }
+
+    #[test]
+    fn retry_delay_doubles_per_attempt() {
+        let base = Duration::from_millis(500);
+
+        assert_eq!(retry_delay(base, 1), Duration::from_millis(500));
+        assert_eq!(retry_delay(base, 3), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn report_counts_successes_and_failures() {
+        let report = BulkReport {
+            items: vec![
+                BulkItemResult {
+                    request_index: 0,
+                    id: None,
+                    attempts: 1,
+                    outcome: Ok(OrganizationResponse::Update(UpdateResponse)),
+                },
+                BulkItemResult {
+                    request_index: 1,
+                    id: None,
+                    attempts: 4,
+                    outcome: Err(BulkFailure {
+                        status_code: Some(429),
+                        code: None,
+                        message: "throttled".to_string(),
+                    }),
+                },
+            ],
+        };
+
+        assert_eq!(report.succeeded(), 1);
+        assert_eq!(
+            report
+                .failures()
+                .map(|item| item.request_index)
+                .collect::<Vec<_>>(),
+            vec![1]
+        );
+        let results = report.results();
+        assert!(results[0].is_success());
+        assert_eq!(results[1].status_code, Some(429));
+        assert_eq!(results[1].retry_count, 3);
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::dataverse::bulk::BulkItemResult;
+
+/// Column names of `write_csv` output, in order.
+pub const BULK_RESULT_CSV_HEADER: [&str; 7] = [
+    "index",
+    "id",
+    "status",
+    "status_code",
+    "error_code",
+    "error_message",
+    "retry_count",
+];
+
+/// Whether a record was written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+    /// The record was written.
+    Succeeded,
+    /// The record was not written.
+    Failed,
+}
+
+/// Outcome of one record of a batch, bulk, or import run, in a form that can be saved as JSON or
+/// CSV to find and resend failed records.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BulkResult {
+    /// Zero-based position of the record in the input: the request list, or the source rows of
+    /// an import.
+    pub index: usize,
+    /// ID of the row: the row created or upserted, or the target of an update or delete. `None`
+    /// when it is not known, such as for a create that failed.
+    pub id: Option<Uuid>,
+    /// Whether the record was written.
+    pub status: BulkStatus,
+    /// HTTP status of the failed request, or `None` when no request was answered.
+    pub status_code: Option<u16>,
+    /// Dataverse error code, when the response included one.
+    pub error_code: Option<String>,
+    /// Why the record failed.
+    pub error_message: Option<String>,
+    /// Times the record was resent after a throttled or temporary failure.
+    pub retry_count: u32,
+}
+
+impl BulkResult {
+    /// A record that was written.
+    pub fn succeeded(index: usize, id: Option<Uuid>, retry_count: u32) -> Self {
+        Self {
+            index,
+            id,
+            status: BulkStatus::Succeeded,
+            status_code: None,
+            error_code: None,
+            error_message: None,
+            retry_count,
+        }
+    }
+
+    /// A record that failed.
+    pub fn failed(index: usize, id: Option<Uuid>, message: impl Into<String>) -> Self {
+        Self {
+            index,
+            id,
+            status: BulkStatus::Failed,
+            status_code: None,
+            error_code: None,
+            error_message: Some(message.into()),
+            retry_count: 0,
+        }
+    }
+
+    /// Whether the record was written.
+    pub fn is_success(&self) -> bool {
+        self.status == BulkStatus::Succeeded
+    }
+}
+
+impl From<&BulkItemResult> for BulkResult {
+    fn from(item: &BulkItemResult) -> Self {
+        let retry_count = item.attempts.saturating_sub(1);
+        match &item.outcome {
+            Ok(_) => Self::succeeded(item.request_index, item.id, retry_count),
+            Err(failure) => Self {
+                status_code: failure.status_code,
+                error_code: failure.code.clone(),
+                retry_count,
+                ..Self::failed(item.request_index, item.id, failure.message.clone())
+            },
+        }
+    }
+}
+
+/// Write one JSON object per result, each on its own line.
+pub fn write_json_lines<W: Write>(results: &[BulkResult], mut writer: W) -> Result<(), String> {
+    for result in results {
+        serde_json::to_writer(&mut writer, result)
+            .map_err(|e| format!("Failed to write bulk results: {e}"))?;
+        writer
+            .write_all(b"\n")
+            .map_err(|e| format!("Failed to write bulk results: {e}"))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write bulk results: {e}"))
+}
+
+/// Write the results as CSV with a `BULK_RESULT_CSV_HEADER` header row. Missing values are
+/// empty fields.
+pub fn write_csv<W: Write>(results: &[BulkResult], mut writer: W) -> Result<(), String> {
+    let header = BULK_RESULT_CSV_HEADER.map(str::to_string);
+    writeln!(writer, "{}", csv_record(&header))
+        .map_err(|e| format!("Failed to write bulk results: {e}"))?;
+    for result in results {
+        let status = match result.status {
+            BulkStatus::Succeeded => "succeeded",
+            BulkStatus::Failed => "failed",
+        };
+        let values = [
+            result.index.to_string(),
+            result.id.map(|id| id.to_string()).unwrap_or_default(),
+            status.to_string(),
+            result
+                .status_code
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            result.error_code.clone().unwrap_or_default(),
+            result.error_message.clone().unwrap_or_default(),
+            result.retry_count.to_string(),
+        ];
+        writeln!(writer, "{}", csv_record(&values))
+            .map_err(|e| format!("Failed to write bulk results: {e}"))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write bulk results: {e}"))
+}
+
+/// Format values as one CSV record, quoting those that need it.
+pub(crate) fn csv_record(values: &[String]) -> String {
+    values
+        .iter()
+        .map(|value| {
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{BulkResult, BulkStatus, write_csv, write_json_lines};
+
+    #[test]
+    fn writes_results_as_json_lines_and_csv() {
+        let id = Uuid::parse_str("00000000-0000-0000-0000-000000000001").expect("uuid");
+        let results = vec![
+            BulkResult::succeeded(0, Some(id), 1),
+            BulkResult {
+                status_code: Some(400),
+                error_code: Some("0x80040237".to_string()),
+                ..BulkResult::failed(1, None, "Duplicate \"name\", rejected")
+            },
+        ];
+
+        let mut json = Vec::new();
+        write_json_lines(&results, &mut json).expect("json");
+        let lines = String::from_utf8(json).expect("utf8");
+        let parsed = lines
+            .lines()
+            .map(|line| serde_json::from_str::<BulkResult>(line).expect("parse"))
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, results);
+        assert!(lines.contains("\"status\":\"failed\""));
+
+        let mut csv = Vec::new();
+        write_csv(&results, &mut csv).expect("csv");
+        assert_eq!(
+            String::from_utf8(csv).expect("utf8"),
+            "index,id,status,status_code,error_code,error_message,retry_count\n\
+             0,00000000-0000-0000-0000-000000000001,succeeded,,,,1\n\
+             1,,failed,400,0x80040237,\"Duplicate \"\"name\"\", rejected\",0\n"
+        );
+        assert_eq!(parsed[1].status, BulkStatus::Failed);
+        assert!(parsed[0].is_success());
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Business units per `systemusers` query when listing the users of a subtree.
+const USER_QUERY_CHUNK: usize = 50;
+
+/// Columns read for each business unit.
+pub(crate) const BUSINESS_UNIT_COLUMNS: &str =
+    "businessunitid,name,_parentbusinessunitid_value,isdisabled";
+
+/// A `businessunit` row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BusinessUnit {
+    /// Business unit ID.
+    #[serde(rename = "businessunitid")]
+    pub business_unit_id: Uuid,
+    /// Business unit name.
+    #[serde(default)]
+    pub name: String,
+    /// Parent business unit ID, `None` for the root business unit.
+    #[serde(rename = "_parentbusinessunitid_value", default)]
+    pub parent_business_unit_id: Option<Uuid>,
+    /// True if the business unit is disabled.
+    #[serde(rename = "isdisabled", default)]
+    pub is_disabled: bool,
+}
+
+/// A business unit and the business units below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusinessUnitNode {
+    /// The business unit at this node.
+    pub business_unit: BusinessUnit,
+    /// Child business units, ordered by name.
+    pub children: Vec<BusinessUnitNode>,
+}
+
+impl BusinessUnitNode {
+    /// Find the node for `business_unit_id` in this subtree.
+    pub fn find(&self, business_unit_id: Uuid) -> Option<&BusinessUnitNode> {
+        if self.business_unit.business_unit_id == business_unit_id {
+            return Some(self);
+        }
+        self.children
+            .iter()
+            .find_map(|child| child.find(business_unit_id))
+    }
+
+    /// Every business unit in this subtree, parents before children.
+    pub fn business_units(&self) -> Vec<&BusinessUnit> {
+        let mut units = vec![&self.business_unit];
+        for child in &self.children {
+            units.extend(child.business_units());
+        }
+        units
+    }
+
+    /// IDs of every business unit in this subtree, including this one.
+    pub fn business_unit_ids(&self) -> Vec<Uuid> {
+        self.business_units()
+            .into_iter()
+            .map(|unit| unit.business_unit_id)
+            .collect()
+    }
+}
+
+/// A `systemuser` row returned for a business unit subtree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BusinessUnitUser {
+    /// User ID.
+    #[serde(rename = "systemuserid")]
+    pub system_user_id: Uuid,
+    /// Full name.
+    #[serde(rename = "fullname", default)]
+    pub full_name: Option<String>,
+    /// Sign-in name, usually the user principal name.
+    #[serde(rename = "domainname", default)]
+    pub domain_name: Option<String>,
+    /// Business unit the user belongs to.
+    #[serde(rename = "_businessunitid_value")]
+    pub business_unit_id: Uuid,
+    /// True if the user is disabled.
+    #[serde(rename = "isdisabled", default)]
+    pub is_disabled: bool,
+}
+
+/// Arrange business units into a tree under the root business unit, the one without a parent.
+pub(crate) fn build_business_unit_tree(
+    units: Vec<BusinessUnit>,
+) -> Result<BusinessUnitNode, String> {
+    let mut children: HashMap<Option<Uuid>, Vec<BusinessUnit>> = HashMap::new();
+    for unit in units {
+        children
+            .entry(unit.parent_business_unit_id)
+            .or_default()
+            .push(unit);
+    }
+
+    let root = children
+        .get_mut(&None)
+        .and_then(|roots| roots.pop())
+        .ok_or_else(|| "No root business unit found".to_string())?;
+    Ok(attach_children(root, &mut children))
+}
+
+fn attach_children(
+    business_unit: BusinessUnit,
+    children: &mut HashMap<Option<Uuid>, Vec<BusinessUnit>>,
+) -> BusinessUnitNode {
+    let mut direct = children
+        .remove(&Some(business_unit.business_unit_id))
+        .unwrap_or_default();
+    direct.sort_by(|a, b| a.name.cmp(&b.name));
+    BusinessUnitNode {
+        business_unit,
+        children: direct
+            .into_iter()
+            .map(|child| attach_children(child, children))
+            .collect(),
+    }
+}
+
+/// `systemusers` queries that together return the users of `business_unit_ids`.
+pub(crate) fn subtree_user_queries(business_unit_ids: &[Uuid]) -> Vec<String> {
+    business_unit_ids
+        .chunks(USER_QUERY_CHUNK)
+        .map(|chunk| {
+            let filter = chunk
+                .iter()
+                .map(|id| format!("_businessunitid_value eq {id}"))
+                .collect::<Vec<_>>()
+                .join(" or ");
+            format!(
+                "systemusers?$select=systemuserid,fullname,domainname,_businessunitid_value,isdisabled&$filter={filter}"
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{BusinessUnit, build_business_unit_tree, subtree_user_queries};
+
+    fn unit(name: &str, parent: Option<Uuid>) -> BusinessUnit {
+        BusinessUnit {
+            business_unit_id: Uuid::new_v4(),
+            name: name.to_string(),
+            parent_business_unit_id: parent,
+            is_disabled: false,
+        }
+    }
+
+    #[test]
+    fn builds_tree_under_root_business_unit() {
+        let root = unit("Contoso", None);
+        let sales = unit("Sales", Some(root.business_unit_id));
+        let emea = unit("EMEA", Some(sales.business_unit_id));
+        let finance = unit("Finance", Some(root.business_unit_id));
+
+        let tree = build_business_unit_tree(vec![
+            emea.clone(),
+            sales.clone(),
+            finance.clone(),
+            root.clone(),
+        ])
+        .expect("should build tree");
+
+        assert_eq!(tree.business_unit, root);
+        assert_eq!(
+            tree.children
+                .iter()
+                .map(|child| child.business_unit.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Finance", "Sales"]
+        );
+        let sales_node = tree.find(sales.business_unit_id).expect("should find Sales");
+        assert_eq!(
+            sales_node.business_unit_ids(),
+            vec![sales.business_unit_id, emea.business_unit_id]
+        );
+        assert!(tree.find(Uuid::new_v4()).is_none());
+        assert!(build_business_unit_tree(vec![emea]).is_err());
+    }
+
+    #[test]
+    fn user_queries_filter_by_business_unit_in_chunks() {
+        let ids = (0..51).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+
+        let queries = subtree_user_queries(&ids);
+
+        assert_eq!(queries.len(), 2);
+        assert!(queries[0].starts_with("systemusers?$select=systemuserid,"));
+        assert_eq!(queries[0].matches(" or ").count(), 49);
+        assert!(queries[1].ends_with(&format!("$filter=_businessunitid_value eq {}", ids[50])));
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::dataverse::entityattribute::EntityAttribute;
+
+/// Prefix of the error message returned when a table does not support a feature.
+const UNSUPPORTED_PREFIX: &str = "Unsupported: ";
+
+/// Storage type of a table, from `EntityMetadata.TableType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableType {
+    /// Rows stored in Dataverse's relational store.
+    Standard,
+    /// Rows stored in Azure Cosmos DB for high-volume data.
+    Elastic,
+    /// Rows read from an external source through a data provider.
+    Virtual,
+    /// A table type this crate does not know yet.
+    Unknown,
+}
+
+/// Features a table has to support before high-level helpers use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFeature {
+    /// Change tracking delta queries, used by `retrieve_changes`.
+    ChangeTracking,
+    /// Reading the table's audit history.
+    Audit,
+    /// File column upload and download.
+    FileColumns,
+}
+
+impl fmt::Display for TableFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::ChangeTracking => "change tracking",
+            Self::Audit => "audit history",
+            Self::FileColumns => "file columns",
+        })
+    }
+}
+
+/// What a table supports, derived from its metadata.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableCapabilities {
+    /// Table logical name.
+    pub logical_name: String,
+    /// Storage type of the table.
+    pub table_type: TableType,
+    /// True if change tracking is enabled on the table.
+    pub change_tracking_enabled: bool,
+    /// True if auditing is enabled on the table.
+    pub audit_enabled: bool,
+    /// Logical names of the table's file columns.
+    pub file_columns: Vec<String>,
+}
+
+impl TableCapabilities {
+    /// True for virtual tables.
+    pub fn is_virtual(&self) -> bool {
+        self.table_type == TableType::Virtual
+    }
+
+    /// True for elastic tables.
+    pub fn is_elastic(&self) -> bool {
+        self.table_type == TableType::Elastic
+    }
+
+    /// True if the table has at least one file column.
+    pub fn has_file_columns(&self) -> bool {
+        !self.file_columns.is_empty()
+    }
+
+    /// Fail with guidance when the table does not support `feature`.
+    pub fn ensure(&self, feature: TableFeature) -> Result<(), Unsupported> {
+        let guidance = match feature {
+            _ if self.is_virtual() => Some(
+                "Virtual tables read rows from an external data provider; use the provider's own API for this."
+                    .to_string(),
+            ),
+            TableFeature::ChangeTracking if !self.change_tracking_enabled => Some(
+                "Turn on 'Track changes' in the table's advanced options in Power Apps.".to_string(),
+            ),
+            TableFeature::Audit if !self.audit_enabled => Some(
+                "Turn on 'Audit changes to its data' for the table, and auditing for the environment."
+                    .to_string(),
+            ),
+            TableFeature::FileColumns if !self.has_file_columns() => {
+                Some("Add a file column to the table before uploading files.".to_string())
+            }
+            _ => None,
+        };
+
+        match guidance {
+            Some(guidance) => Err(Unsupported {
+                logical_name: self.logical_name.clone(),
+                feature,
+                guidance,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A feature a table does not support, with guidance on how to enable it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    /// Table logical name.
+    pub logical_name: String,
+    /// The feature that was requested.
+    pub feature: TableFeature,
+    /// What to change so the feature works, or what to use instead.
+    pub guidance: String,
+}
+
+impl Unsupported {
+    /// True if an error message returned by this crate reports an unsupported table feature.
+    pub fn is_unsupported(message: &str) -> bool {
+        message.starts_with(UNSUPPORTED_PREFIX)
+    }
+}
+
+/// Formats as the error message the client returns, starting with `Unsupported: `.
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{UNSUPPORTED_PREFIX}'{}' does not support {}. {}",
+            self.logical_name, self.feature, self.guidance
+        )
+    }
+}
+
+/// Read capabilities from a table definition selected with `TableType`, `ChangeTrackingEnabled`,
+/// and `IsAuditEnabled`, and the table's attributes.
+pub(crate) fn parse_table_capabilities(
+    logical_name: &str,
+    definition: &Value,
+    attributes: &[EntityAttribute],
+) -> TableCapabilities {
+    let table_type = match definition.get("TableType").and_then(Value::as_str) {
+        Some(table_type) if table_type.eq_ignore_ascii_case("Standard") => TableType::Standard,
+        Some(table_type) if table_type.eq_ignore_ascii_case("Elastic") => TableType::Elastic,
+        Some(table_type) if table_type.eq_ignore_ascii_case("Virtual") => TableType::Virtual,
+        Some(_) => TableType::Unknown,
+        None => TableType::Standard,
+    };
+
+    TableCapabilities {
+        logical_name: logical_name.to_string(),
+        table_type,
+        change_tracking_enabled: definition
+            .get("ChangeTrackingEnabled")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        audit_enabled: definition
+            .get("IsAuditEnabled")
+            .and_then(|value| value.get("Value"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        file_columns: attributes
+            .iter()
+            .filter(|attribute| {
+                attribute
+                    .attribute_type_name
+                    .as_ref()
+                    .and_then(|name| name.value.as_deref())
+                    == Some("FileType")
+            })
+            .map(|attribute| attribute.logical_name.clone())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{TableFeature, TableType, Unsupported, parse_table_capabilities};
+    use crate::dataverse::entityattribute::EntityAttribute;
+
+    fn attribute(logical_name: &str, type_name: &str) -> EntityAttribute {
+        serde_json::from_value(json!({
+            "LogicalName": logical_name,
+            "SchemaName": logical_name,
+            "AttributeTypeName": {"Value": type_name}
+        }))
+        .expect("should parse")
+    }
+
+    #[test]
+    fn reads_capabilities_from_metadata() {
+        let capabilities = parse_table_capabilities(
+            "cr_document",
+            &json!({
+                "TableType": "Standard",
+                "ChangeTrackingEnabled": true,
+                "IsAuditEnabled": {"Value": false, "CanBeChanged": true}
+            }),
+            &[
+                attribute("cr_name", "StringType"),
+                attribute("cr_contract", "FileType"),
+            ],
+        );
+
+        assert_eq!(capabilities.table_type, TableType::Standard);
+        assert!(capabilities.ensure(TableFeature::ChangeTracking).is_ok());
+        assert!(capabilities.ensure(TableFeature::FileColumns).is_ok());
+        assert_eq!(capabilities.file_columns, vec!["cr_contract"]);
+
+        let audit = capabilities
+            .ensure(TableFeature::Audit)
+            .expect_err("auditing is off");
+        assert_eq!(audit.feature, TableFeature::Audit);
+        assert!(Unsupported::is_unsupported(&audit.to_string()));
+        assert!(
+            audit
+                .to_string()
+                .starts_with("Unsupported: 'cr_document' does not support audit history.")
+        );
+    }
+
+    #[test]
+    fn virtual_tables_support_no_features() {
+        let capabilities = parse_table_capabilities(
+            "cr_external",
+            &json!({"TableType": "Virtual", "ChangeTrackingEnabled": true}),
+            &[attribute("cr_file", "FileType")],
+        );
+
+        assert!(capabilities.is_virtual());
+        assert!(capabilities.ensure(TableFeature::ChangeTracking).is_err());
+        assert!(capabilities.ensure(TableFeature::FileColumns).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::dataverse::entitydefinition::EntityDefinition;
+use crate::dataverse::label::LocalizedLabel;
+
+/// Record count for a single table.
+#[derive(Debug, Clone)]
+pub struct TableRecordCount {
+    /// Table logical name.
+    pub logical_name: String,
+    /// Entity set name.
+    pub entity_set_name: String,
+    /// Localized display name, when the table has one.
+    pub display_name: Option<String>,
+    /// True if the table is custom.
+    pub is_custom_entity: bool,
+    /// Number of rows in the table.
+    pub record_count: i64,
+}
+
+/// Record counts across tables, largest table first.
+#[derive(Debug, Clone, Default)]
+pub struct CapacityReport {
+    /// Per-table record counts, ordered by descending `record_count`.
+    pub tables: Vec<TableRecordCount>,
+    /// Sum of all table record counts.
+    pub total_records: i64,
+}
+
+/// Read the `EntityRecordCountCollection` returned by `RetrieveTotalRecordCount`.
+pub(crate) fn parse_record_count_collection(json: &Value) -> Result<HashMap<String, i64>, String> {
+    let collection = json
+        .get("EntityRecordCountCollection")
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+    let keys = collection
+        .get("Keys")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+    let values = collection
+        .get("Values")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    Ok(keys
+        .iter()
+        .zip(values)
+        .filter_map(|(key, value)| Some((key.as_str()?.to_string(), value.as_i64()?)))
+        .collect())
+}
+
+/// Combine record counts with table metadata into a report.
+pub(crate) fn build_capacity_report(
+    definitions: &[EntityDefinition],
+    counts: &HashMap<String, i64>,
+) -> CapacityReport {
+    let mut tables: Vec<TableRecordCount> = definitions
+        .iter()
+        .filter_map(|definition| {
+            let record_count = *counts.get(&definition.logical_name)?;
+            Some(TableRecordCount {
+                logical_name: definition.logical_name.clone(),
+                entity_set_name: definition.entity_set_name.clone(),
+                display_name: definition
+                    .display_name
+                    .as_ref()
+                    .and_then(LocalizedLabel::user_localized)
+                    .map(str::to_string),
+                is_custom_entity: definition.is_custom_entity,
+                record_count,
+            })
+        })
+        .collect();
+    tables.sort_by(|left, right| {
+        right
+            .record_count
+            .cmp(&left.record_count)
+            .then_with(|| left.logical_name.cmp(&right.logical_name))
+    });
+
+    CapacityReport {
+        total_records: tables.iter().map(|table| table.record_count).sum(),
+        tables,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{build_capacity_report, parse_record_count_collection};
+    use crate::dataverse::entitydefinition::EntityDefinition;
+    use crate::dataverse::label::LocalizedLabel;
+
+    fn definition(logical_name: &str, label: &str) -> EntityDefinition {
+        EntityDefinition {
+            odata_context: None,
+            logical_name: logical_name.to_string(),
+            schema_name: logical_name.to_string(),
+            display_name: LocalizedLabel::from_value(Some(&json!({
+                "UserLocalizedLabel": { "Label": label }
+            }))),
+            entity_set_name: format!("{logical_name}s"),
+            is_custom_entity: false,
+            is_activity: None,
+            ownership_type: None,
+            primary_id_attribute: None,
+            primary_name_attribute: None,
+            extra: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn parses_record_count_collection() {
+        let counts = parse_record_count_collection(&json!({
+            "EntityRecordCountCollection": {
+                "Count": 2,
+                "IsReadOnly": false,
+                "Keys": ["account", "contact"],
+                "Values": [10, 250]
+            }
+        }))
+        .expect("should parse");
+
+        assert_eq!(counts.get("account"), Some(&10));
+        assert_eq!(counts.get("contact"), Some(&250));
+    }
+
+    #[test]
+    fn report_orders_tables_by_size_with_display_names() {
+        let definitions = vec![
+            definition("account", "Account"),
+            definition("contact", "Contact"),
+        ];
+        let counts = HashMap::from([("account".to_string(), 10), ("contact".to_string(), 250)]);
+
+        let report = build_capacity_report(&definitions, &counts);
+
+        assert_eq!(report.total_records, 260);
+        assert_eq!(report.tables[0].logical_name, "contact");
+        assert_eq!(report.tables[0].display_name.as_deref(), Some("Contact"));
+        assert_eq!(report.tables[1].record_count, 10);
+    }
+}
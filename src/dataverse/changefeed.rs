@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::Stream;
+use futures_util::stream;
+use sha2::{Digest, Sha256};
+
+use crate::dataverse::apierror::ApiError;
+use crate::dataverse::serviceclient::ServiceClient;
+use crate::dataverse::sync::{ChangeEvent, ChangeTrackingResult};
+
+/// Default wait between polls.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Where a `ChangeFeed` keeps the delta link of each table and query between polls and restarts.
+pub trait DeltaTokenStore: Send + Sync {
+    /// The stored delta link for `key`, or `None` before the first poll.
+    fn load(&self, key: &str) -> Result<Option<String>, String>;
+    /// Store the delta link for `key`, replacing any earlier one.
+    fn save(&self, key: &str, delta_link: &str) -> Result<(), String>;
+    /// Forget the delta link for `key`, so the next poll starts over.
+    fn clear(&self, key: &str) -> Result<(), String>;
+}
+
+/// Delta links held in memory, lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryDeltaTokenStore {
+    links: Mutex<HashMap<String, String>>,
+}
+
+impl DeltaTokenStore for MemoryDeltaTokenStore {
+    fn load(&self, key: &str) -> Result<Option<String>, String> {
+        let links = self
+            .links
+            .lock()
+            .map_err(|_| "Delta token store lock poisoned".to_string())?;
+        Ok(links.get(key).cloned())
+    }
+
+    fn save(&self, key: &str, delta_link: &str) -> Result<(), String> {
+        self.links
+            .lock()
+            .map_err(|_| "Delta token store lock poisoned".to_string())?
+            .insert(key.to_string(), delta_link.to_string());
+        Ok(())
+    }
+
+    fn clear(&self, key: &str) -> Result<(), String> {
+        self.links
+            .lock()
+            .map_err(|_| "Delta token store lock poisoned".to_string())?
+            .remove(key);
+        Ok(())
+    }
+}
+
+/// Delta links saved as one `{key}.deltalink` file per table and query in a directory.
+#[derive(Debug, Clone)]
+pub struct FileDeltaTokenStore {
+    directory: PathBuf,
+}
+
+impl FileDeltaTokenStore {
+    /// Store delta links in `directory`, creating it on the first save.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let file_name = key
+            .chars()
+            .map(|ch| if ch.is_ascii_alphanumeric() { ch } else { '_' })
+            .collect::<String>();
+        self.directory.join(format!("{file_name}.deltalink"))
+    }
+}
+
+impl DeltaTokenStore for FileDeltaTokenStore {
+    fn load(&self, key: &str) -> Result<Option<String>, String> {
+        match fs::read_to_string(self.path(key)) {
+            Ok(link) => Ok(Some(link.trim().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Failed to read delta link: {e}")),
+        }
+    }
+
+    fn save(&self, key: &str, delta_link: &str) -> Result<(), String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| format!("Failed to create delta link directory: {e}"))?;
+        fs::write(self.path(key), delta_link).map_err(|e| format!("Failed to save delta link: {e}"))
+    }
+
+    fn clear(&self, key: &str) -> Result<(), String> {
+        match fs::remove_file(self.path(key)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to remove delta link: {e}")),
+        }
+    }
+}
+
+/// Polls change tracking for one table and hands each batch of created, updated, and deleted
+/// rows to the caller, keeping its position in a `DeltaTokenStore`.
+///
+/// A delta link is saved only after its batch has been handled, so a batch whose handling fails
+/// or is interrupted is read again on the next poll.
+pub struct ChangeFeed<'a> {
+    client: &'a ServiceClient,
+    entity: String,
+    query: String,
+    key: String,
+    store: Arc<dyn DeltaTokenStore>,
+    interval: Duration,
+    skip_initial_rows: bool,
+}
+
+impl<'a> ChangeFeed<'a> {
+    /// Follow changes to `entity` (an entity set name) for rows matching `query`, such as
+    /// `$select=name,statecode`. The table must have change tracking enabled. The delta link is
+    /// stored under `store_key(entity, query)`, so a feed with a different query starts over.
+    pub fn new(
+        client: &'a ServiceClient,
+        entity: &str,
+        query: &str,
+        store: Arc<dyn DeltaTokenStore>,
+    ) -> Self {
+        Self {
+            client,
+            entity: entity.to_string(),
+            query: query.to_string(),
+            key: store_key(entity, query),
+            store,
+            interval: DEFAULT_POLL_INTERVAL,
+            skip_initial_rows: false,
+        }
+    }
+
+    /// Wait `interval` between polls. Defaults to 30 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// On the first poll without a stored delta link, record the link without reporting the
+    /// table's existing rows, so only later changes are delivered.
+    pub fn skip_initial_rows(mut self, skip: bool) -> Self {
+        self.skip_initial_rows = skip;
+        self
+    }
+
+    /// Forget the stored delta link, so the next poll starts over from a full read.
+    pub fn forget(&self) -> Result<(), String> {
+        self.store.clear(&self.key)
+    }
+
+    /// Read the changes since the stored delta link without saving the new one. The first read
+    /// without a stored link reports every matching row, unless `skip_initial_rows` is set. A
+    /// stored link that Dataverse rejects, such as one that expired, is forgotten, so the next
+    /// poll starts over instead of failing the same way.
+    async fn read(&self) -> Result<ChangeTrackingResult, String> {
+        match self.store.load(&self.key)? {
+            Some(delta_link) => {
+                let result = self
+                    .client
+                    .retrieve_changes_since(&self.entity, &delta_link)
+                    .await;
+                if let Err(e) = &result
+                    && is_rejected_link(e)
+                {
+                    self.forget()?;
+                }
+                result
+            }
+            None => {
+                let mut result = self
+                    .client
+                    .retrieve_changes(&self.entity, &self.query)
+                    .await?;
+                if self.skip_initial_rows {
+                    result.changes.clear();
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn commit(&self, result: &ChangeTrackingResult) -> Result<(), String> {
+        match &result.delta_link {
+            Some(delta_link) => self.store.save(&self.key, delta_link),
+            None => Ok(()),
+        }
+    }
+
+    /// Poll once: hand any changes to `on_changes`, then save the new delta link. Returns the
+    /// number of changes handled.
+    pub async fn poll<F>(&self, mut on_changes: F) -> Result<usize, String>
+    where
+        F: AsyncFnMut(Vec<ChangeEvent>) -> Result<(), String>,
+    {
+        let mut result = self.read().await?;
+        let count = result.changes.len();
+        if count > 0 {
+            on_changes(std::mem::take(&mut result.changes)).await?;
+        }
+        self.commit(&result)?;
+        Ok(count)
+    }
+
+    /// Poll every `interval` until a poll or `on_changes` fails, returning that error. Returns
+    /// `Ok` at the next poll after the client is shut down. Drop the future to stop sooner.
+    pub async fn run<F>(&self, mut on_changes: F) -> Result<(), String>
+    where
+        F: AsyncFnMut(Vec<ChangeEvent>) -> Result<(), String>,
+    {
+        while !self.client.is_shut_down() {
+            self.poll(&mut on_changes).await?;
+            tokio::time::sleep(self.interval).await;
+        }
+        Ok(())
+    }
+
+    /// Batches of changes as a stream, polling every `interval` and skipping empty polls. A
+    /// batch's delta link is saved when the next item is requested, so stopping before that
+    /// reads the batch again later. A failed poll yields the error and is retried after the
+    /// interval. The stream ends at the next poll after the client is shut down.
+    pub fn into_stream(self) -> impl Stream<Item = Result<Vec<ChangeEvent>, String>> + 'a {
+        stream::unfold(
+            (self, None::<ChangeTrackingResult>, true),
+            |(feed, pending, first)| async move {
+                if let Some(handled) = &pending
+                    && let Err(e) = feed.commit(handled)
+                {
+                    return Some((Err(e), (feed, None, false)));
+                }
+                let mut wait = !first;
+                loop {
+                    if wait {
+                        tokio::time::sleep(feed.interval).await;
+                    }
+                    wait = true;
+                    if feed.client.is_shut_down() {
+                        return None;
+                    }
+                    match feed.read().await {
+                        Ok(mut result) if result.changes.is_empty() => {
+                            if let Err(e) = feed.commit(&result) {
+                                return Some((Err(e), (feed, None, false)));
+                            }
+                            result.delta_link = None;
+                        }
+                        Ok(mut result) => {
+                            let changes = std::mem::take(&mut result.changes);
+                            return Some((Ok(changes), (feed, Some(result), false)));
+                        }
+                        Err(e) => return Some((Err(e), (feed, None, false))),
+                    }
+                }
+            },
+        )
+    }
+}
+
+/// The store key of a feed: the entity set name followed by a hash of the query, so feeds on
+/// the same table with different queries keep separate delta links.
+pub fn store_key(entity: &str, query: &str) -> String {
+    let digest = Sha256::digest(query.as_bytes());
+    let hash = digest[..8]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!("{entity}-{hash}")
+}
+
+/// True when Dataverse refused a delta link itself rather than failing for a passing reason:
+/// an expired or invalid link is answered with `400 Bad Request` or `410 Gone`.
+fn is_rejected_link(error: &str) -> bool {
+    ApiError::parse(error).is_some_and(|error| matches!(error.status_code, 400 | 410))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{DeltaTokenStore, FileDeltaTokenStore, MemoryDeltaTokenStore, store_key};
+    use crate::testsupport::unique_temp_path;
+
+    #[test]
+    fn stores_save_load_and_clear_links() {
+        let directory = unique_temp_path("delta");
+        let stores: [Box<dyn DeltaTokenStore>; 2] = [
+            Box::new(MemoryDeltaTokenStore::default()),
+            Box::new(FileDeltaTokenStore::new(&directory)),
+        ];
+
+        for store in stores {
+            assert_eq!(store.load("accounts").expect("load"), None);
+            store
+                .save(
+                    "accounts",
+                    "https://example.crm.dynamics.com/api/data/v9.2/accounts?$deltatoken=1",
+                )
+                .expect("save");
+            assert_eq!(
+                store.load("accounts").expect("load").as_deref(),
+                Some("https://example.crm.dynamics.com/api/data/v9.2/accounts?$deltatoken=1")
+            );
+            store.clear("accounts").expect("clear");
+            store.clear("accounts").expect("clear twice");
+            assert_eq!(store.load("accounts").expect("load"), None);
+        }
+
+        fs::remove_dir_all(directory).ok();
+    }
+
+    #[test]
+    fn store_keys_differ_by_query() {
+        let key = store_key("accounts", "$select=name");
+        assert!(key.starts_with("accounts-"));
+        assert_eq!(key, store_key("accounts", "$select=name"));
+        assert_ne!(key, store_key("accounts", "$select=name,statecode"));
+        assert_ne!(key, store_key("contacts", "$select=name"));
+    }
+}
@@ -0,0 +1,616 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::RequestBuilder;
+use reqwest::header::{HeaderName, HeaderValue};
+
+use crate::LogLevel;
+use crate::auth::config::AuthConfig;
+use crate::auth::connectionstring::{
+    parse_connection_string_auth_config, parse_connection_string_url,
+};
+use crate::auth::tokencache::TokenCache;
+use crate::dataverse::dataverseurl::DataverseUrl;
+use crate::dataverse::pageretry::PageRetryPolicy;
+use crate::dataverse::requestparameters::is_bypass_header;
+use crate::dataverse::responsemeta::{ResponseCallback, ResponseMeta};
+use crate::dataverse::serviceclient::ServiceClient;
+use crate::dataverse::transport::TransportMode;
+use crate::dataverse::url::DEFAULT_API_VERSION;
+
+/// Adjusts every Dataverse Web API request a `ServiceClient` sends, for example to add a header
+/// a proxy or gateway expects. Token requests do not pass through middleware.
+pub trait RequestMiddleware: Send + Sync {
+    /// Return the request to send in place of `request`.
+    fn on_request(&self, request: RequestBuilder) -> RequestBuilder;
+}
+
+/// How a built client authenticates.
+#[derive(Clone)]
+pub(crate) enum Credentials {
+    /// Acquire and refresh tokens with this configuration.
+    Auth(AuthConfig),
+    /// Send this token as is. It is never refreshed.
+    StaticToken(String),
+    /// Share this token state with other clients.
+    TokenCache(Arc<TokenCache>),
+}
+
+/// Step-by-step construction of a `ServiceClient`, for settings the positional constructors do
+/// not cover.
+///
+/// Set credentials with `auth`, `connection_string`, or `static_token`; everything else has a
+/// default.
+#[derive(Clone)]
+pub struct ServiceClientBuilder {
+    pub(crate) url: Option<String>,
+    pub(crate) credentials: Option<Credentials>,
+    pub(crate) connection_string: Option<String>,
+    pub(crate) api_version: String,
+    pub(crate) page_retry_policy: PageRetryPolicy,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) http2_only: bool,
+    pub(crate) pool_idle_timeout: Option<Duration>,
+    pub(crate) pool_max_idle_per_host: Option<usize>,
+    pub(crate) shared_http_client: Option<reqwest::Client>,
+    pub(crate) middleware: Vec<Arc<dyn RequestMiddleware>>,
+    pub(crate) default_headers: Vec<(String, String)>,
+    pub(crate) allow_bypass_custom_logic: bool,
+    pub(crate) allow_insecure_url: bool,
+    pub(crate) on_response: Option<ResponseCallback>,
+    pub(crate) log_level: LogLevel,
+    pub(crate) transport: TransportMode,
+    pub(crate) label_language: Option<i32>,
+}
+
+impl Default for ServiceClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServiceClientBuilder {
+    /// A builder with no credentials, Web API v9.2, the default page retry policy, no timeouts,
+    /// no middleware or default headers, `LogLevel::Error`, a live transport, and labels in the caller's language.
+    pub fn new() -> Self {
+        Self {
+            url: None,
+            credentials: None,
+            connection_string: None,
+            api_version: DEFAULT_API_VERSION.to_string(),
+            page_retry_policy: PageRetryPolicy::default(),
+            timeout: None,
+            connect_timeout: None,
+            http2_only: false,
+            pool_idle_timeout: None,
+            pool_max_idle_per_host: None,
+            shared_http_client: None,
+            middleware: Vec::new(),
+            default_headers: Vec::new(),
+            allow_bypass_custom_logic: false,
+            allow_insecure_url: false,
+            on_response: None,
+            log_level: LogLevel::Error,
+            transport: TransportMode::Live,
+            label_language: None,
+        }
+    }
+
+    /// Dataverse environment URL, such as `https://contoso.crm.dynamics.com`. Defaults to the
+    /// URL in the auth configuration or connection string; required with `static_token`.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Acquire and refresh tokens with `auth`.
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.credentials = Some(Credentials::Auth(auth));
+        self.connection_string = None;
+        self
+    }
+
+    /// Read the URL and auth configuration from a Dataverse connection string when building.
+    pub fn connection_string(mut self, connection_string: impl Into<String>) -> Self {
+        self.connection_string = Some(connection_string.into());
+        self.credentials = None;
+        self
+    }
+
+    /// Send `access_token` on every request instead of acquiring one, for tokens obtained
+    /// elsewhere such as a managed identity. The token is never refreshed, so build a new client
+    /// before it expires.
+    pub fn static_token(mut self, access_token: impl Into<String>) -> Self {
+        self.credentials = Some(Credentials::StaticToken(access_token.into()));
+        self.connection_string = None;
+        self
+    }
+
+    /// Take tokens from `cache`, shared with every other client built from it, such as a cache
+    /// renewed in the background by `TokenCache::spawn_auto_refresh`. A `url` set as well must
+    /// be the environment the cache's tokens are for.
+    pub fn token_cache(mut self, cache: Arc<TokenCache>) -> Self {
+        self.credentials = Some(Credentials::TokenCache(cache));
+        self.connection_string = None;
+        self
+    }
+
+    /// Web API version, such as `9.1`. Defaults to `9.2`. Applies to every request, including the
+    /// parts of a `$batch`.
+    pub fn api_version(mut self, version: impl Into<String>) -> Self {
+        self.api_version = version.into();
+        self
+    }
+
+    /// Retries of a failed page in multi-page reads. See `ServiceClient::set_page_retry_policy`.
+    pub fn page_retry_policy(mut self, policy: PageRetryPolicy) -> Self {
+        self.page_retry_policy = policy;
+        self
+    }
+
+    /// Fail a Web API request that has not completed within `timeout`, from connecting until the
+    /// response body is read.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fail a Web API request that cannot connect within `timeout`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Speak HTTP/2 from the first request instead of negotiating it, so parallel requests share
+    /// one multiplexed connection per host rather than opening a connection each. Without it,
+    /// HTTP/2 is still used when the server offers it during the TLS handshake.
+    pub fn http2_only(mut self, http2_only: bool) -> Self {
+        self.http2_only = http2_only;
+        self
+    }
+
+    /// Close pooled connections that have been idle for `timeout`. Defaults to 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep at most `max` idle connections per host for reuse. Connections beyond it are closed
+    /// once their request completes; the number of requests in flight is not limited, so bound
+    /// parallel loads with `BulkOptions::max_concurrency` instead. Unlimited by default.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    /// Send Web API requests through `client`, such as the one `ServiceClient::http_client`
+    /// returns, so several clients for the same environment share one connection pool. The
+    /// timeout, HTTP/2, and pool settings belong to the shared client and cannot be set here too.
+    pub fn shared_http_client(mut self, client: reqwest::Client) -> Self {
+        self.shared_http_client = Some(client);
+        self
+    }
+
+    /// Pass every request through `middleware`, after any added earlier. Middleware runs before
+    /// the client adds `CallerObjectId` and `x-ms-client-request-id`; token requests do not pass
+    /// through it.
+    pub fn middleware(mut self, middleware: Arc<dyn RequestMiddleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Send the header `name: value` on every Web API request, such as
+    /// `MSCRM.SolutionUniqueName` to add created components to a solution. Setting a name again
+    /// replaces its value. A header set on the call itself, through `RequestOptions::headers` or
+    /// `RequestParameters::custom_headers`, takes precedence. A `$batch` request carries the
+    /// header once, not on each of its parts.
+    pub fn default_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        self.default_headers
+            .retain(|(existing, _)| !existing.eq_ignore_ascii_case(&name));
+        self.default_headers.push((name, value.into()));
+        self
+    }
+
+    /// Allow writes that skip custom plug-ins and flows, through the bypass fields of
+    /// `RequestParameters` or bypass headers. Off by default, so a write asking to bypass fails
+    /// before it is sent unless the client opted in, for example in data migration tooling.
+    pub fn allow_bypass_custom_logic(mut self, allow: bool) -> Self {
+        self.allow_bypass_custom_logic = allow;
+        self
+    }
+
+    /// Accept an `http` URL or a single-label host such as `localhost`, for a local mock server
+    /// or proxy. Off by default, so the URL must be an `https` environment URL. See
+    /// `DataverseUrl::parse_insecure`. Tokens for `auth` are then requested for the same URL, so
+    /// pair it with `AuthConfig::client_credentials_for_insecure_environment`.
+    pub fn allow_insecure_url(mut self, allow: bool) -> Self {
+        self.allow_insecure_url = allow;
+        self
+    }
+
+    /// Call `callback` with the status, request IDs, and rate limit headers of every Web API
+    /// response, successful or not, for example to log the `x-ms-service-request-id` of each
+    /// write. Responses to `$batch` requests are reported once, not per part. The callback runs
+    /// on the task that sent the request, so it should return quickly.
+    pub fn on_response(mut self, callback: impl Fn(&ResponseMeta) + Send + Sync + 'static) -> Self {
+        self.on_response = Some(Arc::new(callback));
+        self
+    }
+
+    /// SDK log verbosity. Defaults to `LogLevel::Error`.
+    pub fn log_level(mut self, log_level: LogLevel) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Record or replay traffic instead of only sending it. See `TransportMode`.
+    pub fn transport(mut self, transport: TransportMode) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Read metadata labels in the language `lcid`, such as 1036 for French, instead of the
+    /// calling user's language. The language must be provisioned in the environment; labels
+    /// without a translation in it stay in the caller's language.
+    ///
+    /// Every `UserLocalizedLabel` in a metadata response, such as table display names and choice
+    /// labels, is replaced by its translation before parsing. Formatted values follow the user's
+    /// personal settings instead; see `ServiceClient::save_user_ui_language`.
+    pub fn label_language(mut self, lcid: i32) -> Self {
+        self.label_language = Some(lcid);
+        self
+    }
+
+    /// Resolve the credentials and environment URL, checking the settings without contacting
+    /// Dataverse.
+    pub(crate) fn resolve(&self) -> Result<(Credentials, String), String> {
+        let credentials = match (&self.credentials, &self.connection_string) {
+            (Some(credentials), _) => credentials.clone(),
+            (None, Some(connection_string)) => Credentials::Auth(
+                parse_connection_string_auth_config(connection_string)?,
+            ),
+            (None, None) => {
+                return Err(
+                    "ServiceClientBuilder needs auth, a connection string, a static token, or a token cache"
+                        .to_string(),
+                );
+            }
+        };
+        let url = match (&self.url, &self.connection_string, &credentials) {
+            (Some(url), _, _) => url.clone(),
+            (None, Some(connection_string), _) => parse_connection_string_url(connection_string)?,
+            (None, None, Credentials::Auth(auth)) => auth.dataverse_url().to_string(),
+            (None, None, Credentials::TokenCache(cache)) => match cache.auth() {
+                Some(auth) => auth.dataverse_url().to_string(),
+                None => {
+                    return Err("ServiceClientBuilder needs a url with a static token".to_string());
+                }
+            },
+            (None, None, Credentials::StaticToken(_)) => {
+                return Err("ServiceClientBuilder needs a url with a static token".to_string());
+            }
+        };
+        let version = self.api_version.trim_start_matches('v');
+        if version.is_empty()
+            || !version
+                .split('.')
+                .all(|part| !part.is_empty() && part.chars().all(|ch| ch.is_ascii_digit()))
+        {
+            return Err(format!(
+                "Invalid Web API version '{}'; expected a version such as 9.2",
+                self.api_version
+            ));
+        }
+        let parse = if self.allow_insecure_url {
+            DataverseUrl::parse_insecure
+        } else {
+            DataverseUrl::parse
+        };
+        let url = parse(&url)?;
+        if let Credentials::TokenCache(cache) = &credentials
+            && let Some(auth) = cache.auth()
+        {
+            let cache_url = parse(auth.dataverse_url())?;
+            if cache_url != url {
+                return Err(format!(
+                    "The token cache holds tokens for {cache_url}, not {url}"
+                ));
+            }
+        }
+        Ok((credentials, url.to_string()))
+    }
+
+    /// Parse the default headers, rejecting invalid names and values, and bypass headers unless
+    /// `allow_bypass_custom_logic` is set.
+    pub(crate) fn header_values(&self) -> Result<Vec<(HeaderName, HeaderValue)>, String> {
+        self.default_headers
+            .iter()
+            .map(|(name, value)| {
+                if !self.allow_bypass_custom_logic && is_bypass_header(name) {
+                    return Err(bypass_not_allowed());
+                }
+                let header = HeaderName::from_bytes(name.as_bytes())
+                    .map_err(|_| format!("Invalid default header name '{name}'"))?;
+                let value = HeaderValue::from_str(value)
+                    .map_err(|_| format!("Invalid value for default header '{name}'"))?;
+                Ok((header, value))
+            })
+            .collect()
+    }
+
+    /// Build the HTTP client with the configured timeouts.
+    pub(crate) fn http_client(&self) -> Result<reqwest::Client, String> {
+        if let Some(client) = &self.shared_http_client {
+            if self.timeout.is_some()
+                || self.connect_timeout.is_some()
+                || self.http2_only
+                || self.pool_idle_timeout.is_some()
+                || self.pool_max_idle_per_host.is_some()
+            {
+                return Err(
+                    "Timeout, HTTP/2, and pool settings cannot be combined with a shared HTTP client"
+                        .to_string(),
+                );
+            }
+            return Ok(client.clone());
+        }
+
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if self.http2_only {
+            builder = builder.http2_prior_knowledge();
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Failed to build HTTP client: {e}"))
+    }
+
+    /// Build the client. Unless the transport replays a recording, this acquires a token first,
+    /// as `ServiceClient::new_with_auth` does.
+    pub async fn build(self) -> Result<ServiceClient, String> {
+        ServiceClient::from_builder(self).await
+    }
+}
+
+/// Error for a request that asks to bypass custom logic on a client that did not opt in.
+pub(crate) fn bypass_not_allowed() -> String {
+    "Bypassing custom plug-ins and flows requires ServiceClientBuilder::allow_bypass_custom_logic"
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::{Credentials, ServiceClientBuilder, bypass_not_allowed};
+    use crate::auth::config::AuthConfig;
+    use crate::auth::credentials::TEST_AUTHORITY;
+    use crate::auth::token::CachedToken;
+    use crate::auth::tokencache::TokenCache;
+    use crate::testsupport::unique_temp_path;
+
+    #[test]
+    fn resolves_url_and_credentials() {
+        let (credentials, url) = ServiceClientBuilder::new()
+            .connection_string(
+                "AuthType=ClientSecret;Url=https://contoso.crm.dynamics.com/;ClientId=client;ClientSecret=secret;TenantId=tenant",
+            )
+            .resolve()
+            .expect("should resolve");
+        assert!(matches!(credentials, Credentials::Auth(_)));
+        assert_eq!(url, "https://contoso.crm.dynamics.com");
+
+        let (credentials, url) = ServiceClientBuilder::new()
+            .static_token("token")
+            .url("https://fabrikam.crm.dynamics.com")
+            .api_version("9.1")
+            .resolve()
+            .expect("should resolve");
+        assert!(matches!(credentials, Credentials::StaticToken(token) if token == "token"));
+        assert_eq!(url, "https://fabrikam.crm.dynamics.com");
+
+        assert!(ServiceClientBuilder::new().resolve().is_err());
+        assert!(
+            ServiceClientBuilder::new()
+                .static_token("token")
+                .resolve()
+                .is_err()
+        );
+        assert!(
+            ServiceClientBuilder::new()
+                .static_token("token")
+                .url("https://fabrikam.crm.dynamics.com")
+                .api_version("latest")
+                .resolve()
+                .is_err()
+        );
+        let error = ServiceClientBuilder::new()
+            .static_token("token")
+            .url("https://fabrikam.crm.dynamics.com/api/data/v9.2")
+            .resolve()
+            .err()
+            .expect("should reject a Web API path");
+        assert!(error.starts_with("Invalid Dataverse URL"), "{error}");
+        let local = ServiceClientBuilder::new()
+            .static_token("token")
+            .url("http://localhost:5555/");
+        assert!(local.resolve().is_err());
+        let (_, url) = local
+            .allow_insecure_url(true)
+            .resolve()
+            .expect("should accept a local server");
+        assert_eq!(url, "http://localhost:5555");
+
+        let auth = AuthConfig::client_credentials_for_environment(
+            "client",
+            "secret",
+            "tenant",
+            "https://contoso.crm.dynamics.com",
+        )
+        .expect("auth");
+        let cache = Arc::new(TokenCache::fixed(
+            Some(auth),
+            PathBuf::new(),
+            CachedToken {
+                access_token: "token".to_string(),
+                refresh_token: None,
+                expires_at: None,
+            },
+        ));
+        let (_, url) = ServiceClientBuilder::new()
+            .token_cache(cache.clone())
+            .url("https://contoso.crm.dynamics.com/")
+            .resolve()
+            .expect("same environment");
+        assert_eq!(url, "https://contoso.crm.dynamics.com");
+        assert_eq!(
+            ServiceClientBuilder::new()
+                .token_cache(cache)
+                .url("https://fabrikam.crm.dynamics.com")
+                .resolve()
+                .err()
+                .expect("should reject another environment"),
+            "The token cache holds tokens for https://contoso.crm.dynamics.com, not https://fabrikam.crm.dynamics.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn acquires_a_token_for_an_insecure_local_url() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.expect("bind");
+        let port = listener.local_addr().expect("addr").port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.expect("accept");
+            let mut request = Vec::new();
+            let mut chunk = [0u8; 1024];
+            // The form is short, so the request is complete once its body has arrived.
+            while !String::from_utf8_lossy(&request).contains("grant_type=") {
+                let read = stream.read(&mut chunk).await.expect("read");
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&chunk[..read]);
+            }
+            let body = r#"{"access_token":"local-token","expires_in":3600}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.ok();
+            stream.shutdown().await.ok();
+            String::from_utf8_lossy(&request).into_owned()
+        });
+
+        let cache_dir = unique_temp_path("insecure_url_token");
+        let mut auth = AuthConfig::client_credentials_for_insecure_environment(
+            "client",
+            "secret",
+            "tenant",
+            &format!("http://localhost:{port}"),
+        )
+        .expect("auth");
+        if let AuthConfig::ClientCredentials {
+            token_cache_store_path,
+            ..
+        } = &mut auth
+        {
+            *token_cache_store_path = Some(cache_dir.to_string_lossy().into_owned());
+        }
+        let client = TEST_AUTHORITY
+            .scope(
+                format!("http://127.0.0.1:{port}"),
+                ServiceClientBuilder::new()
+                    .auth(auth)
+                    .allow_insecure_url(true)
+                    .build(),
+            )
+            .await
+            .expect("should acquire a token for the local URL");
+        assert!(client.token_expires_at().await.is_some());
+
+        let request = server.await.expect("server");
+        assert!(
+            request.starts_with("POST /tenant/oauth2/v2.0/token "),
+            "{request}"
+        );
+        let scope = format!("scope=http%3A%2F%2Flocalhost%3A{port}%2F.default");
+        assert!(request.contains(&scope), "{request}");
+        fs::remove_dir_all(cache_dir).ok();
+    }
+
+    #[test]
+    fn default_headers_replace_by_name_and_are_validated() {
+        let headers = ServiceClientBuilder::new()
+            .default_header("MSCRM.SolutionUniqueName", "first")
+            .default_header("mscrm.solutionuniquename", "tools")
+            .default_header("x-tenant", "contoso")
+            .header_values()
+            .expect("should parse");
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].0.as_str(), "mscrm.solutionuniquename");
+        assert_eq!(headers[0].1, "tools");
+
+        let error = ServiceClientBuilder::new()
+            .default_header("Bad Header", "value")
+            .header_values()
+            .expect_err("should reject the name");
+        assert_eq!(error, "Invalid default header name 'Bad Header'");
+
+        let builder =
+            ServiceClientBuilder::new().default_header("MSCRM.BypassCustomPluginExecution", "true");
+        assert_eq!(builder.header_values(), Err(bypass_not_allowed()));
+        assert!(
+            builder
+                .allow_bypass_custom_logic(true)
+                .header_values()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn shared_http_client_excludes_connection_settings() {
+        let shared = reqwest::Client::new();
+        assert!(
+            ServiceClientBuilder::new()
+                .shared_http_client(shared.clone())
+                .http_client()
+                .is_ok()
+        );
+        assert!(
+            ServiceClientBuilder::new()
+                .http2_only(true)
+                .pool_idle_timeout(Duration::from_secs(30))
+                .pool_max_idle_per_host(8)
+                .http_client()
+                .is_ok()
+        );
+
+        let error = ServiceClientBuilder::new()
+            .shared_http_client(shared)
+            .pool_max_idle_per_host(8)
+            .http_client()
+            .expect_err("pool settings belong to the shared client");
+        assert_eq!(
+            error,
+            "Timeout, HTTP/2, and pool settings cannot be combined with a shared HTTP client"
+        );
+    }
+}
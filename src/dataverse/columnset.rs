@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::dataverse::fetchxml::{escape_xml_attribute, fetch_tag_attr_value};
+
+/// Default columns applied to FetchXML queries whose root entity selects no attributes, and to
+/// OData queries without `$select`.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultColumnSets {
+    /// Registered column lists keyed by lowercase entity logical name.
+    pub by_entity: HashMap<String, Vec<String>>,
+    /// Fall back to the entity's primary id and primary name attributes when no list is registered.
+    pub derive_from_metadata: bool,
+}
+
+impl DefaultColumnSets {
+    /// Register the default columns for an entity, replacing any previous list.
+    pub fn set(&mut self, logical_name: &str, columns: Vec<String>) {
+        self.by_entity
+            .insert(logical_name.to_ascii_lowercase(), columns);
+    }
+
+    /// Remove the default columns for an entity.
+    pub fn remove(&mut self, logical_name: &str) {
+        self.by_entity.remove(&logical_name.to_ascii_lowercase());
+    }
+
+    /// Return the registered default columns for an entity.
+    pub fn get(&self, logical_name: &str) -> Option<&[String]> {
+        self.by_entity
+            .get(&logical_name.to_ascii_lowercase())
+            .map(Vec::as_slice)
+    }
+}
+
+/// Insert `<attribute>` elements into the root `<entity>` when it selects no columns.
+/// Aggregate queries and queries that already select attributes are returned unchanged.
+pub(crate) fn apply_default_attributes(
+    fetchxml: &str,
+    columns: &[String],
+) -> Result<String, String> {
+    let aggregate = fetch_tag_attr_value(fetchxml, "aggregate")?
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"));
+    if columns.is_empty() || aggregate {
+        return Ok(fetchxml.to_string());
+    }
+
+    let entity_start = fetchxml
+        .find("<entity")
+        .ok_or_else(|| "FetchXML must contain an <entity> element".to_string())?;
+    let entity_tag_end = fetchxml[entity_start..]
+        .find('>')
+        .ok_or_else(|| "FetchXML <entity> element is not closed".to_string())?
+        + entity_start;
+
+    let attributes: String = columns
+        .iter()
+        .map(|column| format!("<attribute name=\"{}\" />", escape_xml_attribute(column)))
+        .collect();
+
+    // A self-closing root entity has no children at all, so it is expanded in place.
+    if fetchxml[..entity_tag_end].ends_with('/') {
+        let open_tag = fetchxml[entity_start..entity_tag_end - 1].trim_end();
+        return Ok(format!(
+            "{}{}>{}</entity>{}",
+            &fetchxml[..entity_start],
+            open_tag,
+            attributes,
+            &fetchxml[entity_tag_end + 1..]
+        ));
+    }
+
+    if root_entity_selects_columns(&fetchxml[entity_tag_end + 1..]) {
+        return Ok(fetchxml.to_string());
+    }
+
+    Ok(format!(
+        "{}{}{}",
+        &fetchxml[..=entity_tag_end],
+        attributes,
+        &fetchxml[entity_tag_end + 1..]
+    ))
+}
+
+/// Add `$select` with `columns` to OData query options that select no columns. Queries with
+/// `$select` or `$apply` are returned unchanged; `$select` inside `$expand` does not count.
+pub(crate) fn apply_default_select(query: &str, columns: &[String]) -> String {
+    let query = query.trim_start_matches('?');
+    let selects = query.split('&').any(|option| {
+        let option = option.to_ascii_lowercase();
+        option.starts_with("$select=") || option.starts_with("$apply=")
+    });
+    if columns.is_empty() || selects {
+        return query.to_string();
+    }
+
+    let select = format!("$select={}", columns.join(","));
+    if query.is_empty() {
+        select
+    } else {
+        format!("{query}&{select}")
+    }
+}
+
+/// Scan the body of the root entity, ignoring attributes that belong to linked entities.
+fn root_entity_selects_columns(entity_body: &str) -> bool {
+    let mut link_depth = 0usize;
+    let mut rest = entity_body;
+
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            return false;
+        };
+        let tag = &rest[start..=start + end];
+        rest = &rest[start + end + 1..];
+
+        if tag.starts_with("<link-entity") {
+            if !tag.ends_with("/>") {
+                link_depth += 1;
+            }
+        } else if tag.starts_with("</link-entity") {
+            link_depth = link_depth.saturating_sub(1);
+        } else if tag.starts_with("</entity") {
+            return false;
+        } else if link_depth == 0
+            && (tag.starts_with("<attribute") || tag.starts_with("<all-attributes"))
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DefaultColumnSets, apply_default_attributes, apply_default_select};
+
+    fn columns() -> Vec<String> {
+        vec!["accountid".to_string(), "name".to_string()]
+    }
+
+    #[test]
+    fn inserts_defaults_when_root_entity_has_no_attributes() {
+        let fetchxml = apply_default_attributes(
+            "<fetch><entity name=\"account\"><filter><condition attribute=\"statecode\" operator=\"eq\" value=\"0\" /></filter></entity></fetch>",
+            &columns(),
+        )
+        .expect("should apply");
+
+        assert_eq!(
+            fetchxml,
+            "<fetch><entity name=\"account\"><attribute name=\"accountid\" /><attribute name=\"name\" /><filter><condition attribute=\"statecode\" operator=\"eq\" value=\"0\" /></filter></entity></fetch>"
+        );
+    }
+
+    #[test]
+    fn expands_self_closing_root_entity() {
+        let fetchxml =
+            apply_default_attributes("<fetch><entity name=\"account\" /></fetch>", &columns())
+                .expect("should apply");
+
+        assert_eq!(
+            fetchxml,
+            "<fetch><entity name=\"account\"><attribute name=\"accountid\" /><attribute name=\"name\" /></entity></fetch>"
+        );
+    }
+
+    #[test]
+    fn ignores_linked_entity_attributes_when_checking_root_columns() {
+        let fetchxml = apply_default_attributes(
+            "<fetch><entity name=\"account\"><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\"><attribute name=\"fullname\" /></link-entity></entity></fetch>",
+            &columns(),
+        )
+        .expect("should apply");
+
+        assert!(
+            fetchxml
+                .starts_with("<fetch><entity name=\"account\"><attribute name=\"accountid\" />")
+        );
+    }
+
+    #[test]
+    fn keeps_queries_that_already_select_columns() {
+        let original = "<fetch><entity name=\"account\"><all-attributes /></entity></fetch>";
+
+        assert_eq!(
+            apply_default_attributes(original, &columns()).expect("should apply"),
+            original
+        );
+    }
+
+    #[test]
+    fn skips_aggregate_queries_with_either_quote() {
+        for original in [
+            "<fetch aggregate=\"true\"><entity name=\"account\" /></fetch>",
+            "<fetch aggregate='true'><entity name='account' /></fetch>",
+        ] {
+            assert_eq!(
+                apply_default_attributes(original, &columns()).expect("should apply"),
+                original
+            );
+        }
+    }
+
+    #[test]
+    fn escapes_column_names() {
+        let fetchxml = apply_default_attributes(
+            "<fetch><entity name=\"account\" /></fetch>",
+            &["a\"b".to_string()],
+        )
+        .expect("should apply");
+
+        assert_eq!(
+            fetchxml,
+            "<fetch><entity name=\"account\"><attribute name=\"a&quot;b\" /></entity></fetch>"
+        );
+    }
+
+    #[test]
+    fn adds_select_to_odata_queries_without_one() {
+        assert_eq!(
+            apply_default_select("", &columns()),
+            "$select=accountid,name"
+        );
+        assert_eq!(
+            apply_default_select(
+                "$filter=statecode eq 0&$expand=primarycontactid($select=fullname)",
+                &columns()
+            ),
+            "$filter=statecode eq 0&$expand=primarycontactid($select=fullname)&$select=accountid,name"
+        );
+        assert_eq!(
+            apply_default_select("$select=name&$top=5", &columns()),
+            "$select=name&$top=5"
+        );
+        assert_eq!(
+            apply_default_select("$apply=aggregate($count as total)", &columns()),
+            "$apply=aggregate($count as total)"
+        );
+    }
+
+    #[test]
+    fn column_sets_are_case_insensitive() {
+        let mut sets = DefaultColumnSets::default();
+        sets.set("Account", columns());
+
+        assert_eq!(sets.get("account"), Some(columns().as_slice()));
+        sets.remove("ACCOUNT");
+        assert!(sets.get("account").is_none());
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Result of `ServiceClient::validate_connection`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionHealth {
+    /// Calling user (`systemuser`) ID reported by `WhoAmI`.
+    pub user_id: Uuid,
+    /// Organization ID reported by `WhoAmI`.
+    pub organization_id: Uuid,
+    /// Dataverse version of the environment, such as `9.2.24094.00200`.
+    pub version: String,
+    /// Round trip of the `WhoAmI` request, including acquiring a token when none was cached.
+    pub who_am_i_latency: Duration,
+    /// Round trip of the metadata request.
+    pub metadata_latency: Duration,
+}
+
+impl ConnectionHealth {
+    /// Combined round trip of the checks.
+    pub fn latency(&self) -> Duration {
+        self.who_am_i_latency + self.metadata_latency
+    }
+}
+
+/// Read the version from a `RetrieveVersion` function response.
+pub(crate) fn parse_version(json: &Value) -> Result<String, String> {
+    json.get("Version")
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| "RetrieveVersion response missing Version".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse_version;
+
+    #[test]
+    fn reads_the_environment_version() {
+        assert_eq!(
+            parse_version(&json!({ "Version": "9.2.24094.00200" })).expect("should parse"),
+            "9.2.24094.00200"
+        );
+        assert!(parse_version(&json!({})).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+/// Dataverse error code returned when an aggregate query touches more than 50,000 records.
+const AGGREGATE_RECORD_LIMIT_CODE: &str = "0x8004e023";
+/// Dataverse error name returned alongside `AGGREGATE_RECORD_LIMIT_CODE`.
+const AGGREGATE_RECORD_LIMIT_NAME: &str = "AggregateQueryRecordLimit";
+
+/// Reason a FetchXML count stopped before it could prove every matching record was seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountLimit {
+    /// Dataverse rejected an aggregate query because it exceeded the aggregate record limit.
+    AggregateRecordLimit,
+    /// The query declared `top` (capped at 5000 by Dataverse) and returned exactly that many rows.
+    TopCap,
+    /// Paging stopped at the caller-supplied page ceiling while Dataverse still reported more records.
+    PageCeiling,
+}
+
+/// Result of a FetchXML count, including whether the number is exact or only a lower bound.
+#[derive(Debug, Clone, Default)]
+pub struct CountResult {
+    /// Number of records counted.
+    pub count: usize,
+    /// Number of pages requested from Dataverse while counting.
+    pub pages: usize,
+    /// Limit that stopped counting early, when one was hit.
+    pub limit: Option<CountLimit>,
+}
+
+impl CountResult {
+    /// True when a limit was hit and `count` is only a lower bound of the matching records.
+    pub fn is_lower_bound(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    /// The count when it is exact, or an error naming the limit that was hit, for callers that
+    /// only take a number.
+    pub(crate) fn exact(&self) -> Result<usize, String> {
+        match self.limit {
+            None => Ok(self.count),
+            Some(CountLimit::AggregateRecordLimit) => Err(format!(
+                "Count exceeded the Dataverse aggregate record limit ({AGGREGATE_RECORD_LIMIT_NAME}, {AGGREGATE_RECORD_LIMIT_CODE}) after {} records",
+                self.count
+            )),
+            Some(CountLimit::TopCap) => Err(format!(
+                "Count reached the query's top of {} records, so more records may match",
+                self.count
+            )),
+            Some(CountLimit::PageCeiling) => Err(format!(
+                "Count stopped at the page ceiling after {} records while more records remain",
+                self.count
+            )),
+        }
+    }
+}
+
+/// Determine whether a Dataverse error message reports the aggregate query record limit.
+pub(crate) fn is_aggregate_limit_error(message: &str) -> bool {
+    message.contains(AGGREGATE_RECORD_LIMIT_NAME)
+        || message
+            .to_ascii_lowercase()
+            .contains(AGGREGATE_RECORD_LIMIT_CODE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CountLimit, CountResult, is_aggregate_limit_error};
+
+    #[test]
+    fn lower_bound_follows_limit() {
+        let exact = CountResult {
+            count: 10,
+            pages: 1,
+            limit: None,
+        };
+        let capped = CountResult {
+            count: 5000,
+            pages: 1,
+            limit: Some(CountLimit::TopCap),
+        };
+
+        assert!(!exact.is_lower_bound());
+        assert!(capped.is_lower_bound());
+        assert_eq!(exact.exact(), Ok(10));
+        assert!(capped.exact().is_err());
+
+        let aggregate = CountResult {
+            count: 0,
+            pages: 1,
+            limit: Some(CountLimit::AggregateRecordLimit),
+        };
+        assert!(is_aggregate_limit_error(
+            &aggregate.exact().expect_err("limit")
+        ));
+    }
+
+    #[test]
+    fn detects_aggregate_limit_error_by_name_or_code() {
+        assert!(is_aggregate_limit_error(
+            "Dataverse API error (400 Bad Request): {\"error\":{\"code\":\"0x8004E023\",\"message\":\"AggregateQueryRecordLimit exceeded. Cannot perform this operation.\"}}"
+        ));
+        assert!(is_aggregate_limit_error(
+            "{\"error\":{\"code\":\"0x8004e023\"}}"
+        ));
+        assert!(!is_aggregate_limit_error(
+            "Dataverse API error (401 Unauthorized)"
+        ));
+    }
+}
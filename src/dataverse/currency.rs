@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::dataverse::entity::{Entity, Money, TRANSACTION_CURRENCY_ATTRIBUTE, Value};
+
+/// Currencies per `transactioncurrencies` query.
+const CURRENCY_QUERY_CHUNK: usize = 50;
+
+/// Symbol and precision of a `transactioncurrency` row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CurrencyFormat {
+    /// Currency ID.
+    #[serde(rename = "transactioncurrencyid")]
+    pub currency_id: Uuid,
+    /// ISO 4217 code, such as `EUR`.
+    #[serde(rename = "isocurrencycode", default)]
+    pub iso_code: String,
+    /// Symbol shown before amounts, such as `€`.
+    #[serde(rename = "currencysymbol", default)]
+    pub symbol: String,
+    /// Decimal places shown for amounts in this currency.
+    #[serde(rename = "currencyprecision", default)]
+    pub precision: u32,
+}
+
+impl CurrencyFormat {
+    /// Round `amount` to this currency's precision, for numeric spreadsheet cells.
+    pub fn round(&self, amount: Decimal) -> Decimal {
+        amount.round_dp_with_strategy(self.precision, RoundingStrategy::MidpointAwayFromZero)
+    }
+
+    /// Format `amount` as model-driven apps show it, such as `€1,234.50`: rounded to the
+    /// currency precision, with the symbol and thousands separators.
+    pub fn format(&self, amount: Decimal) -> String {
+        let rounded = self.round(amount);
+        let digits = format!("{:.*}", self.precision as usize, rounded.abs());
+        let (whole, fraction) = match digits.split_once('.') {
+            Some((whole, fraction)) => (whole, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+
+        let mut grouped = String::new();
+        for (index, digit) in whole.chars().enumerate() {
+            if index > 0 && (whole.len() - index) % 3 == 0 {
+                grouped.push(',');
+            }
+            grouped.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            grouped.push('.');
+            grouped.push_str(fraction);
+        }
+
+        let sign = if rounded.is_sign_negative() && !rounded.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        format!("{sign}{}{grouped}", self.symbol)
+    }
+}
+
+/// Formats of the currencies used by a set of rows, from
+/// `ServiceClient::retrieve_currency_formats`.
+#[derive(Debug, Clone, Default)]
+pub struct CurrencyFormats {
+    /// Formats keyed by currency ID.
+    pub by_id: HashMap<Uuid, CurrencyFormat>,
+}
+
+impl CurrencyFormats {
+    /// Format of the currency `money` is in, falling back to the row's `transactioncurrencyid`.
+    pub fn format_of(&self, money: &Money, row: Option<&Entity>) -> Option<&CurrencyFormat> {
+        let currency_id = money
+            .currency
+            .as_ref()
+            .map(|currency| currency.id)
+            .or_else(|| row.and_then(row_currency_id))?;
+        self.by_id.get(&currency_id)
+    }
+
+    /// Format a money value with its currency's symbol and precision, or as the plain amount
+    /// when the currency is unknown.
+    pub fn format(&self, money: &Money, row: Option<&Entity>) -> String {
+        match self.format_of(money, row) {
+            Some(format) => format.format(money.value),
+            None => money.value.to_string(),
+        }
+    }
+
+    /// The money column `column` of `row`, formatted with its currency. `None` when the column
+    /// is missing or not a money value.
+    pub fn format_attribute(&self, row: &Entity, column: &str) -> Option<String> {
+        match row.attributes.get(column)? {
+            Value::Money(money) => Some(self.format(money, Some(row))),
+            _ => None,
+        }
+    }
+}
+
+fn row_currency_id(row: &Entity) -> Option<Uuid> {
+    match row.attributes.get(TRANSACTION_CURRENCY_ATTRIBUTE)? {
+        Value::EntityReference(reference) => Some(reference.id),
+        _ => None,
+    }
+}
+
+/// Distinct currency IDs of the money values and `transactioncurrencyid` lookups in `rows`.
+pub(crate) fn currency_ids(rows: &[Entity]) -> Vec<Uuid> {
+    let mut seen = HashSet::new();
+    rows.iter()
+        .flat_map(|row| {
+            row.attributes
+                .values()
+                .filter_map(|value| match value {
+                    Value::Money(money) => money.currency.as_ref().map(|currency| currency.id),
+                    _ => None,
+                })
+                .chain(row_currency_id(row))
+        })
+        .filter(|id| seen.insert(*id))
+        .collect()
+}
+
+/// `transactioncurrencies` queries that together return the currencies in `currency_ids`.
+pub(crate) fn currency_queries(currency_ids: &[Uuid]) -> Vec<String> {
+    currency_ids
+        .chunks(CURRENCY_QUERY_CHUNK)
+        .map(|chunk| {
+            let filter = chunk
+                .iter()
+                .map(|id| format!("transactioncurrencyid eq {id}"))
+                .collect::<Vec<_>>()
+                .join(" or ");
+            format!(
+                "transactioncurrencies?$select=transactioncurrencyid,isocurrencycode,currencysymbol,currencyprecision&$filter={filter}"
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    use super::{CurrencyFormat, CurrencyFormats, currency_ids, currency_queries};
+    use crate::dataverse::entity::{Entity, EntityReference, Money, Value};
+
+    fn euro(precision: u32) -> CurrencyFormat {
+        CurrencyFormat {
+            currency_id: Uuid::new_v4(),
+            iso_code: "EUR".to_string(),
+            symbol: "€".to_string(),
+            precision,
+        }
+    }
+
+    #[test]
+    fn formats_with_symbol_precision_and_grouping() {
+        let format = euro(2);
+
+        assert_eq!(format.format(Decimal::new(1234567891, 3)), "€1,234,567.89");
+        assert_eq!(format.format(Decimal::new(-1005, 1)), "-€100.50");
+        assert_eq!(format.format(Decimal::new(-1, 3)), "€0.00");
+        assert_eq!(euro(0).format(Decimal::new(9995, 1)), "€1,000");
+        assert_eq!(format.round(Decimal::new(12345, 3)), Decimal::new(1235, 2));
+    }
+
+    #[test]
+    fn formats_money_with_row_currency() {
+        let format = euro(2);
+        let formats = CurrencyFormats {
+            by_id: [(format.currency_id, format.clone())].into_iter().collect(),
+        };
+        let mut row = Entity::new(Uuid::new_v4(), "opportunity", None);
+        row.attributes.insert(
+            "transactioncurrencyid".to_string(),
+            Value::EntityReference(EntityReference {
+                id: format.currency_id,
+                logical_name: "transactioncurrency".to_string(),
+                name: None,
+            }),
+        );
+        row.attributes.insert(
+            "estimatedvalue".to_string(),
+            Value::Money(Money::new(Decimal::new(25000, 0))),
+        );
+        row.attributes.insert(
+            "budgetamount".to_string(),
+            Value::Money(Money::with_currency(Decimal::new(5, 1), Uuid::new_v4())),
+        );
+
+        assert_eq!(
+            formats.format_attribute(&row, "estimatedvalue").as_deref(),
+            Some("€25,000.00")
+        );
+        assert_eq!(
+            formats.format_attribute(&row, "budgetamount").as_deref(),
+            Some("0.5")
+        );
+        assert_eq!(formats.format_attribute(&row, "name"), None);
+        assert_eq!(currency_ids(&[row.clone(), row]).len(), 2);
+    }
+
+    #[test]
+    fn currency_queries_filter_by_id() {
+        let id = Uuid::new_v4();
+
+        assert_eq!(
+            currency_queries(&[id]),
+            vec![format!(
+                "transactioncurrencies?$select=transactioncurrencyid,isocurrencycode,currencysymbol,currencyprecision&$filter=transactioncurrencyid eq {id}"
+            )]
+        );
+    }
+}
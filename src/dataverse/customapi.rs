@@ -0,0 +1,364 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::url::{encode_query_value, encode_string_literal};
+
+/// Data type of a Custom API request parameter or response property, from the `type` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CustomApiParameterType {
+    Boolean,
+    DateTime,
+    Decimal,
+    Entity,
+    EntityCollection,
+    EntityReference,
+    Float,
+    Integer,
+    Money,
+    Picklist,
+    String,
+    StringArray,
+    Guid,
+}
+
+impl CustomApiParameterType {
+    /// Map the `type` choice value to a parameter type.
+    pub fn from_code(code: i64) -> Option<Self> {
+        Some(match code {
+            0 => Self::Boolean,
+            1 => Self::DateTime,
+            2 => Self::Decimal,
+            3 => Self::Entity,
+            4 => Self::EntityCollection,
+            5 => Self::EntityReference,
+            6 => Self::Float,
+            7 => Self::Integer,
+            8 => Self::Money,
+            9 => Self::Picklist,
+            10 => Self::String,
+            11 => Self::StringArray,
+            12 => Self::Guid,
+            _ => return None,
+        })
+    }
+
+    /// Check that a JSON value has the shape the Web API expects for this type.
+    fn accepts(self, value: &Value) -> bool {
+        match self {
+            Self::Boolean => value.is_boolean(),
+            Self::DateTime => value.is_string(),
+            Self::Decimal | Self::Float | Self::Money => value.is_number(),
+            Self::Integer | Self::Picklist => value.is_i64(),
+            Self::String => value.is_string(),
+            Self::StringArray => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(Value::is_string)),
+            Self::Guid => value
+                .as_str()
+                .is_some_and(|text| Uuid::parse_str(text).is_ok()),
+            Self::Entity | Self::EntityReference => value.is_object(),
+            Self::EntityCollection => value
+                .as_array()
+                .is_some_and(|items| items.iter().all(Value::is_object)),
+        }
+    }
+}
+
+/// Request parameter or response property of a Custom API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomApiParameter {
+    /// Parameter name used in the request body or function URL.
+    pub unique_name: String,
+    /// Parameter data type.
+    pub parameter_type: CustomApiParameterType,
+    /// True when the caller may leave the parameter out. Always false for response properties.
+    pub is_optional: bool,
+    /// Table of `Entity`, `EntityCollection`, and `EntityReference` parameters, when fixed.
+    pub logical_entity_name: Option<String>,
+}
+
+/// Custom API definition read from the `customapi` table and its parameter tables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomApiDefinition {
+    /// Message name the API is invoked with.
+    pub unique_name: String,
+    /// True for functions, which are called with `GET`; actions are called with `POST`.
+    pub is_function: bool,
+    /// `bindingtype` choice value: 0 global, 1 table, 2 table collection.
+    pub binding_type: i64,
+    /// Table the API is bound to, for bound APIs.
+    pub bound_entity_logical_name: Option<String>,
+    /// Request parameters.
+    pub request_parameters: Vec<CustomApiParameter>,
+    /// Response properties.
+    pub response_properties: Vec<CustomApiParameter>,
+}
+
+/// Read a Custom API definition from a `customapis` query that expands its parameters.
+pub(crate) fn parse_custom_api_definition(
+    json: &Value,
+    unique_name: &str,
+) -> Result<CustomApiDefinition, String> {
+    let item = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?
+        .first()
+        .ok_or_else(|| format!("Custom API '{unique_name}' was not found"))?;
+
+    Ok(CustomApiDefinition {
+        unique_name: item
+            .get("uniquename")
+            .and_then(|value| value.as_str())
+            .unwrap_or(unique_name)
+            .to_string(),
+        is_function: item
+            .get("isfunction")
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false),
+        binding_type: item
+            .get("bindingtype")
+            .and_then(|value| value.as_i64())
+            .unwrap_or(0),
+        bound_entity_logical_name: item
+            .get("boundentitylogicalname")
+            .and_then(|value| value.as_str())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string),
+        request_parameters: parse_parameters(item.get("CustomAPIRequestParameters"))?,
+        response_properties: parse_parameters(item.get("CustomAPIResponseProperties"))?,
+    })
+}
+
+fn parse_parameters(json: Option<&Value>) -> Result<Vec<CustomApiParameter>, String> {
+    json.and_then(|value| value.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .map(|item| {
+            let unique_name = item
+                .get("uniquename")
+                .and_then(|value| value.as_str())
+                .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+            let code = item
+                .get("type")
+                .and_then(|value| value.as_i64())
+                .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+            Ok(CustomApiParameter {
+                unique_name: unique_name.to_string(),
+                parameter_type: CustomApiParameterType::from_code(code).ok_or_else(|| {
+                    format!("Unknown type {code} for Custom API parameter '{unique_name}'")
+                })?,
+                is_optional: item
+                    .get("isoptional")
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or(false),
+                logical_entity_name: item
+                    .get("logicalentityname")
+                    .and_then(|value| value.as_str())
+                    .filter(|value| !value.is_empty())
+                    .map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// Check request parameters against the definition: every name must exist, required parameters
+/// must be present, and values must have the declared type.
+pub(crate) fn validate_custom_api_parameters(
+    definition: &CustomApiDefinition,
+    parameters: &HashMap<String, Value>,
+) -> Result<(), String> {
+    for (name, value) in parameters {
+        let parameter = definition
+            .request_parameters
+            .iter()
+            .find(|parameter| parameter.unique_name == *name)
+            .ok_or_else(|| {
+                let valid = definition
+                    .request_parameters
+                    .iter()
+                    .map(|parameter| parameter.unique_name.as_str())
+                    .collect::<Vec<_>>();
+                format!(
+                    "Unknown parameter '{name}' for Custom API '{}'. Valid parameters: {}",
+                    definition.unique_name,
+                    if valid.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        valid.join(", ")
+                    }
+                )
+            })?;
+
+        if !value.is_null() && !parameter.parameter_type.accepts(value) {
+            return Err(format!(
+                "Parameter '{name}' for Custom API '{}' must be {:?}, got {value}",
+                definition.unique_name, parameter.parameter_type
+            ));
+        }
+    }
+
+    let missing = definition
+        .request_parameters
+        .iter()
+        .filter(|parameter| {
+            !parameter.is_optional
+                && parameters
+                    .get(&parameter.unique_name)
+                    .is_none_or(Value::is_null)
+        })
+        .map(|parameter| parameter.unique_name.as_str())
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(format!(
+            "Missing required parameters for Custom API '{}': {}",
+            definition.unique_name,
+            missing.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Build the path of an unbound function call, passing each parameter through an alias such as
+/// `name(Value=@p0)?@p0=...`.
+pub(crate) fn custom_api_function_path(
+    unique_name: &str,
+    parameters: &HashMap<String, Value>,
+) -> String {
+    let mut names = parameters.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let arguments = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| format!("{name}=@p{index}"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let aliases = names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let value = match &parameters[*name] {
+                Value::String(text) if Uuid::parse_str(text).is_ok() => text.clone(),
+                Value::String(text) => encode_string_literal(text),
+                other => encode_query_value(&other.to_string()),
+            };
+            format!("@p{index}={value}")
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+
+    if aliases.is_empty() {
+        format!("{unique_name}()")
+    } else {
+        format!("{unique_name}({arguments})?{aliases}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{
+        CustomApiParameterType, custom_api_function_path, parse_custom_api_definition,
+        validate_custom_api_parameters,
+    };
+
+    fn definition() -> super::CustomApiDefinition {
+        parse_custom_api_definition(
+            &json!({
+                "value": [{
+                    "uniquename": "sample_Score",
+                    "isfunction": false,
+                    "bindingtype": 0,
+                    "boundentitylogicalname": null,
+                    "CustomAPIRequestParameters": [
+                        { "uniquename": "Text", "type": 10, "isoptional": false },
+                        { "uniquename": "Weight", "type": 7, "isoptional": true },
+                        { "uniquename": "Target", "type": 5, "isoptional": true, "logicalentityname": "account" }
+                    ],
+                    "CustomAPIResponseProperties": [
+                        { "uniquename": "Score", "type": 2 }
+                    ]
+                }]
+            }),
+            "sample_Score",
+        )
+        .expect("should parse")
+    }
+
+    #[test]
+    fn parses_custom_api_definition() {
+        let definition = definition();
+
+        assert!(!definition.is_function);
+        assert_eq!(definition.request_parameters.len(), 3);
+        assert_eq!(
+            definition.request_parameters[2].logical_entity_name.as_deref(),
+            Some("account")
+        );
+        assert_eq!(
+            definition.response_properties[0].parameter_type,
+            CustomApiParameterType::Decimal
+        );
+        assert!(parse_custom_api_definition(&json!({ "value": [] }), "missing").is_err());
+    }
+
+    #[test]
+    fn validates_parameter_names_types_and_required_parameters() {
+        let definition = definition();
+
+        let valid = HashMap::from([
+            ("Text".to_string(), json!("hello")),
+            ("Weight".to_string(), json!(3)),
+        ]);
+        assert!(validate_custom_api_parameters(&definition, &valid).is_ok());
+
+        let unknown = HashMap::from([
+            ("Text".to_string(), json!("hello")),
+            ("Wieght".to_string(), json!(3)),
+        ]);
+        assert_eq!(
+            validate_custom_api_parameters(&definition, &unknown).unwrap_err(),
+            "Unknown parameter 'Wieght' for Custom API 'sample_Score'. Valid parameters: Text, Weight, Target"
+        );
+
+        let wrong_type = HashMap::from([
+            ("Text".to_string(), json!("hello")),
+            ("Weight".to_string(), json!("3")),
+        ]);
+        assert!(
+            validate_custom_api_parameters(&definition, &wrong_type)
+                .unwrap_err()
+                .contains("must be Integer")
+        );
+
+        assert_eq!(
+            validate_custom_api_parameters(&definition, &HashMap::new()).unwrap_err(),
+            "Missing required parameters for Custom API 'sample_Score': Text"
+        );
+    }
+
+    #[test]
+    fn builds_function_path_with_parameter_aliases() {
+        let parameters = HashMap::from([
+            ("Name".to_string(), json!("O'Brien")),
+            ("Count".to_string(), json!(2)),
+        ]);
+
+        assert_eq!(
+            custom_api_function_path("sample_Lookup", &parameters),
+            "sample_Lookup(Count=@p0,Name=@p1)?@p0=2&@p1='O%27%27Brien'"
+        );
+        assert_eq!(
+            custom_api_function_path("sample_Ping", &HashMap::new()),
+            "sample_Ping()"
+        );
+    }
+}
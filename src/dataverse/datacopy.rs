@@ -0,0 +1,1006 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::dataverse::progress::{ProgressCallback, ProgressTracker};
+use crate::dataverse::alternatekey::KeyAttributes;
+use crate::dataverse::batch::{
+    ExecuteMultipleRequest, ExecuteMultipleSettings, OrganizationRequest, UpsertRequest,
+};
+use crate::dataverse::entity::{Entity, EntityReference, Value};
+use crate::dataverse::entityattribute::EntityAttribute;
+use crate::dataverse::fetchxml::escape_xml_attribute;
+use crate::dataverse::serviceclient::ServiceClient;
+
+const MAX_BATCH_SIZE: usize = 1000;
+pub(crate) const LOOKUP_QUERY_CHUNK: usize = 100;
+const MODIFIED_ON_ATTRIBUTE: &str = "modifiedon";
+/// Ends source paging once `continue_on_error` is off and a row has failed. `copy_records`
+/// returns the report instead of this error.
+const STOPPED: &str = "Data copy stopped at the first failure";
+
+/// How copied rows are matched to existing rows in the target environment.
+#[derive(Debug, Clone, Default)]
+pub enum UpsertKey {
+    /// Upsert by primary ID, keeping the source row IDs in the target.
+    #[default]
+    PrimaryId,
+    /// Upsert by an alternate key defined on the target table, using these attribute values
+    /// from each row as written, after lookups are remapped.
+    AlternateKey(Vec<String>),
+}
+
+/// Remap lookups to a table by matching key attribute values instead of row IDs.
+#[derive(Debug, Clone)]
+pub struct LookupRemap {
+    /// Logical name of the referenced table, such as `account`.
+    pub logical_name: String,
+    /// Attributes on the referenced table that identify the same row in both environments.
+    pub key_attributes: Vec<String>,
+}
+
+/// What to write when a source row matches a row that already exists in the target.
+#[derive(Debug, Clone)]
+pub enum ConflictResolution {
+    /// Overwrite the target row with the source row.
+    WriteSource,
+    /// Keep the target row unchanged and skip the source row.
+    KeepTarget,
+    /// Write these attributes instead of the source row's, for example a field-by-field merge.
+    WriteMerged(Box<Entity>),
+}
+
+/// Caller-supplied conflict handling for `ConflictStrategy::Custom`.
+pub trait ConflictResolver: Send + Sync {
+    /// Decide what to write when `source` matches the existing `target` row. `target` carries
+    /// every column of the target row.
+    fn resolve(&self, source: &Entity, target: &Entity) -> ConflictResolution;
+}
+
+/// How `copy_records` handles source rows that already exist in the target.
+#[derive(Clone, Default)]
+pub enum ConflictStrategy {
+    /// Upsert every row, overwriting target rows. Target rows are not read first, so conflicts
+    /// are not reported.
+    #[default]
+    SourceWins,
+    /// Skip source rows that already exist in the target.
+    TargetWins,
+    /// Write a source row only when its `modifiedon` is later than the target row's. The
+    /// FetchXML must select `modifiedon`; a matched row without it on either side is reported as
+    /// a failure and not written.
+    NewestWins,
+    /// Let a resolver decide for each existing row.
+    Custom(Arc<dyn ConflictResolver>),
+}
+
+impl fmt::Debug for ConflictStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SourceWins => f.write_str("SourceWins"),
+            Self::TargetWins => f.write_str("TargetWins"),
+            Self::NewestWins => f.write_str("NewestWins"),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+/// A source row that matched an existing target row, and how the conflict was resolved.
+#[derive(Debug, Clone)]
+pub struct DataCopyConflict {
+    /// Source row ID.
+    pub source_id: Uuid,
+    /// ID of the matching target row.
+    pub target_id: Uuid,
+    /// `WriteSource` and `WriteMerged` rows are upserted; `KeepTarget` rows are skipped.
+    pub resolution: ConflictResolution,
+}
+
+/// Options for `copy_records`.
+#[derive(Debug, Clone)]
+pub struct DataCopyOptions {
+    /// How rows are matched in the target environment.
+    pub upsert_key: UpsertKey,
+    /// Lookup targets whose IDs differ between environments.
+    pub lookup_remaps: Vec<LookupRemap>,
+    /// Upserts per `ExecuteMultiple` batch, capped at 1000.
+    pub batch_size: usize,
+    /// FetchXML page size used when reading from the source.
+    pub page_size: Option<i32>,
+    /// Keep copying after a failed row instead of stopping. A stopped copy still returns its
+    /// report, with `DataCopyReport::stopped` set.
+    pub continue_on_error: bool,
+    /// How rows that already exist in the target are handled.
+    pub conflict_strategy: ConflictStrategy,
+    /// Called after each source page is written, with the pages and rows read so far.
+    pub progress: Option<ProgressCallback>,
+}
+
+impl Default for DataCopyOptions {
+    fn default() -> Self {
+        Self {
+            upsert_key: UpsertKey::default(),
+            lookup_remaps: Vec::new(),
+            batch_size: 100,
+            page_size: None,
+            continue_on_error: true,
+            conflict_strategy: ConflictStrategy::default(),
+            progress: None,
+        }
+    }
+}
+
+/// A source row that could not be written to the target.
+#[derive(Debug, Clone)]
+pub struct DataCopyFailure {
+    /// Source row ID.
+    pub source_id: Uuid,
+    /// Failure message from Dataverse or from key resolution.
+    pub message: String,
+}
+
+/// A lookup that was dropped because no matching target row was found.
+#[derive(Debug, Clone)]
+pub struct UnresolvedLookup {
+    /// Source row ID.
+    pub source_id: Uuid,
+    /// Lookup attribute logical name.
+    pub attribute: String,
+    /// Referenced row in the source environment.
+    pub reference: EntityReference,
+}
+
+/// Summary of a `copy_records` run.
+#[derive(Debug, Clone, Default)]
+pub struct DataCopyReport {
+    /// Rows read from the source.
+    pub read: usize,
+    /// Rows upserted into the target.
+    pub written: usize,
+    /// Rows that failed to write.
+    pub failures: Vec<DataCopyFailure>,
+    /// Lookups omitted from the written rows because they could not be remapped.
+    pub unresolved_lookups: Vec<UnresolvedLookup>,
+    /// Source rows that matched an existing target row, when the conflict strategy reads target
+    /// rows.
+    pub conflicts: Vec<DataCopyConflict>,
+    /// Whether the copy stopped at the first failure because `continue_on_error` is off. Rows
+    /// after it were not written.
+    pub stopped: bool,
+}
+
+/// Copy rows returned by `fetchxml` from `source` into `target` using batched upserts.
+///
+/// Source pages are streamed, so memory use is bounded by the page and batch size. Only
+/// attributes that exist on the target table and are valid for create or update are written.
+/// Lookups to tables listed in `lookup_remaps` are rewritten to the target row with matching key
+/// values; lookups that cannot be resolved are left out of the written row and reported.
+pub async fn copy_records(
+    source: &ServiceClient,
+    target: &ServiceClient,
+    entity: &str,
+    fetchxml: &str,
+    options: &DataCopyOptions,
+) -> Result<DataCopyReport, String> {
+    let definition = target.resolve_entity_definition(entity).await?;
+    let primary_id = definition
+        .primary_id_attribute
+        .clone()
+        .unwrap_or_else(|| format!("{}id", definition.logical_name));
+    let writable = writable_columns(
+        target
+            .list_entity_attributes(&definition.logical_name)
+            .await?,
+    );
+
+    let batch_size = options.batch_size.clamp(1, MAX_BATCH_SIZE);
+    let mut report = DataCopyReport::default();
+    let mut remapped_ids: HashMap<(String, Uuid), Option<Uuid>> = HashMap::new();
+    let mut progress = ProgressTracker::new(options.progress.as_ref(), None);
+
+    let copied = source
+        .retrieve_multiple_fetchxml_for_each_page(
+            entity,
+            fetchxml,
+            options.page_size,
+            async |_, page| {
+                report.read += page.len();
+
+                for remap in &options.lookup_remaps {
+                    let pending = pending_lookup_ids(&page, &remap.logical_name, &remapped_ids);
+                    if pending.is_empty() {
+                        continue;
+                    }
+                    let resolved = resolve_remapped_ids(source, target, remap, &pending).await?;
+                    for id in pending {
+                        remapped_ids.insert(
+                            (remap.logical_name.to_ascii_lowercase(), id),
+                            resolved.get(&id).copied(),
+                        );
+                    }
+                }
+
+                let rows = page
+                    .iter()
+                    .map(|row| {
+                        let written = build_target_row(
+                            row,
+                            &definition.logical_name,
+                            &primary_id,
+                            &writable,
+                            options,
+                            &remapped_ids,
+                            &mut report.unresolved_lookups,
+                        );
+                        (row, written)
+                    })
+                    .collect::<Vec<_>>();
+
+                let existing = match options.conflict_strategy {
+                    ConflictStrategy::SourceWins => HashMap::new(),
+                    _ => {
+                        existing_target_rows(
+                            target,
+                            &definition.logical_name,
+                            &definition.entity_set_name,
+                            &primary_id,
+                            &rows,
+                            options,
+                        )
+                        .await?
+                    }
+                };
+
+                let mut requests = Vec::with_capacity(rows.len());
+                for (row, mut written) in rows {
+                    if let Some(existing) = existing.get(&row.id) {
+                        let resolution =
+                            match resolve_conflict(&options.conflict_strategy, row, existing) {
+                                Ok(resolution) => resolution,
+                                Err(message) => {
+                                    report.failures.push(DataCopyFailure {
+                                        source_id: row.id,
+                                        message,
+                                    });
+                                    if !options.continue_on_error {
+                                        report.stopped = true;
+                                        return Err(STOPPED.to_string());
+                                    }
+                                    continue;
+                                }
+                            };
+                        report.conflicts.push(DataCopyConflict {
+                            source_id: row.id,
+                            target_id: existing.id,
+                            resolution: resolution.clone(),
+                        });
+                        match resolution {
+                            ConflictResolution::WriteSource => {}
+                            ConflictResolution::KeepTarget => continue,
+                            ConflictResolution::WriteMerged(merged) => {
+                                written.attributes = merged
+                                    .attributes
+                                    .into_iter()
+                                    .filter(|(attribute, _)| {
+                                        let normalized = attribute.to_ascii_lowercase();
+                                        writable.contains(&normalized) && normalized != primary_id
+                                    })
+                                    .collect();
+                            }
+                        }
+                    }
+                    match build_upsert(written, &options.upsert_key) {
+                        Ok(request) => requests.push((row.id, request)),
+                        Err(message) => {
+                            report.failures.push(DataCopyFailure {
+                                source_id: row.id,
+                                message,
+                            });
+                            if !options.continue_on_error {
+                                report.stopped = true;
+                                return Err(STOPPED.to_string());
+                            }
+                        }
+                    }
+                }
+
+                for chunk in requests.chunks(batch_size) {
+                    let batch = ExecuteMultipleRequest {
+                        settings: ExecuteMultipleSettings {
+                            continue_on_error: options.continue_on_error,
+                            return_responses: true,
+                        },
+                        requests: chunk
+                            .iter()
+                            .map(|(_, request)| OrganizationRequest::Upsert(request.clone()))
+                            .collect(),
+                    };
+                    let response = target.execute_multiple(&batch).await?;
+                    let failures_before = report.failures.len();
+                    for item in response.responses {
+                        match item.fault {
+                            Some(fault) => report.failures.push(DataCopyFailure {
+                                source_id: chunk[item.request_index].0,
+                                message: fault.message,
+                            }),
+                            None => report.written += 1,
+                        }
+                    }
+
+                    if !options.continue_on_error && report.failures.len() > failures_before {
+                        report.stopped = true;
+                        return Err(STOPPED.to_string());
+                    }
+                }
+
+                progress.page_done(page.len());
+                Ok(())
+            },
+        )
+        .await;
+
+    if let Err(error) = copied
+        && !report.stopped
+    {
+        return Err(error);
+    }
+    Ok(report)
+}
+
+/// Read the target rows that `rows` would overwrite, keyed by source row ID. Each source row is
+/// paired with the row written for it, whose remapped values alternate keys are matched on.
+/// Custom resolvers get every column; the built-in strategies only need `modifiedon`.
+async fn existing_target_rows(
+    target: &ServiceClient,
+    logical_name: &str,
+    entity_set_name: &str,
+    primary_id: &str,
+    rows: &[(&Entity, Entity)],
+    options: &DataCopyOptions,
+) -> Result<HashMap<Uuid, Entity>, String> {
+    let all_attributes = matches!(options.conflict_strategy, ConflictStrategy::Custom(_));
+    let mut existing = HashMap::new();
+
+    match &options.upsert_key {
+        UpsertKey::PrimaryId => {
+            let ids = rows.iter().map(|(row, _)| row.id).collect::<Vec<_>>();
+            for chunk in ids.chunks(LOOKUP_QUERY_CHUNK) {
+                let fetchxml = build_existing_rows_fetchxml(
+                    logical_name,
+                    &build_id_lookup_fetchxml(
+                        logical_name,
+                        primary_id,
+                        &[MODIFIED_ON_ATTRIBUTE.to_string()],
+                        chunk,
+                    ),
+                    all_attributes,
+                );
+                for row in target
+                    .retrieve_multiple_fetchxml_paging(entity_set_name, &fetchxml)
+                    .await?
+                {
+                    existing.insert(row.id, row);
+                }
+            }
+        }
+        UpsertKey::AlternateKey(key_attributes) => {
+            let mut source_ids: HashMap<Vec<String>, Vec<Uuid>> = HashMap::new();
+            for (row, written) in rows {
+                if let Some(key) = key_values(written, key_attributes) {
+                    source_ids.entry(key).or_default().push(row.id);
+                }
+            }
+            let keys = source_ids.keys().cloned().collect::<Vec<_>>();
+            let mut columns = key_attributes.clone();
+            columns.push(MODIFIED_ON_ATTRIBUTE.to_string());
+            for chunk in keys.chunks(LOOKUP_QUERY_CHUNK) {
+                let fetchxml = build_existing_rows_fetchxml(
+                    logical_name,
+                    &build_key_lookup_fetchxml(logical_name, &columns, chunk),
+                    all_attributes,
+                );
+                for row in target
+                    .retrieve_multiple_fetchxml_paging(entity_set_name, &fetchxml)
+                    .await?
+                {
+                    let Some(ids) =
+                        key_values(&row, key_attributes).and_then(|key| source_ids.get(&key))
+                    else {
+                        continue;
+                    };
+                    for id in ids {
+                        existing.insert(*id, row.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(existing)
+}
+
+/// Lowercase names of the columns an upsert may write: those valid for create, as the upsert may
+/// create the row, or for update, as it may update an existing one.
+fn writable_columns(attributes: Vec<EntityAttribute>) -> HashSet<String> {
+    attributes
+        .into_iter()
+        .filter(|attribute| {
+            attribute.is_valid_for_create != Some(false)
+                || attribute.is_valid_for_update != Some(false)
+        })
+        .map(|attribute| attribute.logical_name.to_ascii_lowercase())
+        .collect()
+}
+
+/// Swap the selected columns of a lookup query for `<all-attributes />` when a resolver needs
+/// the whole target row.
+fn build_existing_rows_fetchxml(logical_name: &str, fetchxml: &str, all_attributes: bool) -> String {
+    if !all_attributes {
+        return fetchxml.to_string();
+    }
+    let root = format!("<fetch><entity name=\"{logical_name}\">");
+    let filter_start = fetchxml.find("<filter").unwrap_or(fetchxml.len());
+    format!("{root}<all-attributes />{}", &fetchxml[filter_start..])
+}
+
+/// Decide what to write for a source row that matches `target`. `NewestWins` fails when either
+/// row has no `modifiedon`, since the rows cannot be compared.
+fn resolve_conflict(
+    strategy: &ConflictStrategy,
+    source: &Entity,
+    target: &Entity,
+) -> Result<ConflictResolution, String> {
+    Ok(match strategy {
+        ConflictStrategy::SourceWins => ConflictResolution::WriteSource,
+        ConflictStrategy::TargetWins => ConflictResolution::KeepTarget,
+        ConflictStrategy::NewestWins => {
+            let modified_on = |row: &Entity, side: &str| match row
+                .attributes
+                .get(MODIFIED_ON_ATTRIBUTE)
+            {
+                Some(Value::DateTime(value)) => Ok(*value),
+                _ => Err(format!(
+                    "Cannot pick the newest row: the {side} row {} has no {MODIFIED_ON_ATTRIBUTE}",
+                    row.id
+                )),
+            };
+            if modified_on(source, "source")? > modified_on(target, "target")? {
+                ConflictResolution::WriteSource
+            } else {
+                ConflictResolution::KeepTarget
+            }
+        }
+        ConflictStrategy::Custom(resolver) => resolver.resolve(source, target),
+    })
+}
+
+/// Collect lookup IDs to `logical_name` in `page` that have not been resolved yet.
+fn pending_lookup_ids(
+    page: &[Entity],
+    logical_name: &str,
+    resolved: &HashMap<(String, Uuid), Option<Uuid>>,
+) -> Vec<Uuid> {
+    let logical_name = logical_name.to_ascii_lowercase();
+    let mut ids = Vec::new();
+
+    for value in page.iter().flat_map(|row| row.attributes.values()) {
+        if let Value::EntityReference(reference) = value
+            && reference.logical_name.eq_ignore_ascii_case(&logical_name)
+            && !resolved.contains_key(&(logical_name.clone(), reference.id))
+            && !ids.contains(&reference.id)
+        {
+            ids.push(reference.id);
+        }
+    }
+
+    ids
+}
+
+/// Map source row IDs of `remap.logical_name` to target row IDs with the same key values.
+async fn resolve_remapped_ids(
+    source: &ServiceClient,
+    target: &ServiceClient,
+    remap: &LookupRemap,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, Uuid>, String> {
+    let source_definition = source
+        .resolve_entity_definition(&remap.logical_name)
+        .await?;
+    let target_definition = target
+        .resolve_entity_definition(&remap.logical_name)
+        .await?;
+    let source_primary_id = source_definition
+        .primary_id_attribute
+        .clone()
+        .unwrap_or_else(|| format!("{}id", source_definition.logical_name));
+
+    let mut source_keys: HashMap<Vec<String>, Vec<Uuid>> = HashMap::new();
+    for chunk in ids.chunks(LOOKUP_QUERY_CHUNK) {
+        let fetchxml = build_id_lookup_fetchxml(
+            &source_definition.logical_name,
+            &source_primary_id,
+            &remap.key_attributes,
+            chunk,
+        );
+        for row in source
+            .retrieve_multiple_fetchxml_paging(&source_definition.entity_set_name, &fetchxml)
+            .await?
+        {
+            if let Some(key) = key_values(&row, &remap.key_attributes) {
+                source_keys.entry(key).or_default().push(row.id);
+            }
+        }
+    }
+
+    let mut resolved = HashMap::new();
+    let keys = source_keys.keys().cloned().collect::<Vec<_>>();
+    for chunk in keys.chunks(LOOKUP_QUERY_CHUNK) {
+        let fetchxml = build_key_lookup_fetchxml(
+            &target_definition.logical_name,
+            &remap.key_attributes,
+            chunk,
+        );
+        for row in target
+            .retrieve_multiple_fetchxml_paging(&target_definition.entity_set_name, &fetchxml)
+            .await?
+        {
+            if let Some(source_ids) =
+                key_values(&row, &remap.key_attributes).and_then(|key| source_keys.get(&key))
+            {
+                for source_id in source_ids {
+                    resolved.insert(*source_id, row.id);
+                }
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Build the row written to the target: writable attributes only, with remapped lookups.
+fn build_target_row(
+    row: &Entity,
+    logical_name: &str,
+    primary_id: &str,
+    writable: &HashSet<String>,
+    options: &DataCopyOptions,
+    remapped_ids: &HashMap<(String, Uuid), Option<Uuid>>,
+    unresolved: &mut Vec<UnresolvedLookup>,
+) -> Entity {
+    let keyed_by_id = matches!(options.upsert_key, UpsertKey::PrimaryId);
+    let mut written = Entity::new(
+        if keyed_by_id { row.id } else { Uuid::nil() },
+        logical_name,
+        None,
+    );
+
+    for (attribute, value) in &row.attributes {
+        let normalized = attribute.to_ascii_lowercase();
+        if !writable.contains(&normalized) || normalized.eq_ignore_ascii_case(primary_id) {
+            continue;
+        }
+
+        let value = match value {
+            Value::EntityReference(reference) => {
+                let key = (reference.logical_name.to_ascii_lowercase(), reference.id);
+                match remapped_ids.get(&key) {
+                    Some(Some(id)) => Value::EntityReference(EntityReference {
+                        id: *id,
+                        ..reference.clone()
+                    }),
+                    Some(None) => {
+                        unresolved.push(UnresolvedLookup {
+                            source_id: row.id,
+                            attribute: attribute.clone(),
+                            reference: reference.clone(),
+                        });
+                        continue;
+                    }
+                    None => value.clone(),
+                }
+            }
+            _ => value.clone(),
+        };
+        written.attributes.insert(attribute.clone(), value);
+    }
+
+    written
+}
+
+/// Upsert `written`, taking alternate key values from it so remapped lookups key the target row.
+fn build_upsert(written: Entity, key: &UpsertKey) -> Result<UpsertRequest, String> {
+    match key {
+        UpsertKey::PrimaryId => Ok(UpsertRequest::new(written)),
+        UpsertKey::AlternateKey(attributes) => {
+            let key = attributes
+                .iter()
+                .map(|attribute| match written.attributes.get(attribute) {
+                    Some(Value::Null) | None => Err(format!(
+                        "Written row is missing alternate key attribute {attribute}"
+                    )),
+                    Some(value) => Ok((attribute.clone(), value.clone())),
+                })
+                .collect::<Result<KeyAttributes, String>>()?;
+            Ok(UpsertRequest::with_alternate_key(written, key))
+        }
+    }
+}
+
+/// Comparable form of a key value; strings compare case-insensitively like Dataverse does.
+fn key_value_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.to_lowercase()),
+        Value::Int(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        Value::Decimal(value) => Some(value.normalize().to_string()),
+        Value::Boolean(value) => Some(value.to_string()),
+        Value::Guid(value) => Some(value.as_hyphenated().to_string()),
+        Value::Money(value) => Some(value.value.normalize().to_string()),
+        Value::OptionSetValue(value) => Some(value.value.to_string()),
+        Value::EntityReference(reference) => Some(reference.id.as_hyphenated().to_string()),
+        Value::DateTime(value) => Some(value.to_rfc3339()),
+        Value::Date(value) => Some(value.format("%Y-%m-%d").to_string()),
+        Value::OptionSetValueCollection(_)
+        | Value::PartyList(_)
+        | Value::EntityCollection(_)
+        | Value::Null => None,
+    }
+}
+
+pub(crate) fn key_values(row: &Entity, key_attributes: &[String]) -> Option<Vec<String>> {
+    key_attributes
+        .iter()
+        .map(|attribute| row.attributes.get(attribute).and_then(key_value_string))
+        .collect()
+}
+
+fn build_id_lookup_fetchxml(
+    logical_name: &str,
+    primary_id: &str,
+    key_attributes: &[String],
+    ids: &[Uuid],
+) -> String {
+    let mut fetchxml = format!("<fetch><entity name=\"{logical_name}\">");
+    fetchxml.push_str(&format!("<attribute name=\"{primary_id}\" />"));
+    for attribute in key_attributes {
+        fetchxml.push_str(&format!("<attribute name=\"{attribute}\" />"));
+    }
+    fetchxml.push_str(&format!(
+        "<filter><condition attribute=\"{primary_id}\" operator=\"in\">"
+    ));
+    for id in ids {
+        fetchxml.push_str(&format!("<value>{}</value>", id.as_hyphenated()));
+    }
+    fetchxml.push_str("</condition></filter></entity></fetch>");
+    fetchxml
+}
+
+pub(crate) fn build_key_lookup_fetchxml(
+    logical_name: &str,
+    key_attributes: &[String],
+    keys: &[Vec<String>],
+) -> String {
+    let mut fetchxml = format!("<fetch><entity name=\"{logical_name}\">");
+    for attribute in key_attributes {
+        fetchxml.push_str(&format!("<attribute name=\"{attribute}\" />"));
+    }
+    fetchxml.push_str("<filter type=\"or\">");
+    for key in keys {
+        fetchxml.push_str("<filter type=\"and\">");
+        for (attribute, value) in key_attributes.iter().zip(key) {
+            fetchxml.push_str(&format!(
+                "<condition attribute=\"{attribute}\" operator=\"eq\" value=\"{}\" />",
+                escape_xml_attribute(value)
+            ));
+        }
+        fetchxml.push_str("</filter>");
+    }
+    fetchxml.push_str("</filter></entity></fetch>");
+    fetchxml
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use uuid::Uuid;
+
+    use std::sync::Arc;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{
+        ConflictResolution, ConflictResolver, ConflictStrategy, DataCopyOptions, UpsertKey,
+        build_existing_rows_fetchxml, build_key_lookup_fetchxml, build_target_row, build_upsert,
+        key_values, pending_lookup_ids, resolve_conflict, writable_columns,
+    };
+    use crate::dataverse::entity::{Entity, EntityReference, Value};
+
+    fn reference(logical_name: &str, id: Uuid) -> Value {
+        Value::EntityReference(EntityReference {
+            id,
+            logical_name: logical_name.to_string(),
+            name: None,
+        })
+    }
+
+    fn source_contact(account_id: Uuid) -> Entity {
+        let mut row = Entity::new(Uuid::new_v4(), "contact", None);
+        row.attributes
+            .insert("contactid".to_string(), Value::Guid(row.id));
+        row.attributes.insert(
+            "emailaddress1".to_string(),
+            Value::String("a@contoso.com".to_string()),
+        );
+        row.attributes.insert(
+            "parentcustomerid".to_string(),
+            reference("account", account_id),
+        );
+        row.attributes.insert(
+            "parentcustomeridname".to_string(),
+            Value::String("Contoso".to_string()),
+        );
+        row.attributes.insert("__rownum".to_string(), Value::Int(1));
+        row
+    }
+
+    #[test]
+    fn target_row_keeps_writable_attributes_and_remaps_lookups() {
+        let source_account = Uuid::new_v4();
+        let target_account = Uuid::new_v4();
+        let row = source_contact(source_account);
+        let writable = ["contactid", "emailaddress1", "parentcustomerid"]
+            .into_iter()
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        let remapped = HashMap::from([(
+            ("account".to_string(), source_account),
+            Some(target_account),
+        )]);
+        let mut unresolved = Vec::new();
+
+        let written = build_target_row(
+            &row,
+            "contact",
+            "contactid",
+            &writable,
+            &DataCopyOptions::default(),
+            &remapped,
+            &mut unresolved,
+        );
+
+        assert_eq!(written.id, row.id);
+        assert_eq!(written.attributes.len(), 2);
+        assert!(matches!(
+            written.attributes.get("parentcustomerid"),
+            Some(Value::EntityReference(reference)) if reference.id == target_account
+        ));
+        assert!(unresolved.is_empty());
+    }
+
+    #[test]
+    fn unresolved_lookups_are_dropped_and_reported() {
+        let source_account = Uuid::new_v4();
+        let row = source_contact(source_account);
+        let writable = ["parentcustomerid".to_string()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let remapped = HashMap::from([(("account".to_string(), source_account), None)]);
+        let mut unresolved = Vec::new();
+
+        let written = build_target_row(
+            &row,
+            "contact",
+            "contactid",
+            &writable,
+            &DataCopyOptions::default(),
+            &remapped,
+            &mut unresolved,
+        );
+
+        assert!(written.attributes.is_empty());
+        assert_eq!(unresolved.len(), 1);
+        assert_eq!(unresolved[0].attribute, "parentcustomerid");
+    }
+
+    #[test]
+    fn alternate_key_upsert_requires_key_values() {
+        let row = source_contact(Uuid::new_v4());
+        let key = UpsertKey::AlternateKey(vec!["emailaddress1".to_string()]);
+        let request = build_upsert(row.clone(), &key).expect("should build");
+        assert_eq!(request.alternate_key.len(), 1);
+
+        let missing = UpsertKey::AlternateKey(vec!["employeeid".to_string()]);
+        assert!(build_upsert(row, &missing).is_err());
+    }
+
+    #[test]
+    fn alternate_keys_use_remapped_lookup_values() {
+        let source_account = Uuid::new_v4();
+        let target_account = Uuid::new_v4();
+        let row = source_contact(source_account);
+        let writable = ["emailaddress1", "parentcustomerid"]
+            .into_iter()
+            .map(str::to_string)
+            .collect::<HashSet<_>>();
+        let remapped = HashMap::from([(
+            ("account".to_string(), source_account),
+            Some(target_account),
+        )]);
+        let key_attributes = vec!["parentcustomerid".to_string(), "emailaddress1".to_string()];
+        let options = DataCopyOptions {
+            upsert_key: UpsertKey::AlternateKey(key_attributes.clone()),
+            ..DataCopyOptions::default()
+        };
+
+        let written = build_target_row(
+            &row,
+            "contact",
+            "contactid",
+            &writable,
+            &options,
+            &remapped,
+            &mut Vec::new(),
+        );
+
+        assert_eq!(
+            key_values(&written, &key_attributes),
+            Some(vec![
+                target_account.as_hyphenated().to_string(),
+                "a@contoso.com".to_string()
+            ])
+        );
+        let request = build_upsert(written, &options.upsert_key).expect("should build");
+        assert!(matches!(
+            request.alternate_key.iter().find(|(name, _)| name == "parentcustomerid"),
+            Some((_, Value::EntityReference(reference))) if reference.id == target_account
+        ));
+    }
+
+    #[test]
+    fn pending_ids_skip_resolved_and_other_tables() {
+        let resolved_id = Uuid::new_v4();
+        let pending_id = Uuid::new_v4();
+        let mut page = vec![source_contact(resolved_id), source_contact(pending_id)];
+        page[0].attributes.insert(
+            "ownerid".to_string(),
+            reference("systemuser", Uuid::new_v4()),
+        );
+        let resolved = HashMap::from([(("account".to_string(), resolved_id), None)]);
+
+        assert_eq!(
+            pending_lookup_ids(&page, "account", &resolved),
+            vec![pending_id]
+        );
+    }
+
+    fn modified(hour: u32) -> Entity {
+        let mut row = Entity::new(Uuid::new_v4(), "contact", None);
+        row.attributes.insert(
+            "modifiedon".to_string(),
+            Value::DateTime(Utc.with_ymd_and_hms(2024, 5, 1, hour, 0, 0).unwrap()),
+        );
+        row
+    }
+
+    #[test]
+    fn conflict_strategies_pick_the_row_to_keep() {
+        let older = modified(8);
+        let newer = modified(9);
+
+        assert!(matches!(
+            resolve_conflict(&ConflictStrategy::SourceWins, &older, &newer),
+            Ok(ConflictResolution::WriteSource)
+        ));
+        assert!(matches!(
+            resolve_conflict(&ConflictStrategy::TargetWins, &newer, &older),
+            Ok(ConflictResolution::KeepTarget)
+        ));
+        assert!(matches!(
+            resolve_conflict(&ConflictStrategy::NewestWins, &newer, &older),
+            Ok(ConflictResolution::WriteSource)
+        ));
+        assert!(matches!(
+            resolve_conflict(&ConflictStrategy::NewestWins, &older, &newer),
+            Ok(ConflictResolution::KeepTarget)
+        ));
+        let mut unstamped = modified(9);
+        unstamped.attributes.remove("modifiedon");
+        assert_eq!(
+            resolve_conflict(&ConflictStrategy::NewestWins, &unstamped, &older).unwrap_err(),
+            format!(
+                "Cannot pick the newest row: the source row {} has no modifiedon",
+                unstamped.id
+            )
+        );
+        assert!(resolve_conflict(&ConflictStrategy::NewestWins, &newer, &unstamped).is_err());
+
+        struct KeepEmail;
+        impl ConflictResolver for KeepEmail {
+            fn resolve(&self, source: &Entity, target: &Entity) -> ConflictResolution {
+                let mut merged = source.clone();
+                if let Some(email) = target.attributes.get("emailaddress1") {
+                    merged
+                        .attributes
+                        .insert("emailaddress1".to_string(), email.clone());
+                }
+                ConflictResolution::WriteMerged(Box::new(merged))
+            }
+        }
+        let mut target = modified(9);
+        target.attributes.insert(
+            "emailaddress1".to_string(),
+            Value::String("target@contoso.com".to_string()),
+        );
+        assert!(matches!(
+            resolve_conflict(&ConflictStrategy::Custom(Arc::new(KeepEmail)), &older, &target),
+            Ok(ConflictResolution::WriteMerged(merged))
+                if matches!(merged.attributes.get("emailaddress1"), Some(Value::String(email)) if email == "target@contoso.com")
+        ));
+    }
+
+    #[test]
+    fn existing_rows_fetch_reads_all_attributes_for_custom_resolvers() {
+        let lookup = build_key_lookup_fetchxml(
+            "contact",
+            &["emailaddress1".to_string(), "modifiedon".to_string()],
+            &[vec!["a@contoso.com".to_string()]],
+        );
+
+        assert_eq!(build_existing_rows_fetchxml("contact", &lookup, false), lookup);
+        let all = build_existing_rows_fetchxml("contact", &lookup, true);
+        assert!(all.starts_with("<fetch><entity name=\"contact\"><all-attributes /><filter"));
+        assert!(!all.contains("<attribute "));
+    }
+
+    #[test]
+    fn writable_columns_include_create_only_and_update_only_columns() {
+        let attribute = |logical_name: &str, create: bool, update: bool| {
+            serde_json::from_value(serde_json::json!({
+                "LogicalName": logical_name,
+                "SchemaName": logical_name,
+                "IsValidForCreate": create,
+                "IsValidForUpdate": update,
+            }))
+            .expect("attribute")
+        };
+
+        let writable = writable_columns(vec![
+            attribute("name", true, true),
+            attribute("transactioncurrencyid", true, false),
+            attribute("statecode", false, true),
+            attribute("createdon", false, false),
+        ]);
+
+        assert_eq!(
+            writable,
+            HashSet::from([
+                "name".to_string(),
+                "transactioncurrencyid".to_string(),
+                "statecode".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn key_lookup_fetch_matches_any_key_tuple() {
+        let keys = vec![vec!["acc-1".to_string()], vec!["o&b".to_string()]];
+        let fetchxml = build_key_lookup_fetchxml("account", &["accountnumber".to_string()], &keys);
+
+        assert!(fetchxml.contains("<filter type=\"or\">"));
+        assert!(fetchxml.contains("value=\"acc-1\""));
+        assert!(fetchxml.contains("value=\"o&amp;b\""));
+
+        let mut row = Entity::new(Uuid::new_v4(), "account", None);
+        row.attributes.insert(
+            "accountnumber".to_string(),
+            Value::String("ACC-1".to_string()),
+        );
+        assert_eq!(
+            key_values(&row, &["accountnumber".to_string()]),
+            Some(vec!["acc-1".to_string()])
+        );
+    }
+}
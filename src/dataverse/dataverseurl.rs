@@ -0,0 +1,232 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A validated Dataverse environment URL, such as `https://contoso.crm.dynamics.com`.
+///
+/// The URL must use `https` and name only a host, optionally with a port. Microsoft hosts under
+/// `dynamics.com` must be environment hosts such as `contoso.crm4.dynamics.com`, and Power Apps
+/// maker hosts are rejected; other hosts are accepted as custom domains. Trailing slashes are
+/// removed and the scheme and host are lowercased. `parse_insecure` also accepts `http` and
+/// single-label hosts such as `localhost`, for local mock servers and proxies.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DataverseUrl(String);
+
+impl DataverseUrl {
+    /// Validate and normalize an environment URL. A pasted Web API URL such as
+    /// `https://contoso.crm.dynamics.com/api/data/v9.2` fails here rather than with a `404` on
+    /// every request.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        Self::parse_with(value, false)
+    }
+
+    /// Validate and normalize an environment URL, also accepting `http` and single-label hosts.
+    pub fn parse_insecure(value: &str) -> Result<Self, String> {
+        Self::parse_with(value, true)
+    }
+
+    /// `parse_insecure` when `allow_insecure` is set, otherwise `parse`.
+    pub(crate) fn parse_with(value: &str, allow_insecure: bool) -> Result<Self, String> {
+        let trimmed = value.trim().trim_end_matches('/');
+        let (scheme, rest) = trimmed
+            .split_once("://")
+            .ok_or_else(|| format!("Invalid Dataverse URL '{value}': expected https://<host>"))?;
+        let scheme = scheme.to_ascii_lowercase();
+        if scheme != "https" && !(allow_insecure && scheme == "http") {
+            return Err(format!(
+                "Invalid Dataverse URL '{value}': Dataverse only accepts https"
+            ));
+        }
+        if rest.contains(['/', '?', '#']) {
+            return Err(format!(
+                "Invalid Dataverse URL '{value}': use the environment root, such as https://contoso.crm.dynamics.com, without a path or query"
+            ));
+        }
+        if rest.contains('@') {
+            return Err(format!(
+                "Invalid Dataverse URL '{value}': credentials do not belong in the URL"
+            ));
+        }
+
+        let authority = rest.to_ascii_lowercase();
+        let (host, port) = match authority.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority.as_str(), None),
+        };
+        if port.is_some_and(|port| port.parse::<u16>().is_err()) {
+            return Err(format!("Invalid Dataverse URL '{value}': invalid port"));
+        }
+        let labels = host.split('.').collect::<Vec<_>>();
+        if (labels.len() < 2 && !allow_insecure)
+            || labels.iter().any(|label| {
+                label.is_empty()
+                    || label.starts_with('-')
+                    || label.ends_with('-')
+                    || !label
+                        .chars()
+                        .all(|ch| ch.is_ascii_alphanumeric() || ch == '-')
+            })
+        {
+            return Err(format!(
+                "Invalid Dataverse URL '{value}': invalid host '{host}'"
+            ));
+        }
+        let maker_host = host == "powerapps.com" || host.ends_with(".powerapps.com");
+        if maker_host || (host.ends_with(".dynamics.com") && !is_environment_host(&labels)) {
+            return Err(format!(
+                "Invalid Dataverse URL '{value}': '{host}' is not a Dataverse environment host; expected a host such as contoso.crm.dynamics.com"
+            ));
+        }
+
+        Ok(Self(format!("{scheme}://{authority}")))
+    }
+
+    /// The normalized URL, without a trailing slash.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Host name, such as `contoso.crm.dynamics.com`.
+    pub fn host(&self) -> &str {
+        let authority = self
+            .0
+            .split_once("://")
+            .map_or(self.0.as_str(), |(_, rest)| rest);
+        authority
+            .split_once(':')
+            .map_or(authority, |(host, _)| host)
+    }
+
+    /// OAuth scope for app-only tokens: the environment URL followed by `/.default`.
+    pub fn default_scope(&self) -> String {
+        format!("{}/.default", self.0)
+    }
+}
+
+/// True for `<org>[.api].crm<N>.dynamics.com`.
+fn is_environment_host(labels: &[&str]) -> bool {
+    let [.., region, "dynamics", "com"] = labels else {
+        return false;
+    };
+    labels.len() >= 4
+        && region
+            .strip_prefix("crm")
+            .is_some_and(|suffix| suffix.chars().all(|ch| ch.is_ascii_digit()))
+}
+
+impl fmt::Display for DataverseUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for DataverseUrl {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::parse(value)
+    }
+}
+
+impl AsRef<str> for DataverseUrl {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Serialize for DataverseUrl {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for DataverseUrl {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DataverseUrl;
+
+    #[test]
+    fn normalizes_and_rejects_environment_urls() {
+        for (value, expected) in [
+            (
+                "https://contoso.crm.dynamics.com/",
+                "https://contoso.crm.dynamics.com",
+            ),
+            (
+                " HTTPS://Contoso.CRM4.Dynamics.com// ",
+                "https://contoso.crm4.dynamics.com",
+            ),
+            (
+                "https://contoso.api.crm.dynamics.com",
+                "https://contoso.api.crm.dynamics.com",
+            ),
+            (
+                "https://crm.contoso.com:8443",
+                "https://crm.contoso.com:8443",
+            ),
+        ] {
+            assert_eq!(DataverseUrl::parse(value).expect(value).as_str(), expected);
+        }
+
+        for value in [
+            "contoso.crm.dynamics.com",
+            "http://contoso.crm.dynamics.com",
+            "https://contoso.crm.dynamics.com/api/data/v9.2",
+            "https://make.powerapps.com",
+            "https://contoso.dynamics.com",
+            "https://crm.dynamics.com",
+            "https://localhost",
+            "https://contoso.crm.dynamics.com:99999",
+        ] {
+            let error = DataverseUrl::parse(value).expect_err(value);
+            assert!(error.starts_with("Invalid Dataverse URL"), "{error}");
+        }
+    }
+
+    #[test]
+    fn insecure_parsing_accepts_http_and_single_label_hosts() {
+        for (value, expected) in [
+            ("http://localhost:5555/", "http://localhost:5555"),
+            ("HTTP://Proxy", "http://proxy"),
+            (
+                "https://contoso.crm.dynamics.com",
+                "https://contoso.crm.dynamics.com",
+            ),
+        ] {
+            assert_eq!(
+                DataverseUrl::parse_insecure(value).expect(value).as_str(),
+                expected
+            );
+        }
+        assert_eq!(
+            DataverseUrl::parse_insecure("http://localhost:5555")
+                .expect("url")
+                .host(),
+            "localhost"
+        );
+        for value in [
+            "ftp://localhost",
+            "http://localhost/api/data/v9.2",
+            "https://make.powerapps.com",
+        ] {
+            assert!(DataverseUrl::parse_insecure(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn derives_host_and_default_scope() {
+        let url = DataverseUrl::parse("https://contoso.crm.dynamics.com/").expect("url");
+        assert_eq!(url.host(), "contoso.crm.dynamics.com");
+        assert_eq!(
+            url.default_scope(),
+            "https://contoso.crm.dynamics.com/.default"
+        );
+    }
+}
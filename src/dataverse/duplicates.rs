@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+use crate::dataverse::url::{encode_query_value, encode_string_literal};
+
+/// Rows requested per `RetrieveDuplicates` page.
+pub(crate) const DUPLICATE_PAGE_SIZE: u32 = 250;
+
+/// Path of a `RetrieveDuplicates` call checking `record`, a `logical_name` row that need not
+/// exist yet, against the published duplicate detection rules for `matching_logical_name`.
+pub(crate) fn retrieve_duplicates_path(
+    logical_name: &str,
+    matching_logical_name: &str,
+    record: &HashMap<String, Value>,
+    page_number: u32,
+) -> String {
+    let mut business_entity = Map::new();
+    business_entity.insert(
+        "@odata.type".to_string(),
+        Value::String(format!("Microsoft.Dynamics.CRM.{logical_name}")),
+    );
+    business_entity.extend(
+        record
+            .iter()
+            .map(|(attribute, value)| (attribute.clone(), value.clone())),
+    );
+    let paging_info = json!({
+        "PageNumber": page_number,
+        "Count": DUPLICATE_PAGE_SIZE,
+    });
+    format!(
+        "RetrieveDuplicates(BusinessEntity=@p1,MatchingEntityName=@p2,PagingInfo=@p3)?@p1={}&@p2={}&@p3={}",
+        encode_query_value(&Value::Object(business_entity).to_string()),
+        encode_string_literal(matching_logical_name),
+        encode_query_value(&paging_info.to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::{Value, json};
+
+    use super::retrieve_duplicates_path;
+
+    #[test]
+    fn builds_retrieve_duplicates_parameters() {
+        let record = HashMap::from([("emailaddress1".to_string(), json!("someone@contoso.com"))]);
+        let path = retrieve_duplicates_path("contact", "contact", &record, 2);
+        let (function, query) = path.split_once('?').expect("query");
+        assert_eq!(
+            function,
+            "RetrieveDuplicates(BusinessEntity=@p1,MatchingEntityName=@p2,PagingInfo=@p3)"
+        );
+
+        let parameters = query
+            .split('&')
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').expect("parameter");
+                (
+                    name,
+                    urlencoding::decode(value).expect("decode").into_owned(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let entity: Value = serde_json::from_str(&parameters["@p1"]).expect("entity json");
+        assert_eq!(entity["@odata.type"], "Microsoft.Dynamics.CRM.contact");
+        assert_eq!(entity["emailaddress1"], "someone@contoso.com");
+        assert_eq!(parameters["@p2"], "'contact'");
+        let paging: Value = serde_json::from_str(&parameters["@p3"]).expect("paging json");
+        assert_eq!(paging["PageNumber"], 2);
+        assert_eq!(paging["Count"], 250);
+    }
+}
@@ -1,10 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use uuid::Uuid;
 
+use crate::dataverse::url::bind_path;
+
 /// Represents a Dataverse attribute value.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(untagged)]
@@ -21,6 +23,9 @@ pub enum Value {
     Boolean(bool),
     /// Date/time value.
     DateTime(DateTime<Utc>),
+    /// Calendar date for columns with `DateOnly` behavior, which Dataverse stores without a time
+    /// or time zone.
+    Date(NaiveDate),
     /// GUID value.
     Guid(Uuid),
     /// Money value.
@@ -33,13 +38,76 @@ pub enum Value {
     Null,
     /// Entity reference value (lookup).
     EntityReference(EntityReference),
+    /// Activity party list, such as the `to` recipients of an email.
+    PartyList(Vec<EntityReference>),
+    /// Related rows under a collection-valued navigation property, created together with the
+    /// parent in one request (deep insert).
+    EntityCollection(Vec<Entity>),
 }
 
+/// Logical name of the lookup that sets the currency of a row's money columns.
+pub const TRANSACTION_CURRENCY_ATTRIBUTE: &str = "transactioncurrencyid";
+
 /// Dataverse money value.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Money {
-    /// Monetary amount.
+    /// Monetary amount in the row's transaction currency.
     pub value: Decimal,
+    /// Amount converted to the organization's base currency, read from the `{column}_base`
+    /// column. Dataverse calculates this value, so it is never written.
+    #[serde(default)]
+    pub base_value: Option<Decimal>,
+    /// Row transaction currency (`transactioncurrencyid`).
+    #[serde(default)]
+    pub currency: Option<EntityReference>,
+}
+
+impl Money {
+    /// Create a money value without currency details.
+    pub fn new(value: Decimal) -> Self {
+        Self {
+            value,
+            base_value: None,
+            currency: None,
+        }
+    }
+
+    /// Create a money value in the currency with this `transactioncurrency` ID.
+    pub fn with_currency(value: Decimal, currency_id: Uuid) -> Self {
+        Self {
+            value,
+            base_value: None,
+            currency: Some(EntityReference {
+                id: currency_id,
+                logical_name: "transactioncurrency".to_string(),
+                name: None,
+            }),
+        }
+    }
+
+    /// Write this amount to `column` in a create or update payload. When a currency is set, the
+    /// row's `transactioncurrencyid` is bound as well, since Dataverse stores one currency per row.
+    pub fn apply_to(
+        &self,
+        attributes: &mut HashMap<String, serde_json::Value>,
+        column: &str,
+    ) -> Result<(), String> {
+        let amount = serde_json::from_str::<serde_json::Value>(&self.value.to_string())
+            .map_err(|e| format!("Failed to serialize money value '{}': {e}", self.value))?;
+        attributes.insert(column.to_string(), amount);
+
+        if let Some(currency) = &self.currency {
+            attributes.insert(
+                format!("{TRANSACTION_CURRENCY_ATTRIBUTE}@odata.bind"),
+                serde_json::Value::String(bind_path(
+                    "transactioncurrencies",
+                    currency.id.as_hyphenated(),
+                )),
+            );
+        }
+
+        Ok(())
+    }
 }
 
 /// Dataverse single option-set value.
@@ -81,8 +149,31 @@ pub struct Entity {
     pub logical_name: String,
     /// Primary name for the entity record, when provided.
     pub name: Option<String>,
-    /// Attribute map keyed by logical names.
+    /// Row version from the `@odata.etag` annotation, for `If-Match` concurrency checks. Only
+    /// set on rows read from Dataverse.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Attribute map keyed by logical names. Iteration order is unspecified; use
+    /// `sorted_attributes` for stable output. Serialization always writes keys in sorted order.
+    #[serde(serialize_with = "serialize_sorted")]
     pub attributes: HashMap<Attribute, Value>,
+    /// `@odata.nextLink` of each expanded collection Dataverse truncated, keyed by navigation
+    /// property, for `ServiceClient::expand_remaining`.
+    #[serde(skip)]
+    pub(crate) expanded_next_links: HashMap<String, String>,
+    /// Position of the row in a paged result, counting from 1 across pages. Only set when
+    /// `RequestOptions::row_numbers` asks for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub row_number: Option<i64>,
+    /// The row's JSON exactly as Dataverse returned it, annotations included. Only set when
+    /// `ServiceClient::set_keep_raw_json` asks for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+    /// Columns of each linked table of a FetchXML query, keyed by the `link-entity` alias. Only
+    /// set when `RequestOptions::nest_linked_entities` asks for it; otherwise they stay in
+    /// `attributes` as `alias.column`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub linked: BTreeMap<String, Entity>,
 }
 
 impl Entity {
@@ -92,27 +183,148 @@ impl Entity {
             id,
             logical_name: logical_name.into(),
             name,
+            etag: None,
             attributes: HashMap::new(),
+            expanded_next_links: HashMap::new(),
+            row_number: None,
+            raw: None,
+            linked: BTreeMap::new(),
+        }
+    }
+
+    /// Whether Dataverse truncated the expanded collection `navigation_property` of this row.
+    /// `ServiceClient::expand_remaining` loads the rest.
+    pub fn has_more_expanded(&self, navigation_property: &str) -> bool {
+        self.expanded_next_links.contains_key(navigation_property)
+    }
+
+    /// Attributes ordered by logical name, so exports and diffs are the same on every run.
+    pub fn sorted_attributes(&self) -> Vec<(&Attribute, &Value)> {
+        let mut attributes = self.attributes.iter().collect::<Vec<_>>();
+        attributes.sort_unstable_by_key(|(name, _)| *name);
+        attributes
+    }
+
+    /// Merge the separate keys Dataverse produces for each lookup into its `EntityReference`.
+    ///
+    /// A lookup column arrives as a value plus `lookuplogicalname` and `FormattedValue`
+    /// annotations, which parsing exposes as the lookup attribute and a sibling `{lookup}name`
+    /// attribute. This moves the display name into `EntityReference::name` and removes the sibling
+    /// key, along with any raw `_{lookup}_value` or annotation keys, so each lookup is one column.
+    pub fn merge_lookup_annotations(&mut self) {
+        let lookups = self
+            .attributes
+            .iter()
+            .filter(|(_, value)| matches!(value, Value::EntityReference(_)))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for lookup in lookups {
+            let name = match self.attributes.remove(&format!("{lookup}name")) {
+                Some(Value::String(name)) => Some(name),
+                _ => None,
+            };
+            let raw_key = format!("_{lookup}_value");
+            self.attributes.retain(|key, _| {
+                key != &raw_key
+                    && !key
+                        .split_once('@')
+                        .is_some_and(|(base, _)| base == lookup || base == raw_key)
+            });
+
+            if let Some(Value::EntityReference(reference)) = self.attributes.get_mut(&lookup)
+                && reference.name.is_none()
+            {
+                reference.name = name;
+            }
+        }
+    }
+
+    /// Move the `prefix.column` attributes of each linked table in `links`, given as
+    /// `(prefix, table)`, into a row under `linked`. A linked row's ID is read from its primary
+    /// ID column, named in `primary_id_attributes` by table, when the query selected it.
+    pub(crate) fn nest_linked_attributes(
+        &mut self,
+        links: &[(String, String)],
+        primary_id_attributes: &HashMap<String, String>,
+    ) {
+        for (prefix, table) in links {
+            let key_prefix = format!("{prefix}.");
+            let columns = self
+                .attributes
+                .keys()
+                .filter(|key| key.starts_with(&key_prefix))
+                .cloned()
+                .collect::<Vec<_>>();
+            if columns.is_empty() {
+                continue;
+            }
+
+            let mut linked = Entity::new(Uuid::nil(), table.clone(), None);
+            for key in columns {
+                if let Some(value) = self.attributes.remove(&key) {
+                    linked
+                        .attributes
+                        .insert(key[key_prefix.len()..].to_string(), value);
+                }
+            }
+            if let Some(Value::Guid(id)) = primary_id_attributes
+                .get(table)
+                .and_then(|primary_id| linked.attributes.get(primary_id))
+            {
+                linked.id = *id;
+            }
+            self.linked.insert(prefix.clone(), linked);
         }
     }
 }
 
+/// Sorted union of the attribute names of `entities`, for stable CSV headers or table columns
+/// when rows carry different attributes.
+pub fn sorted_attribute_names(entities: &[Entity]) -> Vec<&str> {
+    entities
+        .iter()
+        .flat_map(|entity| entity.attributes.keys().map(String::as_str))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+fn serialize_sorted<S>(
+    attributes: &HashMap<Attribute, Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_map(attributes.iter().collect::<BTreeMap<_, _>>())
+}
+
 impl Default for Entity {
     fn default() -> Self {
         Self {
             id: Uuid::nil(),
             logical_name: String::new(),
             name: None,
+            etag: None,
             attributes: HashMap::new(),
+            expanded_next_links: HashMap::new(),
+            row_number: None,
+            raw: None,
+            linked: BTreeMap::new(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Entity;
+    use std::collections::HashMap;
+
+    use rust_decimal::Decimal;
     use uuid::Uuid;
 
+    use super::{Entity, EntityReference, Money, Value, sorted_attribute_names};
+
     #[test]
     fn new_entity_starts_with_empty_attribute_map() {
         let id = Uuid::new_v4();
@@ -124,6 +336,139 @@ mod tests {
         assert!(entity.attributes.is_empty());
     }
 
+    #[test]
+    fn attributes_iterate_and_serialize_in_name_order() {
+        let mut first = Entity::new(Uuid::nil(), "account", None);
+        for name in ["telephone1", "accountnumber", "name", "createdon"] {
+            first
+                .attributes
+                .insert(name.to_string(), Value::String(name.to_string()));
+        }
+        let mut second = Entity::new(Uuid::nil(), "account", None);
+        second
+            .attributes
+            .insert("websiteurl".to_string(), Value::Null);
+
+        assert_eq!(
+            first
+                .sorted_attributes()
+                .into_iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["accountnumber", "createdon", "name", "telephone1"]
+        );
+        assert_eq!(
+            sorted_attribute_names(&[first.clone(), second]),
+            [
+                "accountnumber",
+                "createdon",
+                "name",
+                "telephone1",
+                "websiteurl"
+            ]
+        );
+
+        let json = serde_json::to_string(&first).expect("should serialize");
+        let (_, json) = json
+            .split_once("\"attributes\":")
+            .expect("attributes present");
+        let positions = ["accountnumber", "createdon", "name", "telephone1"]
+            .map(|name| json.find(&format!("\"{name}\":")).expect("key present"));
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn merge_lookup_annotations_collapses_lookup_keys() {
+        let id = Uuid::new_v4();
+        let mut entity = Entity::new(Uuid::new_v4(), "contact", None);
+        entity.attributes.insert(
+            "parentcustomerid".to_string(),
+            Value::EntityReference(EntityReference {
+                id,
+                logical_name: "account".to_string(),
+                name: None,
+            }),
+        );
+        entity.attributes.insert(
+            "parentcustomeridname".to_string(),
+            Value::String("Contoso".to_string()),
+        );
+        entity.attributes.insert(
+            "parentcustomerid@Microsoft.Dynamics.CRM.lookuplogicalname".to_string(),
+            Value::String("account".to_string()),
+        );
+        entity
+            .attributes
+            .insert("fullname".to_string(), Value::String("Ada".to_string()));
+
+        entity.merge_lookup_annotations();
+
+        assert_eq!(entity.attributes.len(), 2);
+        assert!(matches!(
+            entity.attributes.get("parentcustomerid"),
+            Some(Value::EntityReference(reference))
+                if reference.id == id && reference.name.as_deref() == Some("Contoso")
+        ));
+    }
+
+    #[test]
+    fn nest_linked_attributes_groups_columns_by_alias() {
+        let contact_id = Uuid::new_v4();
+        let mut entity = Entity::new(Uuid::new_v4(), "account", None);
+        entity
+            .attributes
+            .insert("name".to_string(), Value::String("Contoso".to_string()));
+        entity
+            .attributes
+            .insert("pc.contactid".to_string(), Value::Guid(contact_id));
+        entity.attributes.insert(
+            "pc.fullname".to_string(),
+            Value::String("Nancy Anderson".to_string()),
+        );
+        entity.attributes.insert(
+            "owner.fullname".to_string(),
+            Value::String("Kim Abercrombie".to_string()),
+        );
+
+        entity.nest_linked_attributes(
+            &[
+                ("pc".to_string(), "contact".to_string()),
+                ("owner".to_string(), "systemuser".to_string()),
+                ("unused".to_string(), "team".to_string()),
+            ],
+            &HashMap::from([("contact".to_string(), "contactid".to_string())]),
+        );
+
+        assert_eq!(entity.attributes.len(), 1);
+        assert_eq!(entity.linked.len(), 2);
+        let contact = &entity.linked["pc"];
+        assert_eq!(contact.id, contact_id);
+        assert_eq!(contact.logical_name, "contact");
+        assert!(matches!(
+            contact.attributes.get("fullname"),
+            Some(Value::String(name)) if name == "Nancy Anderson"
+        ));
+        assert!(entity.linked["owner"].id.is_nil());
+    }
+
+    #[test]
+    fn money_apply_to_binds_transaction_currency() {
+        let currency_id = Uuid::new_v4();
+        let mut attributes = HashMap::new();
+
+        Money::with_currency(Decimal::new(12345, 2), currency_id)
+            .apply_to(&mut attributes, "revenue")
+            .expect("should apply");
+
+        assert_eq!(attributes.get("revenue"), Some(&serde_json::json!(123.45)));
+        assert_eq!(
+            attributes.get("transactioncurrencyid@odata.bind"),
+            Some(&serde_json::Value::String(format!(
+                "/transactioncurrencies({currency_id})"
+            )))
+        );
+    }
+
     #[test]
     fn default_entity_uses_nil_identity() {
         let entity = Entity::default();
@@ -1,4 +1,17 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dataverse::label::LocalizedLabel;
+use crate::dataverse::optionset::{OptionMetadata, parse_option};
+
+/// Derived attribute metadata types read into `AttributeDetail`.
+pub(crate) const ATTRIBUTE_DETAIL_METADATA_TYPES: &[&str] = &[
+    "BooleanAttributeMetadata",
+    "PicklistAttributeMetadata",
+    "MultiSelectPicklistAttributeMetadata",
+    "StateAttributeMetadata",
+    "StatusAttributeMetadata",
+];
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AttributeTypeName {
@@ -7,6 +20,17 @@ pub struct AttributeTypeName {
     pub value: Option<String>,
 }
 
+/// How a DateTime attribute stores and converts time zone information.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeBehavior {
+    /// Stored in UTC and converted to the user's time zone for display.
+    UserLocal,
+    /// Date without a time or time zone.
+    DateOnly,
+    /// Date and time stored as entered, without time zone conversion.
+    TimeZoneIndependent,
+}
+
 /// Dataverse attribute metadata.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EntityAttribute {
@@ -16,6 +40,9 @@ pub struct EntityAttribute {
     /// Schema name of the attribute.
     #[serde(rename = "SchemaName")]
     pub schema_name: String,
+    /// Display name in each installed language.
+    #[serde(rename = "DisplayName", default)]
+    pub display_name: Option<LocalizedLabel>,
     /// Attribute type name.
     #[serde(rename = "AttributeType")]
     pub attribute_type: Option<String>,
@@ -31,7 +58,241 @@ pub struct EntityAttribute {
     /// True if the attribute is valid for read operations.
     #[serde(rename = "IsValidForRead")]
     pub is_valid_for_read: Option<bool>,
+    /// True if the attribute can be set when a row is created.
+    #[serde(rename = "IsValidForCreate", default)]
+    pub is_valid_for_create: Option<bool>,
     /// True if the attribute is valid for update operations.
     #[serde(rename = "IsValidForUpdate")]
     pub is_valid_for_update: Option<bool>,
+    /// Date and time behavior, for DateTime attributes.
+    #[serde(default)]
+    pub date_time_behavior: Option<DateTimeBehavior>,
+    /// Type-specific metadata of Boolean, choice, state, and status attributes, filled by
+    /// `ServiceClient::list_entity_attributes_with_details`.
+    #[serde(default)]
+    pub detail: Option<AttributeDetail>,
+}
+
+/// Metadata only a derived attribute type carries, such as the labels of a Yes/No column.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum AttributeDetail {
+    /// `BooleanAttributeMetadata`: the labels and colors of the two options.
+    Boolean {
+        /// Option for `true`, with value 1.
+        true_option: OptionMetadata,
+        /// Option for `false`, with value 0.
+        false_option: OptionMetadata,
+    },
+    /// `PicklistAttributeMetadata`: the options of a choice column.
+    Picklist(Vec<OptionMetadata>),
+    /// `MultiSelectPicklistAttributeMetadata`: the options of a multi-select choice column.
+    MultiSelectPicklist(Vec<OptionMetadata>),
+    /// `StateAttributeMetadata`: the states of `statecode`.
+    State(Vec<StateOption>),
+    /// `StatusAttributeMetadata`: the status reasons of `statuscode` and the state of each.
+    Status(Vec<StatusOption>),
+}
+
+impl AttributeDetail {
+    /// Options of a choice, multi-select choice, state, or status attribute, or both options of a
+    /// Boolean attribute.
+    pub fn options(&self) -> Vec<&OptionMetadata> {
+        match self {
+            Self::Boolean {
+                true_option,
+                false_option,
+            } => vec![true_option, false_option],
+            Self::Picklist(options) | Self::MultiSelectPicklist(options) => {
+                options.iter().collect()
+            }
+            Self::State(states) => states.iter().map(|state| &state.option).collect(),
+            Self::Status(statuses) => statuses.iter().map(|status| &status.option).collect(),
+        }
+    }
+
+    /// The option with `value`.
+    pub fn option(&self, value: i32) -> Option<&OptionMetadata> {
+        self.options()
+            .into_iter()
+            .find(|option| option.value == value)
+    }
+
+    /// Label of the option with `value`.
+    pub fn label(&self, value: i32) -> Option<&str> {
+        self.option(value)?.user_label()
+    }
+}
+
+/// A `statecode` option.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StateOption {
+    /// The state's value and label.
+    pub option: OptionMetadata,
+    /// Status reason a row gets when it moves to this state.
+    pub default_status: Option<i32>,
+    /// Label of the state that does not change with the language, such as `Active`.
+    pub invariant_name: Option<String>,
+}
+
+/// A `statuscode` option.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StatusOption {
+    /// The status reason's value and label.
+    pub option: OptionMetadata,
+    /// The `statecode` value this status reason belongs to.
+    pub state: Option<i32>,
+}
+
+/// Status reasons valid for the `statecode` value `state`.
+pub fn statuses_for_state(statuses: &[StatusOption], state: i32) -> Vec<&StatusOption> {
+    statuses
+        .iter()
+        .filter(|status| status.state == Some(state))
+        .collect()
+}
+
+/// Read the details of an attribute list cast to `metadata_type`, one of
+/// `ATTRIBUTE_DETAIL_METADATA_TYPES`, with `OptionSet` and `GlobalOptionSet` expanded.
+pub(crate) fn parse_attribute_details(
+    metadata_type: &str,
+    json: &Value,
+) -> Result<Vec<(String, AttributeDetail)>, String> {
+    let attributes = json
+        .get("value")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    Ok(attributes
+        .iter()
+        .filter_map(|attribute| {
+            let logical_name = attribute.get("LogicalName")?.as_str()?.to_string();
+            let option_set = attribute
+                .get("OptionSet")
+                .filter(|value| !value.is_null())
+                .or_else(|| attribute.get("GlobalOptionSet"))?;
+            let options = || -> Vec<&Value> {
+                option_set
+                    .get("Options")
+                    .and_then(Value::as_array)
+                    .map(|options| options.iter().collect())
+                    .unwrap_or_default()
+            };
+            let int = |option: &Value, name: &str| {
+                option
+                    .get(name)
+                    .and_then(Value::as_i64)
+                    .and_then(|value| i32::try_from(value).ok())
+            };
+
+            let detail = match metadata_type {
+                "BooleanAttributeMetadata" => AttributeDetail::Boolean {
+                    true_option: parse_option(option_set.get("TrueOption")?)?,
+                    false_option: parse_option(option_set.get("FalseOption")?)?,
+                },
+                "PicklistAttributeMetadata" => AttributeDetail::Picklist(
+                    options().into_iter().filter_map(parse_option).collect(),
+                ),
+                "MultiSelectPicklistAttributeMetadata" => AttributeDetail::MultiSelectPicklist(
+                    options().into_iter().filter_map(parse_option).collect(),
+                ),
+                "StateAttributeMetadata" => AttributeDetail::State(
+                    options()
+                        .into_iter()
+                        .filter_map(|option| {
+                            Some(StateOption {
+                                option: parse_option(option)?,
+                                default_status: int(option, "DefaultStatus"),
+                                invariant_name: option
+                                    .get("InvariantName")
+                                    .and_then(Value::as_str)
+                                    .map(str::to_string),
+                            })
+                        })
+                        .collect(),
+                ),
+                "StatusAttributeMetadata" => AttributeDetail::Status(
+                    options()
+                        .into_iter()
+                        .filter_map(|option| {
+                            Some(StatusOption {
+                                option: parse_option(option)?,
+                                state: int(option, "State"),
+                            })
+                        })
+                        .collect(),
+                ),
+                _ => return None,
+            };
+            Some((logical_name, detail))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{AttributeDetail, parse_attribute_details, statuses_for_state};
+
+    fn label(text: &str) -> serde_json::Value {
+        json!({"UserLocalizedLabel": {"Label": text, "LanguageCode": 1033}})
+    }
+
+    #[test]
+    fn parses_boolean_labels_and_colors() {
+        let json = json!({"value": [{
+            "LogicalName": "donotemail",
+            "OptionSet": {
+                "TrueOption": {"Value": 1, "Label": label("Do Not Allow"), "Color": "#ff0000"},
+                "FalseOption": {"Value": 0, "Label": label("Allow"), "Color": null}
+            }
+        }]});
+
+        let details = parse_attribute_details("BooleanAttributeMetadata", &json).expect("parses");
+
+        assert_eq!(details[0].0, "donotemail");
+        let AttributeDetail::Boolean {
+            true_option,
+            false_option,
+        } = &details[0].1
+        else {
+            panic!("expected a Boolean detail");
+        };
+        assert_eq!(true_option.color.as_deref(), Some("#ff0000"));
+        assert_eq!(false_option.color, None);
+        assert_eq!(details[0].1.label(0), Some("Allow"));
+    }
+
+    #[test]
+    fn parses_status_state_mappings_and_global_option_sets() {
+        let status = json!({"value": [{
+            "LogicalName": "statuscode",
+            "OptionSet": {"Options": [
+                {"Value": 1, "State": 0, "Label": label("Active")},
+                {"Value": 2, "State": 1, "Label": label("Inactive")},
+                {"Value": 3, "State": 1, "Label": label("Closed"), "Color": "#808080"}
+            ]}
+        }]});
+        let details = parse_attribute_details("StatusAttributeMetadata", &status).expect("parses");
+        let AttributeDetail::Status(statuses) = &details[0].1 else {
+            panic!("expected a Status detail");
+        };
+        assert_eq!(statuses_for_state(statuses, 1).len(), 2);
+        assert_eq!(
+            details[0]
+                .1
+                .option(3)
+                .and_then(|option| option.color.as_deref()),
+            Some("#808080")
+        );
+
+        let picklist = json!({"value": [{
+            "LogicalName": "industrycode",
+            "OptionSet": null,
+            "GlobalOptionSet": {"Options": [{"Value": 1, "Label": label("Accounting")}]}
+        }]});
+        let details =
+            parse_attribute_details("PicklistAttributeMetadata", &picklist).expect("parses");
+        assert_eq!(details[0].1.label(1), Some("Accounting"));
+    }
 }
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::dataverse::label::LocalizedLabel;
+
 /// Dataverse entity definition metadata.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EntityDefinition {
@@ -15,9 +17,9 @@ pub struct EntityDefinition {
     /// Schema name of the entity.
     #[serde(rename = "SchemaName")]
     pub schema_name: String,
-    /// Display name payload.
-    #[serde(rename = "DisplayName")]
-    pub display_name: Option<Value>,
+    /// Display name in each installed language.
+    #[serde(rename = "DisplayName", default)]
+    pub display_name: Option<LocalizedLabel>,
     /// Entity set (collection) name.
     #[serde(rename = "EntitySetName")]
     pub entity_set_name: String,
@@ -30,7 +32,106 @@ pub struct EntityDefinition {
     /// Primary ID attribute logical name.
     #[serde(rename = "PrimaryIdAttribute")]
     pub primary_id_attribute: Option<String>,
+    /// Primary name attribute logical name.
+    #[serde(rename = "PrimaryNameAttribute")]
+    pub primary_name_attribute: Option<String>,
+    /// Who owns rows in the table.
+    #[serde(rename = "OwnershipType", default)]
+    pub ownership_type: Option<OwnershipType>,
     /// Additional fields returned by the API.
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
 }
+
+/// Table ownership model, from `EntityMetadata.OwnershipType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OwnershipType {
+    /// No ownership, such as for some virtual and intersect tables.
+    None,
+    /// Rows are owned by a user or team.
+    UserOwned,
+    /// Rows are owned by a team.
+    TeamOwned,
+    /// Rows are owned by a business unit.
+    BusinessOwned,
+    /// Rows belong to the organization and have no owner.
+    OrganizationOwned,
+    /// Rows are owned through their business unit parent.
+    BusinessParented,
+    /// An ownership type this crate does not know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+impl OwnershipType {
+    /// True when rows have an owner that can be changed with assign and shared with other
+    /// principals.
+    pub fn supports_assign_and_share(self) -> bool {
+        matches!(self, Self::UserOwned | Self::TeamOwned)
+    }
+}
+
+impl EntityDefinition {
+    /// True unless metadata says rows cannot be assigned or shared. Tables whose ownership was
+    /// not retrieved are assumed to support it.
+    pub fn supports_assign_and_share(&self) -> bool {
+        self.ownership_type
+            .is_none_or(OwnershipType::supports_assign_and_share)
+    }
+
+    /// Fail with a clear error for tables whose rows cannot be assigned or shared, instead of
+    /// letting Dataverse reject the request.
+    pub(crate) fn ensure_assign_and_share(&self, operation: &str) -> Result<(), String> {
+        match self.ownership_type {
+            Some(ownership) if !ownership.supports_assign_and_share() => Err(format!(
+                "Cannot {operation} rows of '{}' because the table is {ownership:?}",
+                self.logical_name
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{EntityDefinition, OwnershipType};
+
+    fn definition(ownership: &str) -> EntityDefinition {
+        serde_json::from_value(json!({
+            "LogicalName": "cr_setting",
+            "SchemaName": "cr_Setting",
+            "EntitySetName": "cr_settings",
+            "IsCustomEntity": true,
+            "OwnershipType": ownership
+        }))
+        .expect("should parse")
+    }
+
+    #[test]
+    fn parses_ownership_type() {
+        assert_eq!(
+            definition("OrganizationOwned").ownership_type,
+            Some(OwnershipType::OrganizationOwned)
+        );
+        assert_eq!(
+            definition("SomethingNew").ownership_type,
+            Some(OwnershipType::Unknown)
+        );
+    }
+
+    #[test]
+    fn organization_owned_tables_reject_assign_and_share() {
+        let organization_owned = definition("OrganizationOwned");
+        assert!(!organization_owned.supports_assign_and_share());
+        assert_eq!(
+            organization_owned.ensure_assign_and_share("share").unwrap_err(),
+            "Cannot share rows of 'cr_setting' because the table is OrganizationOwned"
+        );
+
+        let user_owned = definition("UserOwned");
+        assert!(user_owned.supports_assign_and_share());
+        assert!(user_owned.ensure_assign_and_share("assign").is_ok());
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::url::encode_string_literal;
+
+/// Navigation property from a definition to its current value rows.
+const VALUE_NAVIGATION: &str = "environmentvariabledefinition_environmentvariablevalue";
+
+/// Data type of an environment variable, from `environmentvariabledefinition.type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EnvironmentVariableType {
+    /// Text.
+    String,
+    /// Decimal number, stored as text.
+    Number,
+    /// `yes` or `no`.
+    Boolean,
+    /// JSON document, stored as text.
+    Json,
+    /// Connector data source, such as a SharePoint site.
+    DataSource,
+    /// An Azure Key Vault secret reference. The value is the secret's reference, not the secret.
+    Secret,
+    /// A type code this crate does not know.
+    Other(i64),
+}
+
+impl EnvironmentVariableType {
+    /// Type for an `environmentvariabledefinition.type` option value.
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            100000000 => Self::String,
+            100000001 => Self::Number,
+            100000002 => Self::Boolean,
+            100000003 => Self::Json,
+            100000004 => Self::DataSource,
+            100000005 => Self::Secret,
+            other => Self::Other(other),
+        }
+    }
+}
+
+/// An environment variable definition joined with its current value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvironmentVariable {
+    /// `environmentvariabledefinitionid`.
+    pub definition_id: Uuid,
+    /// Schema name, such as `contoso_ApiUrl`.
+    pub schema_name: String,
+    /// Display name, when set.
+    pub display_name: Option<String>,
+    /// Data type of the variable.
+    pub variable_type: EnvironmentVariableType,
+    /// Default value stored on the definition.
+    pub default_value: Option<String>,
+    /// `environmentvariablevalueid` of the current value row, when the environment has one.
+    pub value_id: Option<Uuid>,
+    /// Current value set for this environment, overriding the default.
+    pub value: Option<String>,
+}
+
+impl EnvironmentVariable {
+    /// The current value when set, otherwise the default value.
+    pub fn effective_value(&self) -> Option<&str> {
+        self.value.as_deref().or(self.default_value.as_deref())
+    }
+}
+
+/// Query for the definition with `schema_name` and its value rows.
+pub(crate) fn environment_variable_query(schema_name: &str) -> String {
+    format!(
+        "environmentvariabledefinitions?$select=environmentvariabledefinitionid,schemaname,displayname,type,defaultvalue&$filter=schemaname eq {}&$expand={VALUE_NAVIGATION}($select=environmentvariablevalueid,value)",
+        encode_string_literal(schema_name)
+    )
+}
+
+/// Read the first definition of an `environment_variable_query` response.
+pub(crate) fn parse_environment_variable(
+    json: &Value,
+    schema_name: &str,
+) -> Result<EnvironmentVariable, String> {
+    let definition = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?
+        .first()
+        .ok_or_else(|| format!("Environment variable '{schema_name}' not found"))?;
+
+    let text = |row: &Value, key: &str| {
+        row.get(key)
+            .and_then(|value| value.as_str())
+            .map(str::to_string)
+    };
+    let id = |row: &Value, key: &str| {
+        row.get(key)
+            .and_then(|value| value.as_str())
+            .and_then(|value| Uuid::parse_str(value).ok())
+    };
+
+    let definition_id = id(definition, "environmentvariabledefinitionid").ok_or_else(|| {
+        format!("Environment variable '{schema_name}' has no environmentvariabledefinitionid")
+    })?;
+    // Dataverse allows a single value row per definition; solution layering can briefly leave
+    // more, in which case the first one returned is used.
+    let value_row = definition
+        .get(VALUE_NAVIGATION)
+        .and_then(|rows| rows.as_array())
+        .and_then(|rows| rows.first());
+
+    Ok(EnvironmentVariable {
+        definition_id,
+        schema_name: text(definition, "schemaname").unwrap_or_else(|| schema_name.to_string()),
+        display_name: text(definition, "displayname"),
+        variable_type: EnvironmentVariableType::from_code(
+            definition
+                .get("type")
+                .and_then(|value| value.as_i64())
+                .unwrap_or(100000000),
+        ),
+        default_value: text(definition, "defaultvalue"),
+        value_id: value_row.and_then(|row| id(row, "environmentvariablevalueid")),
+        value: value_row.and_then(|row| text(row, "value")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{EnvironmentVariableType, parse_environment_variable};
+
+    #[test]
+    fn joins_definition_and_value() {
+        let variable = parse_environment_variable(
+            &json!({
+                "value": [{
+                    "environmentvariabledefinitionid": "11111111-1111-1111-1111-111111111111",
+                    "schemaname": "contoso_ApiUrl",
+                    "displayname": "API URL",
+                    "type": 100000000,
+                    "defaultvalue": "https://default.contoso.com",
+                    "environmentvariabledefinition_environmentvariablevalue": [{
+                        "environmentvariablevalueid": "22222222-2222-2222-2222-222222222222",
+                        "value": "https://test.contoso.com"
+                    }]
+                }]
+            }),
+            "contoso_ApiUrl",
+        )
+        .expect("should parse");
+        assert_eq!(variable.variable_type, EnvironmentVariableType::String);
+        assert_eq!(variable.effective_value(), Some("https://test.contoso.com"));
+        assert!(variable.value_id.is_some());
+
+        let variable = parse_environment_variable(
+            &json!({
+                "value": [{
+                    "environmentvariabledefinitionid": "11111111-1111-1111-1111-111111111111",
+                    "schemaname": "contoso_Enabled",
+                    "type": 100000002,
+                    "defaultvalue": "yes",
+                    "environmentvariabledefinition_environmentvariablevalue": []
+                }]
+            }),
+            "contoso_Enabled",
+        )
+        .expect("should parse");
+        assert_eq!(variable.variable_type, EnvironmentVariableType::Boolean);
+        assert_eq!(variable.value_id, None);
+        assert_eq!(variable.effective_value(), Some("yes"));
+
+        let missing = parse_environment_variable(&json!({ "value": [] }), "contoso_Missing")
+            .expect_err("should fail");
+        assert!(missing.contains("contoso_Missing"));
+    }
+}
@@ -0,0 +1,213 @@
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde_json::Value;
+use uuid::Uuid;
+
+/// Headers that make Dataverse run a request as another user.
+const IMPERSONATION_HEADERS: [&str; 2] = ["CallerObjectId", "MSCRMCallerID"];
+
+/// A security role, as assigned to a user or team.
+#[derive(Debug, Clone)]
+pub struct SecurityRole {
+    /// Role ID.
+    pub id: Uuid,
+    /// Role display name.
+    pub name: String,
+}
+
+/// Identity of the caller as reported by Dataverse `WhoAmI`.
+#[derive(Debug, Clone)]
+pub struct ExecutionContext {
+    /// Calling user (`systemuser`) ID.
+    pub user_id: Uuid,
+    /// Business unit the calling user belongs to.
+    pub business_unit_id: Uuid,
+    /// Organization ID.
+    pub organization_id: Uuid,
+    /// Security roles assigned directly to the calling user, when they have been loaded.
+    pub roles: Option<Vec<SecurityRole>>,
+}
+
+impl ExecutionContext {
+    /// True when the user's loaded roles include `role_name`, compared case-insensitively.
+    /// Returns false when roles have not been loaded.
+    pub fn has_role(&self, role_name: &str) -> bool {
+        self.roles.as_ref().is_some_and(|roles| {
+            roles
+                .iter()
+                .any(|role| role.name.eq_ignore_ascii_case(role_name))
+        })
+    }
+}
+
+/// Parse a `WhoAmI` function response.
+pub(crate) fn parse_who_am_i(json: &Value) -> Result<ExecutionContext, String> {
+    let guid = |key: &str| {
+        json.get(key)
+            .and_then(|value| value.as_str())
+            .and_then(|value| Uuid::parse_str(value).ok())
+            .ok_or_else(|| format!("WhoAmI response missing {key}"))
+    };
+
+    Ok(ExecutionContext {
+        user_id: guid("UserId")?,
+        business_unit_id: guid("BusinessUnitId")?,
+        organization_id: guid("OrganizationId")?,
+        roles: None,
+    })
+}
+
+/// Parse a collection of `role` rows, such as a user's `systemuserroles_association`.
+pub(crate) fn parse_security_roles(json: &Value) -> Result<Vec<SecurityRole>, String> {
+    let rows = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    Ok(rows
+        .iter()
+        .filter_map(|row| {
+            let id = row
+                .get("roleid")
+                .and_then(|value| value.as_str())
+                .and_then(|value| Uuid::parse_str(value).ok())?;
+            let name = row
+                .get("name")
+                .and_then(|value| value.as_str())
+                .unwrap_or_default()
+                .to_string();
+            Some(SecurityRole { id, name })
+        })
+        .collect())
+}
+
+/// Extract the Azure AD object ID from a `systemusers` query filtered by UPN.
+pub(crate) fn parse_caller_object_id(json: &Value, upn: &str) -> Result<Uuid, String> {
+    let user = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?
+        .first()
+        .ok_or_else(|| format!("No Dataverse user found with UPN {upn}"))?;
+
+    user.get("azureactivedirectoryobjectid")
+        .and_then(|value| value.as_str())
+        .and_then(|value| Uuid::parse_str(value).ok())
+        .ok_or_else(|| format!("Dataverse user {upn} has no Azure AD object ID"))
+}
+
+/// Send `CallerObjectId` for the impersonated `caller`, or, for a request that must run as the
+/// signed-in identity, remove every impersonation header, including ones from default headers and
+/// middleware.
+pub(crate) fn apply_impersonation(
+    headers: &mut HeaderMap,
+    caller: Option<Uuid>,
+    impersonate: bool,
+) {
+    if !impersonate {
+        for name in IMPERSONATION_HEADERS {
+            headers.remove(name);
+        }
+    } else if let Some(caller) = caller
+        && let Ok(value) = HeaderValue::from_str(&caller.as_hyphenated().to_string())
+    {
+        headers.insert(IMPERSONATION_HEADERS[0], value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        apply_impersonation, parse_caller_object_id, parse_security_roles, parse_who_am_i,
+    };
+
+    #[test]
+    fn impersonation_headers_are_set_or_removed() {
+        let caller = Uuid::parse_str("55555555-5555-5555-5555-555555555555").expect("uuid");
+
+        let mut headers = HeaderMap::new();
+        apply_impersonation(&mut headers, Some(caller), true);
+        assert_eq!(
+            headers.get("callerobjectid"),
+            Some(&HeaderValue::from_static(
+                "55555555-5555-5555-5555-555555555555"
+            ))
+        );
+
+        headers.insert("MSCRMCallerID", HeaderValue::from_static("other"));
+        headers.insert("Accept", HeaderValue::from_static("application/json"));
+        apply_impersonation(&mut headers, Some(caller), false);
+        assert!(!headers.contains_key("CallerObjectId"));
+        assert!(!headers.contains_key("MSCRMCallerID"));
+        assert!(headers.contains_key("Accept"));
+
+        let mut headers = HeaderMap::new();
+        apply_impersonation(&mut headers, None, true);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn parses_who_am_i_identifiers() {
+        let context = parse_who_am_i(&json!({
+            "BusinessUnitId": "11111111-1111-1111-1111-111111111111",
+            "UserId": "22222222-2222-2222-2222-222222222222",
+            "OrganizationId": "33333333-3333-3333-3333-333333333333"
+        }))
+        .expect("should parse");
+
+        assert_eq!(
+            context.user_id.to_string(),
+            "22222222-2222-2222-2222-222222222222"
+        );
+        assert!(context.roles.is_none());
+        assert!(!context.has_role("System Administrator"));
+    }
+
+    #[test]
+    fn role_check_is_case_insensitive() {
+        let mut context = parse_who_am_i(&json!({
+            "BusinessUnitId": "11111111-1111-1111-1111-111111111111",
+            "UserId": "22222222-2222-2222-2222-222222222222",
+            "OrganizationId": "33333333-3333-3333-3333-333333333333"
+        }))
+        .expect("should parse");
+        context.roles = Some(
+            parse_security_roles(&json!({
+                "value": [
+                    { "roleid": "44444444-4444-4444-4444-444444444444", "name": "System Administrator" }
+                ]
+            }))
+            .expect("should parse roles"),
+        );
+
+        assert!(context.has_role("system administrator"));
+        assert!(!context.has_role("Salesperson"));
+    }
+
+    #[test]
+    fn caller_object_id_requires_a_matching_user_with_an_object_id() {
+        let caller = parse_caller_object_id(
+            &json!({
+                "value": [{
+                    "systemuserid": "22222222-2222-2222-2222-222222222222",
+                    "azureactivedirectoryobjectid": "55555555-5555-5555-5555-555555555555"
+                }]
+            }),
+            "ada@contoso.com",
+        )
+        .expect("should parse");
+
+        assert_eq!(caller.to_string(), "55555555-5555-5555-5555-555555555555");
+        assert!(parse_caller_object_id(&json!({ "value": [] }), "ada@contoso.com").is_err());
+        assert!(
+            parse_caller_object_id(
+                &json!({ "value": [{ "azureactivedirectoryobjectid": null }] }),
+                "app@contoso.com"
+            )
+            .is_err()
+        );
+    }
+}
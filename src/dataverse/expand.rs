@@ -0,0 +1,228 @@
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+/// Collection-valued navigation property of a table, from its one-to-many and many-to-many
+/// relationships, such as `contact_customer_accounts` on `account`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectionNavigation {
+    /// Navigation property used in `$expand`.
+    pub navigation_property: String,
+    /// Logical name of the table the related rows belong to.
+    pub related_entity: String,
+}
+
+/// Read collection-valued navigation properties from a `OneToManyRelationships` collection.
+pub(crate) fn parse_one_to_many_navigations(
+    json: &Value,
+) -> Result<Vec<CollectionNavigation>, String> {
+    Ok(relationship_items(json)?
+        .iter()
+        .filter_map(|item| {
+            let text = |name: &str| item.get(name).and_then(|value| value.as_str());
+            Some(CollectionNavigation {
+                navigation_property: text("ReferencedEntityNavigationPropertyName")?.to_string(),
+                related_entity: text("ReferencingEntity")?.to_ascii_lowercase(),
+            })
+        })
+        .collect())
+}
+
+/// Read the navigation properties of `logical_name` from a `ManyToManyRelationships` collection.
+/// A self-referencing relationship has one navigation property per side, and both lead back to
+/// the same table.
+pub(crate) fn parse_many_to_many_navigations(
+    json: &Value,
+    logical_name: &str,
+) -> Result<Vec<CollectionNavigation>, String> {
+    let mut navigations = Vec::new();
+    for item in relationship_items(json)? {
+        let text = |name: &str| item.get(name).and_then(|value| value.as_str());
+        let sides = [
+            (
+                "Entity1LogicalName",
+                "Entity1NavigationPropertyName",
+                "Entity2LogicalName",
+            ),
+            (
+                "Entity2LogicalName",
+                "Entity2NavigationPropertyName",
+                "Entity1LogicalName",
+            ),
+        ];
+        for (own, navigation, related) in sides {
+            if let (Some(own), Some(navigation), Some(related)) =
+                (text(own), text(navigation), text(related))
+                && own.eq_ignore_ascii_case(logical_name)
+            {
+                navigations.push(CollectionNavigation {
+                    navigation_property: navigation.to_string(),
+                    related_entity: related.to_ascii_lowercase(),
+                });
+            }
+        }
+    }
+    Ok(navigations)
+}
+
+fn relationship_items(json: &Value) -> Result<&Vec<Value>, String> {
+    json.get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())
+}
+
+/// Find the collection navigation property named `navigation_property`.
+pub(crate) fn find_collection_navigation<'a>(
+    navigations: &'a [CollectionNavigation],
+    navigation_property: &str,
+) -> Option<&'a CollectionNavigation> {
+    navigations.iter().find(|navigation| {
+        navigation
+            .navigation_property
+            .eq_ignore_ascii_case(navigation_property)
+    })
+}
+
+/// Names of the expanded collections in a row collection response: keys whose value is an array
+/// of rows, or that carry an `@odata.nextLink` because Dataverse truncated them.
+pub(crate) fn expanded_collection_properties(json: &Value) -> Vec<String> {
+    let mut properties = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|record| record.as_object())
+        .flat_map(|record| {
+            record.iter().filter_map(|(key, value)| {
+                if let Some(property) = key.strip_suffix("@odata.nextLink") {
+                    return Some(property.to_string());
+                }
+                let is_rows = value
+                    .as_array()
+                    .is_some_and(|rows| rows.iter().all(Value::is_object));
+                (!key.contains('@') && is_rows).then(|| key.clone())
+            })
+        })
+        .collect::<Vec<_>>();
+    properties.sort_unstable();
+    properties.dedup();
+    properties
+}
+
+/// Primary id of a record in a row collection response.
+pub(crate) fn record_id(record: &Value, primary_id_attribute: &str) -> Option<Uuid> {
+    record
+        .get(primary_id_attribute)
+        .and_then(|value| value.as_str())
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// The `@odata.nextLink` of an expanded collection in a record, when Dataverse truncated it.
+pub(crate) fn expanded_next_link(record: &Value, navigation_property: &str) -> Option<String> {
+    record
+        .get(format!("{navigation_property}@odata.nextLink"))
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+}
+
+/// The rows of one expanded collection in a record, shaped as a row collection response so the
+/// entity parser can read them.
+pub(crate) fn expanded_rows(record: &Value, navigation_property: &str) -> Value {
+    let rows = record
+        .get(navigation_property)
+        .and_then(|value| value.as_array())
+        .cloned()
+        .unwrap_or_default();
+    let mut response = Map::new();
+    response.insert("value".to_string(), Value::Array(rows));
+    Value::Object(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        CollectionNavigation, expanded_collection_properties, expanded_next_link, expanded_rows,
+        find_collection_navigation, parse_many_to_many_navigations, parse_one_to_many_navigations,
+        record_id,
+    };
+
+    #[test]
+    fn reads_collection_navigations_from_relationships() {
+        let one_to_many = json!({"value": [{
+            "ReferencedEntityNavigationPropertyName": "contact_customer_accounts",
+            "ReferencingEntity": "contact"
+        }]});
+        let many_to_many = json!({"value": [{
+            "Entity1LogicalName": "account",
+            "Entity1NavigationPropertyName": "accountleads_association",
+            "Entity2LogicalName": "lead",
+            "Entity2NavigationPropertyName": "accountleads_association"
+        }]});
+
+        let mut navigations = parse_one_to_many_navigations(&one_to_many).expect("should parse");
+        navigations.extend(
+            parse_many_to_many_navigations(&many_to_many, "account").expect("should parse"),
+        );
+
+        assert_eq!(
+            navigations,
+            vec![
+                CollectionNavigation {
+                    navigation_property: "contact_customer_accounts".to_string(),
+                    related_entity: "contact".to_string(),
+                },
+                CollectionNavigation {
+                    navigation_property: "accountleads_association".to_string(),
+                    related_entity: "lead".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            find_collection_navigation(&navigations, "Contact_Customer_Accounts")
+                .map(|navigation| navigation.related_entity.as_str()),
+            Some("contact")
+        );
+    }
+
+    #[test]
+    fn finds_expanded_collections_and_their_rows() {
+        let json = json!({"value": [
+            {
+                "accountid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                "contact_customer_accounts": [{"contactid": "bbbbbbbb-bbbb-cccc-dddd-eeeeeeeeeeee"}],
+                "contact_customer_accounts@odata.nextLink": "https://example.crm.dynamics.com/next",
+                "name@OData.Community.Display.V1.FormattedValue": "Contoso"
+            },
+            {
+                "accountid": "cccccccc-bbbb-cccc-dddd-eeeeeeeeeeee",
+                "Account_Tasks": [],
+                "multiselect": "1,2"
+            }
+        ]});
+
+        assert_eq!(
+            expanded_collection_properties(&json),
+            vec!["Account_Tasks", "contact_customer_accounts"]
+        );
+        assert_eq!(
+            expanded_rows(&json["value"][0], "contact_customer_accounts")["value"]
+                .as_array()
+                .map(Vec::len),
+            Some(1)
+        );
+        assert_eq!(
+            expanded_rows(&json["value"][1], "missing"),
+            json!({"value": []})
+        );
+        assert_eq!(
+            record_id(&json["value"][1], "accountid").map(|id| id.to_string()),
+            Some("cccccccc-bbbb-cccc-dddd-eeeeeeeeeeee".to_string())
+        );
+        assert_eq!(
+            expanded_next_link(&json["value"][0], "contact_customer_accounts").as_deref(),
+            Some("https://example.crm.dynamics.com/next")
+        );
+        assert_eq!(expanded_next_link(&json["value"][1], "Account_Tasks"), None);
+    }
+}
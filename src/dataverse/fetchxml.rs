@@ -1,5 +1,149 @@
-/// Add paging attributes to a FetchXML query.
-pub(crate) fn apply_paging(
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::dataverse::schema::parse_attributes;
+
+/// Query performance and data source options set as `<fetch>` attributes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchOptions {
+    /// Set `latematerialize="true"`, fetching linked data only for the rows that are returned.
+    pub late_materialize: bool,
+    /// Set `useraworderby="true"`, sorting choice columns by integer value instead of label.
+    pub use_raw_order_by: bool,
+    /// Set `no-lock="true"`, the legacy hint to read without shared locks.
+    pub no_lock: bool,
+    /// Send the query once, exactly as written, instead of following the paging cookie. The
+    /// query's own `page`, `count`, and `paging-cookie` attributes are left untouched.
+    pub no_auto_paging: bool,
+    /// Set `datasource="retained"`, reading rows moved to long term retention instead of active
+    /// rows.
+    pub retained_data: bool,
+    /// Set `datasource="bin"`, reading deleted rows held in the recycle bin instead of active
+    /// rows.
+    pub deleted_records: bool,
+    /// SQL query hints added to the `options` attribute, after any the query already lists.
+    pub query_hints: Vec<QueryHint>,
+}
+
+/// A SQL Server query hint Dataverse accepts in the FetchXML `options` attribute. Hints change
+/// how the database plans a query; use them only when a query is measurably slow without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryHint {
+    /// `OptimizeForUnknown`: plan for average parameter values instead of the first ones seen.
+    OptimizeForUnknown,
+    /// `ForceOrder`: join tables in the order the query lists them.
+    ForceOrder,
+    /// `DisableRowGoal`: plan to read every row rather than return the first rows quickly.
+    DisableRowGoal,
+    /// `EnableOptimizerHotfixes`: use query optimizer fixes that are off by default.
+    EnableOptimizerHotfixes,
+    /// `LoopJoin`: use nested loop joins.
+    LoopJoin,
+    /// `MergeJoin`: use merge joins.
+    MergeJoin,
+    /// `HashJoin`: use hash joins.
+    HashJoin,
+    /// `NO_PERFORMANCE_SPOOL`: skip spool operators in the plan.
+    NoPerformanceSpool,
+    /// `ENABLE_HIST_AMENDMENT_FOR_ASC_KEYS`: estimate rows beyond the last statistics step for
+    /// ascending keys.
+    EnableHistAmendmentForAscKeys,
+}
+
+impl QueryHint {
+    /// The hint as written in the `options` attribute.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QueryHint::OptimizeForUnknown => "OptimizeForUnknown",
+            QueryHint::ForceOrder => "ForceOrder",
+            QueryHint::DisableRowGoal => "DisableRowGoal",
+            QueryHint::EnableOptimizerHotfixes => "EnableOptimizerHotfixes",
+            QueryHint::LoopJoin => "LoopJoin",
+            QueryHint::MergeJoin => "MergeJoin",
+            QueryHint::HashJoin => "HashJoin",
+            QueryHint::NoPerformanceSpool => "NO_PERFORMANCE_SPOOL",
+            QueryHint::EnableHistAmendmentForAscKeys => "ENABLE_HIST_AMENDMENT_FOR_ASC_KEYS",
+        }
+    }
+}
+
+impl FetchOptions {
+    /// Check that the options can be used with a query.
+    pub fn validate(&self, fetchxml: &str) -> Result<(), String> {
+        let aggregate = fetch_tag_attr_value(fetchxml, "aggregate")?.as_deref() == Some("true");
+        if self.late_materialize && aggregate {
+            return Err("latematerialize cannot be used with aggregate queries".to_string());
+        }
+        if self.use_raw_order_by && !fetchxml.contains("<order") {
+            return Err("useraworderby requires at least one <order> element".to_string());
+        }
+        if self.retained_data && self.deleted_records {
+            return Err("retained_data and deleted_records set different data sources".to_string());
+        }
+        let joins = self
+            .query_hints
+            .iter()
+            .filter(|hint| {
+                matches!(
+                    hint,
+                    QueryHint::LoopJoin | QueryHint::MergeJoin | QueryHint::HashJoin
+                )
+            })
+            .collect::<HashSet<_>>();
+        if joins.len() > 1 {
+            return Err("query_hints can request only one join type".to_string());
+        }
+        Ok(())
+    }
+
+    /// Validate the options and set the matching `<fetch>` attributes. Options left `false` do not
+    /// change attributes the query already has. `no_auto_paging` is not an attribute and is
+    /// ignored here.
+    pub fn apply(&self, fetchxml: &str) -> Result<String, String> {
+        self.validate(fetchxml)?;
+
+        let mut updated = fetchxml.to_string();
+        for (enabled, name) in [
+            (self.late_materialize, "latematerialize"),
+            (self.use_raw_order_by, "useraworderby"),
+            (self.no_lock, "no-lock"),
+        ] {
+            if enabled {
+                updated = upsert_fetch_attr(&updated, name, "true")?;
+            }
+        }
+        if self.retained_data {
+            updated = upsert_fetch_attr(&updated, "datasource", "retained")?;
+        }
+        if self.deleted_records {
+            updated = upsert_fetch_attr(&updated, "datasource", "bin")?;
+        }
+        if !self.query_hints.is_empty() {
+            let mut hints: Vec<String> = fetch_tag_attr_value(&updated, "options")?
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|hint| !hint.is_empty())
+                .map(str::to_string)
+                .collect();
+            for hint in &self.query_hints {
+                if !hints
+                    .iter()
+                    .any(|existing| existing.eq_ignore_ascii_case(hint.as_str()))
+                {
+                    hints.push(hint.as_str().to_string());
+                }
+            }
+            updated = upsert_fetch_attr(&updated, "options", &hints.join(","))?;
+        }
+        Ok(updated)
+    }
+}
+
+/// Set the `page` attribute and, when given, the `paging-cookie` attribute of a FetchXML query.
+/// The cookie is the decoded value from `FetchXmlPage::paging_cookie`; it is XML-escaped here.
+pub fn apply_paging(
     fetchxml: &str,
     page: i32,
     paging_cookie: Option<&str>,
@@ -12,6 +156,51 @@ pub(crate) fn apply_paging(
     Ok(updated)
 }
 
+/// Position of a FetchXML page: the page number and the paging cookie of the page before it.
+/// Tokens serialize, so a long export can store its position after each page and resume there
+/// after a restart instead of starting again from page 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageToken {
+    /// Page number to request, starting at 1.
+    pub page: i32,
+    /// Decoded paging cookie of the previous page, when Dataverse sent one.
+    pub paging_cookie: Option<String>,
+}
+
+impl PageToken {
+    /// Token for the first page of a query.
+    pub fn first() -> Self {
+        Self {
+            page: 1,
+            paging_cookie: None,
+        }
+    }
+
+    /// Set this token's `page` and `paging-cookie` attributes on a FetchXML query.
+    pub fn apply(&self, fetchxml: &str) -> Result<String, String> {
+        apply_paging(fetchxml, self.page, self.paging_cookie.as_deref())
+    }
+}
+
+/// Token for the page after the one `fetchxml` requested, or `None` when Dataverse reported no
+/// more records.
+pub(crate) fn next_page_token(
+    fetchxml: &str,
+    more_records: bool,
+    paging_cookie: Option<String>,
+) -> Result<Option<PageToken>, String> {
+    if !more_records {
+        return Ok(None);
+    }
+    let page = fetch_tag_attr_value(fetchxml, "page")?
+        .and_then(|page| page.parse::<i32>().ok())
+        .unwrap_or(1);
+    Ok(Some(PageToken {
+        page: page + 1,
+        paging_cookie,
+    }))
+}
+
 /// Ensure aggregate queries include a count page size.
 pub(crate) fn ensure_aggregate_page_size(
     fetchxml: &str,
@@ -42,6 +231,40 @@ pub(crate) fn fetch_tag_has_attr(fetchxml: &str, name: &str) -> Result<bool, Str
     Ok(tag.contains(&format!("{}=", name)))
 }
 
+/// Read the value of a `<fetch>` tag attribute, when present.
+pub(crate) fn fetch_tag_attr_value(fetchxml: &str, name: &str) -> Result<Option<String>, String> {
+    let fetch_start = fetchxml
+        .find("<fetch")
+        .ok_or_else(|| "FetchXML must start with a <fetch> element".to_string())?;
+    let tag_end = fetchxml[fetch_start..]
+        .find('>')
+        .ok_or_else(|| "FetchXML <fetch> element is not closed".to_string())?
+        + fetch_start;
+
+    let tag = &fetchxml[fetch_start..=tag_end];
+    let attr_key = format!("{}=", name);
+    let Some(attr_index) = tag.find(&attr_key) else {
+        return Ok(None);
+    };
+
+    let quote_index = attr_index + attr_key.len();
+    let quote = tag
+        .as_bytes()
+        .get(quote_index)
+        .ok_or_else(|| format!("Invalid fetch attribute '{}'", name))?;
+    if *quote != b'"' && *quote != b'\'' {
+        return Err(format!("Invalid fetch attribute '{}'", name));
+    }
+    let quote_char = *quote as char;
+    let value_start = quote_index + 1;
+    let value_end = tag[value_start..]
+        .find(quote_char)
+        .ok_or_else(|| format!("Invalid fetch attribute '{}'", name))?
+        + value_start;
+
+    Ok(Some(tag[value_start..value_end].to_string()))
+}
+
 /// Insert or replace a `<fetch>` tag attribute.
 fn upsert_fetch_attr(fetchxml: &str, name: &str, value: &str) -> Result<String, String> {
     let fetch_start = fetchxml
@@ -88,8 +311,299 @@ fn upsert_fetch_attr(fetchxml: &str, name: &str, value: &str) -> Result<String,
     Ok(inserted)
 }
 
+/// Split a query whose root-entity `in` conditions list more than `max_values` values into
+/// queries that each list at most `max_values`. Run one after another, the parts match the same
+/// rows as the query, but not in one sorted order and not under one row limit, so queries with
+/// `top`, `count`, `distinct`, `aggregate`, or an `<order>` are never split.
+///
+/// Returns `None` when no condition needs splitting or the query cannot be split. Conditions
+/// inside `<link-entity>` are left alone because splitting them would change which linked rows
+/// outer joins return.
+pub(crate) fn split_in_conditions(
+    fetchxml: &str,
+    max_values: usize,
+) -> Result<Option<Vec<String>>, String> {
+    if max_values == 0 || !can_split(fetchxml)? {
+        return Ok(None);
+    }
+    let Some((body_start, body_end)) = find_oversized_in_condition(fetchxml, max_values)? else {
+        return Ok(None);
+    };
+
+    let values = value_elements(&fetchxml[body_start..body_end]);
+    let mut parts = Vec::new();
+    for chunk in values.chunks(max_values) {
+        let part = format!(
+            "{}{}{}",
+            &fetchxml[..body_start],
+            chunk.concat(),
+            &fetchxml[body_end..]
+        );
+        // Another condition may also be oversized; splitting each part again keeps every
+        // combination of chunks.
+        match split_in_conditions(&part, max_values)? {
+            Some(nested) => parts.extend(nested),
+            None => parts.push(part),
+        }
+    }
+    Ok(Some(parts))
+}
+
+/// Whether the parts of a split query return the rows the query would: no row limit, ordering,
+/// distinct rows, or aggregation that only holds within a part.
+fn can_split(fetchxml: &str) -> Result<bool, String> {
+    let fetch_start = fetchxml
+        .find("<fetch")
+        .ok_or_else(|| "FetchXML must start with a <fetch> element".to_string())?;
+    let tag_end = fetchxml[fetch_start..]
+        .find('>')
+        .ok_or_else(|| "FetchXML <fetch> element is not closed".to_string())?
+        + fetch_start;
+    let tag = fetchxml[fetch_start + "<fetch".len()..tag_end].trim_end_matches('/');
+    for (name, value) in parse_attributes(tag)? {
+        match name.as_str() {
+            "top" | "count" => return Ok(false),
+            "distinct" | "aggregate" if value.eq_ignore_ascii_case("true") => return Ok(false),
+            _ => {}
+        }
+    }
+    Ok(!fetchxml.contains("<order"))
+}
+
+/// Find the body of the first root-entity `in` condition with more than `max_values` values.
+fn find_oversized_in_condition(
+    fetchxml: &str,
+    max_values: usize,
+) -> Result<Option<(usize, usize)>, String> {
+    let mut link_depth = 0usize;
+    let mut position = 0;
+
+    while let Some(offset) = fetchxml[position..].find('<') {
+        let tag_start = position + offset;
+        let tag_end = fetchxml[tag_start..]
+            .find('>')
+            .ok_or_else(|| "FetchXML element is not closed".to_string())?
+            + tag_start;
+        let tag = &fetchxml[tag_start..=tag_end];
+        position = tag_end + 1;
+
+        if tag.starts_with("<link-entity") {
+            if !tag.ends_with("/>") {
+                link_depth += 1;
+            }
+        } else if tag.starts_with("</link-entity") {
+            link_depth = link_depth.saturating_sub(1);
+        } else if tag.starts_with("<condition")
+            && !tag.ends_with("/>")
+            && link_depth == 0
+            && (tag.contains("operator=\"in\"") || tag.contains("operator='in'"))
+        {
+            let body_end = fetchxml[position..]
+                .find("</condition>")
+                .ok_or_else(|| "FetchXML <condition> element is not closed".to_string())?
+                + position;
+            if value_elements(&fetchxml[position..body_end]).len() > max_values {
+                return Ok(Some((position, body_end)));
+            }
+            position = body_end;
+        }
+    }
+
+    Ok(None)
+}
+
+/// The `<value>` elements of a condition body, as written.
+fn value_elements(body: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = body[position..].find("<value") {
+        let start = position + offset;
+        let Some(end) = body[start..].find("</value>") else {
+            break;
+        };
+        let end = start + end + "</value>".len();
+        values.push(&body[start..end]);
+        position = end;
+    }
+    values
+}
+
+/// Order a query by `primary_id_attribute` when its root entity has no `<order>`, so paging
+/// returns each row once. Aggregate queries and queries that already order are left unchanged.
+pub(crate) fn ensure_primary_key_order(
+    fetchxml: &str,
+    primary_id_attribute: &str,
+) -> Result<String, String> {
+    if fetch_tag_attr_value(fetchxml, "aggregate")?.as_deref() == Some("true") {
+        return Ok(fetchxml.to_string());
+    }
+
+    let order = format!(
+        "<order attribute=\"{}\" />",
+        escape_xml_attribute(primary_id_attribute)
+    );
+    let mut link_depth = 0usize;
+    let mut entity_depth = 0usize;
+    let mut position = 0;
+
+    while let Some(offset) = fetchxml[position..].find('<') {
+        let tag_start = position + offset;
+        let tag_end = fetchxml[tag_start..]
+            .find('>')
+            .ok_or_else(|| "FetchXML element is not closed".to_string())?
+            + tag_start;
+        let tag = &fetchxml[tag_start..=tag_end];
+        position = tag_end + 1;
+
+        if tag.starts_with("<entity") && entity_depth == 0 {
+            if let Some(open_tag) = tag.strip_suffix("/>") {
+                let open_tag = open_tag.trim_end();
+                return Ok(format!(
+                    "{}{open_tag}>{order}</entity>{}",
+                    &fetchxml[..tag_start],
+                    &fetchxml[position..]
+                ));
+            }
+            entity_depth = 1;
+        } else if tag.starts_with("<link-entity") {
+            if !tag.ends_with("/>") {
+                link_depth += 1;
+            }
+        } else if tag.starts_with("</link-entity") {
+            link_depth = link_depth.saturating_sub(1);
+        } else if tag.starts_with("<order") && link_depth == 0 {
+            return Ok(fetchxml.to_string());
+        } else if tag.starts_with("</entity") && link_depth == 0 {
+            return Ok(format!(
+                "{}{order}{}",
+                &fetchxml[..tag_start],
+                &fetchxml[tag_start..]
+            ));
+        }
+    }
+
+    Err("FetchXML must contain an <entity> element".to_string())
+}
+
+/// Where the columns of a FetchXML result row come from, beyond the root table's own columns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ResultColumns {
+    /// `(prefix, table)` of each linked table. Dataverse returns its columns as `prefix.column`.
+    pub links: Vec<(String, String)>,
+    /// Each `<attribute>` element, in query order.
+    pub attributes: Vec<ResultAttribute>,
+}
+
+/// An `<attribute>` element of a FetchXML query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ResultAttribute {
+    /// Key of the value in a result row: the alias, or the column name, prefixed with
+    /// `prefix.` on a linked table.
+    pub key: String,
+    pub table: String,
+    pub column: String,
+    pub aliased: bool,
+    /// The `aggregate` function, such as `sum`.
+    pub aggregate: Option<String>,
+    pub group_by: bool,
+    /// Whether `dategrouping` groups the column by a date part, such as the year.
+    pub date_grouping: bool,
+}
+
+impl ResultAttribute {
+    /// Whether the value has the column's type. Counts, averages, and date parts are numbers
+    /// whatever the column is.
+    pub fn keeps_column_type(&self) -> bool {
+        !self.date_grouping
+            && !matches!(
+                self.aggregate.as_deref(),
+                Some("count" | "countcolumn" | "avg")
+            )
+    }
+}
+
+/// Scan the linked tables and attributes of a FetchXML query. A link-entity without an alias
+/// gets the prefix Dataverse generates: its table name and a number counting the links to that
+/// table, such as `contact1`.
+pub(crate) fn fetchxml_result_columns(fetchxml: &str) -> Result<ResultColumns, String> {
+    let mut columns = ResultColumns::default();
+    let mut link_counts: HashMap<String, usize> = HashMap::new();
+    // Table of each open entity and link-entity element, with the link prefix.
+    let mut stack: Vec<(String, Option<String>)> = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = fetchxml[position..].find('<') {
+        let tag_start = position + offset;
+        let tag_end = fetchxml[tag_start..]
+            .find('>')
+            .ok_or_else(|| "FetchXML element is not closed".to_string())?
+            + tag_start;
+        let tag = &fetchxml[tag_start + 1..tag_end];
+        position = tag_end + 1;
+
+        if let Some(closing) = tag.strip_prefix('/') {
+            if matches!(closing.trim(), "entity" | "link-entity") {
+                stack.pop();
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(|ch: char| ch.is_whitespace()).unwrap_or(tag.len());
+        let attributes = parse_attributes(&tag[name_end..])?;
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_ascii_lowercase())
+        };
+
+        match &tag[..name_end] {
+            element @ ("entity" | "link-entity") => {
+                let table = attribute("name")
+                    .ok_or_else(|| format!("FetchXML <{element}> element has no name"))?;
+                let prefix = (element == "link-entity").then(|| {
+                    attribute("alias").unwrap_or_else(|| {
+                        let count = link_counts.entry(table.clone()).or_default();
+                        *count += 1;
+                        format!("{table}{count}")
+                    })
+                });
+                if let Some(prefix) = &prefix {
+                    columns.links.push((prefix.clone(), table.clone()));
+                }
+                if !self_closing {
+                    stack.push((table, prefix));
+                }
+            }
+            "attribute" => {
+                if let (Some((table, prefix)), Some(column)) = (stack.last(), attribute("name")) {
+                    let alias = attribute("alias");
+                    columns.attributes.push(ResultAttribute {
+                        key: match (&alias, prefix) {
+                            (Some(alias), _) => alias.clone(),
+                            (None, Some(prefix)) => format!("{prefix}.{column}"),
+                            (None, None) => column.clone(),
+                        },
+                        table: table.clone(),
+                        column,
+                        aliased: alias.is_some(),
+                        aggregate: attribute("aggregate"),
+                        group_by: attribute("groupby").as_deref() == Some("true"),
+                        date_grouping: attribute("dategrouping").is_some(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(columns)
+}
+
 /// Escape XML attribute values for FetchXML.
-fn escape_xml_attribute(value: &str) -> String {
+pub(crate) fn escape_xml_attribute(value: &str) -> String {
     value
         .replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -100,7 +614,40 @@ fn escape_xml_attribute(value: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{apply_paging, ensure_aggregate_page_size, fetch_tag_has_attr};
+    use super::{
+        FetchOptions, PageToken, QueryHint, apply_paging, ensure_aggregate_page_size,
+        ensure_primary_key_order, fetch_tag_attr_value, fetch_tag_has_attr,
+        fetchxml_result_columns, next_page_token, split_in_conditions,
+    };
+
+    #[test]
+    fn primary_key_order_is_added_only_without_root_order() {
+        assert_eq!(
+            ensure_primary_key_order("<fetch><entity name=\"account\" /></fetch>", "accountid")
+                .expect("should order"),
+            "<fetch><entity name=\"account\"><order attribute=\"accountid\" /></entity></fetch>"
+        );
+        assert_eq!(
+            ensure_primary_key_order(
+                "<fetch><entity name=\"account\"><attribute name=\"name\" /><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\"><order attribute=\"fullname\" /></link-entity></entity></fetch>",
+                "accountid"
+            )
+            .expect("should order"),
+            "<fetch><entity name=\"account\"><attribute name=\"name\" /><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\"><order attribute=\"fullname\" /></link-entity><order attribute=\"accountid\" /></entity></fetch>"
+        );
+
+        let ordered =
+            "<fetch><entity name=\"account\"><order attribute=\"name\" /></entity></fetch>";
+        assert_eq!(
+            ensure_primary_key_order(ordered, "accountid").expect("unchanged"),
+            ordered
+        );
+        let aggregate = "<fetch aggregate=\"true\"><entity name=\"account\"><attribute name=\"accountid\" aggregate=\"count\" alias=\"n\" /></entity></fetch>";
+        assert_eq!(
+            ensure_primary_key_order(aggregate, "accountid").expect("unchanged"),
+            aggregate
+        );
+    }
 
     #[test]
     fn apply_paging_inserts_page_and_cookie() {
@@ -111,6 +658,37 @@ mod tests {
         assert!(updated.contains("paging-cookie=\"a&amp;b\""));
     }
 
+    #[test]
+    fn page_tokens_advance_and_round_trip() {
+        let fetchxml = "<fetch count=\"50\" page=\"2\"><entity name=\"account\" /></fetch>";
+        let next = next_page_token(fetchxml, true, Some("<cookie page=\"2\" />".to_string()))
+            .expect("should read page")
+            .expect("should have next page");
+
+        assert_eq!(next.page, 3);
+        assert_eq!(
+            next_page_token(fetchxml, false, None).expect("should read page"),
+            None
+        );
+        assert_eq!(
+            next_page_token("<fetch><entity name=\"account\" /></fetch>", true, None)
+                .expect("should read page")
+                .map(|token| token.page),
+            Some(2)
+        );
+
+        let stored = serde_json::to_string(&next).expect("should serialize");
+        let resumed: PageToken = serde_json::from_str(&stored).expect("should deserialize");
+        assert_eq!(resumed, next);
+        assert!(
+            resumed
+                .apply(fetchxml)
+                .expect("should apply")
+                .contains("page=\"3\"")
+        );
+        assert_eq!(PageToken::first().page, 1);
+    }
+
     #[test]
     fn ensure_aggregate_page_size_only_changes_aggregate_fetches() {
         let aggregate = ensure_aggregate_page_size(
@@ -135,4 +713,221 @@ mod tests {
         assert!(fetch_tag_has_attr(fetchxml, "page").expect("should parse"));
         assert!(!fetch_tag_has_attr(fetchxml, "count").expect("should parse"));
     }
+
+    #[test]
+    fn fetch_tag_attr_value_reads_quoted_values() {
+        let fetchxml = "<fetch top='50' mapping=\"logical\"><entity name=\"account\" /></fetch>";
+
+        assert_eq!(
+            fetch_tag_attr_value(fetchxml, "top").expect("should parse"),
+            Some("50".to_string())
+        );
+        assert_eq!(
+            fetch_tag_attr_value(fetchxml, "mapping").expect("should parse"),
+            Some("logical".to_string())
+        );
+        assert_eq!(fetch_tag_attr_value(fetchxml, "count").expect("should parse"), None);
+    }
+
+    #[test]
+    fn fetch_options_set_attributes_and_reject_unsupported_combinations() {
+        let fetchxml = "<fetch no-lock=\"false\"><entity name=\"account\"><order attribute=\"statuscode\" /></entity></fetch>";
+        let options = FetchOptions {
+            late_materialize: true,
+            use_raw_order_by: true,
+            no_lock: true,
+            no_auto_paging: true,
+            retained_data: true,
+            deleted_records: false,
+            query_hints: Vec::new(),
+        };
+
+        let updated = options.apply(fetchxml).expect("should apply");
+        assert!(updated.contains("latematerialize=\"true\""));
+        assert!(updated.contains("useraworderby=\"true\""));
+        assert!(updated.contains("no-lock=\"true\""));
+        assert!(updated.contains("datasource=\"retained\""));
+        assert_eq!(FetchOptions::default().apply(fetchxml).expect("should apply"), fetchxml);
+
+        let aggregate = "<fetch aggregate=\"true\"><entity name=\"account\" /></fetch>";
+        let late = FetchOptions {
+            late_materialize: true,
+            ..FetchOptions::default()
+        };
+        assert!(late.apply(aggregate).is_err());
+
+        let deleted = FetchOptions {
+            deleted_records: true,
+            ..FetchOptions::default()
+        };
+        assert!(
+            deleted
+                .apply(fetchxml)
+                .expect("should apply")
+                .contains("datasource=\"bin\"")
+        );
+        let both = FetchOptions {
+            retained_data: true,
+            ..deleted
+        };
+        assert!(both.apply(fetchxml).is_err());
+
+        let raw_order = FetchOptions {
+            use_raw_order_by: true,
+            ..FetchOptions::default()
+        };
+        assert!(raw_order.apply("<fetch><entity name=\"account\" /></fetch>").is_err());
+    }
+
+    #[test]
+    fn query_hints_join_the_options_attribute() {
+        let hinted = FetchOptions {
+            query_hints: vec![QueryHint::OptimizeForUnknown, QueryHint::DisableRowGoal],
+            ..FetchOptions::default()
+        };
+
+        assert_eq!(
+            hinted
+                .apply("<fetch options=\"DisableRowGoal\"><entity name=\"account\" /></fetch>")
+                .expect("should apply"),
+            "<fetch options=\"DisableRowGoal,OptimizeForUnknown\"><entity name=\"account\" /></fetch>"
+        );
+
+        let joins = FetchOptions {
+            query_hints: vec![QueryHint::LoopJoin, QueryHint::HashJoin],
+            ..FetchOptions::default()
+        };
+        assert_eq!(
+            joins
+                .apply("<fetch><entity name=\"account\" /></fetch>")
+                .expect_err("conflicting joins"),
+            "query_hints can request only one join type"
+        );
+    }
+
+    #[test]
+    fn split_in_conditions_chunks_root_entity_value_lists() {
+        let fetchxml = concat!(
+            "<fetch><entity name=\"account\">",
+            "<filter><condition attribute=\"accountnumber\" operator=\"in\">",
+            "<value>A</value><value>B</value><value>C</value>",
+            "</condition>",
+            "<condition attribute=\"statecode\" operator=\"eq\" value=\"0\" /></filter>",
+            "</entity></fetch>"
+        );
+
+        let parts = split_in_conditions(fetchxml, 2)
+            .expect("should split")
+            .expect("should need splitting");
+
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].contains("<value>A</value><value>B</value></condition>"));
+        assert!(parts[1].contains("operator=\"in\"><value>C</value></condition>"));
+        assert!(parts.iter().all(|part| part.contains("statecode")));
+        assert_eq!(split_in_conditions(fetchxml, 3).expect("should parse"), None);
+        assert_eq!(split_in_conditions(fetchxml, 0).expect("should parse"), None);
+    }
+
+    #[test]
+    fn split_in_conditions_leaves_limited_ordered_and_aggregate_queries_whole() {
+        let condition = concat!(
+            "<filter><condition attribute=\"accountnumber\" operator=\"in\">",
+            "<value>A</value><value>B</value><value>C</value>",
+            "</condition></filter>"
+        );
+        for fetch in [
+            "<fetch top=\"10\">",
+            "<fetch count='50'>",
+            "<fetch distinct=\"true\">",
+            "<fetch aggregate='true'>",
+        ] {
+            let fetchxml = format!("{fetch}<entity name=\"account\">{condition}</entity></fetch>");
+            assert_eq!(
+                split_in_conditions(&fetchxml, 1).expect("should parse"),
+                None,
+                "{fetch}"
+            );
+        }
+
+        let ordered = format!(
+            "<fetch><entity name=\"account\"><order attribute=\"name\" />{condition}</entity></fetch>"
+        );
+        assert_eq!(
+            split_in_conditions(&ordered, 1).expect("should parse"),
+            None
+        );
+        let not_distinct = format!(
+            "<fetch distinct=\"false\"><entity name=\"account\">{condition}</entity></fetch>"
+        );
+        assert!(
+            split_in_conditions(&not_distinct, 1)
+                .expect("should parse")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn split_in_conditions_combines_oversized_lists_and_skips_link_entities() {
+        let fetchxml = concat!(
+            "<fetch><entity name=\"contact\">",
+            "<link-entity name=\"account\" from=\"accountid\" to=\"parentcustomerid\">",
+            "<filter><condition attribute=\"name\" operator=\"in\">",
+            "<value>X</value><value>Y</value><value>Z</value>",
+            "</condition></filter></link-entity>",
+            "<filter type=\"and\">",
+            "<condition attribute=\"firstname\" operator=\"in\"><value>1</value><value>2</value></condition>",
+            "<condition attribute=\"lastname\" operator=\"in\"><value>3</value><value>4</value></condition>",
+            "</filter></entity></fetch>"
+        );
+
+        let parts = split_in_conditions(fetchxml, 1)
+            .expect("should split")
+            .expect("should need splitting");
+
+        assert_eq!(parts.len(), 4);
+        assert!(parts.iter().all(|part| part.contains("<value>X</value><value>Y</value><value>Z</value>")));
+        assert!(parts[3].contains("<value>2</value></condition>"));
+        assert!(parts[3].contains("<value>4</value></condition>"));
+    }
+
+    #[test]
+    fn result_columns_name_links_and_attributes() {
+        let columns = fetchxml_result_columns(
+            "<fetch aggregate=\"true\"><entity name=\"account\"><attribute name=\"revenue\" alias=\"total\" aggregate=\"sum\" /><attribute name=\"accountid\" alias=\"n\" aggregate=\"count\" /><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\" alias=\"PC\"><attribute name=\"birthdate\" alias=\"born\" groupby=\"true\" /></link-entity><link-entity name=\"contact\" from=\"parentcustomerid\" to=\"accountid\"><link-entity name=\"systemuser\" from=\"systemuserid\" to=\"ownerid\" /></link-entity></entity></fetch>",
+        )
+        .expect("should scan");
+
+        assert_eq!(
+            columns.links,
+            vec![
+                ("pc".to_string(), "contact".to_string()),
+                ("contact1".to_string(), "contact".to_string()),
+                ("systemuser1".to_string(), "systemuser".to_string()),
+            ]
+        );
+        assert_eq!(
+            columns
+                .attributes
+                .iter()
+                .map(|attribute| (attribute.key.as_str(), attribute.keeps_column_type()))
+                .collect::<Vec<_>>(),
+            vec![("total", true), ("n", false), ("born", true)]
+        );
+        assert_eq!(columns.attributes[2].table, "contact");
+        assert_eq!(columns.attributes[2].column, "birthdate");
+        assert!(columns.attributes[2].group_by);
+
+        let distinct = fetchxml_result_columns(
+            "<fetch distinct=\"true\"><entity name=\"account\"><attribute name=\"name\" /><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\"><attribute name=\"createdon\" alias=\"year\" groupby=\"true\" dategrouping=\"year\" /><attribute name=\"fullname\" /></link-entity></entity></fetch>",
+        )
+        .expect("should scan");
+        assert_eq!(
+            distinct
+                .attributes
+                .iter()
+                .map(|attribute| (attribute.key.as_str(), attribute.keeps_column_type()))
+                .collect::<Vec<_>>(),
+            vec![("name", true), ("year", false), ("contact1.fullname", true)]
+        );
+    }
 }
@@ -0,0 +1,187 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Chunk size used when Dataverse does not return `x-ms-chunk-size` (4 MB).
+pub(crate) const DEFAULT_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Persisted state of a chunked file column upload, used to resume after an interruption.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileUploadSession {
+    /// Entity set the row belongs to.
+    pub entity_set: String,
+    /// Row ID.
+    pub id: String,
+    /// File column logical name.
+    pub column: String,
+    /// File name sent in `x-ms-file-name`.
+    pub file_name: String,
+    /// Total file size in bytes.
+    pub file_size: u64,
+    /// Hex SHA-256 of the file content, so a changed file of the same name and size is not
+    /// resumed. Empty in sessions saved before the hash was recorded, which never match.
+    #[serde(default)]
+    pub content_sha256: String,
+    /// Upload session URL returned in the `Location` header, including the session token.
+    pub location: String,
+    /// Chunk size requested by Dataverse.
+    pub chunk_size: u64,
+    /// Bytes Dataverse has acknowledged so far.
+    pub uploaded: u64,
+}
+
+impl FileUploadSession {
+    /// True when this session was started for the same file content and target.
+    pub(crate) fn matches(
+        &self,
+        entity_set: &str,
+        id: &str,
+        column: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> bool {
+        self.entity_set == entity_set
+            && self.id.eq_ignore_ascii_case(id)
+            && self.column.eq_ignore_ascii_case(column)
+            && self.file_name == file_name
+            && self.file_size == data.len() as u64
+            && self.content_sha256 == content_sha256(data)
+    }
+}
+
+/// Hex SHA-256 of file content, recorded in `FileUploadSession::content_sha256`.
+pub(crate) fn content_sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Load a saved upload session, returning `None` when the file does not exist.
+pub(crate) fn load_upload_session(path: &Path) -> Result<Option<FileUploadSession>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&contents)
+        .map(Some)
+        .map_err(|e| format!("Failed to parse upload session: {e}"))
+}
+
+/// Persist an upload session so the upload can resume from `uploaded`.
+pub(crate) fn save_upload_session(path: &Path, session: &FileUploadSession) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let json = serde_json::to_string_pretty(session).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// Format the `Content-Range` header for the chunk starting at `start` with `length` bytes.
+pub(crate) fn content_range(start: u64, length: u64, total: u64) -> String {
+    format!("bytes {}-{}/{}", start, start + length - 1, total)
+}
+
+/// Read `MaxSizeInKB` from a `FileAttributeMetadata` response.
+pub(crate) fn parse_max_size_kb(json: &Value) -> Option<i64> {
+    json.get("MaxSizeInKB").and_then(|value| value.as_i64())
+}
+
+/// Fail fast when a file is larger than the column allows.
+pub(crate) fn check_file_size(
+    column: &str,
+    file_size: u64,
+    max_size_kb: Option<i64>,
+) -> Result<(), String> {
+    match max_size_kb {
+        Some(max_size_kb) if file_size > max_size_kb.max(0) as u64 * 1024 => Err(format!(
+            "File is {file_size} bytes but column {column} allows at most {max_size_kb} KB"
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use super::{
+        FileUploadSession, check_file_size, content_range, content_sha256, load_upload_session,
+        parse_max_size_kb, save_upload_session,
+    };
+    use crate::testsupport::unique_temp_path;
+
+    #[test]
+    fn content_range_is_inclusive() {
+        assert_eq!(content_range(0, 4, 10), "bytes 0-3/10");
+        assert_eq!(content_range(8, 2, 10), "bytes 8-9/10");
+    }
+
+    #[test]
+    fn rejects_files_over_the_column_limit() {
+        assert_eq!(
+            parse_max_size_kb(&json!({ "MaxSizeInKB": 32768 })),
+            Some(32768)
+        );
+        assert!(check_file_size("cr_document", 1024, Some(1)).is_ok());
+        assert!(check_file_size("cr_document", 1025, Some(1)).is_err());
+        assert!(check_file_size("cr_document", u64::MAX, None).is_ok());
+    }
+
+    #[test]
+    fn upload_session_round_trips_and_matches_target() {
+        let path = unique_temp_path("upload").join("session.json");
+        let session = FileUploadSession {
+            entity_set: "accounts".to_string(),
+            id: "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee".to_string(),
+            column: "cr_document".to_string(),
+            file_name: "report.pdf".to_string(),
+            file_size: 10,
+            content_sha256: content_sha256(b"0123456789"),
+            location: "https://example.crm.dynamics.com/upload?sessiontoken=abc".to_string(),
+            chunk_size: 4,
+            uploaded: 4,
+        };
+
+        save_upload_session(&path, &session).expect("should save");
+        let loaded = load_upload_session(&path)
+            .expect("should load")
+            .expect("should exist");
+
+        assert_eq!(loaded, session);
+        assert!(loaded.matches(
+            "accounts",
+            "AAAAAAAA-BBBB-CCCC-DDDD-EEEEEEEEEEEE",
+            "cr_document",
+            "report.pdf",
+            b"0123456789"
+        ));
+        assert!(!loaded.matches(
+            "accounts",
+            &session.id,
+            "cr_document",
+            "report.pdf",
+            b"01234567890"
+        ));
+        // Same name and size, different content.
+        assert!(!loaded.matches(
+            "accounts",
+            &session.id,
+            "cr_document",
+            "report.pdf",
+            b"9876543210"
+        ));
+        assert_eq!(
+            content_sha256(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+}
@@ -0,0 +1,174 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use uuid::Uuid;
+
+/// A validated Dataverse row ID.
+///
+/// Parses the forms Dataverse and its tools produce, with or without braces or hyphens and in
+/// either case, and displays the lowercase hyphenated form the Web API expects in row paths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Guid(Uuid);
+
+impl Guid {
+    /// Parse a row ID such as `{00000000-0000-0000-0000-000000000001}`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let trimmed = value.trim();
+        let unbraced = trimmed
+            .strip_prefix('{')
+            .and_then(|value| value.strip_suffix('}'))
+            .unwrap_or(trimmed);
+        // `Uuid::parse_str` also accepts URNs and braces, which are not valid in a row path.
+        if unbraced.starts_with(['{', 'u', 'U']) {
+            return Err(format!("Invalid GUID '{value}'"));
+        }
+        Uuid::parse_str(unbraced)
+            .map(Self)
+            .map_err(|e| format!("Invalid GUID '{value}': {e}"))
+    }
+
+    /// The nil GUID, `00000000-0000-0000-0000-000000000000`.
+    pub const fn nil() -> Self {
+        Self(Uuid::nil())
+    }
+
+    /// True for the nil GUID, which Dataverse never assigns to a row.
+    pub fn is_nil(&self) -> bool {
+        self.0.is_nil()
+    }
+
+    /// The underlying `Uuid`.
+    pub const fn as_uuid(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.as_hyphenated().fmt(f)
+    }
+}
+
+impl FromStr for Guid {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::parse(value)
+    }
+}
+
+impl From<Uuid> for Guid {
+    fn from(value: Uuid) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Guid> for Uuid {
+    fn from(value: Guid) -> Self {
+        value.0
+    }
+}
+
+impl Serialize for Guid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Guid {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Self::parse(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Row IDs accepted by `ServiceClient` row methods: `Guid`, `Uuid`, or a string that is checked
+/// before any request is sent.
+pub trait IntoGuid {
+    /// Convert to a `Guid`, failing for malformed strings.
+    fn into_guid(self) -> Result<Guid, String>;
+}
+
+impl IntoGuid for Guid {
+    fn into_guid(self) -> Result<Guid, String> {
+        Ok(self)
+    }
+}
+
+impl IntoGuid for Uuid {
+    fn into_guid(self) -> Result<Guid, String> {
+        Ok(Guid(self))
+    }
+}
+
+impl IntoGuid for &Uuid {
+    fn into_guid(self) -> Result<Guid, String> {
+        Ok(Guid(*self))
+    }
+}
+
+impl IntoGuid for &str {
+    fn into_guid(self) -> Result<Guid, String> {
+        Guid::parse(self)
+    }
+}
+
+impl IntoGuid for &String {
+    fn into_guid(self) -> Result<Guid, String> {
+        Guid::parse(self)
+    }
+}
+
+impl IntoGuid for String {
+    fn into_guid(self) -> Result<Guid, String> {
+        Guid::parse(&self)
+    }
+}
+
+/// Convert each of `ids`, failing at the first malformed one.
+pub(crate) fn into_uuids(
+    ids: impl IntoIterator<Item = impl IntoGuid>,
+) -> Result<Vec<Uuid>, String> {
+    ids.into_iter()
+        .map(|id| id.into_guid().map(Uuid::from))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{Guid, IntoGuid};
+
+    #[test]
+    fn parses_and_normalizes_row_ids() {
+        let expected = "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee";
+        for value in [
+            "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "{AAAAAAAA-BBBB-CCCC-DDDD-EEEEEEEEEEEE}",
+            " aaaaaaaabbbbccccddddeeeeeeeeeeee ",
+        ] {
+            assert_eq!(Guid::parse(value).expect(value).to_string(), expected);
+        }
+
+        for value in [
+            "",
+            "not-a-guid",
+            "{aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "urn:uuid:aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee'",
+        ] {
+            let error = Guid::parse(value).expect_err(value);
+            assert!(error.starts_with("Invalid GUID"), "{error}");
+        }
+
+        let uuid = Uuid::parse_str(expected).expect("uuid");
+        assert_eq!(uuid.into_guid(), Ok(Guid::from(uuid)));
+        assert_eq!(
+            serde_json::to_string(&Guid::from(uuid)).expect("serialize"),
+            format!("\"{expected}\"")
+        );
+        assert!(Guid::nil().is_nil());
+    }
+}
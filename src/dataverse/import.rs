@@ -0,0 +1,979 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::dataverse::alternatekey::KeyAttributes;
+use crate::dataverse::batch::{CreateRequest, OrganizationRequest, UpsertRequest};
+use crate::dataverse::bulk::{BulkExecutor, BulkOptions};
+use crate::dataverse::bulkresult::{BulkResult, csv_record};
+use crate::dataverse::datacopy::{LOOKUP_QUERY_CHUNK, build_key_lookup_fetchxml, key_values};
+use crate::dataverse::entity::{
+    Entity, EntityReference, Money, OptionSetValue, OptionSetValueCollection, Value,
+};
+use crate::dataverse::entityattribute::{DateTimeBehavior, EntityAttribute};
+use crate::dataverse::optionset::{OptionSetMap, option_value_by_label};
+use crate::dataverse::parse::attribute_type_key;
+use crate::dataverse::serviceclient::ServiceClient;
+
+/// Source rows converted and written per bulk run, which bounds the rows and requests held at once.
+const ROWS_PER_CHUNK: usize = 1000;
+/// Columns the error report adds to each failed source row.
+const REPORT_LINE_COLUMN: &str = "import_line";
+const REPORT_ERROR_COLUMN: &str = "import_error";
+
+/// Format of an import source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Comma-separated values with a header row, quoted as in RFC 4180.
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// How a source field becomes a column value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldConversion {
+    /// Convert the text by the column's metadata type. Choice columns accept option values or
+    /// labels.
+    Auto,
+    /// Bind a lookup to the row of `table` whose `key_attribute` equals the source value, such as
+    /// an account by `accountnumber`.
+    Lookup {
+        /// Logical name of the referenced table.
+        table: String,
+        /// Column of the referenced table that identifies the row.
+        key_attribute: String,
+    },
+}
+
+/// Maps one source field to a column of the imported table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMapping {
+    /// CSV header or JSON key of the source field.
+    pub source: String,
+    /// Logical name of the column written.
+    pub attribute: String,
+    /// How the source value is converted.
+    pub conversion: FieldConversion,
+}
+
+impl FieldMapping {
+    /// Write `source` to `attribute`, converted by the column's type.
+    pub fn new(source: impl Into<String>, attribute: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            attribute: attribute.into(),
+            conversion: FieldConversion::Auto,
+        }
+    }
+
+    /// Bind the lookup `attribute` to the row of `table` whose `key_attribute` equals `source`.
+    pub fn lookup(
+        source: impl Into<String>,
+        attribute: impl Into<String>,
+        table: impl Into<String>,
+        key_attribute: impl Into<String>,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            attribute: attribute.into(),
+            conversion: FieldConversion::Lookup {
+                table: table.into(),
+                key_attribute: key_attribute.into(),
+            },
+        }
+    }
+}
+
+/// Which table an import writes and how source fields map to its columns.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportMapping {
+    /// Logical name of the imported table.
+    pub table: String,
+    /// Source fields to write. Fields that are not mapped are ignored.
+    pub fields: Vec<FieldMapping>,
+    /// Columns of an alternate key of the table. When set, rows are upserted by that key
+    /// instead of created.
+    pub upsert_key: Vec<String>,
+}
+
+/// Options for `import_records`.
+#[derive(Debug, Clone, Default)]
+pub struct ImportOptions {
+    /// Batch size, concurrency, and retries of the writes.
+    pub bulk: BulkOptions,
+    /// Write each failed source row here, in the source format, with its line number and error
+    /// added as `import_line` and `import_error`.
+    pub error_report: Option<PathBuf>,
+    /// Write empty source values as null instead of leaving the column out of the row.
+    pub empty_as_null: bool,
+}
+
+/// Summary of an `import_records` run.
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Source rows read.
+    pub read: usize,
+    /// Rows created or upserted.
+    pub written: usize,
+    /// Outcome of every source row, indexed by its zero-based position in the source. Rows that
+    /// failed conversion, lookup resolution, or the write are `BulkStatus::Failed`; a row that
+    /// failed before it was sent has no ID and no retries.
+    pub results: Vec<BulkResult>,
+}
+
+/// Import the rows of the file at `path`. See `import_records`.
+pub async fn import_file(
+    client: &ServiceClient,
+    path: &Path,
+    format: ImportFormat,
+    mapping: &ImportMapping,
+    options: &ImportOptions,
+) -> Result<ImportReport, String> {
+    let file = File::open(path)
+        .map_err(|e| format!("Failed to open import file '{}': {e}", path.display()))?;
+    import_records(client, BufReader::new(file), format, mapping, options).await
+}
+
+/// Read CSV or JSON Lines rows from `input`, convert them with `mapping`, and create or upsert
+/// them with a `BulkExecutor`.
+///
+/// Values are converted by the column metadata of the table, and choice labels and lookups are
+/// resolved before a row is sent, so a row that cannot be converted fails on its own without a
+/// request. Rows are read and written in chunks, so only one chunk of source rows and requests is
+/// held at a time. The report still keeps a `BulkResult` for every row, and the IDs of resolved
+/// lookup key values are cached for the whole import, so those grow with the input.
+/// Fails before writing anything when the mapping names a column the table does not have.
+pub async fn import_records<R: BufRead>(
+    client: &ServiceClient,
+    input: R,
+    format: ImportFormat,
+    mapping: &ImportMapping,
+    options: &ImportOptions,
+) -> Result<ImportReport, String> {
+    let attributes = client
+        .list_entity_attributes(&mapping.table)
+        .await?
+        .into_iter()
+        .map(|attribute| (attribute.logical_name.to_ascii_lowercase(), attribute))
+        .collect::<HashMap<_, _>>();
+    check_mapping(mapping, &attributes)?;
+    let option_sets = client.list_entity_option_sets(&mapping.table).await?;
+    let executor = BulkExecutor::new(client, options.bulk.clone());
+
+    let mut source = SourceReader::new(input, format)?;
+    if let Some(headers) = source.headers() {
+        for field in &mapping.fields {
+            if !headers.iter().any(|header| header == &field.source) {
+                return Err(format!(
+                    "Source column '{}' not found in the CSV header",
+                    field.source
+                ));
+            }
+        }
+    }
+    let mut error_report = match &options.error_report {
+        Some(path) => Some(ErrorReport::create(path, format, source.headers())?),
+        None => None,
+    };
+
+    let mut lookups = LookupCache::default();
+    let mut report = ImportReport::default();
+    loop {
+        let mut rows = Vec::new();
+        while rows.len() < ROWS_PER_CHUNK
+            && let Some(row) = source.next_row()?
+        {
+            rows.push(row);
+        }
+        if rows.is_empty() {
+            break;
+        }
+        let first_index = report.read;
+        report.read += rows.len();
+        lookups.resolve(client, mapping, &source, &rows).await?;
+
+        let mut requests = Vec::new();
+        let mut sent_rows = Vec::new();
+        let mut failed = Vec::new();
+        let mut results = Vec::with_capacity(rows.len());
+        for (position, row) in rows.iter().enumerate() {
+            let request = build_entity(
+                mapping,
+                &attributes,
+                &option_sets,
+                &lookups,
+                &source,
+                row,
+                options.empty_as_null,
+            )
+            .and_then(|entity| build_request(entity, &mapping.upsert_key));
+            match request {
+                Ok(request) => {
+                    requests.push(request);
+                    sent_rows.push((first_index + position, row));
+                }
+                Err(message) => {
+                    results.push(BulkResult::failed(first_index + position, None, &message));
+                    failed.push((row, message));
+                }
+            }
+        }
+
+        let bulk_report = executor.execute(requests).await;
+        report.written += bulk_report.succeeded();
+        for item in &bulk_report.items {
+            let (index, row) = sent_rows[item.request_index];
+            if let Err(failure) = &item.outcome {
+                failed.push((row, failure.message.clone()));
+            }
+            results.push(BulkResult {
+                index,
+                ..BulkResult::from(item)
+            });
+        }
+        results.sort_unstable_by_key(|result| result.index);
+        report.results.extend(results);
+
+        if let Some(error_report) = &mut error_report {
+            failed.sort_by_key(|(row, _)| row.line);
+            for (row, message) in failed {
+                error_report.write(&source, row, &message)?;
+            }
+        }
+    }
+
+    if let Some(error_report) = &mut error_report {
+        error_report.finish()?;
+    }
+    Ok(report)
+}
+
+/// Fail on a mapped column the table does not have, or a lookup mapped as a plain value.
+fn check_mapping(
+    mapping: &ImportMapping,
+    attributes: &HashMap<String, EntityAttribute>,
+) -> Result<(), String> {
+    for field in &mapping.fields {
+        let attribute = attributes
+            .get(&field.attribute.to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "Column '{}' not found on '{}'",
+                    field.attribute, mapping.table
+                )
+            })?;
+        if field.conversion == FieldConversion::Auto && is_lookup(attribute) {
+            return Err(format!(
+                "Column '{}' is a lookup; map it with FieldMapping::lookup",
+                field.attribute
+            ));
+        }
+    }
+    for key in &mapping.upsert_key {
+        if !mapping
+            .fields
+            .iter()
+            .any(|field| field.attribute.eq_ignore_ascii_case(key))
+        {
+            return Err(format!("Upsert key column '{key}' is not mapped"));
+        }
+    }
+    Ok(())
+}
+
+fn is_lookup(attribute: &EntityAttribute) -> bool {
+    matches!(
+        attribute_type_key(Some(attribute)),
+        Some("Lookup" | "LookupType" | "Customer" | "CustomerType" | "Owner" | "OwnerType")
+    )
+}
+
+/// Convert one source row into a row of the imported table.
+fn build_entity(
+    mapping: &ImportMapping,
+    attributes: &HashMap<String, EntityAttribute>,
+    option_sets: &OptionSetMap,
+    lookups: &LookupCache,
+    source: &SourceReader<impl BufRead>,
+    row: &SourceRow,
+    empty_as_null: bool,
+) -> Result<Entity, String> {
+    if let SourceFields::Invalid(message) = &row.fields {
+        return Err(message.clone());
+    }
+    let mut entity = Entity::new(Uuid::nil(), mapping.table.clone(), None);
+    for field in &mapping.fields {
+        let attribute = field.attribute.to_ascii_lowercase();
+        let text = source.field(row, &field.source).unwrap_or_default();
+        let value = if text.trim().is_empty() {
+            if !empty_as_null {
+                continue;
+            }
+            Value::Null
+        } else {
+            match &field.conversion {
+                FieldConversion::Auto => {
+                    convert_text(&mapping.table, &text, &attributes[&attribute], option_sets)
+                        .map_err(|message| format!("{}: {message}", field.source))?
+                }
+                FieldConversion::Lookup {
+                    table,
+                    key_attribute,
+                } => Value::EntityReference(EntityReference {
+                    id: lookups.get(table, key_attribute, &text)?,
+                    logical_name: table.clone(),
+                    name: None,
+                }),
+            }
+        };
+        entity.attributes.insert(attribute, value);
+    }
+    Ok(entity)
+}
+
+fn build_request(entity: Entity, upsert_key: &[String]) -> Result<OrganizationRequest, String> {
+    if upsert_key.is_empty() {
+        return Ok(OrganizationRequest::Create(CreateRequest::new(entity)));
+    }
+    let key = upsert_key
+        .iter()
+        .map(|column| {
+            let column = column.to_ascii_lowercase();
+            match entity.attributes.get(&column) {
+                Some(value) if !matches!(value, Value::Null) => Ok((column, value.clone())),
+                _ => Err(format!("Row has no value for key column '{column}'")),
+            }
+        })
+        .collect::<Result<KeyAttributes, String>>()?;
+    Ok(OrganizationRequest::Upsert(
+        UpsertRequest::with_alternate_key(entity, key),
+    ))
+}
+
+/// Convert the text of a source value by the type of `attribute`.
+fn convert_text(
+    table: &str,
+    text: &str,
+    attribute: &EntityAttribute,
+    option_sets: &OptionSetMap,
+) -> Result<Value, String> {
+    let trimmed = text.trim();
+    let invalid = |kind: &str| format!("'{trimmed}' is not a valid {kind}");
+    let column = attribute.logical_name.as_str();
+    let option_value = |text: &str| match text.parse::<i32>() {
+        Ok(value) => Ok(value),
+        Err(_) => option_value_by_label(table, column, text, option_sets),
+    };
+
+    Ok(
+        match attribute_type_key(Some(attribute)).unwrap_or_default() {
+            "Integer" | "IntegerType" | "BigInt" | "BigIntType" => {
+                Value::Int(trimmed.parse().map_err(|_| invalid("whole number"))?)
+            }
+            "Double" | "DoubleType" => {
+                Value::Float(trimmed.parse().map_err(|_| invalid("number"))?)
+            }
+            "Decimal" | "DecimalType" => {
+                Value::Decimal(Decimal::from_str(trimmed).map_err(|_| invalid("decimal"))?)
+            }
+            "Money" | "MoneyType" => Value::Money(Money::new(
+                Decimal::from_str(trimmed).map_err(|_| invalid("currency amount"))?,
+            )),
+            "Boolean" | "BooleanType" => {
+                Value::Boolean(parse_bool(trimmed).ok_or_else(|| invalid("yes/no value"))?)
+            }
+            "DateTime" | "DateTimeType"
+                if attribute.date_time_behavior == Some(DateTimeBehavior::DateOnly) =>
+            {
+                Value::Date(
+                    parse_datetime(trimmed)
+                        .map(|value| value.date_naive())
+                        .ok_or_else(|| invalid("date"))?,
+                )
+            }
+            "DateTime" | "DateTimeType" => {
+                Value::DateTime(parse_datetime(trimmed).ok_or_else(|| invalid("date and time"))?)
+            }
+            "Uniqueidentifier" | "UniqueidentifierType" | "Guid" => {
+                Value::Guid(Uuid::parse_str(trimmed).map_err(|_| invalid("GUID"))?)
+            }
+            "Picklist" | "PicklistType" | "State" | "StateType" | "Status" | "StatusType" => {
+                Value::OptionSetValue(OptionSetValue {
+                    value: option_value(trimmed)?,
+                    name: None,
+                })
+            }
+            "MultiSelectPicklist" | "MultiSelectPicklistType" => {
+                Value::OptionSetValueCollection(OptionSetValueCollection {
+                    values: trimmed
+                        .split([',', ';'])
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(option_value)
+                        .collect::<Result<_, _>>()?,
+                })
+            }
+            _ => Value::String(text.to_string()),
+        },
+    )
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" => Some(true),
+        "false" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse an RFC 3339 timestamp, or a date and time without an offset, or a date alone, as UTC.
+fn parse_datetime(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(text)
+        .map(|value| value.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"]
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+                .map(|value| value.and_utc())
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|value| value.and_utc())
+        })
+}
+
+/// IDs of the rows each lookup key value matched, by table, key column, and lowercase key
+/// value, so a value is queried once per import.
+#[derive(Default)]
+struct LookupCache {
+    ids: HashMap<(String, String), HashMap<String, Vec<Uuid>>>,
+}
+
+impl LookupCache {
+    /// Query the lookup targets of `rows` that are not cached yet.
+    async fn resolve(
+        &mut self,
+        client: &ServiceClient,
+        mapping: &ImportMapping,
+        source: &SourceReader<impl BufRead>,
+        rows: &[SourceRow],
+    ) -> Result<(), String> {
+        for field in &mapping.fields {
+            let FieldConversion::Lookup {
+                table,
+                key_attribute,
+            } = &field.conversion
+            else {
+                continue;
+            };
+            let cached = self
+                .ids
+                .entry((
+                    table.to_ascii_lowercase(),
+                    key_attribute.to_ascii_lowercase(),
+                ))
+                .or_default();
+            let mut pending = rows
+                .iter()
+                .filter_map(|row| source.field(row, &field.source))
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty() && !cached.contains_key(&value.to_lowercase()))
+                .collect::<Vec<_>>();
+            pending.sort_unstable();
+            pending.dedup();
+            if pending.is_empty() {
+                continue;
+            }
+            for value in &pending {
+                cached.insert(value.to_lowercase(), Vec::new());
+            }
+
+            let definition = client.resolve_entity_definition(table).await?;
+            let key_attributes = [key_attribute.clone()];
+            for chunk in pending.chunks(LOOKUP_QUERY_CHUNK) {
+                let keys = chunk
+                    .iter()
+                    .map(|value| vec![value.clone()])
+                    .collect::<Vec<_>>();
+                let fetchxml =
+                    build_key_lookup_fetchxml(&definition.logical_name, &key_attributes, &keys);
+                for row in client
+                    .retrieve_multiple_fetchxml_paging(&definition.entity_set_name, &fetchxml)
+                    .await?
+                {
+                    if let Some(mut key) = key_values(&row, &key_attributes) {
+                        cached.entry(key.remove(0)).or_default().push(row.id);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, table: &str, key_attribute: &str, value: &str) -> Result<Uuid, String> {
+        let value = value.trim();
+        match self
+            .ids
+            .get(&(
+                table.to_ascii_lowercase(),
+                key_attribute.to_ascii_lowercase(),
+            ))
+            .and_then(|ids| ids.get(&value.to_lowercase()))
+            .map(Vec::as_slice)
+        {
+            Some([id]) => Ok(*id),
+            Some([_, _, ..]) => Err(format!(
+                "More than one '{table}' row has {key_attribute} '{value}'"
+            )),
+            _ => Err(format!("No '{table}' row has {key_attribute} '{value}'")),
+        }
+    }
+}
+
+/// A source row and the line it starts on.
+struct SourceRow {
+    line: usize,
+    fields: SourceFields,
+}
+
+enum SourceFields {
+    Csv(Vec<String>),
+    Json(Map<String, JsonValue>),
+    /// A row that could not be read, such as a line that is not JSON, with the reason.
+    Invalid(String),
+}
+
+/// The line a CSV record starts on, and its fields or why they could not be read.
+type CsvRecord = (usize, Result<Vec<String>, String>);
+
+/// Reads source rows one at a time.
+struct SourceReader<R> {
+    input: R,
+    format: ImportFormat,
+    line: usize,
+    headers: Option<Vec<String>>,
+}
+
+impl<R: BufRead> SourceReader<R> {
+    /// Start reading, taking the header row of a CSV source.
+    fn new(input: R, format: ImportFormat) -> Result<Self, String> {
+        let mut reader = Self {
+            input,
+            format,
+            line: 0,
+            headers: None,
+        };
+        if format == ImportFormat::Csv {
+            let (_, headers) = reader
+                .next_csv_record()?
+                .ok_or_else(|| "CSV import file has no header row".to_string())?;
+            let mut headers = headers?;
+            if let Some(first) = headers.first_mut() {
+                *first = first.trim_start_matches('\u{feff}').to_string();
+            }
+            reader.headers = Some(headers);
+        }
+        Ok(reader)
+    }
+
+    fn headers(&self) -> Option<&[String]> {
+        self.headers.as_deref()
+    }
+
+    /// Text of the field `name`, or `None` when the row does not have it. JSON arrays become
+    /// comma-separated values.
+    fn field(&self, row: &SourceRow, name: &str) -> Option<String> {
+        match &row.fields {
+            SourceFields::Csv(values) => {
+                let index = self.headers()?.iter().position(|header| header == name)?;
+                values.get(index).cloned()
+            }
+            SourceFields::Json(object) => match object.get(name)? {
+                JsonValue::Null => None,
+                JsonValue::Array(values) => {
+                    Some(values.iter().map(json_text).collect::<Vec<_>>().join(","))
+                }
+                value => Some(json_text(value)),
+            },
+            SourceFields::Invalid(_) => None,
+        }
+    }
+
+    /// Read the next row. A row that cannot be parsed is returned as `SourceFields::Invalid`, so
+    /// it fails on its own; only a failure to read the input is an error.
+    fn next_row(&mut self) -> Result<Option<SourceRow>, String> {
+        match self.format {
+            ImportFormat::Csv => Ok(self.next_csv_record()?.map(|(line, values)| SourceRow {
+                line,
+                fields: match values {
+                    Ok(values) => SourceFields::Csv(values),
+                    Err(message) => SourceFields::Invalid(message),
+                },
+            })),
+            ImportFormat::JsonLines => loop {
+                let Some(text) = self.read_line()? else {
+                    return Ok(None);
+                };
+                if text.trim().is_empty() {
+                    continue;
+                }
+                let fields = match serde_json::from_str(&text) {
+                    Ok(JsonValue::Object(object)) => SourceFields::Json(object),
+                    _ => SourceFields::Invalid(format!("Line {} is not a JSON object", self.line)),
+                };
+                return Ok(Some(SourceRow {
+                    line: self.line,
+                    fields,
+                }));
+            },
+        }
+    }
+
+    /// Read the next CSV record and the line it starts on. A record spans several lines when a
+    /// quoted field holds line breaks; one whose quote is never closed is an `Err` record.
+    /// Blank lines are skipped.
+    fn next_csv_record(&mut self) -> Result<Option<CsvRecord>, String> {
+        let mut values = Vec::new();
+        let mut value = String::new();
+        let mut in_quotes = false;
+        let mut start = None;
+        loop {
+            let Some(text) = self.read_line()? else {
+                return Ok(match start {
+                    Some(start) if in_quotes => Some((
+                        start,
+                        Err(format!(
+                            "Quoted CSV field starting on line {start} is not closed"
+                        )),
+                    )),
+                    _ => None,
+                });
+            };
+            if start.is_none() && text.trim().is_empty() {
+                continue;
+            }
+            start.get_or_insert(self.line);
+
+            let mut chars = text.chars().peekable();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '"' if in_quotes && chars.peek() == Some(&'"') => {
+                        chars.next();
+                        value.push('"');
+                    }
+                    '"' if in_quotes => in_quotes = false,
+                    '"' if value.is_empty() => in_quotes = true,
+                    ',' if !in_quotes => values.push(std::mem::take(&mut value)),
+                    _ => value.push(ch),
+                }
+            }
+            if in_quotes {
+                value.push('\n');
+                continue;
+            }
+            values.push(value);
+            return Ok(start.map(|start| (start, Ok(values))));
+        }
+    }
+
+    /// Read one line without its line break, or `None` at the end of the input.
+    fn read_line(&mut self) -> Result<Option<String>, String> {
+        let mut text = String::new();
+        let read = self
+            .input
+            .read_line(&mut text)
+            .map_err(|e| format!("Failed to read import file: {e}"))?;
+        if read == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        let trimmed = text.trim_end_matches(['\n', '\r']).len();
+        text.truncate(trimmed);
+        Ok(Some(text))
+    }
+}
+
+fn json_text(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// The file failed source rows are written to.
+struct ErrorReport {
+    output: BufWriter<File>,
+}
+
+impl ErrorReport {
+    fn create(
+        path: &Path,
+        format: ImportFormat,
+        headers: Option<&[String]>,
+    ) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create error report '{}': {e}", path.display()))?;
+        let mut report = Self {
+            output: BufWriter::new(file),
+        };
+        if format == ImportFormat::Csv {
+            let mut header = headers.unwrap_or_default().to_vec();
+            header.push(REPORT_LINE_COLUMN.to_string());
+            header.push(REPORT_ERROR_COLUMN.to_string());
+            report.write_line(&csv_record(&header))?;
+        }
+        Ok(report)
+    }
+
+    fn write<R: BufRead>(
+        &mut self,
+        source: &SourceReader<R>,
+        row: &SourceRow,
+        message: &str,
+    ) -> Result<(), String> {
+        let line = match &row.fields {
+            SourceFields::Csv(values) => {
+                let mut values = values.clone();
+                values.resize(
+                    source
+                        .headers()
+                        .map_or(0, <[String]>::len)
+                        .max(values.len()),
+                    String::new(),
+                );
+                values.push(row.line.to_string());
+                values.push(message.to_string());
+                csv_record(&values)
+            }
+            SourceFields::Json(object) => {
+                let mut object = object.clone();
+                object.insert(REPORT_LINE_COLUMN.to_string(), JsonValue::from(row.line));
+                object.insert(
+                    REPORT_ERROR_COLUMN.to_string(),
+                    JsonValue::String(message.to_string()),
+                );
+                JsonValue::Object(object).to_string()
+            }
+            // The source text is left out, so the report itself stays readable; the line
+            // number points back to it.
+            SourceFields::Invalid(_) => match source.headers() {
+                Some(headers) => {
+                    let mut values = vec![String::new(); headers.len()];
+                    values.push(row.line.to_string());
+                    values.push(message.to_string());
+                    csv_record(&values)
+                }
+                None => serde_json::json!({
+                    REPORT_LINE_COLUMN: row.line,
+                    REPORT_ERROR_COLUMN: message,
+                })
+                .to_string(),
+            },
+        };
+        self.write_line(&line)
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.output, "{line}").map_err(|e| format!("Failed to write error report: {e}"))
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        self.output
+            .flush()
+            .map_err(|e| format!("Failed to write error report: {e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{
+        FieldMapping, ImportFormat, ImportMapping, SourceFields, SourceReader, check_mapping,
+        convert_text, csv_record,
+    };
+    use crate::dataverse::entity::Value;
+    use crate::dataverse::entityattribute::EntityAttribute;
+    use crate::dataverse::optionset::parse_option_set_attributes;
+
+    fn attribute(logical_name: &str, attribute_type: &str) -> EntityAttribute {
+        serde_json::from_value(json!({
+            "LogicalName": logical_name,
+            "SchemaName": logical_name,
+            "AttributeType": attribute_type,
+            "AttributeTypeName": null,
+            "IsCustomAttribute": false,
+            "IsValidODataAttribute": true,
+            "IsValidForRead": true,
+            "IsValidForUpdate": true,
+        }))
+        .expect("attribute")
+    }
+
+    #[test]
+    fn reads_quoted_csv_fields_across_lines() {
+        let input = "\u{feff}name,description\n\n\"Contoso, Ltd\",\"Says \"\"hi\"\"\nand bye\"\nFabrikam,\n";
+        let mut reader = SourceReader::new(input.as_bytes(), ImportFormat::Csv).expect("header");
+        assert_eq!(
+            reader.headers(),
+            Some(&["name".to_string(), "description".to_string()][..])
+        );
+
+        let first = reader.next_row().expect("read").expect("row");
+        assert_eq!(first.line, 3);
+        assert_eq!(
+            reader.field(&first, "name").as_deref(),
+            Some("Contoso, Ltd")
+        );
+        assert_eq!(
+            reader.field(&first, "description").as_deref(),
+            Some("Says \"hi\"\nand bye")
+        );
+        let second = reader.next_row().expect("read").expect("row");
+        assert_eq!(second.line, 5);
+        assert_eq!(reader.field(&second, "description").as_deref(), Some(""));
+        assert!(reader.next_row().expect("read").is_none());
+
+        assert_eq!(
+            csv_record(&["Contoso, Ltd".to_string(), "plain".to_string()]),
+            "\"Contoso, Ltd\",plain"
+        );
+    }
+
+    #[test]
+    fn reads_malformed_rows_as_invalid_and_continues() {
+        let input = "{\"name\":\"Contoso\"}\n{\"name\": \n[1]\n{\"name\":\"Fabrikam\"}\n";
+        let mut reader =
+            SourceReader::new(input.as_bytes(), ImportFormat::JsonLines).expect("reader");
+        let mut rows = Vec::new();
+        while let Some(row) = reader.next_row().expect("read") {
+            rows.push(row);
+        }
+
+        assert_eq!(rows.len(), 4);
+        assert!(matches!(
+            &rows[1].fields,
+            SourceFields::Invalid(message) if message == "Line 2 is not a JSON object"
+        ));
+        assert!(matches!(&rows[2].fields, SourceFields::Invalid(_)));
+        assert_eq!(reader.field(&rows[3], "name").as_deref(), Some("Fabrikam"));
+    }
+
+    #[test]
+    fn converts_text_by_column_type_and_option_label() {
+        let option_sets = parse_option_set_attributes(&json!({
+            "value": [{
+                "LogicalName": "industrycode",
+                "OptionSet": { "Options": [
+                    { "Value": 1, "Label": { "UserLocalizedLabel": { "Label": "Accounting" } } },
+                    { "Value": 2, "Label": { "UserLocalizedLabel": { "Label": "Agriculture" } } }
+                ] }
+            }]
+        }))
+        .expect("option sets");
+
+        assert!(matches!(
+            convert_text(
+                "account",
+                " 12 ",
+                &attribute("numberofemployees", "Integer"),
+                &option_sets
+            ),
+            Ok(Value::Int(12))
+        ));
+        assert!(matches!(
+            convert_text(
+                "account",
+                "yes",
+                &attribute("donotemail", "Boolean"),
+                &option_sets
+            ),
+            Ok(Value::Boolean(true))
+        ));
+        assert!(matches!(
+            convert_text("account", "agriculture", &attribute("industrycode", "Picklist"), &option_sets),
+            Ok(Value::OptionSetValue(option)) if option.value == 2
+        ));
+        assert!(matches!(
+            convert_text("account", "2024-05-01", &attribute("lastusedincampaign", "DateTime"), &option_sets),
+            Ok(Value::DateTime(value)) if value.to_rfc3339() == "2024-05-01T00:00:00+00:00"
+        ));
+        assert_eq!(
+            convert_text(
+                "account",
+                "many",
+                &attribute("numberofemployees", "Integer"),
+                &option_sets
+            )
+            .expect_err("not a number"),
+            "'many' is not a valid whole number"
+        );
+        assert!(
+            convert_text(
+                "account",
+                "Mining",
+                &attribute("industrycode", "Picklist"),
+                &option_sets
+            )
+            .expect_err("unknown label")
+            .contains("Valid labels: Accounting, Agriculture")
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_columns_and_unmapped_lookups() {
+        let attributes = HashMap::from([
+            ("name".to_string(), attribute("name", "String")),
+            (
+                "primarycontactid".to_string(),
+                attribute("primarycontactid", "Lookup"),
+            ),
+        ]);
+        let mapping = |fields: Vec<FieldMapping>| ImportMapping {
+            table: "account".to_string(),
+            fields,
+            upsert_key: Vec::new(),
+        };
+
+        assert!(
+            check_mapping(
+                &mapping(vec![
+                    FieldMapping::new("Name", "name"),
+                    FieldMapping::lookup("Contact", "primarycontactid", "contact", "emailaddress1"),
+                ]),
+                &attributes
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            check_mapping(
+                &mapping(vec![FieldMapping::new("Nmae", "nmae")]),
+                &attributes
+            ),
+            Err("Column 'nmae' not found on 'account'".to_string())
+        );
+        assert_eq!(
+            check_mapping(
+                &mapping(vec![FieldMapping::new("Contact", "primarycontactid")]),
+                &attributes
+            ),
+            Err(
+                "Column 'primarycontactid' is a lookup; map it with FieldMapping::lookup"
+                    .to_string()
+            )
+        );
+    }
+}
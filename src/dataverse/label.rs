@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One translation of a metadata label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageLabel {
+    /// Label text.
+    #[serde(rename = "Label")]
+    pub label: String,
+    /// Language code identifier (LCID), such as 1033 for English (United States).
+    #[serde(rename = "LanguageCode", default)]
+    pub language_code: i32,
+}
+
+/// A metadata `Label`, such as a table or column `DisplayName` or an option label, with every
+/// translation and the one in the caller's language.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalizedLabel {
+    /// Translations for each installed language.
+    #[serde(rename = "LocalizedLabels", default)]
+    pub localized_labels: Vec<LanguageLabel>,
+    /// The translation in the calling user's language.
+    #[serde(rename = "UserLocalizedLabel", default)]
+    pub user_localized_label: Option<LanguageLabel>,
+}
+
+impl LocalizedLabel {
+    /// Label text in the language `lcid`, such as 1036 for French.
+    pub fn get(&self, lcid: i32) -> Option<&str> {
+        self.localized_labels
+            .iter()
+            .chain(&self.user_localized_label)
+            .find(|label| label.language_code == lcid)
+            .map(|label| label.label.as_str())
+    }
+
+    /// Label text in the calling user's language.
+    pub fn user_localized(&self) -> Option<&str> {
+        self.user_localized_label
+            .as_ref()
+            .map(|label| label.label.as_str())
+    }
+
+    /// Parse a `Label` payload. `None` for `null` and other shapes.
+    pub(crate) fn from_value(value: Option<&Value>) -> Option<Self> {
+        value
+            .filter(|value| value.is_object())
+            .and_then(|value| Self::deserialize(value).ok())
+    }
+}
+
+/// Replace every `UserLocalizedLabel` in a metadata response with its `lcid` translation, so
+/// labels read as if the caller's language were `lcid`. Labels without that translation keep the
+/// caller's language.
+pub(crate) fn localize_labels(json: &mut Value, lcid: i32) {
+    match json {
+        Value::Object(object) => {
+            let translation = object
+                .get("LocalizedLabels")
+                .and_then(|labels| labels.as_array())
+                .and_then(|labels| {
+                    labels.iter().find(|label| {
+                        label.get("LanguageCode").and_then(|code| code.as_i64())
+                            == Some(i64::from(lcid))
+                    })
+                })
+                .cloned();
+            if let Some(translation) = translation {
+                object.insert("UserLocalizedLabel".to_string(), translation);
+            }
+            object
+                .values_mut()
+                .for_each(|value| localize_labels(value, lcid));
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| localize_labels(value, lcid)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{LocalizedLabel, localize_labels};
+
+    #[test]
+    fn reads_labels_by_language() {
+        let label = LocalizedLabel::from_value(Some(&json!({
+            "LocalizedLabels": [
+                {"Label": "Account", "LanguageCode": 1033},
+                {"Label": "Compte", "LanguageCode": 1036}
+            ],
+            "UserLocalizedLabel": {"Label": "Account", "LanguageCode": 1033}
+        })))
+        .expect("should parse");
+
+        assert_eq!(label.get(1036), Some("Compte"));
+        assert_eq!(label.get(1031), None);
+        assert_eq!(label.user_localized(), Some("Account"));
+
+        let empty = LocalizedLabel::from_value(Some(&json!({
+            "LocalizedLabels": [],
+            "UserLocalizedLabel": null
+        })))
+        .expect("should parse");
+        assert_eq!(empty.user_localized(), None);
+        assert!(LocalizedLabel::from_value(Some(&json!(null))).is_none());
+    }
+
+    #[test]
+    fn localizes_nested_labels() {
+        let mut json = json!({
+            "value": [{
+                "DisplayName": {
+                    "LocalizedLabels": [
+                        {"Label": "Account", "LanguageCode": 1033},
+                        {"Label": "Compte", "LanguageCode": 1036}
+                    ],
+                    "UserLocalizedLabel": {"Label": "Account", "LanguageCode": 1033}
+                },
+                "Description": {
+                    "LocalizedLabels": [{"Label": "Business", "LanguageCode": 1033}],
+                    "UserLocalizedLabel": {"Label": "Business", "LanguageCode": 1033}
+                }
+            }]
+        });
+        localize_labels(&mut json, 1036);
+
+        let display_name = LocalizedLabel::from_value(Some(&json["value"][0]["DisplayName"]))
+            .expect("should parse");
+        assert_eq!(display_name.user_localized(), Some("Compte"));
+        let description = LocalizedLabel::from_value(Some(&json["value"][0]["Description"]))
+            .expect("should parse");
+        assert_eq!(description.user_localized(), Some("Business"));
+    }
+}
@@ -0,0 +1,52 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::url::row_path;
+
+/// Path of the `RetrieveProvisionedLanguages` function.
+pub(crate) const PROVISIONED_LANGUAGES_PATH: &str = "RetrieveProvisionedLanguages()";
+
+/// Path of the `usersettings` row of `user_id`, selecting its UI language.
+pub(crate) fn user_language_path(user_id: Uuid) -> String {
+    format!(
+        "{}?$select=uilanguageid",
+        row_path("usersettingscollection", user_id.as_hyphenated())
+    )
+}
+
+/// Read the language codes of a `RetrieveProvisionedLanguages` response.
+pub(crate) fn parse_provisioned_languages(json: &Value) -> Result<Vec<i32>, String> {
+    json.get("RetrieveProvisionedLanguages")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?
+        .iter()
+        .map(|code| {
+            code.as_i64()
+                .and_then(|code| i32::try_from(code).ok())
+                .ok_or_else(|| format!("Invalid language code {code}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{parse_provisioned_languages, user_language_path};
+
+    #[test]
+    fn reads_languages_and_user_settings_path() {
+        assert_eq!(
+            parse_provisioned_languages(&json!({ "RetrieveProvisionedLanguages": [1033, 1036] })),
+            Ok(vec![1033, 1036])
+        );
+        assert!(parse_provisioned_languages(&json!({})).is_err());
+
+        let user_id = Uuid::parse_str("11111111-1111-1111-1111-111111111111").expect("uuid");
+        assert_eq!(
+            user_language_path(user_id),
+            "usersettingscollection(11111111-1111-1111-1111-111111111111)?$select=uilanguageid"
+        );
+    }
+}
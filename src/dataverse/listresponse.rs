@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dataverse::entity::Entity;
+use crate::dataverse::fetchxml::PageToken;
+use crate::dataverse::requestoptions::RequestOptions;
+
+/// One page of a Dataverse Web API collection response.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ListResponse<T> {
+    /// Items on this page.
+    pub value: Vec<T>,
+    /// URL of the next page (`@odata.nextLink`), when more results are available.
+    #[serde(rename = "@odata.nextLink", default)]
+    pub next_link: Option<String>,
+    /// URL that returns changes since this response (`@odata.deltaLink`), on the last page of a
+    /// query sent with `odata.track-changes`.
+    #[serde(rename = "@odata.deltaLink", default)]
+    pub delta_link: Option<String>,
+    /// Total matching rows (`@odata.count`), when `$count=true` was requested.
+    #[serde(rename = "@odata.count", default)]
+    pub count: Option<i64>,
+    /// True when more rows match than `@odata.count` can report; Dataverse stops counting at
+    /// 5,000.
+    #[serde(
+        rename = "@Microsoft.Dynamics.CRM.totalrecordcountlimitexceeded",
+        default
+    )]
+    pub count_limit_exceeded: bool,
+    /// Position of the next page of an OData row query, when `next_link` is set. Only row
+    /// queries fill it in; other collections leave it `None`.
+    #[serde(skip)]
+    pub next_page: Option<PageCursor>,
+}
+
+impl<T> ListResponse<T> {
+    /// True when Dataverse returned a next page link.
+    pub fn has_more(&self) -> bool {
+        self.next_link.is_some()
+    }
+}
+
+/// Position of the next page of an OData row query: the `@odata.nextLink` Dataverse returned,
+/// with the table and the `Prefer` preferences every page request must repeat. Like `PageToken`
+/// for FetchXML, a cursor serializes, so a "load more" UI can hand it to a client and back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageCursor {
+    /// Entity set name the rows belong to.
+    pub entity: String,
+    /// `@odata.nextLink` URL of the next page, carrying its `$skiptoken`.
+    pub next_link: String,
+    /// `odata.maxpagesize` of the query.
+    pub max_page_size: Option<u32>,
+    /// Annotations the query requested, when not the defaults.
+    pub include_annotations: Option<Vec<String>>,
+    /// Extra headers the query sent with `RequestOptions::headers`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+}
+
+impl PageCursor {
+    pub(crate) fn new(entity: &str, next_link: &str, options: &RequestOptions) -> Self {
+        Self {
+            entity: entity.to_string(),
+            next_link: next_link.to_string(),
+            max_page_size: options.max_page_size,
+            include_annotations: options.include_annotations.clone(),
+            headers: options.headers.clone(),
+        }
+    }
+
+    /// The `$skiptoken` of the next page, decoded, for logging or comparing cursors.
+    pub fn skip_token(&self) -> Option<String> {
+        let (_, query) = self.next_link.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            (urlencoding::decode(name).ok()? == "$skiptoken")
+                .then(|| urlencoding::decode(value).ok().map(|value| value.into_owned()))
+                .flatten()
+        })
+    }
+
+    /// The `Prefer` preferences and headers to send with the next page.
+    pub(crate) fn options(&self) -> RequestOptions {
+        RequestOptions {
+            max_page_size: self.max_page_size,
+            include_annotations: self.include_annotations.clone(),
+            headers: self.headers.clone(),
+            ..RequestOptions::default()
+        }
+    }
+}
+
+/// One FetchXML response page with the paging metadata Dataverse returned for it.
+#[derive(Debug, Clone)]
+pub struct FetchXmlPage {
+    /// Rows on this page.
+    pub entities: Vec<Entity>,
+    /// `@Microsoft.Dynamics.CRM.morerecords`: true when another page follows.
+    pub more_records: bool,
+    /// Decoded paging cookie to pass back with the next page number, when Dataverse sent one.
+    pub paging_cookie: Option<String>,
+    /// `@Microsoft.Dynamics.CRM.totalrecordcount`, when the query set
+    /// `returntotalrecordcount="true"`.
+    pub total_record_count: Option<i64>,
+    /// True when the total record count stopped at Dataverse's 5,000 row limit.
+    pub total_record_count_limit_exceeded: bool,
+    /// Position of the next page, when `more_records` is true. Store it to resume the query later.
+    pub next_page: Option<PageToken>,
+}
+
+impl FetchXmlPage {
+    /// True when Dataverse reported more records after this page.
+    pub fn has_more(&self) -> bool {
+        self.more_records
+    }
+}
+
+/// Read `@Microsoft.Dynamics.CRM.totalrecordcount`, which is `-1` unless the query asked for it.
+pub(crate) fn parse_total_record_count(json: &Value) -> Option<i64> {
+    json.get("@Microsoft.Dynamics.CRM.totalrecordcount")
+        .and_then(|value| value.as_i64())
+        .filter(|count| *count >= 0)
+}
+
+/// Read `@Microsoft.Dynamics.CRM.totalrecordcountlimitexceeded`, returned with FetchXML total
+/// record counts and OData `$count=true` queries.
+pub(crate) fn parse_total_record_count_limit_exceeded(json: &Value) -> bool {
+    json.get("@Microsoft.Dynamics.CRM.totalrecordcountlimitexceeded")
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false)
+}
+
+/// Read `@odata.nextLink` from a collection response.
+pub(crate) fn parse_next_link(json: &Value) -> Option<String> {
+    json.get("@odata.nextLink")
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+}
+
+/// Read `@odata.deltaLink` from a collection response.
+pub(crate) fn parse_delta_link(json: &Value) -> Option<String> {
+    json.get("@odata.deltaLink")
+        .and_then(|value| value.as_str())
+        .map(|value| value.to_string())
+}
+
+/// Read `@odata.count` from a collection response.
+pub(crate) fn parse_count(json: &Value) -> Option<i64> {
+    json.get("@odata.count").and_then(|value| value.as_i64())
+}
+
+/// Reject next links that point away from the connected environment, so the bearer token is
+/// only ever sent to the Dataverse host the client was created for.
+pub(crate) fn validate_next_link(base_url: &str, next_link: &str) -> Result<(), String> {
+    let base = format!("{}/", base_url.trim_end_matches('/'));
+    if next_link
+        .to_ascii_lowercase()
+        .starts_with(&base.to_ascii_lowercase())
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Next link '{next_link}' does not belong to {base_url}"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        ListResponse, PageCursor, parse_next_link, parse_total_record_count,
+        parse_total_record_count_limit_exceeded, validate_next_link,
+    };
+    use crate::dataverse::requestoptions::RequestOptions;
+
+    #[test]
+    fn deserializes_next_link_and_count() {
+        let response: ListResponse<serde_json::Value> = serde_json::from_value(json!({
+            "@odata.count": 7,
+            "@odata.nextLink": "https://example.crm.dynamics.com/api/data/v9.2/accounts?$skiptoken=abc",
+            "value": [{}, {}]
+        }))
+        .expect("should deserialize");
+
+        assert_eq!(response.value.len(), 2);
+        assert_eq!(response.count, Some(7));
+        assert!(!response.count_limit_exceeded);
+        assert_eq!(response.delta_link, None);
+        assert!(response.has_more());
+    }
+
+    #[test]
+    fn page_cursor_keeps_skip_token_and_page_size() {
+        let options = RequestOptions {
+            max_page_size: Some(25),
+            ..RequestOptions::default()
+        };
+        let cursor = PageCursor::new(
+            "accounts",
+            "https://example.crm.dynamics.com/api/data/v9.2/accounts?$select=name&$skiptoken=%3Ccookie%20pagenumber=%222%22%20/%3E",
+            &options,
+        );
+
+        assert_eq!(
+            cursor.skip_token().as_deref(),
+            Some("<cookie pagenumber=\"2\" />")
+        );
+        assert_eq!(cursor.options(), options);
+
+        let restored: PageCursor =
+            serde_json::from_str(&serde_json::to_string(&cursor).expect("serialize"))
+                .expect("deserialize");
+        assert_eq!(restored, cursor);
+    }
+
+    #[test]
+    fn last_page_has_no_next_link() {
+        let json = json!({ "value": [] });
+        let response: ListResponse<serde_json::Value> =
+            serde_json::from_value(json.clone()).expect("should deserialize");
+
+        assert!(!response.has_more());
+        assert_eq!(parse_next_link(&json), None);
+    }
+
+    #[test]
+    fn reads_fetchxml_total_record_count_annotations() {
+        let json = json!({
+            "@Microsoft.Dynamics.CRM.totalrecordcount": 5000,
+            "@Microsoft.Dynamics.CRM.totalrecordcountlimitexceeded": true,
+            "value": []
+        });
+        assert_eq!(parse_total_record_count(&json), Some(5000));
+        assert!(parse_total_record_count_limit_exceeded(&json));
+
+        let not_requested = json!({
+            "@Microsoft.Dynamics.CRM.totalrecordcount": -1,
+            "value": []
+        });
+        assert_eq!(parse_total_record_count(&not_requested), None);
+        assert!(!parse_total_record_count_limit_exceeded(&not_requested));
+    }
+
+    #[test]
+    fn next_link_must_target_connected_environment() {
+        let base = "https://example.crm.dynamics.com";
+
+        assert!(
+            validate_next_link(
+                base,
+                "https://example.crm.dynamics.com/api/data/v9.2/accounts?$skiptoken=1"
+            )
+            .is_ok()
+        );
+        assert!(
+            validate_next_link(base, "https://example.crm.dynamics.com.evil.test/api").is_err()
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde_json::{Map, Value};
+
+use crate::dataverse::entity::EntityReference;
+use crate::dataverse::url::bind_path;
+
+/// Single-valued navigation property that writes a lookup column, from `ManyToOneRelationships`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookupNavigation {
+    /// Lookup column logical name, for example `parentcustomerid`.
+    pub attribute: String,
+    /// Logical name of the table the lookup points at.
+    pub referenced_entity: String,
+    /// Navigation property used in `@odata.bind`, for example `parentcustomerid_account`.
+    pub navigation_property: String,
+}
+
+/// Read lookup navigation properties from a `ManyToOneRelationships` collection.
+pub(crate) fn parse_lookup_navigations(json: &Value) -> Result<Vec<LookupNavigation>, String> {
+    let items = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    Ok(items
+        .iter()
+        .filter_map(|item| {
+            let text = |name: &str| item.get(name).and_then(|value| value.as_str());
+            Some(LookupNavigation {
+                attribute: text("ReferencingAttribute")?.to_ascii_lowercase(),
+                referenced_entity: text("ReferencedEntity")?.to_ascii_lowercase(),
+                navigation_property: text("ReferencingEntityNavigationPropertyName")?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Find the navigation property for a lookup column. Polymorphic lookups such as customer or
+/// owner have one navigation property per target table, so `referenced_entity` picks between
+/// them; `None` takes the first, which is enough to clear the column.
+pub(crate) fn find_navigation_property<'a>(
+    navigations: &'a [LookupNavigation],
+    attribute: &str,
+    referenced_entity: Option<&str>,
+) -> Option<&'a str> {
+    navigations
+        .iter()
+        .find(|navigation| {
+            navigation.attribute.eq_ignore_ascii_case(attribute)
+                && referenced_entity.is_none_or(|referenced| {
+                    navigation.referenced_entity.eq_ignore_ascii_case(referenced)
+                })
+        })
+        .map(|navigation| navigation.navigation_property.as_str())
+}
+
+/// Write `navigation@odata.bind` for a lookup, falling back to the column name when metadata does
+/// not list a navigation property for it.
+pub(crate) fn bind_lookup(
+    body: &mut Map<String, Value>,
+    navigations: &[LookupNavigation],
+    attribute: &str,
+    reference: &EntityReference,
+    entity_set_name: &str,
+) {
+    let navigation_property =
+        find_navigation_property(navigations, attribute, Some(&reference.logical_name))
+            .unwrap_or(attribute);
+    body.insert(
+        format!("{navigation_property}@odata.bind"),
+        Value::String(bind_path(entity_set_name, reference.id.as_hyphenated())),
+    );
+}
+
+/// Write `navigation@odata.bind: null` to disassociate a lookup. Returns false when the column is
+/// not a known lookup, so the caller can write a plain null instead.
+pub(crate) fn clear_lookup(
+    body: &mut Map<String, Value>,
+    navigations: &[LookupNavigation],
+    attribute: &str,
+) -> bool {
+    match find_navigation_property(navigations, attribute, None) {
+        Some(navigation_property) => {
+            body.insert(format!("{navigation_property}@odata.bind"), Value::Null);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Map, Value, json};
+    use uuid::Uuid;
+
+    use super::{bind_lookup, clear_lookup, find_navigation_property, parse_lookup_navigations};
+    use crate::dataverse::entity::EntityReference;
+
+    fn navigations() -> Vec<super::LookupNavigation> {
+        parse_lookup_navigations(&json!({
+            "value": [
+                {
+                    "ReferencingAttribute": "parentcustomerid",
+                    "ReferencedEntity": "account",
+                    "ReferencingEntityNavigationPropertyName": "parentcustomerid_account"
+                },
+                {
+                    "ReferencingAttribute": "parentcustomerid",
+                    "ReferencedEntity": "contact",
+                    "ReferencingEntityNavigationPropertyName": "parentcustomerid_contact"
+                },
+                {
+                    "ReferencingAttribute": "cr123_projectid",
+                    "ReferencedEntity": "cr123_project",
+                    "ReferencingEntityNavigationPropertyName": "cr123_ProjectId"
+                }
+            ]
+        }))
+        .expect("should parse")
+    }
+
+    #[test]
+    fn picks_navigation_property_by_referenced_table() {
+        let navigations = navigations();
+
+        assert_eq!(
+            find_navigation_property(&navigations, "parentcustomerid", Some("contact")),
+            Some("parentcustomerid_contact")
+        );
+        assert_eq!(
+            find_navigation_property(&navigations, "ParentCustomerId", None),
+            Some("parentcustomerid_account")
+        );
+        assert_eq!(
+            find_navigation_property(&navigations, "parentcustomerid", Some("lead")),
+            None
+        );
+    }
+
+    #[test]
+    fn binds_and_clears_lookups_through_navigation_properties() {
+        let navigations = navigations();
+        let id = Uuid::new_v4();
+        let mut body = Map::new();
+
+        bind_lookup(
+            &mut body,
+            &navigations,
+            "cr123_projectid",
+            &EntityReference {
+                id,
+                logical_name: "cr123_project".to_string(),
+                name: None,
+            },
+            "cr123_projects",
+        );
+        assert!(clear_lookup(&mut body, &navigations, "parentcustomerid"));
+        assert!(!clear_lookup(&mut body, &navigations, "description"));
+
+        assert_eq!(
+            body.get("cr123_ProjectId@odata.bind"),
+            Some(&Value::String(format!("/cr123_projects({id})")))
+        );
+        assert_eq!(
+            body.get("parentcustomerid_account@odata.bind"),
+            Some(&Value::Null)
+        );
+        assert!(!body.contains_key("description@odata.bind"));
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use serde_json::{Map, Value, json};
+
+/// Tables the `Merge` action supports.
+pub const MERGEABLE_ENTITIES: [&str; 4] = ["account", "contact", "lead", "incident"];
+
+/// Fail before calling `Merge` for a table Dataverse cannot merge.
+pub(crate) fn check_mergeable(logical_name: &str) -> Result<(), String> {
+    if MERGEABLE_ENTITIES
+        .iter()
+        .any(|entity| entity.eq_ignore_ascii_case(logical_name))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Merge is not supported for '{logical_name}'. Supported tables: {}",
+            MERGEABLE_ENTITIES.join(", ")
+        ))
+    }
+}
+
+/// Build the body for the `Merge` action. `UpdateContent` is required even when empty, and like
+/// `Target` and `Subordinate` it must be typed with `@odata.type`.
+pub(crate) fn build_merge_body(
+    logical_name: &str,
+    target: Value,
+    subordinate: Value,
+    update_content: Option<&HashMap<String, Value>>,
+    perform_parenting_checks: bool,
+) -> Value {
+    let mut content = update_content
+        .map(|content| {
+            content
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect::<Map<String, Value>>()
+        })
+        .unwrap_or_default();
+    content.insert(
+        "@odata.type".to_string(),
+        Value::String(format!("Microsoft.Dynamics.CRM.{logical_name}")),
+    );
+
+    json!({
+        "Target": target,
+        "Subordinate": subordinate,
+        "UpdateContent": content,
+        "PerformParentingChecks": perform_parenting_checks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{build_merge_body, check_mergeable};
+    use crate::dataverse::access::action_entity_reference;
+
+    #[test]
+    fn builds_typed_merge_body() {
+        let target = Uuid::new_v4();
+        let subordinate = Uuid::new_v4();
+        let update_content = HashMap::from([("telephone1".to_string(), json!("555-0100"))]);
+
+        let body = build_merge_body(
+            "account",
+            action_entity_reference("account", "accountid", target),
+            action_entity_reference("account", "accountid", subordinate),
+            Some(&update_content),
+            true,
+        );
+
+        assert_eq!(
+            body,
+            json!({
+                "Target": {
+                    "@odata.type": "Microsoft.Dynamics.CRM.account",
+                    "accountid": target.to_string()
+                },
+                "Subordinate": {
+                    "@odata.type": "Microsoft.Dynamics.CRM.account",
+                    "accountid": subordinate.to_string()
+                },
+                "UpdateContent": {
+                    "@odata.type": "Microsoft.Dynamics.CRM.account",
+                    "telephone1": "555-0100"
+                },
+                "PerformParentingChecks": true
+            })
+        );
+    }
+
+    #[test]
+    fn empty_update_content_is_still_typed() {
+        let body = build_merge_body("lead", json!({}), json!({}), None, false);
+
+        assert_eq!(
+            body["UpdateContent"],
+            json!({ "@odata.type": "Microsoft.Dynamics.CRM.lead" })
+        );
+        assert!(check_mergeable("Contact").is_ok());
+        assert!(check_mergeable("opportunity").is_err());
+    }
+}
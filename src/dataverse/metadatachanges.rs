@@ -0,0 +1,300 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::dataverse::entitydefinition::EntityDefinition;
+use crate::dataverse::url::{encode_query_value, encode_string_literal};
+
+/// Table properties every query requests, so cached definitions always parse as
+/// `EntityDefinition`.
+const REQUIRED_PROPERTIES: &[&str] = &[
+    "MetadataId",
+    "LogicalName",
+    "SchemaName",
+    "EntitySetName",
+    "IsCustomEntity",
+];
+
+/// Table properties requested by `MetadataQuery::default`, the same ones
+/// `list_entity_definitions` selects.
+const DEFAULT_PROPERTIES: &[&str] = &[
+    "DisplayName",
+    "IsActivity",
+    "PrimaryIdAttribute",
+    "PrimaryNameAttribute",
+    "OwnershipType",
+];
+
+/// Which table definitions `RetrieveMetadataChanges` returns, and which of their properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataQuery {
+    /// Table logical names to return. Empty for every table.
+    pub logical_names: Vec<String>,
+    /// Table properties to return, such as `DisplayName`. `MetadataId`, `LogicalName`,
+    /// `SchemaName`, `EntitySetName`, and `IsCustomEntity` are always requested.
+    pub properties: Vec<String>,
+}
+
+impl Default for MetadataQuery {
+    fn default() -> Self {
+        Self {
+            logical_names: Vec::new(),
+            properties: DEFAULT_PROPERTIES
+                .iter()
+                .map(|property| property.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl MetadataQuery {
+    /// Limit the query to the tables `logical_names`.
+    pub fn for_tables(logical_names: &[&str]) -> Self {
+        Self {
+            logical_names: logical_names
+                .iter()
+                .map(|name| name.to_ascii_lowercase())
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// The `EntityQueryExpression` sent as the `Query` parameter.
+    pub(crate) fn to_query_expression(&self) -> Value {
+        let mut property_names = REQUIRED_PROPERTIES
+            .iter()
+            .map(|property| property.to_string())
+            .collect::<Vec<_>>();
+        for property in &self.properties {
+            if !property_names.contains(property) {
+                property_names.push(property.clone());
+            }
+        }
+
+        let mut query = json!({
+            "Properties": {"AllProperties": false, "PropertyNames": property_names}
+        });
+        if !self.logical_names.is_empty() {
+            query["Criteria"] = json!({
+                "FilterOperator": "Or",
+                "Conditions": self
+                    .logical_names
+                    .iter()
+                    .map(|name| json!({
+                        "PropertyName": "LogicalName",
+                        "ConditionOperator": "Equals",
+                        "Value": {"Value": name, "Type": "System.String"}
+                    }))
+                    .collect::<Vec<_>>()
+            });
+        }
+        query
+    }
+}
+
+/// Table definitions changed since a client version stamp, from `RetrieveMetadataChanges`.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataChanges {
+    /// Definitions of new and changed tables as returned. Properties that did not change since
+    /// the client version stamp are `null`.
+    pub entity_metadata: Vec<Value>,
+    /// `MetadataId`s of tables deleted since the client version stamp.
+    pub deleted_entity_ids: Vec<Uuid>,
+    /// Version stamp to pass on the next call.
+    pub server_version_stamp: String,
+}
+
+/// A local copy of table definitions kept current with `ServiceClient::sync_metadata_cache`.
+/// Serialize it to keep it across restarts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MetadataCache {
+    /// Version stamp of the last sync, or `None` before the first.
+    pub version_stamp: Option<String>,
+    /// Table definitions keyed by `MetadataId`.
+    pub entities: HashMap<Uuid, Map<String, Value>>,
+}
+
+impl MetadataCache {
+    /// Apply a set of changes: merge changed properties, drop deleted tables, and keep the new
+    /// version stamp.
+    pub fn apply(&mut self, changes: &MetadataChanges) {
+        for entity in &changes.entity_metadata {
+            let Some(object) = entity.as_object() else {
+                continue;
+            };
+            let Some(metadata_id) = object
+                .get("MetadataId")
+                .and_then(Value::as_str)
+                .and_then(|id| Uuid::parse_str(id).ok())
+            else {
+                continue;
+            };
+            let cached = self.entities.entry(metadata_id).or_default();
+            for (key, value) in object {
+                if !value.is_null() {
+                    cached.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        for id in &changes.deleted_entity_ids {
+            self.entities.remove(id);
+        }
+        self.version_stamp = Some(changes.server_version_stamp.clone());
+    }
+
+    /// Cached table definitions, ordered by logical name.
+    pub fn definitions(&self) -> Vec<EntityDefinition> {
+        let mut definitions = self
+            .entities
+            .values()
+            .filter_map(|entity| EntityDefinition::deserialize(&Value::Object(entity.clone())).ok())
+            .collect::<Vec<_>>();
+        definitions.sort_by(|a, b| a.logical_name.cmp(&b.logical_name));
+        definitions
+    }
+
+    /// The cached definition of the table `logical_name`.
+    pub fn definition(&self, logical_name: &str) -> Option<EntityDefinition> {
+        self.definitions()
+            .into_iter()
+            .find(|definition| definition.logical_name.eq_ignore_ascii_case(logical_name))
+    }
+}
+
+/// `RetrieveMetadataChanges` path for `query`, asking for deleted tables when
+/// `client_version_stamp` is set.
+pub(crate) fn metadata_changes_path(
+    query: &MetadataQuery,
+    client_version_stamp: Option<&str>,
+) -> String {
+    let query = encode_query_value(&query.to_query_expression().to_string());
+    match client_version_stamp {
+        Some(stamp) => format!(
+            "RetrieveMetadataChanges(Query=@q,ClientVersionStamp=@v,DeletedMetadataFilters=@d)?@q={query}&@v={}&@d=Microsoft.Dynamics.CRM.DeletedMetadataFilters'Entity'",
+            encode_string_literal(stamp)
+        ),
+        None => format!("RetrieveMetadataChanges(Query=@q)?@q={query}"),
+    }
+}
+
+/// Read a `RetrieveMetadataChangesResponse`.
+pub(crate) fn parse_metadata_changes(json: &Value) -> Result<MetadataChanges, String> {
+    let invalid = || "Invalid response from Dataverse".to_string();
+    let entity_metadata = json
+        .get("EntityMetadata")
+        .and_then(Value::as_array)
+        .ok_or_else(invalid)?
+        .clone();
+    let server_version_stamp = json
+        .get("ServerVersionStamp")
+        .and_then(Value::as_str)
+        .ok_or_else(invalid)?
+        .to_string();
+
+    // `DeletedMetadata` is a collection of `Keys` (metadata kinds) and `Values` (ID lists).
+    let deleted = json.get("DeletedMetadata");
+    let keys = deleted
+        .and_then(|deleted| deleted.get("Keys"))
+        .and_then(Value::as_array);
+    let values = deleted
+        .and_then(|deleted| deleted.get("Values"))
+        .and_then(Value::as_array);
+    let deleted_entity_ids = match (keys, values) {
+        (Some(keys), Some(values)) => keys
+            .iter()
+            .zip(values)
+            .filter(|(key, _)| key.as_str() == Some("Entity"))
+            .filter_map(|(_, ids)| ids.as_array())
+            .flatten()
+            .filter_map(|id| Uuid::parse_str(id.as_str()?).ok())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(MetadataChanges {
+        entity_metadata,
+        deleted_entity_ids,
+        server_version_stamp,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{MetadataCache, MetadataQuery, metadata_changes_path, parse_metadata_changes};
+
+    #[test]
+    fn builds_query_expression_with_required_properties() {
+        let query = MetadataQuery::for_tables(&["Account"]).to_query_expression();
+
+        let names = query["Properties"]["PropertyNames"]
+            .as_array()
+            .expect("property names");
+        assert_eq!(names[0], "MetadataId");
+        assert!(names.contains(&json!("DisplayName")));
+        assert_eq!(
+            query["Criteria"]["Conditions"][0]["Value"]["Value"],
+            "account"
+        );
+        assert!(
+            metadata_changes_path(&MetadataQuery::default(), Some("12345"))
+                .ends_with("&@v='12345'&@d=Microsoft.Dynamics.CRM.DeletedMetadataFilters'Entity'")
+        );
+    }
+
+    #[test]
+    fn cache_merges_changes_and_drops_deleted_tables() {
+        let account = Uuid::new_v4();
+        let contact = Uuid::new_v4();
+        let definition = |id: Uuid, name: &str, display: Option<&str>| {
+            json!({
+                "MetadataId": id,
+                "LogicalName": name,
+                "SchemaName": name,
+                "EntitySetName": format!("{name}s"),
+                "IsCustomEntity": false,
+                "DisplayName": display.map(|label| json!({"UserLocalizedLabel": {"Label": label}}))
+            })
+        };
+        let mut cache = MetadataCache::default();
+        cache.apply(
+            &parse_metadata_changes(&json!({
+                "EntityMetadata": [
+                    definition(account, "account", Some("Account")),
+                    definition(contact, "contact", Some("Contact"))
+                ],
+                "ServerVersionStamp": "1"
+            }))
+            .expect("should parse"),
+        );
+
+        cache.apply(
+            &parse_metadata_changes(&json!({
+                "EntityMetadata": [{
+                    "MetadataId": account,
+                    "LogicalName": null,
+                    "DisplayName": {"UserLocalizedLabel": {"Label": "Customer"}}
+                }],
+                "DeletedMetadata": {"Keys": ["Entity"], "Values": [[contact]]},
+                "ServerVersionStamp": "2"
+            }))
+            .expect("should parse"),
+        );
+
+        assert_eq!(cache.version_stamp.as_deref(), Some("2"));
+        let definitions = cache.definitions();
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(
+            definitions[0]
+                .display_name
+                .as_ref()
+                .and_then(|label| label.user_localized()),
+            Some("Customer")
+        );
+        assert!(cache.definition("contact").is_none());
+    }
+}
@@ -1,10 +1,113 @@
+/// Row sharing types for `GrantAccess`, `ModifyAccess`, and `RevokeAccess`.
+pub mod access;
+/// Reports of why a user can or cannot reach a row.
+pub mod accessdiagnostics;
+/// Party list columns of activities, read from and written to `activityparty` rows.
+pub mod activityparty;
+/// Typed rows of aggregate and distinct FetchXML queries.
+pub mod aggregate;
+pub mod alternatekey;
+/// Structured Web API errors parsed from OData error bodies.
+pub mod apierror;
+/// OData `$apply` aggregation queries.
+pub mod apply;
 pub mod batch;
+/// Bulk writes as concurrent `$batch` calls with per-request retries and reporting.
+pub mod bulk;
+/// Row-level outcomes of batch, bulk, and import runs, with JSON Lines and CSV output.
+pub mod bulkresult;
+/// Business unit hierarchy and the users in a subtree.
+pub mod businessunit;
+/// Table capability checks derived from metadata.
+pub mod capabilities;
+pub mod capacity;
+/// Polling change feeds over change tracking, with pluggable delta link storage.
+pub mod changefeed;
+/// Builder for `ServiceClient` construction and request middleware.
+pub mod clientbuilder;
+pub mod columnset;
+/// Connection health checks for readiness probes and startup.
+pub mod connectionhealth;
+pub mod countresult;
+/// Currency symbols and precision for formatting money columns in exports.
+pub mod currency;
+/// Custom API definitions and typed, validated invocation.
+pub mod customapi;
+/// Cross-environment record copy using streamed FetchXML reads and batched upserts.
+pub mod datacopy;
+/// Validated Dataverse environment URLs and their OAuth scope.
+pub mod dataverseurl;
+/// `RetrieveDuplicates` checks against published duplicate detection rules.
+pub mod duplicates;
 pub mod entity;
 pub mod entityattribute;
 pub mod entitydefinition;
 pub mod entityrelationship;
+/// Environment variable definitions joined with their current values.
+pub mod environmentvariable;
+pub mod executioncontext;
+/// `$expand` of collection-valued navigation properties and their nested paging.
+pub mod expand;
 pub mod fetchxml;
+/// Chunked, resumable file column uploads.
+pub mod fileupload;
+/// Validated Dataverse row IDs.
+pub mod guid;
+/// CSV and JSON Lines imports with column mapping, lookup resolution, and an error report.
+pub mod import;
+/// Localized table, column, and option labels from metadata.
+pub mod label;
+/// Provisioned languages and the calling user's UI language.
+pub mod language;
+pub mod listresponse;
+/// Lookup `@odata.bind` helpers driven by relationship metadata.
+pub mod lookupbind;
+/// `Merge` action support for account, contact, lead, and incident deduplication.
+pub mod merge;
+/// Incremental table definition sync with `RetrieveMetadataChanges`.
+pub mod metadatachanges;
+/// Custom table definitions for `ServiceClient::create_table`.
+pub mod newtable;
+pub mod optionset;
+pub mod organization;
 pub mod parse;
+/// Retries of single pages during multi-page reads.
+pub mod pageretry;
+/// Progress reporting for multi-page retrieves and bulk writes.
+pub mod progress;
+/// Checks of the tables and columns a query names against cached metadata.
+pub mod queryvalidation;
+/// Typed creation and lookup of users, business units, and teams.
+pub mod provisioning;
+/// Recycle bin queries and restores of deleted rows.
+pub mod recyclebin;
+/// Typed execution contexts Dataverse sends to webhooks and Azure Service Bus.
+pub mod remotecontext;
+/// `x-ms-client-request-id` stamping and request IDs in API errors.
+pub mod requestid;
+/// Status, request IDs, and rate limit headers of successful and failed responses.
+pub mod responsemeta;
+/// `Prefer` header options for retrieval and write requests.
+pub mod requestoptions;
 /// Request parameter helpers for Dataverse create and update operations.
 pub mod requestparameters;
+/// Team membership and security role assignment.
+pub mod security;
+/// CSDL `$metadata` parsing for offline checks of entity sets, properties, and operations.
+pub mod schema;
 pub mod serviceclient;
+/// Incremental synchronization helpers.
+pub mod sync;
+/// Change-detecting writes of recurring syncs, matched by alternate key.
+pub mod syncwriter;
+/// Optional `metrics` crate instrumentation of requests, retries, pages, and throttling.
+pub mod telemetry;
+/// Retrieved rows that record changed columns for minimal updates.
+pub mod trackedentity;
+/// Record and replay transport for running Dataverse tests without live credentials.
+pub mod transport;
+/// Web API URL and path building with consistent escaping.
+pub mod url;
+/// Caller-supplied conversion of retrieved attribute values.
+pub mod valueconverter;
+pub mod writebuilder;
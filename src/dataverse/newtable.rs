@@ -0,0 +1,157 @@
+use serde_json::{Value, json};
+
+use crate::dataverse::entitydefinition::OwnershipType;
+
+/// Columns read back from a table definition after it is created.
+pub(crate) const CREATED_TABLE_SELECT: &str = "$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType";
+
+/// Longest value of the primary name column of a table created with `ServiceClient::create_table`.
+const PRIMARY_NAME_MAX_LENGTH: i32 = 100;
+
+/// Values for creating a custom table with `ServiceClient::create_table`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTable {
+    /// Schema name, starting with the publisher's customization prefix, such as `new_Widget`.
+    pub schema_name: String,
+    /// Singular display name.
+    pub display_name: String,
+    /// Plural display name.
+    pub display_collection_name: String,
+    /// Table description.
+    pub description: Option<String>,
+    /// Schema name of the primary name column, such as `new_Name`.
+    pub primary_name_schema_name: String,
+    /// `UserOwned` or `OrganizationOwned`.
+    pub ownership_type: OwnershipType,
+    /// Language of the labels, such as 1033 for English.
+    pub language_code: i32,
+    /// Unmanaged solution the table is added to, instead of the default solution.
+    pub solution_unique_name: Option<String>,
+}
+
+impl NewTable {
+    /// Logical name of the created table: the schema name in lower case.
+    pub fn logical_name(&self) -> String {
+        self.schema_name.to_lowercase()
+    }
+
+    /// The `EntityMetadata` create payload, with a string primary name column.
+    pub fn to_entity_metadata(&self) -> Result<Value, String> {
+        for schema_name in [&self.schema_name, &self.primary_name_schema_name] {
+            if !is_prefixed_schema_name(schema_name) {
+                return Err(format!(
+                    "Invalid schema name '{schema_name}': expected a customization prefix and name, such as new_Widget"
+                ));
+            }
+        }
+        if !matches!(
+            self.ownership_type,
+            OwnershipType::UserOwned | OwnershipType::OrganizationOwned
+        ) {
+            return Err(format!(
+                "Table '{}' must be UserOwned or OrganizationOwned",
+                self.schema_name
+            ));
+        }
+
+        let description = self.description.as_deref().unwrap_or(&self.display_name);
+        Ok(json!({
+            "@odata.type": "Microsoft.Dynamics.CRM.EntityMetadata",
+            "SchemaName": self.schema_name,
+            "DisplayName": label(&self.display_name, self.language_code),
+            "DisplayCollectionName": label(&self.display_collection_name, self.language_code),
+            "Description": label(description, self.language_code),
+            "OwnershipType": self.ownership_type,
+            "IsActivity": false,
+            "HasActivities": false,
+            "HasNotes": false,
+            "Attributes": [{
+                "@odata.type": "Microsoft.Dynamics.CRM.StringAttributeMetadata",
+                "AttributeType": "String",
+                "AttributeTypeName": { "Value": "StringType" },
+                "SchemaName": self.primary_name_schema_name,
+                "IsPrimaryName": true,
+                "RequiredLevel": {
+                    "Value": "None",
+                    "CanBeChanged": true,
+                    "ManagedPropertyLogicalName": "canmodifyrequirementlevelsettings"
+                },
+                "MaxLength": PRIMARY_NAME_MAX_LENGTH,
+                "FormatName": { "Value": "Text" },
+                "DisplayName": label("Name", self.language_code),
+                "Description": label("Primary name", self.language_code),
+            }],
+        }))
+    }
+}
+
+/// A `Label` with a single localized value.
+fn label(text: &str, language_code: i32) -> Value {
+    json!({
+        "@odata.type": "Microsoft.Dynamics.CRM.Label",
+        "LocalizedLabels": [{
+            "@odata.type": "Microsoft.Dynamics.CRM.LocalizedLabel",
+            "Label": text,
+            "LanguageCode": language_code,
+        }],
+    })
+}
+
+/// True for `<prefix>_<name>` with letters, digits, and underscores only.
+fn is_prefixed_schema_name(schema_name: &str) -> bool {
+    schema_name.split_once('_').is_some_and(|(prefix, name)| {
+        !prefix.is_empty()
+            && !name.is_empty()
+            && prefix.starts_with(|ch: char| ch.is_ascii_alphabetic())
+            && schema_name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewTable;
+    use crate::dataverse::entitydefinition::OwnershipType;
+
+    fn widget() -> NewTable {
+        NewTable {
+            schema_name: "new_Widget".to_string(),
+            display_name: "Widget".to_string(),
+            display_collection_name: "Widgets".to_string(),
+            description: None,
+            primary_name_schema_name: "new_Name".to_string(),
+            ownership_type: OwnershipType::UserOwned,
+            language_code: 1033,
+            solution_unique_name: None,
+        }
+    }
+
+    #[test]
+    fn builds_entity_metadata_payload() {
+        let table = widget();
+        assert_eq!(table.logical_name(), "new_widget");
+        let metadata = table.to_entity_metadata().expect("should build");
+        assert_eq!(metadata["SchemaName"], "new_Widget");
+        assert_eq!(metadata["OwnershipType"], "UserOwned");
+        assert_eq!(
+            metadata["DisplayCollectionName"]["LocalizedLabels"][0]["Label"],
+            "Widgets"
+        );
+        assert_eq!(metadata["Attributes"][0]["IsPrimaryName"], true);
+        assert_eq!(metadata["Attributes"][0]["SchemaName"], "new_Name");
+
+        for schema_name in ["Widget", "new_", "_Widget", "new_Wid get"] {
+            let table = NewTable {
+                schema_name: schema_name.to_string(),
+                ..widget()
+            };
+            assert!(table.to_entity_metadata().is_err(), "{schema_name}");
+        }
+        let table = NewTable {
+            ownership_type: OwnershipType::TeamOwned,
+            ..widget()
+        };
+        assert!(table.to_entity_metadata().is_err());
+    }
+}
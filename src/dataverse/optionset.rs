@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::dataverse::entity::{Entity, Value as RowValue};
+use crate::dataverse::label::LocalizedLabel;
+
+/// Derived attribute metadata types that carry an option set.
+pub(crate) const OPTION_SET_METADATA_TYPES: &[&str] = &[
+    "PicklistAttributeMetadata",
+    "MultiSelectPicklistAttributeMetadata",
+    "StateAttributeMetadata",
+    "StatusAttributeMetadata",
+];
+
+/// A single option in a choice, status, or state column.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OptionMetadata {
+    /// Numeric option value.
+    pub value: i32,
+    /// Option label in each installed language.
+    pub label: Option<LocalizedLabel>,
+    /// Color shown for the option in model-driven apps, such as `#0000ff`.
+    #[serde(default)]
+    pub color: Option<String>,
+}
+
+impl OptionMetadata {
+    /// Option label in the calling user's language.
+    pub fn user_label(&self) -> Option<&str> {
+        self.label.as_ref()?.user_localized()
+    }
+}
+
+/// Options for each choice column of a table, keyed by column logical name.
+pub type OptionSetMap = HashMap<String, Vec<OptionMetadata>>;
+
+/// Parse a derived-type attribute list expanded with `OptionSet` (and `GlobalOptionSet`).
+pub(crate) fn parse_option_set_attributes(json: &Value) -> Result<OptionSetMap, String> {
+    let attributes = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+
+    Ok(attributes
+        .iter()
+        .filter_map(|attribute| {
+            let logical_name = attribute.get("LogicalName")?.as_str()?.to_string();
+            let option_set = attribute
+                .get("OptionSet")
+                .filter(|value| !value.is_null())
+                .or_else(|| attribute.get("GlobalOptionSet"))?;
+            let options = option_set
+                .get("Options")?
+                .as_array()?
+                .iter()
+                .filter_map(parse_option)
+                .collect();
+            Some((logical_name, options))
+        })
+        .collect())
+}
+
+/// Parse one `OptionMetadata` object from an option set's `Options`, `TrueOption`, or `FalseOption`.
+pub(crate) fn parse_option(option: &Value) -> Option<OptionMetadata> {
+    Some(OptionMetadata {
+        value: i32::try_from(option.get("Value")?.as_i64()?).ok()?,
+        label: LocalizedLabel::from_value(option.get("Label")),
+        color: option
+            .get("Color")
+            .and_then(Value::as_str)
+            .filter(|color| !color.is_empty())
+            .map(str::to_string),
+    })
+}
+
+/// Validate the choice values in a create or update payload.
+pub(crate) fn validate_payload_options(
+    logical_name: &str,
+    payload: &HashMap<String, Value>,
+    option_sets: &OptionSetMap,
+) -> Result<(), String> {
+    for (column, value) in payload {
+        let Some(options) = option_sets.get(column) else {
+            continue;
+        };
+        let values = match value {
+            Value::Number(number) => number.as_i64().map(|value| vec![value]).ok_or_else(|| {
+                invalid_value_error(logical_name, column, &number.to_string(), options)
+            })?,
+            Value::String(values) => values
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value
+                        .parse::<i64>()
+                        .map_err(|_| invalid_value_error(logical_name, column, value, options))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => continue,
+        };
+        check_values(logical_name, column, &values, options)?;
+    }
+
+    Ok(())
+}
+
+/// Validate the choice values on a typed entity before it is written.
+pub(crate) fn validate_entity_options(
+    entity: &Entity,
+    option_sets: &OptionSetMap,
+) -> Result<(), String> {
+    for (column, value) in &entity.attributes {
+        let Some(options) = option_sets.get(column) else {
+            continue;
+        };
+        let values = match value {
+            RowValue::OptionSetValue(option) => vec![i64::from(option.value)],
+            RowValue::OptionSetValueCollection(collection) => {
+                collection.values.iter().copied().map(i64::from).collect()
+            }
+            RowValue::Int(value) => vec![*value],
+            _ => continue,
+        };
+        check_values(&entity.logical_name, column, &values, options)?;
+    }
+
+    Ok(())
+}
+
+/// Value of the option labeled `label` in the choice column `column`, comparing labels in the
+/// calling user's language without regard to case.
+pub(crate) fn option_value_by_label(
+    logical_name: &str,
+    column: &str,
+    label: &str,
+    option_sets: &OptionSetMap,
+) -> Result<i32, String> {
+    let options = option_sets
+        .get(column)
+        .ok_or_else(|| format!("'{column}' is not a choice column on '{logical_name}'"))?;
+    let label = label.trim();
+    let mut matches = options.iter().filter(|option| {
+        option
+            .user_label()
+            .is_some_and(|option_label| option_label.trim().eq_ignore_ascii_case(label))
+    });
+    match (matches.next(), matches.next()) {
+        (Some(option), None) => Ok(option.value),
+        (Some(_), Some(_)) => Err(format!(
+            "Label '{label}' matches more than one option of choice column '{column}' on '{logical_name}'; set the value instead"
+        )),
+        (None, _) => {
+            let valid = options
+                .iter()
+                .filter_map(OptionMetadata::user_label)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(format!(
+                "No option labeled '{label}' in choice column '{column}' on '{logical_name}'. Valid labels: {valid}"
+            ))
+        }
+    }
+}
+
+fn check_values(
+    logical_name: &str,
+    column: &str,
+    values: &[i64],
+    options: &[OptionMetadata],
+) -> Result<(), String> {
+    match values.iter().find(|value| {
+        !options
+            .iter()
+            .any(|option| i64::from(option.value) == **value)
+    }) {
+        Some(value) => Err(invalid_value_error(
+            logical_name,
+            column,
+            &value.to_string(),
+            options,
+        )),
+        None => Ok(()),
+    }
+}
+
+fn invalid_value_error(
+    logical_name: &str,
+    column: &str,
+    value: &str,
+    options: &[OptionMetadata],
+) -> String {
+    let valid = options
+        .iter()
+        .map(|option| match option.user_label() {
+            Some(label) => format!("{} ({label})", option.value),
+            None => option.value.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "Invalid value {value} for choice column '{column}' on '{logical_name}'. Valid values: {valid}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        option_value_by_label, parse_option_set_attributes, validate_entity_options,
+        validate_payload_options,
+    };
+    use crate::dataverse::entity::{Entity, OptionSetValue, Value};
+
+    fn option_sets() -> super::OptionSetMap {
+        parse_option_set_attributes(&json!({
+            "value": [
+                {
+                    "LogicalName": "preferredcontactmethodcode",
+                    "OptionSet": {
+                        "Options": [
+                            { "Value": 1, "Label": { "UserLocalizedLabel": { "Label": "Any" } } },
+                            { "Value": 2, "Label": { "UserLocalizedLabel": { "Label": "Email" } } }
+                        ]
+                    },
+                    "GlobalOptionSet": null
+                },
+                {
+                    "LogicalName": "cr_tags",
+                    "OptionSet": null,
+                    "GlobalOptionSet": { "Options": [ { "Value": 100, "Label": null } ] }
+                }
+            ]
+        }))
+        .expect("should parse")
+    }
+
+    #[test]
+    fn accepts_known_values_and_lists_valid_values_on_error() {
+        let option_sets = option_sets();
+        let valid = HashMap::from([
+            ("preferredcontactmethodcode".to_string(), json!(2)),
+            ("cr_tags".to_string(), json!("100")),
+            ("firstname".to_string(), json!("Ada")),
+        ]);
+        assert!(validate_payload_options("contact", &valid, &option_sets).is_ok());
+
+        let invalid = HashMap::from([("preferredcontactmethodcode".to_string(), json!(7))]);
+        let error = validate_payload_options("contact", &invalid, &option_sets)
+            .expect_err("should reject unknown option");
+        assert_eq!(
+            error,
+            "Invalid value 7 for choice column 'preferredcontactmethodcode' on 'contact'. Valid values: 1 (Any), 2 (Email)"
+        );
+    }
+
+    #[test]
+    fn validates_typed_entity_choice_values() {
+        let mut entity = Entity::new(Uuid::new_v4(), "contact", None);
+        entity.attributes.insert(
+            "preferredcontactmethodcode".to_string(),
+            Value::OptionSetValue(OptionSetValue {
+                value: 3,
+                name: None,
+            }),
+        );
+
+        assert!(validate_entity_options(&entity, &option_sets()).is_err());
+    }
+
+    #[test]
+    fn resolves_option_labels_and_lists_valid_labels_on_error() {
+        let option_sets = option_sets();
+        assert_eq!(
+            option_value_by_label(
+                "contact",
+                "preferredcontactmethodcode",
+                " email ",
+                &option_sets
+            ),
+            Ok(2)
+        );
+        assert_eq!(
+            option_value_by_label("contact", "preferredcontactmethodcode", "Fax", &option_sets),
+            Err("No option labeled 'Fax' in choice column 'preferredcontactmethodcode' on 'contact'. Valid labels: Any, Email".to_string())
+        );
+        assert_eq!(
+            option_value_by_label("contact", "firstname", "Ada", &option_sets),
+            Err("'firstname' is not a choice column on 'contact'".to_string())
+        );
+    }
+}
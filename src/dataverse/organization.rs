@@ -0,0 +1,140 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::entity::EntityReference;
+
+/// Identifying details for the Dataverse organization a client is connected to.
+#[derive(Debug, Clone)]
+pub struct OrganizationInfo {
+    /// Organization ID.
+    pub organization_id: Uuid,
+    /// Organization display name.
+    pub friendly_name: String,
+    /// Organization unique name.
+    pub unique_name: Option<String>,
+    /// URL name (the host prefix of the environment URL).
+    pub url_name: Option<String>,
+    /// Dataverse version, such as `9.2.24031.00190`.
+    pub version: Option<String>,
+    /// Power Platform environment ID.
+    pub environment_id: Option<String>,
+    /// Azure AD tenant ID that owns the organization.
+    pub tenant_id: Option<String>,
+    /// Geography the organization is hosted in.
+    pub geo: Option<String>,
+    /// Base language code (LCID).
+    pub language_code: Option<i32>,
+    /// Base currency lookup.
+    pub base_currency: Option<EntityReference>,
+}
+
+/// Combine the `RetrieveCurrentOrganization` detail and the `organization` row into one record.
+pub(crate) fn parse_organization_info(
+    current_organization: &Value,
+    organization_row: Option<&Value>,
+) -> Result<OrganizationInfo, String> {
+    let detail = current_organization
+        .get("Detail")
+        .ok_or_else(|| "RetrieveCurrentOrganization response missing Detail".to_string())?;
+
+    let organization_id = detail
+        .get("OrganizationId")
+        .and_then(|value| value.as_str())
+        .and_then(|value| Uuid::parse_str(value).ok())
+        .ok_or_else(|| "RetrieveCurrentOrganization response missing OrganizationId".to_string())?;
+
+    let friendly_name = string_field(detail, "FriendlyName")
+        .or_else(|| organization_row.and_then(|row| string_field(row, "name")))
+        .unwrap_or_default();
+
+    let base_currency = organization_row.and_then(|row| {
+        let id = row
+            .get("_basecurrencyid_value")
+            .and_then(|value| value.as_str())
+            .and_then(|value| Uuid::parse_str(value).ok())?;
+        Some(EntityReference {
+            id,
+            logical_name: "transactioncurrency".to_string(),
+            name: string_field(
+                row,
+                "_basecurrencyid_value@OData.Community.Display.V1.FormattedValue",
+            ),
+        })
+    });
+
+    Ok(OrganizationInfo {
+        organization_id,
+        friendly_name,
+        unique_name: string_field(detail, "UniqueName"),
+        url_name: string_field(detail, "UrlName"),
+        version: string_field(detail, "OrganizationVersion"),
+        environment_id: string_field(detail, "EnvironmentId"),
+        tenant_id: string_field(detail, "TenantId"),
+        geo: string_field(detail, "Geo"),
+        language_code: organization_row
+            .and_then(|row| row.get("languagecode"))
+            .and_then(|value| value.as_i64())
+            .and_then(|value| i32::try_from(value).ok()),
+        base_currency,
+    })
+}
+
+fn string_field(value: &Value, key: &str) -> Option<String> {
+    value
+        .get(key)
+        .and_then(|value| value.as_str())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::parse_organization_info;
+
+    #[test]
+    fn merges_current_organization_detail_with_organization_row() {
+        let detail = json!({
+            "Detail": {
+                "OrganizationId": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                "FriendlyName": "Contoso",
+                "UniqueName": "unq123",
+                "UrlName": "contoso",
+                "OrganizationVersion": "9.2.24031.00190",
+                "EnvironmentId": "env-1",
+                "TenantId": "tenant-1",
+                "Geo": "NA"
+            }
+        });
+        let row = json!({
+            "organizationid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+            "name": "Contoso",
+            "languagecode": 1033,
+            "_basecurrencyid_value": "11111111-2222-3333-4444-555555555555",
+            "_basecurrencyid_value@OData.Community.Display.V1.FormattedValue": "US Dollar"
+        });
+
+        let info = parse_organization_info(&detail, Some(&row)).expect("should parse");
+
+        assert_eq!(
+            info.organization_id,
+            Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid")
+        );
+        assert_eq!(info.friendly_name, "Contoso");
+        assert_eq!(info.version.as_deref(), Some("9.2.24031.00190"));
+        assert_eq!(info.language_code, Some(1033));
+        let currency = info.base_currency.expect("base currency");
+        assert_eq!(currency.logical_name, "transactioncurrency");
+        assert_eq!(currency.name.as_deref(), Some("US Dollar"));
+    }
+
+    #[test]
+    fn requires_organization_id() {
+        let error = parse_organization_info(&json!({ "Detail": {} }), None)
+            .expect_err("should reject missing id");
+
+        assert!(error.contains("OrganizationId"));
+    }
+}
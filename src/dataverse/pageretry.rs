@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use crate::dataverse::apierror::ApiError;
+use crate::dataverse::bulk::{is_transient_error, retry_delay};
+
+/// How multi-page reads retry a page that failed transiently, set with
+/// `ServiceClient::set_page_retry_policy`. Separate from `BulkOptions` retries, which cover
+/// writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRetryPolicy {
+    /// Retries of one page before the read fails. `0` turns page retries off.
+    pub attempts: u32,
+    /// Wait before the first retry. Later retries double it. A `Retry-After` sent with a
+    /// throttled or unavailable response is waited instead.
+    pub delay: Duration,
+}
+
+impl Default for PageRetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl PageRetryPolicy {
+    /// Never retry a page.
+    pub fn disabled() -> Self {
+        Self {
+            attempts: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Wait before retry `attempt` of a page that failed with `error`, or `None` when the error
+    /// is not transient or the retries are used up. The `Retry-After` of the error wins over the
+    /// doubling delay.
+    pub(crate) fn next_delay(&self, attempt: u32, error: &str) -> Option<Duration> {
+        (attempt <= self.attempts && is_transient_error(error)).then(|| {
+            ApiError::parse(error)
+                .and_then(|error| error.retry_after)
+                .unwrap_or_else(|| retry_delay(self.delay, attempt))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::PageRetryPolicy;
+
+    #[test]
+    fn retries_only_transient_errors_within_attempts() {
+        let policy = PageRetryPolicy {
+            attempts: 2,
+            delay: Duration::from_millis(100),
+        };
+
+        assert_eq!(
+            policy.next_delay(1, "Request failed: connection reset"),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            policy.next_delay(2, "Dataverse API error (503 Service Unavailable): busy"),
+            Some(Duration::from_millis(200))
+        );
+        assert_eq!(
+            policy.next_delay(3, "Request failed: connection reset"),
+            None
+        );
+        assert_eq!(
+            policy.next_delay(1, "Dataverse API error (400 Bad Request): bad fetch"),
+            None
+        );
+        assert_eq!(
+            PageRetryPolicy::disabled().next_delay(1, "Request failed: timeout"),
+            None
+        );
+    }
+
+    #[test]
+    fn waits_the_retry_after_of_throttled_pages() {
+        let policy = PageRetryPolicy {
+            attempts: 2,
+            delay: Duration::from_millis(100),
+        };
+
+        assert_eq!(
+            policy.next_delay(
+                1,
+                "Dataverse API error (429 Too Many Requests): slow down [retry after: 7s]"
+            ),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            policy.next_delay(
+                2,
+                "Dataverse API error (503 Service Unavailable): busy [client request id: x, retry after: 0s]"
+            ),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            policy.next_delay(
+                3,
+                "Dataverse API error (429 Too Many Requests): slow down [retry after: 7s]"
+            ),
+            None
+        );
+    }
+}
@@ -1,21 +1,24 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use log::warn;
 use rust_decimal::Decimal;
 use serde_json::Value;
 
+use crate::dataverse::activityparty::{ACTIVITY_PARTIES_SUFFIX, parse_activity_parties};
 use crate::dataverse::entity::Value::{
-    Boolean, DateTime as DateTimeValue, Decimal as DecimalValue,
+    Boolean, Date as DateValue, DateTime as DateTimeValue, Decimal as DecimalValue,
     EntityReference as EntityRefValue, Float, Guid as GuidValue, Int, Money as MoneyValue, Null,
-    OptionSetValue as OptionSetSingle, OptionSetValueCollection as OptionSetMany, String,
+    OptionSetValue as OptionSetSingle, OptionSetValueCollection as OptionSetMany,
+    PartyList as PartyListValue, String,
 };
 use crate::dataverse::entity::{
     Attribute, Entity, EntityReference, Money, OptionSetValue, OptionSetValueCollection,
-    Value as RowValue,
+    TRANSACTION_CURRENCY_ATTRIBUTE, Value as RowValue,
 };
-use crate::dataverse::entityattribute::EntityAttribute;
+use crate::dataverse::entityattribute::{DateTimeBehavior, EntityAttribute};
+use crate::dataverse::valueconverter::ValueConverter;
 use uuid::Uuid;
 
 const FORMATTED_VALUE_SUFFIX: &str = "@OData.Community.Display.V1.FormattedValue";
@@ -49,6 +52,35 @@ pub(crate) fn parse_entities_from_response(
     entity_set: &str,
     primary_id_attribute: Option<&str>,
     entity_attributes: Option<&HashMap<std::string::String, EntityAttribute>>,
+    converter: Option<&dyn ValueConverter>,
+) -> Result<Vec<Entity>, std::string::String> {
+    parse_rows(
+        json,
+        entity_set,
+        primary_id_attribute,
+        entity_attributes,
+        converter,
+        true,
+    )
+}
+
+/// Parse aggregate rows, such as `$apply` results, which have no primary id. Rows get a nil id.
+pub(crate) fn parse_aggregate_rows_from_response(
+    json: &Value,
+    entity_set: &str,
+    entity_attributes: Option<&HashMap<std::string::String, EntityAttribute>>,
+    converter: Option<&dyn ValueConverter>,
+) -> Result<Vec<Entity>, std::string::String> {
+    parse_rows(json, entity_set, None, entity_attributes, converter, false)
+}
+
+fn parse_rows(
+    json: &Value,
+    entity_set: &str,
+    primary_id_attribute: Option<&str>,
+    entity_attributes: Option<&HashMap<std::string::String, EntityAttribute>>,
+    converter: Option<&dyn ValueConverter>,
+    require_id: bool,
 ) -> Result<Vec<Entity>, std::string::String> {
     let response_object = json
         .as_object()
@@ -74,17 +106,17 @@ pub(crate) fn parse_entities_from_response(
         // Fast-path parsing assumes the usual `<logicalname>id` convention unless metadata already
         // told us the real primary id attribute. Failing here is preferable to silently producing
         // malformed entities with missing ids.
-        let id_value = record
-            .get(&primary_id_key)
-            .and_then(|value| value.as_str())
-            .ok_or_else(|| {
-                format!(
+        let id = match record.get(&primary_id_key).and_then(|value| value.as_str()) {
+            Some(id_value) => Uuid::parse_str(id_value)
+                .map_err(|_| "Invalid response from Dataverse".to_string())?,
+            None if !require_id => Uuid::nil(),
+            None => {
+                return Err(format!(
                     "Primary id '{}' not found for entity set '{}'",
                     primary_id_key, entity_set
-                )
-            })?;
-        let id =
-            Uuid::parse_str(id_value).map_err(|_| "Invalid response from Dataverse".to_string())?;
+                ));
+            }
+        };
 
         let name = record
             .get("name")
@@ -92,14 +124,36 @@ pub(crate) fn parse_entities_from_response(
             .map(|value| value.to_string());
 
         let mut entity = Entity::new(id, &logical_name, name);
+        entity.etag = record
+            .get("@odata.etag")
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string());
 
         let mut lookup_keys: Vec<(std::string::String, std::string::String)> = Vec::new();
 
         for (key, value) in record {
+            // Annotations describe a column or the row rather than holding a value; the ones this
+            // crate understands are folded into typed values or `Entity` fields below.
             if key.contains('@') {
                 continue;
             }
 
+            if key.ends_with(ACTIVITY_PARTIES_SUFFIX)
+                && let Some(parties) = value.as_array()
+            {
+                for (column, party) in parse_activity_parties(parties) {
+                    match entity
+                        .attributes
+                        .entry(column.to_string())
+                        .or_insert_with(|| PartyListValue(Vec::new()))
+                    {
+                        PartyListValue(list) => list.push(party),
+                        _ => warn!("Party list column '{column}' is also returned directly"),
+                    }
+                }
+                continue;
+            }
+
             if let Some(base) = lookup_base_attribute(key) {
                 let id = value
                     .as_str()
@@ -120,6 +174,7 @@ pub(crate) fn parse_entities_from_response(
                 entity_attributes.and_then(|attributes| {
                     attributes.get(&normalize_attribute_name(key))
                 }),
+                converter,
             )
                 .map_err(|_| "Invalid response from Dataverse".to_string())?;
 
@@ -176,6 +231,7 @@ pub(crate) fn parse_entities_from_response(
 
         apply_lookup_attribute_annotations(&mut entity.attributes, record);
         apply_formatted_value_names(&mut entity.attributes, record);
+        apply_money_details(&mut entity.attributes, record);
 
         entities.push(entity);
     }
@@ -183,6 +239,17 @@ pub(crate) fn parse_entities_from_response(
     Ok(entities)
 }
 
+/// Store each record of a list response in `Entity::raw` of the row parsed from it, which
+/// parsing produces in the same order.
+pub(crate) fn attach_raw_rows(json: &Value, entities: &mut [Entity]) {
+    let Some(records) = json.get("value").and_then(|value| value.as_array()) else {
+        return;
+    };
+    for (entity, record) in entities.iter_mut().zip(records) {
+        entity.raw = Some(record.clone());
+    }
+}
+
 /// Count the number of records in a Dataverse list response.
 pub(crate) fn parse_record_count_from_response(json: &Value) -> Result<usize, std::string::String> {
     let response_object = json
@@ -204,17 +271,28 @@ fn add_attribute(
     key: &str,
     value: &Value,
     attribute: Option<&EntityAttribute>,
+    converter: Option<&dyn ValueConverter>,
 ) -> Result<bool, std::string::String> {
     if value.is_null() {
         attributes.insert(key.to_string(), Null);
         return Ok(true);
     }
 
-    if let Some(attribute_type) = attribute_type_key(attribute) {
-        if let Some(parsed) = parse_typed_attribute_value(value, attribute_type)? {
-            attributes.insert(key.to_string(), parsed);
-            return Ok(true);
-        }
+    if let Some(converted) = converter.and_then(|converter| converter.convert(key, value, attribute))
+    {
+        attributes.insert(key.to_string(), converted);
+        return Ok(true);
+    }
+
+    if let Some(attribute_type) = attribute_type_key(attribute)
+        && let Some(parsed) = parse_typed_attribute_value(
+            value,
+            attribute_type,
+            attribute.and_then(|attribute| attribute.date_time_behavior),
+        )?
+    {
+        attributes.insert(key.to_string(), parsed);
+        return Ok(true);
     }
 
     if value.is_i64() {
@@ -267,8 +345,12 @@ fn add_attribute(
 fn parse_typed_attribute_value(
     value: &Value,
     attribute_type: &str,
+    date_time_behavior: Option<DateTimeBehavior>,
 ) -> Result<Option<RowValue>, std::string::String> {
     match attribute_type {
+        "DateTime" | "DateTimeType" if date_time_behavior == Some(DateTimeBehavior::DateOnly) => {
+            Ok(parse_date_value(value).map(DateValue))
+        }
         "BigInt" | "BigIntType" => Ok(parse_i64_value(value).map(Int)),
         "Boolean" | "BooleanType" => Ok(parse_bool_value(value).map(Boolean)),
         "DateTime" | "DateTimeType" => Ok(parse_datetime_value(value).map(DateTimeValue)),
@@ -279,7 +361,7 @@ fn parse_typed_attribute_value(
             Ok(parse_guid_value(value).map(GuidValue))
         }
         "Money" | "MoneyType" => Ok(parse_decimal_value(value).map(|value| {
-            MoneyValue(Money { value })
+            MoneyValue(Money::new(value))
         })),
         "Picklist" | "PicklistType" | "State" | "StateType" | "Status" | "StatusType" => {
             Ok(parse_i32_value(value).map(|value| {
@@ -313,7 +395,7 @@ fn parse_typed_attribute_value(
     }
 }
 
-fn attribute_type_key(attribute: Option<&EntityAttribute>) -> Option<&str> {
+pub(crate) fn attribute_type_key(attribute: Option<&EntityAttribute>) -> Option<&str> {
     attribute
         .and_then(|attribute| {
             attribute
@@ -332,6 +414,13 @@ fn parse_i64_value(value: &Value) -> Option<i64> {
     value
         .as_i64()
         .or_else(|| value.as_u64().and_then(|value| i64::try_from(value).ok()))
+        // Aggregates and some computed columns serialize whole numbers as `10.0`.
+        .or_else(|| {
+            value
+                .as_f64()
+                .filter(|value| value.fract() == 0.0 && value.abs() < i64::MAX as f64)
+                .map(|value| value as i64)
+        })
 }
 
 fn parse_i32_value(value: &Value) -> Option<i32> {
@@ -364,29 +453,32 @@ fn parse_datetime_value(value: &Value) -> Option<DateTime<Utc>> {
         .map(|value| value.with_timezone(&Utc))
 }
 
+fn parse_date_value(value: &Value) -> Option<NaiveDate> {
+    // DateOnly columns are documented as `yyyy-MM-dd`, but older API versions and some
+    // projections still return a midnight timestamp, so accept both.
+    let value = value.as_str()?;
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(value).ok().map(|value| value.date_naive()))
+}
+
 fn parse_guid_value(value: &Value) -> Option<Uuid> {
     value
         .as_str()
         .and_then(|value| Uuid::parse_str(value).ok())
 }
 
+/// Multi-select values arrive as a comma-separated string such as `"1,3"`. A value that is not
+/// a list of whole numbers is left to the untyped parsing rather than losing the bad entries.
 fn parse_multi_select_value(value: &Value) -> Option<Vec<i32>> {
     match value {
-        Value::String(value) => {
-            let parsed: Vec<i32> = value
-                .split(',')
-                .map(str::trim)
-                .filter(|value| !value.is_empty())
-                .filter_map(|value| value.parse::<i32>().ok())
-                .collect();
-            Some(parsed)
-        }
-        Value::Array(values) => Some(
-            values
-                .iter()
-                .filter_map(parse_i32_value)
-                .collect(),
-        ),
+        Value::String(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(|value| value.parse::<i32>().ok())
+            .collect(),
+        Value::Array(values) => values.iter().map(parse_i32_value).collect(),
         _ => None,
     }
 }
@@ -433,6 +525,57 @@ fn apply_formatted_value_names(
     }
 }
 
+fn apply_money_details(
+    attributes: &mut HashMap<Attribute, RowValue>,
+    record: &serde_json::Map<std::string::String, Value>,
+) {
+    // Dataverse returns money columns as bare numbers with the base-currency amount in a
+    // `{column}_base` sibling and the currency on the row's `transactioncurrencyid` lookup. Fold
+    // both into `Money` so callers see the amount, base amount, and currency together. Numeric
+    // values with a `_base` sibling are treated as money even without attribute metadata.
+    let currency = match attributes.get(TRANSACTION_CURRENCY_ATTRIBUTE) {
+        Some(EntityRefValue(reference)) => Some(reference.clone()),
+        _ => None,
+    };
+
+    let money_columns: Vec<std::string::String> = attributes
+        .keys()
+        .filter(|key| !key.ends_with("_base"))
+        .filter(|key| {
+            matches!(attributes.get(*key), Some(MoneyValue(_)))
+                || record.contains_key(&format!("{key}_base"))
+        })
+        .cloned()
+        .collect();
+
+    for column in money_columns {
+        let base_value = record
+            .get(&format!("{column}_base"))
+            .and_then(parse_decimal_value);
+
+        let Some(attribute) = attributes.get_mut(&column) else {
+            continue;
+        };
+
+        let value = match attribute {
+            MoneyValue(money) => money.value,
+            Int(_) | Float(_) | DecimalValue(_) => {
+                match record.get(&column).and_then(parse_decimal_value) {
+                    Some(value) => value,
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+
+        *attribute = MoneyValue(Money {
+            value,
+            base_value,
+            currency: currency.clone(),
+        });
+    }
+}
+
 fn apply_lookup_attribute_annotations(
     attributes: &mut HashMap<Attribute, RowValue>,
     record: &serde_json::Map<std::string::String, Value>,
@@ -515,10 +658,12 @@ mod tests {
     use serde_json::json;
 
     use super::{
-        extract_paging_cookie, infer_logical_name, parse_entities_from_response,
-        parse_more_records, parse_record_count_from_response,
+        attach_raw_rows, extract_paging_cookie, infer_logical_name,
+        parse_aggregate_rows_from_response, parse_entities_from_response, parse_more_records,
+        parse_record_count_from_response,
     };
-    use crate::dataverse::entityattribute::{AttributeTypeName, EntityAttribute};
+    use crate::dataverse::entityattribute::{AttributeTypeName, DateTimeBehavior, EntityAttribute};
+    use crate::dataverse::valueconverter::ValueConverter;
 
     #[test]
     fn parses_more_records_from_bool_and_string_annotations() {
@@ -563,6 +708,7 @@ mod tests {
             EntityAttribute {
                 logical_name: "statecode".to_string(),
                 schema_name: "StateCode".to_string(),
+                display_name: None,
                 attribute_type: Some("State".to_string()),
                 attribute_type_name: Some(AttributeTypeName {
                     value: Some("StateType".to_string()),
@@ -570,7 +716,10 @@ mod tests {
                 is_custom_attribute: Some(false),
                 is_valid_odata_attribute: Some(true),
                 is_valid_for_read: Some(true),
+                is_valid_for_create: Some(false),
                 is_valid_for_update: Some(false),
+                date_time_behavior: None,
+                detail: None,
             },
         )]);
 
@@ -580,6 +729,7 @@ mod tests {
                 "contacts",
                 Some("contactid"),
                 Some(&entity_attributes),
+                None,
             )
             .expect("should parse entities");
 
@@ -599,6 +749,214 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn folds_base_amount_and_currency_into_money_values() {
+        let json = json!({
+            "value": [
+                {
+                    "opportunityid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                    "estimatedvalue": 123.45,
+                    "estimatedvalue_base": 98.76,
+                    "_transactioncurrencyid_value": "11111111-2222-3333-4444-555555555555",
+                    "_transactioncurrencyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "transactioncurrency",
+                    "_transactioncurrencyid_value@OData.Community.Display.V1.FormattedValue": "Euro"
+                }
+            ]
+        });
+
+        let entities = parse_entities_from_response(&json, "opportunities", None, None, None)
+            .expect("should parse entities");
+
+        let Some(crate::dataverse::entity::Value::Money(money)) =
+            entities[0].attributes.get("estimatedvalue")
+        else {
+            panic!("expected money value");
+        };
+        assert_eq!(money.value.to_string(), "123.45");
+        assert_eq!(money.base_value.map(|value| value.to_string()).as_deref(), Some("98.76"));
+        assert_eq!(
+            money.currency.as_ref().and_then(|currency| currency.name.as_deref()),
+            Some("Euro")
+        );
+    }
+
+    #[cfg(feature = "decimal-precision")]
+    #[test]
+    fn money_beyond_f64_precision_parses_exactly() {
+        let json: serde_json::Value = serde_json::from_str(
+            r#"{"value":[{"opportunityid":"aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee","estimatedvalue":922337203685477.5807,"estimatedvalue_base":922337203685477.5807}]}"#,
+        )
+        .expect("should parse json");
+
+        let entities = parse_entities_from_response(&json, "opportunities", None, None, None)
+            .expect("should parse entities");
+
+        let Some(crate::dataverse::entity::Value::Money(money)) =
+            entities[0].attributes.get("estimatedvalue")
+        else {
+            panic!("expected money value");
+        };
+        assert_eq!(money.value.to_string(), "922337203685477.5807");
+    }
+
+    #[test]
+    fn expanded_activity_parties_become_party_lists() {
+        let json = json!({
+            "value": [
+                {
+                    "activityid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                    "email_activity_parties": [
+                        {
+                            "participationtypemask": 1,
+                            "_partyid_value": "11111111-1111-1111-1111-111111111111",
+                            "_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "systemuser"
+                        },
+                        {
+                            "participationtypemask": 2,
+                            "_partyid_value": "22222222-2222-2222-2222-222222222222",
+                            "_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "contact"
+                        },
+                        {
+                            "participationtypemask": 2,
+                            "_partyid_value": "33333333-3333-3333-3333-333333333333",
+                            "_partyid_value@Microsoft.Dynamics.CRM.lookuplogicalname": "account"
+                        }
+                    ]
+                }
+            ]
+        });
+
+        let entities =
+            parse_entities_from_response(&json, "emails", Some("activityid"), None, None)
+                .expect("should parse entities");
+
+        let party_names = |column: &str| match entities[0].attributes.get(column) {
+            Some(crate::dataverse::entity::Value::PartyList(parties)) => parties
+                .iter()
+                .map(|party| party.logical_name.as_str())
+                .collect::<Vec<_>>(),
+            other => panic!("expected a party list, got {other:?}"),
+        };
+        assert_eq!(party_names("from"), vec!["systemuser"]);
+        assert_eq!(party_names("to"), vec!["contact", "account"]);
+        assert!(
+            !entities[0]
+                .attributes
+                .contains_key("email_activity_parties")
+        );
+    }
+
+    #[test]
+    fn date_only_columns_parse_as_dates() {
+        let json = json!({
+            "value": [
+                {
+                    "contactid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                    "birthdate": "1990-04-12",
+                    "createdon": "2024-01-02T03:04:05Z",
+                    "numberofchildren": 2.0
+                }
+            ]
+        });
+        let attribute = |logical_name: &str, behavior: DateTimeBehavior| EntityAttribute {
+            logical_name: logical_name.to_string(),
+            schema_name: logical_name.to_string(),
+            display_name: None,
+            attribute_type: Some("DateTime".to_string()),
+            attribute_type_name: None,
+            is_custom_attribute: Some(false),
+            is_valid_odata_attribute: Some(true),
+            is_valid_for_read: Some(true),
+            is_valid_for_create: Some(true),
+            is_valid_for_update: Some(true),
+            date_time_behavior: Some(behavior),
+            detail: None,
+        };
+        let entity_attributes = HashMap::from([
+            (
+                "birthdate".to_string(),
+                attribute("birthdate", DateTimeBehavior::DateOnly),
+            ),
+            (
+                "createdon".to_string(),
+                attribute("createdon", DateTimeBehavior::UserLocal),
+            ),
+            (
+                "numberofchildren".to_string(),
+                EntityAttribute {
+                    attribute_type: Some("Integer".to_string()),
+                    date_time_behavior: None,
+                    ..attribute("numberofchildren", DateTimeBehavior::UserLocal)
+                },
+            ),
+        ]);
+
+        let entities = parse_entities_from_response(
+            &json,
+            "contacts",
+            Some("contactid"),
+            Some(&entity_attributes),
+            None,
+        )
+        .expect("should parse entities");
+
+        assert!(matches!(
+            entities[0].attributes.get("birthdate"),
+            Some(crate::dataverse::entity::Value::Date(date)) if date.to_string() == "1990-04-12"
+        ));
+        assert!(matches!(
+            entities[0].attributes.get("createdon"),
+            Some(crate::dataverse::entity::Value::DateTime(_))
+        ));
+        assert!(matches!(
+            entities[0].attributes.get("numberofchildren"),
+            Some(crate::dataverse::entity::Value::Int(2))
+        ));
+    }
+
+    #[test]
+    fn value_converter_overrides_built_in_conversion() {
+        struct DecimalText;
+
+        impl ValueConverter for DecimalText {
+            fn convert(
+                &self,
+                _key: &str,
+                value: &serde_json::Value,
+                _attribute: Option<&EntityAttribute>,
+            ) -> Option<crate::dataverse::entity::Value> {
+                value
+                    .as_f64()
+                    .filter(|_| !value.is_i64())
+                    .map(|_| crate::dataverse::entity::Value::String(value.to_string()))
+            }
+        }
+
+        let json = json!({
+            "value": [
+                {
+                    "opportunityid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                    "estimatedvalue": 123.45,
+                    "estimatedvalue_base": 98.76,
+                    "closeprobability": 40
+                }
+            ]
+        });
+
+        let entities =
+            parse_entities_from_response(&json, "opportunities", None, None, Some(&DecimalText))
+                .expect("should parse entities");
+
+        assert!(matches!(
+            entities[0].attributes.get("estimatedvalue"),
+            Some(crate::dataverse::entity::Value::String(text)) if text == "123.45"
+        ));
+        assert!(matches!(
+            entities[0].attributes.get("closeprobability"),
+            Some(crate::dataverse::entity::Value::Int(40))
+        ));
+    }
+
     #[test]
     fn record_count_uses_value_array_length() {
         let count = parse_record_count_from_response(&json!({
@@ -609,10 +967,62 @@ mod tests {
         assert_eq!(count, 3);
     }
 
+    #[test]
+    fn separates_etag_and_annotations_from_attributes() {
+        let json = json!({
+            "value": [
+                {
+                    "@odata.etag": "W/\"1234567\"",
+                    "accountid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee",
+                    "name": "Contoso",
+                    "name@OData.Community.Display.V1.FormattedValue": "Contoso",
+                    "revenue@Microsoft.Dynamics.CRM.associatednavigationproperty": "revenue"
+                }
+            ]
+        });
+
+        let entities = parse_entities_from_response(&json, "accounts", None, None, None)
+            .expect("should parse entities");
+
+        let entity = &entities[0];
+        assert_eq!(entity.logical_name, "account");
+        assert_eq!(entity.etag.as_deref(), Some("W/\"1234567\""));
+        let mut keys = entity
+            .attributes
+            .keys()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, vec!["accountid", "name"]);
+
+        let mut entities = entities;
+        attach_raw_rows(&json, &mut entities);
+        assert_eq!(entities[0].raw.as_ref(), Some(&json["value"][0]));
+    }
+
     #[test]
     fn infer_logical_name_handles_common_entity_set_pluralization() {
         assert_eq!(infer_logical_name("contacts"), "contact");
         assert_eq!(infer_logical_name("categories"), "category");
         assert_eq!(infer_logical_name("boxes"), "box");
     }
+
+    #[test]
+    fn parses_aggregate_rows_without_primary_id() {
+        let json = json!({
+            "value": [
+                {"industrycode": 1, "total": 1500, "@odata.id": null},
+                {"industrycode": 2, "total": 250}
+            ]
+        });
+
+        assert!(parse_entities_from_response(&json, "accounts", None, None, None).is_err());
+        let rows = parse_aggregate_rows_from_response(&json, "accounts", None, None)
+            .expect("should parse");
+
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].id.is_nil());
+        assert_eq!(rows[0].logical_name, "account");
+        assert!(rows[1].attributes.contains_key("total"));
+    }
 }
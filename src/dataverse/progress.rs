@@ -0,0 +1,145 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Progress of a multi-page retrieve or bulk write, reported after each page or batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Pages retrieved or batches completed so far.
+    pub pages: usize,
+    /// Records retrieved or requests processed so far.
+    pub records: usize,
+    /// Records the operation will process in total, when known up front.
+    pub total_records: Option<usize>,
+    /// Time since the operation started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Average records per second since the operation started, `0.0` before any time has passed.
+    pub fn records_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            self.records as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Callback that receives `Progress` updates, such as one that redraws a progress bar. It runs
+/// on the task driving the operation, so it should return quickly.
+#[derive(Clone)]
+pub struct ProgressCallback(Arc<dyn Fn(&Progress) + Send + Sync>);
+
+impl ProgressCallback {
+    /// Wrap a closure called after each page or batch.
+    pub fn new(callback: impl Fn(&Progress) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+
+    /// Call the callback with `progress`.
+    pub fn report(&self, progress: &Progress) {
+        (self.0)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ProgressCallback(..)")
+    }
+}
+
+/// Counts pages and records for an operation and reports them to an optional callback.
+pub(crate) struct ProgressTracker<'a> {
+    callback: Option<&'a ProgressCallback>,
+    started: Instant,
+    pages: usize,
+    records: usize,
+    total_records: Option<usize>,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub(crate) fn new(
+        callback: Option<&'a ProgressCallback>,
+        total_records: Option<usize>,
+    ) -> Self {
+        Self {
+            callback,
+            started: Instant::now(),
+            pages: 0,
+            records: 0,
+            total_records,
+        }
+    }
+
+    /// Count a finished page or batch of `records` records and report the totals.
+    pub(crate) fn page_done(&mut self, records: usize) {
+        self.pages += 1;
+        self.records += records;
+        if let Some(callback) = self.callback {
+            callback.report(&self.progress());
+        }
+    }
+
+    pub(crate) fn progress(&self) -> Progress {
+        Progress {
+            pages: self.pages,
+            records: self.records,
+            total_records: self.total_records,
+            elapsed: self.started.elapsed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::{Progress, ProgressCallback, ProgressTracker};
+
+    #[test]
+    fn rate_is_records_per_elapsed_second() {
+        let progress = Progress {
+            pages: 2,
+            records: 500,
+            total_records: None,
+            elapsed: Duration::from_secs(2),
+        };
+
+        assert_eq!(progress.records_per_second(), 250.0);
+        assert_eq!(
+            Progress {
+                elapsed: Duration::ZERO,
+                ..progress
+            }
+            .records_per_second(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn tracker_reports_running_totals() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let seen = Arc::clone(&seen);
+            ProgressCallback::new(move |progress| {
+                seen.lock().unwrap().push((
+                    progress.pages,
+                    progress.records,
+                    progress.total_records,
+                ));
+            })
+        };
+
+        let mut tracker = ProgressTracker::new(Some(&callback), Some(7));
+        tracker.page_done(5);
+        tracker.page_done(2);
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(1, 5, Some(7)), (2, 7, Some(7))]
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::url::{encode_string_literal, row_path};
+use crate::dataverse::writebuilder::EntityWriteBuilder;
+
+/// Columns read for each `systemuser`.
+pub(crate) const SYSTEM_USER_COLUMNS: &str = "systemuserid,fullname,firstname,lastname,domainname,internalemailaddress,azureactivedirectoryobjectid,_businessunitid_value,isdisabled";
+
+/// Columns read for each `team`.
+pub(crate) const TEAM_COLUMNS: &str = "teamid,name,teamtype,_businessunitid_value,_administratorid_value,azureactivedirectoryobjectid";
+
+/// A `systemuser` row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemUser {
+    /// User ID.
+    #[serde(rename = "systemuserid")]
+    pub system_user_id: Uuid,
+    /// Full name.
+    #[serde(rename = "fullname", default)]
+    pub full_name: Option<String>,
+    /// First name.
+    #[serde(rename = "firstname", default)]
+    pub first_name: Option<String>,
+    /// Last name.
+    #[serde(rename = "lastname", default)]
+    pub last_name: Option<String>,
+    /// Sign-in name, usually the user principal name.
+    #[serde(rename = "domainname", default)]
+    pub domain_name: Option<String>,
+    /// Primary email address.
+    #[serde(rename = "internalemailaddress", default)]
+    pub internal_email_address: Option<String>,
+    /// Microsoft Entra ID object ID of the user.
+    #[serde(rename = "azureactivedirectoryobjectid", default)]
+    pub azure_ad_object_id: Option<Uuid>,
+    /// Business unit the user belongs to.
+    #[serde(rename = "_businessunitid_value")]
+    pub business_unit_id: Uuid,
+    /// True if the user is disabled.
+    #[serde(rename = "isdisabled", default)]
+    pub is_disabled: bool,
+}
+
+/// Values for creating a `systemuser` with `ServiceClient::create_system_user`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewSystemUser {
+    /// Sign-in name, usually the user principal name.
+    pub domain_name: String,
+    /// First name.
+    pub first_name: String,
+    /// Last name.
+    pub last_name: String,
+    /// Primary email address.
+    pub internal_email_address: Option<String>,
+    /// Microsoft Entra ID object ID, linking the row to an existing Entra ID user.
+    pub azure_ad_object_id: Option<Uuid>,
+    /// Business unit the user belongs to.
+    pub business_unit_id: Uuid,
+}
+
+impl NewSystemUser {
+    /// The create payload, binding `businessunitid` to the business unit.
+    pub fn to_attributes(&self) -> Result<HashMap<String, Value>, String> {
+        let mut builder = EntityWriteBuilder::new()
+            .set_string("domainname", &self.domain_name)
+            .set_string("firstname", &self.first_name)
+            .set_string("lastname", &self.last_name)
+            .set_lookup("businessunitid", "businessunits", self.business_unit_id);
+        if let Some(email) = &self.internal_email_address {
+            builder = builder.set_string("internalemailaddress", email);
+        }
+        if let Some(object_id) = self.azure_ad_object_id {
+            builder = builder.set_string("azureactivedirectoryobjectid", object_id.to_string());
+        }
+        builder.build()
+    }
+}
+
+/// Values for creating a `businessunit` with `ServiceClient::create_business_unit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewBusinessUnit {
+    /// Business unit name.
+    pub name: String,
+    /// Parent business unit. Only the root business unit has no parent, and it already exists.
+    pub parent_business_unit_id: Uuid,
+}
+
+impl NewBusinessUnit {
+    /// The create payload, binding `parentbusinessunitid` to the parent.
+    pub fn to_attributes(&self) -> Result<HashMap<String, Value>, String> {
+        EntityWriteBuilder::new()
+            .set_string("name", &self.name)
+            .set_lookup(
+                "parentbusinessunitid",
+                "businessunits",
+                self.parent_business_unit_id,
+            )
+            .build()
+    }
+}
+
+/// The `teamtype` of a team.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "i32", into = "i32")]
+pub enum TeamType {
+    /// A team that can own rows.
+    Owner,
+    /// A team used to share individual rows.
+    Access,
+    /// A team whose members come from a Microsoft Entra ID security group.
+    EntraIdSecurityGroup,
+    /// A team whose members come from a Microsoft Entra ID Office group.
+    EntraIdOfficeGroup,
+    /// A value this crate does not know.
+    Other(i32),
+}
+
+impl From<i32> for TeamType {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => Self::Owner,
+            1 => Self::Access,
+            2 => Self::EntraIdSecurityGroup,
+            3 => Self::EntraIdOfficeGroup,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl From<TeamType> for i32 {
+    fn from(value: TeamType) -> Self {
+        match value {
+            TeamType::Owner => 0,
+            TeamType::Access => 1,
+            TeamType::EntraIdSecurityGroup => 2,
+            TeamType::EntraIdOfficeGroup => 3,
+            TeamType::Other(other) => other,
+        }
+    }
+}
+
+/// A `team` row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Team {
+    /// Team ID.
+    #[serde(rename = "teamid")]
+    pub team_id: Uuid,
+    /// Team name.
+    #[serde(default)]
+    pub name: String,
+    /// Team type.
+    #[serde(rename = "teamtype")]
+    pub team_type: TeamType,
+    /// Business unit the team belongs to.
+    #[serde(rename = "_businessunitid_value")]
+    pub business_unit_id: Uuid,
+    /// User who administers the team.
+    #[serde(rename = "_administratorid_value", default)]
+    pub administrator_id: Option<Uuid>,
+    /// Microsoft Entra ID group of a group team.
+    #[serde(rename = "azureactivedirectoryobjectid", default)]
+    pub azure_ad_object_id: Option<Uuid>,
+}
+
+/// Values for creating a `team` with `ServiceClient::create_team`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewTeam {
+    /// Team name.
+    pub name: String,
+    /// Business unit the team belongs to.
+    pub business_unit_id: Uuid,
+    /// Team type.
+    pub team_type: TeamType,
+    /// User who administers the team. Dataverse uses the caller when `None`.
+    pub administrator_id: Option<Uuid>,
+    /// Microsoft Entra ID group of a group team. Required for the group team types.
+    pub azure_ad_object_id: Option<Uuid>,
+}
+
+impl NewTeam {
+    /// The create payload, binding `businessunitid` and `administratorid`.
+    pub fn to_attributes(&self) -> Result<HashMap<String, Value>, String> {
+        let group_team = matches!(
+            self.team_type,
+            TeamType::EntraIdSecurityGroup | TeamType::EntraIdOfficeGroup
+        );
+        if group_team && self.azure_ad_object_id.is_none() {
+            return Err(format!(
+                "Team '{}' is a Microsoft Entra ID group team and needs the group's object ID",
+                self.name
+            ));
+        }
+
+        let mut builder = EntityWriteBuilder::new()
+            .set_string("name", &self.name)
+            .set_optionset("teamtype", self.team_type.into())
+            .set_lookup("businessunitid", "businessunits", self.business_unit_id);
+        if let Some(administrator_id) = self.administrator_id {
+            builder = builder.set_lookup("administratorid", "systemusers", administrator_id);
+        }
+        if let Some(object_id) = self.azure_ad_object_id {
+            builder = builder.set_string("azureactivedirectoryobjectid", object_id.to_string());
+        }
+        builder.build()
+    }
+}
+
+/// Path that reads the row `id` of `entity_set` with `columns`.
+pub(crate) fn row_query(entity_set: &str, id: Uuid, columns: &str) -> String {
+    format!(
+        "{}?$select={columns}",
+        row_path(entity_set, id.as_hyphenated())
+    )
+}
+
+/// `systemusers` query for the user with the Microsoft Entra ID object ID `object_id`.
+pub(crate) fn user_by_object_id_query(object_id: Uuid) -> String {
+    format!(
+        "systemusers?$select={SYSTEM_USER_COLUMNS}&$filter=azureactivedirectoryobjectid eq {object_id}"
+    )
+}
+
+/// `teams` query for the team named `name` in the business unit `business_unit_id`.
+pub(crate) fn team_by_name_query(name: &str, business_unit_id: Uuid) -> String {
+    format!(
+        "teams?$select={TEAM_COLUMNS}&$filter=name eq {} and _businessunitid_value eq {business_unit_id}",
+        encode_string_literal(name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use super::{NewSystemUser, NewTeam, Team, TeamType, user_by_object_id_query};
+
+    #[test]
+    fn new_user_binds_business_unit() {
+        let business_unit_id =
+            Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid");
+        let attributes = NewSystemUser {
+            domain_name: "ana@contoso.com".to_string(),
+            first_name: "Ana".to_string(),
+            last_name: "Silva".to_string(),
+            internal_email_address: None,
+            azure_ad_object_id: None,
+            business_unit_id,
+        }
+        .to_attributes()
+        .expect("should build");
+
+        assert_eq!(
+            attributes["businessunitid@odata.bind"],
+            "/businessunits(11111111-2222-3333-4444-555555555555)"
+        );
+        assert_eq!(attributes["domainname"], "ana@contoso.com");
+        assert!(!attributes.contains_key("internalemailaddress"));
+        assert!(user_by_object_id_query(business_unit_id).ends_with(
+            "&$filter=azureactivedirectoryobjectid eq 11111111-2222-3333-4444-555555555555"
+        ));
+    }
+
+    #[test]
+    fn group_teams_need_an_object_id() {
+        let business_unit_id = Uuid::new_v4();
+        let mut team = NewTeam {
+            name: "Sales".to_string(),
+            business_unit_id,
+            team_type: TeamType::EntraIdSecurityGroup,
+            administrator_id: None,
+            azure_ad_object_id: None,
+        };
+        assert!(team.to_attributes().is_err());
+
+        team.team_type = TeamType::Access;
+        let attributes = team.to_attributes().expect("should build");
+        assert_eq!(attributes["teamtype"], Value::from(1));
+        assert!(!attributes.contains_key("administratorid@odata.bind"));
+
+        let parsed: Team = serde_json::from_value(json!({
+            "teamid": Uuid::new_v4(),
+            "name": "Sales",
+            "teamtype": 7,
+            "_businessunitid_value": business_unit_id
+        }))
+        .expect("should parse");
+        assert_eq!(parsed.team_type, TeamType::Other(7));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::dataverse::schema::parse_attributes;
+
+/// Tables and columns a query refers to, by logical name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct QueryReferences {
+    /// Tables, in the order they appear.
+    pub entities: Vec<String>,
+    /// `(table, column)` pairs, in the order they appear.
+    pub columns: Vec<(String, String)>,
+}
+
+/// Tables and columns named by a FetchXML query's `entity`, `link-entity`, `attribute`, `order`,
+/// and `condition` elements. Conditions on a linked table through `entityname` are resolved by
+/// alias.
+pub(crate) fn fetchxml_references(fetchxml: &str) -> Result<QueryReferences, String> {
+    let mut references = QueryReferences::default();
+    let mut aliases = HashMap::new();
+    let mut stack: Vec<String> = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = fetchxml[position..].find('<') {
+        let tag_start = position + offset;
+        let tag_end = fetchxml[tag_start..]
+            .find('>')
+            .ok_or_else(|| "FetchXML element is not closed".to_string())?
+            + tag_start;
+        let tag = &fetchxml[tag_start + 1..tag_end];
+        position = tag_end + 1;
+
+        if let Some(closing) = tag.strip_prefix('/') {
+            if matches!(closing.trim(), "entity" | "link-entity") {
+                stack.pop();
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(|ch: char| ch.is_whitespace()).unwrap_or(tag.len());
+        let attributes = parse_attributes(&tag[name_end..])?;
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_ascii_lowercase())
+        };
+        let current = stack.last().cloned();
+
+        match &tag[..name_end] {
+            element @ ("entity" | "link-entity") => {
+                let name = attribute("name")
+                    .ok_or_else(|| format!("FetchXML <{element}> element has no name"))?;
+                references.entities.push(name.clone());
+                if element == "link-entity" {
+                    if let Some(from) = attribute("from") {
+                        references.columns.push((name.clone(), from));
+                    }
+                    if let (Some(parent), Some(to)) = (&current, attribute("to")) {
+                        references.columns.push((parent.clone(), to));
+                    }
+                    if let Some(alias) = attribute("alias") {
+                        aliases.insert(alias, name.clone());
+                    }
+                }
+                if !self_closing {
+                    stack.push(name);
+                }
+            }
+            "attribute" | "order" => {
+                let column = if tag.starts_with("order") {
+                    attribute("attribute")
+                } else {
+                    attribute("name")
+                };
+                if let (Some(entity), Some(column)) = (current, column) {
+                    references.columns.push((entity, column));
+                }
+            }
+            "condition" => {
+                let entity = match attribute("entityname") {
+                    Some(name) => aliases.get(&name).cloned().or(Some(name)),
+                    None => current,
+                };
+                if let (Some(entity), Some(column)) = (entity, attribute("attribute")) {
+                    references.columns.push((entity, column));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(references)
+}
+
+/// Columns named in the `$select` and `$orderby` options of an OData query, with lookup
+/// properties such as `_primarycontactid_value` reduced to their column names.
+pub(crate) fn odata_columns(query: &str) -> Vec<String> {
+    let query = query.trim_start_matches('?');
+    let mut columns = Vec::new();
+    for option in top_level_options(query) {
+        let (name, value) = option.split_once('=').unwrap_or((option, ""));
+        let value = urlencoding::decode(value)
+            .map(|value| value.into_owned())
+            .unwrap_or_else(|_| value.to_string());
+        let names = match name {
+            "$select" => value.split(',').map(str::trim).collect::<Vec<_>>(),
+            "$orderby" => value
+                .split(',')
+                .filter_map(|item| item.split_whitespace().next())
+                .collect(),
+            _ => continue,
+        };
+        columns.extend(
+            names
+                .into_iter()
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    name.strip_prefix('_')
+                        .and_then(|name| name.strip_suffix("_value"))
+                        .unwrap_or(name)
+                        .to_ascii_lowercase()
+                }),
+        );
+    }
+    columns
+}
+
+/// Split query options on `&`, leaving options nested in `$expand` parentheses whole.
+fn top_level_options(query: &str) -> Vec<&str> {
+    let mut options = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, ch) in query.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '&' if depth == 0 => {
+                options.push(&query[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    options.push(&query[start..]);
+    options
+}
+
+/// Error for a table name that matches no table, suggesting the closest one.
+pub(crate) fn unknown_entity_error<'a>(
+    entity: &str,
+    known: impl IntoIterator<Item = &'a str>,
+) -> String {
+    with_suggestion(format!("entity '{entity}' not found"), entity, known)
+}
+
+/// Error for a column that `entity` does not have, suggesting the closest one.
+pub(crate) fn unknown_attribute_error<'a>(
+    attribute: &str,
+    entity: &str,
+    known: impl IntoIterator<Item = &'a str>,
+) -> String {
+    with_suggestion(
+        format!("attribute '{attribute}' not found on '{entity}'"),
+        attribute,
+        known,
+    )
+}
+
+fn with_suggestion<'a>(
+    message: String,
+    name: &str,
+    known: impl IntoIterator<Item = &'a str>,
+) -> String {
+    match closest_match(name, known) {
+        Some(suggestion) => format!("{message}; did you mean '{suggestion}'?"),
+        None => message,
+    }
+}
+
+/// The known name nearest to `name` by edit distance, when it is close enough to be a typo:
+/// at most one edit per three characters, and at least one.
+fn closest_match<'a>(name: &str, known: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let name = name.to_ascii_lowercase();
+    let limit = (name.chars().count() / 3).max(1);
+    known
+        .into_iter()
+        .map(|candidate| {
+            (
+                edit_distance(&name, &candidate.to_ascii_lowercase()),
+                candidate,
+            )
+        })
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Logical names of every column in an `Attributes?$select=LogicalName` response.
+pub(crate) fn parse_column_names(json: &Value) -> Result<Vec<String>, String> {
+    json.get("value")
+        .and_then(|value| value.as_array())
+        .ok_or_else(|| "Invalid response from Dataverse".to_string())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| item.get("LogicalName").and_then(|name| name.as_str()))
+                .map(str::to_string)
+                .collect()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{
+        edit_distance, fetchxml_references, odata_columns, parse_column_names,
+        unknown_attribute_error, unknown_entity_error,
+    };
+
+    #[test]
+    fn reads_column_names() {
+        let json =
+            json!({"value": [{"LogicalName": "accountid"}, {"LogicalName": "address1_composite"}]});
+
+        assert_eq!(
+            parse_column_names(&json).expect("should parse"),
+            vec!["accountid", "address1_composite"]
+        );
+        assert!(parse_column_names(&json!({})).is_err());
+    }
+
+    #[test]
+    fn collects_fetchxml_tables_and_columns() {
+        let references = fetchxml_references(
+            "<fetch><entity name=\"account\"><attribute name=\"name\" /><order attribute=\"createdon\" /><link-entity name=\"contact\" from=\"contactid\" to=\"primarycontactid\" alias=\"pc\"><attribute name=\"fullname\" /></link-entity><filter><condition entityname=\"pc\" attribute=\"emailaddress1\" operator=\"not-null\" /><condition attribute=\"statecode\" operator=\"eq\" value=\"0\" /></filter></entity></fetch>",
+        )
+        .expect("should scan");
+
+        assert_eq!(references.entities, vec!["account", "contact"]);
+        let columns = references
+            .columns
+            .iter()
+            .map(|(entity, column)| format!("{entity}.{column}"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![
+                "account.name",
+                "account.createdon",
+                "contact.contactid",
+                "account.primarycontactid",
+                "contact.fullname",
+                "contact.emailaddress1",
+                "account.statecode",
+            ]
+        );
+    }
+
+    #[test]
+    fn collects_odata_select_and_orderby_columns() {
+        assert_eq!(
+            odata_columns(
+                "$select=name,_primarycontactid_value&$orderby=createdon%20desc&$expand=primarycontactid($select=fullname;$orderby=lastname)&$filter=statecode eq 0"
+            ),
+            vec!["name", "primarycontactid", "createdon"]
+        );
+    }
+
+    #[test]
+    fn suggests_close_names_only() {
+        assert_eq!(edit_distance("accontid", "accountid"), 1);
+        assert_eq!(
+            unknown_attribute_error("accontid", "account", ["name", "accountid"]),
+            "attribute 'accontid' not found on 'account'; did you mean 'accountid'?"
+        );
+        assert_eq!(
+            unknown_entity_error("opportunityproduct", ["account", "contact"]),
+            "entity 'opportunityproduct' not found"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::dataverse::access::action_entity_reference;
+
+/// Recycle bin settings of the tables whose deleted rows can be restored.
+pub(crate) const RECYCLE_BIN_TABLES_QUERY: &str =
+    "recyclebinconfigs?$select=name&$filter=statecode eq 0 and isreadyforrecyclebin eq true";
+
+/// A `recyclebinconfig` row.
+#[derive(Debug, Deserialize)]
+pub(crate) struct RecycleBinConfig {
+    /// Logical name of the table.
+    pub(crate) name: String,
+}
+
+/// Body of the `Restore` action for the deleted row `id` of `logical_name`.
+pub(crate) fn restore_body(logical_name: &str, primary_id_attribute: &str, id: Uuid) -> Value {
+    json!({
+        "Target": action_entity_reference(logical_name, primary_id_attribute, id),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::restore_body;
+
+    #[test]
+    fn builds_restore_target() {
+        let id = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+        assert_eq!(
+            restore_body("account", "accountid", id),
+            json!({
+                "Target": {
+                    "@odata.type": "Microsoft.Dynamics.CRM.account",
+                    "accountid": "aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee"
+                }
+            })
+        );
+    }
+}
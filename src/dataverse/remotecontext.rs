@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::Value as JsonValue;
+use uuid::Uuid;
+
+use crate::dataverse::entity::{
+    Entity, EntityReference, Money, OptionSetValue, OptionSetValueCollection, Value,
+};
+
+/// `Stage` of a step registered in the pre-validation stage.
+pub const STAGE_PRE_VALIDATION: i32 = 10;
+/// `Stage` of a step registered in the pre-operation stage.
+pub const STAGE_PRE_OPERATION: i32 = 20;
+/// `Stage` of a step registered in the post-operation stage.
+pub const STAGE_POST_OPERATION: i32 = 40;
+
+/// `Mode` of a step that runs synchronously.
+pub const MODE_SYNCHRONOUS: i32 = 0;
+/// `Mode` of a step that runs asynchronously.
+pub const MODE_ASYNCHRONOUS: i32 = 1;
+
+/// The execution context Dataverse posts to a webhook or sends to Azure Service Bus when a
+/// registered step runs, parsed from its JSON form.
+#[derive(Debug, Clone, Default)]
+pub struct RemoteExecutionContext {
+    /// Message that triggered the step, such as `Create` or `Update`.
+    pub message_name: String,
+    /// Logical name of the table the message ran on.
+    pub primary_entity_name: String,
+    /// ID of the row the message ran on.
+    pub primary_entity_id: Uuid,
+    /// Logical name of the second table of a relationship message, or `none`.
+    pub secondary_entity_name: Option<String>,
+    /// Stage of the step, such as `STAGE_POST_OPERATION`.
+    pub stage: i32,
+    /// Whether the step ran synchronously (`MODE_SYNCHRONOUS`) or asynchronously.
+    pub mode: i32,
+    /// How deep the step ran in a chain of plug-ins and workflows, starting at 1.
+    pub depth: i32,
+    /// User the operation ran as.
+    pub user_id: Uuid,
+    /// User who started the operation, which differs from `user_id` under impersonation.
+    pub initiating_user_id: Uuid,
+    /// Business unit of `user_id`.
+    pub business_unit_id: Uuid,
+    /// Organization ID.
+    pub organization_id: Uuid,
+    /// Organization unique name.
+    pub organization_name: String,
+    /// ID shared by every step that runs for one incoming request, to detect loops.
+    pub correlation_id: Uuid,
+    /// ID of the system job of an asynchronous step.
+    pub operation_id: Uuid,
+    /// ID of the incoming request, when Dataverse assigned one.
+    pub request_id: Option<Uuid>,
+    /// When the system job of an asynchronous step was created.
+    pub operation_created_on: Option<DateTime<Utc>>,
+    /// True if the step ran inside the database transaction.
+    pub is_in_transaction: bool,
+    /// The `sdkmessageprocessingstep` that sent the context.
+    pub owning_extension: Option<EntityReference>,
+    /// Message request parameters, such as `Target`.
+    pub input_parameters: HashMap<String, ContextParameter>,
+    /// Message response parameters, such as `id` after a `Create`.
+    pub output_parameters: HashMap<String, ContextParameter>,
+    /// Values passed between steps of the same operation.
+    pub shared_variables: HashMap<String, ContextParameter>,
+    /// Row images taken before the operation, keyed by the image alias.
+    pub pre_entity_images: HashMap<String, Entity>,
+    /// Row images taken after the operation, keyed by the image alias.
+    pub post_entity_images: HashMap<String, Entity>,
+    /// Context of the operation that caused this one, when the step ran inside another.
+    pub parent_context: Option<Box<RemoteExecutionContext>>,
+}
+
+/// A value in the parameter collections of a `RemoteExecutionContext`.
+#[derive(Debug, Clone)]
+pub enum ContextParameter {
+    /// A row, such as the `Target` of `Create` or `Update`.
+    Entity(Entity),
+    /// Any other value this crate can type, such as the `EntityReference` `Target` of `Delete`.
+    Value(Value),
+    /// A value of a type this crate does not model, such as a `ColumnSet` or a query, as sent.
+    Other(JsonValue),
+}
+
+impl RemoteExecutionContext {
+    /// Parse the JSON body of a webhook request or of a Service Bus message sent in JSON format.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let value = serde_json::from_str::<JsonValue>(json)
+            .map_err(|e| format!("Failed to parse execution context JSON: {e}"))?;
+        Self::from_json(&value)
+    }
+
+    /// Read an execution context from already parsed JSON.
+    pub fn from_json(json: &JsonValue) -> Result<Self, String> {
+        let object = json
+            .as_object()
+            .ok_or_else(|| "Execution context is not a JSON object".to_string())?;
+        let text = |key: &str| object.get(key).and_then(JsonValue::as_str);
+        let uuid = |key: &str| optional_uuid(object.get(key), key).map(Option::unwrap_or_default);
+        let number = |key: &str| {
+            object
+                .get(key)
+                .and_then(JsonValue::as_i64)
+                .and_then(|value| i32::try_from(value).ok())
+                .unwrap_or_default()
+        };
+
+        Ok(Self {
+            message_name: text("MessageName").unwrap_or_default().to_string(),
+            primary_entity_name: text("PrimaryEntityName").unwrap_or_default().to_string(),
+            primary_entity_id: uuid("PrimaryEntityId")?,
+            secondary_entity_name: text("SecondaryEntityName")
+                .filter(|name| *name != "none")
+                .map(str::to_string),
+            stage: number("Stage"),
+            mode: number("Mode"),
+            depth: number("Depth"),
+            user_id: uuid("UserId")?,
+            initiating_user_id: uuid("InitiatingUserId")?,
+            business_unit_id: uuid("BusinessUnitId")?,
+            organization_id: uuid("OrganizationId")?,
+            organization_name: text("OrganizationName").unwrap_or_default().to_string(),
+            correlation_id: uuid("CorrelationId")?,
+            operation_id: uuid("OperationId")?,
+            request_id: optional_uuid(object.get("RequestId"), "RequestId")?,
+            operation_created_on: text("OperationCreatedOn").map(parse_date).transpose()?,
+            is_in_transaction: object
+                .get("IsInTransaction")
+                .and_then(JsonValue::as_bool)
+                .unwrap_or_default(),
+            owning_extension: match object.get("OwningExtension") {
+                Some(JsonValue::Null) | None => None,
+                Some(reference) => Some(parse_entity_reference(reference)?),
+            },
+            input_parameters: parse_parameters(object.get("InputParameters"))?,
+            output_parameters: parse_parameters(object.get("OutputParameters"))?,
+            shared_variables: parse_parameters(object.get("SharedVariables"))?,
+            pre_entity_images: parse_images(object.get("PreEntityImages"))?,
+            post_entity_images: parse_images(object.get("PostEntityImages"))?,
+            parent_context: match object.get("ParentContext") {
+                Some(JsonValue::Null) | None => None,
+                Some(parent) => Some(Box::new(Self::from_json(parent)?)),
+            },
+        })
+    }
+
+    /// The `Target` input parameter when it is a row, as for `Create` and `Update`.
+    pub fn target(&self) -> Option<&Entity> {
+        match self.input_parameters.get("Target") {
+            Some(ContextParameter::Entity(entity)) => Some(entity),
+            _ => None,
+        }
+    }
+
+    /// The `Target` input parameter when it is a reference, as for `Delete` and `Assign`.
+    pub fn target_reference(&self) -> Option<&EntityReference> {
+        match self.input_parameters.get("Target") {
+            Some(ContextParameter::Value(Value::EntityReference(reference))) => Some(reference),
+            _ => None,
+        }
+    }
+}
+
+/// Entries of a serialized `ParameterCollection`, `EntityImageCollection`, or attribute
+/// collection: an array of `{"key": ..., "value": ...}` objects.
+fn key_value_pairs(json: Option<&JsonValue>) -> Result<Vec<(&str, &JsonValue)>, String> {
+    let items = match json {
+        Some(JsonValue::Array(items)) => items,
+        Some(JsonValue::Null) | None => return Ok(Vec::new()),
+        Some(_) => return Err("Expected a key/value collection".to_string()),
+    };
+    items
+        .iter()
+        .map(|item| {
+            let key = item
+                .get("key")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| "Key/value entry has no key".to_string())?;
+            Ok((key, item.get("value").unwrap_or(&JsonValue::Null)))
+        })
+        .collect()
+}
+
+fn parse_parameters(json: Option<&JsonValue>) -> Result<HashMap<String, ContextParameter>, String> {
+    Ok(key_value_pairs(json)?
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), parse_parameter(value)))
+        .collect())
+}
+
+fn parse_parameter(json: &JsonValue) -> ContextParameter {
+    if type_name(json) == Some("Entity")
+        && let Ok(entity) = parse_entity(json)
+    {
+        return ContextParameter::Entity(entity);
+    }
+    match parse_value(json) {
+        Ok(value) => ContextParameter::Value(value),
+        Err(_) => ContextParameter::Other(json.clone()),
+    }
+}
+
+fn parse_images(json: Option<&JsonValue>) -> Result<HashMap<String, Entity>, String> {
+    key_value_pairs(json)?
+        .into_iter()
+        .map(|(key, value)| Ok((key.to_string(), parse_entity(value)?)))
+        .collect()
+}
+
+/// The type of a serialized SDK object, from a `__type` hint such as
+/// `EntityReference:http://schemas.microsoft.com/xrm/2011/Contracts`.
+fn type_name(json: &JsonValue) -> Option<&str> {
+    json.get("__type")
+        .and_then(JsonValue::as_str)
+        .map(|hint| hint.split(':').next().unwrap_or(hint))
+}
+
+/// A serialized SDK `Entity`. Formatted values become the labels of option set values.
+fn parse_entity(json: &JsonValue) -> Result<Entity, String> {
+    let logical_name = json
+        .get("LogicalName")
+        .and_then(JsonValue::as_str)
+        .ok_or_else(|| "Entity in execution context has no LogicalName".to_string())?;
+    let id = optional_uuid(json.get("Id"), "Id")?.unwrap_or_default();
+    let formatted_values = key_value_pairs(json.get("FormattedValues"))?
+        .into_iter()
+        .filter_map(|(key, value)| value.as_str().map(|label| (key, label)))
+        .collect::<HashMap<_, _>>();
+
+    let mut entity = Entity::new(id, logical_name, None);
+    for (key, value) in key_value_pairs(json.get("Attributes"))? {
+        let mut value = parse_value(value)
+            .map_err(|e| format!("Failed to read attribute '{key}' of {logical_name}: {e}"))?;
+        if let Value::OptionSetValue(option) = &mut value {
+            option.name = formatted_values.get(key).map(|label| label.to_string());
+        }
+        entity.attributes.insert(key.to_string(), value);
+    }
+    Ok(entity)
+}
+
+fn parse_entity_reference(json: &JsonValue) -> Result<EntityReference, String> {
+    Ok(EntityReference {
+        id: optional_uuid(json.get("Id"), "Id")?.unwrap_or_default(),
+        logical_name: json
+            .get("LogicalName")
+            .and_then(JsonValue::as_str)
+            .ok_or_else(|| "EntityReference in execution context has no LogicalName".to_string())?
+            .to_string(),
+        name: json
+            .get("Name")
+            .and_then(JsonValue::as_str)
+            .map(str::to_string),
+    })
+}
+
+/// An attribute or parameter value. `DateTime` values arrive as `/Date(ms)/` strings, and SDK
+/// types carry a `__type` hint.
+fn parse_value(json: &JsonValue) -> Result<Value, String> {
+    match json {
+        JsonValue::Null => Ok(Value::Null),
+        JsonValue::Bool(value) => Ok(Value::Boolean(*value)),
+        JsonValue::Number(number) => Ok(match number.as_i64() {
+            Some(value) => Value::Int(value),
+            None => Value::Float(number.as_f64().unwrap_or_default()),
+        }),
+        JsonValue::String(text) if text.starts_with("/Date(") => {
+            Ok(Value::DateTime(parse_date(text)?))
+        }
+        JsonValue::String(text) => Ok(Value::String(text.clone())),
+        JsonValue::Array(items) => {
+            let values = items
+                .iter()
+                .map(|item| match type_name(item) {
+                    Some("OptionSetValue") => option_value(item),
+                    _ => Err("Only arrays of OptionSetValue are supported".to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::OptionSetValueCollection(OptionSetValueCollection {
+                values,
+            }))
+        }
+        JsonValue::Object(_) => match type_name(json) {
+            Some("EntityReference") => Ok(Value::EntityReference(parse_entity_reference(json)?)),
+            Some("OptionSetValue") => Ok(Value::OptionSetValue(OptionSetValue {
+                value: option_value(json)?,
+                name: None,
+            })),
+            Some("Money") => {
+                let amount = json
+                    .get("Value")
+                    .ok_or_else(|| "Money value has no Value".to_string())?;
+                let amount = amount
+                    .to_string()
+                    .parse::<Decimal>()
+                    .or_else(|_| Decimal::from_scientific(&amount.to_string()))
+                    .map_err(|e| format!("Invalid Money value '{amount}': {e}"))?;
+                Ok(Value::Money(Money::new(amount)))
+            }
+            Some("AliasedValue") => parse_value(json.get("Value").unwrap_or(&JsonValue::Null)),
+            Some("EntityCollection") => {
+                let entities = match json.get("Entities") {
+                    Some(JsonValue::Array(entities)) => entities
+                        .iter()
+                        .map(parse_entity)
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ => Vec::new(),
+                };
+                Ok(Value::EntityCollection(entities))
+            }
+            Some(other) => Err(format!("Unsupported value type '{other}'")),
+            None => Err("Object value has no __type".to_string()),
+        },
+    }
+}
+
+fn option_value(json: &JsonValue) -> Result<i32, String> {
+    json.get("Value")
+        .and_then(JsonValue::as_i64)
+        .and_then(|value| i32::try_from(value).ok())
+        .ok_or_else(|| "OptionSetValue has no integer Value".to_string())
+}
+
+fn optional_uuid(json: Option<&JsonValue>, key: &str) -> Result<Option<Uuid>, String> {
+    match json {
+        Some(JsonValue::String(text)) => Uuid::parse_str(text)
+            .map(Some)
+            .map_err(|e| format!("Invalid GUID in {key}: {e}")),
+        Some(JsonValue::Null) | None => Ok(None),
+        Some(other) => Err(format!("Invalid GUID in {key}: {other}")),
+    }
+}
+
+/// A WCF JSON date such as `/Date(1495843212232)/` or `/Date(1495843212232+0000)/`. The
+/// milliseconds count from the Unix epoch in UTC; the offset only says how to display it.
+fn parse_date(text: &str) -> Result<DateTime<Utc>, String> {
+    let inner = text
+        .strip_prefix("/Date(")
+        .and_then(|rest| rest.strip_suffix(")/"))
+        .ok_or_else(|| format!("Invalid date '{text}'"))?;
+    let millis_end = inner
+        .char_indices()
+        .skip(1)
+        .find(|(_, ch)| matches!(ch, '+' | '-'))
+        .map_or(inner.len(), |(index, _)| index);
+    inner[..millis_end]
+        .parse::<i64>()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .ok_or_else(|| format!("Invalid date '{text}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{
+        ContextParameter, MODE_ASYNCHRONOUS, RemoteExecutionContext, STAGE_POST_OPERATION,
+        parse_date,
+    };
+    use crate::dataverse::entity::Value;
+
+    const CONTEXT: &str = r#"{
+        "BusinessUnitId": "5d2c2a1e-9e4b-e611-80e5-c4346bac0a3c",
+        "CorrelationId": "0f2dcd5e-84a5-4c1e-9a66-2a1d5a4cb1a3",
+        "Depth": 1,
+        "InitiatingUserId": "6d2c2a1e-9e4b-e611-80e5-c4346bac0a3c",
+        "InputParameters": [{
+            "key": "Target",
+            "value": {
+                "__type": "Entity:http://schemas.microsoft.com/xrm/2011/Contracts",
+                "Attributes": [
+                    {"key": "name", "value": "Contoso"},
+                    {"key": "accountid", "value": "11111111-2222-3333-4444-555555555555"},
+                    {"key": "industrycode", "value": {"__type": "OptionSetValue:http://schemas.microsoft.com/xrm/2011/Contracts", "Value": 7}},
+                    {"key": "revenue", "value": {"__type": "Money:http://schemas.microsoft.com/xrm/2011/Contracts", "Value": 1200.5}},
+                    {"key": "ownerid", "value": {"__type": "EntityReference:http://schemas.microsoft.com/xrm/2011/Contracts", "Id": "6d2c2a1e-9e4b-e611-80e5-c4346bac0a3c", "KeyAttributes": [], "LogicalName": "systemuser", "Name": null, "RowVersion": null}},
+                    {"key": "createdon", "value": "/Date(1495843212000)/"}
+                ],
+                "EntityState": null,
+                "FormattedValues": [{"key": "industrycode", "value": "Consulting"}],
+                "Id": "11111111-2222-3333-4444-555555555555",
+                "KeyAttributes": [],
+                "LogicalName": "account",
+                "RelatedEntities": [],
+                "RowVersion": null
+            }
+        }, {
+            "key": "ColumnSet",
+            "value": {"__type": "ColumnSet:http://schemas.microsoft.com/xrm/2011/Contracts", "AllColumns": true, "Columns": []}
+        }],
+        "IsExecutingOffline": false,
+        "IsInTransaction": false,
+        "IsOfflinePlayback": false,
+        "IsolationMode": 1,
+        "MessageName": "Create",
+        "Mode": 1,
+        "OperationCreatedOn": "/Date(1495843212232+0000)/",
+        "OperationId": "c1a6b1e8-1d43-e711-80e8-c4346bac0a3c",
+        "OrganizationId": "7d2c2a1e-9e4b-e611-80e5-c4346bac0a3c",
+        "OrganizationName": "contoso",
+        "OutputParameters": [{"key": "id", "value": "11111111-2222-3333-4444-555555555555"}],
+        "OwningExtension": {"Id": "8d2c2a1e-9e4b-e611-80e5-c4346bac0a3c", "KeyAttributes": [], "LogicalName": "sdkmessageprocessingstep", "Name": null, "RowVersion": null},
+        "ParentContext": null,
+        "PostEntityImages": [{
+            "key": "AsynchronousStepPrimaryName",
+            "value": {
+                "Attributes": [{"key": "name", "value": "Contoso"}],
+                "EntityState": null,
+                "FormattedValues": [],
+                "Id": "11111111-2222-3333-4444-555555555555",
+                "KeyAttributes": [],
+                "LogicalName": "account",
+                "RelatedEntities": [],
+                "RowVersion": null
+            }
+        }],
+        "PreEntityImages": [],
+        "PrimaryEntityId": "11111111-2222-3333-4444-555555555555",
+        "PrimaryEntityName": "account",
+        "RequestId": null,
+        "SecondaryEntityName": "none",
+        "SharedVariables": [],
+        "Stage": 40,
+        "UserId": "6d2c2a1e-9e4b-e611-80e5-c4346bac0a3c"
+    }"#;
+
+    #[test]
+    fn parses_webhook_context() {
+        let context = RemoteExecutionContext::parse(CONTEXT).expect("should parse");
+
+        assert_eq!(context.message_name, "Create");
+        assert_eq!(context.stage, STAGE_POST_OPERATION);
+        assert_eq!(context.mode, MODE_ASYNCHRONOUS);
+        assert_eq!(context.secondary_entity_name, None);
+        assert_eq!(context.request_id, None);
+        assert_eq!(
+            context
+                .owning_extension
+                .as_ref()
+                .expect("step")
+                .logical_name,
+            "sdkmessageprocessingstep"
+        );
+
+        let target = context.target().expect("target row");
+        assert_eq!(target.logical_name, "account");
+        assert_eq!(
+            target.id,
+            Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid")
+        );
+        assert!(matches!(&target.attributes["name"], Value::String(name) if name == "Contoso"));
+        assert!(matches!(
+            &target.attributes["industrycode"],
+            Value::OptionSetValue(option) if option.value == 7 && option.name.as_deref() == Some("Consulting")
+        ));
+        assert!(matches!(
+            &target.attributes["revenue"],
+            Value::Money(money) if money.value.to_string() == "1200.5"
+        ));
+        assert!(matches!(
+            &target.attributes["ownerid"],
+            Value::EntityReference(owner) if owner.logical_name == "systemuser"
+        ));
+        assert!(matches!(
+            &target.attributes["createdon"],
+            Value::DateTime(date) if date.timestamp_millis() == 1_495_843_212_000
+        ));
+        assert!(matches!(
+            context.input_parameters["ColumnSet"],
+            ContextParameter::Other(_)
+        ));
+        assert!(matches!(
+            &context.output_parameters["id"],
+            ContextParameter::Value(Value::String(_))
+        ));
+        assert_eq!(
+            context.post_entity_images["AsynchronousStepPrimaryName"].logical_name,
+            "account"
+        );
+        assert!(context.pre_entity_images.is_empty());
+    }
+
+    #[test]
+    fn parses_wcf_dates() {
+        assert_eq!(
+            parse_date("/Date(1495843212232+0000)/")
+                .expect("date")
+                .timestamp_millis(),
+            1_495_843_212_232
+        );
+        assert_eq!(
+            parse_date("/Date(-1000)/")
+                .expect("date")
+                .timestamp_millis(),
+            -1000
+        );
+        assert!(parse_date("2017-05-27").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use reqwest::header::{HeaderMap, HeaderValue};
+use reqwest::{Client, Request, RequestBuilder, StatusCode};
+use uuid::Uuid;
+
+use crate::dataverse::apierror::{ApiError, retry_after};
+
+/// Header the client sends to identify one logical operation. A retry of the same operation must
+/// send the same value so Dataverse and Microsoft support can tie the attempts together.
+pub const CLIENT_REQUEST_ID_HEADER: &str = "x-ms-client-request-id";
+/// Header Dataverse returns to identify the request on the service side.
+pub const SERVICE_REQUEST_ID_HEADER: &str = "x-ms-service-request-id";
+
+tokio::task_local! {
+    /// Client request ID of the logical operation the current task is running, shared by every
+    /// attempt of it.
+    static OPERATION_REQUEST_ID: String;
+}
+
+/// Run `operation` so that every request it sends, retries included, carries one client request
+/// ID. An operation started inside another keeps the outer operation's ID.
+pub(crate) async fn with_operation_request_id<F: Future>(operation: F) -> F::Output {
+    if OPERATION_REQUEST_ID.try_with(|_| ()).is_ok() {
+        return operation.await;
+    }
+    OPERATION_REQUEST_ID
+        .scope(Uuid::new_v4().as_hyphenated().to_string(), operation)
+        .await
+}
+
+/// Stamp the client request ID of the current operation on a request unless the caller already
+/// set one, and return the ID the request carries. Outside an operation each request gets a new
+/// ID.
+pub(crate) fn ensure_client_request_id(
+    request: RequestBuilder,
+) -> Result<(Client, Request, String), String> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(|e| format!("Request failed: {e}"))?;
+
+    let id = assign_client_request_id(request.headers_mut());
+    Ok((client, request, id))
+}
+
+fn assign_client_request_id(headers: &mut HeaderMap) -> String {
+    if let Some(existing) = headers
+        .get(CLIENT_REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        return existing.to_string();
+    }
+
+    let id = OPERATION_REQUEST_ID
+        .try_with(String::clone)
+        .unwrap_or_else(|_| Uuid::new_v4().as_hyphenated().to_string());
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        headers.insert(CLIENT_REQUEST_ID_HEADER, value);
+    }
+    id
+}
+
+/// Copy the client request ID onto response headers that do not echo it, so error handling only
+/// needs the response to report both IDs.
+pub(crate) fn echo_client_request_id(headers: &mut HeaderMap, id: &str) {
+    if headers.contains_key(CLIENT_REQUEST_ID_HEADER) {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(id) {
+        headers.insert(CLIENT_REQUEST_ID_HEADER, value);
+    }
+}
+
+/// Format a failed Web API response, appending the client and service request IDs when known.
+/// `ApiError::parse` reads the message back into its structured form.
+pub(crate) fn api_error(status: StatusCode, headers: &HeaderMap, body: &str) -> String {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    ApiError {
+        client_request_id: header(CLIENT_REQUEST_ID_HEADER),
+        service_request_id: header(SERVICE_REQUEST_ID_HEADER),
+        retry_after: retry_after(headers),
+        ..ApiError::from_body(status.as_u16(), body)
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::{
+        CLIENT_REQUEST_ID_HEADER, SERVICE_REQUEST_ID_HEADER, api_error, echo_client_request_id,
+        ensure_client_request_id,
+    };
+
+    #[test]
+    fn keeps_caller_supplied_client_request_id() {
+        let client = reqwest::Client::new();
+
+        let (_, request, id) =
+            ensure_client_request_id(client.get("https://example.crm.dynamics.com/api/data/v9.2/"))
+                .expect("should build");
+        assert_eq!(
+            request.headers()[CLIENT_REQUEST_ID_HEADER]
+                .to_str()
+                .unwrap(),
+            id
+        );
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+
+        let (_, request, id) = ensure_client_request_id(
+            client
+                .get("https://example.crm.dynamics.com/api/data/v9.2/")
+                .header(CLIENT_REQUEST_ID_HEADER, "retry-1"),
+        )
+        .expect("should build");
+        assert_eq!(id, "retry-1");
+        assert_eq!(
+            request
+                .headers()
+                .get_all(CLIENT_REQUEST_ID_HEADER)
+                .iter()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn reports_request_ids_in_api_errors() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            api_error(StatusCode::BAD_REQUEST, &headers, "bad"),
+            "Dataverse API error (400 Bad Request): bad"
+        );
+
+        echo_client_request_id(&mut headers, "client-1");
+        headers.insert(
+            SERVICE_REQUEST_ID_HEADER,
+            HeaderValue::from_static("service-1"),
+        );
+        assert_eq!(
+            api_error(StatusCode::BAD_REQUEST, &headers, "bad"),
+            "Dataverse API error (400 Bad Request): bad [client request id: client-1, service request id: service-1]"
+        );
+
+        echo_client_request_id(&mut headers, "client-2");
+        assert_eq!(headers[CLIENT_REQUEST_ID_HEADER], "client-1");
+    }
+}
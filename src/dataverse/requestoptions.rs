@@ -0,0 +1,144 @@
+use reqwest::RequestBuilder;
+
+use crate::dataverse::requestparameters::insert_headers;
+
+/// Annotations requested by FetchXML retrieval; paging relies on the first two.
+pub(crate) const FETCHXML_ANNOTATIONS: [&str; 4] = [
+    "Microsoft.Dynamics.CRM.fetchxmlpagingcookie",
+    "Microsoft.Dynamics.CRM.morerecords",
+    "Microsoft.Dynamics.CRM.lookuplogicalname",
+    "OData.Community.Display.V1.FormattedValue",
+];
+/// Annotations requested for OData row collections and single rows, which entity parsing uses
+/// to build lookups and choice labels.
+pub(crate) const ENTITY_ANNOTATIONS: [&str; 2] = [
+    "Microsoft.Dynamics.CRM.lookuplogicalname",
+    "OData.Community.Display.V1.FormattedValue",
+];
+/// Annotations requested by metadata and other single-document reads.
+pub(crate) const FORMATTED_VALUE_ANNOTATIONS: [&str; 1] =
+    ["OData.Community.Display.V1.FormattedValue"];
+
+/// `Prefer` header preferences and extra headers for a Dataverse Web API request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestOptions {
+    /// Annotations to request with `odata.include-annotations`, such as
+    /// `OData.Community.Display.V1.FormattedValue` or `*`. `None` keeps the call's defaults; an
+    /// empty list requests none.
+    pub include_annotations: Option<Vec<String>>,
+    /// `odata.maxpagesize`: rows per page for OData collection queries.
+    pub max_page_size: Option<u32>,
+    /// `return=representation`: have create and update return the written row.
+    pub return_representation: bool,
+    /// `odata.track-changes`: return an `@odata.deltaLink` for reading later changes.
+    pub track_changes: bool,
+    /// Number rows in `Entity::row_number`, counting from 1 across pages, in FetchXML paging.
+    /// Applied by the client and not sent to Dataverse.
+    pub row_numbers: bool,
+    /// Drop rows whose primary id an earlier page already returned, in FetchXML paging. Applied
+    /// by the client and not sent to Dataverse.
+    pub deduplicate: bool,
+    /// Order FetchXML paging by the primary id when the query has no root `<order>`, so pages do
+    /// not overlap. Applied by the client and not sent to Dataverse.
+    pub stable_order: bool,
+    /// Move the columns of each FetchXML `link-entity` from `alias.column` attributes into
+    /// `Entity::linked`, keyed by alias. Applied by the client and not sent to Dataverse.
+    pub nest_linked_entities: bool,
+    /// Extra headers to send as `(name, value)` pairs, such as `MSCRM.SolutionUniqueName`.
+    /// They replace a default header of the same name set with
+    /// `ServiceClientBuilder::default_header`, and a `Prefer` entry replaces the composed one.
+    pub headers: Vec<(String, String)>,
+}
+
+impl RequestOptions {
+    /// Compose the `Prefer` header value, using `default_annotations` when no annotation list is
+    /// set. Returns `None` when nothing is preferred.
+    pub fn prefer_header(&self, default_annotations: &[&str]) -> Option<String> {
+        let annotations = match &self.include_annotations {
+            Some(annotations) => annotations.iter().map(String::as_str).collect::<Vec<_>>(),
+            None => default_annotations.to_vec(),
+        };
+
+        let mut preferences = Vec::new();
+        if !annotations.is_empty() {
+            preferences.push(format!(
+                "odata.include-annotations=\"{}\"",
+                annotations.join(",")
+            ));
+        }
+        if let Some(max_page_size) = self.max_page_size {
+            preferences.push(format!("odata.maxpagesize={max_page_size}"));
+        }
+        if self.return_representation {
+            preferences.push("return=representation".to_string());
+        }
+        if self.track_changes {
+            preferences.push("odata.track-changes".to_string());
+        }
+
+        (!preferences.is_empty()).then(|| preferences.join(","))
+    }
+
+    /// Set the `Prefer` header and extra headers on an outgoing request. An extra header replaces
+    /// a header of the same name, including the composed `Prefer`.
+    pub(crate) fn apply(
+        &self,
+        request: RequestBuilder,
+        default_annotations: &[&str],
+    ) -> RequestBuilder {
+        let request = match self.prefer_header(default_annotations) {
+            Some(prefer) => request.header("Prefer", prefer),
+            None => request,
+        };
+        insert_headers(request, &self.headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ENTITY_ANNOTATIONS, RequestOptions};
+
+    #[test]
+    fn default_options_request_the_call_defaults() {
+        assert_eq!(
+            RequestOptions::default().prefer_header(&ENTITY_ANNOTATIONS),
+            Some(
+                "odata.include-annotations=\"Microsoft.Dynamics.CRM.lookuplogicalname,OData.Community.Display.V1.FormattedValue\""
+                    .to_string()
+            )
+        );
+        assert_eq!(RequestOptions::default().prefer_header(&[]), None);
+    }
+
+    #[test]
+    fn combines_preferences_into_one_header() {
+        let options = RequestOptions {
+            include_annotations: Some(vec!["*".to_string()]),
+            max_page_size: Some(50),
+            return_representation: true,
+            track_changes: true,
+            row_numbers: true,
+            deduplicate: true,
+            stable_order: true,
+            nest_linked_entities: true,
+            headers: vec![("MSCRM.SolutionUniqueName".to_string(), "tools".to_string())],
+        };
+
+        assert_eq!(
+            options.prefer_header(&ENTITY_ANNOTATIONS).as_deref(),
+            Some(
+                "odata.include-annotations=\"*\",odata.maxpagesize=50,return=representation,odata.track-changes"
+            )
+        );
+
+        let no_annotations = RequestOptions {
+            include_annotations: Some(Vec::new()),
+            max_page_size: Some(10),
+            ..RequestOptions::default()
+        };
+        assert_eq!(
+            no_annotations.prefer_header(&ENTITY_ANNOTATIONS).as_deref(),
+            Some("odata.maxpagesize=10")
+        );
+    }
+}
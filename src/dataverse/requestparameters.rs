@@ -1,6 +1,19 @@
 use reqwest::RequestBuilder;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+/// Headers that skip custom plug-ins, flows, or other business logic, which a client only sends
+/// after `ServiceClientBuilder::allow_bypass_custom_logic`.
+const BYPASS_HEADERS: [&str; 4] = [
+    "MSCRM.BypassBusinessLogicExecution",
+    "MSCRM.BypassBusinessLogicExecutionStepIds",
+    "MSCRM.BypassCustomPluginExecution",
+    "MSCRM.SuppressCallbackRegistrationExpanderJob",
+];
 
 /// Optional Dataverse request parameters for create and update operations.
+///
+/// The bypass fields skip custom plug-ins and flows, so a client refuses to send them unless it
+/// was built with `ServiceClientBuilder::allow_bypass_custom_logic`.
 #[derive(Debug, Clone, Default)]
 pub struct RequestParameters {
     /// Send `MSCRM.BypassBusinessLogicExecution=CustomSync`.
@@ -11,6 +24,11 @@ pub struct RequestParameters {
     pub bypass_custom_plugin_execution: bool,
     /// Send `MSCRM.SuppressCallbackRegistrationExpanderJob=true`.
     pub suppress_callback_registration_expander_job: bool,
+    /// Extra headers to send as `(name, value)` pairs, such as `MSCRM.SolutionUniqueName` or
+    /// `MSCRM.BypassBusinessLogicExecutionStepIds`. Within a batch they are sent on the
+    /// operation's part. They replace a default header of the same name set with
+    /// `ServiceClientBuilder::default_header`.
+    pub custom_headers: Vec<(String, String)>,
     // Step-specific bypass ids are intentionally omitted for now because they need a more stable
     // public shape than a raw string list. The current API only exposes the simple boolean-style
     // switches that map cleanly to well-known headers.
@@ -18,7 +36,30 @@ pub struct RequestParameters {
 }
 
 impl RequestParameters {
-    /// Return the Dataverse request headers represented by these parameters.
+    /// Parameters for loading data without running synchronous plug-ins or triggering flows:
+    /// `MSCRM.BypassCustomPluginExecution` and `MSCRM.SuppressCallbackRegistrationExpanderJob`.
+    /// The caller needs the `prvBypassCustomPlugins` privilege, and the client must be built with
+    /// `ServiceClientBuilder::allow_bypass_custom_logic`.
+    pub fn bypass_plugins_and_flows() -> Self {
+        Self {
+            bypass_custom_plugin_execution: true,
+            suppress_callback_registration_expander_job: true,
+            ..Self::default()
+        }
+    }
+
+    /// True when these parameters skip custom business logic, through a bypass field or a
+    /// bypass header in `custom_headers`.
+    pub fn bypasses_custom_logic(&self) -> bool {
+        self.headers()
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(self.custom_headers.iter().map(|(name, _)| name.as_str()))
+            .any(is_bypass_header)
+    }
+
+    /// Return the Dataverse request headers represented by these parameters, without
+    /// `custom_headers`.
     pub fn headers(&self) -> Vec<(&'static str, &'static str)> {
         let mut headers = Vec::new();
 
@@ -42,6 +83,7 @@ impl RequestParameters {
         for (header, value) in self.headers() {
             request = request.header(header, value);
         }
+        request = insert_headers(request, &self.custom_headers);
 
         // Step-id bypass headers are not emitted yet for the same reason documented on the struct:
         // the crate does not currently expose a stable typed API for managing those ids.
@@ -69,9 +111,46 @@ impl RequestParameters {
     }
 }
 
+/// True for a header that skips custom business logic.
+pub(crate) fn is_bypass_header(name: &str) -> bool {
+    BYPASS_HEADERS
+        .iter()
+        .any(|header| header.eq_ignore_ascii_case(name))
+}
+
+/// Parse a header name and value, rejecting those that are not valid HTTP, such as a CR or LF
+/// that would start another header.
+pub(crate) fn parse_header(name: &str, value: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let header = HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| format!("Invalid header name '{name}'"))?;
+    let value =
+        HeaderValue::from_str(value).map_err(|_| format!("Invalid value for header '{name}'"))?;
+    Ok((header, value))
+}
+
+/// Set per-call headers on a request, replacing any value already set under the same name
+/// instead of sending both. An invalid header is left for reqwest to report when the request is
+/// sent.
+pub(crate) fn insert_headers(
+    mut request: RequestBuilder,
+    headers: &[(String, String)],
+) -> RequestBuilder {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        match parse_header(name, value) {
+            Ok((name, value)) => {
+                map.insert(name, value);
+            }
+            Err(_) => request = request.header(name, value),
+        }
+    }
+    // `RequestBuilder::headers` replaces existing values of each name rather than appending.
+    request.headers(map)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::RequestParameters;
+    use super::{RequestParameters, insert_headers, parse_header};
 
     #[test]
     fn headers_include_requested_bypass_flags() {
@@ -80,6 +159,7 @@ mod tests {
             bypass_business_logic_execution_custom_async: true,
             bypass_custom_plugin_execution: true,
             suppress_callback_registration_expander_job: true,
+            custom_headers: vec![("MSCRM.SolutionUniqueName".to_string(), "tools".to_string())],
         };
 
         let headers = parameters.headers();
@@ -93,6 +173,11 @@ mod tests {
             "MSCRM.SuppressCallbackRegistrationExpanderJob",
             "true"
         )));
+        assert!(
+            !headers
+                .iter()
+                .any(|(name, _)| *name == "MSCRM.SolutionUniqueName")
+        );
     }
 
     #[test]
@@ -103,4 +188,50 @@ mod tests {
             .iter()
             .any(|(name, _)| *name == "MSCRM.BypassBusinessLogicExecution"));
     }
+
+    #[test]
+    fn detects_bypass_fields_and_headers() {
+        assert!(RequestParameters::bypass_plugins_and_flows().bypasses_custom_logic());
+        assert!(!RequestParameters::default().bypasses_custom_logic());
+
+        let custom = RequestParameters {
+            custom_headers: vec![(
+                "mscrm.bypassbusinesslogicexecutionstepids".to_string(),
+                "45e0c603-0d0b-466e-a286-d7fc1cda8361".to_string(),
+            )],
+            ..RequestParameters::default()
+        };
+        assert!(custom.bypasses_custom_logic());
+
+        let solution = RequestParameters {
+            custom_headers: vec![("MSCRM.SolutionUniqueName".to_string(), "tools".to_string())],
+            ..RequestParameters::default()
+        };
+        assert!(!solution.bypasses_custom_logic());
+    }
+
+    #[test]
+    fn per_call_headers_replace_earlier_values_and_reject_line_breaks() {
+        let request = reqwest::Client::new()
+            .get("https://contoso.crm.dynamics.com/api/data/v9.2/accounts")
+            .header("Prefer", "odata.maxpagesize=10");
+        let request = insert_headers(
+            request,
+            &[("Prefer".to_string(), "return=representation".to_string())],
+        )
+        .build()
+        .expect("request");
+
+        let prefer = request.headers().get_all("prefer").iter().collect::<Vec<_>>();
+        assert_eq!(prefer, ["return=representation"]);
+
+        assert_eq!(
+            parse_header("X-Test\r\nInjected", "1").unwrap_err(),
+            "Invalid header name 'X-Test\r\nInjected'"
+        );
+        assert_eq!(
+            parse_header("X-Test", "1\r\nInjected: 2").unwrap_err(),
+            "Invalid value for header 'X-Test'"
+        );
+    }
 }
@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use reqwest::Method;
+use reqwest::header::HeaderMap;
+
+use crate::dataverse::requestid::{CLIENT_REQUEST_ID_HEADER, SERVICE_REQUEST_ID_HEADER};
+use crate::log::sanitize_url;
+
+/// Prefix of the service protection headers Dataverse returns with the remaining request budget.
+const RATE_LIMIT_HEADER_PREFIX: &str = "x-ms-ratelimit-";
+/// Number of requests left in the current service protection window.
+const BURST_REMAINING_HEADER: &str = "x-ms-ratelimit-burst-remaining-xrm-requests";
+
+/// Called with the `ResponseMeta` of every response a client receives. See
+/// `ServiceClientBuilder::on_response`.
+pub type ResponseCallback = Arc<dyn Fn(&ResponseMeta) + Send + Sync>;
+
+/// Status and headers of one Web API response, so the Dataverse request IDs of successful calls
+/// can be logged as well as those of failures.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// HTTP method of the request.
+    pub method: String,
+    /// Request path, without the query string, with alternate key values redacted as in logs.
+    pub path: String,
+    /// HTTP status code.
+    pub status: u16,
+    /// `x-ms-client-request-id` the request was sent with.
+    pub client_request_id: Option<String>,
+    /// `x-ms-service-request-id` Dataverse returned.
+    pub service_request_id: Option<String>,
+    /// Requests left in the current service protection window, from
+    /// `x-ms-ratelimit-burst-remaining-xrm-requests`.
+    pub burst_remaining_requests: Option<u64>,
+    /// Every `x-ms-ratelimit-*` header as returned, with lowercase names.
+    pub rate_limit_headers: Vec<(String, String)>,
+    /// `OData-Version` of the response.
+    pub odata_version: Option<String>,
+}
+
+impl ResponseMeta {
+    /// Read the metadata of a response to a `method` request for `path`.
+    pub(crate) fn from_response(
+        method: &Method,
+        path: &str,
+        status: u16,
+        headers: &HeaderMap,
+    ) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let mut rate_limit_headers: Vec<(String, String)> = headers
+            .iter()
+            .filter(|(name, _)| name.as_str().starts_with(RATE_LIMIT_HEADER_PREFIX))
+            .filter_map(|(name, value)| {
+                Some((name.as_str().to_string(), value.to_str().ok()?.to_string()))
+            })
+            .collect();
+        rate_limit_headers.sort();
+
+        Self {
+            method: method.as_str().to_string(),
+            path: sanitize_url(path),
+            status,
+            client_request_id: header(CLIENT_REQUEST_ID_HEADER),
+            service_request_id: header(SERVICE_REQUEST_ID_HEADER),
+            burst_remaining_requests: header(BURST_REMAINING_HEADER)
+                .and_then(|value| value.trim().parse().ok()),
+            rate_limit_headers,
+            odata_version: header("OData-Version"),
+        }
+    }
+
+    /// Whether the status is in the 2xx range.
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// The result of an operation together with the metadata of the response that produced it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithMeta<T> {
+    /// The operation's result.
+    pub value: T,
+    /// Status and headers of the response.
+    pub meta: ResponseMeta,
+}
+
+impl<T> WithMeta<T> {
+    /// Drop the metadata and keep the result.
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Method;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::ResponseMeta;
+
+    #[test]
+    fn reads_request_ids_and_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ms-client-request-id",
+            HeaderValue::from_static("client-1"),
+        );
+        headers.insert(
+            "x-ms-service-request-id",
+            HeaderValue::from_static("service-1"),
+        );
+        headers.insert(
+            "x-ms-ratelimit-burst-remaining-xrm-requests",
+            HeaderValue::from_static("5998"),
+        );
+        headers.insert(
+            "x-ms-ratelimit-time-remaining-xrm-requests",
+            HeaderValue::from_static("1,199.73"),
+        );
+        headers.insert("OData-Version", HeaderValue::from_static("4.0"));
+
+        let meta = ResponseMeta::from_response(
+            &Method::PATCH,
+            "/api/data/v9.2/accounts(00000000-0000-0000-0000-000000000001)",
+            204,
+            &headers,
+        );
+
+        assert!(meta.is_success());
+        assert_eq!(meta.method, "PATCH");
+        assert_eq!(meta.client_request_id.as_deref(), Some("client-1"));
+        assert_eq!(meta.service_request_id.as_deref(), Some("service-1"));
+        assert_eq!(meta.burst_remaining_requests, Some(5998));
+        assert_eq!(
+            meta.rate_limit_headers,
+            vec![
+                (
+                    "x-ms-ratelimit-burst-remaining-xrm-requests".to_string(),
+                    "5998".to_string()
+                ),
+                (
+                    "x-ms-ratelimit-time-remaining-xrm-requests".to_string(),
+                    "1,199.73".to_string()
+                ),
+            ]
+        );
+        assert_eq!(meta.odata_version.as_deref(), Some("4.0"));
+    }
+}
@@ -0,0 +1,529 @@
+use std::collections::HashMap;
+
+/// Base types followed when looking up inherited properties, guarding against cycles.
+const MAX_BASE_TYPE_DEPTH: usize = 16;
+
+/// The Web API's CSDL `$metadata` document: entity types, entity sets, actions, and functions.
+/// Type names are stored without their `Microsoft.Dynamics.CRM.` or `mscrm.` prefix.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServiceSchema {
+    /// Entity types keyed by name, such as `account`.
+    pub entity_types: HashMap<String, SchemaEntityType>,
+    /// Entity type name of each entity set, keyed by entity set name, such as `accounts`.
+    pub entity_sets: HashMap<String, String>,
+    /// Actions, including bound overloads.
+    pub actions: Vec<SchemaOperation>,
+    /// Functions, including bound overloads.
+    pub functions: Vec<SchemaOperation>,
+}
+
+/// An `EntityType` element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaEntityType {
+    /// Type name, such as `account`.
+    pub name: String,
+    /// Type it inherits properties from, such as `crmbaseentity`.
+    pub base_type: Option<String>,
+    /// True for abstract types, which have no entity set.
+    pub is_abstract: bool,
+    /// Key property names.
+    pub key: Vec<String>,
+    /// Structural properties declared on this type.
+    pub properties: Vec<SchemaProperty>,
+    /// Navigation properties declared on this type.
+    pub navigation_properties: Vec<SchemaNavigationProperty>,
+}
+
+/// A `Property` element. Lookup columns appear as `_name_value` properties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaProperty {
+    /// Property name, such as `name` or `_primarycontactid_value`.
+    pub name: String,
+    /// Type, such as `Edm.String`.
+    pub type_name: String,
+    /// False when the property is declared `Nullable="false"`.
+    pub nullable: bool,
+}
+
+/// A `NavigationProperty` element.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaNavigationProperty {
+    /// Property name, such as `primarycontactid` or `contact_customer_accounts`.
+    pub name: String,
+    /// Related entity type, such as `contact`.
+    pub target_type: String,
+    /// True for collection-valued properties.
+    pub is_collection: bool,
+    /// Navigation property on the related type that points back, when declared.
+    pub partner: Option<String>,
+}
+
+/// An `Action` or `Function` element.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SchemaOperation {
+    /// Operation name, such as `WhoAmI`.
+    pub name: String,
+    /// True when the first parameter is the entity or collection it is bound to.
+    pub is_bound: bool,
+    /// Parameters in declaration order.
+    pub parameters: Vec<SchemaParameter>,
+    /// Return type, or `None` for actions that return nothing.
+    pub return_type: Option<String>,
+}
+
+/// A `Parameter` of an action or function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaParameter {
+    /// Parameter name.
+    pub name: String,
+    /// Type, such as `Edm.Guid` or `Collection(mscrm.crmbaseentity)`.
+    pub type_name: String,
+    /// False when the parameter is declared `Nullable="false"`.
+    pub nullable: bool,
+}
+
+/// What the element being read belongs to.
+enum Context {
+    None,
+    EntityType(String),
+    Operation { is_action: bool, index: usize },
+    Other,
+}
+
+impl ServiceSchema {
+    /// Parse a CSDL `$metadata` document, such as one downloaded earlier and saved to disk.
+    pub fn parse(csdl: &str) -> Result<Self, String> {
+        let mut schema = Self::default();
+        let mut context = Context::None;
+        let mut position = 0;
+
+        while let Some(offset) = csdl[position..].find('<') {
+            let tag_start = position + offset;
+            if csdl[tag_start..].starts_with("<!--") {
+                position = csdl[tag_start..]
+                    .find("-->")
+                    .map(|end| tag_start + end + 3)
+                    .ok_or_else(|| "CSDL comment is not closed".to_string())?;
+                continue;
+            }
+            let tag_end = csdl[tag_start..]
+                .find('>')
+                .ok_or_else(|| "CSDL element is not closed".to_string())?
+                + tag_start;
+            let tag = &csdl[tag_start + 1..tag_end];
+            position = tag_end + 1;
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+
+            if let Some(closing) = tag.strip_prefix('/') {
+                if matches!(
+                    local_name(closing.trim()),
+                    "EntityType" | "ComplexType" | "EnumType" | "Action" | "Function"
+                ) {
+                    context = Context::None;
+                }
+                continue;
+            }
+
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+            let name_end = tag.find(|ch: char| ch.is_whitespace()).unwrap_or(tag.len());
+            let element = local_name(&tag[..name_end]);
+            let attributes = parse_attributes(&tag[name_end..])?;
+            let attribute = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            let required = |name: &str| {
+                attribute(name)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("CSDL {element} element is missing {name}"))
+            };
+
+            match (element, &context) {
+                ("EntityType", _) => {
+                    let name = required("Name")?;
+                    schema.entity_types.insert(
+                        name.clone(),
+                        SchemaEntityType {
+                            name: name.clone(),
+                            base_type: attribute("BaseType").map(unqualified_type),
+                            is_abstract: attribute("Abstract") == Some("true"),
+                            ..SchemaEntityType::default()
+                        },
+                    );
+                    context = if self_closing {
+                        Context::None
+                    } else {
+                        Context::EntityType(name)
+                    };
+                }
+                ("ComplexType" | "EnumType", _) if !self_closing => context = Context::Other,
+                ("Action" | "Function", _) => {
+                    let operation = SchemaOperation {
+                        name: required("Name")?,
+                        is_bound: attribute("IsBound") == Some("true"),
+                        ..SchemaOperation::default()
+                    };
+                    let is_action = element == "Action";
+                    let operations = if is_action {
+                        &mut schema.actions
+                    } else {
+                        &mut schema.functions
+                    };
+                    operations.push(operation);
+                    context = if self_closing {
+                        Context::None
+                    } else {
+                        Context::Operation {
+                            is_action,
+                            index: operations.len() - 1,
+                        }
+                    };
+                }
+                ("EntitySet", _) => {
+                    schema.entity_sets.insert(
+                        required("Name")?,
+                        unqualified_type(&required("EntityType")?),
+                    );
+                }
+                ("PropertyRef", Context::EntityType(name)) => {
+                    let key = required("Name")?;
+                    if let Some(entity_type) = schema.entity_types.get_mut(name) {
+                        entity_type.key.push(key);
+                    }
+                }
+                ("Property", Context::EntityType(name)) => {
+                    let property = SchemaProperty {
+                        name: required("Name")?,
+                        type_name: required("Type")?,
+                        nullable: attribute("Nullable") != Some("false"),
+                    };
+                    if let Some(entity_type) = schema.entity_types.get_mut(name) {
+                        entity_type.properties.push(property);
+                    }
+                }
+                ("NavigationProperty", Context::EntityType(name)) => {
+                    let type_name = required("Type")?;
+                    let property = SchemaNavigationProperty {
+                        name: required("Name")?,
+                        target_type: unqualified_type(&type_name),
+                        is_collection: type_name.starts_with("Collection("),
+                        partner: attribute("Partner").map(str::to_string),
+                    };
+                    if let Some(entity_type) = schema.entity_types.get_mut(name) {
+                        entity_type.navigation_properties.push(property);
+                    }
+                }
+                ("Parameter", Context::Operation { is_action, index }) => {
+                    let parameter = SchemaParameter {
+                        name: required("Name")?,
+                        type_name: required("Type")?,
+                        nullable: attribute("Nullable") != Some("false"),
+                    };
+                    schema
+                        .operation_mut(*is_action, *index)
+                        .parameters
+                        .push(parameter);
+                }
+                ("ReturnType", Context::Operation { is_action, index }) => {
+                    schema.operation_mut(*is_action, *index).return_type = Some(required("Type")?);
+                }
+                _ => {}
+            }
+        }
+
+        if schema.entity_types.is_empty() {
+            return Err("CSDL document contains no entity types".to_string());
+        }
+        Ok(schema)
+    }
+
+    fn operation_mut(&mut self, is_action: bool, index: usize) -> &mut SchemaOperation {
+        if is_action {
+            &mut self.actions[index]
+        } else {
+            &mut self.functions[index]
+        }
+    }
+
+    /// The entity type `name`, with or without its namespace prefix.
+    pub fn entity_type(&self, name: &str) -> Option<&SchemaEntityType> {
+        self.entity_types.get(&unqualified_type(name))
+    }
+
+    /// The entity type of the entity set `entity_set`, such as `account` for `accounts`.
+    pub fn entity_type_for_set(&self, entity_set: &str) -> Option<&SchemaEntityType> {
+        self.entity_type(self.entity_sets.get(entity_set)?)
+    }
+
+    /// `entity_type` and the types it inherits from, nearest first.
+    fn type_chain(&self, entity_type: &str) -> Vec<&SchemaEntityType> {
+        let mut chain = Vec::new();
+        let mut next = self.entity_type(entity_type);
+        while let Some(current) = next {
+            if chain.len() == MAX_BASE_TYPE_DEPTH {
+                break;
+            }
+            chain.push(current);
+            next = current
+                .base_type
+                .as_deref()
+                .and_then(|base| self.entity_type(base));
+        }
+        chain
+    }
+
+    /// Structural properties of `entity_type`, including inherited ones.
+    pub fn properties(&self, entity_type: &str) -> Vec<&SchemaProperty> {
+        self.type_chain(entity_type)
+            .into_iter()
+            .flat_map(|entity_type| &entity_type.properties)
+            .collect()
+    }
+
+    /// Navigation properties of `entity_type`, including inherited ones.
+    pub fn navigation_properties(&self, entity_type: &str) -> Vec<&SchemaNavigationProperty> {
+        self.type_chain(entity_type)
+            .into_iter()
+            .flat_map(|entity_type| &entity_type.navigation_properties)
+            .collect()
+    }
+
+    /// The structural property `name` of `entity_type`, including inherited ones.
+    pub fn property(&self, entity_type: &str, name: &str) -> Option<&SchemaProperty> {
+        self.properties(entity_type)
+            .into_iter()
+            .find(|property| property.name == name)
+    }
+
+    /// The navigation property `name` of `entity_type`, including inherited ones.
+    pub fn navigation_property(
+        &self,
+        entity_type: &str,
+        name: &str,
+    ) -> Option<&SchemaNavigationProperty> {
+        self.navigation_properties(entity_type)
+            .into_iter()
+            .find(|property| property.name == name)
+    }
+
+    /// The unbound action `name`.
+    pub fn action(&self, name: &str) -> Option<&SchemaOperation> {
+        self.actions
+            .iter()
+            .find(|action| action.name == name && !action.is_bound)
+    }
+
+    /// The unbound function `name`.
+    pub fn function(&self, name: &str) -> Option<&SchemaOperation> {
+        self.functions
+            .iter()
+            .find(|function| function.name == name && !function.is_bound)
+    }
+
+    /// Check that every column in a `$select` list is a property of the entity set's type.
+    pub fn validate_select(&self, entity_set: &str, columns: &[&str]) -> Result<(), String> {
+        let entity_type = self.require_entity_set(entity_set)?;
+        for column in columns {
+            if self.property(&entity_type.name, column).is_none() {
+                return Err(format!(
+                    "Property '{column}' not found on entity type '{}'",
+                    entity_type.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every navigation property in an `$expand` list belongs to the entity set's
+    /// type.
+    pub fn validate_expand(
+        &self,
+        entity_set: &str,
+        navigation_properties: &[&str],
+    ) -> Result<(), String> {
+        let entity_type = self.require_entity_set(entity_set)?;
+        for navigation in navigation_properties {
+            if self
+                .navigation_property(&entity_type.name, navigation)
+                .is_none()
+            {
+                return Err(format!(
+                    "Navigation property '{navigation}' not found on entity type '{}'",
+                    entity_type.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn require_entity_set(&self, entity_set: &str) -> Result<&SchemaEntityType, String> {
+        self.entity_type_for_set(entity_set)
+            .ok_or_else(|| format!("Entity set '{entity_set}' not found in $metadata"))
+    }
+}
+
+/// Element name without its namespace prefix, such as `Schema` for `edmx:Schema`.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Type name without `Collection(...)` or its namespace, such as `contact` for
+/// `Collection(mscrm.contact)`.
+fn unqualified_type(type_name: &str) -> String {
+    let type_name = type_name
+        .strip_prefix("Collection(")
+        .and_then(|inner| inner.strip_suffix(')'))
+        .unwrap_or(type_name);
+    type_name
+        .rsplit('.')
+        .next()
+        .unwrap_or(type_name)
+        .to_string()
+}
+
+/// `name="value"` pairs of an XML start tag, with XML entities in values decoded.
+pub(crate) fn parse_attributes(text: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let equals = rest
+            .find('=')
+            .ok_or_else(|| format!("Invalid XML attribute '{rest}'"))?;
+        let name = rest[..equals].trim().to_string();
+        let value_text = rest[equals + 1..].trim_start();
+        let quote = value_text
+            .chars()
+            .next()
+            .filter(|quote| *quote == '"' || *quote == '\'')
+            .ok_or_else(|| format!("Invalid XML attribute '{name}'"))?;
+        let value_end = value_text[1..]
+            .find(quote)
+            .ok_or_else(|| format!("Invalid XML attribute '{name}'"))?
+            + 1;
+        attributes.push((name, decode_entities(&value_text[1..value_end])));
+        rest = value_text[value_end + 1..].trim_start();
+    }
+    Ok(attributes)
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServiceSchema;
+
+    const CSDL: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<edmx:Edmx Version="4.0" xmlns:edmx="http://docs.oasis-open.org/odata/ns/edmx">
+  <edmx:DataServices>
+    <Schema Namespace="Microsoft.Dynamics.CRM" Alias="mscrm" xmlns="http://docs.oasis-open.org/odata/ns/edm">
+      <EntityType Name="crmbaseentity" Abstract="true" />
+      <EntityType Name="account" BaseType="mscrm.crmbaseentity">
+        <Key><PropertyRef Name="accountid" /></Key>
+        <Property Name="accountid" Type="Edm.Guid" />
+        <Property Name="name" Type="Edm.String" Unicode="false">
+          <Annotation Term="Org.OData.Core.V1.Description" String="Company &amp; name" />
+        </Property>
+        <Property Name="_primarycontactid_value" Type="Edm.Guid" />
+        <NavigationProperty Name="primarycontactid" Type="mscrm.contact" Nullable="false" Partner="account_primary_contact">
+          <ReferentialConstraint Property="_primarycontactid_value" ReferencedProperty="contactid" />
+        </NavigationProperty>
+        <NavigationProperty Name="contact_customer_accounts" Type="Collection(mscrm.contact)" Partner="parentcustomerid_account" />
+      </EntityType>
+      <ComplexType Name="WhoAmIResponse">
+        <Property Name="UserId" Type="Edm.Guid" Nullable="false" />
+      </ComplexType>
+      <!-- <EntityType Name="commented" /> -->
+      <Function Name="WhoAmI" IsBound="false">
+        <ReturnType Type="mscrm.WhoAmIResponse" Nullable="false" />
+      </Function>
+      <Action Name="Merge" IsBound="false">
+        <Parameter Name="Target" Type="mscrm.crmbaseentity" Nullable="false" />
+        <Parameter Name="PerformParentingChecks" Type="Edm.Boolean" Nullable="false" />
+      </Action>
+      <EntityContainer Name="System">
+        <EntitySet Name="accounts" EntityType="Microsoft.Dynamics.CRM.account">
+          <NavigationPropertyBinding Path="primarycontactid" Target="contacts" />
+        </EntitySet>
+      </EntityContainer>
+    </Schema>
+  </edmx:DataServices>
+</edmx:Edmx>"#;
+
+    #[test]
+    fn parses_entity_types_sets_and_operations() {
+        let schema = ServiceSchema::parse(CSDL).expect("should parse");
+
+        let account = schema
+            .entity_type_for_set("accounts")
+            .expect("accounts set");
+        assert_eq!(account.name, "account");
+        assert_eq!(account.base_type.as_deref(), Some("crmbaseentity"));
+        assert_eq!(account.key, vec!["accountid"]);
+        assert_eq!(account.properties.len(), 3);
+        assert!(
+            schema
+                .entity_type("mscrm.crmbaseentity")
+                .expect("base")
+                .is_abstract
+        );
+        assert!(schema.entity_type("commented").is_none());
+
+        let contacts = schema
+            .navigation_property("account", "contact_customer_accounts")
+            .expect("collection navigation");
+        assert_eq!(contacts.target_type, "contact");
+        assert!(contacts.is_collection);
+        assert_eq!(
+            schema
+                .navigation_property("account", "primarycontactid")
+                .and_then(|navigation| navigation.partner.as_deref()),
+            Some("account_primary_contact")
+        );
+
+        assert_eq!(
+            schema
+                .function("WhoAmI")
+                .and_then(|function| function.return_type.as_deref()),
+            Some("mscrm.WhoAmIResponse")
+        );
+        let merge = schema.action("Merge").expect("Merge action");
+        assert_eq!(merge.parameters.len(), 2);
+        assert!(!merge.parameters[0].nullable);
+        assert!(merge.return_type.is_none());
+    }
+
+    #[test]
+    fn validates_select_and_expand_against_entity_sets() {
+        let schema = ServiceSchema::parse(CSDL).expect("should parse");
+
+        assert!(
+            schema
+                .validate_select("accounts", &["name", "_primarycontactid_value"])
+                .is_ok()
+        );
+        assert_eq!(
+            schema.validate_select("accounts", &["nmae"]).unwrap_err(),
+            "Property 'nmae' not found on entity type 'account'"
+        );
+        assert!(
+            schema
+                .validate_expand("accounts", &["primarycontactid"])
+                .is_ok()
+        );
+        assert!(schema.validate_expand("accounts", &["name"]).is_err());
+        assert!(schema.validate_select("acounts", &["name"]).is_err());
+        assert!(ServiceSchema::parse("<edmx:Edmx />").is_err());
+    }
+}
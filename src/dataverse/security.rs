@@ -0,0 +1,90 @@
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+use crate::dataverse::access::action_entity_reference;
+use crate::dataverse::entity::EntityReference;
+use crate::dataverse::url::{encode_string_literal, row_path};
+
+/// Body of the `AddMembersTeam` and `RemoveMembersTeam` actions for the users `user_ids`.
+pub(crate) fn team_members_body(user_ids: &[Uuid]) -> Value {
+    json!({
+        "Members": user_ids
+            .iter()
+            .map(|id| action_entity_reference("systemuser", "systemuserid", *id))
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Path of the role collection of a `systemuser` or `team` principal.
+pub(crate) fn principal_roles_path(principal: &EntityReference) -> Result<String, String> {
+    let (entity_set, navigation) = match principal.logical_name.as_str() {
+        "systemuser" => ("systemusers", "systemuserroles_association"),
+        "team" => ("teams", "teamroles_association"),
+        other => {
+            return Err(format!(
+                "Security roles can only be assigned to a systemuser or team, not '{other}'"
+            ));
+        }
+    };
+    Ok(format!(
+        "{}/{navigation}",
+        row_path(entity_set, principal.id.as_hyphenated())
+    ))
+}
+
+/// `roles` query for the role named `name` in the business unit `business_unit_id`. Each
+/// business unit has its own copy of every role.
+pub(crate) fn security_role_query(name: &str, business_unit_id: Uuid) -> String {
+    format!(
+        "roles?$select=roleid,name&$filter=name eq {} and _businessunitid_value eq {business_unit_id}",
+        encode_string_literal(name)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{principal_roles_path, security_role_query, team_members_body};
+    use crate::dataverse::entity::EntityReference;
+
+    #[test]
+    fn builds_team_members_body() {
+        let user = Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid");
+
+        assert_eq!(
+            team_members_body(&[user]),
+            json!({
+                "Members": [{
+                    "@odata.type": "Microsoft.Dynamics.CRM.systemuser",
+                    "systemuserid": "11111111-2222-3333-4444-555555555555"
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn role_paths_depend_on_principal_type() {
+        let id = Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid");
+        let principal = |logical_name: &str| EntityReference {
+            id,
+            logical_name: logical_name.to_string(),
+            name: None,
+        };
+
+        assert_eq!(
+            principal_roles_path(&principal("systemuser")).expect("user path"),
+            "systemusers(11111111-2222-3333-4444-555555555555)/systemuserroles_association"
+        );
+        assert_eq!(
+            principal_roles_path(&principal("team")).expect("team path"),
+            "teams(11111111-2222-3333-4444-555555555555)/teamroles_association"
+        );
+        assert!(principal_roles_path(&principal("account")).is_err());
+        assert_eq!(
+            security_role_query("Sales Person", id),
+            "roles?$select=roleid,name&$filter=name eq 'Sales%20Person' and _businessunitid_value eq 11111111-2222-3333-4444-555555555555"
+        );
+    }
+}
@@ -1,51 +1,177 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use chrono::{DateTime, Utc};
-use log::debug;
-use reqwest::Client;
-use reqwest::header::CONTENT_TYPE;
+use log::{Level, debug, error, warn};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use reqwest::header::{CONTENT_TYPE, HeaderName, HeaderValue};
 use serde::de::DeserializeOwned;
 use serde_json::Map;
 use serde_json::Value;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 use uuid::Uuid;
 
 use crate::LogLevel;
 use crate::auth::config::AuthConfig;
-use crate::auth::connectionstring::{
-    parse_connection_string_auth_config, parse_connection_string_url,
+use crate::auth::token::{CachedToken, parse_jwt_expiry};
+use crate::auth::tokencache::TokenCache;
+use crate::dataverse::access::{
+    AccessRights, action_entity_reference, build_principal_access_body, parse_principal_access,
+    principal_reference,
 };
-use crate::auth::credentials::{TokenExchange, refresh_device_code_token};
-use crate::auth::token::{
-    CachedToken, fetch_token_for_config, is_expiring_soon, load_cached_token,
-    resolve_token_cache_file_path, save_cached_token,
+use crate::dataverse::accessdiagnostics::{
+    AccessDiagnosis, TeamAccess, parse_owner, parse_shared_principals, shared_principals_path,
+    user_teams_path,
 };
+use crate::dataverse::activityparty::ACTIVITY_PARTIES_SUFFIX;
+use crate::dataverse::aggregate::{AggregateRow, aggregate_rows};
+use crate::dataverse::alternatekey::format_key_segment;
+use crate::dataverse::apierror::{ApiError, EXPIRED_VERSION_STAMP};
+use crate::dataverse::apply::apply_query_options;
 use crate::dataverse::batch::{
     ExecuteMultipleRequest, ExecuteMultipleResponse, ExecuteMultipleResponseItem,
-    OrganizationRequest, ParsedBatchPart, PreparedBatchItem, PreparedBatchRequest,
-    entity_to_write_body, parse_batch_response_parts, parse_fault,
+    OrganizationRequest, ParsedBatchPart, PreparedBatchItem, PreparedBatchRequest, batch_get_item, batch_get_item_with_prefer,
+    batch_part_json, entity_to_write_body, entity_to_write_map, parse_batch_response_parts,
+    parse_fault,
+};
+use crate::dataverse::bulkresult::BulkResult;
+use crate::dataverse::businessunit::{
+    BUSINESS_UNIT_COLUMNS, BusinessUnit, BusinessUnitNode, BusinessUnitUser,
+    build_business_unit_tree, subtree_user_queries,
+};
+use crate::dataverse::capacity::{
+    CapacityReport, build_capacity_report, parse_record_count_collection,
+};
+use crate::dataverse::capabilities::{
+    TableCapabilities, TableFeature, parse_table_capabilities,
+};
+use crate::dataverse::clientbuilder::{
+    Credentials, RequestMiddleware, ServiceClientBuilder, bypass_not_allowed,
+};
+use crate::dataverse::columnset::{
+    DefaultColumnSets, apply_default_attributes, apply_default_select,
+};
+use crate::dataverse::connectionhealth::{ConnectionHealth, parse_version};
+use crate::dataverse::countresult::{CountLimit, CountResult, is_aggregate_limit_error};
+use crate::dataverse::currency::{CurrencyFormat, CurrencyFormats, currency_ids, currency_queries};
+use crate::dataverse::customapi::{
+    CustomApiDefinition, custom_api_function_path, parse_custom_api_definition,
+    validate_custom_api_parameters,
+};
+use crate::dataverse::duplicates::{DUPLICATE_PAGE_SIZE, retrieve_duplicates_path};
+use crate::dataverse::entity::{Entity, EntityReference, Value as DataverseValue};
+use crate::dataverse::entityattribute::{
+    ATTRIBUTE_DETAIL_METADATA_TYPES, AttributeDetail, AttributeTypeName, DateTimeBehavior,
+    EntityAttribute, parse_attribute_details,
 };
-use crate::dataverse::entity::Entity;
-use crate::dataverse::entity::Value::Int;
-use crate::dataverse::entityattribute::EntityAttribute;
 use crate::dataverse::entitydefinition::EntityDefinition;
 use crate::dataverse::entityrelationship::EntityRelationship;
-use crate::dataverse::fetchxml::{apply_paging, ensure_aggregate_page_size, fetch_tag_has_attr};
+use crate::dataverse::environmentvariable::{
+    EnvironmentVariable, environment_variable_query, parse_environment_variable,
+};
+use crate::dataverse::expand::{
+    CollectionNavigation, expanded_collection_properties, expanded_next_link, expanded_rows,
+    find_collection_navigation, parse_many_to_many_navigations, parse_one_to_many_navigations,
+    record_id,
+};
+use crate::dataverse::executioncontext::{
+    ExecutionContext, SecurityRole, apply_impersonation, parse_caller_object_id,
+    parse_security_roles, parse_who_am_i,
+};
+use crate::dataverse::guid::{IntoGuid, into_uuids};
+use crate::dataverse::label::localize_labels;
+use crate::dataverse::language::{
+    PROVISIONED_LANGUAGES_PATH, parse_provisioned_languages, user_language_path,
+};
+use crate::dataverse::listresponse::{
+    FetchXmlPage, ListResponse, PageCursor, parse_count, parse_delta_link, parse_next_link,
+    parse_total_record_count, parse_total_record_count_limit_exceeded, validate_next_link,
+};
+use crate::dataverse::merge::{build_merge_body, check_mergeable};
+use crate::dataverse::metadatachanges::{
+    MetadataCache, MetadataChanges, MetadataQuery, metadata_changes_path, parse_metadata_changes,
+};
+use crate::dataverse::newtable::{CREATED_TABLE_SELECT, NewTable};
+use crate::dataverse::lookupbind::{LookupNavigation, parse_lookup_navigations};
+use crate::dataverse::fileupload::{
+    DEFAULT_CHUNK_SIZE, FileUploadSession, check_file_size, content_range, content_sha256,
+    load_upload_session, parse_max_size_kb, save_upload_session,
+};
+use crate::dataverse::fetchxml::{
+    FetchOptions, PageToken, ResultColumns, apply_paging, ensure_aggregate_page_size,
+    ensure_primary_key_order, fetch_tag_attr_value, fetch_tag_has_attr, fetchxml_result_columns,
+    next_page_token, split_in_conditions,
+};
 use crate::dataverse::parse::{
-    extract_paging_cookie, parse_entities_from_response, parse_more_records,
-    parse_record_count_from_response,
+    attach_raw_rows, extract_paging_cookie, parse_aggregate_rows_from_response,
+    parse_entities_from_response, parse_more_records, parse_record_count_from_response,
+};
+use crate::dataverse::optionset::{
+    OPTION_SET_METADATA_TYPES, OptionSetMap, parse_option_set_attributes,
+    validate_entity_options, validate_payload_options,
 };
-use crate::dataverse::requestparameters::RequestParameters;
+use crate::dataverse::organization::{OrganizationInfo, parse_organization_info};
+use crate::dataverse::pageretry::PageRetryPolicy;
+use crate::dataverse::progress::{ProgressCallback, ProgressTracker};
+use crate::dataverse::provisioning::{
+    NewBusinessUnit, NewSystemUser, NewTeam, SYSTEM_USER_COLUMNS, SystemUser, TEAM_COLUMNS, Team,
+    row_query, team_by_name_query, user_by_object_id_query,
+};
+use crate::dataverse::queryvalidation::{
+    fetchxml_references, odata_columns, parse_column_names, unknown_attribute_error,
+    unknown_entity_error,
+};
+use crate::dataverse::recyclebin::{RECYCLE_BIN_TABLES_QUERY, RecycleBinConfig, restore_body};
+use crate::dataverse::requestid::{
+    api_error, echo_client_request_id, ensure_client_request_id, with_operation_request_id,
+};
+use crate::dataverse::requestoptions::{
+    ENTITY_ANNOTATIONS, FETCHXML_ANNOTATIONS, FORMATTED_VALUE_ANNOTATIONS, RequestOptions,
+};
+use crate::dataverse::requestparameters::{RequestParameters, parse_header};
+use crate::dataverse::responsemeta::{ResponseCallback, ResponseMeta, WithMeta};
+use crate::dataverse::schema::ServiceSchema;
+use crate::dataverse::security::{principal_roles_path, security_role_query, team_members_body};
+use crate::dataverse::sync::{
+    ChangeTrackingResult, VersionSyncResult, build_version_sync_fetchxml, change_events,
+    max_version, without_deleted_rows,
+};
+use crate::dataverse::telemetry::{record_page, record_request, record_retries};
+use crate::dataverse::trackedentity::TrackedEntity;
+use crate::dataverse::transport::{Transport, TransportMode};
+use crate::dataverse::url::{
+    attribute_definition_path, batch_request_path, bind_path, encode_query_value,
+    encode_string_literal, entity_definition_path, fetchxml_query_path, odata_query_path, row_path,
+    select_path, web_api_path, web_api_url, with_inline_count,
+};
+use crate::dataverse::valueconverter::ValueConverter;
+use crate::log::{sanitize_fetchxml, sanitize_message, sanitize_url};
 
-const ROW_NUMBER_ATTRIBUTE: &str = "__rownum";
 const AGGREGATE_PAGE_SIZE: i32 = 5000;
 const DEFAULT_FETCHXML_PAGE_SIZE: i32 = 5000;
+const RECORD_COUNT_CHUNK_SIZE: usize = 100;
+
+/// Default `in` condition size above which FetchXML paging splits the query. Splitting is off
+/// until `set_in_condition_split_threshold` turns it on.
+const DEFAULT_IN_CONDITION_SPLIT_THRESHOLD: usize = 0;
+
+/// Default FetchXML GET URL length above which the query is sent inside a `$batch` request body,
+/// matching Dataverse's 32 KB URL limit.
+const DEFAULT_BATCH_GET_URL_THRESHOLD: usize = 32_768;
+
+/// Tables per `get_metadata_bulk` batch, keeping each batch well under the 1000-part limit.
+const METADATA_BULK_CHUNK_SIZE: usize = 250;
 
-/// OData list wrapper returned by Dataverse metadata endpoints.
 #[derive(Debug, serde::Deserialize)]
-struct ODataList<T> {
-    value: Vec<T>,
+struct DateTimeAttributeBehavior {
+    #[serde(rename = "LogicalName")]
+    logical_name: String,
+    #[serde(rename = "DateTimeBehavior")]
+    date_time_behavior: Option<AttributeTypeName>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -85,74 +211,374 @@ struct EntityRelationshipManyToMany {
 /// HTTP client for Dataverse Web API operations.
 pub struct ServiceClient {
     client: Client,
-    auth: AuthConfig,
     base_url: std::string::String,
-    token_cache_path: PathBuf,
-    token: Mutex<CachedToken>,
+    // Web API root such as `/api/data/v9.2`, prefixed to every request path.
+    api_path: String,
+    middleware: Vec<Arc<dyn RequestMiddleware>>,
+    // Added to every request that does not set the header itself.
+    default_headers: Vec<(HeaderName, HeaderValue)>,
+    // Writes that bypass custom plug-ins and flows are refused unless the builder opted in.
+    allow_bypass_custom_logic: bool,
+    on_response: Option<ResponseCallback>,
+    // Language every metadata label is rewritten to, or `None` for the caller's language.
+    label_language: Option<i32>,
+    // Shared with other clients when built from a `TokenCache`.
+    token_cache: Arc<TokenCache>,
     // Entity definitions are cached as a single blob because most metadata-driven features need
     // the full list, and Dataverse returns them efficiently in one request.
     entity_definitions_cache: Mutex<Option<Vec<EntityDefinition>>>,
     // Attribute metadata is cached per logical entity name because callers usually fan out to only
     // a small number of entities during a session.
     entity_attributes_cache: Mutex<HashMap<String, Vec<EntityAttribute>>>,
-    log_level: LogLevel,
+    // FetchXML can name columns the attribute list above filters out, such as those that are not
+    // valid in OData, so query validation keeps every column name separately.
+    column_names_cache: Mutex<HashMap<String, Vec<String>>>,
+    lookup_navigations_cache: Mutex<HashMap<String, Vec<LookupNavigation>>>,
+    collection_navigations_cache: Mutex<HashMap<String, Vec<CollectionNavigation>>>,
+    table_capabilities_cache: Mutex<HashMap<String, TableCapabilities>>,
+    log_level: AtomicU8,
+    transport: Transport,
+    default_columns: Mutex<DefaultColumnSets>,
+    // The caller's identity does not change for the lifetime of a token, so WhoAmI is issued at
+    // most once and roles are only loaded when a caller asks for them.
+    execution_context_cache: Mutex<Option<ExecutionContext>>,
+    merge_lookup_annotations: AtomicBool,
+    keep_raw_json: AtomicBool,
+    // Sent as `CallerObjectId` on every Dataverse request when set, so all operations run as the
+    // impersonated user.
+    caller_object_id: Mutex<Option<Uuid>>,
+    // Choice options are cached per logical entity name alongside attribute metadata, and are
+    // only loaded when write validation is enabled or a caller asks for them.
+    option_sets_cache: Mutex<HashMap<String, OptionSetMap>>,
+    attribute_details_cache: Mutex<HashMap<String, HashMap<String, AttributeDetail>>>,
+    validate_option_sets: AtomicBool,
+    validate_queries: AtomicBool,
+    // A std lock rather than the tokio mutex because entity parsing is synchronous.
+    value_converter: RwLock<Option<Arc<dyn ValueConverter>>>,
+    page_retry_policy: RwLock<PageRetryPolicy>,
+    custom_api_cache: Mutex<HashMap<String, CustomApiDefinition>>,
+    // `$metadata` runs to tens of megabytes, so it is downloaded once and shared.
+    service_schema_cache: Mutex<Option<Arc<ServiceSchema>>>,
+    in_condition_split_threshold: AtomicUsize,
+    batch_get_url_threshold: AtomicUsize,
+    // Set by `shutdown`; `send` refuses new requests once it is set, and `idle` wakes the
+    // shutdown when the last in-flight request finishes.
+    shutting_down: AtomicBool,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// Counts a request as in flight until it is dropped.
+struct InFlightGuard<'a> {
+    in_flight: &'a AtomicUsize,
+    idle: &'a Notify,
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        if self.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
 }
 
 impl ServiceClient {
     /// Create a new client from a Dataverse connection string.
     pub async fn new(connection_string: &str, log_level: LogLevel) -> Result<Self, String> {
-        let base_url = parse_connection_string_url(connection_string)?;
-        let auth = parse_connection_string_auth_config(connection_string)?;
-        Self::new_internal(auth, base_url, log_level).await
+        Self::builder()
+            .connection_string(connection_string)
+            .log_level(log_level)
+            .build()
+            .await
     }
 
     /// Create a new client from explicit authentication configuration.
     pub async fn new_with_auth(auth: AuthConfig, log_level: LogLevel) -> Result<Self, String> {
-        let base_url = auth.dataverse_url().to_string();
-        Self::new_internal(auth, base_url, log_level).await
+        Self::builder().auth(auth).log_level(log_level).build().await
     }
 
-    async fn new_internal(
+    /// Create a new client that records or replays Dataverse traffic.
+    /// Replay mode never acquires a token, so recorded tests can run without live credentials.
+    pub async fn new_with_transport(
         auth: AuthConfig,
-        base_url: String,
         log_level: LogLevel,
+        transport: TransportMode,
     ) -> Result<Self, String> {
-        let token_cache_path = resolve_token_cache_file_path(&auth)?;
+        Self::builder()
+            .auth(auth)
+            .log_level(log_level)
+            .transport(transport)
+            .build()
+            .await
+    }
 
-        // Initialization eagerly ensures a usable token so later requests can fail on Dataverse
-        // semantics instead of first-request authentication setup.
-        let token = if let Some(cached) = load_cached_token(&token_cache_path)? {
-            if !cached.access_token.trim().is_empty() && !is_expiring_soon(cached.expires_at) {
-                cached
-            } else {
-                let refreshed = fetch_token_for_config(&auth).await?;
-                save_cached_token(&token_cache_path, &refreshed)?;
-                refreshed
+    /// Start configuring a client. See `ServiceClientBuilder`.
+    pub fn builder() -> ServiceClientBuilder {
+        ServiceClientBuilder::new()
+    }
+
+    pub(crate) async fn from_builder(builder: ServiceClientBuilder) -> Result<Self, String> {
+        let (credentials, base_url) = builder.resolve()?;
+        let client = builder.http_client()?;
+        let default_headers = builder.header_values()?;
+        let log_level = builder.log_level;
+        let transport = Transport::new(builder.transport.clone())?;
+
+        let token_cache = match credentials {
+            _ if transport.is_replay() => Arc::new(TokenCache::fixed(
+                match credentials {
+                    Credentials::Auth(auth) => Some(auth),
+                    Credentials::TokenCache(cache) => cache.auth().cloned(),
+                    Credentials::StaticToken(_) => None,
+                },
+                PathBuf::new(),
+                CachedToken {
+                    access_token: "replay".to_string(),
+                    refresh_token: None,
+                    expires_at: Some(u64::MAX),
+                },
+            )),
+            Credentials::StaticToken(access_token) => {
+                let expires_at = parse_jwt_expiry(&access_token);
+                Arc::new(TokenCache::fixed(
+                    None,
+                    PathBuf::new(),
+                    CachedToken {
+                        access_token,
+                        refresh_token: None,
+                        expires_at,
+                    },
+                ))
             }
-        } else {
-            let fetched = fetch_token_for_config(&auth).await?;
-            save_cached_token(&token_cache_path, &fetched)?;
-            fetched
+            // Initialization eagerly ensures a usable token so later requests can fail on
+            // Dataverse semantics instead of first-request authentication setup.
+            Credentials::Auth(auth) => {
+                Arc::new(
+                    TokenCache::load_with_options(auth, builder.allow_insecure_url, log_level)
+                        .await?,
+                )
+            }
+            Credentials::TokenCache(cache) => cache,
         };
 
         Ok(Self {
-            client: Client::new(),
-            auth,
+            client,
             base_url,
-            token_cache_path,
-            token: Mutex::new(token),
+            api_path: web_api_path(&builder.api_version),
+            middleware: builder.middleware,
+            default_headers,
+            allow_bypass_custom_logic: builder.allow_bypass_custom_logic,
+            on_response: builder.on_response,
+            label_language: builder.label_language,
+            token_cache,
             entity_definitions_cache: Mutex::new(None),
             entity_attributes_cache: Mutex::new(HashMap::new()),
-            log_level,
+            column_names_cache: Mutex::new(HashMap::new()),
+            lookup_navigations_cache: Mutex::new(HashMap::new()),
+            collection_navigations_cache: Mutex::new(HashMap::new()),
+            table_capabilities_cache: Mutex::new(HashMap::new()),
+            log_level: AtomicU8::new(log_level.as_u8()),
+            transport,
+            default_columns: Mutex::new(DefaultColumnSets::default()),
+            execution_context_cache: Mutex::new(None),
+            merge_lookup_annotations: AtomicBool::new(false),
+            keep_raw_json: AtomicBool::new(false),
+            caller_object_id: Mutex::new(None),
+            option_sets_cache: Mutex::new(HashMap::new()),
+            attribute_details_cache: Mutex::new(HashMap::new()),
+            validate_option_sets: AtomicBool::new(false),
+            validate_queries: AtomicBool::new(false),
+            value_converter: RwLock::new(None),
+            page_retry_policy: RwLock::new(builder.page_retry_policy),
+            custom_api_cache: Mutex::new(HashMap::new()),
+            service_schema_cache: Mutex::new(None),
+            in_condition_split_threshold: AtomicUsize::new(DEFAULT_IN_CONDITION_SPLIT_THRESHOLD),
+            batch_get_url_threshold: AtomicUsize::new(DEFAULT_BATCH_GET_URL_THRESHOLD),
+            shutting_down: AtomicBool::new(false),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
         })
     }
 
     /// Return the current token expiry as a UTC datetime.
     pub async fn token_expires_at(&self) -> Option<DateTime<Utc>> {
-        let expires_at = self.token.lock().await.expires_at?;
+        let expires_at = self.token_cache.expires_at().await?;
         DateTime::<Utc>::from_timestamp(expires_at as i64, 0)
     }
 
+    /// Register the columns selected by FetchXML queries against `entity` that select no
+    /// attributes, and by OData queries against it without `$select`. `entity` may be a logical
+    /// or entity set name; either registers the table, so queries by the other name use it too.
+    pub async fn set_default_columns(
+        &self,
+        entity: &str,
+        columns: Vec<String>,
+    ) -> Result<(), String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        self.default_columns
+            .lock()
+            .await
+            .set(&logical_name, columns);
+        Ok(())
+    }
+
+    /// Remove the registered default columns for `entity`, a logical or entity set name.
+    pub async fn clear_default_columns(&self, entity: &str) -> Result<(), String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        self.default_columns.lock().await.remove(&logical_name);
+        Ok(())
+    }
+
+    /// Select the primary id and primary name attributes by default for entities without a
+    /// registered column list.
+    pub async fn set_derive_default_columns(&self, enabled: bool) {
+        self.default_columns.lock().await.derive_from_metadata = enabled;
+    }
+
+    /// Collapse each lookup into a single `EntityReference` attribute on retrieved entities,
+    /// dropping the sibling `{lookup}name` keys. See `Entity::merge_lookup_annotations`.
+    pub fn set_merge_lookup_annotations(&self, enabled: bool) {
+        self.merge_lookup_annotations
+            .store(enabled, Ordering::Relaxed);
+    }
+
+    /// Keep each retrieved row's original JSON in `Entity::raw`, for values the typed attributes
+    /// do not capture. Costs a copy of every row.
+    pub fn set_keep_raw_json(&self, enabled: bool) {
+        self.keep_raw_json.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Consult `converter` for every attribute of retrieved rows before the built-in conversion, or
+    /// restore the built-in conversion with `None`. See `ValueConverter`.
+    pub fn set_value_converter(&self, converter: Option<Arc<dyn ValueConverter>>) {
+        if let Ok(mut current) = self.value_converter.write() {
+            *current = converter;
+        }
+    }
+
+    /// Retry a page of a multi-page read that fails with a connection error or a throttled or
+    /// unavailable response, resending the same page request. Applies to FetchXML paging, paged
+    /// counts, and change tracking reads. Use `PageRetryPolicy::disabled()` to fail on the first
+    /// error.
+    pub fn set_page_retry_policy(&self, policy: PageRetryPolicy) {
+        if let Ok(mut current) = self.page_retry_policy.write() {
+            *current = policy;
+        }
+    }
+
+    /// Send one page request of a multi-page read, retrying it under the page retry policy.
+    async fn with_page_retry<T>(
+        &self,
+        mut request: impl AsyncFnMut() -> Result<T, String>,
+    ) -> Result<T, String> {
+        let policy = self
+            .page_retry_policy
+            .read()
+            .map(|policy| *policy)
+            .unwrap_or_default();
+        with_operation_request_id(async {
+            let mut attempt = 1;
+            loop {
+                match request().await {
+                    Err(error) => match policy.next_delay(attempt, &error) {
+                        Some(delay) => {
+                            if self.log_level().includes(Level::Warn) {
+                                warn!(
+                                    "Retrying page after transient error (attempt {attempt}): {}",
+                                    sanitize_message(&error)
+                                );
+                            }
+                            record_retries("page", 1);
+                            tokio::time::sleep(delay).await;
+                            attempt += 1;
+                        }
+                        None => return Err(error),
+                    },
+                    result => return result,
+                }
+            }
+        })
+        .await
+    }
+
+    /// Logging level the client currently emits at.
+    pub fn log_level(&self) -> LogLevel {
+        LogLevel::from_u8(self.log_level.load(Ordering::Relaxed))
+    }
+
+    /// Language metadata labels are read in, or `None` for the calling user's language. See
+    /// `ServiceClientBuilder::label_language`.
+    pub fn label_language(&self) -> Option<i32> {
+        self.label_language
+    }
+
+    /// The HTTP client requests are sent through. Pass it to
+    /// `ServiceClientBuilder::shared_http_client` to give another client for the same environment
+    /// the same connection pool.
+    pub fn http_client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Change the logging level of this client. Requests already in flight pick up the new level
+    /// for the messages they have not emitted yet.
+    pub fn set_log_level(&self, log_level: LogLevel) {
+        self.log_level.store(log_level.as_u8(), Ordering::Relaxed);
+    }
+
+    /// Stop accepting requests and wait up to `deadline` for requests already in flight to
+    /// finish. Requests started after this call fail with `Client is shut down`. Fails when
+    /// requests are still running at the deadline; they are not cancelled.
+    ///
+    /// Also stops the auto-refresh task of the client's `TokenCache`, so other clients sharing
+    /// the cache fall back to refreshing tokens on demand, and a `ChangeFeed` driven by `run` or
+    /// `into_stream` stops at its next poll. `BulkExecutor` and `copy_records` write within the
+    /// caller's own future, so await them before shutting down or their remaining batches fail.
+    pub async fn shutdown(&self, deadline: Duration) -> Result<(), String> {
+        self.shutting_down.store(true, Ordering::Release);
+        self.token_cache.stop_auto_refresh();
+        let started = Instant::now();
+
+        loop {
+            let mut idle = pin!(self.idle.notified());
+            // Register for the wake-up before checking the count so a request that finishes in
+            // between is not missed.
+            idle.as_mut().enable();
+            let in_flight = self.in_flight.load(Ordering::Acquire);
+            if in_flight == 0 {
+                return Ok(());
+            }
+
+            let remaining = deadline.saturating_sub(started.elapsed());
+            if remaining.is_zero() || tokio::time::timeout(remaining, idle).await.is_err() {
+                return Err(format!(
+                    "Shutdown deadline passed with {} requests still in flight",
+                    self.in_flight.load(Ordering::Acquire)
+                ));
+            }
+        }
+    }
+
+    /// True once `shutdown` has been called.
+    pub fn is_shut_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Acquire)
+    }
+
+    /// Split FetchXML `in` conditions with more than `max_values` values into several queries
+    /// whose results are merged by the paging helpers. `0`, the default, turns splitting off.
+    /// Queries with `top`, `count`, `distinct`, `aggregate`, or an `<order>` are never split,
+    /// because their parts would each apply the limit or order on their own.
+    pub fn set_in_condition_split_threshold(&self, max_values: usize) {
+        self.in_condition_split_threshold.store(max_values, Ordering::Relaxed);
+    }
+
+    /// Send FetchXML queries whose GET URL is longer than `max_length` characters as a GET inside a
+    /// `$batch` request body, where the URL length limit does not apply. `0` always sends a plain
+    /// GET.
+    pub fn set_batch_get_url_threshold(&self, max_length: usize) {
+        self.batch_get_url_threshold
+            .store(max_length, Ordering::Relaxed);
+    }
+
     /// Retrieve a single FetchXML response page without automatic paging.
     pub async fn retrieve_multiple_fetchxml(
         &self,
@@ -161,9 +587,11 @@ impl ServiceClient {
     ) -> Result<Vec<Entity>, std::string::String> {
         let primary_id_attribute = self.resolve_primary_id_attribute(entity).await?;
         let attribute_map = self.entity_attribute_map(entity).await?;
+        let fetchxml = self.apply_default_columns(entity, fetchxml).await?;
+        self.validate_fetchxml_if_enabled(&fetchxml).await?;
         self.retrieve_multiple_fetchxml_single(
             entity,
-            fetchxml,
+            &fetchxml,
             primary_id_attribute.as_deref(),
             Some(&attribute_map),
         )
@@ -180,6 +608,70 @@ impl ServiceClient {
             .await
     }
 
+    /// Retrieve every page of a FetchXML query after applying typed performance options such as
+    /// `latematerialize`, failing before the request when the combination is unsupported.
+    pub async fn retrieve_multiple_fetchxml_paging_with_options(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        options: &FetchOptions,
+    ) -> Result<Vec<Entity>, String> {
+        if options.no_auto_paging {
+            return Ok(self
+                .retrieve_multiple_fetchxml_page(entity, fetchxml, options)
+                .await?
+                .entities);
+        }
+        let fetchxml = options.apply(fetchxml)?;
+        self.retrieve_multiple_fetchxml_paging(entity, &fetchxml).await
+    }
+
+    /// Send a FetchXML query once and return the page Dataverse answers with, together with its
+    /// `morerecords` flag, paging cookie, and total record count. The query's own `page`, `count`,
+    /// and `paging-cookie` attributes are sent unchanged, so callers drive paging themselves.
+    pub async fn retrieve_multiple_fetchxml_page(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        options: &FetchOptions,
+    ) -> Result<FetchXmlPage, String> {
+        let fetchxml = options.apply(fetchxml)?;
+        let primary_id_attribute = self.resolve_primary_id_attribute(entity).await?;
+        let attribute_map = self.entity_attribute_map(entity).await?;
+        let fetchxml = self.apply_default_columns(entity, &fetchxml).await?;
+        self.validate_fetchxml_if_enabled(&fetchxml).await?;
+
+        let json = self.fetch_fetchxml_json(entity, &fetchxml).await?;
+        let more_records = parse_more_records(&json);
+        let paging_cookie = extract_paging_cookie(&json);
+        Ok(FetchXmlPage {
+            entities: self.parse_entities(
+                &json,
+                entity,
+                primary_id_attribute.as_deref(),
+                Some(&attribute_map),
+            )?,
+            more_records,
+            next_page: next_page_token(&fetchxml, more_records, paging_cookie.clone())?,
+            paging_cookie,
+            total_record_count: parse_total_record_count(&json),
+            total_record_count_limit_exceeded: parse_total_record_count_limit_exceeded(&json),
+        })
+    }
+
+    /// Retrieve the page of a FetchXML query at `token`, such as `PageToken::first()` or the
+    /// `next_page` of an earlier page restored from a checkpoint.
+    pub async fn retrieve_multiple_fetchxml_page_with_token(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        token: &PageToken,
+        options: &FetchOptions,
+    ) -> Result<FetchXmlPage, String> {
+        self.retrieve_multiple_fetchxml_page(entity, &token.apply(fetchxml)?, options)
+            .await
+    }
+
     /// Retrieve multiple records by FetchXML, automatically paging until all results are returned.
     /// Uses the provided page size when specified, otherwise defaults to 5000 records per page.
     /// Reports page-level progress as `(page_number, total_records_retrieved_so_far)`.
@@ -193,117 +685,452 @@ impl ServiceClient {
     where
         F: FnMut(usize, usize),
     {
-        let page_size = page_size.unwrap_or(DEFAULT_FETCHXML_PAGE_SIZE);
-        let primary_id_attribute = self.resolve_primary_id_attribute(entity).await?;
-        let attribute_map = self.entity_attribute_map(entity).await?;
-        if fetch_tag_has_attr(fetchxml, "top")? {
-            let entities = self
-                .retrieve_multiple_fetchxml_single(
-                    entity,
-                    fetchxml,
-                    primary_id_attribute.as_deref(),
-                    Some(&attribute_map),
-                )
-                .await?;
-            on_progress(1, entities.len());
-            return Ok(entities);
-        }
-
-        let mut page = 1;
-        let mut paging_cookie: Option<std::string::String> = None;
         let mut entities: Vec<Entity> = vec![];
+        self.retrieve_multiple_fetchxml_for_each_page(
+            entity,
+            fetchxml,
+            page_size,
+            async |page, page_entities| {
+                entities.extend(page_entities);
+                on_progress(page, entities.len());
+                Ok(())
+            },
+        )
+        .await?;
 
-        loop {
-            let fetchxml = ensure_fetch_page_size(fetchxml, page_size)?;
-            let fetch_with_paging = apply_paging(
-                &ensure_aggregate_page_size(&fetchxml, AGGREGATE_PAGE_SIZE)?,
-                page,
-                paging_cookie.as_deref(),
-            )?;
+        Ok(entities)
+    }
 
-            if self.log_level.includes_debug() {
-                debug!("Fetch page: {}", page);
-                debug!("FetchXML: {}", fetch_with_paging);
-            }
+    /// Retrieve every page of a FetchXML query, reporting pages, records, and elapsed time to
+    /// `progress` after each page. Uses 5000 records per page unless `page_size` is given.
+    pub async fn retrieve_multiple_fetchxml_paging_with_progress_callback(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        page_size: Option<i32>,
+        progress: &ProgressCallback,
+    ) -> Result<Vec<Entity>, String> {
+        let mut tracker = ProgressTracker::new(Some(progress), None);
+        let mut entities: Vec<Entity> = vec![];
+        self.retrieve_multiple_fetchxml_for_each_page(
+            entity,
+            fetchxml,
+            page_size,
+            async |_, page_entities| {
+                tracker.page_done(page_entities.len());
+                entities.extend(page_entities);
+                Ok(())
+            },
+        )
+        .await?;
 
-            let mut url = format!("{}/api/data/v9.2/{}", self.base_url, entity);
-            url.push_str("?fetchXml=");
-            url.push_str(&urlencoding::encode(&fetch_with_paging));
+        Ok(entities)
+    }
 
-            if self.log_level.includes_debug() {
-                debug!("Url: {:?}", url);
-            }
+    /// Retrieve every page of a FetchXML query with `options`: its client-side paging options,
+    /// such as `RequestOptions::row_numbers`, and its headers, sent with every page. Uses 5000
+    /// records per page.
+    pub async fn retrieve_multiple_fetchxml_paging_with_request_options(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        options: &RequestOptions,
+    ) -> Result<Vec<Entity>, String> {
+        let mut entities: Vec<Entity> = vec![];
+        self.retrieve_multiple_fetchxml_for_each_page_with_options(
+            entity,
+            fetchxml,
+            None,
+            options,
+            async |_, page_entities| {
+                entities.extend(page_entities);
+                Ok(())
+            },
+        )
+        .await?;
 
-            let access_token = self.get_access_token().await?;
-            let resp = self
-                .client
-                .get(&url)
-                .bearer_auth(&access_token)
-                .header("Accept", "application/json")
-                .header(
-                    "Prefer",
-                    "odata.include-annotations=\"Microsoft.Dynamics.CRM.fetchxmlpagingcookie,Microsoft.Dynamics.CRM.morerecords,Microsoft.Dynamics.CRM.lookuplogicalname,OData.Community.Display.V1.FormattedValue\"",
-                )
-                .send()
-                .await
-                .map_err(|e| format!("Request failed: {e}"))?;
+        Ok(entities)
+    }
 
-            let status = resp.status();
+    /// Retrieve multiple records by FetchXML, handing each page to `on_page` as soon as it arrives
+    /// instead of collecting every page in memory. `on_page` receives `(page_number, entities)`;
+    /// returning an error stops paging. Returns the total number of records retrieved.
+    pub async fn retrieve_multiple_fetchxml_for_each_page<F>(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        page_size: Option<i32>,
+        on_page: F,
+    ) -> Result<usize, std::string::String>
+    where
+        F: AsyncFnMut(usize, Vec<Entity>) -> Result<(), std::string::String>,
+    {
+        self.retrieve_multiple_fetchxml_for_each_page_with_options(
+            entity,
+            fetchxml,
+            page_size,
+            &RequestOptions::default(),
+            on_page,
+        )
+        .await
+    }
 
-            if !status.is_success() {
-                let body = resp.text().await.unwrap_or_default();
-                return Err(format!("Dataverse API error ({}): {}", status, body));
-            }
+    /// `retrieve_multiple_fetchxml_for_each_page` with `options`: its client-side paging options,
+    /// such as `RequestOptions::stable_order`, and its headers, sent with every page.
+    pub async fn retrieve_multiple_fetchxml_for_each_page_with_options<F>(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        page_size: Option<i32>,
+        options: &RequestOptions,
+        on_page: F,
+    ) -> Result<usize, std::string::String>
+    where
+        F: AsyncFnMut(usize, Vec<Entity>) -> Result<(), std::string::String>,
+    {
+        let attribute_map = self.entity_attribute_map(entity).await?;
+        self.fetchxml_for_each_page(
+            entity,
+            fetchxml,
+            page_size,
+            options,
+            &attribute_map,
+            on_page,
+        )
+        .await
+    }
 
-            let json: Value = resp
-                .json()
-                .await
-                .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+    /// Retrieve every page of a FetchXML query with values converted by their column's metadata
+    /// type, also for the columns of linked tables and aliased attributes, which
+    /// `retrieve_multiple_fetchxml_paging` leaves as JSON types. For example, an `Integer` column
+    /// is an `Int` even when Dataverse serializes it as `10.0`, and a `DateTime` column read
+    /// through a link-entity is a `DateTime` rather than a string. Table metadata comes from the
+    /// client's cache, loaded once per table.
+    pub async fn retrieve_multiple_typed(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+    ) -> Result<Vec<Entity>, String> {
+        let columns = fetchxml_result_columns(fetchxml)?;
+        let attribute_map = self.result_attribute_map(entity, &columns).await?;
+
+        let mut entities = Vec::new();
+        self.fetchxml_for_each_page(
+            entity,
+            fetchxml,
+            None,
+            &RequestOptions::default(),
+            &attribute_map,
+            async |_, page_entities| {
+                entities.extend(page_entities);
+                Ok(())
+            },
+        )
+        .await?;
+        Ok(entities)
+    }
 
-            let mut page_entities = parse_entities_from_response(
+    /// Run an aggregate or distinct FetchXML query and return one `AggregateRow` per group or
+    /// distinct combination. Each value is stored under its alias, or for a distinct query
+    /// without aliases under its column name, and typed by the column's metadata: a `sum` of a
+    /// money column is `Value::Money` and a grouped lookup is `Value::EntityReference`. Counts,
+    /// averages, and `dategrouping` parts keep their JSON number type. Grouping keys and
+    /// aggregated values are kept apart, in query order.
+    pub async fn retrieve_aggregate(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+    ) -> Result<Vec<AggregateRow>, String> {
+        let aggregate = fetch_tag_attr_value(fetchxml, "aggregate")?.as_deref() == Some("true");
+        let distinct = fetch_tag_attr_value(fetchxml, "distinct")?.as_deref() == Some("true");
+        if !aggregate && !distinct {
+            return Err(
+                "retrieve_aggregate needs a FetchXML query with aggregate=\"true\" or distinct=\"true\""
+                    .to_string(),
+            );
+        }
+        self.validate_fetchxml_if_enabled(fetchxml).await?;
+        let columns = fetchxml_result_columns(fetchxml)?;
+        let attribute_map = self.result_attribute_map(entity, &columns).await?;
+        let converter = self
+            .value_converter
+            .read()
+            .map_err(|_| "Value converter lock poisoned".to_string())?
+            .clone();
+        let paged = !fetch_tag_has_attr(fetchxml, "top")?;
+
+        let mut rows = Vec::new();
+        let mut page = 1;
+        let mut paging_cookie: Option<String> = None;
+        loop {
+            let request = if paged {
+                apply_paging(
+                    &ensure_aggregate_page_size(fetchxml, AGGREGATE_PAGE_SIZE)?,
+                    page,
+                    paging_cookie.as_deref(),
+                )?
+            } else {
+                fetchxml.to_string()
+            };
+            let json = self
+                .with_page_retry(async || self.fetch_fetchxml_json(entity, &request).await)
+                .await?;
+            let entities = parse_aggregate_rows_from_response(
                 &json,
                 entity,
-                primary_id_attribute.as_deref(),
                 Some(&attribute_map),
+                converter.as_deref(),
             )?;
-            let start_index = entities.len();
-            for (offset, entity) in page_entities.iter_mut().enumerate() {
-                let row_number = (start_index + offset + 1) as i64;
-                entity
-                    .attributes
-                    .insert(ROW_NUMBER_ATTRIBUTE.to_string(), Int(row_number));
-            }
-            entities.extend(page_entities);
-            on_progress(page as usize, entities.len());
-
-            let more_records = parse_more_records(&json);
-            if !more_records {
+            let records = json
+                .get("value")
+                .and_then(|value| value.as_array())
+                .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+            rows.extend(aggregate_rows(records, entities, &columns.attributes, aggregate));
+            record_page("fetchxml");
+
+            if !paged || !parse_more_records(&json) {
                 break;
             }
-
             paging_cookie = extract_paging_cookie(&json);
             page += 1;
         }
+        Ok(rows)
+    }
 
-        Ok(entities)
+    /// Metadata of every column a FetchXML result row can hold: the root table's columns, the
+    /// columns of linked tables as `prefix.column`, and aliased attributes whose value keeps the
+    /// column's type.
+    async fn result_attribute_map(
+        &self,
+        entity: &str,
+        columns: &ResultColumns,
+    ) -> Result<HashMap<String, EntityAttribute>, String> {
+        let mut attribute_map = self.entity_attribute_map(entity).await?;
+        let aliased = columns
+            .attributes
+            .iter()
+            .filter(|attribute| attribute.aliased && attribute.keeps_column_type())
+            .collect::<Vec<_>>();
+        let mut linked_maps = HashMap::new();
+        for table in columns
+            .links
+            .iter()
+            .map(|(_, table)| table)
+            .chain(aliased.iter().map(|attribute| &attribute.table))
+        {
+            if !linked_maps.contains_key(table) {
+                linked_maps.insert(table.clone(), self.entity_attribute_map(table).await?);
+            }
+        }
+        for (prefix, table) in &columns.links {
+            for (name, attribute) in &linked_maps[table] {
+                attribute_map.insert(format!("{prefix}.{name}"), attribute.clone());
+            }
+        }
+        for attribute in aliased {
+            if let Some(metadata) = linked_maps[&attribute.table].get(&attribute.column) {
+                attribute_map.insert(attribute.key.clone(), metadata.clone());
+            }
+        }
+        Ok(attribute_map)
     }
 
-    /// Count records for a FetchXML query without retrieving all data.
-    pub async fn retrieve_multiple_fetchxml_count(
+    /// The `(prefix, table)` of each linked table of a FetchXML query, with the primary ID column
+    /// of each of those tables.
+    async fn linked_tables(
+        &self,
+        fetchxml: &str,
+    ) -> Result<(Vec<(String, String)>, HashMap<String, String>), String> {
+        let links = fetchxml_result_columns(fetchxml)?.links;
+        let mut primary_id_attributes = HashMap::new();
+        for (_, table) in &links {
+            if !primary_id_attributes.contains_key(table)
+                && let Some(primary_id) = self.resolve_primary_id_attribute(table).await?
+            {
+                primary_id_attributes.insert(table.clone(), primary_id);
+            }
+        }
+        Ok((links, primary_id_attributes))
+    }
+
+    /// Page through a FetchXML query, parsing values with `attribute_map`.
+    async fn fetchxml_for_each_page<F>(
         &self,
         entity: &str,
         fetchxml: &str,
-    ) -> Result<usize, std::string::String> {
+        page_size: Option<i32>,
+        options: &RequestOptions,
+        attribute_map: &HashMap<String, EntityAttribute>,
+        mut on_page: F,
+    ) -> Result<usize, std::string::String>
+    where
+        F: AsyncFnMut(usize, Vec<Entity>) -> Result<(), std::string::String>,
+    {
+        let page_size = page_size.unwrap_or(DEFAULT_FETCHXML_PAGE_SIZE);
+        let primary_id_attribute = self.resolve_primary_id_attribute(entity).await?;
+        let fetchxml = self.apply_default_columns(entity, fetchxml).await?;
+        self.validate_fetchxml_if_enabled(&fetchxml).await?;
+        let fetchxml = fetchxml.as_str();
+        let linked_tables = if options.nest_linked_entities {
+            Some(self.linked_tables(fetchxml).await?)
+        } else {
+            None
+        };
+        let nest_linked = |entities: &mut [Entity]| {
+            if let Some((links, primary_id_attributes)) = &linked_tables {
+                for entity in entities {
+                    entity.nest_linked_attributes(links, primary_id_attributes);
+                }
+            }
+        };
         if fetch_tag_has_attr(fetchxml, "top")? {
-            let resp = self
-                .retrieve_multiple_fetchxml_single(entity, fetchxml, None, None)
+            let json = self
+                .fetch_fetchxml_json_with_headers(entity, fetchxml, &options.headers)
                 .await?;
-            return Ok(resp.len());
+            let mut entities = self.parse_entities(
+                &json,
+                entity,
+                primary_id_attribute.as_deref(),
+                Some(attribute_map),
+            )?;
+            nest_linked(&mut entities);
+            if options.row_numbers {
+                for (index, entity) in entities.iter_mut().enumerate() {
+                    entity.row_number = Some(index as i64 + 1);
+                }
+            }
+            let total = entities.len();
+            on_page(1, entities).await?;
+            return Ok(total);
+        }
+
+        // Very long `in` lists are run as several queries and merged when splitting is on.
+        let split_threshold = self.in_condition_split_threshold.load(Ordering::Relaxed);
+        let queries = split_in_conditions(fetchxml, split_threshold)?;
+        let split = queries.is_some();
+        let mut queries = queries.unwrap_or_else(|| vec![fetchxml.to_string()]);
+        if options.stable_order
+            && let Some(primary_id_attribute) = primary_id_attribute.as_deref()
+        {
+            for query in &mut queries {
+                *query = ensure_primary_key_order(query, primary_id_attribute)?;
+            }
+        }
+        let mut seen_ids = HashSet::new();
+        let mut page_number = 0usize;
+        let mut total = 0usize;
+
+        for fetchxml in &queries {
+            let mut page = 1;
+            let mut paging_cookie: Option<std::string::String> = None;
+
+            loop {
+                let fetchxml = ensure_fetch_page_size(fetchxml, page_size)?;
+                let fetch_with_paging = apply_paging(
+                    &ensure_aggregate_page_size(&fetchxml, AGGREGATE_PAGE_SIZE)?,
+                    page,
+                    paging_cookie.as_deref(),
+                )?;
+
+                if self.log_level().includes_debug() {
+                    debug!("Fetch page: {}", page);
+                }
+
+                // A retry resends the same page and paging cookie, so earlier pages are kept.
+                let json = self
+                    .with_page_retry(async || {
+                        self.fetch_fetchxml_json_with_headers(
+                            entity,
+                            &fetch_with_paging,
+                            &options.headers,
+                        )
+                        .await
+                    })
+                    .await?;
+
+                let mut page_entities = self.parse_entities(
+                    &json,
+                    entity,
+                    primary_id_attribute.as_deref(),
+                    Some(attribute_map),
+                )?;
+                // A row can match more than one part when the split condition sits in an `or`
+                // filter, so merged results keep the first copy of each row. Unordered paging can
+                // also repeat a row on a later page.
+                if split || options.deduplicate {
+                    page_entities.retain(|entity| entity.id.is_nil() || seen_ids.insert(entity.id));
+                }
+                nest_linked(&mut page_entities);
+                if options.row_numbers {
+                    for (offset, entity) in page_entities.iter_mut().enumerate() {
+                        entity.row_number = Some((total + offset + 1) as i64);
+                    }
+                }
+                total += page_entities.len();
+                page_number += 1;
+                record_page("fetchxml");
+                on_page(page_number, page_entities).await?;
+
+                let more_records = parse_more_records(&json);
+                if !more_records {
+                    break;
+                }
+
+                paging_cookie = extract_paging_cookie(&json);
+                page += 1;
+            }
+        }
+
+        Ok(total)
+    }
+
+    /// Count records for a FetchXML query without retrieving all data. Fails when the count
+    /// would only be a lower bound, such as when the aggregate record limit is hit; use
+    /// `retrieve_multiple_fetchxml_count_detailed` to get the partial count instead.
+    pub async fn retrieve_multiple_fetchxml_count(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+    ) -> Result<usize, std::string::String> {
+        self.retrieve_multiple_fetchxml_count_detailed(entity, fetchxml, None)
+            .await?
+            .exact()
+    }
+
+    /// Count records for a FetchXML query and report whether a Dataverse or caller limit was hit.
+    /// When `page_ceiling` is set, paging stops after that many pages and the count is flagged as
+    /// a lower bound if Dataverse still reported more records.
+    pub async fn retrieve_multiple_fetchxml_count_detailed(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        page_ceiling: Option<usize>,
+    ) -> Result<CountResult, std::string::String> {
+        self.validate_fetchxml_if_enabled(fetchxml).await?;
+        if let Some(top) = fetch_tag_attr_value(fetchxml, "top")? {
+            let json = match self.fetch_fetchxml_json(entity, fetchxml).await {
+                Ok(json) => json,
+                Err(error) if is_aggregate_limit_error(&error) => {
+                    return Ok(CountResult {
+                        count: 0,
+                        pages: 1,
+                        limit: Some(CountLimit::AggregateRecordLimit),
+                    });
+                }
+                Err(error) => return Err(error),
+            };
+            let count = parse_record_count_from_response(&json)?;
+            let top_reached = top.trim().parse::<usize>().is_ok_and(|top| count >= top);
+            return Ok(CountResult {
+                count,
+                pages: 1,
+                limit: (top_reached || parse_more_records(&json)).then_some(CountLimit::TopCap),
+            });
         }
 
         let mut page = 1;
         let mut paging_cookie: Option<std::string::String> = None;
-        let mut total = 0usize;
+        let mut result = CountResult::default();
 
         loop {
             let fetch_with_paging = apply_paging(
@@ -312,57 +1139,370 @@ impl ServiceClient {
                 paging_cookie.as_deref(),
             )?;
 
-            if self.log_level.includes_debug() {
+            if self.log_level().includes_debug() {
                 debug!("Fetch page: {}", page);
-                debug!("FetchXML: {}", fetch_with_paging);
             }
 
-            let mut url = format!("{}/api/data/v9.2/{}", self.base_url, entity);
-            url.push_str("?fetchXml=");
-            url.push_str(&urlencoding::encode(&fetch_with_paging));
+            // Aggregate queries that exceed Dataverse's record limit fail outright rather than
+            // returning a partial page, so the rows counted so far become the lower bound.
+            let json = match self
+                .with_page_retry(async || {
+                    self.fetch_fetchxml_json(entity, &fetch_with_paging).await
+                })
+                .await
+            {
+                Ok(json) => json,
+                Err(error) if is_aggregate_limit_error(&error) => {
+                    result.limit = Some(CountLimit::AggregateRecordLimit);
+                    break;
+                }
+                Err(error) => return Err(error),
+            };
+
+            result.count += parse_record_count_from_response(&json)?;
+            result.pages = page as usize;
+
+            let more_records = parse_more_records(&json);
+            if !more_records {
+                break;
+            }
 
-            if self.log_level.includes_debug() {
-                debug!("Url: {:?}", url);
+            if page_ceiling.is_some_and(|ceiling| result.pages >= ceiling) {
+                result.limit = Some(CountLimit::PageCeiling);
+                break;
             }
 
-            let access_token = self.get_access_token().await?;
-            let resp = self
-                .client
-                .get(&url)
-                .bearer_auth(&access_token)
-                .header("Accept", "application/json")
-                .header(
-                    "Prefer",
-                    "odata.include-annotations=\"Microsoft.Dynamics.CRM.fetchxmlpagingcookie,Microsoft.Dynamics.CRM.morerecords,Microsoft.Dynamics.CRM.lookuplogicalname,OData.Community.Display.V1.FormattedValue\"",
-                )
-                .send()
-                .await
-                .map_err(|e| format!("Request failed: {e}"))?;
+            paging_cookie = extract_paging_cookie(&json);
+            page += 1;
+        }
 
-            let status = resp.status();
+        Ok(result)
+    }
 
-            if !status.is_success() {
-                let body = resp.text().await.unwrap_or_default();
-                return Err(format!("Dataverse API error ({}): {}", status, body));
+    /// Retrieve rows whose `versionnumber` is greater than `since`, ordered by version.
+    /// Pass `None` for an initial sync, then persist `max_version` as the next checkpoint. This is a
+    /// lightweight alternative to change tracking for tables where it is not enabled; it reports
+    /// new and updated rows but not deletions.
+    pub async fn retrieve_changes_since_version(
+        &self,
+        entity: &str,
+        columns: &[&str],
+        since: Option<i64>,
+    ) -> Result<VersionSyncResult, String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        let fetchxml = build_version_sync_fetchxml(&logical_name, columns, since);
+        let entities = self
+            .retrieve_multiple_fetchxml_paging(entity, &fetchxml)
+            .await?;
+
+        Ok(VersionSyncResult {
+            max_version: max_version(&entities, since),
+            entities,
+        })
+    }
+
+    /// Start change tracking for a table: read every row matching an OData query, such as
+    /// `$select=name`, and return the delta link for later calls to `retrieve_changes_since`.
+    /// The table must have change tracking enabled.
+    pub async fn retrieve_changes(
+        &self,
+        entity: &str,
+        query: &str,
+    ) -> Result<ChangeTrackingResult, String> {
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &odata_query_path(entity, query),
+        );
+        self.read_changes(entity, url).await
+    }
+
+    /// Read rows created, updated, or deleted since a delta link returned by `retrieve_changes`
+    /// or an earlier call. Deleted rows are reported as `ChangeEvent::Deleted`.
+    pub async fn retrieve_changes_since(
+        &self,
+        entity: &str,
+        delta_link: &str,
+    ) -> Result<ChangeTrackingResult, String> {
+        validate_next_link(&self.base_url, delta_link)?;
+        self.read_changes(entity, delta_link.to_string()).await
+    }
+
+    async fn read_changes(
+        &self,
+        entity: &str,
+        mut url: String,
+    ) -> Result<ChangeTrackingResult, String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        self.ensure_table_feature(&logical_name, TableFeature::ChangeTracking)
+            .await?;
+        let options = RequestOptions {
+            track_changes: true,
+            ..RequestOptions::default()
+        };
+        let mut result = ChangeTrackingResult::default();
+
+        loop {
+            let json = self
+                .with_page_retry(async || self.get_list_json(&url, &options).await)
+                .await?;
+            record_page("changes");
+            let rows = self
+                .parse_entity_rows(entity, &without_deleted_rows(&json))
+                .await?;
+            result
+                .changes
+                .extend(change_events(&json, rows, &logical_name));
+
+            match parse_next_link(&json) {
+                Some(next_link) => {
+                    validate_next_link(&self.base_url, &next_link)?;
+                    url = next_link;
+                }
+                None => {
+                    result.delta_link = parse_delta_link(&json);
+                    return Ok(result);
+                }
             }
+        }
+    }
 
-            let json: Value = resp
-                .json()
-                .await
-                .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+    /// Retrieve one page of rows with an OData query, such as
+    /// `$select=name&$filter=statecode eq 0`. Use `follow_next_link_entities` with
+    /// `ListResponse::next_link` to read later pages.
+    pub async fn retrieve_multiple_odata(
+        &self,
+        entity: &str,
+        query: &str,
+    ) -> Result<ListResponse<Entity>, String> {
+        self.retrieve_multiple_odata_with_options(entity, query, &RequestOptions::default())
+            .await
+    }
+
+    /// Retrieve one page of rows with an OData query and caller-chosen `Prefer` preferences,
+    /// such as `odata.maxpagesize` or a narrower annotation list. Pass the same options to
+    /// `follow_next_link_entities_with_options` for later pages.
+    pub async fn retrieve_multiple_odata_with_options(
+        &self,
+        entity: &str,
+        query: &str,
+        options: &RequestOptions,
+    ) -> Result<ListResponse<Entity>, String> {
+        let query = self.apply_default_select(entity, query).await?;
+        if self.validate_queries.load(Ordering::Relaxed) {
+            self.validate_odata_query(entity, &query).await?;
+        }
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &odata_query_path(entity, &query),
+        );
+        self.retrieve_entity_list_page(entity, &url, options).await
+    }
 
-            total += parse_record_count_from_response(&json)?;
+    /// Retrieve rows with an OData query, adding `$count=true` unless the query sets `$count`, so
+    /// the page carries the total number of matching rows in `ListResponse::count`. Dataverse
+    /// counts at most 5,000 rows; see `ListResponse::count_limit_exceeded`.
+    pub async fn retrieve_multiple_odata_with_count(
+        &self,
+        entity: &str,
+        query: &str,
+    ) -> Result<ListResponse<Entity>, String> {
+        self.retrieve_multiple_odata(entity, &with_inline_count(query)).await
+    }
 
-            let more_records = parse_more_records(&json);
-            if !more_records {
+    /// Run an OData `$apply` aggregation, such as `ApplyQuery::build()` or
+    /// `groupby((industrycode),aggregate(revenue with sum as total))`, and return one row per
+    /// group. Aggregate rows have no primary id, so each `Entity::id` is nil; grouped columns keep
+    /// their types and aggregated values are stored under their aliases. Lookup columns group by
+    /// their `_name_value` property.
+    pub async fn retrieve_aggregate_odata(
+        &self,
+        entity: &str,
+        apply: &str,
+    ) -> Result<Vec<Entity>, String> {
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &odata_query_path(entity, &apply_query_options(apply)),
+        );
+        let json = self
+            .get_list_json(&url, &RequestOptions::default())
+            .await?;
+        let attribute_map = self.entity_attribute_map(entity).await?;
+        let converter = self
+            .value_converter
+            .read()
+            .map_err(|_| "Value converter lock poisoned".to_string())?
+            .clone();
+        let mut entities = parse_aggregate_rows_from_response(
+            &json,
+            entity,
+            Some(&attribute_map),
+            converter.as_deref(),
+        )?;
+        if self.merge_lookup_annotations.load(Ordering::Relaxed) {
+            entities
+                .iter_mut()
+                .for_each(Entity::merge_lookup_annotations);
+        }
+        if self.keep_raw_json.load(Ordering::Relaxed) {
+            attach_raw_rows(&json, &mut entities);
+        }
+        Ok(entities)
+    }
+
+    /// Retrieve the row identified by alternate key values, such as
+    /// `[("accountnumber", Value::String("ACC-001"))]`, instead of its GUID. String values are
+    /// quoted and escaped; numbers, booleans, GUIDs, and dates are sent bare, and date-times in
+    /// UTC with a `Z` suffix. A key with several columns needs every column. `columns` limits
+    /// the returned columns; an empty slice returns them all. A missing row fails with a `404`.
+    pub async fn retrieve_entity_by_alternate_key(
+        &self,
+        entity_set: &str,
+        key_pairs: &[(std::string::String, DataverseValue)],
+        columns: &[&str],
+    ) -> Result<Entity, String> {
+        let key_segment = format_key_segment(key_pairs)?;
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &select_path(&row_path(entity_set, key_segment), columns),
+        );
+        let json = self.get_list_json(&url, &RequestOptions::default()).await?;
+
+        self.parse_single_entity(entity_set, json).await
+    }
+
+    /// Find existing rows that the published duplicate detection rules for `entity_set` match
+    /// against `record_attrs`, using the `RetrieveDuplicates` function. The record need not exist,
+    /// so import tools can check a row before creating it. Returns every matching row, read in
+    /// pages of 250. Duplicate detection must be enabled for the environment and the table.
+    pub async fn retrieve_duplicates(
+        &self,
+        entity_set: &str,
+        record_attrs: &HashMap<std::string::String, Value>,
+    ) -> Result<Vec<Entity>, String> {
+        let logical_name = self.resolve_entity_logical_name(entity_set).await?;
+        let mut duplicates = Vec::new();
+        for page_number in 1.. {
+            let json = self
+                .get_json(&retrieve_duplicates_path(
+                    &logical_name,
+                    &logical_name,
+                    record_attrs,
+                    page_number,
+                ))
+                .await?;
+            let page = self.parse_entity_rows(entity_set, &json).await?;
+            let complete = page.len() < DUPLICATE_PAGE_SIZE as usize;
+            duplicates.extend(page);
+            if complete {
                 break;
             }
+        }
+        Ok(duplicates)
+    }
 
-            paging_cookie = extract_paging_cookie(&json);
-            page += 1;
+    /// Check whether the row `id` exists in `entity_set`, reading only its primary key. A missing
+    /// row returns `false` rather than an error; other failures, such as missing read privileges,
+    /// are still errors.
+    pub async fn entity_exists(&self, entity_set: &str, id: impl IntoGuid) -> Result<bool, String> {
+        let id = Uuid::from(id.into_guid()?);
+        let primary_id = self
+            .resolve_primary_id_attribute(entity_set)
+            .await?
+            .ok_or_else(|| format!("No primary id attribute found for '{entity_set}'"))?;
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &select_path(&row_path(entity_set, id.as_hyphenated()), &[&primary_id]),
+        );
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .get(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+        let resp = self.send(request).await?;
+
+        let status = resp.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(false);
         }
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+        Ok(true)
+    }
 
-        Ok(total)
+    /// Retrieve the page of rows at an `@odata.nextLink` URL returned for `entity`. The link must
+    /// point at the connected environment, so the access token is never sent elsewhere.
+    pub async fn follow_next_link_entities(
+        &self,
+        entity: &str,
+        next_link: &str,
+    ) -> Result<ListResponse<Entity>, String> {
+        self.follow_next_link_entities_with_options(entity, next_link, &RequestOptions::default())
+            .await
+    }
+
+    /// Retrieve the page at an `@odata.nextLink` URL with `Prefer` preferences. Dataverse needs
+    /// `odata.maxpagesize` on every page request, not only the first.
+    pub async fn follow_next_link_entities_with_options(
+        &self,
+        entity: &str,
+        next_link: &str,
+        options: &RequestOptions,
+    ) -> Result<ListResponse<Entity>, String> {
+        validate_next_link(&self.base_url, next_link)?;
+        self.retrieve_entity_list_page(entity, next_link, options).await
+    }
+
+    /// Retrieve the page a `PageCursor` from `ListResponse::next_page` points at, repeating the
+    /// original query's page size and annotations.
+    pub async fn next_page(&self, cursor: &PageCursor) -> Result<ListResponse<Entity>, String> {
+        self.follow_next_link_entities_with_options(
+            &cursor.entity,
+            &cursor.next_link,
+            &cursor.options(),
+        )
+        .await
+    }
+
+    /// Retrieve the page at an `@odata.nextLink` URL for any collection the crate deserializes,
+    /// such as metadata types or caller-defined row structs. The link must point at the connected
+    /// environment, so the access token is never sent elsewhere.
+    pub async fn follow_next_link<T>(&self, next_link: &str) -> Result<ListResponse<T>, String>
+    where
+        T: DeserializeOwned,
+    {
+        validate_next_link(&self.base_url, next_link)?;
+        self.get_list_page(next_link).await
+    }
+
+    async fn retrieve_entity_list_page(
+        &self,
+        entity: &str,
+        url: &str,
+        options: &RequestOptions,
+    ) -> Result<ListResponse<Entity>, String> {
+        let json = self.get_list_json(url, options).await?;
+
+        let next_link = parse_next_link(&json);
+        Ok(ListResponse {
+            value: self.parse_entity_rows(entity, &json).await?,
+            next_page: next_link
+                .as_deref()
+                .map(|next_link| PageCursor::new(entity, next_link, options)),
+            next_link,
+            delta_link: parse_delta_link(&json),
+            count: parse_count(&json),
+            count_limit_exceeded: parse_total_record_count_limit_exceeded(&json),
+        })
     }
 
     /// Retrieve a single page of FetchXML results.
@@ -373,45 +1513,110 @@ impl ServiceClient {
         primary_id_attribute: Option<&str>,
         entity_attributes: Option<&HashMap<String, EntityAttribute>>,
     ) -> Result<Vec<Entity>, std::string::String> {
-        if self.log_level.includes_debug() {
-            debug!("FetchXML: {}", fetchxml);
+        let json = self.fetch_fetchxml_json(entity, fetchxml).await?;
+        self.parse_entities(&json, entity, primary_id_attribute, entity_attributes)
+    }
+
+    /// Parse a FetchXML response, applying the client's value converter and lookup normalization
+    /// option.
+    fn parse_entities(
+        &self,
+        json: &Value,
+        entity: &str,
+        primary_id_attribute: Option<&str>,
+        entity_attributes: Option<&HashMap<String, EntityAttribute>>,
+    ) -> Result<Vec<Entity>, std::string::String> {
+        let converter = self
+            .value_converter
+            .read()
+            .map_err(|_| "Value converter lock poisoned".to_string())?
+            .clone();
+        let mut entities = parse_entities_from_response(
+            json,
+            entity,
+            primary_id_attribute,
+            entity_attributes,
+            converter.as_deref(),
+        )?;
+        if self.merge_lookup_annotations.load(Ordering::Relaxed) {
+            entities
+                .iter_mut()
+                .for_each(Entity::merge_lookup_annotations);
+        }
+        if self.keep_raw_json.load(Ordering::Relaxed) {
+            attach_raw_rows(json, &mut entities);
+        }
+        Ok(entities)
+    }
+
+    /// Send a FetchXML request and return the raw Dataverse JSON payload.
+    async fn fetch_fetchxml_json(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+    ) -> Result<Value, std::string::String> {
+        self.fetch_fetchxml_json_with_headers(entity, fetchxml, &[]).await
+    }
+
+    /// `fetch_fetchxml_json` with extra `headers`, which replace same-named defaults.
+    async fn fetch_fetchxml_json_with_headers(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+        headers: &[(String, String)],
+    ) -> Result<Value, std::string::String> {
+        if self.log_level().includes_debug() {
+            debug!("FetchXML: {}", sanitize_fetchxml(fetchxml));
         }
 
-        let mut url = format!("{}/api/data/v9.2/{}", self.base_url, entity);
-        url.push_str("?fetchXml=");
-        url.push_str(&urlencoding::encode(fetchxml));
+        let path = fetchxml_query_path(entity, fetchxml);
+        let url = web_api_url(&self.base_url, &self.api_path, &path);
+
+        if self.log_level().includes_debug() {
+            debug!("Url: {:?}", sanitize_url(&url));
+        }
 
-        if self.log_level.includes_debug() {
-            debug!("Url: {:?}", url);
+        let threshold = self.batch_get_url_threshold.load(Ordering::Relaxed);
+        if threshold > 0 && url.len() > threshold {
+            if self.log_level().includes_debug() {
+                debug!("Url is {} characters; sending it in a $batch request", url.len());
+            }
+            let mut item = batch_get_item_with_prefer(
+                &path,
+                RequestOptions::default().prefer_header(&FETCHXML_ANNOTATIONS),
+            );
+            item.prepared_request.parameters.custom_headers = headers.to_vec();
+            let parts = self.send_batch(&[item], false).await?;
+            let part = parts
+                .first()
+                .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+            return batch_part_json(part);
         }
 
         let access_token = self.get_access_token().await?;
-        let resp = self
+        let request = self
             .client
             .get(&url)
             .bearer_auth(&access_token)
-            .header("Accept", "application/json")
-            .header(
-                "Prefer",
-                "odata.include-annotations=\"Microsoft.Dynamics.CRM.fetchxmlpagingcookie,Microsoft.Dynamics.CRM.morerecords,Microsoft.Dynamics.CRM.lookuplogicalname,OData.Community.Display.V1.FormattedValue\"",
-            )
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+            .header("Accept", "application/json");
+        let request = RequestOptions {
+            headers: headers.to_vec(),
+            ..RequestOptions::default()
+        }
+        .apply(request, &FETCHXML_ANNOTATIONS);
+        let resp = self.send(request).await?;
 
         let status = resp.status();
 
         if !status.is_success() {
+            let headers = resp.headers().clone();
             let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
+            return Err(api_error(status, &headers, &body));
         }
 
-        let json: Value = resp
-            .json()
+        resp.json()
             .await
-            .map_err(|e| format!("Failed to parse JSON: {e}"))?;
-
-        parse_entities_from_response(&json, entity, primary_id_attribute, entity_attributes)
+            .map_err(|e| format!("Failed to parse JSON: {e}"))
     }
 
     /// List all entity definitions.
@@ -425,38 +1630,133 @@ impl ServiceClient {
             }
         }
 
-        let url = format!(
-            "{}/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute",
-            self.base_url
-        );
+        let value = self
+            .list_metadata_collection::<EntityDefinition>(
+                "EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+            )
+            .await?;
+        let mut cache = self.entity_definitions_cache.lock().await;
+        *cache = Some(value.clone());
+
+        Ok(value)
+    }
+
+    /// Create a custom table with a string primary name column and return its definition. The
+    /// cached entity definitions are cleared so later lookups see the new table.
+    pub async fn create_table(&self, table: &NewTable) -> Result<EntityDefinition, String> {
+        self.post_table(table).await?;
+        self.created_table_definition(table).await
+    }
+
+    /// Send the definition of `table`, without reading back the created table.
+    pub(crate) async fn post_table(&self, table: &NewTable) -> Result<(), String> {
+        let metadata = table.to_entity_metadata()?;
+        let url = web_api_url(&self.base_url, &self.api_path, "EntityDefinitions");
 
         let access_token = self.get_access_token().await?;
-        let resp = self
+        let mut request = self
             .client
-            .get(&url)
+            .post(&url)
             .bearer_auth(&access_token)
             .header("Accept", "application/json")
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+            .header("Content-Type", "application/json")
+            .json(&metadata);
+        if let Some(solution) = &table.solution_unique_name {
+            request = request.header("MSCRM.SolutionUniqueName", solution);
+        }
 
+        let resp = self.send(request).await?;
         let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+        *self.entity_definitions_cache.lock().await = None;
+        Ok(())
+    }
+
+    /// Read the definition of `table` after it was created.
+    pub(crate) async fn created_table_definition(
+        &self,
+        table: &NewTable,
+    ) -> Result<EntityDefinition, String> {
+        let json = self
+            .get_json(&format!(
+                "{}?{CREATED_TABLE_SELECT}",
+                entity_definition_path(&table.logical_name())
+            ))
+            .await?;
+        serde_json::from_value(json).map_err(|e| format!("Failed to parse JSON: {e}"))
+    }
 
+    /// Delete the custom table `logical_name` with all its rows, and clear the cached metadata of
+    /// the table.
+    pub async fn delete_table(&self, logical_name: &str) -> Result<(), String> {
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &entity_definition_path(logical_name),
+        );
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .delete(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+
+        let resp = self.send(request).await?;
+        let status = resp.status();
         if !status.is_success() {
+            let headers = resp.headers().clone();
             let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
+            return Err(api_error(status, &headers, &body));
         }
 
-        let parsed: ODataList<EntityDefinition> = resp
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+        *self.entity_definitions_cache.lock().await = None;
+        self.entity_attributes_cache.lock().await.remove(logical_name);
+        self.column_names_cache.lock().await.remove(logical_name);
+        Ok(())
+    }
 
-        let value = parsed.value;
-        let mut cache = self.entity_definitions_cache.lock().await;
-        *cache = Some(value.clone());
+    /// Retrieve the table definitions matching `query` that changed since `client_version_stamp`,
+    /// with the IDs of deleted tables. Pass `None` for every matching definition.
+    pub async fn retrieve_metadata_changes(
+        &self,
+        query: &MetadataQuery,
+        client_version_stamp: Option<&str>,
+    ) -> Result<MetadataChanges, String> {
+        let json = self
+            .get_json(&metadata_changes_path(query, client_version_stamp))
+            .await?;
+        parse_metadata_changes(&json)
+    }
 
-        Ok(value)
+    /// Bring a local metadata cache up to date, fetching only the changes since its version
+    /// stamp. When Dataverse no longer has changes that old, the cache is cleared and reloaded.
+    pub async fn sync_metadata_cache(
+        &self,
+        cache: &mut MetadataCache,
+        query: &MetadataQuery,
+    ) -> Result<(), String> {
+        let changes = match self
+            .retrieve_metadata_changes(query, cache.version_stamp.as_deref())
+            .await
+        {
+            Ok(changes) => changes,
+            Err(error)
+                if cache.version_stamp.is_some()
+                    && ApiError::parse(&error)
+                        .is_some_and(|error| error.has_code(EXPIRED_VERSION_STAMP)) =>
+            {
+                *cache = MetadataCache::default();
+                self.retrieve_metadata_changes(query, None).await?
+            }
+            Err(error) => return Err(error),
+        };
+        cache.apply(&changes);
+        Ok(())
     }
 
     /// List entity attributes for a given logical name.
@@ -471,51 +1771,481 @@ impl ServiceClient {
             }
         }
 
-        let logical = logical_name.replace('\'', "''");
-        let url = format!(
-            "{}/api/data/v9.2/EntityDefinitions(LogicalName='{}')/Attributes?$select=LogicalName,SchemaName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForUpdate&$filter=IsValidODataAttribute eq true and IsValidForRead eq true",
-            self.base_url, logical
-        );
+        let mut value = self
+            .list_metadata_collection::<EntityAttribute>(&entity_attributes_path(logical_name))
+            .await?;
 
-        let access_token = self.get_access_token().await?;
-        let resp = self
-            .client
-            .get(&url)
-            .bearer_auth(&access_token)
-            .header("Accept", "application/json")
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
-
-        let status = resp.status();
-
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
+        // DateTimeBehavior only exists on the derived DateTimeAttributeMetadata type, so it needs
+        // a cast query and is merged onto the base attribute list.
+        if value
+            .iter()
+            .any(|attribute| attribute.attribute_type.as_deref() == Some("DateTime"))
+        {
+            let behaviors = self
+                .list_metadata_collection::<DateTimeAttributeBehavior>(&date_time_behaviors_path(
+                    logical_name,
+                ))
+                .await?;
+            merge_date_time_behaviors(&mut value, behaviors);
         }
 
-        let parsed: ODataList<EntityAttribute> = resp
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse JSON: {e}"))?;
-
-        let value = parsed.value;
         let mut cache = self.entity_attributes_cache.lock().await;
         cache.insert(normalize_entity_name(logical_name), value.clone());
 
         Ok(value)
     }
 
+    /// List entity attributes with `EntityAttribute::detail` filled for Boolean, choice,
+    /// multi-select choice, state, and status attributes: option labels and colors, the state of
+    /// each status reason, and the default status of each state.
+    pub async fn list_entity_attributes_with_details(
+        &self,
+        logical_name: &str,
+    ) -> Result<Vec<EntityAttribute>, String> {
+        let mut attributes = self.list_entity_attributes(logical_name).await?;
+        let details = self.attribute_details(logical_name).await?;
+        for attribute in &mut attributes {
+            attribute.detail = details.get(&attribute.logical_name).cloned();
+        }
+        Ok(attributes)
+    }
+
+    async fn attribute_details(
+        &self,
+        logical_name: &str,
+    ) -> Result<HashMap<String, AttributeDetail>, String> {
+        {
+            let cache = self.attribute_details_cache.lock().await;
+            if let Some(value) = cache.get(&normalize_entity_name(logical_name)) {
+                return Ok(value.clone());
+            }
+        }
+
+        // Each derived type is a separate cast query; the base attribute list cannot expand
+        // `OptionSet`.
+        let definition = entity_definition_path(logical_name);
+        let mut details = HashMap::new();
+        for metadata_type in ATTRIBUTE_DETAIL_METADATA_TYPES {
+            let expand = if *metadata_type == "BooleanAttributeMetadata" {
+                "OptionSet($select=TrueOption,FalseOption),GlobalOptionSet($select=TrueOption,FalseOption)"
+            } else {
+                "OptionSet($select=Options),GlobalOptionSet($select=Options)"
+            };
+            let json = self
+                .get_json(&format!(
+                    "{definition}/Attributes/Microsoft.Dynamics.CRM.{metadata_type}?$select=LogicalName&$expand={expand}"
+                ))
+                .await?;
+            details.extend(parse_attribute_details(metadata_type, &json)?);
+        }
+
+        let mut cache = self.attribute_details_cache.lock().await;
+        cache.insert(normalize_entity_name(logical_name), details.clone());
+
+        Ok(details)
+    }
+
+    /// List attribute metadata for several tables in one `$batch` round trip, keyed by the
+    /// normalized logical name. Tables can be named by logical, schema, or entity set name. Tables
+    /// already in the metadata cache are not requested again.
+    pub async fn get_metadata_bulk(
+        &self,
+        entities: &[&str],
+    ) -> Result<HashMap<String, Vec<EntityAttribute>>, String> {
+        let definitions = self.list_entity_definitions().await?;
+        let mut logical_names = Vec::new();
+        for entity in entities {
+            let target = normalize_entity_name(entity);
+            let definition = definitions
+                .iter()
+                .find(|definition| {
+                    normalize_entity_name(&definition.entity_set_name) == target
+                        || normalize_entity_name(&definition.logical_name) == target
+                        || normalize_entity_name(&definition.schema_name) == target
+                })
+                .ok_or_else(|| format!("Entity metadata not found for '{entity}'"))?;
+            logical_names.push(normalize_entity_name(&definition.logical_name));
+        }
+
+        let mut result = HashMap::new();
+        let mut pending = Vec::new();
+        {
+            let cache = self.entity_attributes_cache.lock().await;
+            for logical_name in logical_names {
+                if result.contains_key(&logical_name) || pending.contains(&logical_name) {
+                    continue;
+                }
+                match cache.get(&logical_name) {
+                    Some(value) => {
+                        result.insert(logical_name, value.clone());
+                    }
+                    None => pending.push(logical_name),
+                }
+            }
+        }
+
+        for chunk in pending.chunks(METADATA_BULK_CHUNK_SIZE) {
+            // Each table takes two parts: its attributes and the DateTimeBehavior cast query.
+            let items = chunk
+                .iter()
+                .flat_map(|logical_name| {
+                    [
+                        batch_get_item(&entity_attributes_path(logical_name)),
+                        batch_get_item(&date_time_behaviors_path(logical_name)),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            let parts = self.send_batch(&items, true).await?;
+            if parts.len() != items.len() {
+                return Err("Invalid response from Dataverse".to_string());
+            }
+
+            for (logical_name, parts) in chunk.iter().zip(parts.chunks(2)) {
+                let mut value = self
+                    .collect_batch_list::<EntityAttribute>(&parts[0])
+                    .await
+                    .map_err(|e| format!("Failed to load metadata for '{logical_name}': {e}"))?;
+                let behaviors = self
+                    .collect_batch_list::<DateTimeAttributeBehavior>(&parts[1])
+                    .await
+                    .map_err(|e| format!("Failed to load metadata for '{logical_name}': {e}"))?;
+                merge_date_time_behaviors(&mut value, behaviors);
+
+                self.entity_attributes_cache
+                    .lock()
+                    .await
+                    .insert(logical_name.clone(), value.clone());
+                result.insert(logical_name.clone(), value);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// List the navigation properties that write each lookup column on a table, as used in
+    /// `@odata.bind`.
+    pub async fn list_lookup_navigations(
+        &self,
+        logical_name: &str,
+    ) -> Result<Vec<LookupNavigation>, String> {
+        {
+            let cache = self.lookup_navigations_cache.lock().await;
+            if let Some(value) = cache.get(&normalize_entity_name(logical_name)) {
+                return Ok(value.clone());
+            }
+        }
+
+        let json = self
+            .get_json(&format!(
+                "{}/ManyToOneRelationships?$select=ReferencingAttribute,ReferencedEntity,ReferencingEntityNavigationPropertyName",
+                entity_definition_path(logical_name)
+            ))
+            .await?;
+        let navigations = parse_lookup_navigations(&json)?;
+
+        let mut cache = self.lookup_navigations_cache.lock().await;
+        cache.insert(normalize_entity_name(logical_name), navigations.clone());
+
+        Ok(navigations)
+    }
+
+    /// List the collection-valued navigation properties of a table, as used in `$expand`, with
+    /// the table each one leads to.
+    pub async fn list_collection_navigations(
+        &self,
+        logical_name: &str,
+    ) -> Result<Vec<CollectionNavigation>, String> {
+        {
+            let cache = self.collection_navigations_cache.lock().await;
+            if let Some(value) = cache.get(&normalize_entity_name(logical_name)) {
+                return Ok(value.clone());
+            }
+        }
+
+        let definition = entity_definition_path(logical_name);
+        let one_to_many = self
+            .get_json(&format!(
+                "{definition}/OneToManyRelationships?$select=ReferencedEntityNavigationPropertyName,ReferencingEntity"
+            ))
+            .await?;
+        let many_to_many = self
+            .get_json(&format!(
+                "{definition}/ManyToManyRelationships?$select=Entity1LogicalName,Entity1NavigationPropertyName,Entity2LogicalName,Entity2NavigationPropertyName"
+            ))
+            .await?;
+        let mut navigations = parse_one_to_many_navigations(&one_to_many)?;
+        navigations.extend(parse_many_to_many_navigations(&many_to_many, logical_name)?);
+
+        let mut cache = self.collection_navigations_cache.lock().await;
+        cache.insert(normalize_entity_name(logical_name), navigations.clone());
+
+        Ok(navigations)
+    }
+
+    /// Read what a table supports from its metadata: table type, change tracking, auditing, and
+    /// file columns. Accepts a logical name or entity set name. Results are cached per table.
+    /// Virtual tables support none of these features. `retrieve_changes`,
+    /// `retrieve_changes_since`, and `upload_file` check them before sending a request.
+    pub async fn retrieve_table_capabilities(
+        &self,
+        entity: &str,
+    ) -> Result<TableCapabilities, String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        {
+            let cache = self.table_capabilities_cache.lock().await;
+            if let Some(value) = cache.get(&logical_name) {
+                return Ok(value.clone());
+            }
+        }
+
+        let definition = self
+            .get_json(&format!(
+                "{}?$select=LogicalName,TableType,ChangeTrackingEnabled,IsAuditEnabled",
+                entity_definition_path(&logical_name)
+            ))
+            .await?;
+        let attributes = self.list_entity_attributes(&logical_name).await?;
+        let capabilities = parse_table_capabilities(&logical_name, &definition, &attributes);
+
+        let mut cache = self.table_capabilities_cache.lock().await;
+        cache.insert(logical_name, capabilities.clone());
+
+        Ok(capabilities)
+    }
+
+    /// Fail with an `Unsupported` error and guidance when `entity` does not support `feature`,
+    /// such as `TableFeature::Audit` before reading audit history. `Unsupported::is_unsupported`
+    /// recognizes the error.
+    pub async fn ensure_table_feature(
+        &self,
+        entity: &str,
+        feature: TableFeature,
+    ) -> Result<(), String> {
+        self.retrieve_table_capabilities(entity)
+            .await?
+            .ensure(feature)
+            .map_err(|unsupported| unsupported.to_string())
+    }
+
+    /// Convert an entity into a create or update payload. `Value::EntityReference` attributes
+    /// become `@odata.bind` entries on the lookup's navigation property, and `Value::Null` on a
+    /// lookup disassociates it.
+    ///
+    /// The navigation property comes from the table's many-to-one relationships, so custom and
+    /// polymorphic lookups bind correctly. `Value::EntityCollection` attributes become nested
+    /// rows, so `create_entity` performs a deep insert. Batch create, update, and upsert requests
+    /// use the same conversion.
+    pub async fn build_write_payload(
+        &self,
+        entity: &Entity,
+    ) -> Result<HashMap<String, serde_json::Value>, String> {
+        let entity_set_name_by_logical_name = self.entity_set_name_map().await?;
+        let mut lookup_navigations = HashMap::new();
+        self.load_lookup_navigations(entity, &mut lookup_navigations).await?;
+
+        Ok(
+            entity_to_write_map(entity, &entity_set_name_by_logical_name, &lookup_navigations)?
+                .into_iter()
+                .collect(),
+        )
+    }
+
+    /// Load lookup navigation metadata for every table in a write, including deep-insert rows,
+    /// that sets or clears a possible lookup column.
+    async fn load_lookup_navigations(
+        &self,
+        entity: &Entity,
+        lookup_navigations: &mut HashMap<String, Vec<LookupNavigation>>,
+    ) -> Result<(), String> {
+        let mut pending = vec![entity];
+        while let Some(entity) = pending.pop() {
+            let logical_name = normalize_entity_name(&entity.logical_name);
+            let mut writes_lookups = false;
+            for value in entity.attributes.values() {
+                match value {
+                    DataverseValue::EntityReference(_) | DataverseValue::Null => {
+                        writes_lookups = true;
+                    }
+                    DataverseValue::EntityCollection(rows) => pending.extend(rows),
+                    _ => {}
+                }
+            }
+
+            if writes_lookups && !lookup_navigations.contains_key(&logical_name) {
+                let navigations = self.list_lookup_navigations(&logical_name).await?;
+                lookup_navigations.insert(logical_name, navigations);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List the options of every choice, multi-select choice, state, and status column on a table,
+    /// keyed by column logical name.
+    pub async fn list_entity_option_sets(
+        &self,
+        logical_name: &str,
+    ) -> Result<OptionSetMap, std::string::String> {
+        {
+            let cache = self.option_sets_cache.lock().await;
+            if let Some(value) = cache.get(&normalize_entity_name(logical_name)) {
+                return Ok(value.clone());
+            }
+        }
+
+        let definition = entity_definition_path(logical_name);
+        let mut option_sets = OptionSetMap::new();
+        for metadata_type in OPTION_SET_METADATA_TYPES {
+            let json = self
+                .get_json(&format!(
+                    "{definition}/Attributes/Microsoft.Dynamics.CRM.{metadata_type}?$select=LogicalName&$expand=OptionSet($select=Options)"
+                ))
+                .await?;
+            option_sets.extend(parse_option_set_attributes(&json)?);
+        }
+
+        let mut cache = self.option_sets_cache.lock().await;
+        cache.insert(normalize_entity_name(logical_name), option_sets.clone());
+
+        Ok(option_sets)
+    }
+
+    /// Check choice values against option set metadata before create, update, and batch writes,
+    /// failing with the list of valid values instead of a server error. Options are loaded once
+    /// per table.
+    pub fn set_validate_option_sets(&self, enabled: bool) {
+        self.validate_option_sets.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Check the tables and columns of FetchXML and OData queries against cached metadata before
+    /// sending them, failing with the unknown name and the closest known one instead of a
+    /// server error. A paged FetchXML query is checked once, before its first page. See
+    /// `validate_fetchxml` and `validate_odata_query`.
+    pub fn set_validate_queries(&self, enabled: bool) {
+        self.validate_queries.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Check that every table and column a FetchXML query names exists, suggesting the closest
+    /// name for a typo, such as `attribute 'accontid' not found on 'account'; did you mean
+    /// 'accountid'?`.
+    ///
+    /// Checks `entity` and `link-entity` names, their `from` and `to` columns, and the columns of
+    /// `attribute`, `order`, and `condition` elements, resolving `entityname` aliases. Columns are
+    /// compared with every column of the table, since FetchXML can name columns OData cannot.
+    pub async fn validate_fetchxml(&self, fetchxml: &str) -> Result<(), String> {
+        let references = fetchxml_references(fetchxml)?;
+        let definitions = self.list_entity_definitions().await?;
+        for entity in &references.entities {
+            if !definitions
+                .iter()
+                .any(|definition| definition.logical_name.eq_ignore_ascii_case(entity))
+            {
+                return Err(unknown_entity_error(
+                    entity,
+                    definitions
+                        .iter()
+                        .map(|definition| definition.logical_name.as_str()),
+                ));
+            }
+        }
+
+        let mut columns_by_entity: Vec<(&str, Vec<&str>)> = Vec::new();
+        for (entity, column) in &references.columns {
+            match columns_by_entity
+                .iter_mut()
+                .find(|(name, _)| *name == entity.as_str())
+            {
+                Some((_, columns)) => columns.push(column),
+                None => columns_by_entity.push((entity, vec![column])),
+            }
+        }
+        for (entity, columns) in columns_by_entity {
+            let known = self.column_names(entity).await?;
+            check_columns(
+                entity,
+                &columns,
+                &known.iter().map(String::as_str).collect::<Vec<_>>(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Check that the entity set and the `$select` and `$orderby` columns of an OData query
+    /// exist. `$filter` and `$expand` are not checked; use `ServiceSchema::validate_expand` for
+    /// navigation properties. Lookup properties such as `_primarycontactid_value` are checked as
+    /// `primarycontactid`, and columns are compared with those `list_entity_attributes` returns.
+    pub async fn validate_odata_query(&self, entity: &str, query: &str) -> Result<(), String> {
+        let definitions = self.list_entity_definitions().await?;
+        let target = normalize_entity_name(entity);
+        let definition = definitions
+            .iter()
+            .find(|definition| {
+                normalize_entity_name(&definition.entity_set_name) == target
+                    || normalize_entity_name(&definition.logical_name) == target
+            })
+            .ok_or_else(|| {
+                unknown_entity_error(
+                    entity,
+                    definitions
+                        .iter()
+                        .map(|definition| definition.entity_set_name.as_str()),
+                )
+            })?;
+
+        let columns = odata_columns(query);
+        let attributes = self
+            .list_entity_attributes(&definition.logical_name)
+            .await?;
+        check_columns(
+            &definition.logical_name,
+            &columns.iter().map(String::as_str).collect::<Vec<_>>(),
+            &attributes
+                .iter()
+                .map(|attribute| attribute.logical_name.as_str())
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Logical names of every column of `logical_name`, including those the OData attribute list
+    /// leaves out.
+    async fn column_names(&self, logical_name: &str) -> Result<Vec<String>, String> {
+        let key = normalize_entity_name(logical_name);
+        if let Some(names) = self.column_names_cache.lock().await.get(&key) {
+            return Ok(names.clone());
+        }
+
+        let json = self
+            .get_json(&format!(
+                "{}/Attributes?$select=LogicalName",
+                entity_definition_path(logical_name)
+            ))
+            .await?;
+        let names = parse_column_names(&json)?;
+        self.column_names_cache
+            .lock()
+            .await
+            .insert(key, names.clone());
+        Ok(names)
+    }
+
+    /// Validate a FetchXML query once, before any page is sent, when query validation is on.
+    async fn validate_fetchxml_if_enabled(&self, fetchxml: &str) -> Result<(), String> {
+        if self.validate_queries.load(Ordering::Relaxed) {
+            self.validate_fetchxml(fetchxml).await?;
+        }
+        Ok(())
+    }
+
     /// List entity relationships for a given logical name.
     pub async fn list_entity_relationships(
         &self,
         logical_name: &str,
     ) -> Result<Vec<EntityRelationship>, std::string::String> {
-        let logical = logical_name.replace('\'', "''");
+        let definition = entity_definition_path(logical_name);
         let many_to_one = self
             .list_metadata_collection::<EntityRelationshipDirectional>(&format!(
-                "EntityDefinitions(LogicalName='{}')/ManyToOneRelationships?$select=SchemaName,ReferencedEntity,ReferencedAttribute,ReferencingEntity,ReferencingAttribute,IsCustomRelationship",
-                logical
+                "{}/ManyToOneRelationships?$select=SchemaName,ReferencedEntity,ReferencedAttribute,ReferencingEntity,ReferencingAttribute,IsCustomRelationship",
+                definition
             ))
             .await?
             .into_iter()
@@ -533,8 +2263,8 @@ impl ServiceClient {
 
         let one_to_many = self
             .list_metadata_collection::<EntityRelationshipDirectional>(&format!(
-                "EntityDefinitions(LogicalName='{}')/OneToManyRelationships?$select=SchemaName,ReferencedEntity,ReferencedAttribute,ReferencingEntity,ReferencingAttribute,IsCustomRelationship",
-                logical
+                "{}/OneToManyRelationships?$select=SchemaName,ReferencedEntity,ReferencedAttribute,ReferencingEntity,ReferencingAttribute,IsCustomRelationship",
+                definition
             ))
             .await?
             .into_iter()
@@ -552,8 +2282,8 @@ impl ServiceClient {
 
         let many_to_many = self
             .list_metadata_collection::<EntityRelationshipManyToMany>(&format!(
-                "EntityDefinitions(LogicalName='{}')/ManyToManyRelationships?$select=SchemaName,Entity1LogicalName,Entity2LogicalName,IntersectEntityName,IsCustomRelationship",
-                logical
+                "{}/ManyToManyRelationships?$select=SchemaName,Entity1LogicalName,Entity2LogicalName,IntersectEntityName,IsCustomRelationship",
+                definition
             ))
             .await?
             .into_iter()
@@ -572,554 +2302,4186 @@ impl ServiceClient {
         Ok(many_to_one.chain(one_to_many).chain(many_to_many).collect())
     }
 
-    /// Update a single entity record by ID.
-    pub async fn update_entity(
+    /// Retrieve identifying details for the connected organization.
+    /// Combines `RetrieveCurrentOrganization` with the `organization` row so multi-environment
+    /// tools can confirm they are pointed at the expected org. The ID, name, version, and geo
+    /// come from the former; the base language and currency from the latter.
+    pub async fn retrieve_organization_info(&self) -> Result<OrganizationInfo, String> {
+        let current_organization = self
+            .get_json(
+                "RetrieveCurrentOrganization(AccessType=Microsoft.Dynamics.CRM.EndpointAccessType'Default')",
+            )
+            .await?;
+        let organizations = self
+            .get_json("organizations?$select=organizationid,name,languagecode,_basecurrencyid_value")
+            .await?;
+        let organization_row = organizations
+            .get("value")
+            .and_then(|value| value.as_array())
+            .and_then(|rows| rows.first());
+
+        parse_organization_info(&current_organization, organization_row)
+    }
+
+    /// Retrieve the symbol and precision of every currency used by the money columns of `rows`,
+    /// in one query per 50 currencies, for formatting exported amounts as model-driven apps
+    /// show them.
+    pub async fn retrieve_currency_formats(
         &self,
-        entity_set: &str,
-        id: &str,
-        attributes: &HashMap<std::string::String, Value>,
-    ) -> Result<(), std::string::String> {
-        self.update_entity_with_options(entity_set, id, attributes, &RequestParameters::default())
-            .await
+        rows: &[Entity],
+    ) -> Result<CurrencyFormats, String> {
+        let mut formats = CurrencyFormats::default();
+        for query in currency_queries(&currency_ids(rows)) {
+            for format in self
+                .list_metadata_collection::<CurrencyFormat>(&query)
+                .await?
+            {
+                formats.by_id.insert(format.currency_id, format);
+            }
+        }
+        Ok(formats)
     }
 
-    /// Create a single entity record and return its ID when available.
-    pub async fn create_entity(
+    /// Retrieve every business unit and arrange them into a tree under the root business unit,
+    /// which has no parent. Children are ordered by name.
+    pub async fn get_business_unit_tree(&self) -> Result<BusinessUnitNode, String> {
+        let units = self
+            .list_metadata_collection::<BusinessUnit>(&format!(
+                "businessunits?$select={BUSINESS_UNIT_COLUMNS}"
+            ))
+            .await?;
+        build_business_unit_tree(units)
+    }
+
+    /// List the users of a business unit and every business unit below it. Disabled users are
+    /// included with `is_disabled` set. To avoid reading the business units again, pass a node of
+    /// an already loaded tree to `list_users_in_business_units`.
+    pub async fn list_business_unit_subtree_users(
         &self,
-        entity_set: &str,
-        attributes: &HashMap<std::string::String, Value>,
-    ) -> Result<Option<Uuid>, std::string::String> {
-        self.create_entity_with_options(entity_set, attributes, &RequestParameters::default())
-            .await
+        business_unit_id: impl IntoGuid,
+    ) -> Result<Vec<BusinessUnitUser>, String> {
+        let business_unit_id = Uuid::from(business_unit_id.into_guid()?);
+        let tree = self.get_business_unit_tree().await?;
+        let subtree = tree
+            .find(business_unit_id)
+            .ok_or_else(|| format!("Business unit '{business_unit_id}' not found"))?;
+        self.list_users_in_business_units(subtree).await
     }
 
-    /// Create a single entity record with Dataverse request parameters.
-    pub async fn create_entity_with_options(
+    /// List the users of every business unit in `subtree`, such as a node of a tree returned by
+    /// `get_business_unit_tree`.
+    pub async fn list_users_in_business_units(
         &self,
-        entity_set: &str,
-        attributes: &HashMap<std::string::String, Value>,
-        options: &RequestParameters,
-    ) -> Result<Option<Uuid>, std::string::String> {
-        let url = format!("{}/api/data/v9.2/{}", self.base_url, entity_set);
+        subtree: &BusinessUnitNode,
+    ) -> Result<Vec<BusinessUnitUser>, String> {
+        let mut users = Vec::new();
+        for query in subtree_user_queries(&subtree.business_unit_ids()) {
+            users.extend(
+                self.list_metadata_collection::<BusinessUnitUser>(&query)
+                    .await?,
+            );
+        }
+        Ok(users)
+    }
 
-        let access_token = self.get_access_token().await?;
-        let request = self
-            .client
-            .post(&url)
-            .bearer_auth(&access_token)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(attributes);
+    /// Create a business unit under `business_unit.parent_business_unit_id`, returning its ID.
+    pub async fn create_business_unit(
+        &self,
+        business_unit: &NewBusinessUnit,
+    ) -> Result<Uuid, String> {
+        self.create_entity("businessunits", &business_unit.to_attributes()?)
+            .await?
+            .ok_or_else(|| "Dataverse did not return the created business unit ID".to_string())
+    }
 
-        let resp = options
-            .apply(request)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+    /// Read a business unit by ID.
+    pub async fn retrieve_business_unit(
+        &self,
+        business_unit_id: impl IntoGuid,
+    ) -> Result<BusinessUnit, String> {
+        let business_unit_id = Uuid::from(business_unit_id.into_guid()?);
+        self.get_row(&row_query(
+            "businessunits",
+            business_unit_id,
+            BUSINESS_UNIT_COLUMNS,
+        ))
+        .await
+    }
 
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
-        }
+    /// Create a user in `user.business_unit_id`, returning its ID. Users synchronized from
+    /// Microsoft Entra ID are created by Dataverse itself; pass `azure_ad_object_id` to link a
+    /// row to an Entra ID user ahead of that.
+    pub async fn create_system_user(&self, user: &NewSystemUser) -> Result<Uuid, String> {
+        self.create_entity("systemusers", &user.to_attributes()?)
+            .await?
+            .ok_or_else(|| "Dataverse did not return the created user ID".to_string())
+    }
 
-        Ok(resp
-            .headers()
-            .get("OData-EntityId")
-            .or_else(|| resp.headers().get("Location"))
-            .and_then(|value| value.to_str().ok())
-            .and_then(parse_uuid_from_uri))
+    /// Read a user by ID.
+    pub async fn retrieve_system_user(
+        &self,
+        system_user_id: impl IntoGuid,
+    ) -> Result<SystemUser, String> {
+        let system_user_id = Uuid::from(system_user_id.into_guid()?);
+        self.get_row(&row_query(
+            "systemusers",
+            system_user_id,
+            SYSTEM_USER_COLUMNS,
+        ))
+        .await
     }
 
-    /// Update a single entity record by ID with Dataverse request parameters.
-    pub async fn update_entity_with_options(
+    /// Find the user with the Microsoft Entra ID object ID `object_id`, or `None` when no user
+    /// has it.
+    pub async fn find_user_by_azure_ad_object_id(
         &self,
-        entity_set: &str,
-        id: &str,
-        attributes: &HashMap<std::string::String, Value>,
-        options: &RequestParameters,
-    ) -> Result<(), std::string::String> {
-        let trimmed = id.trim_matches(|ch| ch == '{' || ch == '}');
-        let url = format!(
-            "{}/api/data/v9.2/{}({})",
-            self.base_url, entity_set, trimmed
-        );
+        object_id: Uuid,
+    ) -> Result<Option<SystemUser>, String> {
+        Ok(self
+            .list_metadata_collection::<SystemUser>(&user_by_object_id_query(object_id))
+            .await?
+            .into_iter()
+            .next())
+    }
 
-        let access_token = self.get_access_token().await?;
-        let request = self
-            .client
-            .patch(&url)
-            .bearer_auth(&access_token)
-            .header("Accept", "application/json")
-            .header("Content-Type", "application/json")
-            .json(&attributes);
+    /// Create a team, returning its ID.
+    pub async fn create_team(&self, team: &NewTeam) -> Result<Uuid, String> {
+        self.create_entity("teams", &team.to_attributes()?)
+            .await?
+            .ok_or_else(|| "Dataverse did not return the created team ID".to_string())
+    }
 
-        let resp = options
-            .apply(request)
-            .send()
+    /// Read a team by ID.
+    pub async fn retrieve_team(&self, team_id: impl IntoGuid) -> Result<Team, String> {
+        let team_id = Uuid::from(team_id.into_guid()?);
+        self.get_row(&row_query("teams", team_id, TEAM_COLUMNS))
             .await
-            .map_err(|e| format!("Request failed: {e}"))?;
-
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
-        }
-
-        Ok(())
     }
 
-    /// Delete a single entity record by ID.
-    pub async fn delete_entity(
+    /// Find the team named `name` in a business unit, or `None` when it has no such team.
+    pub async fn find_team_by_name(
         &self,
-        entity_set: &str,
-        id: &str,
-    ) -> Result<(), std::string::String> {
-        self.delete_entity_with_options(entity_set, id, &RequestParameters::default())
-            .await
+        name: &str,
+        business_unit_id: impl IntoGuid,
+    ) -> Result<Option<Team>, String> {
+        let business_unit_id = Uuid::from(business_unit_id.into_guid()?);
+        Ok(self
+            .list_metadata_collection::<Team>(&team_by_name_query(name, business_unit_id))
+            .await?
+            .into_iter()
+            .next())
     }
 
-    /// Delete a single entity record by ID with Dataverse request parameters.
-    pub async fn delete_entity_with_options(
+    /// Read one row at `path` into `T`.
+    async fn get_row<T>(&self, path: &str) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        serde_json::from_value(self.get_json(path).await?)
+            .map_err(|e| format!("Failed to parse JSON: {e}"))
+    }
+
+    /// Report record counts per table using `RetrieveTotalRecordCount`, with display names from
+    /// entity metadata. Pass table logical names or entity set names to limit the report, or
+    /// `None` for every table. Dataverse refreshes these counts periodically, so they can lag
+    /// recent changes by up to a day.
+    pub async fn retrieve_capacity_report(
         &self,
-        entity_set: &str,
-        id: &str,
-        options: &RequestParameters,
-    ) -> Result<(), std::string::String> {
-        let trimmed = id.trim_matches(|ch| ch == '{' || ch == '}');
-        let url = format!(
-            "{}/api/data/v9.2/{}({})",
-            self.base_url, entity_set, trimmed
-        );
+        tables: Option<&[&str]>,
+    ) -> Result<CapacityReport, String> {
+        let definitions = self.list_entity_definitions().await?;
+        let definitions = match tables {
+            Some(tables) => {
+                let wanted = tables
+                    .iter()
+                    .map(|table| normalize_entity_name(table))
+                    .collect::<Vec<_>>();
+                definitions
+                    .into_iter()
+                    .filter(|definition| {
+                        wanted.contains(&normalize_entity_name(&definition.logical_name))
+                            || wanted.contains(&normalize_entity_name(&definition.entity_set_name))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            None => definitions,
+        };
 
-        let access_token = self.get_access_token().await?;
-        let request = self
-            .client
-            .delete(&url)
-            .bearer_auth(&access_token)
-            .header("Accept", "application/json");
+        let mut counts = HashMap::new();
+        // Table names travel in the query string, so large environments are split across calls.
+        for chunk in definitions.chunks(RECORD_COUNT_CHUNK_SIZE) {
+            let names = serde_json::to_string(
+                &chunk
+                    .iter()
+                    .map(|definition| definition.logical_name.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .map_err(|e| format!("Failed to serialize table names: {e}"))?;
+            let json = self
+                .get_json(&format!(
+                    "RetrieveTotalRecordCount(EntityNames=@names)?@names={}",
+                    encode_query_value(&names)
+                ))
+                .await?;
+            counts.extend(parse_record_count_collection(&json)?);
+        }
+
+        Ok(build_capacity_report(&definitions, &counts))
+    }
 
-        let resp = options
-            .apply(request)
-            .send()
+    /// Check that the client can reach Dataverse and read metadata, for readiness probes and to
+    /// fail fast at startup on a wrong URL, bad credentials, or a user without access. Sends
+    /// `WhoAmI`, `RetrieveVersion`, and a single-table metadata read, always contacting Dataverse
+    /// even when the caller's identity is cached. A successful check leaves a token and the
+    /// execution context cached for the calls that follow. Errors name the check that failed.
+    pub async fn validate_connection(&self) -> Result<ConnectionHealth, String> {
+        let started = Instant::now();
+        let context = self
+            .get_json("WhoAmI")
             .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+            .and_then(|json| parse_who_am_i(&json))
+            .map_err(|e| format!("Connection check WhoAmI failed: {e}"))?;
+        let who_am_i_latency = started.elapsed();
+        *self.execution_context_cache.lock().await = Some(context.clone());
 
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
+        let version = self
+            .get_json("RetrieveVersion()")
+            .await
+            .and_then(|json| parse_version(&json))
+            .map_err(|e| format!("Connection check RetrieveVersion failed: {e}"))?;
+
+        let started = Instant::now();
+        self.get_json(&format!(
+            "{}?$select=LogicalName",
+            entity_definition_path("systemuser")
+        ))
+        .await
+        .map_err(|e| format!("Connection check metadata read failed: {e}"))?;
+
+        Ok(ConnectionHealth {
+            user_id: context.user_id,
+            organization_id: context.organization_id,
+            version,
+            who_am_i_latency,
+            metadata_latency: started.elapsed(),
+        })
+    }
+
+    /// Return the caller's execution context, issuing `WhoAmI` on first use. The user, business
+    /// unit, and organization IDs are cached for later calls.
+    pub async fn execution_context(&self) -> Result<ExecutionContext, String> {
+        {
+            let cache = self.execution_context_cache.lock().await;
+            if let Some(context) = &*cache {
+                return Ok(context.clone());
+            }
         }
 
-        Ok(())
+        let context = parse_who_am_i(&self.get_json("WhoAmI").await?)?;
+        let mut cache = self.execution_context_cache.lock().await;
+        Ok(cache.get_or_insert(context).clone())
     }
 
-    /// Execute multiple create, update, and delete requests using a single Dataverse batch call.
-    pub async fn execute_multiple(
-        &self,
-        request: &ExecuteMultipleRequest,
-    ) -> Result<ExecuteMultipleResponse, String> {
-        if request.requests.is_empty() {
-            return Ok(ExecuteMultipleResponse::default());
+    /// Return the caller's execution context with the user's directly assigned security roles
+    /// loaded, for policy checks with `ExecutionContext::has_role`.
+    pub async fn execution_context_with_roles(&self) -> Result<ExecutionContext, String> {
+        let mut context = self.execution_context().await?;
+        if context.roles.is_some() {
+            return Ok(context);
         }
 
-        if request.requests.len() > 1000 {
+        let roles = parse_security_roles(
+            &self
+                .get_json(&format!(
+                    "{}/systemuserroles_association?$select=roleid,name",
+                    row_path("systemusers", context.user_id.as_hyphenated())
+                ))
+                .await?,
+        )?;
+        context.roles = Some(roles);
+
+        let mut cache = self.execution_context_cache.lock().await;
+        *cache = Some(context.clone());
+        Ok(context)
+    }
+
+    /// Impersonate the user with this Azure AD object ID on all later requests, or stop
+    /// impersonating with `None`. The caller must hold the `prvActOnBehalfOfAnotherUser` privilege.
+    pub async fn set_caller_object_id(&self, caller_object_id: Option<Uuid>) {
+        *self.caller_object_id.lock().await = caller_object_id;
+        self.clear_execution_context().await;
+    }
+
+    /// Return the Azure AD object ID currently sent as `CallerObjectId`, if any.
+    pub async fn caller_object_id(&self) -> Option<Uuid> {
+        *self.caller_object_id.lock().await
+    }
+
+    /// Look up the `systemuser` whose `domainname` is `upn` and impersonate it on all later
+    /// requests. Returns the user's Azure AD object ID. The lookup itself runs as the signed-in
+    /// identity, even when another caller is already impersonated.
+    ///
+    /// The connected identity needs the `prvActOnBehalfOfAnotherUser` privilege. Changing the
+    /// caller clears the cached execution context.
+    pub async fn resolve_caller_by_upn(&self, upn: &str) -> Result<Uuid, String> {
+        let json = self
+            .get_json_as(
+                &format!(
+                    "systemusers?$select=systemuserid,azureactivedirectoryobjectid&$filter=domainname eq {}",
+                    encode_string_literal(upn)
+                ),
+                false,
+            )
+            .await?;
+        let caller_object_id = parse_caller_object_id(&json, upn)?;
+        self.set_caller_object_id(Some(caller_object_id)).await;
+        Ok(caller_object_id)
+    }
+
+    /// Discard the cached execution context so the next call re-issues `WhoAmI`.
+    pub async fn clear_execution_context(&self) {
+        *self.execution_context_cache.lock().await = None;
+    }
+
+    /// List the language codes (LCIDs) installed in the environment, such as 1033 for English.
+    pub async fn list_provisioned_languages(&self) -> Result<Vec<i32>, String> {
+        parse_provisioned_languages(&self.get_json(PROVISIONED_LANGUAGES_PATH).await?)
+    }
+
+    /// Read the calling user's UI language from their personal settings. Dataverse formats
+    /// choice labels and other formatted values in this language.
+    pub async fn get_user_language(&self) -> Result<i32, String> {
+        let user_id = self.execution_context().await?.user_id;
+        let json = self.get_json(&user_language_path(user_id)).await?;
+        json.get("uilanguageid")
+            .and_then(|value| value.as_i64())
+            .and_then(|value| i32::try_from(value).ok())
+            .ok_or_else(|| "User settings have no uilanguageid".to_string())
+    }
+
+    /// Save `lcid` as the calling user's UI language in their server-side personal settings, so
+    /// later formatted values come back in that language. `lcid` must be a provisioned language.
+    ///
+    /// The change is persistent and not scoped to the client: it writes `uilanguageid` on the
+    /// user's `usersettings` row, so it outlives the session and also applies to every other
+    /// client and to the user's Dataverse apps. With a shared or application user, it changes the
+    /// language for everyone using that identity. Read the current value with
+    /// `get_user_language` first and save it back when done, or use
+    /// `ServiceClientBuilder::label_language` for metadata labels, which changes nothing on the
+    /// server.
+    pub async fn save_user_ui_language(&self, lcid: i32) -> Result<(), String> {
+        if !self.list_provisioned_languages().await?.contains(&lcid) {
             return Err(format!(
-                "ExecuteMultipleRequest contains {} requests, exceeding the Dataverse batch limit of 1000",
-                request.requests.len()
+                "Language {lcid} is not provisioned in this environment"
             ));
         }
+        let user_id = self.execution_context().await?.user_id;
+        self.update_entity(
+            "usersettingscollection",
+            user_id,
+            &HashMap::from([("uilanguageid".to_string(), Value::from(lcid))]),
+        )
+        .await
+    }
 
-        let entity_set_name_by_logical_name = self.entity_set_name_map().await?;
-        let prepared_requests = self
-            .prepare_batch_requests(&request.requests, &entity_set_name_by_logical_name)?;
-        let boundary = format!("batch_{}", Uuid::new_v4().as_hyphenated());
-        let body = self.build_batch_body(&boundary, &prepared_requests);
-        let url = format!("{}/api/data/v9.2/$batch", self.base_url);
-        let access_token = self.get_access_token().await?;
+    /// Read the environment variable with `schema_name`, joining its definition with the value
+    /// set in this environment. `EnvironmentVariable::effective_value` returns the value, or the
+    /// definition's default when no value is set.
+    ///
+    /// Values are returned as stored text whatever the variable's type; for a `Secret` variable
+    /// that is the Azure Key Vault reference, not the secret. An unknown schema name fails with
+    /// `Environment variable '…' not found`.
+    pub async fn get_environment_variable(
+        &self,
+        schema_name: &str,
+    ) -> Result<EnvironmentVariable, String> {
+        let json = self
+            .get_json(&environment_variable_query(schema_name))
+            .await?;
+        parse_environment_variable(&json, schema_name)
+    }
 
-        let mut http_request = self
+    /// Set the value of the environment variable with `schema_name` in this environment, updating
+    /// its value row or creating one when only the default is set. Returns the variable with the
+    /// new value.
+    pub async fn set_environment_variable_value(
+        &self,
+        schema_name: &str,
+        value: &str,
+    ) -> Result<EnvironmentVariable, String> {
+        let mut variable = self.get_environment_variable(schema_name).await?;
+        let mut attributes =
+            HashMap::from([("value".to_string(), Value::String(value.to_string()))]);
+        match variable.value_id {
+            Some(value_id) => {
+                self.update_entity("environmentvariablevalues", value_id, &attributes)
+                    .await?;
+            }
+            None => {
+                attributes.insert(
+                    "EnvironmentVariableDefinitionId@odata.bind".to_string(),
+                    Value::String(bind_path(
+                        "environmentvariabledefinitions",
+                        variable.definition_id.as_hyphenated(),
+                    )),
+                );
+                variable.value_id = self
+                    .create_entity("environmentvariablevalues", &attributes)
+                    .await?;
+            }
+        }
+        variable.value = Some(value.to_string());
+        Ok(variable)
+    }
+
+    /// Update a single entity record by ID.
+    pub async fn update_entity(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        attributes: &HashMap<std::string::String, Value>,
+    ) -> Result<(), std::string::String> {
+        self.update_entity_with_options(entity_set, id, attributes, &RequestParameters::default())
+            .await
+    }
+
+    /// Create a single entity record and return its ID when available.
+    pub async fn create_entity(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<std::string::String, Value>,
+    ) -> Result<Option<Uuid>, std::string::String> {
+        self.create_entity_with_options(entity_set, attributes, &RequestParameters::default())
+            .await
+    }
+
+    /// Create a single entity record with Dataverse request parameters.
+    pub async fn create_entity_with_options(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<std::string::String, Value>,
+        options: &RequestParameters,
+    ) -> Result<Option<Uuid>, std::string::String> {
+        self.create_entity_with_meta(entity_set, attributes, options)
+            .await
+            .map(WithMeta::into_inner)
+    }
+
+    /// `create_entity_with_options`, also returning the status and headers of the response,
+    /// such as the `x-ms-service-request-id` to log for the write.
+    pub async fn create_entity_with_meta(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<std::string::String, Value>,
+        options: &RequestParameters,
+    ) -> Result<WithMeta<Option<Uuid>>, String> {
+        self.check_bypass(options)?;
+        self.validate_payload_options(entity_set, attributes).await?;
+        let url = web_api_url(&self.base_url, &self.api_path, entity_set);
+
+        let access_token = self.get_access_token().await?;
+        let request = self
             .client
             .post(&url)
             .bearer_auth(&access_token)
-            .header("OData-MaxVersion", "4.0")
-            .header("OData-Version", "4.0")
-            .header("If-None-Match", "null")
             .header("Accept", "application/json")
-            .header("Content-Type", format!("multipart/mixed; boundary={boundary}"))
-            .body(body);
-
-        if request.settings.continue_on_error {
-            http_request = http_request.header("Prefer", "odata.continue-on-error");
-        }
+            .header("Content-Type", "application/json")
+            .json(attributes);
 
-        let resp = http_request
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+        let resp = self.send(options.apply(request)).await?;
 
         let status = resp.status();
-        let content_type = resp
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        let id = resp
             .headers()
-            .get(CONTENT_TYPE)
+            .get("OData-EntityId")
+            .or_else(|| resp.headers().get("Location"))
             .and_then(|value| value.to_str().ok())
-            .map(|value| value.to_string());
-        let response_text = resp
-            .text()
-            .await
-            .map_err(|e| format!("Failed to read batch response: {e}"))?;
-
-        if !status.is_success() && !content_type.as_deref().unwrap_or_default().starts_with("multipart/mixed") {
-            return Err(format!(
-                "Dataverse API error ({}): {}",
-                status,
-                response_text
-            ));
-        }
+            .and_then(parse_uuid_from_uri);
+        Ok(WithMeta {
+            value: id,
+            meta: response_meta(&Method::POST, &url, &resp),
+        })
+    }
 
-        let parts = parse_batch_response_parts(content_type.as_deref(), &response_text)?;
-        self.map_batch_response(request, parts)
+    /// Create a row and return it as Dataverse stored it, including server-set columns, without a
+    /// second retrieve. `select` limits the returned columns; an empty slice returns them all.
+    /// Lookups and choice labels are parsed as they are for retrieval.
+    pub async fn create_entity_and_return(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<std::string::String, Value>,
+        select: &[&str],
+    ) -> Result<Entity, String> {
+        self.create_entity_and_return_with_options(
+            entity_set,
+            attributes,
+            select,
+            &RequestParameters::default(),
+        )
+        .await
     }
 
-    async fn get_access_token(&self) -> Result<String, String> {
-        let mut token = self.token.lock().await;
-        if !token.access_token.trim().is_empty() && !is_expiring_soon(token.expires_at) {
-            return Ok(token.access_token.clone());
-        }
-
-        // Refreshing while the mutex is held keeps parallel callers from racing into multiple token
-        // refreshes and then stomping each other's cache file updates.
-        let refreshed = match &self.auth {
-            AuthConfig::ClientCredentials { .. } => {
-                println!("Refreshing access token before request using client credentials...");
-                fetch_token_for_config(&self.auth).await?
-            }
-            AuthConfig::DeviceCode {
-                client_id,
-                dataverse_url,
-                tenant_id,
-                ..
-            } => {
-                println!("Refreshing access token before request using device code...");
-                let refresh_token = token.refresh_token.clone().ok_or(
-                    "Device code token cannot refresh without a refresh token".to_string(),
-                )?;
-                let scope = format!(
-                    "{}/user_impersonation offline_access openid profile",
-                    dataverse_url
-                );
-                let token: TokenExchange =
-                    refresh_device_code_token(client_id, tenant_id, &scope, &refresh_token).await?;
+    /// Create a row with `return=representation` and Dataverse request parameters.
+    pub async fn create_entity_and_return_with_options(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<std::string::String, Value>,
+        select: &[&str],
+        options: &RequestParameters,
+    ) -> Result<Entity, String> {
+        self.check_bypass(options)?;
+        self.validate_payload_options(entity_set, attributes).await?;
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &select_path(entity_set, select),
+        );
 
-                CachedToken {
-                    access_token: token.access_token,
-                    refresh_token: Some(token.refresh_token),
-                    expires_at: Some(token.expires_at),
-                }
-            }
-        };
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .post(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(attributes);
 
-        save_cached_token(&self.token_cache_path, &refreshed)?;
-        let access_token = refreshed.access_token.clone();
-        *token = refreshed;
-        Ok(access_token)
+        self.send_returning_representation(entity_set, options.apply(request)).await
     }
 
-    async fn list_metadata_collection<T>(&self, path: &str) -> Result<Vec<T>, String>
-    where
-        T: DeserializeOwned,
-    {
-        let url = format!("{}/api/data/v9.2/{}", self.base_url, path);
+    /// Update a row and return it after the update, without a second retrieve.
+    pub async fn update_entity_and_return(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        attributes: &HashMap<std::string::String, Value>,
+        select: &[&str],
+    ) -> Result<Entity, String> {
+        self.update_entity_and_return_with_options(
+            entity_set,
+            id,
+            attributes,
+            select,
+            &RequestParameters::default(),
+        )
+        .await
+    }
+
+    /// Update a row with `return=representation` and Dataverse request parameters.
+    pub async fn update_entity_and_return_with_options(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        attributes: &HashMap<std::string::String, Value>,
+        select: &[&str],
+        options: &RequestParameters,
+    ) -> Result<Entity, String> {
+        self.check_bypass(options)?;
+        let id = id.into_guid()?;
+        self.validate_payload_options(entity_set, attributes).await?;
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &select_path(&row_path(entity_set, id), select),
+        );
 
         let access_token = self.get_access_token().await?;
-        let resp = self
+        let request = self
             .client
-            .get(&url)
+            .patch(&url)
             .bearer_auth(&access_token)
             .header("Accept", "application/json")
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {e}"))?;
+            .header("Content-Type", "application/json")
+            .json(attributes);
 
-        let status = resp.status();
+        self.send_returning_representation(entity_set, options.apply(request)).await
+    }
+
+    /// Send a create or update that asks for `return=representation` and parse the echoed row.
+    async fn send_returning_representation(
+        &self,
+        entity_set: &str,
+        request: RequestBuilder,
+    ) -> Result<Entity, String> {
+        let request = RequestOptions {
+            return_representation: true,
+            ..RequestOptions::default()
+        }
+        .apply(request, &ENTITY_ANNOTATIONS);
+        let resp = self.send(request).await?;
 
+        let status = resp.status();
         if !status.is_success() {
+            let headers = resp.headers().clone();
             let body = resp.text().await.unwrap_or_default();
-            return Err(format!("Dataverse API error ({}): {}", status, body));
+            return Err(api_error(status, &headers, &body));
         }
 
-        let parsed: ODataList<T> = resp
+        let json: Value = resp
             .json()
             .await
             .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+        self.parse_single_entity(entity_set, json).await
+    }
+
+    /// Parse a single-row response body, such as a keyed retrieve or a returned representation.
+    async fn parse_single_entity(&self, entity_set: &str, json: Value) -> Result<Entity, String> {
+        let mut response = Map::new();
+        response.insert("value".to_string(), Value::Array(vec![json]));
 
-        Ok(parsed.value)
+        self.parse_entity_rows(entity_set, &Value::Object(response))
+            .await?
+            .pop()
+            .ok_or_else(|| "Invalid response from Dataverse".to_string())
     }
 
-    async fn resolve_primary_id_attribute(
+    /// Parse an OData row collection response, including rows of expanded collections.
+    async fn parse_entity_rows(&self, entity: &str, json: &Value) -> Result<Vec<Entity>, String> {
+        let mut entities = self.parse_rows_with_metadata(entity, json).await?;
+        self.attach_expanded_collections(entity, json, &mut entities)
+            .await?;
+        Ok(entities)
+    }
+
+    async fn parse_rows_with_metadata(
         &self,
-        entity_set: &str,
-    ) -> Result<Option<String>, String> {
-        let definitions = self.list_entity_definitions().await?;
-        let target = normalize_entity_name(entity_set);
+        entity: &str,
+        json: &Value,
+    ) -> Result<Vec<Entity>, String> {
+        let primary_id_attribute = self.resolve_primary_id_attribute(entity).await?;
+        let attribute_map = self.entity_attribute_map(entity).await?;
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
 
-        Ok(definitions
-            .into_iter()
-            .find(|definition| {
-                normalize_entity_name(&definition.entity_set_name) == target
-                    || normalize_entity_name(&definition.logical_name) == target
-                    || normalize_entity_name(&definition.schema_name) == target
-            })
-            .and_then(|definition| definition.primary_id_attribute))
+        let mut entities = self.parse_entities(
+            json,
+            entity,
+            primary_id_attribute.as_deref(),
+            Some(&attribute_map),
+        )?;
+        // Parsing guesses the logical name from the entity set name, which is wrong for tables
+        // whose set name is not a simple plural, so take it from metadata instead.
+        for row in &mut entities {
+            row.logical_name.clone_from(&logical_name);
+        }
+        Ok(entities)
     }
 
-    async fn resolve_entity_logical_name(&self, entity_name: &str) -> Result<String, String> {
-        let definitions = self.list_entity_definitions().await?;
-        let target = normalize_entity_name(entity_name);
+    /// Store each expanded collection in a response as a `Value::EntityCollection` attribute of
+    /// its row, parsed with the related table's metadata, and keep the `@odata.nextLink` of each
+    /// truncated one for `expand_remaining`. Records are matched to rows by their primary id.
+    async fn attach_expanded_collections(
+        &self,
+        entity: &str,
+        json: &Value,
+        entities: &mut [Entity],
+    ) -> Result<(), String> {
+        let properties = expanded_collection_properties(json);
+        if properties.is_empty() {
+            return Ok(());
+        }
 
-        definitions
-            .into_iter()
-            .find(|definition| {
-                normalize_entity_name(&definition.entity_set_name) == target
-                    || normalize_entity_name(&definition.logical_name) == target
-                    || normalize_entity_name(&definition.schema_name) == target
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        let primary_id_attribute = self
+            .resolve_primary_id_attribute(entity)
+            .await?
+            .unwrap_or_else(|| format!("{logical_name}id"));
+        let navigations = self.list_collection_navigations(&logical_name).await?;
+        let entity_set_name_by_logical_name = self.entity_set_name_map().await?;
+        let records = json
+            .get("value")
+            .and_then(|value| value.as_array())
+            .ok_or_else(|| "Invalid response from Dataverse".to_string())?;
+        let row_positions = entities
+            .iter()
+            .enumerate()
+            .map(|(position, entity)| (entity.id, position))
+            .collect::<HashMap<_, _>>();
+        let matched_records = records
+            .iter()
+            .filter_map(|record| {
+                let position = row_positions.get(&record_id(record, &primary_id_attribute)?)?;
+                Some((record, *position))
             })
-            .map(|definition| definition.logical_name)
-            .ok_or_else(|| format!("Entity metadata not found for '{}'", entity_name))
-    }
+            .collect::<Vec<_>>();
 
-    async fn entity_attribute_map(
+        for property in properties {
+            // Parsing already turned an activity's parties into its party list columns.
+            if property.ends_with(ACTIVITY_PARTIES_SUFFIX) {
+                continue;
+            }
+            for (record, position) in &matched_records {
+                if let Some(next_link) = expanded_next_link(record, &property) {
+                    entities[*position]
+                        .expanded_next_links
+                        .insert(property.clone(), next_link);
+                }
+            }
+            let Some(entity_set) =
+                find_collection_navigation(&navigations, &property).and_then(|navigation| {
+                    entity_set_name_by_logical_name.get(&navigation.related_entity)
+                })
+            else {
+                if self.log_level().includes(Level::Warn) {
+                    warn!("Collection navigation property not found in metadata: {property}");
+                }
+                continue;
+            };
+
+            for (record, position) in &matched_records {
+                if record.get(&property).is_none() {
+                    continue;
+                }
+                let rows = self
+                    .parse_rows_with_metadata(entity_set, &expanded_rows(record, &property))
+                    .await?;
+                entities[*position]
+                    .attributes
+                    .insert(property.clone(), DataverseValue::EntityCollection(rows));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read the rest of an expanded collection that Dataverse truncated, following the
+    /// `@odata.nextLink` kept with the row until every related row is loaded. The rows are
+    /// appended to the `Value::EntityCollection` attribute named `navigation_property`, skipping
+    /// rows already present, and the link is removed once the collection is complete. Does nothing
+    /// when the collection was not truncated.
+    pub async fn expand_remaining(
         &self,
-        entity_name: &str,
-    ) -> Result<HashMap<String, EntityAttribute>, String> {
-        let logical_name = self.resolve_entity_logical_name(entity_name).await?;
-        let attributes = self.list_entity_attributes(&logical_name).await?;
-        let mut map = HashMap::new();
-        for attribute in attributes {
-            map.insert(attribute.logical_name.to_ascii_lowercase(), attribute.clone());
-            map.entry(attribute.schema_name.to_ascii_lowercase())
-                .or_insert(attribute);
+        entity: &mut Entity,
+        navigation_property: &str,
+    ) -> Result<(), String> {
+        let Some(mut next_link) = entity.expanded_next_links.get(navigation_property).cloned()
+        else {
+            return Ok(());
+        };
+
+        let navigations = self
+            .list_collection_navigations(&entity.logical_name)
+            .await?;
+        let related_entity = find_collection_navigation(&navigations, navigation_property)
+            .map(|navigation| navigation.related_entity.clone())
+            .ok_or_else(|| {
+                format!(
+                    "Collection navigation property '{navigation_property}' not found on '{}'",
+                    entity.logical_name
+                )
+            })?;
+        let entity_set = self
+            .resolve_entity_definition(&related_entity)
+            .await?
+            .entity_set_name;
+
+        let mut rows = match entity.attributes.remove(navigation_property) {
+            Some(DataverseValue::EntityCollection(rows)) => rows,
+            _ => Vec::new(),
+        };
+        let mut seen = rows.iter().map(|row| row.id).collect::<HashSet<_>>();
+
+        loop {
+            validate_next_link(&self.base_url, &next_link)?;
+            let json = self
+                .get_list_json(&next_link, &RequestOptions::default())
+                .await?;
+            rows.extend(
+                self.parse_rows_with_metadata(&entity_set, &json)
+                    .await?
+                    .into_iter()
+                    .filter(|row| seen.insert(row.id)),
+            );
+
+            match parse_next_link(&json) {
+                Some(link) => next_link = link,
+                None => break,
+            }
         }
-        Ok(map)
+
+        entity.expanded_next_links.remove(navigation_property);
+        entity.attributes.insert(
+            navigation_property.to_string(),
+            DataverseValue::EntityCollection(rows),
+        );
+        Ok(())
+    }
+
+    /// Update a single entity record by ID with Dataverse request parameters.
+    pub async fn update_entity_with_options(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        attributes: &HashMap<std::string::String, Value>,
+        options: &RequestParameters,
+    ) -> Result<(), std::string::String> {
+        self.update_entity_with_meta(entity_set, id, attributes, options)
+            .await
+            .map(WithMeta::into_inner)
+    }
+
+    /// `update_entity_with_options`, also returning the status and headers of the response.
+    pub async fn update_entity_with_meta(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        attributes: &HashMap<std::string::String, Value>,
+        options: &RequestParameters,
+    ) -> Result<WithMeta<()>, String> {
+        self.check_bypass(options)?;
+        let id = id.into_guid()?;
+        self.validate_payload_options(entity_set, attributes).await?;
+        let url = web_api_url(&self.base_url, &self.api_path, &row_path(entity_set, id));
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .patch(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(&attributes);
+
+        let resp = self.send(options.apply(request)).await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        Ok(WithMeta {
+            value: (),
+            meta: response_meta(&Method::PATCH, &url, &resp),
+        })
+    }
+
+    /// Update a tracked row with only its changed columns, then take its current values as
+    /// unchanged. When the row has an ETag, the update is sent with `If-Match` and fails if the
+    /// row changed since it was read. Returns `false` without sending a request when nothing
+    /// changed.
+    ///
+    /// Dataverse does not return the new row version from an update, so the row's ETag is cleared
+    /// and later updates are unconditional until the row is read again.
+    pub async fn update_tracked(&self, tracked: &mut TrackedEntity) -> Result<bool, String> {
+        self.update_tracked_with_options(tracked, &RequestParameters::default())
+            .await
+    }
+
+    /// `update_tracked` with Dataverse request parameters.
+    pub async fn update_tracked_with_options(
+        &self,
+        tracked: &mut TrackedEntity,
+        options: &RequestParameters,
+    ) -> Result<bool, String> {
+        if !tracked.is_modified() {
+            return Ok(false);
+        }
+        let changes = tracked.changes();
+        let entity_set = self
+            .entity_set_name_map()
+            .await?
+            .remove(&normalize_entity_name(&changes.logical_name))
+            .ok_or_else(|| {
+                format!(
+                    "Entity set metadata not found for '{}'",
+                    changes.logical_name
+                )
+            })?;
+        let payload = self.build_write_payload(&changes).await?;
+        let mut options = options.clone();
+        if let Some(etag) = &changes.etag
+            && !options
+                .custom_headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("If-Match"))
+        {
+            options
+                .custom_headers
+                .push(("If-Match".to_string(), etag.clone()));
+        }
+        self.update_entity_with_options(&entity_set, changes.id, &payload, &options)
+            .await?;
+        tracked.accept_changes();
+        tracked.clear_etag();
+        Ok(true)
+    }
+
+    /// Delete a single entity record by ID.
+    pub async fn delete_entity(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+    ) -> Result<(), std::string::String> {
+        self.delete_entity_with_options(entity_set, id, &RequestParameters::default())
+            .await
+    }
+
+    /// Delete a single entity record by ID with Dataverse request parameters.
+    pub async fn delete_entity_with_options(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        options: &RequestParameters,
+    ) -> Result<(), std::string::String> {
+        self.delete_entity_with_meta(entity_set, id, options)
+            .await
+            .map(WithMeta::into_inner)
+    }
+
+    /// `delete_entity_with_options`, also returning the status and headers of the response.
+    pub async fn delete_entity_with_meta(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        options: &RequestParameters,
+    ) -> Result<WithMeta<()>, String> {
+        self.check_bypass(options)?;
+        let id = id.into_guid()?;
+        let url = web_api_url(&self.base_url, &self.api_path, &row_path(entity_set, id));
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .delete(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+
+        let resp = self.send(options.apply(request)).await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        Ok(WithMeta {
+            value: (),
+            meta: response_meta(&Method::DELETE, &url, &resp),
+        })
+    }
+
+    /// List the logical names of the tables whose deleted rows the recycle bin keeps. Rows of
+    /// other tables are gone once deleted. The recycle bin must be turned on for the environment,
+    /// and keeps rows for its configured number of days.
+    pub async fn list_recycle_bin_tables(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .list_metadata_collection::<RecycleBinConfig>(RECYCLE_BIN_TABLES_QUERY)
+            .await?
+            .into_iter()
+            .map(|config| config.name)
+            .collect())
+    }
+
+    /// Retrieve every deleted row of `entity` in the recycle bin that matches a FetchXML query,
+    /// such as `<fetch><entity name="account"><attribute name="name" /></entity></fetch>`. Pass
+    /// a returned row's `id` to `restore_record`.
+    pub async fn retrieve_deleted_records(
+        &self,
+        entity: &str,
+        fetchxml: &str,
+    ) -> Result<Vec<Entity>, String> {
+        self.retrieve_multiple_fetchxml_paging_with_options(
+            entity,
+            fetchxml,
+            &FetchOptions {
+                deleted_records: true,
+                ..FetchOptions::default()
+            },
+        )
+        .await
+    }
+
+    /// Restore the deleted row `id` of `entity_set` from the recycle bin with the `Restore`
+    /// action, under its original ID.
+    pub async fn restore_record(&self, entity_set: &str, id: impl IntoGuid) -> Result<(), String> {
+        let id = Uuid::from(id.into_guid()?);
+        let definition = self.resolve_entity_definition(entity_set).await?;
+        let primary_id_attribute = definition
+            .primary_id_attribute
+            .as_deref()
+            .ok_or_else(|| format!("No primary id attribute found for '{entity_set}'"))?;
+        self.post_action(
+            "Restore",
+            &restore_body(&definition.logical_name, primary_id_attribute, id),
+        )
+        .await
+    }
+
+    /// Read the maximum size, in KB, configured for a file column.
+    pub async fn retrieve_file_column_max_size_kb(
+        &self,
+        entity: &str,
+        column: &str,
+    ) -> Result<Option<i64>, String> {
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        let json = self
+            .get_json(&format!(
+                "{}/Microsoft.Dynamics.CRM.FileAttributeMetadata?$select=MaxSizeInKB",
+                attribute_definition_path(&logical_name, &column.to_ascii_lowercase())
+            ))
+            .await?;
+        Ok(parse_max_size_kb(&json))
+    }
+
+    /// Upload a file to a file column in chunks.
+    ///
+    /// The file size is checked against the column's `MaxSizeInKB` before any data is sent. When
+    /// `session_path` is set, the upload session is saved there after every chunk so a later call
+    /// with the same arguments resumes from the last acknowledged byte. The file is removed once
+    /// the upload completes.
+    pub async fn upload_file(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        column: &str,
+        file_name: &str,
+        data: &[u8],
+        session_path: Option<&Path>,
+    ) -> Result<(), String> {
+        let id = &id.into_guid()?.to_string();
+        let file_size = data.len() as u64;
+        self.ensure_table_feature(entity_set, TableFeature::FileColumns)
+            .await?;
+        let max_size_kb = self
+            .retrieve_file_column_max_size_kb(entity_set, column)
+            .await?;
+        check_file_size(column, file_size, max_size_kb)?;
+
+        let resumed = match session_path {
+            Some(path) => load_upload_session(path)?
+                .filter(|session| session.matches(entity_set, id, column, file_name, data)),
+            None => None,
+        };
+        let mut session = match resumed {
+            Some(session) => session,
+            None => {
+                let session = self
+                    .start_file_upload(entity_set, id, column, file_name, data)
+                    .await?;
+                if let Some(path) = session_path {
+                    save_upload_session(path, &session)?;
+                }
+                session
+            }
+        };
+
+        while session.uploaded < file_size {
+            let start = session.uploaded;
+            let end = (start + session.chunk_size).min(file_size);
+            let access_token = self.get_access_token().await?;
+            let request = self
+                .client
+                .patch(&session.location)
+                .bearer_auth(&access_token)
+                .header("Content-Type", "application/octet-stream")
+                .header("x-ms-file-name", file_name)
+                .header("Content-Range", content_range(start, end - start, file_size))
+                .body(data[start as usize..end as usize].to_vec());
+
+            let resp = self.send(request).await?;
+            let status = resp.status();
+            if !status.is_success() {
+                let headers = resp.headers().clone();
+                let body = resp.text().await.unwrap_or_default();
+                return Err(api_error(status, &headers, &body));
+            }
+
+            session.uploaded = end;
+            if let Some(path) = session_path {
+                save_upload_session(path, &session)?;
+            }
+        }
+
+        if let Some(path) = session_path
+            && path.exists()
+        {
+            std::fs::remove_file(path).map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    /// Begin a chunked upload session for a file column.
+    async fn start_file_upload(
+        &self,
+        entity_set: &str,
+        id: &str,
+        column: &str,
+        file_name: &str,
+        data: &[u8],
+    ) -> Result<FileUploadSession, String> {
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &format!("{}/{column}", row_path(entity_set, id)),
+        );
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .patch(&url)
+            .bearer_auth(&access_token)
+            .header("x-ms-transfer-mode", "chunked")
+            .header("x-ms-file-name", file_name);
+
+        let resp = self.send(request).await?;
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        let location = resp
+            .headers()
+            .get("Location")
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| "Dataverse did not return an upload session location".to_string())?;
+        let location = if location.starts_with('/') {
+            format!("{}{}", self.base_url, location)
+        } else {
+            location.to_string()
+        };
+        let chunk_size = resp
+            .headers()
+            .get("x-ms-chunk-size")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .filter(|value| *value > 0)
+            .unwrap_or(DEFAULT_CHUNK_SIZE);
+
+        Ok(FileUploadSession {
+            entity_set: entity_set.to_string(),
+            id: id.to_string(),
+            column: column.to_string(),
+            file_name: file_name.to_string(),
+            file_size: data.len() as u64,
+            content_sha256: content_sha256(data),
+            location,
+            chunk_size,
+            uploaded: 0,
+        })
+    }
+
+    /// Check whether rows of a table can be assigned and shared. Organization-owned tables have no
+    /// owner, so generic tooling can use this to skip assign and share steps for them.
+    pub async fn supports_assign_and_share(&self, entity: &str) -> Result<bool, String> {
+        Ok(self
+            .resolve_entity_definition(entity)
+            .await?
+            .supports_assign_and_share())
+    }
+
+    /// Assign a row to a user or team by setting its `ownerid`. Fails before the request for
+    /// tables that are not user- or team-owned.
+    pub async fn assign_record(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        owner: &EntityReference,
+    ) -> Result<(), String> {
+        let id = id.into_guid()?;
+        self.resolve_entity_definition(entity_set)
+            .await?
+            .ensure_assign_and_share("assign")?;
+        let owner_definition = self.resolve_entity_definition(&owner.logical_name).await?;
+        let attributes = HashMap::from([(
+            "ownerid@odata.bind".to_string(),
+            Value::String(bind_path(
+                &owner_definition.entity_set_name,
+                owner.id.as_hyphenated(),
+            )),
+        )]);
+        self.update_entity(entity_set, id, &attributes)
+            .await
+    }
+
+    /// Share a row with a user or team using the `GrantAccess` action.
+    pub async fn grant_access(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        principal: &EntityReference,
+        access_rights: AccessRights,
+    ) -> Result<(), String> {
+        let id = Uuid::from(id.into_guid()?);
+        self.resolve_entity_definition(entity_set)
+            .await?
+            .ensure_assign_and_share("share")?;
+        let target = self.action_target(entity_set, id).await?;
+        self.post_action(
+            "GrantAccess",
+            &build_principal_access_body(target, principal, access_rights),
+        )
+        .await
+    }
+
+    /// Replace the access a user or team has to a shared row using the `ModifyAccess` action.
+    pub async fn modify_access(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        principal: &EntityReference,
+        access_rights: AccessRights,
+    ) -> Result<(), String> {
+        let id = Uuid::from(id.into_guid()?);
+        self.resolve_entity_definition(entity_set)
+            .await?
+            .ensure_assign_and_share("share")?;
+        let target = self.action_target(entity_set, id).await?;
+        self.post_action(
+            "ModifyAccess",
+            &build_principal_access_body(target, principal, access_rights),
+        )
+        .await
+    }
+
+    /// Stop sharing a row with a user or team using the `RevokeAccess` action.
+    pub async fn revoke_access(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        principal: &EntityReference,
+    ) -> Result<(), String> {
+        let id = Uuid::from(id.into_guid()?);
+        self.resolve_entity_definition(entity_set)
+            .await?
+            .ensure_assign_and_share("share")?;
+        let target = self.action_target(entity_set, id).await?;
+        self.post_action(
+            "RevokeAccess",
+            &serde_json::json!({
+                "Target": target,
+                "Revokee": principal_reference(principal),
+            }),
+        )
+        .await
+    }
+
+    /// Return the effective access a user or team has to a row, from ownership, roles, and
+    /// sharing combined, using the `RetrievePrincipalAccess` function.
+    pub async fn retrieve_principal_access(
+        &self,
+        entity_set: &str,
+        id: impl IntoGuid,
+        principal: &EntityReference,
+    ) -> Result<AccessRights, String> {
+        let id = Uuid::from(id.into_guid()?);
+        let target = self.resolve_entity_definition(entity_set).await?;
+        let principal_definition = self
+            .resolve_entity_definition(&principal.logical_name)
+            .await?;
+        let target_id = format!(
+            "{{\"@odata.id\":\"{}\"}}",
+            row_path(&target.entity_set_name, id.as_hyphenated())
+        );
+        let json = self
+            .get_json(&format!(
+                "{}/Microsoft.Dynamics.CRM.RetrievePrincipalAccess(Target=@tid)?@tid={}",
+                row_path(&principal_definition.entity_set_name, principal.id.as_hyphenated()),
+                encode_query_value(&target_id)
+            ))
+            .await?;
+        parse_principal_access(&json)
+    }
+
+    /// Explain why a user can or cannot reach a row: their effective rights from
+    /// `RetrievePrincipalAccess`, the row's owner, the shares from
+    /// `RetrieveSharedPrincipalsAndAccess`, and the user's roles and teams with the teams' roles.
+    /// See `AccessDiagnosis::findings` for a summary.
+    pub async fn diagnose_access(
+        &self,
+        user_id: impl IntoGuid,
+        entity_set: &str,
+        record_id: impl IntoGuid,
+    ) -> Result<AccessDiagnosis, String> {
+        let user_id = Uuid::from(user_id.into_guid()?);
+        let record_id = Uuid::from(record_id.into_guid()?);
+        let definition = self.resolve_entity_definition(entity_set).await?;
+        let user = self.retrieve_system_user(user_id).await?;
+        let user_reference = EntityReference {
+            id: user_id,
+            logical_name: "systemuser".to_string(),
+            name: user.full_name.clone(),
+        };
+        let access = self
+            .retrieve_principal_access(&definition.entity_set_name, record_id, &user_reference)
+            .await?;
+
+        // Organization-owned tables have no owner column and cannot be shared.
+        let (owner, shares) = if definition.supports_assign_and_share() {
+            let url = web_api_url(
+                &self.base_url,
+                &self.api_path,
+                &select_path(
+                    &row_path(&definition.entity_set_name, record_id.as_hyphenated()),
+                    &["ownerid"],
+                ),
+            );
+            let row = self.get_list_json(&url, &RequestOptions::default()).await?;
+            let shares = parse_shared_principals(
+                &self
+                    .get_json(&shared_principals_path(
+                        &definition.entity_set_name,
+                        record_id,
+                    ))
+                    .await?,
+            )?;
+            (parse_owner(&row), shares)
+        } else {
+            (None, Vec::new())
+        };
+
+        let user_roles = self.list_security_roles(&user_reference).await?;
+        let mut teams = Vec::new();
+        for team in self
+            .list_metadata_collection::<Team>(&user_teams_path(user_id))
+            .await?
+        {
+            let roles = self
+                .list_security_roles(&EntityReference {
+                    id: team.team_id,
+                    logical_name: "team".to_string(),
+                    name: Some(team.name.clone()),
+                })
+                .await?;
+            teams.push(TeamAccess { team, roles });
+        }
+
+        Ok(AccessDiagnosis {
+            user,
+            record: EntityReference {
+                id: record_id,
+                logical_name: definition.logical_name,
+                name: None,
+            },
+            access,
+            owner,
+            user_roles,
+            teams,
+            shares,
+        })
+    }
+
+    /// Add users to an owner or access team using the `AddMembersTeam` action. Membership of
+    /// Microsoft Entra ID group teams is managed in the group instead.
+    pub async fn add_team_members(
+        &self,
+        team_id: impl IntoGuid,
+        user_ids: impl IntoIterator<Item = impl IntoGuid>,
+    ) -> Result<(), String> {
+        let team_id = Uuid::from(team_id.into_guid()?);
+        let user_ids = into_uuids(user_ids)?;
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+        self.post_action(
+            &format!(
+                "{}/Microsoft.Dynamics.CRM.AddMembersTeam",
+                row_path("teams", team_id.as_hyphenated())
+            ),
+            &team_members_body(&user_ids),
+        )
+        .await
+    }
+
+    /// Remove users from an owner or access team using the `RemoveMembersTeam` action.
+    pub async fn remove_team_members(
+        &self,
+        team_id: impl IntoGuid,
+        user_ids: impl IntoIterator<Item = impl IntoGuid>,
+    ) -> Result<(), String> {
+        let team_id = Uuid::from(team_id.into_guid()?);
+        let user_ids = into_uuids(user_ids)?;
+        if user_ids.is_empty() {
+            return Ok(());
+        }
+        self.post_action(
+            &format!(
+                "{}/Microsoft.Dynamics.CRM.RemoveMembersTeam",
+                row_path("teams", team_id.as_hyphenated())
+            ),
+            &team_members_body(&user_ids),
+        )
+        .await
+    }
+
+    /// Assign a security role to a user or team by associating it through
+    /// `systemuserroles_association` or `teamroles_association`. The role must belong to the
+    /// principal's business unit; use `find_security_role` to find that copy of it.
+    pub async fn assign_security_role(
+        &self,
+        principal: &EntityReference,
+        role_id: impl IntoGuid,
+    ) -> Result<(), String> {
+        let role_id = Uuid::from(role_id.into_guid()?);
+        let roles_path = principal_roles_path(principal)?;
+        self.post_action(
+            &format!("{roles_path}/$ref"),
+            &serde_json::json!({
+                "@odata.id": web_api_url(&self.base_url, &self.api_path, &row_path("roles", role_id.as_hyphenated()))
+            }),
+        )
+        .await?;
+        self.clear_execution_context().await;
+        Ok(())
+    }
+
+    /// Remove a security role from a user or team.
+    pub async fn remove_security_role(
+        &self,
+        principal: &EntityReference,
+        role_id: impl IntoGuid,
+    ) -> Result<(), String> {
+        let role_id = Uuid::from(role_id.into_guid()?);
+        let roles_path = principal_roles_path(principal)?;
+        let url = web_api_url(
+            &self.base_url,
+            &self.api_path,
+            &format!("{}/$ref", row_path(&roles_path, role_id.as_hyphenated())),
+        );
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .delete(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+
+        let resp = self.send(request).await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        self.clear_execution_context().await;
+        Ok(())
+    }
+
+    /// List the security roles assigned directly to a user or team. Roles a user holds through
+    /// team membership are not included.
+    pub async fn list_security_roles(
+        &self,
+        principal: &EntityReference,
+    ) -> Result<Vec<SecurityRole>, String> {
+        parse_security_roles(
+            &self
+                .get_json(&format!(
+                    "{}?$select=roleid,name",
+                    principal_roles_path(principal)?
+                ))
+                .await?,
+        )
+    }
+
+    /// Find the security role named `name` in a business unit, or `None` when it has no such role.
+    pub async fn find_security_role(
+        &self,
+        name: &str,
+        business_unit_id: impl IntoGuid,
+    ) -> Result<Option<SecurityRole>, String> {
+        let business_unit_id = Uuid::from(business_unit_id.into_guid()?);
+        let roles = parse_security_roles(
+            &self
+                .get_json(&security_role_query(name, business_unit_id))
+                .await?,
+        )?;
+        Ok(roles.into_iter().next())
+    }
+
+    /// Validate choice values in a create or update payload when option set validation is enabled.
+    async fn validate_payload_options(
+        &self,
+        entity_set: &str,
+        attributes: &HashMap<String, Value>,
+    ) -> Result<(), String> {
+        if !self.validate_option_sets.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let logical_name = self.resolve_entity_logical_name(entity_set).await?;
+        let option_sets = self.list_entity_option_sets(&logical_name).await?;
+        validate_payload_options(&logical_name, attributes, &option_sets)
+    }
+
+    /// Read a Custom API definition with its request parameters and response properties.
+    pub async fn retrieve_custom_api(
+        &self,
+        unique_name: &str,
+    ) -> Result<CustomApiDefinition, String> {
+        {
+            let cache = self.custom_api_cache.lock().await;
+            if let Some(definition) = cache.get(unique_name) {
+                return Ok(definition.clone());
+            }
+        }
+
+        let json = self
+            .get_json(&format!(
+                "customapis?$select=uniquename,bindingtype,boundentitylogicalname,isfunction&$filter=uniquename eq {}&$expand=CustomAPIRequestParameters($select=uniquename,type,isoptional,logicalentityname),CustomAPIResponseProperties($select=uniquename,type,logicalentityname)",
+                encode_string_literal(unique_name)
+            ))
+            .await?;
+        let definition = parse_custom_api_definition(&json, unique_name)?;
+
+        let mut cache = self.custom_api_cache.lock().await;
+        cache.insert(unique_name.to_string(), definition.clone());
+
+        Ok(definition)
+    }
+
+    /// Download and parse the Web API's CSDL `$metadata` document, for checking entity sets,
+    /// properties, navigation properties, and operations before sending requests. The parsed
+    /// document is cached for the client's lifetime.
+    pub async fn retrieve_service_schema(&self) -> Result<Arc<ServiceSchema>, String> {
+        {
+            let cache = self.service_schema_cache.lock().await;
+            if let Some(schema) = &*cache {
+                return Ok(schema.clone());
+            }
+        }
+
+        let url = web_api_url(&self.base_url, &self.api_path, "$metadata");
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .get(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/xml");
+        let resp = self.send(request).await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        let csdl = resp
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {e}"))?;
+        let schema = Arc::new(ServiceSchema::parse(&csdl)?);
+
+        let mut cache = self.service_schema_cache.lock().await;
+        Ok(cache.get_or_insert(schema).clone())
+    }
+
+    /// Invoke an unbound Custom API after checking `request_params` against its definition, and
+    /// deserialize the response properties into `T`. Functions are called with `GET` and actions
+    /// with `POST`. Use `serde_json::Value` for `T` to read the raw response, or `()` for APIs
+    /// without response properties.
+    pub async fn execute_custom_api<T>(
+        &self,
+        unique_name: &str,
+        request_params: &HashMap<String, serde_json::Value>,
+    ) -> Result<T, String>
+    where
+        T: DeserializeOwned,
+    {
+        let definition = self.retrieve_custom_api(unique_name).await?;
+        if definition.binding_type != 0 {
+            return Err(format!(
+                "Custom API '{unique_name}' is bound to {}; only unbound Custom APIs can be executed",
+                definition
+                    .bound_entity_logical_name
+                    .as_deref()
+                    .unwrap_or("a table")
+            ));
+        }
+        validate_custom_api_parameters(&definition, request_params)?;
+
+        let json = if definition.is_function {
+            self.get_json(&custom_api_function_path(
+                &definition.unique_name,
+                request_params,
+            ))
+            .await?
+        } else {
+            let body = request_params
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect::<serde_json::Map<String, Value>>();
+            self.post_action_json(&definition.unique_name, &Value::Object(body))
+                .await?
+        };
+
+        serde_json::from_value(json)
+            .map_err(|e| format!("Failed to parse Custom API response: {e}"))
+    }
+
+    /// Merge `subordinate` into `target` with the `Merge` action. Child rows move to the target,
+    /// `update_content` columns are written to it, and the subordinate is deactivated. Only
+    /// account, contact, lead, and incident rows can be merged.
+    pub async fn merge_records(
+        &self,
+        entity_set: &str,
+        target: impl IntoGuid,
+        subordinate: impl IntoGuid,
+        update_content: Option<&HashMap<String, serde_json::Value>>,
+        perform_parenting_checks: bool,
+    ) -> Result<(), String> {
+        let target = Uuid::from(target.into_guid()?);
+        let subordinate = Uuid::from(subordinate.into_guid()?);
+        let definition = self.resolve_entity_definition(entity_set).await?;
+        check_mergeable(&definition.logical_name)?;
+
+        let body = build_merge_body(
+            &definition.logical_name,
+            self.action_target(entity_set, target).await?,
+            self.action_target(entity_set, subordinate).await?,
+            update_content,
+            perform_parenting_checks,
+        );
+        self.post_action("Merge", &body).await
+    }
+
+    /// Build the `Target` parameter for a sharing action.
+    async fn action_target(&self, entity_set: &str, id: Uuid) -> Result<Value, String> {
+        let definition = self.resolve_entity_definition(entity_set).await?;
+        let primary_id_attribute = definition
+            .primary_id_attribute
+            .clone()
+            .unwrap_or_else(|| format!("{}id", definition.logical_name));
+        Ok(action_entity_reference(
+            &definition.logical_name,
+            &primary_id_attribute,
+            id,
+        ))
+    }
+
+    /// Invoke an unbound Dataverse action that returns no content.
+    async fn post_action(&self, action: &str, body: &Value) -> Result<(), String> {
+        self.post_action_json(action, body).await.map(|_| ())
+    }
+
+    /// Invoke an unbound Dataverse action, returning its response body or `Value::Null` when the
+    /// action returns no content.
+    async fn post_action_json(&self, action: &str, body: &Value) -> Result<Value, String> {
+        let url = web_api_url(&self.base_url, &self.api_path, action);
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .post(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/json")
+            .json(body);
+
+        let resp = self.send(request).await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        let text = resp
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {e}"))?;
+        if text.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse JSON: {e}"))
+    }
+
+    /// Execute multiple create, update, and delete requests using a single Dataverse batch call.
+    pub async fn execute_multiple(
+        &self,
+        request: &ExecuteMultipleRequest,
+    ) -> Result<ExecuteMultipleResponse, String> {
+        if request.requests.is_empty() {
+            return Ok(ExecuteMultipleResponse::default());
+        }
+
+        if request.requests.len() > 1000 {
+            return Err(format!(
+                "ExecuteMultipleRequest contains {} requests, exceeding the Dataverse batch limit of 1000",
+                request.requests.len()
+            ));
+        }
+
+        if self.validate_option_sets.load(Ordering::Relaxed) {
+            for target in request.requests.iter().filter_map(|request| match request {
+                OrganizationRequest::Create(create) => Some(&create.target),
+                OrganizationRequest::Update(update) => Some(&update.target),
+                OrganizationRequest::Upsert(upsert) => Some(&upsert.target),
+                OrganizationRequest::Delete(_) => None,
+            }) {
+                let option_sets = self.list_entity_option_sets(&target.logical_name).await?;
+                validate_entity_options(target, &option_sets)?;
+            }
+        }
+
+        let entity_set_name_by_logical_name = self.entity_set_name_map().await?;
+        let mut lookup_navigations = HashMap::new();
+        for target in request.requests.iter().filter_map(|request| match request {
+            OrganizationRequest::Create(create) => Some(&create.target),
+            OrganizationRequest::Update(update) => Some(&update.target),
+            OrganizationRequest::Upsert(upsert) => Some(&upsert.target),
+            OrganizationRequest::Delete(_) => None,
+        }) {
+            self.load_lookup_navigations(target, &mut lookup_navigations).await?;
+        }
+        let prepared_requests = self.prepare_batch_requests(
+            &request.requests,
+            &entity_set_name_by_logical_name,
+            &lookup_navigations,
+        )?;
+        let parts = self
+            .send_batch(&prepared_requests, request.settings.continue_on_error)
+            .await?;
+        self.map_batch_response(request, parts)
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        self.send_as(request, true).await
+    }
+
+    /// Send `request`, as the impersonated caller when `impersonate` is true and a caller is set,
+    /// otherwise as the signed-in identity without any impersonation header.
+    async fn send_as(
+        &self,
+        request: RequestBuilder,
+        impersonate: bool,
+    ) -> Result<Response, String> {
+        if self.shutting_down.load(Ordering::Acquire) {
+            return Err("Client is shut down".to_string());
+        }
+        self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let _in_flight = InFlightGuard {
+            in_flight: &self.in_flight,
+            idle: &self.idle,
+        };
+
+        let request = self.middleware.iter().fold(request, |request, middleware| {
+            middleware.on_request(request)
+        });
+        // Retry loops run inside `with_operation_request_id`, so every attempt sends the same ID.
+        let (client, mut request, client_request_id) = ensure_client_request_id(request)?;
+        for (name, value) in &self.default_headers {
+            request
+                .headers_mut()
+                .entry(name)
+                .or_insert_with(|| value.clone());
+        }
+        apply_impersonation(
+            request.headers_mut(),
+            *self.caller_object_id.lock().await,
+            impersonate,
+        );
+        let method = request.method().clone();
+        let path = request.url().path().to_string();
+        let query = request.url().query().map(str::to_string);
+        let started = Instant::now();
+        let sent = self
+            .transport
+            .send(&self.client, RequestBuilder::from_parts(client, request))
+            .await;
+        record_request(
+            method.as_str(),
+            &path,
+            query.as_deref(),
+            sent.as_ref().ok().map(|resp| resp.status().as_u16()),
+            started.elapsed(),
+        );
+        let mut resp = sent?;
+        echo_client_request_id(resp.headers_mut(), &client_request_id);
+        if let Some(on_response) = &self.on_response {
+            on_response(&ResponseMeta::from_response(
+                &method,
+                &path,
+                resp.status().as_u16(),
+                resp.headers(),
+            ));
+        }
+
+        // Failed responses are logged even below `Debug`, so `Error` shows what went wrong without
+        // the URL noise. Throttling is expected under load and logged as a warning.
+        let status = resp.status();
+        let log_level = self.log_level();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            if log_level.includes(Level::Warn) {
+                warn!(
+                    "{method} {} throttled ({status}) client request id: {client_request_id}",
+                    sanitize_url(&path)
+                );
+            }
+        } else if !status.is_success() && log_level.includes(Level::Error) {
+            error!(
+                "{method} {} failed ({status}) client request id: {client_request_id}",
+                sanitize_url(&path)
+            );
+        }
+        Ok(resp)
+    }
+
+    /// Refuse request parameters that bypass custom logic unless the client opted in.
+    fn check_bypass(&self, options: &RequestParameters) -> Result<(), String> {
+        if options.bypasses_custom_logic() && !self.allow_bypass_custom_logic {
+            return Err(bypass_not_allowed());
+        }
+        Ok(())
+    }
+
+    async fn get_access_token(&self) -> Result<String, String> {
+        self.token_cache.access_token(self.log_level()).await
+    }
+
+    async fn get_json(&self, path: &str) -> Result<Value, String> {
+        self.get_json_as(path, true).await
+    }
+
+    /// `get_json`, sent without impersonation headers unless `impersonate` is true.
+    async fn get_json_as(&self, path: &str, impersonate: bool) -> Result<Value, String> {
+        let url = web_api_url(&self.base_url, &self.api_path, path);
+
+        if self.log_level().includes_debug() {
+            debug!("Url: {:?}", sanitize_url(&url));
+        }
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .get(&url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+        let request = RequestOptions::default().apply(request, &FORMATTED_VALUE_ANNOTATIONS);
+        let resp = self.send_as(request, impersonate).await?;
+
+        let status = resp.status();
+
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        let mut json = resp
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse JSON: {e}"))?;
+        self.localize_labels(&mut json);
+        Ok(json)
+    }
+
+    /// Rewrite metadata labels to the configured label language, if any.
+    fn localize_labels(&self, json: &mut Value) {
+        if let Some(lcid) = self.label_language {
+            localize_labels(json, lcid);
+        }
+    }
+
+    async fn list_metadata_collection<T>(&self, path: &str) -> Result<Vec<T>, String>
+    where
+        T: DeserializeOwned,
+    {
+        let mut page = self
+            .get_list_page::<T>(&web_api_url(&self.base_url, &self.api_path, path))
+            .await?;
+        let mut value = std::mem::take(&mut page.value);
+
+        // Metadata collections are normally returned in one response, but follow server-driven
+        // paging when Dataverse asks for it so large solutions are not silently truncated.
+        while let Some(next_link) = page.next_link.take() {
+            page = self.follow_next_link::<T>(&next_link).await?;
+            value.append(&mut page.value);
+        }
+
+        Ok(value)
+    }
+
+    /// Read a metadata collection returned in a batch part, following any next links.
+    async fn collect_batch_list<T>(&self, part: &ParsedBatchPart) -> Result<Vec<T>, String>
+    where
+        T: DeserializeOwned,
+    {
+        let mut json = batch_part_json(part)?;
+        self.localize_labels(&mut json);
+        let mut page: ListResponse<T> =
+            serde_json::from_value(json).map_err(|e| format!("Failed to parse JSON: {e}"))?;
+        let mut value = std::mem::take(&mut page.value);
+
+        while let Some(next_link) = page.next_link.take() {
+            page = self.follow_next_link::<T>(&next_link).await?;
+            value.append(&mut page.value);
+        }
+
+        Ok(value)
+    }
+
+    /// Fetch a row collection page or a single row as raw JSON, requesting the annotations entity
+    /// parsing relies on.
+    async fn get_list_json(&self, url: &str, options: &RequestOptions) -> Result<Value, String> {
+        if self.log_level().includes_debug() {
+            debug!("Url: {:?}", sanitize_url(url));
+        }
+
+        let access_token = self.get_access_token().await?;
+        let request = self
+            .client
+            .get(url)
+            .bearer_auth(&access_token)
+            .header("Accept", "application/json");
+        let resp = self.send(options.apply(request, &ENTITY_ANNOTATIONS)).await?;
+
+        let status = resp.status();
+
+        if !status.is_success() {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            return Err(api_error(status, &headers, &body));
+        }
+
+        resp.json()
+            .await
+            .map_err(|e| format!("Failed to parse JSON: {e}"))
+    }
+
+    async fn get_list_page<T>(&self, url: &str) -> Result<ListResponse<T>, String>
+    where
+        T: DeserializeOwned,
+    {
+        let mut json = self.get_list_json(url, &RequestOptions::default()).await?;
+        self.localize_labels(&mut json);
+        serde_json::from_value(json).map_err(|e| format!("Failed to parse JSON: {e}"))
+    }
+
+    async fn apply_default_columns(&self, entity: &str, fetchxml: &str) -> Result<String, String> {
+        match self.default_columns_of(entity).await? {
+            Some(columns) => apply_default_attributes(fetchxml, &columns),
+            None => Ok(fetchxml.to_string()),
+        }
+    }
+
+    async fn apply_default_select(&self, entity: &str, query: &str) -> Result<String, String> {
+        Ok(match self.default_columns_of(entity).await? {
+            Some(columns) => apply_default_select(query, &columns),
+            None => query.to_string(),
+        })
+    }
+
+    /// The default columns of `entity`: its registered list, or its primary id and name
+    /// attributes when derived from metadata.
+    async fn default_columns_of(&self, entity: &str) -> Result<Option<Vec<String>>, String> {
+        let default_columns = self.default_columns.lock().await.clone();
+        if default_columns.by_entity.is_empty() && !default_columns.derive_from_metadata {
+            return Ok(None);
+        }
+
+        let logical_name = self.resolve_entity_logical_name(entity).await?;
+        let registered = default_columns.get(&logical_name).map(<[String]>::to_vec);
+        let derive_from_metadata = default_columns.derive_from_metadata;
+
+        Ok(match registered {
+            Some(columns) => Some(columns),
+            None if derive_from_metadata => {
+                let definition = self.resolve_entity_definition(entity).await?;
+                Some(
+                    definition
+                        .primary_id_attribute
+                        .into_iter()
+                        .chain(definition.primary_name_attribute)
+                        .collect(),
+                )
+            }
+            None => None,
+        })
+    }
+
+    pub(crate) async fn resolve_entity_definition(&self, entity_name: &str) -> Result<EntityDefinition, String> {
+        let definitions = self.list_entity_definitions().await?;
+        let target = normalize_entity_name(entity_name);
+
+        definitions
+            .into_iter()
+            .find(|definition| {
+                normalize_entity_name(&definition.entity_set_name) == target
+                    || normalize_entity_name(&definition.logical_name) == target
+                    || normalize_entity_name(&definition.schema_name) == target
+            })
+            .ok_or_else(|| format!("Entity metadata not found for '{}'", entity_name))
+    }
+
+    async fn resolve_primary_id_attribute(
+        &self,
+        entity_set: &str,
+    ) -> Result<Option<String>, String> {
+        let definitions = self.list_entity_definitions().await?;
+        let target = normalize_entity_name(entity_set);
+
+        Ok(definitions
+            .into_iter()
+            .find(|definition| {
+                normalize_entity_name(&definition.entity_set_name) == target
+                    || normalize_entity_name(&definition.logical_name) == target
+                    || normalize_entity_name(&definition.schema_name) == target
+            })
+            .and_then(|definition| definition.primary_id_attribute))
+    }
+
+    async fn resolve_entity_logical_name(&self, entity_name: &str) -> Result<String, String> {
+        let definitions = self.list_entity_definitions().await?;
+        let target = normalize_entity_name(entity_name);
+
+        definitions
+            .into_iter()
+            .find(|definition| {
+                normalize_entity_name(&definition.entity_set_name) == target
+                    || normalize_entity_name(&definition.logical_name) == target
+                    || normalize_entity_name(&definition.schema_name) == target
+            })
+            .map(|definition| definition.logical_name)
+            .ok_or_else(|| format!("Entity metadata not found for '{}'", entity_name))
+    }
+
+    async fn entity_attribute_map(
+        &self,
+        entity_name: &str,
+    ) -> Result<HashMap<String, EntityAttribute>, String> {
+        let logical_name = self.resolve_entity_logical_name(entity_name).await?;
+        let attributes = self.list_entity_attributes(&logical_name).await?;
+        let mut map = HashMap::new();
+        for attribute in attributes {
+            map.insert(attribute.logical_name.to_ascii_lowercase(), attribute.clone());
+            map.entry(attribute.schema_name.to_ascii_lowercase())
+                .or_insert(attribute);
+        }
+        Ok(map)
+    }
+
+    async fn entity_set_name_map(&self) -> Result<HashMap<String, String>, String> {
+        let definitions = self.list_entity_definitions().await?;
+        Ok(definitions
+            .into_iter()
+            .map(|definition| {
+                (
+                    definition.logical_name.to_ascii_lowercase(),
+                    definition.entity_set_name,
+                )
+            })
+            .collect())
+    }
+
+    fn prepare_batch_requests(
+        &self,
+        requests: &[OrganizationRequest],
+        entity_set_name_by_logical_name: &HashMap<String, String>,
+        lookup_navigations: &HashMap<String, Vec<LookupNavigation>>,
+    ) -> Result<Vec<PreparedBatchItem>, String> {
+        requests
+            .iter()
+            .enumerate()
+            .map(|(request_index, request)| {
+                self.prepare_batch_request(
+                    request_index,
+                    request.clone(),
+                    entity_set_name_by_logical_name,
+                    lookup_navigations,
+                )
+            })
+            .collect()
+    }
+
+    fn prepare_batch_request(
+        &self,
+        _request_index: usize,
+        request: OrganizationRequest,
+        entity_set_name_by_logical_name: &HashMap<String, String>,
+        lookup_navigations: &HashMap<String, Vec<LookupNavigation>>,
+    ) -> Result<PreparedBatchItem, String> {
+        let prepared = match &request {
+            OrganizationRequest::Create(request) => {
+                let entity_set_name = entity_set_name_by_logical_name
+                    .get(&request.target.logical_name.to_ascii_lowercase())
+                    .ok_or_else(|| {
+                        format!(
+                            "Entity set metadata not found for '{}'",
+                            request.target.logical_name
+                        )
+                    })?;
+
+                PreparedBatchRequest {
+                    method: "POST",
+                    path: batch_request_path(entity_set_name),
+                    body: Some(entity_to_write_body(
+                        &request.target,
+                        entity_set_name_by_logical_name,
+                        lookup_navigations,
+                    )?),
+                    parameters: request.parameters.clone(),
+                    prefer: None,
+                }
+            }
+            OrganizationRequest::Update(request) => {
+                if request.target.id.is_nil() {
+                    return Err("UpdateRequest target must include a non-empty entity ID".to_string());
+                }
+
+                let entity_set_name = entity_set_name_by_logical_name
+                    .get(&request.target.logical_name.to_ascii_lowercase())
+                    .ok_or_else(|| {
+                        format!(
+                            "Entity set metadata not found for '{}'",
+                            request.target.logical_name
+                        )
+                    })?;
+
+                PreparedBatchRequest {
+                    method: "PATCH",
+                    path: batch_request_path(&row_path(
+                        entity_set_name,
+                        request.target.id.as_hyphenated(),
+                    )),
+                    body: Some(entity_to_write_body(
+                        &request.target,
+                        entity_set_name_by_logical_name,
+                        lookup_navigations,
+                    )?),
+                    parameters: request.parameters.clone(),
+                    prefer: None,
+                }
+            }
+            OrganizationRequest::Delete(request) => {
+                let entity_set_name = entity_set_name_by_logical_name
+                    .get(&request.target.logical_name.to_ascii_lowercase())
+                    .ok_or_else(|| {
+                        format!(
+                            "Entity set metadata not found for '{}'",
+                            request.target.logical_name
+                        )
+                    })?;
+
+                PreparedBatchRequest {
+                    method: "DELETE",
+                    path: batch_request_path(&row_path(
+                        entity_set_name,
+                        request.target.id.as_hyphenated(),
+                    )),
+                    body: None,
+                    parameters: request.parameters.clone(),
+                    prefer: None,
+                }
+            }
+            OrganizationRequest::Upsert(request) => {
+                let entity_set_name = entity_set_name_by_logical_name
+                    .get(&request.target.logical_name.to_ascii_lowercase())
+                    .ok_or_else(|| {
+                        format!(
+                            "Entity set metadata not found for '{}'",
+                            request.target.logical_name
+                        )
+                    })?;
+
+                let key = if request.alternate_key.is_empty() {
+                    if request.target.id.is_nil() {
+                        return Err(
+                            "UpsertRequest target must include an entity ID or an alternate key"
+                                .to_string(),
+                        );
+                    }
+                    request.target.id.as_hyphenated().to_string()
+                } else {
+                    format_key_segment(&request.alternate_key)?
+                };
+
+                PreparedBatchRequest {
+                    method: "PATCH",
+                    path: batch_request_path(&row_path(entity_set_name, key)),
+                    body: Some(entity_to_write_body(
+                        &request.target,
+                        entity_set_name_by_logical_name,
+                        lookup_navigations,
+                    )?),
+                    parameters: request.parameters.clone(),
+                    prefer: None,
+                }
+            }
+        };
+
+        self.check_bypass(&prepared.parameters)?;
+        Ok(PreparedBatchItem {
+            prepared_request: prepared,
+        })
+    }
+
+    /// Send prepared requests as one `$batch` call and split the multipart response.
+    async fn send_batch(
+        &self,
+        prepared_requests: &[PreparedBatchItem],
+        continue_on_error: bool,
+    ) -> Result<Vec<ParsedBatchPart>, String> {
+        let boundary = format!("batch_{}", Uuid::new_v4().as_hyphenated());
+        let body = self.build_batch_body(&boundary, prepared_requests)?;
+        let url = web_api_url(&self.base_url, &self.api_path, "$batch");
+        let access_token = self.get_access_token().await?;
+
+        let mut http_request = self
+            .client
+            .post(&url)
+            .bearer_auth(&access_token)
+            .header("OData-MaxVersion", "4.0")
+            .header("OData-Version", "4.0")
+            .header("If-None-Match", "null")
+            .header("Accept", "application/json")
+            .header("Content-Type", format!("multipart/mixed; boundary={boundary}"))
+            .body(body);
+
+        if continue_on_error {
+            http_request = http_request.header("Prefer", "odata.continue-on-error");
+        }
+
+        let resp = self.send(http_request).await?;
+
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let content_type = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+        let response_text = resp
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read batch response: {e}"))?;
+
+        if !status.is_success() && !content_type.as_deref().unwrap_or_default().starts_with("multipart/mixed") {
+            return Err(api_error(status, &headers, &response_text));
+        }
+
+        parse_batch_response_parts(content_type.as_deref(), &response_text)
+    }
+
+    fn build_batch_body(
+        &self,
+        boundary: &str,
+        requests: &[PreparedBatchItem],
+    ) -> Result<String, String> {
+        let mut body = String::new();
+
+        for (content_id, item) in requests.iter().enumerate() {
+            // Dataverse expects `$batch` parts in raw HTTP-message form rather than as plain JSON
+            // fragments, so the multipart body is assembled manually here.
+            body.push_str(&format!("--{boundary}\r\n"));
+            body.push_str("Content-Type: application/http\r\n");
+            body.push_str("Content-Transfer-Encoding: binary\r\n");
+            body.push_str(&format!("Content-ID: {}\r\n\r\n", content_id + 1));
+            body.push_str(&format!(
+                "{} {}/{} HTTP/1.1\r\n",
+                item.prepared_request.method, self.api_path, item.prepared_request.path
+            ));
+            body.push_str("Accept: application/json\r\n");
+
+            let parameters = &item.prepared_request.parameters;
+            let mut headers: Vec<(&str, &str)> = parameters.headers();
+            if let Some(prefer) = &item.prepared_request.prefer {
+                headers.push(("Prefer", prefer));
+            }
+            // Custom headers are written into the multipart body as raw text, so a CR or LF
+            // would start another header or part; they replace a header of the same name.
+            for (name, value) in &parameters.custom_headers {
+                parse_header(name, value)?;
+                headers.retain(|(existing, _)| !existing.eq_ignore_ascii_case(name));
+                headers.push((name, value));
+            }
+            for (header, value) in headers {
+                body.push_str(&format!("{header}: {value}\r\n"));
+            }
+
+            if let Some(payload) = &item.prepared_request.body {
+                body.push_str("Content-Type: application/json;type=entry\r\n\r\n");
+                body.push_str(payload);
+                body.push_str("\r\n");
+            } else {
+                body.push_str("\r\n");
+            }
+        }
+
+        body.push_str(&format!("--{boundary}--\r\n"));
+        Ok(body)
+    }
+
+    fn map_batch_response(
+        &self,
+        request: &ExecuteMultipleRequest,
+        parts: Vec<ParsedBatchPart>,
+    ) -> Result<ExecuteMultipleResponse, String> {
+        let mut response = ExecuteMultipleResponse::default();
+
+        for (part_index, part) in parts.iter().enumerate() {
+            let Some(source_request) = request.requests.get(part_index) else {
+                break;
+            };
+
+            let target_id = source_request.target_id();
+            if part.status_code >= 400 {
+                let fault = parse_fault(part);
+                response.results.push(BulkResult {
+                    status_code: Some(fault.status_code),
+                    error_code: fault.code.clone(),
+                    ..BulkResult::failed(part_index, target_id, fault.message.clone())
+                });
+                response.responses.push(ExecuteMultipleResponseItem {
+                    request_index: part_index,
+                    response: None,
+                    fault: Some(fault),
+                });
+                continue;
+            }
+
+            let success = source_request.success_response(&part.headers);
+            response.results.push(BulkResult::succeeded(
+                part_index,
+                success.id().or(target_id),
+                0,
+            ));
+            if request.settings.return_responses {
+                response.responses.push(ExecuteMultipleResponseItem {
+                    request_index: part_index,
+                    response: Some(success),
+                    fault: None,
+                });
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+fn ensure_fetch_page_size(fetchxml: &str, page_size: i32) -> Result<String, String> {
+    if fetch_tag_has_attr(fetchxml, "count")? {
+        return Ok(fetchxml.to_string());
+    }
+
+    let fetch_start = fetchxml
+        .find("<fetch")
+        .ok_or_else(|| "FetchXML must start with a <fetch> element".to_string())?;
+    let tag_end = fetchxml[fetch_start..]
+        .find('>')
+        .ok_or_else(|| "FetchXML <fetch> element is not closed".to_string())?
+        + fetch_start;
+
+    let mut inserted = String::new();
+    inserted.push_str(&fetchxml[..tag_end]);
+    inserted.push_str(&format!(" count=\"{page_size}\""));
+    inserted.push_str(&fetchxml[tag_end..]);
+    Ok(inserted)
+}
+
+/// Fail on the first of `columns` that is not in `known`, the columns of `logical_name`.
+fn check_columns(logical_name: &str, columns: &[&str], known: &[&str]) -> Result<(), String> {
+    for column in columns {
+        if !known.iter().any(|name| name.eq_ignore_ascii_case(column)) {
+            return Err(unknown_attribute_error(
+                column,
+                logical_name,
+                known.iter().copied(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn entity_attributes_path(logical_name: &str) -> String {
+    format!(
+        "{}/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute eq true and IsValidForRead eq true",
+        entity_definition_path(logical_name)
+    )
+}
+
+fn date_time_behaviors_path(logical_name: &str) -> String {
+    format!(
+        "{}/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+        entity_definition_path(logical_name)
+    )
+}
+
+fn merge_date_time_behaviors(
+    attributes: &mut [EntityAttribute],
+    behaviors: Vec<DateTimeAttributeBehavior>,
+) {
+    let behaviors = behaviors
+        .into_iter()
+        .filter_map(|attribute| {
+            let behavior = attribute.date_time_behavior?.value?;
+            let behavior = serde_json::from_value(Value::String(behavior)).ok()?;
+            Some((attribute.logical_name, behavior))
+        })
+        .collect::<HashMap<String, DateTimeBehavior>>();
+    for attribute in attributes {
+        attribute.date_time_behavior = behaviors.get(&attribute.logical_name).copied();
+    }
+}
+
+fn normalize_entity_name(value: &str) -> String {
+    value
+        .trim_matches(|ch| ch == '[' || ch == ']' || ch == '"' || ch == '`')
+        .to_ascii_lowercase()
+}
+
+/// Metadata of a response to a `method` request sent to `url`. The path comes from the request,
+/// since a replayed response has no URL of its own.
+fn response_meta(method: &Method, url: &str, resp: &Response) -> ResponseMeta {
+    let path = reqwest::Url::parse(url)
+        .map(|url| url.path().to_string())
+        .unwrap_or_else(|_| url.to_string());
+    ResponseMeta::from_response(method, &path, resp.status().as_u16(), resp.headers())
+}
+
+fn parse_uuid_from_uri(value: &str) -> Option<Uuid> {
+    let start = value.rfind('(')? + 1;
+    let end = value.rfind(')')?;
+    Uuid::parse_str(value[start..end].trim_matches('{').trim_matches('}')).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+    use reqwest::RequestBuilder;
+
+    use super::{
+        AGGREGATE_PAGE_SIZE, DEFAULT_FETCHXML_PAGE_SIZE, ServiceClient, date_time_behaviors_path,
+        ensure_fetch_page_size, entity_attributes_path, normalize_entity_name, parse_uuid_from_uri,
+    };
+    use crate::LogLevel;
+    use crate::auth::config::AuthConfig;
+    use crate::dataverse::batch::{
+        CreateRequest, DeleteRequest, ExecuteMultipleRequest, ExecuteMultipleSettings,
+        OrganizationRequest, batch_get_item_with_prefer,
+    };
+    use crate::dataverse::bulk::{BulkExecutor, BulkOptions};
+    use crate::dataverse::changefeed::{
+        ChangeFeed, DeltaTokenStore, MemoryDeltaTokenStore, store_key,
+    };
+    use crate::dataverse::clientbuilder::{RequestMiddleware, bypass_not_allowed};
+    use crate::dataverse::countresult::CountLimit;
+    use crate::dataverse::entity::{Entity, EntityReference, Value as DataverseValue};
+    use crate::dataverse::fetchxml::{apply_paging, ensure_aggregate_page_size};
+    use crate::dataverse::import::{
+        FieldMapping, ImportFormat, ImportMapping, ImportOptions, import_records,
+    };
+    use crate::dataverse::pageretry::PageRetryPolicy;
+    use crate::dataverse::requestoptions::{FETCHXML_ANNOTATIONS, RequestOptions};
+    use crate::dataverse::requestparameters::RequestParameters;
+    use crate::dataverse::sync::ChangeEvent;
+    use crate::dataverse::trackedentity::TrackedEntity;
+    use crate::dataverse::transport::{RecordedExchange, TransportMode};
+    use crate::dataverse::url::batch_request_path;
+    use crate::testsupport::{TEST_URL, exchange, write_exchanges};
+    use uuid::Uuid;
+
+    /// Write a recording of the given `(method, path_and_query, status, body)` exchanges.
+    fn write_recording(exchanges: &[(&str, &str, u16, &str)]) -> PathBuf {
+        write_exchanges(
+            &exchanges
+                .iter()
+                .map(|(method, path, status, body)| exchange(method, path, *status, body))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// A `(status, headers, body)` part of a `$batch` response.
+    type BatchResponsePart<'a> = (u16, &'a [(&'a str, &'a str)], &'a str);
+
+    /// A `$batch` exchange: `requests` are the `(method, path, body)` parts the client sends,
+    /// and `responses` the parts Dataverse answers with.
+    fn batch_exchange(
+        requests: &[(&str, &str, Option<&str>)],
+        status: u16,
+        responses: &[BatchResponsePart],
+    ) -> RecordedExchange {
+        let mut request_body = String::new();
+        for (content_id, (method, path, body)) in requests.iter().enumerate() {
+            request_body.push_str(&format!(
+                "--batch_boundary\r\nContent-Type: application/http\r\nContent-Transfer-Encoding: binary\r\nContent-ID: {}\r\n\r\n{method} /api/data/v9.2/{path} HTTP/1.1\r\nAccept: application/json\r\n",
+                content_id + 1
+            ));
+            match body {
+                Some(body) => request_body.push_str(&format!(
+                    "Content-Type: application/json;type=entry\r\n\r\n{body}\r\n"
+                )),
+                None => request_body.push_str("\r\n"),
+            }
+        }
+        request_body.push_str("--batch_boundary--\r\n");
+
+        let mut response_body = String::new();
+        for (status, headers, body) in responses {
+            response_body.push_str(&format!(
+                "--batchresponse_1\r\nContent-Type: application/http\r\nContent-Transfer-Encoding: binary\r\n\r\nHTTP/1.1 {status} Status\r\n"
+            ));
+            for (name, value) in *headers {
+                response_body.push_str(&format!("{name}: {value}\r\n"));
+            }
+            response_body.push_str(&format!("\r\n{body}\r\n"));
+        }
+        response_body.push_str("--batchresponse_1--\r\n");
+
+        RecordedExchange {
+            method: "POST".to_string(),
+            url: format!("{TEST_URL}/api/data/v9.2/$batch"),
+            request_body: Some(request_body),
+            status,
+            headers: vec![(
+                "content-type".to_string(),
+                "multipart/mixed; boundary=batchresponse_1".to_string(),
+            )],
+            response_body,
+        }
+    }
+
+    /// Build a client that replays the given `(method, path_and_query, status, body)` exchanges.
+    async fn replay_client(exchanges: &[(&str, &str, u16, &str)]) -> (ServiceClient, PathBuf) {
+        let path = write_recording(exchanges);
+        let auth = AuthConfig::ClientCredentials {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            tenant_id: "tenant".to_string(),
+            dataverse_url: TEST_URL.to_string(),
+            token_cache_store_path: None,
+        };
+        let client =
+            ServiceClient::new_with_transport(auth, LogLevel::Error, TransportMode::Replay(path.clone()))
+                .await
+                .expect("should create replay client");
+        (client, path)
+    }
+
+    fn fetch_path(fetchxml: &str) -> String {
+        format!("/api/data/v9.2/accounts?fetchXml={}", urlencoding::encode(fetchxml))
+    }
+
+    #[tokio::test]
+    async fn count_detailed_flags_top_cap_from_replayed_response() {
+        let fetchxml = "<fetch top=\"2\"><entity name=\"account\" /></fetch>";
+        let (client, path) =
+            replay_client(&[("GET", &fetch_path(fetchxml), 200, "{\"value\":[{},{}]}")]).await;
+
+        let result = client
+            .retrieve_multiple_fetchxml_count_detailed("accounts", fetchxml, None)
+            .await
+            .expect("should count");
+
+        assert_eq!(result.count, 2);
+        assert_eq!(result.limit, Some(CountLimit::TopCap));
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn default_columns_are_keyed_by_logical_name() {
+        let (client, path) = replay_client(&[
+            ACCOUNT_DEFINITIONS,
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/accounts?$top=1&$select=name",
+                200,
+                "{\"value\":[]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/accounts?$top=1&$select=accountnumber",
+                200,
+                "{\"value\":[]}",
+            ),
+        ])
+        .await;
+
+        client
+            .set_default_columns("account", vec!["name".to_string()])
+            .await
+            .expect("should register");
+        client
+            .retrieve_multiple_odata("accounts", "$top=1")
+            .await
+            .expect("should select the columns registered for the logical name");
+
+        // Registering by entity set name replaces the list registered by logical name.
+        client
+            .set_default_columns("accounts", vec!["accountnumber".to_string()])
+            .await
+            .expect("should register");
+        client
+            .retrieve_multiple_odata("accounts", "$top=1")
+            .await
+            .expect("should select the columns registered for the set name");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn malformed_row_id_fails_before_sending() {
+        let (client, path) = replay_client(&[]).await;
+
+        let error = client
+            .delete_entity("accounts", "not-a-guid")
+            .await
+            .expect_err("should reject the ID");
+        assert!(error.starts_with("Invalid GUID 'not-a-guid'"), "{error}");
+
+        let error = client
+            .entity_exists("accounts", "not-a-guid")
+            .await
+            .expect_err("should reject the ID");
+        assert!(error.starts_with("Invalid GUID 'not-a-guid'"), "{error}");
+        let error = client
+            .add_team_members("00000000-0000-0000-0000-000000000001", ["not-a-guid"])
+            .await
+            .expect_err("should reject the member ID");
+        assert!(error.starts_with("Invalid GUID 'not-a-guid'"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn bypass_parameters_need_the_client_to_opt_in() {
+        let (client, path) = replay_client(&[]).await;
+        let row_id = "11111111-1111-1111-1111-111111111111";
+        let bypass = RequestParameters::bypass_plugins_and_flows();
+
+        let error = client
+            .delete_entity_with_options("accounts", row_id, &bypass)
+            .await
+            .expect_err("should refuse to bypass");
+        assert_eq!(error, bypass_not_allowed());
+
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .allow_bypass_custom_logic(true)
+            .build()
+            .await
+            .expect("should build client");
+        let error = client
+            .delete_entity_with_options("accounts", row_id, &bypass)
+            .await
+            .expect_err("nothing is recorded");
+        assert!(error.contains("No recorded response"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn successful_writes_report_response_meta() {
+        let path = write_recording(&[(
+            "DELETE",
+            "/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)",
+            204,
+            "",
+        )]);
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .on_response({
+                let seen = seen.clone();
+                move |meta| seen.lock().expect("lock").push(meta.clone())
+            })
+            .build()
+            .await
+            .expect("should build client");
+
+        let deleted = client
+            .delete_entity_with_meta(
+                "accounts",
+                "11111111-1111-1111-1111-111111111111",
+                &RequestParameters::default(),
+            )
+            .await
+            .expect("should delete");
+
+        assert_eq!(deleted.meta.status, 204);
+        assert_eq!(deleted.meta.method, "DELETE");
+        assert_eq!(
+            deleted.meta.path,
+            "/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)"
+        );
+        assert!(deleted.meta.client_request_id.is_some());
+        assert_eq!(*seen.lock().expect("lock"), vec![deleted.meta]);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn update_tracked_sends_only_when_a_column_changed() {
+        let (method, definitions_path, status, body) = ACCOUNT_DEFINITIONS;
+        let account_path = "/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)";
+        // Replay matches request bodies, so each update must send the changed column alone.
+        let path = write_exchanges(&[
+            exchange(method, definitions_path, status, body),
+            RecordedExchange {
+                request_body: Some("{\"name\":\"Fabrikam\"}".to_string()),
+                ..exchange("PATCH", account_path, 204, "")
+            },
+            RecordedExchange {
+                request_body: Some("{\"name\":\"Northwind\"}".to_string()),
+                ..exchange("PATCH", account_path, 204, "")
+            },
+        ]);
+        struct IfMatchHeaders(std::sync::Mutex<Vec<Option<String>>>);
+        impl RequestMiddleware for IfMatchHeaders {
+            fn on_request(&self, request: RequestBuilder) -> RequestBuilder {
+                let if_match = request
+                    .try_clone()
+                    .and_then(|request| request.build().ok())
+                    .filter(|request| request.method() == reqwest::Method::PATCH)
+                    .map(|request| {
+                        request
+                            .headers()
+                            .get("If-Match")
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string)
+                    });
+                if let Some(if_match) = if_match {
+                    self.0.lock().expect("lock").push(if_match);
+                }
+                request
+            }
+        }
+        let seen = Arc::new(IfMatchHeaders(std::sync::Mutex::new(Vec::new())));
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .middleware(seen.clone())
+            .build()
+            .await
+            .expect("should build client");
+        let mut entity = Entity::new(
+            Uuid::parse_str("11111111-1111-1111-1111-111111111111").expect("uuid"),
+            "account",
+            None,
+        );
+        entity.etag = Some("W/\"1\"".to_string());
+        entity.attributes.insert(
+            "name".to_string(),
+            DataverseValue::String("Contoso".to_string()),
+        );
+        let mut tracked = TrackedEntity::new(entity);
+
+        tracked.set("name", DataverseValue::String("Contoso".to_string()));
+        assert!(!client.update_tracked(&mut tracked).await.expect("no-op"));
+
+        tracked.set("name", DataverseValue::String("Fabrikam".to_string()));
+        assert!(
+            client
+                .update_tracked(&mut tracked)
+                .await
+                .expect("should update")
+        );
+        assert!(!tracked.is_modified());
+
+        // The update does not return the new row version, so the next update is unconditional.
+        tracked.set("name", DataverseValue::String("Northwind".to_string()));
+        assert!(
+            client
+                .update_tracked(&mut tracked)
+                .await
+                .expect("should update")
+        );
+        assert_eq!(
+            *seen.0.lock().expect("lock"),
+            [Some("W/\"1\"".to_string()), None]
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    const ACCOUNT_DEFINITIONS: (&str, &str, u16, &str) = (
+        "GET",
+        "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+        200,
+        "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false}]}",
+    );
+
+    fn create_account(name: &str) -> OrganizationRequest {
+        let mut entity = Entity::new(Uuid::nil(), "account", None);
+        entity.attributes.insert(
+            "name".to_string(),
+            DataverseValue::String(name.to_string()),
+        );
+        OrganizationRequest::Create(CreateRequest::new(entity))
+    }
+
+    fn delete_account(id: &str) -> OrganizationRequest {
+        OrganizationRequest::Delete(DeleteRequest::new(EntityReference {
+            id: Uuid::parse_str(id).expect("uuid"),
+            logical_name: "account".to_string(),
+            name: None,
+        }))
+    }
+
+    #[tokio::test]
+    async fn metadata_bulk_resolves_entity_set_names() {
+        let (method, definitions_path, status, body) = ACCOUNT_DEFINITIONS;
+        let attributes = "{\"value\":[{\"LogicalName\":\"name\",\"SchemaName\":\"Name\",\"AttributeType\":\"String\",\"IsCustomAttribute\":false}]}";
+        let path = write_exchanges(&[
+            exchange(method, definitions_path, status, body),
+            batch_exchange(
+                &[
+                    (
+                        "GET",
+                        &batch_request_path(&entity_attributes_path("account")),
+                        None,
+                    ),
+                    (
+                        "GET",
+                        &batch_request_path(&date_time_behaviors_path("account")),
+                        None,
+                    ),
+                ],
+                200,
+                &[
+                    (200, &[("Content-Type", "application/json")], attributes),
+                    (
+                        200,
+                        &[("Content-Type", "application/json")],
+                        "{\"value\":[]}",
+                    ),
+                ],
+            ),
+        ]);
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build client");
+
+        let metadata = client
+            .get_metadata_bulk(&["accounts", "Account"])
+            .await
+            .expect("should load metadata");
+
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(metadata["account"][0].logical_name, "name");
+        assert!(client.get_metadata_bulk(&["contacts"]).await.is_err());
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn execute_multiple_reports_a_result_for_every_answered_request() {
+        let (method, definitions_path, status, body) = ACCOUNT_DEFINITIONS;
+        let path = write_exchanges(&[
+            exchange(method, definitions_path, status, body),
+            batch_exchange(
+                &[
+                    ("POST", "accounts", Some("{\"name\":\"A\"}")),
+                    (
+                        "DELETE",
+                        "accounts(22222222-2222-2222-2222-222222222222)",
+                        None,
+                    ),
+                ],
+                200,
+                &[
+                    (
+                        204,
+                        &[(
+                            "OData-EntityId",
+                            "https://example.crm.dynamics.com/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)",
+                        )],
+                        "",
+                    ),
+                    (
+                        404,
+                        &[("Content-Type", "application/json")],
+                        "{\"error\":{\"code\":\"0x80040217\",\"message\":\"Not found\"}}",
+                    ),
+                ],
+            ),
+        ]);
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build client");
+
+        let response = client
+            .execute_multiple(&ExecuteMultipleRequest {
+                settings: ExecuteMultipleSettings {
+                    continue_on_error: true,
+                    return_responses: false,
+                },
+                requests: vec![
+                    create_account("A"),
+                    delete_account("22222222-2222-2222-2222-222222222222"),
+                ],
+            })
+            .await
+            .expect("should execute");
+
+        assert_eq!(
+            response.responses.len(),
+            1,
+            "only the fault without return_responses"
+        );
+        assert_eq!(
+            response
+                .results
+                .iter()
+                .map(|result| (
+                    result.index,
+                    result.id.map(|id| id.to_string()),
+                    result.is_success()
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    0,
+                    Some("11111111-1111-1111-1111-111111111111".to_string()),
+                    true
+                ),
+                (
+                    1,
+                    Some("22222222-2222-2222-2222-222222222222".to_string()),
+                    false
+                ),
+            ]
+        );
+        assert_eq!(response.results[1].status_code, Some(404));
+        assert_eq!(
+            response.results[1].error_code.as_deref(),
+            Some("0x80040217")
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn bulk_executor_resends_throttled_parts_after_retry_after() {
+        let (method, definitions_path, status, body) = ACCOUNT_DEFINITIONS;
+        let path = write_exchanges(&[
+            exchange(method, definitions_path, status, body),
+            batch_exchange(
+                &[
+                    ("POST", "accounts", Some("{\"name\":\"A\"}")),
+                    ("POST", "accounts", Some("{\"name\":\"B\"}")),
+                ],
+                200,
+                &[
+                    (
+                        204,
+                        &[(
+                            "OData-EntityId",
+                            "https://example.crm.dynamics.com/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)",
+                        )],
+                        "",
+                    ),
+                    (
+                        429,
+                        &[("Content-Type", "application/json"), ("Retry-After", "0")],
+                        "{\"error\":{\"code\":\"0x80072322\",\"message\":\"Number of requests exceeded\"}}",
+                    ),
+                ],
+            ),
+            batch_exchange(
+                &[("POST", "accounts", Some("{\"name\":\"B\"}"))],
+                200,
+                &[(
+                    400,
+                    &[("Content-Type", "application/json")],
+                    "{\"error\":{\"code\":\"0x80040237\",\"message\":\"Duplicate\"}}",
+                )],
+            ),
+        ]);
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build client");
+        // Without the `Retry-After` of 0 the retry would wait `retry_delay`, past the timeout.
+        let executor = BulkExecutor::new(
+            &client,
+            BulkOptions {
+                retry_delay: Duration::from_secs(60),
+                ..BulkOptions::default()
+            },
+        );
+
+        let report = tokio::time::timeout(
+            Duration::from_secs(5),
+            executor.execute(vec![create_account("A"), create_account("B")]),
+        )
+        .await
+        .expect("should honor Retry-After");
+
+        assert_eq!(report.succeeded(), 1);
+        assert_eq!(
+            report.items[0].id,
+            Some(Uuid::parse_str("11111111-1111-1111-1111-111111111111").expect("uuid"))
+        );
+        assert_eq!(report.items[1].attempts, 2);
+        let failure = report.items[1].outcome.as_ref().expect_err("duplicate");
+        assert_eq!(failure.status_code, Some(400));
+        assert_eq!(failure.code.as_deref(), Some("0x80040237"));
+        assert_eq!(failure.message, "Duplicate");
+        assert_eq!(report.results()[1].retry_count, 1);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn bulk_executor_resends_only_idempotent_requests_after_a_gateway_error() {
+        let (method, definitions_path, status, body) = ACCOUNT_DEFINITIONS;
+        let mut gateway_error = batch_exchange(
+            &[
+                ("POST", "accounts", Some("{\"name\":\"A\"}")),
+                (
+                    "DELETE",
+                    "accounts(22222222-2222-2222-2222-222222222222)",
+                    None,
+                ),
+            ],
+            502,
+            &[],
+        );
+        gateway_error.headers = vec![("content-type".to_string(), "text/plain".to_string())];
+        gateway_error.response_body = "Bad Gateway".to_string();
+        let path = write_exchanges(&[
+            exchange(method, definitions_path, status, body),
+            gateway_error,
+            batch_exchange(
+                &[(
+                    "DELETE",
+                    "accounts(33333333-3333-3333-3333-333333333333)",
+                    None,
+                )],
+                200,
+                &[(204, &[], "")],
+            ),
+            batch_exchange(
+                &[(
+                    "DELETE",
+                    "accounts(22222222-2222-2222-2222-222222222222)",
+                    None,
+                )],
+                200,
+                &[(204, &[], "")],
+            ),
+        ]);
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build client");
+        let executor = BulkExecutor::new(
+            &client,
+            BulkOptions {
+                batch_size: 2,
+                max_concurrency: 1,
+                retry_delay: Duration::from_millis(1),
+                ..BulkOptions::default()
+            },
+        );
+
+        let report = executor
+            .execute(vec![
+                create_account("A"),
+                delete_account("22222222-2222-2222-2222-222222222222"),
+                delete_account("33333333-3333-3333-3333-333333333333"),
+            ])
+            .await;
+
+        // The batch may have created the row before the gateway failed, so the create is not
+        // sent again.
+        let failure = report.items[0].outcome.as_ref().expect_err("not resent");
+        assert_eq!(report.items[0].attempts, 1);
+        assert!(failure.message.contains("502"), "{}", failure.message);
+        assert!(report.items[1].outcome.is_ok());
+        assert_eq!(report.items[1].attempts, 2);
+        assert!(report.items[2].outcome.is_ok());
+        assert_eq!(report.items[2].attempts, 1);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn retrieve_aggregate_types_aliases_and_splits_group_keys() {
+        let fetchxml = concat!(
+            "<fetch aggregate=\"true\"><entity name=\"account\">",
+            "<attribute name=\"ownerid\" alias=\"owner\" groupby=\"true\" />",
+            "<attribute name=\"revenue\" alias=\"total\" aggregate=\"sum\" />",
+            "<attribute name=\"accountid\" alias=\"rows\" aggregate=\"count\" />",
+            "</entity></fetch>"
+        );
+        let paged = apply_paging(
+            &ensure_aggregate_page_size(fetchxml, AGGREGATE_PAGE_SIZE).expect("page size"),
+            1,
+            None,
+        )
+        .expect("paging");
+        let (client, path) = replay_client(&[
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[{\"LogicalName\":\"revenue\",\"SchemaName\":\"Revenue\",\"AttributeType\":\"Money\"},{\"LogicalName\":\"ownerid\",\"SchemaName\":\"OwnerId\",\"AttributeType\":\"Owner\"},{\"LogicalName\":\"accountid\",\"SchemaName\":\"AccountId\",\"AttributeType\":\"Uniqueidentifier\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+            (
+                "GET",
+                &fetch_path(&paged),
+                200,
+                "{\"value\":[{\"owner\":\"11111111-1111-1111-1111-111111111111\",\"owner@Microsoft.Dynamics.CRM.lookuplogicalname\":\"systemuser\",\"owner@OData.Community.Display.V1.FormattedValue\":\"Nancy Anderson\",\"total\":1500.5,\"rows\":3}]}",
+            ),
+        ])
+        .await;
+
+        let rows = client
+            .retrieve_aggregate("accounts", fetchxml)
+            .await
+            .expect("should aggregate");
+
+        assert_eq!(rows.len(), 1);
+        assert!(matches!(
+            rows[0].group_key("owner"),
+            Some(DataverseValue::EntityReference(owner))
+                if owner.logical_name == "systemuser" && owner.name.as_deref() == Some("Nancy Anderson")
+        ));
+        assert!(matches!(
+            rows[0].value("total"),
+            Some(DataverseValue::Money(money)) if money.value.to_string() == "1500.5"
+        ));
+        assert!(matches!(rows[0].value("rows"), Some(DataverseValue::Int(3))));
+        assert_eq!(rows[0].group_by.len(), 1);
+
+        let error = client
+            .retrieve_aggregate("accounts", "<fetch><entity name=\"account\" /></fetch>")
+            .await
+            .expect_err("not an aggregate query");
+        assert!(error.starts_with("retrieve_aggregate needs"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn validate_connection_reports_identity_version_and_failing_check() {
+        let who_am_i = "{\"UserId\":\"11111111-1111-1111-1111-111111111111\",\"BusinessUnitId\":\"22222222-2222-2222-2222-222222222222\",\"OrganizationId\":\"33333333-3333-3333-3333-333333333333\"}";
+        let (client, path) = replay_client(&[
+            ("GET", "/api/data/v9.2/WhoAmI", 200, who_am_i),
+            (
+                "GET",
+                "/api/data/v9.2/RetrieveVersion()",
+                200,
+                "{\"Version\":\"9.2.24094.00200\"}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='systemuser')?$select=LogicalName",
+                200,
+                "{\"LogicalName\":\"systemuser\"}",
+            ),
+            ("GET", "/api/data/v9.2/WhoAmI", 401, ""),
+        ])
+        .await;
+
+        let health = client.validate_connection().await.expect("should validate");
+        assert_eq!(health.version, "9.2.24094.00200");
+        assert_eq!(
+            health.user_id.to_string(),
+            "11111111-1111-1111-1111-111111111111"
+        );
+        assert_eq!(
+            client
+                .execution_context()
+                .await
+                .expect("cached")
+                .organization_id,
+            health.organization_id
+        );
+
+        let error = client
+            .validate_connection()
+            .await
+            .expect_err("second WhoAmI is unauthorized");
+        assert!(
+            error.starts_with("Connection check WhoAmI failed"),
+            "{error}"
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn entity_exists_maps_not_found_to_false() {
+        let (client, path) = replay_client(&[
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/accounts(11111111-1111-1111-1111-111111111111)?$select=accountid",
+                200,
+                "{\"accountid\":\"11111111-1111-1111-1111-111111111111\"}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/accounts(22222222-2222-2222-2222-222222222222)?$select=accountid",
+                404,
+                "{\"error\":{\"code\":\"0x80040217\",\"message\":\"Entity Does Not Exist\"}}",
+            ),
+        ])
+        .await;
+
+        let existing = Uuid::parse_str("11111111-1111-1111-1111-111111111111").expect("uuid");
+        let missing = Uuid::parse_str("22222222-2222-2222-2222-222222222222").expect("uuid");
+        let exists = client
+            .entity_exists("accounts", existing)
+            .await
+            .expect("should check");
+        let missing_exists = client
+            .entity_exists("accounts", missing)
+            .await
+            .expect("should check");
+        assert!(exists);
+        assert!(!missing_exists);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn builder_targets_the_configured_api_version_through_middleware() {
+        struct CountRequests(AtomicUsize);
+
+        impl RequestMiddleware for CountRequests {
+            fn on_request(&self, request: RequestBuilder) -> RequestBuilder {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                request.header("x-test", "1")
+            }
+        }
+
+        let fetchxml = "<fetch top=\"2\"><entity name=\"account\" /></fetch>";
+        let path = write_recording(&[(
+            "GET",
+            &format!(
+                "/api/data/v9.1/accounts?fetchXml={}",
+                urlencoding::encode(fetchxml)
+            ),
+            200,
+            "{\"value\":[{},{}]}",
+        )]);
+        let counter = Arc::new(CountRequests(AtomicUsize::new(0)));
+        let client = ServiceClient::builder()
+            .url(TEST_URL)
+            .static_token("token")
+            .api_version("9.1")
+            .timeout(Duration::from_secs(30))
+            .middleware(counter.clone())
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build replay client");
+
+        let result = client
+            .retrieve_multiple_fetchxml_count_detailed("accounts", fetchxml, None)
+            .await
+            .expect("should count");
+
+        assert_eq!(result.count, 2);
+        assert_eq!(counter.0.load(Ordering::Relaxed), 1);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn label_language_rewrites_metadata_labels() {
+        let path = write_recording(&[(
+            "GET",
+            "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+            200,
+            "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"DisplayName\":{\"LocalizedLabels\":[{\"Label\":\"Account\",\"LanguageCode\":1033},{\"Label\":\"Compte\",\"LanguageCode\":1036}],\"UserLocalizedLabel\":{\"Label\":\"Account\",\"LanguageCode\":1033}}}]}",
+        )]);
+        let client = ServiceClient::builder()
+            .url(TEST_URL)
+            .static_token("token")
+            .label_language(1036)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build replay client");
+
+        let definitions = client.list_entity_definitions().await.expect("should list");
+        let display_name = definitions[0].display_name.as_ref().expect("display name");
+        assert_eq!(client.label_language(), Some(1036));
+        assert_eq!(display_name.user_localized(), Some("Compte"));
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn saving_an_unprovisioned_ui_language_writes_nothing() {
+        let (client, path) = replay_client(&[(
+            "GET",
+            "/api/data/v9.2/RetrieveProvisionedLanguages()",
+            200,
+            "{\"RetrieveProvisionedLanguages\":[1033]}",
+        )])
+        .await;
+
+        let error = client
+            .save_user_ui_language(1036)
+            .await
+            .expect_err("1036 is not provisioned");
+        assert_eq!(error, "Language 1036 is not provisioned in this environment");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn row_numbers_count_rows_across_pages() {
+        let fetchxml =
+            "<fetch><entity name=\"account\"><attribute name=\"name\" /></entity></fetch>";
+        let page_fetchxml = |page| {
+            apply_paging(
+                &ensure_fetch_page_size(fetchxml, DEFAULT_FETCHXML_PAGE_SIZE).expect("page size"),
+                page,
+                None,
+            )
+            .expect("paging")
+        };
+        let path = write_recording(&[
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[{\"LogicalName\":\"name\",\"SchemaName\":\"Name\",\"AttributeType\":\"String\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+            (
+                "GET",
+                &fetch_path(&page_fetchxml(1)),
+                200,
+                "{\"value\":[{\"accountid\":\"00000000-0000-0000-0000-000000000001\",\"name\":\"A\"},{\"accountid\":\"00000000-0000-0000-0000-000000000002\",\"name\":\"B\"}],\"@Microsoft.Dynamics.CRM.morerecords\":true}",
+            ),
+            (
+                "GET",
+                &fetch_path(&page_fetchxml(2)),
+                200,
+                "{\"value\":[{\"accountid\":\"00000000-0000-0000-0000-000000000003\",\"name\":\"C\"}]}",
+            ),
+        ]);
+        struct SolutionHeaders(std::sync::Mutex<Vec<Option<String>>>);
+        impl RequestMiddleware for SolutionHeaders {
+            fn on_request(&self, request: RequestBuilder) -> RequestBuilder {
+                let solution = request
+                    .try_clone()
+                    .and_then(|request| request.build().ok())
+                    .filter(|request| request.url().path().ends_with("/accounts"))
+                    .map(|request| {
+                        request
+                            .headers()
+                            .get("MSCRM.SolutionUniqueName")
+                            .and_then(|value| value.to_str().ok())
+                            .map(str::to_string)
+                    });
+                if let Some(solution) = solution {
+                    self.0.lock().expect("lock").push(solution);
+                }
+                request
+            }
+        }
+        let seen = Arc::new(SolutionHeaders(std::sync::Mutex::new(Vec::new())));
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .middleware(seen.clone())
+            .build()
+            .await
+            .expect("should build client");
+        let options = RequestOptions {
+            row_numbers: true,
+            headers: vec![("MSCRM.SolutionUniqueName".to_string(), "Sales".to_string())],
+            ..RequestOptions::default()
+        };
+
+        let rows = client
+            .retrieve_multiple_fetchxml_paging_with_request_options("accounts", fetchxml, &options)
+            .await
+            .expect("should page");
+
+        let numbered = rows
+            .iter()
+            .map(|row| match &row.attributes["name"] {
+                DataverseValue::String(name) => (row.row_number, name.as_str()),
+                other => panic!("unexpected value {other:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            numbered,
+            vec![(Some(1), "A"), (Some(2), "B"), (Some(3), "C")]
+        );
+        assert!(
+            rows.iter()
+                .all(|row| !row.attributes.contains_key("__rownum"))
+        );
+        assert_eq!(
+            *seen.0.lock().expect("lock"),
+            vec![Some("Sales".to_string()), Some("Sales".to_string())],
+            "every page sends the option headers"
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn fetchxml_validation_accepts_columns_outside_the_odata_attribute_list() {
+        let (client, path) = replay_client(&[
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"}]}",
+            ),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName",
+                200,
+                "{\"value\":[{\"LogicalName\":\"accountid\"},{\"LogicalName\":\"address1_composite\"}]}",
+            ),
+        ])
+        .await;
+
+        client
+            .validate_fetchxml(
+                "<fetch><entity name=\"account\"><attribute name=\"address1_composite\" /></entity></fetch>",
+            )
+            .await
+            .expect("a FetchXML-only column is valid");
+        let error = client
+            .validate_fetchxml(
+                "<fetch><entity name=\"account\"><attribute name=\"accountidd\" /></entity></fetch>",
+            )
+            .await
+            .expect_err("unknown column");
+        assert!(error.contains("did you mean 'accountid'"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn paged_count_retries_a_transiently_failed_page() {
+        let fetchxml = "<fetch><entity name=\"account\" /></fetch>";
+        let page = apply_paging(
+            &ensure_aggregate_page_size(fetchxml, AGGREGATE_PAGE_SIZE).expect("page size"),
+            1,
+            None,
+        )
+        .expect("should page");
+        let page_path = fetch_path(&page);
+        let (client, path) = replay_client(&[
+            ("GET", &page_path, 503, "Service Unavailable"),
+            ("GET", &page_path, 200, "{\"value\":[{},{},{}]}"),
+        ])
+        .await;
+        client.set_page_retry_policy(PageRetryPolicy {
+            attempts: 1,
+            delay: std::time::Duration::ZERO,
+        });
+
+        let result = client
+            .retrieve_multiple_fetchxml_count_detailed("accounts", fetchxml, None)
+            .await
+            .expect("should count after retry");
+
+        assert_eq!(result.count, 3);
+        assert_eq!(result.pages, 1);
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
     }
 
-    async fn entity_set_name_map(&self) -> Result<HashMap<String, String>, String> {
-        let definitions = self.list_entity_definitions().await?;
-        Ok(definitions
-            .into_iter()
-            .map(|definition| {
-                (
-                    definition.logical_name.to_ascii_lowercase(),
-                    definition.entity_set_name,
-                )
-            })
-            .collect())
+    #[tokio::test]
+    async fn legacy_count_fails_when_the_aggregate_limit_is_hit() {
+        let fetchxml = "<fetch><entity name=\"account\" /></fetch>";
+        let page = apply_paging(
+            &ensure_aggregate_page_size(fetchxml, AGGREGATE_PAGE_SIZE).expect("page size"),
+            1,
+            None,
+        )
+        .expect("should page");
+        let page_path = fetch_path(&page);
+        let limit = "{\"error\":{\"code\":\"0x8004E023\",\"message\":\"AggregateQueryRecordLimit exceeded. Cannot perform this operation.\"}}";
+        let (client, path) = replay_client(&[
+            ("GET", &page_path, 400, limit),
+            ("GET", &page_path, 400, limit),
+        ])
+        .await;
+
+        let detailed = client
+            .retrieve_multiple_fetchxml_count_detailed("accounts", fetchxml, None)
+            .await
+            .expect("should report the limit");
+        assert_eq!(detailed.limit, Some(CountLimit::AggregateRecordLimit));
+
+        let error = client
+            .retrieve_multiple_fetchxml_count("accounts", fetchxml)
+            .await
+            .expect_err("a lower bound is not a count");
+        assert!(error.contains("AggregateQueryRecordLimit"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
     }
 
-    fn prepare_batch_requests(
-        &self,
-        requests: &[OrganizationRequest],
-        entity_set_name_by_logical_name: &HashMap<String, String>,
-    ) -> Result<Vec<PreparedBatchItem>, String> {
-        requests
-            .iter()
-            .enumerate()
-            .map(|(request_index, request)| {
-                self.prepare_batch_request(
-                    request_index,
-                    request.clone(),
-                    entity_set_name_by_logical_name,
-                )
+    #[tokio::test]
+    async fn page_retries_resend_the_same_client_request_id() {
+        let fetchxml = "<fetch><entity name=\"account\" /></fetch>";
+        let page = apply_paging(
+            &ensure_aggregate_page_size(fetchxml, AGGREGATE_PAGE_SIZE).expect("page size"),
+            1,
+            None,
+        )
+        .expect("should page");
+        let page_path = fetch_path(&page);
+        let path = write_recording(&[
+            ("GET", &page_path, 503, "Service Unavailable"),
+            ("GET", &page_path, 200, "{\"value\":[{}]}"),
+            ("GET", &page_path, 200, "{\"value\":[{}]}"),
+        ]);
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .on_response({
+                let seen = seen.clone();
+                move |meta| seen.lock().expect("lock").push(meta.client_request_id.clone())
             })
-            .collect()
+            .build()
+            .await
+            .expect("should build client");
+        client.set_page_retry_policy(PageRetryPolicy {
+            attempts: 1,
+            delay: Duration::ZERO,
+        });
+
+        for _ in 0..2 {
+            client
+                .retrieve_multiple_fetchxml_count_detailed("accounts", fetchxml, None)
+                .await
+                .expect("should count");
+        }
+
+        let ids = seen.lock().expect("lock").clone();
+        assert_eq!(ids.len(), 3);
+        assert!(ids[0].is_some());
+        assert_eq!(ids[0], ids[1], "a retry resends the operation's ID");
+        assert_ne!(ids[1], ids[2], "a new operation gets a new ID");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
     }
 
-    fn prepare_batch_request(
-        &self,
-        _request_index: usize,
-        request: OrganizationRequest,
-        entity_set_name_by_logical_name: &HashMap<String, String>,
-    ) -> Result<PreparedBatchItem, String> {
-        let prepared = match &request {
-            OrganizationRequest::Create(request) => {
-                let entity_set_name = entity_set_name_by_logical_name
-                    .get(&request.target.logical_name.to_ascii_lowercase())
-                    .ok_or_else(|| {
-                        format!(
-                            "Entity set metadata not found for '{}'",
-                            request.target.logical_name
-                        )
-                    })?;
+    #[tokio::test]
+    async fn security_roles_are_listed_and_removed_through_refs() {
+        let user = EntityReference {
+            id: Uuid::parse_str("11111111-2222-3333-4444-555555555555").expect("uuid"),
+            logical_name: "systemuser".to_string(),
+            name: None,
+        };
+        let role_id = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+        let roles = "/api/data/v9.2/systemusers(11111111-2222-3333-4444-555555555555)/systemuserroles_association";
+        let (client, path) = replay_client(&[
+            (
+                "GET",
+                &format!("{roles}?$select=roleid,name"),
+                200,
+                "{\"value\":[{\"roleid\":\"aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee\",\"name\":\"Salesperson\"}]}",
+            ),
+            (
+                "DELETE",
+                &format!("{roles}(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)/$ref"),
+                204,
+                "",
+            ),
+        ])
+        .await;
+
+        let assigned = client
+            .list_security_roles(&user)
+            .await
+            .expect("should list roles");
+        client
+            .remove_security_role(&user, role_id)
+            .await
+            .expect("should remove role");
 
-                PreparedBatchRequest {
-                    method: "POST",
-                    path: format!("/api/data/v9.2/{entity_set_name}"),
-                    body: Some(entity_to_write_body(
-                        &request.target,
-                        entity_set_name_by_logical_name,
-                    )?),
-                    parameters: request.parameters.clone(),
-                }
-            }
-            OrganizationRequest::Update(request) => {
-                if request.target.id.is_nil() {
-                    return Err("UpdateRequest target must include a non-empty entity ID".to_string());
-                }
+        assert_eq!(assigned.len(), 1);
+        assert_eq!(assigned[0].id, role_id);
+        assert_eq!(assigned[0].name, "Salesperson");
 
-                let entity_set_name = entity_set_name_by_logical_name
-                    .get(&request.target.logical_name.to_ascii_lowercase())
-                    .ok_or_else(|| {
-                        format!(
-                            "Entity set metadata not found for '{}'",
-                            request.target.logical_name
-                        )
-                    })?;
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
 
-                PreparedBatchRequest {
-                    method: "PATCH",
-                    path: format!(
-                        "/api/data/v9.2/{}({})",
-                        entity_set_name,
-                        request.target.id.as_hyphenated()
-                    ),
-                    body: Some(entity_to_write_body(
-                        &request.target,
-                        entity_set_name_by_logical_name,
-                    )?),
-                    parameters: request.parameters.clone(),
-                }
-            }
-            OrganizationRequest::Delete(request) => {
-                let entity_set_name = entity_set_name_by_logical_name
-                    .get(&request.target.logical_name.to_ascii_lowercase())
-                    .ok_or_else(|| {
-                        format!(
-                            "Entity set metadata not found for '{}'",
-                            request.target.logical_name
-                        )
-                    })?;
+    #[tokio::test]
+    async fn follow_next_link_reads_page_and_following_link() {
+        let next = "/api/data/v9.2/accounts?$select=name&$skiptoken=2";
+        let body = format!(
+            "{{\"value\":[{{\"name\":\"Contoso\"}}],\"@odata.nextLink\":\"{TEST_URL}/api/data/v9.2/accounts?$select=name&$skiptoken=3\"}}"
+        );
+        let (client, path) = replay_client(&[("GET", next, 200, &body)]).await;
 
-                PreparedBatchRequest {
-                    method: "DELETE",
-                    path: format!(
-                        "/api/data/v9.2/{}({})",
-                        entity_set_name,
-                        request.target.id.as_hyphenated()
-                    ),
-                    body: None,
-                    parameters: request.parameters.clone(),
-                }
-            }
-        };
+        let page = client
+            .follow_next_link::<serde_json::Value>(&format!("{TEST_URL}{next}"))
+            .await
+            .expect("should follow link");
 
-        Ok(PreparedBatchItem {
-            prepared_request: prepared,
-        })
+        assert_eq!(page.value.len(), 1);
+        assert!(page.has_more());
+        assert!(
+            client
+                .follow_next_link::<serde_json::Value>("https://elsewhere.example.com/api")
+                .await
+                .is_err()
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
     }
 
-    fn build_batch_body(&self, boundary: &str, requests: &[PreparedBatchItem]) -> String {
-        let mut body = String::new();
+    #[tokio::test]
+    async fn shutdown_refuses_new_requests() {
+        let (client, path) = replay_client(&[]).await;
 
-        for (content_id, item) in requests.iter().enumerate() {
-            // Dataverse expects `$batch` parts in raw HTTP-message form rather than as plain JSON
-            // fragments, so the multipart body is assembled manually here.
-            body.push_str(&format!("--{boundary}\r\n"));
-            body.push_str("Content-Type: application/http\r\n");
-            body.push_str("Content-Transfer-Encoding: binary\r\n");
-            body.push_str(&format!("Content-ID: {}\r\n\r\n", content_id + 1));
-            body.push_str(&format!(
-                "{} {} HTTP/1.1\r\n",
-                item.prepared_request.method, item.prepared_request.path
-            ));
-            body.push_str("Accept: application/json\r\n");
+        client
+            .shutdown(std::time::Duration::from_millis(10))
+            .await
+            .expect("nothing in flight");
 
-            for (header, value) in item.prepared_request.parameters.headers() {
-                body.push_str(&format!("{header}: {value}\r\n"));
-            }
+        assert!(client.is_shut_down());
+        assert_eq!(
+            client.retrieve_organization_info().await.unwrap_err(),
+            "Client is shut down"
+        );
 
-            if let Some(payload) = &item.prepared_request.body {
-                body.push_str("Content-Type: application/json;type=entry\r\n\r\n");
-                body.push_str(payload);
-                body.push_str("\r\n");
-            } else {
-                body.push_str("\r\n");
-            }
-        }
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
 
-        body.push_str(&format!("--{boundary}--\r\n"));
-        body
+    #[tokio::test]
+    async fn batch_get_parts_carry_prefer_header() {
+        let (client, path) = replay_client(&[]).await;
+        let item = batch_get_item_with_prefer(
+            "accounts?fetchXml=%3Cfetch%3E",
+            RequestOptions::default().prefer_header(&FETCHXML_ANNOTATIONS),
+        );
+
+        let body = client
+            .build_batch_body("batch_1", &[item])
+            .expect("batch body");
+
+        assert!(body.contains("GET /api/data/v9.2/accounts?fetchXml=%3Cfetch%3E HTTP/1.1\r\n"));
+        assert!(body.contains("Prefer: odata.include-annotations=\""));
+        assert!(body.contains("Microsoft.Dynamics.CRM.morerecords"));
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
     }
 
-    fn map_batch_response(
-        &self,
-        request: &ExecuteMultipleRequest,
-        parts: Vec<ParsedBatchPart>,
-    ) -> Result<ExecuteMultipleResponse, String> {
-        let mut response = ExecuteMultipleResponse::default();
+    #[tokio::test]
+    async fn batch_parts_reject_header_injection_and_replace_prefer() {
+        let (client, path) = replay_client(&[]).await;
+        let mut item = batch_get_item_with_prefer(
+            "accounts",
+            RequestOptions::default().prefer_header(&FETCHXML_ANNOTATIONS),
+        );
+        item.prepared_request.parameters.custom_headers =
+            vec![("Prefer".to_string(), "odata.maxpagesize=5".to_string())];
+
+        let body = client
+            .build_batch_body("batch_1", &[item.clone()])
+            .expect("batch body");
+        assert!(body.contains("Prefer: odata.maxpagesize=5\r\n"));
+        assert_eq!(body.matches("Prefer:").count(), 1);
+
+        item.prepared_request.parameters.custom_headers = vec![(
+            "MSCRM.SolutionUniqueName".to_string(),
+            "tools\r\n\r\nDELETE /api/data/v9.2/accounts(1) HTTP/1.1".to_string(),
+        )];
+        assert_eq!(
+            client.build_batch_body("batch_1", &[item]).unwrap_err(),
+            "Invalid value for header 'MSCRM.SolutionUniqueName'"
+        );
 
-        for (part_index, part) in parts.iter().enumerate() {
-            let Some(source_request) = request.requests.get(part_index) else {
-                break;
-            };
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
 
-            if part.status_code >= 400 {
-                response.responses.push(ExecuteMultipleResponseItem {
-                    request_index: part_index,
-                    response: None,
-                    fault: Some(parse_fault(part)),
-                });
-                continue;
-            }
+    /// Metadata a change feed on `accounts` reads, followed by `changes`.
+    fn change_feed_recording(changes: &[RecordedExchange]) -> PathBuf {
+        let mut exchanges = vec![
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"}]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')?$select=LogicalName,TableType,ChangeTrackingEnabled,IsAuditEnabled",
+                200,
+                "{\"LogicalName\":\"account\",\"TableType\":\"Standard\",\"ChangeTrackingEnabled\":true}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[{\"LogicalName\":\"accountid\",\"SchemaName\":\"AccountId\",\"AttributeType\":\"Uniqueidentifier\"},{\"LogicalName\":\"name\",\"SchemaName\":\"Name\",\"AttributeType\":\"String\"}]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+        ];
+        exchanges.extend_from_slice(changes);
+        write_exchanges(&exchanges)
+    }
 
-            if request.settings.return_responses {
-                response.responses.push(ExecuteMultipleResponseItem {
-                    request_index: part_index,
-                    response: Some(source_request.success_response(&part.headers)),
-                    fault: None,
-                });
-            }
-        }
+    /// A page of changed accounts named `names` answering `accounts?{query}`, ending with the
+    /// delta link `token`.
+    fn changes_page(query: &str, names: &[&str], token: u32) -> RecordedExchange {
+        let rows = names
+            .iter()
+            .map(
+                |name| serde_json::json!({ "accountid": Uuid::new_v4().to_string(), "name": name }),
+            )
+            .collect::<Vec<_>>();
+        let body = serde_json::json!({
+            "value": rows,
+            "@odata.deltaLink": format!("{TEST_URL}/api/data/v9.2/accounts?$select=name&$deltatoken={token}"),
+        });
+        exchange(
+            "GET",
+            &format!("/api/data/v9.2/accounts?{query}"),
+            200,
+            &body.to_string(),
+        )
+    }
 
-        Ok(response)
+    fn stored_link(store: &MemoryDeltaTokenStore, key: &str) -> String {
+        store.load(key).expect("load").expect("link")
     }
-}
 
-fn ensure_fetch_page_size(fetchxml: &str, page_size: i32) -> Result<String, String> {
-    if fetch_tag_has_attr(fetchxml, "count")? {
-        return Ok(fetchxml.to_string());
+    fn changed_names(changes: &[ChangeEvent]) -> Vec<String> {
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                ChangeEvent::NewOrUpdated(row) => match row.attributes.get("name") {
+                    Some(DataverseValue::String(name)) => Some(name.clone()),
+                    _ => None,
+                },
+                ChangeEvent::Deleted { .. } => None,
+            })
+            .collect()
     }
 
-    let fetch_start = fetchxml
-        .find("<fetch")
-        .ok_or_else(|| "FetchXML must start with a <fetch> element".to_string())?;
-    let tag_end = fetchxml[fetch_start..]
-        .find('>')
-        .ok_or_else(|| "FetchXML <fetch> element is not closed".to_string())?
-        + fetch_start;
+    async fn change_feed_client(path: &std::path::Path) -> ServiceClient {
+        ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.to_path_buf()))
+            .build()
+            .await
+            .expect("client")
+    }
 
-    let mut inserted = String::new();
-    inserted.push_str(&fetchxml[..tag_end]);
-    inserted.push_str(&format!(" count=\"{page_size}\""));
-    inserted.push_str(&fetchxml[tag_end..]);
-    Ok(inserted)
-}
+    #[tokio::test]
+    async fn change_feed_polls_keep_links_per_query_and_forget_rejected_links() {
+        let path = change_feed_recording(&[
+            changes_page("$select=name", &["Contoso"], 1),
+            changes_page("$select=name&$deltatoken=1", &["Fabrikam"], 2),
+            changes_page("$select=name&$deltatoken=1", &["Fabrikam"], 2),
+            changes_page("$select=name,statecode", &["Litware"], 7),
+            exchange(
+                "GET",
+                "/api/data/v9.2/accounts?$select=name&$deltatoken=2",
+                400,
+                "{\"error\":{\"code\":\"0x80044352\",\"message\":\"The delta token has expired.\"}}",
+            ),
+            changes_page("$select=name", &["Contoso"], 3),
+        ]);
+        let client = change_feed_client(&path).await;
+        let store = Arc::new(MemoryDeltaTokenStore::default());
+        let feed = ChangeFeed::new(&client, "accounts", "$select=name", store.clone());
+        let key = store_key("accounts", "$select=name");
+        let mut seen = Vec::new();
+
+        let count = feed
+            .poll(async |changes| {
+                seen.extend(changed_names(&changes));
+                Ok(())
+            })
+            .await
+            .expect("first poll");
+        assert_eq!(count, 1);
+        assert!(stored_link(&store, &key).ends_with("$deltatoken=1"));
+
+        let failed = feed.poll(async |_| Err("handler failed".to_string())).await;
+        assert_eq!(failed.unwrap_err(), "handler failed");
+        assert!(stored_link(&store, &key).ends_with("$deltatoken=1"));
+        feed.poll(async |changes| {
+            seen.extend(changed_names(&changes));
+            Ok(())
+        })
+        .await
+        .expect("repeated poll");
+        assert_eq!(seen, ["Contoso", "Fabrikam"]);
 
-fn normalize_entity_name(value: &str) -> String {
-    value
-        .trim_matches(|ch| ch == '[' || ch == ']' || ch == '"' || ch == '`')
-        .to_ascii_lowercase()
-}
+        let other = ChangeFeed::new(&client, "accounts", "$select=name,statecode", store.clone());
+        assert_eq!(other.poll(async |_| Ok(())).await.expect("new query"), 1);
+        assert!(stored_link(&store, &key).ends_with("$deltatoken=2"));
 
-fn parse_uuid_from_uri(value: &str) -> Option<Uuid> {
-    let start = value.rfind('(')? + 1;
-    let end = value.rfind(')')?;
-    Uuid::parse_str(value[start..end].trim_matches('{').trim_matches('}')).ok()
-}
+        let expired = feed.poll(async |_| Ok(())).await.unwrap_err();
+        assert!(expired.contains("(400 "), "{expired}");
+        assert_eq!(store.load(&key).expect("load"), None);
+        assert_eq!(feed.poll(async |_| Ok(())).await.expect("full read"), 1);
+        assert!(stored_link(&store, &key).ends_with("$deltatoken=3"));
 
-#[cfg(test)]
-mod tests {
-    use super::{ensure_fetch_page_size, normalize_entity_name, parse_uuid_from_uri};
-    use uuid::Uuid;
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn change_feed_run_stops_at_the_first_failed_batch() {
+        let path = change_feed_recording(&[
+            changes_page("$select=name", &["Contoso"], 1),
+            changes_page("$select=name&$deltatoken=1", &[], 2),
+            changes_page("$select=name&$deltatoken=2", &["Fabrikam"], 3),
+        ]);
+        let client = change_feed_client(&path).await;
+        let store = Arc::new(MemoryDeltaTokenStore::default());
+        let feed = ChangeFeed::new(&client, "accounts", "$select=name", store.clone())
+            .interval(Duration::ZERO);
+        let mut seen = Vec::new();
+
+        let stopped = feed
+            .run(async |changes| {
+                seen.extend(changed_names(&changes));
+                if seen.len() > 1 {
+                    return Err("stop".to_string());
+                }
+                Ok(())
+            })
+            .await;
+
+        assert_eq!(stopped.unwrap_err(), "stop");
+        assert_eq!(seen, ["Contoso", "Fabrikam"]);
+        assert!(
+            stored_link(&store, &store_key("accounts", "$select=name")).ends_with("$deltatoken=2")
+        );
+
+        client.shutdown(Duration::ZERO).await.expect("idle");
+        assert_eq!(feed.run(async |_| Ok(())).await, Ok(()));
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn change_feed_stream_restarts_after_a_rejected_link() {
+        let path = change_feed_recording(&[
+            changes_page("$select=name", &["Contoso"], 1),
+            changes_page("$select=name&$deltatoken=1", &[], 2),
+            exchange(
+                "GET",
+                "/api/data/v9.2/accounts?$select=name&$deltatoken=2",
+                410,
+                "{\"error\":{\"code\":\"0x80044352\",\"message\":\"The delta token has expired.\"}}",
+            ),
+            changes_page("$select=name", &["Contoso", "Fabrikam"], 3),
+        ]);
+        let client = change_feed_client(&path).await;
+        let store = Arc::new(MemoryDeltaTokenStore::default());
+        let key = store_key("accounts", "$select=name");
+        let stream = ChangeFeed::new(&client, "accounts", "$select=name", store.clone())
+            .interval(Duration::ZERO)
+            .into_stream();
+        let mut stream = std::pin::pin!(stream);
+
+        let first = stream.next().await.expect("item").expect("changes");
+        assert_eq!(changed_names(&first), ["Contoso"]);
+        assert_eq!(store.load(&key).expect("load"), None);
+
+        let expired = stream.next().await.expect("item").unwrap_err();
+        assert!(expired.contains("(410 "), "{expired}");
+        assert_eq!(store.load(&key).expect("load"), None);
+
+        let restarted = stream.next().await.expect("item").expect("changes");
+        assert_eq!(changed_names(&restarted), ["Contoso", "Fabrikam"]);
+
+        client.shutdown(Duration::ZERO).await.expect("idle");
+        assert!(stream.next().await.is_none());
+        assert!(stored_link(&store, &key).ends_with("$deltatoken=3"));
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn import_records_resolves_lookups_and_reports_failed_rows() {
+        let contact_fetch = format!(
+            "/api/data/v9.2/contacts?fetchXml={}",
+            urlencoding::encode(
+                "<fetch count=\"5000\" page=\"1\"><entity name=\"contact\"><attribute name=\"emailaddress1\" /><filter type=\"or\"><filter type=\"and\"><condition attribute=\"emailaddress1\" operator=\"eq\" value=\"ann@contoso.com\" /></filter><filter type=\"and\"><condition attribute=\"emailaddress1\" operator=\"eq\" value=\"nobody@contoso.com\" /></filter></filter></entity></fetch>"
+            )
+        );
+        let path = write_exchanges(&[
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions?$select=LogicalName,SchemaName,DisplayName,EntitySetName,IsCustomEntity,IsActivity,PrimaryIdAttribute,PrimaryNameAttribute,OwnershipType",
+                200,
+                "{\"value\":[{\"LogicalName\":\"account\",\"SchemaName\":\"Account\",\"EntitySetName\":\"accounts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"accountid\"},{\"LogicalName\":\"contact\",\"SchemaName\":\"Contact\",\"EntitySetName\":\"contacts\",\"IsCustomEntity\":false,\"PrimaryIdAttribute\":\"contactid\"}]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[{\"LogicalName\":\"name\",\"SchemaName\":\"Name\",\"AttributeType\":\"String\"},{\"LogicalName\":\"numberofemployees\",\"SchemaName\":\"NumberOfEmployees\",\"AttributeType\":\"Integer\"},{\"LogicalName\":\"primarycontactid\",\"SchemaName\":\"PrimaryContactId\",\"AttributeType\":\"Lookup\"}]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.PicklistAttributeMetadata?$select=LogicalName&$expand=OptionSet($select=Options)",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.MultiSelectPicklistAttributeMetadata?$select=LogicalName&$expand=OptionSet($select=Options)",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.StateAttributeMetadata?$select=LogicalName&$expand=OptionSet($select=Options)",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes/Microsoft.Dynamics.CRM.StatusAttributeMetadata?$select=LogicalName&$expand=OptionSet($select=Options)",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='contact')/Attributes?$select=LogicalName,SchemaName,DisplayName,AttributeType,AttributeTypeName,IsCustomAttribute,IsValidODataAttribute,IsValidForRead,IsValidForCreate,IsValidForUpdate&$filter=IsValidODataAttribute%20eq%20true%20and%20IsValidForRead%20eq%20true",
+                200,
+                "{\"value\":[{\"LogicalName\":\"contactid\",\"SchemaName\":\"ContactId\",\"AttributeType\":\"Uniqueidentifier\"},{\"LogicalName\":\"emailaddress1\",\"SchemaName\":\"EMailAddress1\",\"AttributeType\":\"String\"}]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='contact')/Attributes/Microsoft.Dynamics.CRM.DateTimeAttributeMetadata?$select=LogicalName,DateTimeBehavior",
+                200,
+                "{\"value\":[]}",
+            ),
+            exchange(
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/ManyToOneRelationships?$select=ReferencingAttribute,ReferencedEntity,ReferencingEntityNavigationPropertyName",
+                200,
+                "{\"value\":[{\"ReferencingAttribute\":\"primarycontactid\",\"ReferencedEntity\":\"contact\",\"ReferencingEntityNavigationPropertyName\":\"primarycontactid\"}]}",
+            ),
+            exchange(
+                "GET",
+                &contact_fetch,
+                200,
+                "{\"value\":[{\"contactid\":\"11111111-1111-1111-1111-111111111111\",\"emailaddress1\":\"ann@contoso.com\"}]}",
+            ),
+            batch_exchange(
+                &[
+                    (
+                        "POST",
+                        "accounts",
+                        Some(
+                            "{\"name\":\"Contoso\",\"numberofemployees\":10,\"primarycontactid@odata.bind\":\"/contacts(11111111-1111-1111-1111-111111111111)\"}",
+                        ),
+                    ),
+                    (
+                        "POST",
+                        "accounts",
+                        Some(
+                            "{\"name\":\"Adventure Works\",\"numberofemployees\":7,\"primarycontactid@odata.bind\":\"/contacts(11111111-1111-1111-1111-111111111111)\"}",
+                        ),
+                    ),
+                ],
+                200,
+                &[
+                    (
+                        204,
+                        &[(
+                            "OData-EntityId",
+                            "https://example.crm.dynamics.com/api/data/v9.2/accounts(22222222-2222-2222-2222-222222222222)",
+                        )],
+                        "",
+                    ),
+                    (
+                        400,
+                        &[("Content-Type", "application/json")],
+                        "{\"error\":{\"code\":\"0x80040237\",\"message\":\"A record with matching key values already exists.\"}}",
+                    ),
+                ],
+            ),
+        ]);
+        let client = ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.clone()))
+            .build()
+            .await
+            .expect("should build client");
+        let report_path = path.with_file_name("errors.csv");
+        let input = concat!(
+            "name,employees,contact\n",
+            "Contoso,10,ann@contoso.com\n",
+            "Fabrikam,many,ann@contoso.com\n",
+            "Litware,5,nobody@contoso.com\n",
+            "Adventure Works,7,ann@contoso.com\n",
+            "\"Northwind,3,ann@contoso.com\n",
+        );
+
+        let report = import_records(
+            &client,
+            input.as_bytes(),
+            ImportFormat::Csv,
+            &ImportMapping {
+                table: "account".to_string(),
+                fields: vec![
+                    FieldMapping::new("name", "name"),
+                    FieldMapping::new("employees", "numberofemployees"),
+                    FieldMapping::lookup("contact", "primarycontactid", "contact", "emailaddress1"),
+                ],
+                upsert_key: Vec::new(),
+            },
+            &ImportOptions {
+                bulk: BulkOptions {
+                    retry_delay: Duration::from_millis(1),
+                    ..BulkOptions::default()
+                },
+                error_report: Some(report_path.clone()),
+                empty_as_null: false,
+            },
+        )
+        .await
+        .expect("should import");
+
+        assert_eq!(report.read, 5);
+        assert_eq!(report.written, 1);
+        assert_eq!(
+            report
+                .results
+                .iter()
+                .filter(|result| !result.is_success())
+                .map(|result| (result.index, result.error_message.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                (1, Some("employees: 'many' is not a valid whole number")),
+                (2, Some("No 'contact' row has emailaddress1 'nobody@contoso.com'")),
+                (3, Some("A record with matching key values already exists.")),
+                (4, Some("Quoted CSV field starting on line 6 is not closed")),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(&report_path).expect("error report"),
+            concat!(
+                "name,employees,contact,import_line,import_error\n",
+                "Fabrikam,many,ann@contoso.com,3,employees: 'many' is not a valid whole number\n",
+                "Litware,5,nobody@contoso.com,4,No 'contact' row has emailaddress1 'nobody@contoso.com'\n",
+                "Adventure Works,7,ann@contoso.com,5,A record with matching key values already exists.\n",
+                ",,,6,Quoted CSV field starting on line 6 is not closed\n",
+            )
+        );
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn get_business_unit_tree_nests_child_business_units() {
+        let body = "{\"value\":[{\"businessunitid\":\"22222222-2222-2222-2222-222222222222\",\"name\":\"Sales\",\"_parentbusinessunitid_value\":\"11111111-1111-1111-1111-111111111111\",\"isdisabled\":false},{\"businessunitid\":\"11111111-1111-1111-1111-111111111111\",\"name\":\"Contoso\",\"_parentbusinessunitid_value\":null,\"isdisabled\":false}]}";
+        let (client, path) = replay_client(&[(
+            "GET",
+            "/api/data/v9.2/businessunits?$select=businessunitid,name,_parentbusinessunitid_value,isdisabled",
+            200,
+            body,
+        )])
+        .await;
+
+        let tree = client
+            .get_business_unit_tree()
+            .await
+            .expect("should build tree");
+
+        assert_eq!(tree.business_unit.name, "Contoso");
+        assert_eq!(tree.children.len(), 1);
+        assert_eq!(tree.children[0].business_unit.name, "Sales");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
 
     #[test]
     fn ensure_fetch_page_size_adds_count_when_missing() {
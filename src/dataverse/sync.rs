@@ -0,0 +1,231 @@
+use serde_json::{Map, Value as JsonValue};
+use uuid::Uuid;
+
+use crate::dataverse::entity::{Entity, Value};
+
+/// Logical name of the row version attribute present on every Dataverse table.
+pub const VERSION_NUMBER_ATTRIBUTE: &str = "versionnumber";
+
+/// Rows changed since a version checkpoint, along with the checkpoint to use next time.
+#[derive(Debug, Clone, Default)]
+pub struct VersionSyncResult {
+    /// Rows whose `versionnumber` is greater than the requested checkpoint, in version order.
+    pub entities: Vec<Entity>,
+    /// Highest `versionnumber` seen, or the requested checkpoint when no rows changed.
+    pub max_version: Option<i64>,
+}
+
+/// One change read from a change tracking (delta) response.
+#[derive(Debug, Clone)]
+pub enum ChangeEvent {
+    /// A row created or updated since the previous delta link.
+    NewOrUpdated(Box<Entity>),
+    /// A row deleted since the previous delta link. `entity` is the table logical name.
+    Deleted { id: Uuid, entity: String },
+}
+
+/// Changes read from a change tracking query, along with the link that reads the next changes.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeTrackingResult {
+    /// Changes in the order Dataverse returned them, across every page.
+    pub changes: Vec<ChangeEvent>,
+    /// `@odata.deltaLink` to store and pass to `retrieve_changes_since` on the next run.
+    pub delta_link: Option<String>,
+}
+
+/// ID of a deleted row entry in a delta response, such as
+/// `{"@odata.context": "$metadata#accounts/$deletedEntity", "id": "…", "reason": "deleted"}`.
+pub(crate) fn deleted_row_id(record: &JsonValue) -> Option<Uuid> {
+    let is_deleted = record
+        .get("@odata.context")
+        .and_then(|value| value.as_str())
+        .is_some_and(|context| context.ends_with("/$deletedEntity"))
+        || record.get("reason").and_then(|value| value.as_str()) == Some("deleted");
+    if !is_deleted {
+        return None;
+    }
+    record
+        .get("id")
+        .and_then(|value| value.as_str())
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+/// The rows of a delta response without its deleted entries, so the entity parser only sees
+/// rows that carry columns.
+pub(crate) fn without_deleted_rows(json: &JsonValue) -> JsonValue {
+    let rows = json
+        .get("value")
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter(|record| deleted_row_id(record).is_none())
+        .cloned()
+        .collect();
+    let mut response = Map::new();
+    response.insert("value".to_string(), JsonValue::Array(rows));
+    JsonValue::Object(response)
+}
+
+/// Interleave parsed rows with the deleted entries of a delta response, keeping response order.
+/// `rows` must be the parsed rows of `without_deleted_rows(json)`.
+pub(crate) fn change_events(
+    json: &JsonValue,
+    rows: Vec<Entity>,
+    logical_name: &str,
+) -> Vec<ChangeEvent> {
+    let mut rows = rows.into_iter();
+    json.get("value")
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|record| match deleted_row_id(record) {
+            Some(id) => Some(ChangeEvent::Deleted {
+                id,
+                entity: logical_name.to_string(),
+            }),
+            None => rows
+                .next()
+                .map(|row| ChangeEvent::NewOrUpdated(Box::new(row))),
+        })
+        .collect()
+}
+
+/// Build a FetchXML query for rows with `versionnumber` greater than `since`, ordered by version.
+pub(crate) fn build_version_sync_fetchxml(
+    logical_name: &str,
+    columns: &[&str],
+    since: Option<i64>,
+) -> String {
+    let mut fetchxml = format!("<fetch><entity name=\"{logical_name}\">");
+
+    for column in columns
+        .iter()
+        .filter(|column| !column.eq_ignore_ascii_case(VERSION_NUMBER_ATTRIBUTE))
+    {
+        fetchxml.push_str(&format!("<attribute name=\"{column}\" />"));
+    }
+    fetchxml.push_str(&format!(
+        "<attribute name=\"{VERSION_NUMBER_ATTRIBUTE}\" />"
+    ));
+    fetchxml.push_str(&format!(
+        "<order attribute=\"{VERSION_NUMBER_ATTRIBUTE}\" />"
+    ));
+
+    if let Some(since) = since {
+        fetchxml.push_str(&format!(
+            "<filter><condition attribute=\"{VERSION_NUMBER_ATTRIBUTE}\" operator=\"gt\" value=\"{since}\" /></filter>"
+        ));
+    }
+
+    fetchxml.push_str("</entity></fetch>");
+    fetchxml
+}
+
+/// Read the row version from an entity, accepting the integer or string forms Dataverse emits.
+pub fn entity_version(entity: &Entity) -> Option<i64> {
+    match entity.attributes.get(VERSION_NUMBER_ATTRIBUTE)? {
+        Value::Int(value) => Some(*value),
+        Value::String(value) => value.parse().ok(),
+        Value::Decimal(value) => value.to_string().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Return the highest row version among `entities`, falling back to `since`.
+pub(crate) fn max_version(entities: &[Entity], since: Option<i64>) -> Option<i64> {
+    entities
+        .iter()
+        .filter_map(entity_version)
+        .chain(since)
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{
+        ChangeEvent, build_version_sync_fetchxml, change_events, max_version,
+        without_deleted_rows,
+    };
+    use crate::dataverse::entity::{Entity, Value};
+
+    #[test]
+    fn builds_versioned_fetch_with_checkpoint_filter() {
+        let fetchxml = build_version_sync_fetchxml("account", &["name", "versionnumber"], Some(42));
+
+        assert_eq!(
+            fetchxml,
+            concat!(
+                "<fetch><entity name=\"account\">",
+                "<attribute name=\"name\" />",
+                "<attribute name=\"versionnumber\" />",
+                "<order attribute=\"versionnumber\" />",
+                "<filter><condition attribute=\"versionnumber\" operator=\"gt\" value=\"42\" /></filter>",
+                "</entity></fetch>"
+            )
+        );
+    }
+
+    #[test]
+    fn initial_sync_omits_checkpoint_filter() {
+        let fetchxml = build_version_sync_fetchxml("account", &[], None);
+
+        assert!(!fetchxml.contains("<filter>"));
+    }
+
+    #[test]
+    fn delta_responses_keep_deletions_in_order() {
+        let updated = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+        let deleted = Uuid::parse_str("bbbbbbbb-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+        let json = json!({"value": [
+            {
+                "@odata.context": "https://example.crm.dynamics.com/api/data/v9.2/$metadata#accounts/$deletedEntity",
+                "id": deleted.to_string(),
+                "reason": "deleted"
+            },
+            {"accountid": updated.to_string(), "name": "Contoso"}
+        ]});
+
+        assert_eq!(
+            without_deleted_rows(&json)["value"]
+                .as_array()
+                .map(Vec::len),
+            Some(1)
+        );
+
+        let events = change_events(
+            &json,
+            vec![Entity::new(updated, "account", Some("Contoso".to_string()))],
+            "account",
+        );
+
+        assert!(matches!(
+            events.as_slice(),
+            [
+                ChangeEvent::Deleted { id, entity },
+                ChangeEvent::NewOrUpdated(row),
+            ] if *id == deleted && entity == "account" && row.id == updated
+        ));
+    }
+
+    #[test]
+    fn max_version_prefers_highest_seen_and_falls_back_to_checkpoint() {
+        let entity = |version: Value| {
+            let mut entity = Entity::new(Uuid::new_v4(), "account", None);
+            entity
+                .attributes
+                .insert("versionnumber".to_string(), version);
+            entity
+        };
+        let entities = vec![
+            entity(Value::Int(50)),
+            entity(Value::String("75".to_string())),
+        ];
+
+        assert_eq!(max_version(&entities, Some(42)), Some(75));
+        assert_eq!(max_version(&[], Some(42)), Some(42));
+        assert_eq!(max_version(&[], None), None);
+    }
+}
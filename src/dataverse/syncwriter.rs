@@ -0,0 +1,438 @@
+use std::collections::{BTreeSet, HashMap};
+use std::pin::pin;
+
+use futures_util::{Stream, StreamExt};
+use rust_decimal::Decimal;
+use rust_decimal::prelude::ToPrimitive;
+use uuid::Uuid;
+
+use crate::dataverse::alternatekey::KeyAttributes;
+use crate::dataverse::batch::{OrganizationRequest, UpdateRequest, UpsertRequest};
+use crate::dataverse::bulk::{BulkExecutor, BulkOptions};
+use crate::dataverse::datacopy::{LOOKUP_QUERY_CHUNK, build_key_lookup_fetchxml, key_values};
+use crate::dataverse::entity::{Entity, EntityReference, Value};
+use crate::dataverse::progress::{ProgressCallback, ProgressTracker};
+use crate::dataverse::requestparameters::RequestParameters;
+use crate::dataverse::serviceclient::ServiceClient;
+
+/// Options for `SyncWriter`.
+#[derive(Debug, Clone)]
+pub struct SyncWriterOptions {
+    /// Records read from the stream and compared with Dataverse at a time.
+    pub chunk_size: usize,
+    /// Batch size, concurrency, and retries of the writes.
+    pub bulk: BulkOptions,
+    /// Request parameters of every write, such as `RequestParameters::bypass_plugins_and_flows`.
+    pub parameters: RequestParameters,
+    /// Called after each chunk is written, with the chunks and records processed so far.
+    pub progress: Option<ProgressCallback>,
+}
+
+impl Default for SyncWriterOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: 500,
+            bulk: BulkOptions::default(),
+            parameters: RequestParameters::default(),
+            progress: None,
+        }
+    }
+}
+
+/// A record that could not be synchronized.
+#[derive(Debug, Clone)]
+pub struct SyncFailure {
+    /// Alternate key values of the record, `Value::Null` where the record lacks one.
+    pub key: KeyAttributes,
+    /// Failure message from Dataverse or from checking the record.
+    pub message: String,
+}
+
+/// Summary of a `SyncWriter::write` run.
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    /// Records read from the stream.
+    pub read: usize,
+    /// Records with no matching row, created.
+    pub created: usize,
+    /// Records that differed from their row, updated with the changed columns only.
+    pub updated: usize,
+    /// Records equal to their row, not written.
+    pub unchanged: usize,
+    /// Records that failed to write or were rejected before writing.
+    pub failures: Vec<SyncFailure>,
+}
+
+/// Writes a stream of records to one table, matched by an alternate key, sending only the
+/// records that differ from what Dataverse holds.
+///
+/// Each chunk of records is compared with the current rows, read in batched FetchXML queries by
+/// key. Only the columns a record carries are compared, so a record can hold a subset of the
+/// table's columns. Unchanged records are skipped, changed records update only their changed
+/// columns, and records without a row are created by an upsert on the alternate key. For
+/// recurring syncs where most records did not change, this sends a fraction of the writes of
+/// upserting every record.
+pub struct SyncWriter<'a> {
+    client: &'a ServiceClient,
+    entity: String,
+    key_attributes: Vec<String>,
+    options: SyncWriterOptions,
+}
+
+/// A write planned for a record that differs from Dataverse.
+struct PlannedWrite {
+    key: KeyAttributes,
+    created: bool,
+    request: OrganizationRequest,
+}
+
+impl<'a> SyncWriter<'a> {
+    /// Create a writer for the table `entity`, such as `account`, matching records to rows by
+    /// the columns of an alternate key defined on it, such as `["accountnumber"]`.
+    pub fn new(
+        client: &'a ServiceClient,
+        entity: &str,
+        key_attributes: &[&str],
+        options: SyncWriterOptions,
+    ) -> Self {
+        Self {
+            client,
+            entity: entity.to_string(),
+            key_attributes: key_attributes
+                .iter()
+                .map(|attribute| attribute.to_ascii_lowercase())
+                .collect(),
+            options,
+        }
+    }
+
+    /// Synchronize `records`, such as `futures_util::stream::iter(rows)`, and report what was
+    /// written. Records use column logical names; their IDs and logical names are ignored.
+    /// Failed records do not stop the run; failing to read the current rows does.
+    pub async fn write<S>(&self, records: S) -> Result<SyncReport, String>
+    where
+        S: Stream<Item = Entity>,
+    {
+        if self.key_attributes.is_empty() {
+            return Err("SyncWriter needs at least one alternate key attribute".to_string());
+        }
+        let definition = self.client.resolve_entity_definition(&self.entity).await?;
+        let executor = BulkExecutor::new(self.client, self.options.bulk.clone());
+        let mut report = SyncReport::default();
+        let mut progress = ProgressTracker::new(self.options.progress.as_ref(), None);
+
+        let mut chunks = pin!(records.chunks(self.options.chunk_size.max(1)));
+        while let Some(chunk) = chunks.next().await {
+            report.read += chunk.len();
+            let existing = self
+                .existing_rows(
+                    &definition.logical_name,
+                    &definition.entity_set_name,
+                    &chunk,
+                )
+                .await?;
+            let planned = plan_writes(
+                &definition.logical_name,
+                &self.key_attributes,
+                &self.options.parameters,
+                chunk.iter(),
+                &existing,
+                &mut report,
+            );
+
+            let results = executor
+                .execute(planned.iter().map(|write| write.request.clone()).collect())
+                .await;
+            for item in results.items {
+                let write = &planned[item.request_index];
+                match item.outcome {
+                    Ok(_) if write.created => report.created += 1,
+                    Ok(_) => report.updated += 1,
+                    Err(failure) => report.failures.push(SyncFailure {
+                        key: write.key.clone(),
+                        message: failure.message,
+                    }),
+                }
+            }
+            progress.page_done(chunk.len());
+        }
+
+        Ok(report)
+    }
+
+    /// Read the rows matching the keys of `records`, with every column the records carry, keyed
+    /// by their comparable key values.
+    async fn existing_rows(
+        &self,
+        logical_name: &str,
+        entity_set_name: &str,
+        records: &[Entity],
+    ) -> Result<HashMap<Vec<String>, Entity>, String> {
+        let keys = records
+            .iter()
+            .filter_map(|record| key_values(record, &self.key_attributes))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        // Key columns come first: the lookup query matches keys on the leading columns.
+        let mut columns = self.key_attributes.clone();
+        columns.extend(
+            records
+                .iter()
+                .flat_map(|record| record.attributes.keys())
+                .map(|attribute| attribute.to_ascii_lowercase())
+                .filter(|attribute| !self.key_attributes.contains(attribute))
+                .collect::<BTreeSet<_>>(),
+        );
+
+        let mut existing = HashMap::new();
+        for chunk in keys.chunks(LOOKUP_QUERY_CHUNK) {
+            let fetchxml = build_key_lookup_fetchxml(logical_name, &columns, chunk);
+            for row in self
+                .client
+                .retrieve_multiple_fetchxml_paging(entity_set_name, &fetchxml)
+                .await?
+            {
+                if let Some(key) = key_values(&row, &self.key_attributes) {
+                    existing.insert(key, row);
+                }
+            }
+        }
+        Ok(existing)
+    }
+}
+
+/// Compare `records` with the `existing` rows and plan a write for each record that differs,
+/// counting unchanged records and rejected records in `report`.
+fn plan_writes<'r>(
+    logical_name: &str,
+    key_attributes: &[String],
+    parameters: &RequestParameters,
+    records: impl Iterator<Item = &'r Entity>,
+    existing: &HashMap<Vec<String>, Entity>,
+    report: &mut SyncReport,
+) -> Vec<PlannedWrite> {
+    let mut planned = Vec::new();
+    for record in records {
+        let key = key_attributes
+            .iter()
+            .map(|attribute| {
+                let value = record.attributes.get(attribute).cloned();
+                (attribute.clone(), value.unwrap_or(Value::Null))
+            })
+            .collect::<KeyAttributes>();
+        let Some(key_strings) = key_values(record, key_attributes) else {
+            report.failures.push(SyncFailure {
+                key,
+                message: format!(
+                    "Record is missing a value for alternate key attribute(s) {}",
+                    key_attributes.join(", ")
+                ),
+            });
+            continue;
+        };
+        if record
+            .attributes
+            .values()
+            .any(|value| matches!(value, Value::EntityCollection(_)))
+        {
+            report.failures.push(SyncFailure {
+                key,
+                message: "SyncWriter does not write related rows (EntityCollection values)"
+                    .to_string(),
+            });
+            continue;
+        }
+
+        let (created, request) = match existing.get(&key_strings) {
+            Some(row) => {
+                let mut changed = Entity::new(row.id, logical_name, None);
+                changed.attributes = record
+                    .attributes
+                    .iter()
+                    .filter(|(attribute, value)| {
+                        let attribute = attribute.to_ascii_lowercase();
+                        !key_attributes.contains(&attribute)
+                            && !values_equal(value, row.attributes.get(&attribute))
+                    })
+                    .map(|(attribute, value)| (attribute.clone(), value.clone()))
+                    .collect();
+                if changed.attributes.is_empty() {
+                    report.unchanged += 1;
+                    continue;
+                }
+                let mut update = UpdateRequest::new(changed);
+                update.parameters = parameters.clone();
+                (false, OrganizationRequest::Update(update))
+            }
+            None => {
+                let mut created = Entity::new(Uuid::nil(), logical_name, None);
+                created.attributes = record.attributes.clone();
+                let mut upsert = UpsertRequest::with_alternate_key(created, key.clone());
+                upsert.parameters = parameters.clone();
+                (true, OrganizationRequest::Upsert(upsert))
+            }
+        };
+        planned.push(PlannedWrite {
+            key,
+            created,
+            request,
+        });
+    }
+    planned
+}
+
+/// True when writing `new` would leave the column as `current` has it. Dataverse leaves empty
+/// columns out of query results, so `Value::Null` equals a missing value.
+pub(crate) fn values_equal(new: &Value, current: Option<&Value>) -> bool {
+    let Some(current) = current else {
+        return matches!(new, Value::Null);
+    };
+    match (new, current) {
+        (Value::Null, Value::Null) => true,
+        (Value::String(new), Value::String(current)) => new == current,
+        (Value::Boolean(new), Value::Boolean(current)) => new == current,
+        (Value::Guid(new), Value::Guid(current)) => new == current,
+        // Dataverse stores times to the second.
+        (Value::DateTime(new), Value::DateTime(current)) => new.timestamp() == current.timestamp(),
+        (Value::Date(new), Value::Date(current)) => new == current,
+        (Value::OptionSetValue(new), Value::OptionSetValue(current)) => new.value == current.value,
+        (Value::Int(new), Value::OptionSetValue(current)) => *new == i64::from(current.value),
+        (Value::OptionSetValueCollection(new), Value::OptionSetValueCollection(current)) => {
+            let sorted = |values: &[i32]| values.iter().copied().collect::<BTreeSet<_>>();
+            sorted(&new.values) == sorted(&current.values)
+        }
+        (Value::PartyList(new), Value::PartyList(current)) => {
+            let ids = |parties: &[EntityReference]| {
+                parties.iter().map(|party| party.id).collect::<BTreeSet<_>>()
+            };
+            ids(new) == ids(current)
+        }
+        (Value::EntityReference(new), Value::EntityReference(current)) => {
+            new.id == current.id
+                && (new.logical_name.is_empty()
+                    || new.logical_name.eq_ignore_ascii_case(&current.logical_name))
+        }
+        (new, current) => match (number(new), number(current)) {
+            // Floats compare as floats, since not every float has an exact decimal form.
+            (Some(new_number), Some(current_number))
+                if matches!(new, Value::Float(_)) || matches!(current, Value::Float(_)) =>
+            {
+                new_number.to_f64() == current_number.to_f64()
+            }
+            (Some(new), Some(current)) => new == current,
+            _ => false,
+        },
+    }
+}
+
+/// The amount of an integer, decimal, float, or money value.
+fn number(value: &Value) -> Option<Decimal> {
+    match value {
+        Value::Int(value) => Some(Decimal::from(*value)),
+        Value::Decimal(value) => Some(*value),
+        Value::Float(value) => Decimal::try_from(*value).ok(),
+        Value::Money(money) => Some(money.value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chrono::{TimeZone, Utc};
+    use rust_decimal::Decimal;
+    use uuid::Uuid;
+
+    use super::{SyncReport, plan_writes, values_equal};
+    use crate::dataverse::batch::OrganizationRequest;
+    use crate::dataverse::entity::{Entity, Money, OptionSetValue, Value};
+    use crate::dataverse::requestparameters::RequestParameters;
+
+    fn account(number: &str, name: &str, employees: i64) -> Entity {
+        let mut row = Entity::new(Uuid::new_v4(), "account", None);
+        row.attributes.insert(
+            "accountnumber".to_string(),
+            Value::String(number.to_string()),
+        );
+        row.attributes
+            .insert("name".to_string(), Value::String(name.to_string()));
+        row.attributes
+            .insert("numberofemployees".to_string(), Value::Int(employees));
+        row
+    }
+
+    #[test]
+    fn plans_only_creates_and_changed_columns() {
+        let keys = vec!["accountnumber".to_string()];
+        let current = account("ACC-1", "Contoso", 10);
+        let existing = HashMap::from([(vec!["acc-1".to_string()], current.clone())]);
+        let records = [
+            account("ACC-1", "Contoso", 10),
+            account("acc-1", "Contoso", 12),
+            account("ACC-2", "Fabrikam", 5),
+            Entity::new(Uuid::nil(), "account", None),
+        ];
+        let mut report = SyncReport::default();
+
+        let planned = plan_writes(
+            "account",
+            &keys,
+            &RequestParameters::bypass_plugins_and_flows(),
+            records.iter(),
+            &existing,
+            &mut report,
+        );
+
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(planned.len(), 2);
+        let OrganizationRequest::Update(update) = &planned[0].request else {
+            panic!("should update the existing row");
+        };
+        assert!(!planned[0].created);
+        assert_eq!(update.target.id, current.id);
+        assert_eq!(
+            update.target.attributes.keys().collect::<Vec<_>>(),
+            vec!["numberofemployees"]
+        );
+        assert!(update.parameters.bypass_custom_plugin_execution);
+        let OrganizationRequest::Upsert(upsert) = &planned[1].request else {
+            panic!("should create the new row");
+        };
+        assert!(planned[1].created);
+        assert_eq!(upsert.alternate_key[0].0, "accountnumber");
+        assert_eq!(upsert.target.attributes.len(), 3);
+    }
+
+    #[test]
+    fn compares_values_the_way_dataverse_stores_them() {
+        assert!(values_equal(&Value::Null, None));
+        assert!(!values_equal(&Value::Int(0), None));
+        assert!(values_equal(
+            &Value::Float(2.5),
+            Some(&Value::Decimal(Decimal::new(250, 2)))
+        ));
+        assert!(values_equal(
+            &Value::Decimal(Decimal::new(5, 0)),
+            Some(&Value::Money(Money::new(Decimal::new(500, 2))))
+        ));
+        assert!(values_equal(
+            &Value::Int(3),
+            Some(&Value::OptionSetValue(OptionSetValue {
+                value: 3,
+                name: None
+            }))
+        ));
+        let second = Utc.with_ymd_and_hms(2024, 5, 1, 8, 0, 0).unwrap();
+        assert!(values_equal(
+            &Value::DateTime(second + chrono::Duration::milliseconds(400)),
+            Some(&Value::DateTime(second))
+        ));
+        assert!(!values_equal(
+            &Value::String("Contoso".to_string()),
+            Some(&Value::String("contoso".to_string()))
+        ));
+    }
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+/// Histogram of Web API request durations in seconds, labelled by `method`, `operation`, and
+/// `status`.
+pub const REQUEST_DURATION_METRIC: &str = "dataverse_request_duration_seconds";
+/// Counter of Web API requests, labelled by `method`, `operation`, and `status`.
+pub const REQUESTS_METRIC: &str = "dataverse_requests_total";
+/// Counter of responses throttled by service protection limits.
+pub const THROTTLED_METRIC: &str = "dataverse_throttled_total";
+/// Counter of requests sent again after a transient failure, labelled by `operation`.
+pub const RETRIES_METRIC: &str = "dataverse_retries_total";
+/// Counter of result pages read, labelled by `operation`.
+pub const PAGES_METRIC: &str = "dataverse_pages_total";
+
+/// `status` label of a request: the HTTP status code, or `error` when no response arrived.
+#[cfg(any(feature = "metrics", test))]
+pub(crate) fn status_label(status: Option<u16>) -> String {
+    status.map_or_else(|| "error".to_string(), |status| status.to_string())
+}
+
+/// `operation` label of a request, from its method and URL: `batch`, `metadata`, `fetchxml`,
+/// `retrieve`, `retrieve_multiple`, `create`, `update`, `delete`, `function`, or `action`.
+/// Unbound and bound functions and actions are told apart from entity sets by their leading
+/// capital letter.
+#[cfg(any(feature = "metrics", test))]
+pub(crate) fn operation_label(method: &str, path: &str, query: Option<&str>) -> &'static str {
+    let segment = path.rsplit('/').next().unwrap_or_default();
+    let is_operation = segment.starts_with(|c: char| c.is_ascii_uppercase());
+    if segment == "$batch" {
+        "batch"
+    } else if segment == "$metadata"
+        || [
+            "EntityDefinitions",
+            "RelationshipDefinitions",
+            "GlobalOptionSetDefinitions",
+        ]
+        .iter()
+        .any(|definitions| path.contains(definitions))
+    {
+        "metadata"
+    } else {
+        match method {
+            "GET" if query.is_some_and(|query| query.contains("fetchXml=")) => "fetchxml",
+            "GET" if is_operation => "function",
+            "GET" if segment.ends_with(')') => "retrieve",
+            "GET" => "retrieve_multiple",
+            "POST" if is_operation => "action",
+            "POST" => "create",
+            "PATCH" | "PUT" => "update",
+            "DELETE" => "delete",
+            _ => "other",
+        }
+    }
+}
+
+/// Record one Web API request and how long it took.
+pub(crate) fn record_request(
+    method: &str,
+    path: &str,
+    query: Option<&str>,
+    status: Option<u16>,
+    elapsed: Duration,
+) {
+    #[cfg(feature = "metrics")]
+    {
+        let labels = [
+            ("method", method.to_string()),
+            (
+                "operation",
+                operation_label(method, path, query).to_string(),
+            ),
+            ("status", status_label(status)),
+        ];
+        metrics::histogram!(REQUEST_DURATION_METRIC, &labels).record(elapsed.as_secs_f64());
+        metrics::counter!(REQUESTS_METRIC, &labels).increment(1);
+        if status == Some(429) {
+            metrics::counter!(THROTTLED_METRIC).increment(1);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (method, path, query, status, elapsed);
+}
+
+/// Record `count` throttled batch items, which arrive inside a successful `$batch` response.
+pub(crate) fn record_throttles(count: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(THROTTLED_METRIC).increment(count as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = count;
+}
+
+/// Record `count` requests of `operation` sent again after a transient failure.
+pub(crate) fn record_retries(operation: &'static str, count: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(RETRIES_METRIC, "operation" => operation).increment(count as u64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = (operation, count);
+}
+
+/// Record one result page read by `operation`.
+pub(crate) fn record_page(operation: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!(PAGES_METRIC, "operation" => operation).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = operation;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{operation_label, status_label};
+
+    #[test]
+    fn status_label_names_missing_responses() {
+        assert_eq!(status_label(Some(204)), "204");
+        assert_eq!(status_label(None), "error");
+    }
+
+    #[test]
+    fn operation_label_names_the_kind_of_request() {
+        let labels = [
+            ("POST", "/api/data/v9.2/$batch", None),
+            (
+                "GET",
+                "/api/data/v9.2/EntityDefinitions(LogicalName='account')/Attributes",
+                Some("$select=LogicalName"),
+            ),
+            ("GET", "/api/data/v9.2/accounts", Some("fetchXml=%3Cfetch%3E")),
+            (
+                "GET",
+                "/api/data/v9.2/accounts(00000000-0000-0000-0000-000000000001)",
+                None,
+            ),
+            ("GET", "/api/data/v9.2/accounts", Some("$select=name")),
+            ("GET", "/api/data/v9.2/WhoAmI", None),
+            ("POST", "/api/data/v9.2/accounts", None),
+            (
+                "POST",
+                "/api/data/v9.2/accounts(00000000-0000-0000-0000-000000000001)/Microsoft.Dynamics.CRM.Merge",
+                None,
+            ),
+            ("PATCH", "/api/data/v9.2/accounts(accountnumber='A1')", None),
+            (
+                "DELETE",
+                "/api/data/v9.2/accounts(00000000-0000-0000-0000-000000000001)",
+                None,
+            ),
+        ]
+        .map(|(method, path, query)| operation_label(method, path, query));
+
+        assert_eq!(
+            labels,
+            [
+                "batch",
+                "metadata",
+                "fetchxml",
+                "retrieve",
+                "retrieve_multiple",
+                "function",
+                "create",
+                "action",
+                "update",
+                "delete",
+            ]
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::dataverse::entity::{Attribute, Entity, Value};
+use crate::dataverse::syncwriter::values_equal;
+
+/// A retrieved row that records which columns are changed after retrieval, so an update sends
+/// only those columns instead of every column that was read. See
+/// `ServiceClient::update_tracked`.
+#[derive(Debug, Clone)]
+pub struct TrackedEntity {
+    entity: Entity,
+    original: HashMap<Attribute, Value>,
+    touched: BTreeSet<Attribute>,
+}
+
+impl TrackedEntity {
+    /// Start tracking changes to `entity`, taking its current values as unchanged.
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            original: entity.attributes.clone(),
+            entity,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// The row with its current values.
+    pub fn entity(&self) -> &Entity {
+        &self.entity
+    }
+
+    /// Current value of a column.
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.entity.attributes.get(column)
+    }
+
+    /// Set a column. Setting the value it was retrieved with is not a change.
+    pub fn set(&mut self, column: impl Into<Attribute>, value: Value) {
+        let column = column.into();
+        self.touched.insert(column.clone());
+        self.entity.attributes.insert(column, value);
+    }
+
+    /// Set a column to null.
+    pub fn clear(&mut self, column: impl Into<Attribute>) {
+        self.set(column, Value::Null);
+    }
+
+    /// Columns whose value differs from the retrieved one, in name order. Dataverse leaves empty
+    /// columns out of query results, so clearing a column that was not returned is not a change.
+    pub fn changed_attributes(&self) -> Vec<&str> {
+        self.touched
+            .iter()
+            .filter(|column| self.is_changed(column))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Whether any column has changed.
+    pub fn is_modified(&self) -> bool {
+        self.touched.iter().any(|column| self.is_changed(column))
+    }
+
+    /// The row with only its changed columns, for an update that leaves the other columns
+    /// untouched.
+    pub fn changes(&self) -> Entity {
+        let mut changes = Entity::new(
+            self.entity.id,
+            self.entity.logical_name.clone(),
+            self.entity.name.clone(),
+        );
+        changes.etag = self.entity.etag.clone();
+        for column in self.changed_attributes() {
+            if let Some(value) = self.entity.attributes.get(column) {
+                changes.attributes.insert(column.to_string(), value.clone());
+            }
+        }
+        changes
+    }
+
+    /// Take the current values as unchanged, such as after they were written.
+    pub fn accept_changes(&mut self) {
+        self.original = self.entity.attributes.clone();
+        self.touched.clear();
+    }
+
+    /// Drop the row version once the row has been written, since Dataverse does not return the
+    /// new one from an update.
+    pub(crate) fn clear_etag(&mut self) {
+        self.entity.etag = None;
+    }
+
+    /// Restore the retrieved values of every changed column.
+    pub fn reject_changes(&mut self) {
+        self.entity.attributes = self.original.clone();
+        self.touched.clear();
+    }
+
+    /// Stop tracking and return the row with its current values.
+    pub fn into_entity(self) -> Entity {
+        self.entity
+    }
+
+    fn is_changed(&self, column: &str) -> bool {
+        self.entity
+            .attributes
+            .get(column)
+            .is_some_and(|value| !values_equal(value, self.original.get(column)))
+    }
+}
+
+impl From<Entity> for TrackedEntity {
+    fn from(entity: Entity) -> Self {
+        Self::new(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::TrackedEntity;
+    use crate::dataverse::entity::{Entity, OptionSetValue, Value};
+
+    #[test]
+    fn reports_only_columns_set_to_a_new_value() {
+        let mut entity = Entity::new(Uuid::new_v4(), "account", None);
+        entity
+            .attributes
+            .insert("name".to_string(), Value::String("Contoso".to_string()));
+        entity.attributes.insert(
+            "industrycode".to_string(),
+            Value::OptionSetValue(OptionSetValue {
+                value: 1,
+                name: Some("Accounting".to_string()),
+            }),
+        );
+        entity
+            .attributes
+            .insert("revenue".to_string(), Value::Int(100));
+
+        let mut tracked = TrackedEntity::new(entity);
+        tracked.set("name", Value::String("Contoso".to_string()));
+        tracked.set("industrycode", Value::Int(1));
+        tracked.clear("telephone1");
+        assert!(!tracked.is_modified());
+
+        tracked.set("name", Value::String("Fabrikam".to_string()));
+        tracked.clear("revenue");
+        assert_eq!(tracked.changed_attributes(), vec!["name", "revenue"]);
+        let changes = tracked.changes();
+        assert_eq!(changes.attributes.len(), 2);
+        assert!(matches!(
+            changes.attributes.get("revenue"),
+            Some(Value::Null)
+        ));
+
+        tracked.reject_changes();
+        assert!(!tracked.is_modified());
+        assert!(matches!(tracked.get("name"), Some(Value::String(name)) if name == "Contoso"));
+
+        tracked.set("name", Value::String("Fabrikam".to_string()));
+        tracked.accept_changes();
+        assert!(!tracked.is_modified());
+        assert!(matches!(tracked.get("name"), Some(Value::String(name)) if name == "Fabrikam"));
+    }
+}
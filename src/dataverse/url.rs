@@ -0,0 +1,188 @@
+use std::fmt::Display;
+
+/// Web API version clients target unless configured otherwise.
+pub(crate) const DEFAULT_API_VERSION: &str = "9.2";
+
+/// Path of the Web API root for `version`, such as `/api/data/v9.2`.
+pub(crate) fn web_api_path(version: &str) -> String {
+    format!("/api/data/v{}", version.trim_start_matches('v'))
+}
+
+/// Absolute Web API URL for a path relative to the Web API root `api_path`.
+pub(crate) fn web_api_url(base_url: &str, api_path: &str, path: &str) -> String {
+    format!("{}{api_path}/{path}", base_url.trim_end_matches('/'))
+}
+
+/// Request-line path of a `$batch` part, relative to the Web API root. The request line cannot
+/// contain raw spaces, which OData queries such as `$filter` use freely.
+pub(crate) fn batch_request_path(path: &str) -> String {
+    path.replace(' ', "%20")
+}
+
+/// Percent-encode a value for a query string or key segment.
+pub(crate) fn encode_query_value(value: &str) -> String {
+    urlencoding::encode(value).into_owned()
+}
+
+/// OData string literal for a URL: quoted, with apostrophes doubled, then percent-encoded so
+/// characters such as `+`, `&`, and `#` survive.
+pub(crate) fn encode_string_literal(value: &str) -> String {
+    format!("'{}'", encode_query_value(&value.replace('\'', "''")))
+}
+
+/// Path of a single row, accepting IDs with or without braces. Alternate key segments such as
+/// `accountnumber='A-1'` pass through unchanged.
+pub(crate) fn row_path(entity_set: &str, id: impl Display) -> String {
+    format!(
+        "{entity_set}({})",
+        id.to_string().trim_matches(|ch| ch == '{' || ch == '}')
+    )
+}
+
+/// `@odata.bind` value of a single row: its `row_path` from the Web API root, such as
+/// `/accounts(00000000-0000-0000-0000-000000000001)`. Every binding uses this one form.
+pub(crate) fn bind_path(entity_set: &str, id: impl Display) -> String {
+    format!("/{}", row_path(entity_set, id))
+}
+
+/// Path of a table definition addressed by logical name.
+pub(crate) fn entity_definition_path(logical_name: &str) -> String {
+    format!(
+        "EntityDefinitions(LogicalName={})",
+        encode_string_literal(logical_name)
+    )
+}
+
+/// Path of a column definition addressed by table and column logical names.
+pub(crate) fn attribute_definition_path(logical_name: &str, attribute: &str) -> String {
+    format!(
+        "{}/Attributes(LogicalName={})",
+        entity_definition_path(logical_name),
+        encode_string_literal(attribute)
+    )
+}
+
+/// Query a table with FetchXML.
+pub(crate) fn fetchxml_query_path(entity_set: &str, fetchxml: &str) -> String {
+    format!("{entity_set}?fetchXml={}", encode_query_value(fetchxml))
+}
+
+/// Query a table with OData query options, such as `$select=name&$top=10`.
+pub(crate) fn odata_query_path(entity_set: &str, query: &str) -> String {
+    let query = query.trim_start_matches('?');
+    if query.is_empty() {
+        entity_set.to_string()
+    } else {
+        format!("{entity_set}?{query}")
+    }
+}
+
+/// Path with a `$select` query limiting the returned columns, or the path itself when `columns`
+/// is empty.
+pub(crate) fn select_path(path: &str, columns: &[&str]) -> String {
+    if columns.is_empty() {
+        path.to_string()
+    } else {
+        format!("{path}?$select={}", columns.join(","))
+    }
+}
+
+/// Add `$count=true` to OData query options unless they already set `$count`.
+pub(crate) fn with_inline_count(query: &str) -> String {
+    let query = query.trim_start_matches('?');
+    let has_count = query
+        .split('&')
+        .any(|option| option.to_ascii_lowercase().starts_with("$count="));
+    match (has_count, query.is_empty()) {
+        (true, _) => query.to_string(),
+        (false, true) => "$count=true".to_string(),
+        (false, false) => format!("{query}&$count=true"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        DEFAULT_API_VERSION, attribute_definition_path, batch_request_path, bind_path,
+        encode_string_literal, entity_definition_path, fetchxml_query_path, odata_query_path,
+        row_path, select_path, web_api_path, web_api_url, with_inline_count,
+    };
+
+    #[test]
+    fn string_literals_double_apostrophes_and_encode_the_rest() {
+        assert_eq!(encode_string_literal("O'Brien"), "'O%27%27Brien'");
+        assert_eq!(encode_string_literal("Zoë Müller"), "'Zo%C3%AB%20M%C3%BCller'");
+        assert_eq!(
+            encode_string_literal("a+b@contoso.com"),
+            "'a%2Bb%40contoso.com'"
+        );
+        assert_eq!(encode_string_literal("R&D #1"), "'R%26D%20%231'");
+    }
+
+    #[test]
+    fn builds_web_api_paths() {
+        assert_eq!(
+            web_api_url(
+                "https://example.crm.dynamics.com/",
+                &web_api_path(DEFAULT_API_VERSION),
+                "accounts"
+            ),
+            "https://example.crm.dynamics.com/api/data/v9.2/accounts"
+        );
+        assert_eq!(
+            web_api_url(
+                "https://example.crm.dynamics.com",
+                &web_api_path("v9.1"),
+                "accounts"
+            ),
+            "https://example.crm.dynamics.com/api/data/v9.1/accounts"
+        );
+        assert_eq!(
+            row_path("accounts", "{aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee}"),
+            "accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)"
+        );
+        assert_eq!(
+            bind_path("accounts", "{aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee}"),
+            "/accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)"
+        );
+        assert_eq!(
+            attribute_definition_path("account", "cr_document"),
+            "EntityDefinitions(LogicalName='account')/Attributes(LogicalName='cr_document')"
+        );
+        assert_eq!(
+            entity_definition_path("o'clock"),
+            "EntityDefinitions(LogicalName='o%27%27clock')"
+        );
+        assert_eq!(odata_query_path("accounts", ""), "accounts");
+        assert_eq!(
+            select_path("accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)", &["name", "createdon"]),
+            "accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)?$select=name,createdon"
+        );
+        assert_eq!(select_path("accounts", &[]), "accounts");
+        assert_eq!(
+            odata_query_path("accounts", "?$select=name"),
+            "accounts?$select=name"
+        );
+        assert_eq!(
+            fetchxml_query_path("accounts", "<fetch top=\"1\"/>"),
+            "accounts?fetchXml=%3Cfetch%20top%3D%221%22%2F%3E"
+        );
+        assert_eq!(
+            batch_request_path("accounts?$filter=name eq 'A'"),
+            "accounts?$filter=name%20eq%20'A'"
+        );
+    }
+
+    #[test]
+    fn adds_inline_count_once() {
+        assert_eq!(with_inline_count(""), "$count=true");
+        assert_eq!(
+            with_inline_count("?$select=name&$top=10"),
+            "$select=name&$top=10&$count=true"
+        );
+        assert_eq!(
+            with_inline_count("$select=name&$count=false"),
+            "$select=name&$count=false"
+        );
+    }
+}
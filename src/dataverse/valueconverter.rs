@@ -0,0 +1,25 @@
+use serde_json::Value as JsonValue;
+
+use crate::dataverse::entity::Value;
+use crate::dataverse::entityattribute::EntityAttribute;
+
+/// Caller-supplied conversion of Web API attribute values into `Value`s, consulted before the
+/// built-in conversion for every non-null, non-lookup attribute of a retrieved row.
+///
+/// Return `None` to fall back to the built-in conversion. Lookup columns and formatted-value
+/// annotations are handled by the parser and never reach the converter. Money folding of
+/// `{column}_base` siblings only applies to numeric results, so a money column converted to, for
+/// example, `Value::String` keeps the converter's value.
+///
+/// Numbers arrive as `serde_json` parsed them. Without the `decimal-precision` feature they are
+/// `f64`s, so converting a decimal to text does not recover digits beyond `f64` precision.
+pub trait ValueConverter: Send + Sync {
+    /// Convert one attribute value. `attribute` is the column metadata when the retrieval loaded
+    /// it, so converters can dispatch on the column type instead of the JSON shape.
+    fn convert(
+        &self,
+        key: &str,
+        value: &JsonValue,
+        attribute: Option<&EntityAttribute>,
+    ) -> Option<Value>;
+}
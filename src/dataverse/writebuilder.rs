@@ -0,0 +1,391 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::entity::{Money, TRANSACTION_CURRENCY_ATTRIBUTE};
+use crate::dataverse::entityattribute::EntityAttribute;
+use crate::dataverse::optionset::{OptionSetMap, option_value_by_label};
+use crate::dataverse::serviceclient::ServiceClient;
+use crate::dataverse::url::bind_path;
+
+/// Builds the JSON payload for `ServiceClient::create_entity` and `update_entity` with typed
+/// setters, so lookups, choices, and money columns use the shapes the Web API expects.
+#[derive(Debug, Clone, Default)]
+pub struct EntityWriteBuilder {
+    attributes: HashMap<String, Value>,
+    /// Choice columns set by label, kept out of `attributes` until the labels are resolved.
+    option_labels: HashMap<String, Vec<String>>,
+}
+
+impl EntityWriteBuilder {
+    /// Create an empty payload.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set a text column.
+    pub fn set_string(self, column: &str, value: impl Into<String>) -> Self {
+        self.set(column, Value::String(value.into()))
+    }
+
+    /// Set a whole number column.
+    pub fn set_int(self, column: &str, value: i64) -> Self {
+        self.set(column, Value::from(value))
+    }
+
+    /// Set a decimal number column.
+    pub fn set_decimal(self, column: &str, value: Decimal) -> Self {
+        self.set(column, decimal_to_json(value))
+    }
+
+    /// Set a yes/no column.
+    pub fn set_bool(self, column: &str, value: bool) -> Self {
+        self.set(column, Value::Bool(value))
+    }
+
+    /// Set a date and time column, written in UTC.
+    pub fn set_datetime(self, column: &str, value: DateTime<Utc>) -> Self {
+        self.set(column, Value::String(value.to_rfc3339()))
+    }
+
+    /// Set a `DateOnly` column, written as `yyyy-MM-dd`.
+    pub fn set_date(self, column: &str, value: NaiveDate) -> Self {
+        self.set(column, Value::String(value.format("%Y-%m-%d").to_string()))
+    }
+
+    /// Set a lookup through its single-valued navigation property, producing
+    /// `"{navigation_property}@odata.bind": "/{entity_set}({id})"`.
+    ///
+    /// The navigation property is usually the lookup's logical name for system lookups and its
+    /// schema name for custom lookups. Polymorphic lookups use a per-target property such as
+    /// `parentcustomerid_account`.
+    pub fn set_lookup(self, navigation_property: &str, entity_set: &str, id: Uuid) -> Self {
+        self.set(
+            &format!("{navigation_property}@odata.bind"),
+            Value::String(bind_path(entity_set, id.as_hyphenated())),
+        )
+    }
+
+    /// Remove the value of a lookup, given its single-valued navigation property.
+    pub fn clear_lookup(self, navigation_property: &str) -> Self {
+        self.set(&format!("{navigation_property}@odata.bind"), Value::Null)
+    }
+
+    /// Set a choice, status, or state column.
+    pub fn set_optionset(self, column: &str, value: i32) -> Self {
+        self.set(column, Value::from(value))
+    }
+
+    /// Set a choice, status, or state column by the label of its option, such as `High` for
+    /// `prioritycode`. The label is resolved to its value by `build_resolved`; until then `build`
+    /// fails. A later setter on the same column replaces the label.
+    ///
+    /// Labels are compared without regard to case in the calling user's language, or the
+    /// client's `label_language`. A label shared by two options fails as ambiguous.
+    pub fn set_optionset_by_label(self, column: &str, label: impl Into<String>) -> Self {
+        self.set_labels(column, vec![label.into()])
+    }
+
+    /// Set a multi-select choice column.
+    pub fn set_multi_optionset(self, column: &str, values: &[i32]) -> Self {
+        self.set(column, Value::String(multi_option_value(values)))
+    }
+
+    /// Set a multi-select choice column by the labels of its options. The labels are resolved to
+    /// their values by `build_resolved`; until then `build` fails.
+    pub fn set_multi_optionset_by_label(self, column: &str, labels: &[&str]) -> Self {
+        self.set_labels(
+            column,
+            labels.iter().map(|label| label.to_string()).collect(),
+        )
+    }
+
+    /// Set a currency column. When the money value has a currency, the row's
+    /// `transactioncurrencyid` is bound as well.
+    pub fn set_money(mut self, column: &str, value: &Money) -> Self {
+        self = self.set(column, decimal_to_json(value.value));
+        if let Some(currency) = &value.currency {
+            self = self.set_lookup(
+                TRANSACTION_CURRENCY_ATTRIBUTE,
+                "transactioncurrencies",
+                currency.id,
+            );
+        }
+        self
+    }
+
+    /// Set a column to null. Use `clear_lookup` for lookup columns.
+    pub fn clear(self, column: &str) -> Self {
+        self.set(column, Value::Null)
+    }
+
+    /// Return the payload for `create_entity` or `update_entity`. Fails when a column was set by
+    /// label with `set_optionset_by_label` or `set_multi_optionset_by_label`; use
+    /// `build_resolved` for those payloads.
+    pub fn build(self) -> Result<HashMap<String, Value>, String> {
+        if self.option_labels.is_empty() {
+            return Ok(self.attributes);
+        }
+        let mut columns = self.option_labels.keys().cloned().collect::<Vec<_>>();
+        columns.sort();
+        Err(format!(
+            "Choice columns set by label need build_resolved: {}",
+            columns.join(", ")
+        ))
+    }
+
+    /// Return the payload for writing to the table `logical_name`, with the labels set by
+    /// `set_optionset_by_label` and `set_multi_optionset_by_label` replaced by their option values
+    /// from the client's cached metadata. Multi-select choice columns are written as their
+    /// comma-separated values. Fails with the valid labels when a label matches no option.
+    pub async fn build_resolved(
+        self,
+        client: &ServiceClient,
+        logical_name: &str,
+    ) -> Result<HashMap<String, Value>, String> {
+        if self.option_labels.is_empty() {
+            return Ok(self.attributes);
+        }
+        let option_sets = client.list_entity_option_sets(logical_name).await?;
+        let multi_select = client
+            .list_entity_attributes(logical_name)
+            .await?
+            .into_iter()
+            .filter(is_multi_select)
+            .map(|attribute| attribute.logical_name)
+            .collect::<HashSet<_>>();
+        self.resolve_option_labels(logical_name, &option_sets, &multi_select)
+    }
+
+    /// Set `column` to `value`, replacing any label set for it earlier.
+    fn set(mut self, column: &str, value: Value) -> Self {
+        self.option_labels.remove(column);
+        self.attributes.insert(column.to_string(), value);
+        self
+    }
+
+    fn set_labels(mut self, column: &str, labels: Vec<String>) -> Self {
+        self.attributes.remove(column);
+        self.option_labels.insert(column.to_string(), labels);
+        self
+    }
+
+    fn resolve_option_labels(
+        mut self,
+        logical_name: &str,
+        option_sets: &OptionSetMap,
+        multi_select: &HashSet<String>,
+    ) -> Result<HashMap<String, Value>, String> {
+        for (column, labels) in &self.option_labels {
+            let values = labels
+                .iter()
+                .map(|label| option_value_by_label(logical_name, column, label, option_sets))
+                .collect::<Result<Vec<_>, String>>()?;
+            let value = if multi_select.contains(column) {
+                Value::String(multi_option_value(&values))
+            } else if let [value] = values.as_slice() {
+                Value::from(*value)
+            } else {
+                return Err(format!(
+                    "'{column}' on '{logical_name}' is not a multi-select choice column; set one label"
+                ));
+            };
+            self.attributes.insert(column.clone(), value);
+        }
+        Ok(self.attributes)
+    }
+}
+
+/// Web API form of a multi-select choice value: the option values separated by commas.
+fn multi_option_value(values: &[i32]) -> String {
+    values
+        .iter()
+        .map(i32::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn is_multi_select(attribute: &EntityAttribute) -> bool {
+    attribute
+        .attribute_type_name
+        .as_ref()
+        .and_then(|type_name| type_name.value.as_deref())
+        == Some("MultiSelectPicklistType")
+}
+
+fn decimal_to_json(value: Decimal) -> Value {
+    // Decimal's string form is always a valid JSON number. With the `decimal-precision` feature
+    // the parsed number keeps every digit; without it, digits beyond f64 precision are rounded.
+    serde_json::from_str(&value.to_string()).unwrap_or_else(|_| Value::String(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use rust_decimal::Decimal;
+    use serde_json::{Value, json};
+    use uuid::Uuid;
+
+    use super::EntityWriteBuilder;
+    use crate::dataverse::entity::Money;
+    use crate::dataverse::optionset::{OptionSetMap, parse_option_set_attributes};
+
+    #[test]
+    fn builds_typed_payload_with_lookup_binding() {
+        let account_id = Uuid::parse_str("aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee").expect("uuid");
+
+        let payload = EntityWriteBuilder::new()
+            .set_string("firstname", "Ada")
+            .set_int("numberofchildren", 2)
+            .set_optionset("preferredcontactmethodcode", 2)
+            .set_lookup("parentcustomerid_account", "accounts", account_id)
+            .clear("jobtitle")
+            .build()
+            .expect("should build");
+
+        assert_eq!(payload.get("firstname"), Some(&json!("Ada")));
+        assert_eq!(payload.get("numberofchildren"), Some(&json!(2)));
+        assert_eq!(payload.get("preferredcontactmethodcode"), Some(&json!(2)));
+        assert_eq!(
+            payload.get("parentcustomerid_account@odata.bind"),
+            Some(&json!("/accounts(aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee)"))
+        );
+        assert_eq!(payload.get("jobtitle"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn money_keeps_precision_and_binds_currency() {
+        let currency_id = Uuid::new_v4();
+
+        let payload = EntityWriteBuilder::new()
+            .set_money(
+                "revenue",
+                &Money::with_currency(Decimal::new(1_000_000_001, 2), currency_id),
+            )
+            .build()
+            .expect("should build");
+
+        assert_eq!(
+            payload.get("revenue").map(Value::to_string).as_deref(),
+            Some("10000000.01")
+        );
+        assert_eq!(
+            payload.get("transactioncurrencyid@odata.bind"),
+            Some(&json!(format!("/transactioncurrencies({currency_id})")))
+        );
+    }
+
+    fn option_sets() -> OptionSetMap {
+        parse_option_set_attributes(&json!({
+            "value": [
+                {
+                    "LogicalName": "prioritycode",
+                    "OptionSet": {
+                        "Options": [
+                            { "Value": 0, "Label": { "UserLocalizedLabel": { "Label": "Low" } } },
+                            { "Value": 2, "Label": { "UserLocalizedLabel": { "Label": "High" } } }
+                        ]
+                    }
+                },
+                {
+                    "LogicalName": "cr123_channels",
+                    "OptionSet": {
+                        "Options": [
+                            { "Value": 1, "Label": { "UserLocalizedLabel": { "Label": "Email" } } },
+                            { "Value": 2, "Label": { "UserLocalizedLabel": { "Label": "Phone" } } }
+                        ]
+                    }
+                }
+            ]
+        }))
+        .expect("should parse")
+    }
+
+    #[test]
+    fn option_labels_resolve_to_their_values() {
+        let option_sets = option_sets();
+        let multi_select = HashSet::from(["cr123_channels".to_string()]);
+
+        let payload = EntityWriteBuilder::new()
+            .set_optionset_by_label("prioritycode", "high")
+            .set_optionset_by_label("statuscode", "Active")
+            .set_optionset("statuscode", 1)
+            .set_multi_optionset_by_label("cr123_channels", &["Phone", "email"])
+            .resolve_option_labels("incident", &option_sets, &multi_select)
+            .expect("should resolve");
+        assert_eq!(payload.get("prioritycode"), Some(&json!(2)));
+        assert_eq!(payload.get("statuscode"), Some(&json!(1)));
+        assert_eq!(payload.get("cr123_channels"), Some(&json!("2,1")));
+
+        let payload = EntityWriteBuilder::new()
+            .set_optionset_by_label("cr123_channels", "Email")
+            .resolve_option_labels("incident", &option_sets, &multi_select)
+            .expect("should resolve");
+        assert_eq!(payload.get("cr123_channels"), Some(&json!("1")));
+
+        let error = EntityWriteBuilder::new()
+            .set_multi_optionset_by_label("prioritycode", &["Low", "High"])
+            .resolve_option_labels("incident", &option_sets, &multi_select)
+            .expect_err("single choice");
+        assert!(error.contains("not a multi-select"), "{error}");
+
+        let error = EntityWriteBuilder::new()
+            .set_optionset_by_label("prioritycode", "Urgent")
+            .resolve_option_labels("incident", &option_sets, &multi_select)
+            .expect_err("unknown label");
+        assert_eq!(
+            error,
+            "No option labeled 'Urgent' in choice column 'prioritycode' on 'incident'. Valid labels: Low, High"
+        );
+    }
+
+    #[test]
+    fn labels_stay_out_of_the_payload_until_resolved() {
+        let error = EntityWriteBuilder::new()
+            .set_string("title", "Printer jam")
+            .set_optionset_by_label("prioritycode", "High")
+            .build()
+            .expect_err("unresolved label");
+        assert_eq!(
+            error,
+            "Choice columns set by label need build_resolved: prioritycode"
+        );
+
+        // A later value replaces the label, even text that matches an option's label.
+        let payload = EntityWriteBuilder::new()
+            .set_optionset_by_label("prioritycode", "High")
+            .set_string("prioritycode", "High")
+            .resolve_option_labels("incident", &option_sets(), &HashSet::new())
+            .expect("nothing to resolve");
+        assert_eq!(payload.get("prioritycode"), Some(&json!("High")));
+
+        // A later label replaces an earlier value.
+        let payload = EntityWriteBuilder::new()
+            .set_optionset("prioritycode", 0)
+            .set_optionset_by_label("prioritycode", "High")
+            .resolve_option_labels("incident", &option_sets(), &HashSet::new())
+            .expect("should resolve");
+        assert_eq!(payload.get("prioritycode"), Some(&json!(2)));
+    }
+
+    #[cfg(feature = "decimal-precision")]
+    #[test]
+    fn decimals_beyond_f64_precision_are_written_exactly() {
+        let payload = EntityWriteBuilder::new()
+            .set_decimal(
+                "cr123_rate",
+                "12345678901234.5678901".parse().expect("decimal"),
+            )
+            .build()
+            .expect("should build");
+
+        assert_eq!(
+            payload.get("cr123_rate").map(Value::to_string).as_deref(),
+            Some("12345678901234.5678901")
+        );
+    }
+}
@@ -4,5 +4,9 @@ pub mod auth;
 pub mod dataverse;
 /// Logging helpers and log level definitions.
 pub mod log;
+/// Fixtures for tests against live Dataverse environments, with guaranteed cleanup.
+pub mod testing;
+#[cfg(test)]
+mod testsupport;
 
 pub use log::LogLevel;
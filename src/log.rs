@@ -1,9 +1,26 @@
-use ::log::LevelFilter;
+use ::log::{Level, LevelFilter};
+
+use crate::dataverse::transport::{REDACTED, redact_secrets};
+
+/// Delimiters of FetchXML condition values, raw and as percent-encoded in a request URL.
+const FETCHXML_VALUE_DELIMITERS: [(&str, &str); 6] = [
+    ("value=\"", "\""),
+    ("value='", "'"),
+    ("<value>", "</value>"),
+    ("value%3D%22", "%22"),
+    ("value%3D%27", "%27"),
+    ("%3Cvalue%3E", "%3C%2Fvalue%3E"),
+];
+
+/// Delimiters of OData string literals, raw and percent-encoded.
+const ODATA_LITERAL_DELIMITERS: [(&str, &str); 2] = [("'", "'"), ("%27", "%27")];
 
 /// Logging verbosity for SDK operations.
-#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LogLevel {
+    /// Emit nothing.
+    Off,
     /// Emit error output only.
     Error,
     /// Emit warning and error output.
@@ -20,6 +37,7 @@ impl LogLevel {
     /// Convert the SDK log level to a `log` crate filter.
     pub fn as_filter(self) -> LevelFilter {
         match self {
+            LogLevel::Off => LevelFilter::Off,
             LogLevel::Error => LevelFilter::Error,
             LogLevel::Warn => LevelFilter::Warn,
             LogLevel::Information => LevelFilter::Info,
@@ -28,10 +46,30 @@ impl LogLevel {
         }
     }
 
+    /// Whether messages at `level` should be emitted by the SDK.
+    pub fn includes(self, level: Level) -> bool {
+        level <= self.as_filter()
+    }
+
     /// Whether debug-style messages should be emitted by the SDK.
     pub fn includes_debug(self) -> bool {
         matches!(self, LogLevel::Debug | LogLevel::Trace)
     }
+
+    pub(crate) fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LogLevel::Off,
+            1 => LogLevel::Error,
+            2 => LogLevel::Warn,
+            3 => LogLevel::Information,
+            4 => LogLevel::Debug,
+            _ => LogLevel::Trace,
+        }
+    }
 }
 
 impl Default for LogLevel {
@@ -40,3 +78,108 @@ impl Default for LogLevel {
         LogLevel::Error
     }
 }
+
+/// Prepare a diagnostic message for logging: OAuth tokens, client secrets, and bearer
+/// credentials are replaced with `[REDACTED]`.
+pub(crate) fn sanitize_message(message: &str) -> String {
+    redact_secrets(message)
+}
+
+/// Prepare a request URL or path for logging. Besides secrets, the values compared in `$filter`
+/// string literals, alternate keys, and embedded FetchXML conditions are redacted, since they
+/// are often customer data. Entity sets, column names, and operators are kept.
+pub(crate) fn sanitize_url(url: &str) -> String {
+    let mut sanitized = redact_secrets(url);
+    for (open, close) in FETCHXML_VALUE_DELIMITERS
+        .into_iter()
+        .chain(ODATA_LITERAL_DELIMITERS)
+    {
+        sanitized = redact_between(&sanitized, open, close);
+    }
+    sanitized
+}
+
+/// Prepare a FetchXML query for logging, redacting its condition values.
+pub(crate) fn sanitize_fetchxml(fetchxml: &str) -> String {
+    FETCHXML_VALUE_DELIMITERS
+        .into_iter()
+        .fold(redact_secrets(fetchxml), |sanitized, (open, close)| {
+            redact_between(&sanitized, open, close)
+        })
+}
+
+/// Replace the non-empty text between each `open` and the following `close` with `[REDACTED]`.
+fn redact_between(value: &str, open: &str, close: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(index) = rest.find(open) {
+        let after_open = index + open.len();
+        output.push_str(&rest[..after_open]);
+        rest = &rest[after_open..];
+
+        let Some(end) = rest.find(close) else {
+            break;
+        };
+        if end > 0 {
+            output.push_str(REDACTED);
+        }
+        output.push_str(close);
+        rest = &rest[end + close.len()..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use ::log::Level;
+
+    use super::{LogLevel, sanitize_fetchxml, sanitize_url};
+
+    #[test]
+    fn levels_round_trip_and_off_emits_nothing() {
+        for level in [
+            LogLevel::Off,
+            LogLevel::Error,
+            LogLevel::Warn,
+            LogLevel::Information,
+            LogLevel::Debug,
+            LogLevel::Trace,
+        ] {
+            assert_eq!(LogLevel::from_u8(level.as_u8()), level);
+        }
+
+        assert!(!LogLevel::Off.includes(Level::Error));
+        assert!(LogLevel::Error.includes(Level::Error));
+        assert!(!LogLevel::Error.includes(Level::Warn));
+        assert!(LogLevel::Warn.includes(Level::Warn));
+    }
+
+    #[test]
+    fn sanitizes_urls_and_fetchxml_for_logging() {
+        assert_eq!(
+            sanitize_url(
+                "https://example.crm.dynamics.com/api/data/v9.2/contacts?$filter=emailaddress1 eq 'a@contoso.com' and statecode eq 0"
+            ),
+            "https://example.crm.dynamics.com/api/data/v9.2/contacts?$filter=emailaddress1 eq '[REDACTED]' and statecode eq 0"
+        );
+        assert_eq!(
+            sanitize_url("accounts(accountnumber='A-1')?$select=name"),
+            "accounts(accountnumber='[REDACTED]')?$select=name"
+        );
+        assert_eq!(
+            sanitize_url(
+                "accounts?fetchXml=%3Ccondition%20attribute%3D%22name%22%20operator%3D%22eq%22%20value%3D%22Contoso%22%2F%3E"
+            ),
+            "accounts?fetchXml=%3Ccondition%20attribute%3D%22name%22%20operator%3D%22eq%22%20value%3D%22[REDACTED]%22%2F%3E"
+        );
+        assert_eq!(
+            sanitize_fetchxml(
+                r#"<condition attribute="name" operator="in"><value>Contoso</value><value>Fabrikam</value></condition><condition attribute="city" operator="eq" value="Paris"/>"#
+            ),
+            r#"<condition attribute="name" operator="in"><value>[REDACTED]</value><value>[REDACTED]</value></condition><condition attribute="city" operator="eq" value="[REDACTED]"/>"#
+        );
+    }
+}
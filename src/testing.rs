@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic::{AssertUnwindSafe, resume_unwind};
+
+use futures_util::FutureExt;
+use log::{Level, warn};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::dataverse::apierror::ApiError;
+use crate::dataverse::entitydefinition::{EntityDefinition, OwnershipType};
+use crate::dataverse::newtable::NewTable;
+use crate::dataverse::serviceclient::ServiceClient;
+use crate::log::sanitize_message;
+
+/// A name unique to one test run: `prefix` followed by eight random hex digits, such as
+/// `test-1a2b3c4d`. Useful for row names that identify test data.
+pub fn unique_name(prefix: &str) -> String {
+    format!("{prefix}{}", &Uuid::new_v4().simple().to_string()[..8])
+}
+
+/// Definition of a user-owned table named `<prefix>_Test<random>`, where `prefix` is the
+/// customization prefix of a publisher in the environment, such as `new`.
+pub fn temp_table(prefix: &str) -> NewTable {
+    let schema_name = unique_name(&format!("{prefix}_Test"));
+    NewTable {
+        display_name: schema_name.clone(),
+        display_collection_name: format!("{schema_name} rows"),
+        description: Some("Temporary table created by a test; safe to delete.".to_string()),
+        primary_name_schema_name: format!("{prefix}_Name"),
+        ownership_type: OwnershipType::UserOwned,
+        language_code: 1033,
+        solution_unique_name: None,
+        schema_name,
+    }
+}
+
+/// Create a temporary table from `temp_table(prefix)`, run `test` with its definition, and delete
+/// the table afterwards, also when `test` panics. A panic is resumed after the table is deleted;
+/// otherwise the value of `test` is returned, or the error of a failed delete. When the table is
+/// created but its definition cannot be read, the table is deleted and the read error returned.
+pub async fn with_temp_table<F, Fut, T>(
+    client: &ServiceClient,
+    prefix: &str,
+    test: F,
+) -> Result<T, String>
+where
+    F: FnOnce(EntityDefinition) -> Fut,
+    Fut: Future<Output = T>,
+{
+    with_table(client, &temp_table(prefix), test).await
+}
+
+/// Create `table`, run `test` with its definition, and delete the table afterwards.
+async fn with_table<F, Fut, T>(
+    client: &ServiceClient,
+    table: &NewTable,
+    test: F,
+) -> Result<T, String>
+where
+    F: FnOnce(EntityDefinition) -> Fut,
+    Fut: Future<Output = T>,
+{
+    client.post_table(table).await?;
+    let logical_name = table.logical_name();
+    let definition = match client.created_table_definition(table).await {
+        Ok(definition) => definition,
+        Err(error) => {
+            // The read error matters more than a failed cleanup.
+            client.delete_table(&logical_name).await.ok();
+            return Err(error);
+        }
+    };
+
+    let outcome = AssertUnwindSafe(test(definition)).catch_unwind().await;
+    let cleanup = client.delete_table(&logical_name).await;
+    finish(client, outcome, cleanup, &format!("table {logical_name}"))
+}
+
+/// Create `rows` in `entity_set`, run `test` with their IDs in order, and delete the rows
+/// afterwards, also when `test` panics. Rows the test deleted itself are skipped. When a row
+/// cannot be created, the rows created before it are deleted and the error is returned.
+pub async fn with_rows<F, Fut, T>(
+    client: &ServiceClient,
+    entity_set: &str,
+    rows: &[HashMap<String, Value>],
+    test: F,
+) -> Result<T, String>
+where
+    F: FnOnce(Vec<Uuid>) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut ids = Vec::with_capacity(rows.len());
+    for row in rows {
+        let created = client.create_entity(entity_set, row).await.and_then(|id| {
+            id.ok_or_else(|| {
+                format!("Dataverse did not return the ID of a row created in {entity_set}")
+            })
+        });
+        match created {
+            Ok(id) => ids.push(id),
+            Err(error) => {
+                // The creation error matters more than a failed cleanup.
+                delete_rows(client, entity_set, &ids).await.ok();
+                return Err(error);
+            }
+        }
+    }
+
+    let outcome = AssertUnwindSafe(test(ids.clone())).catch_unwind().await;
+    let cleanup = delete_rows(client, entity_set, &ids).await;
+    finish(client, outcome, cleanup, &format!("rows in {entity_set}"))
+}
+
+/// Delete `ids` from `entity_set` newest first, treating rows that no longer exist as deleted.
+/// Every row is attempted; the first failure is returned.
+async fn delete_rows(client: &ServiceClient, entity_set: &str, ids: &[Uuid]) -> Result<(), String> {
+    let mut first_error = None;
+    for id in ids.iter().rev() {
+        if let Err(error) = client.delete_entity(entity_set, *id).await
+            && ApiError::parse(&error).is_none_or(|api_error| api_error.status_code != 404)
+        {
+            first_error.get_or_insert(error);
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Resume a panic from the test, or return its value unless cleanup failed. A cleanup failure
+/// after a panic is logged as a warning, as the panic is what the test reports.
+fn finish<T>(
+    client: &ServiceClient,
+    outcome: std::thread::Result<T>,
+    cleanup: Result<(), String>,
+    fixture: &str,
+) -> Result<T, String> {
+    match outcome {
+        Err(panic) => {
+            if let Err(error) = cleanup
+                && client.log_level().includes(Level::Warn)
+            {
+                warn!(
+                    "Failed to clean up {fixture} after a panic: {}",
+                    sanitize_message(&error)
+                );
+            }
+            resume_unwind(panic)
+        }
+        Ok(value) => cleanup
+            .map(|()| value)
+            .map_err(|error| format!("Failed to clean up {fixture}: {error}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::panic::AssertUnwindSafe;
+
+    use futures_util::FutureExt;
+    use serde_json::json;
+
+    use super::{temp_table, unique_name, with_rows, with_table};
+    use crate::dataverse::newtable::CREATED_TABLE_SELECT;
+    use crate::dataverse::serviceclient::ServiceClient;
+    use crate::dataverse::transport::{RecordedExchange, TransportMode};
+    use crate::testsupport::{TEST_URL, exchange, write_exchanges};
+
+    const ROW_ID: &str = "11111111-1111-1111-1111-111111111111";
+
+    async fn replay_client(path: &std::path::Path) -> ServiceClient {
+        ServiceClient::builder()
+            .static_token("token")
+            .url(TEST_URL)
+            .transport(TransportMode::Replay(path.to_path_buf()))
+            .build()
+            .await
+            .expect("should build client")
+    }
+
+    #[test]
+    fn names_temporary_tables_with_the_prefix() {
+        let table = temp_table("new");
+        assert!(table.schema_name.starts_with("new_Test"));
+        assert_eq!(table.schema_name.len(), "new_Test".len() + 8);
+        assert!(table.to_entity_metadata().is_ok());
+        assert_ne!(unique_name("row-"), unique_name("row-"));
+    }
+
+    #[tokio::test]
+    async fn deletes_rows_after_a_panicking_test() {
+        let created = exchange("POST", "/api/data/v9.2/accounts", 204, "");
+        let path = write_exchanges(&[
+            RecordedExchange {
+                request_body: Some(r#"{"name":"fixture"}"#.to_string()),
+                headers: vec![(
+                    "odata-entityid".to_string(),
+                    format!("{TEST_URL}/api/data/v9.2/accounts({ROW_ID})"),
+                )],
+                ..created
+            },
+            exchange(
+                "DELETE",
+                &format!("/api/data/v9.2/accounts({ROW_ID})"),
+                204,
+                "",
+            ),
+        ]);
+        let client = replay_client(&path).await;
+
+        let row = HashMap::from([("name".to_string(), json!("fixture"))]);
+        let outcome = AssertUnwindSafe(with_rows(&client, "accounts", &[row], |ids| async move {
+            assert_eq!(ids[0].to_string(), ROW_ID);
+            panic!("test failed");
+        }))
+        .catch_unwind()
+        .await;
+        assert!(outcome.is_err());
+
+        // The recorded DELETE was consumed by the fixture's cleanup.
+        let error = client
+            .delete_entity("accounts", ROW_ID)
+            .await
+            .expect_err("should already be deleted");
+        assert!(error.contains("No recorded response"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+
+    #[tokio::test]
+    async fn deletes_a_created_table_whose_definition_cannot_be_read() {
+        let mut table = temp_table("new");
+        table.schema_name = "new_TestFixture".to_string();
+        let definition = "/api/data/v9.2/EntityDefinitions(LogicalName='new_testfixture')";
+        let path = write_exchanges(&[
+            RecordedExchange {
+                request_body: Some(
+                    serde_json::to_string(&table.to_entity_metadata().expect("metadata"))
+                        .expect("json"),
+                ),
+                ..exchange("POST", "/api/data/v9.2/EntityDefinitions", 204, "")
+            },
+            exchange(
+                "GET",
+                &format!("{definition}?{CREATED_TABLE_SELECT}"),
+                500,
+                r#"{"error":{"code":"0x80040216","message":"Metadata is being published"}}"#,
+            ),
+            exchange("DELETE", definition, 204, ""),
+        ]);
+        let client = replay_client(&path).await;
+
+        let error = with_table(&client, &table, |_| async { panic!("should not run") })
+            .await
+            .expect_err("reading the definition should fail");
+        assert!(error.contains("Metadata is being published"), "{error}");
+
+        // The recorded DELETE was consumed by the fixture's cleanup.
+        let error = client
+            .delete_table("new_testfixture")
+            .await
+            .expect_err("should already be deleted");
+        assert!(error.contains("No recorded response"), "{error}");
+
+        fs::remove_dir_all(path.parent().expect("parent")).ok();
+    }
+}
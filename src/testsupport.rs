@@ -0,0 +1,42 @@
+//! Replay recordings and temporary paths shared by unit tests.
+
+use std::fs;
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use crate::dataverse::transport::RecordedExchange;
+
+/// Environment URL the recorded exchanges are addressed to.
+pub(crate) const TEST_URL: &str = "https://example.crm.dynamics.com";
+
+/// A path under the temporary directory that no other test uses, named after `name`. Nothing is
+/// created there.
+pub(crate) fn unique_temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "powerplatform_dataverse_client_{name}_{}",
+        Uuid::new_v4()
+    ))
+}
+
+/// Write a recording of `exchanges` to a new temporary directory. Remove the directory with
+/// `fs::remove_dir_all(path.parent())` when the test is done.
+pub(crate) fn write_exchanges(exchanges: &[RecordedExchange]) -> PathBuf {
+    let path = unique_temp_path("replay").join("recording.json");
+    let recording = serde_json::json!({ "exchanges": exchanges });
+    fs::create_dir_all(path.parent().expect("parent")).expect("should create dir");
+    fs::write(&path, recording.to_string()).expect("should write recording");
+    path
+}
+
+/// An exchange answering any body sent with `method` to `path` with a JSON `body`.
+pub(crate) fn exchange(method: &str, path: &str, status: u16, body: &str) -> RecordedExchange {
+    RecordedExchange {
+        method: method.to_string(),
+        url: format!("{TEST_URL}{path}"),
+        request_body: None,
+        status,
+        headers: vec![("content-type".to_string(), "application/json".to_string())],
+        response_body: body.to_string(),
+    }
+}